@@ -48,9 +48,31 @@ async fn main() -> Result<(), Box<dyn Error>> {
 				let mut path = url.path().split('/').collect::<Vec<_>>();
 				path[0] = url.host().unwrap(); // The first forward slash causes an empty item and we replace it with the URL's host which you expect to be at the start
 
-				let (status_code, content_type, body) =
-					block_in_place(|| block_on(node.handle_custom_uri(path)));
-				ResponseBuilder::new()
+				// encrypted media is streamed in ranges, so it's able to be seeked through
+				let (status_code, content_type, headers, body) = match path.as_slice() {
+					["encrypted", id] => block_in_place(|| {
+						block_on(
+							node.handle_encrypted_stream(
+								id,
+								req.headers()
+									.get("range")
+									.and_then(|range| range.to_str().ok()),
+							),
+						)
+					}),
+					_ => {
+						let (status_code, content_type, body) = block_in_place(|| {
+							block_on(node.handle_custom_uri(&node.get_request_context(), path))
+						});
+						(status_code, content_type, vec![], body)
+					}
+				};
+
+				headers
+					.into_iter()
+					.fold(ResponseBuilder::new(), |builder, (name, value)| {
+						builder.header(name, value)
+					})
 					.status(status_code)
 					.mimetype(content_type)
 					.body(body)
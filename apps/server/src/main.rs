@@ -3,10 +3,13 @@ use std::{env, net::SocketAddr, path::Path};
 use axum::{
 	extract,
 	handler::Handler,
-	http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+	http::{
+		header::{AUTHORIZATION, CONTENT_TYPE, RANGE},
+		HeaderMap, StatusCode,
+	},
 	routing::get,
 };
-use sd_core::Node;
+use sd_core::{api::Ctx, Node};
 use tracing::info;
 
 mod utils;
@@ -41,25 +44,58 @@ async fn main() {
 		.route("/health", get(|| async { "OK" }))
 		.route("/spacedrive/*id", {
 			let node = node.clone();
-			get(|extract::Path(path): extract::Path<String>| async move {
-				let (status_code, content_type, body) = node
-					.handle_custom_uri(path.split('/').skip(1).collect())
-					.await;
+			get(
+				|extract::Path(path): extract::Path<String>, request_headers: HeaderMap| async move {
+					let path = path.split('/').skip(1).collect::<Vec<_>>();
 
-				(
-					StatusCode::from_u16(status_code).unwrap(),
-					{
-						let mut headers = HeaderMap::new();
-						headers.insert(CONTENT_TYPE, content_type.parse().unwrap());
-						headers
-					},
-					body,
-				)
-			})
+					// encrypted media is streamed in ranges, so it's able to be seeked through
+					let (status_code, content_type, extra_headers, body) = match path.as_slice() {
+						["encrypted", id] => {
+							node.handle_encrypted_stream(
+								id,
+								request_headers
+									.get(RANGE)
+									.and_then(|range| range.to_str().ok()),
+							)
+							.await
+						}
+						_ => {
+							// encrypted thumbnails are only decrypted with an API token that's allowed to reveal them
+							let ctx = Ctx {
+								token: request_headers
+									.get(AUTHORIZATION)
+									.and_then(|token| token.to_str().ok())
+									.and_then(|token| token.strip_prefix("Bearer "))
+									.map(ToString::to_string),
+								..node.get_remote_request_context()
+							};
+
+							let (status_code, content_type, body) =
+								node.handle_custom_uri(&ctx, path).await;
+							(status_code, content_type, vec![], body)
+						}
+					};
+
+					(
+						StatusCode::from_u16(status_code).unwrap(),
+						{
+							let mut headers = HeaderMap::new();
+							headers.insert(CONTENT_TYPE, content_type.parse().unwrap());
+							for (name, value) in extra_headers {
+								headers.insert(name, value.parse().unwrap());
+							}
+							headers
+						},
+						body,
+					)
+				},
+			)
 		})
 		.route(
 			"/rspc/:id",
-			router.endpoint(move || node.get_request_context()).axum(),
+			router
+				.endpoint(move || node.get_remote_request_context())
+				.axum(),
 		)
 		.fallback((|| async { "404 Not Found: We're past the event horizon..." }).into_service());
 
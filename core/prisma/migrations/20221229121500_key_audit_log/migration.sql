@@ -0,0 +1,9 @@
+-- CreateTable
+CREATE TABLE "key_audit_event" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "action" INTEGER NOT NULL,
+    "key_uuid" TEXT,
+    "node_id" INTEGER NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "key_audit_event_node_id_fkey" FOREIGN KEY ("node_id") REFERENCES "node" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "key" ADD COLUMN "source" INTEGER NOT NULL DEFAULT 0;
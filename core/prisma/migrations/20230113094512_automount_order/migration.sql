@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "key" ADD COLUMN "automount_order" INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,14 @@
+-- CreateTable
+CREATE TABLE "key_totp" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "algorithm" BLOB NOT NULL,
+    "salt" BLOB NOT NULL,
+    "nonce" BLOB NOT NULL,
+    "secret" BLOB NOT NULL,
+    "recovery_codes" BLOB NOT NULL,
+    "key_id" INTEGER NOT NULL,
+    CONSTRAINT "key_totp_key_id_fkey" FOREIGN KEY ("key_id") REFERENCES "key" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "key_totp_key_id_key" ON "key_totp"("key_id");
@@ -0,0 +1,26 @@
+-- RedefineTables
+PRAGMA foreign_keys=OFF;
+CREATE TABLE "new_location" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "node_id" INTEGER NOT NULL,
+    "name" TEXT,
+    "local_path" TEXT,
+    "total_capacity" INTEGER,
+    "available_capacity" INTEGER,
+    "filesystem" TEXT,
+    "disk_type" INTEGER,
+    "is_removable" BOOLEAN,
+    "is_online" BOOLEAN NOT NULL DEFAULT true,
+    "is_archived" BOOLEAN NOT NULL DEFAULT false,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "vault_key_id" INTEGER,
+    CONSTRAINT "location_node_id_fkey" FOREIGN KEY ("node_id") REFERENCES "node" ("id") ON DELETE RESTRICT ON UPDATE CASCADE,
+    CONSTRAINT "location_vault_key_id_fkey" FOREIGN KEY ("vault_key_id") REFERENCES "key" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+);
+INSERT INTO "new_location" ("available_capacity", "date_created", "disk_type", "filesystem", "id", "is_archived", "is_online", "is_removable", "local_path", "name", "node_id", "pub_id", "total_capacity") SELECT "available_capacity", "date_created", "disk_type", "filesystem", "id", "is_archived", "is_online", "is_removable", "local_path", "name", "node_id", "pub_id", "total_capacity" FROM "location";
+DROP TABLE "location";
+ALTER TABLE "new_location" RENAME TO "location";
+CREATE UNIQUE INDEX "location_pub_id_key" ON "location"("pub_id");
+PRAGMA foreign_key_check;
+PRAGMA foreign_keys=ON;
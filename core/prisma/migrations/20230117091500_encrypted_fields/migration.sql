@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "media_data" ADD COLUMN "encrypted_location" BLOB;
+
+-- AlterTable
+ALTER TABLE "object" ADD COLUMN "encrypted_note" BLOB;
@@ -0,0 +1,14 @@
+-- CreateTable
+CREATE TABLE "encryption_rule" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "name" TEXT NOT NULL,
+    "glob" TEXT NOT NULL,
+    "location_id" INTEGER,
+    "key_id" INTEGER NOT NULL,
+    "algorithm" BLOB NOT NULL,
+    "erase_original" BOOLEAN NOT NULL DEFAULT false,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "date_modified" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "encryption_rule_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "encryption_rule_key_id_fkey" FOREIGN KEY ("key_id") REFERENCES "key" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
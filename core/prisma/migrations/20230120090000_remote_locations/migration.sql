@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "location" ADD COLUMN "remote_config" TEXT;
+ALTER TABLE "location" ADD COLUMN "remote_credentials" BLOB;
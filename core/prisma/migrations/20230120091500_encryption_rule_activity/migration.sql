@@ -0,0 +1,12 @@
+-- CreateTable
+CREATE TABLE "encryption_rule_activity" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "path" TEXT NOT NULL,
+    "location_id" INTEGER NOT NULL,
+    "outcome" INTEGER NOT NULL,
+    "rule_id" INTEGER,
+    "error" TEXT,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "encryption_rule_activity_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "encryption_rule_activity_rule_id_fkey" FOREIGN KEY ("rule_id") REFERENCES "encryption_rule" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+);
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "job" ADD COLUMN "bytes_processed" TEXT NOT NULL DEFAULT '0';
@@ -0,0 +1,13 @@
+-- CreateTable
+CREATE TABLE "job_log" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "job_id" BLOB NOT NULL,
+    "level" INTEGER NOT NULL,
+    "message" TEXT NOT NULL,
+    "path" TEXT,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "job_log_job_id_fkey" FOREIGN KEY ("job_id") REFERENCES "job" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "job_log_job_id_idx" ON "job_log"("job_id");
@@ -0,0 +1,14 @@
+-- CreateTable
+CREATE TABLE "schedule" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "name" TEXT NOT NULL,
+    "task" TEXT NOT NULL,
+    "interval" INTEGER,
+    "cron" TEXT,
+    "enabled" BOOLEAN NOT NULL DEFAULT true,
+    "last_run" DATETIME,
+    "last_error" TEXT,
+    "next_run" DATETIME NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "date_modified" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "location" ADD COLUMN "symlink_policy" INTEGER NOT NULL DEFAULT 0;
+
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "is_symlink" BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,12 @@
+-- CreateTable
+CREATE TABLE "native_attribute" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "object_id" INTEGER NOT NULL,
+    "kind" INTEGER NOT NULL,
+    "name" TEXT NOT NULL,
+    "value" TEXT NOT NULL,
+    CONSTRAINT "native_attribute_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "native_attribute_object_id_kind_name_key" ON "native_attribute"("object_id", "kind", "name");
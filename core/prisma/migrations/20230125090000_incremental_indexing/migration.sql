@@ -0,0 +1,4 @@
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "size_in_bytes" TEXT;
+ALTER TABLE "file_path" ADD COLUMN "inode" TEXT;
+ALTER TABLE "file_path" ADD COLUMN "fingerprint" TEXT;
@@ -0,0 +1,6 @@
+-- AlterTable
+ALTER TABLE "object" ADD COLUMN "content_hash" TEXT;
+ALTER TABLE "object" ADD COLUMN "content_hash_kind" INTEGER;
+
+-- CreateIndex
+CREATE INDEX "object_content_hash_idx" ON "object"("content_hash");
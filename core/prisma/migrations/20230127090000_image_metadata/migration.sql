@@ -0,0 +1,6 @@
+-- AlterTable
+ALTER TABLE "media_data" ADD COLUMN "capture_date" DATETIME;
+ALTER TABLE "media_data" ADD COLUMN "orientation" INTEGER;
+
+-- CreateIndex
+CREATE INDEX "media_data_capture_date_idx" ON "media_data"("capture_date");
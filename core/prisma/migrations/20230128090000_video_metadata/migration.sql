@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "media_data" ADD COLUMN "container" TEXT;
@@ -0,0 +1,14 @@
+-- AlterTable
+ALTER TABLE "media_data" ADD COLUMN "title" TEXT;
+ALTER TABLE "media_data" ADD COLUMN "artist" TEXT;
+ALTER TABLE "media_data" ADD COLUMN "album" TEXT;
+ALTER TABLE "media_data" ADD COLUMN "album_artist" TEXT;
+ALTER TABLE "media_data" ADD COLUMN "genre" TEXT;
+ALTER TABLE "media_data" ADD COLUMN "track_number" INTEGER;
+ALTER TABLE "media_data" ADD COLUMN "year" INTEGER;
+
+-- CreateIndex
+CREATE INDEX "media_data_artist_idx" ON "media_data"("artist");
+
+-- CreateIndex
+CREATE INDEX "media_data_album_idx" ON "media_data"("album");
@@ -0,0 +1,21 @@
+-- RedefineTables
+PRAGMA foreign_keys=OFF;
+CREATE TABLE "new_tag" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT,
+    "color" TEXT,
+    "total_objects" INTEGER DEFAULT 0,
+    "redundancy_goal" INTEGER DEFAULT 1,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "date_modified" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "parent_id" INTEGER,
+    CONSTRAINT "tag_parent_id_fkey" FOREIGN KEY ("parent_id") REFERENCES "tag" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+);
+INSERT INTO "new_tag" ("color", "date_created", "date_modified", "id", "name", "pub_id", "redundancy_goal", "total_objects") SELECT "color", "date_created", "date_modified", "id", "name", "pub_id", "redundancy_goal", "total_objects" FROM "tag";
+DROP TABLE "tag";
+ALTER TABLE "new_tag" RENAME TO "tag";
+CREATE UNIQUE INDEX "tag_pub_id_key" ON "tag"("pub_id");
+CREATE INDEX "tag_parent_id_idx" ON "tag"("parent_id");
+PRAGMA foreign_key_check;
+PRAGMA foreign_keys=ON;
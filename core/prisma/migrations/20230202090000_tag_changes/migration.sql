@@ -0,0 +1,12 @@
+-- CreateTable
+CREATE TABLE "tag_change" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "change_id" BLOB NOT NULL,
+    "tag_id" INTEGER NOT NULL,
+    "object_id" INTEGER NOT NULL,
+    "assigned" BOOLEAN NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateIndex
+CREATE INDEX "tag_change_change_id_idx" ON "tag_change"("change_id");
@@ -0,0 +1,22 @@
+-- AlterTable
+ALTER TABLE "object_in_album" ADD COLUMN "position" INTEGER NOT NULL DEFAULT 0;
+
+-- RedefineTables
+PRAGMA foreign_keys=OFF;
+CREATE TABLE "new_album" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT NOT NULL,
+    "is_hidden" BOOLEAN NOT NULL DEFAULT false,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "date_modified" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "parent_id" INTEGER,
+    CONSTRAINT "album_parent_id_fkey" FOREIGN KEY ("parent_id") REFERENCES "album" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+);
+INSERT INTO "new_album" ("date_created", "date_modified", "id", "is_hidden", "name", "pub_id") SELECT "date_created", "date_modified", "id", "is_hidden", "name", "pub_id" FROM "album";
+DROP TABLE "album";
+ALTER TABLE "new_album" RENAME TO "album";
+CREATE UNIQUE INDEX "album_pub_id_key" ON "album"("pub_id");
+CREATE INDEX "album_parent_id_idx" ON "album"("parent_id");
+PRAGMA foreign_key_check;
+PRAGMA foreign_keys=ON;
@@ -0,0 +1,15 @@
+-- CreateTable
+CREATE TABLE "trashed_item" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "materialized_path" TEXT NOT NULL,
+    "location_id" INTEGER NOT NULL,
+    "is_dir" BOOLEAN NOT NULL DEFAULT false,
+    "size_in_bytes" TEXT NOT NULL DEFAULT '0',
+    "object_id" INTEGER,
+    "date_trashed" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "trashed_item_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "trashed_item_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE SET NULL ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "trashed_item_location_id_idx" ON "trashed_item"("location_id");
@@ -0,0 +1,22 @@
+-- AlterTable
+ALTER TABLE "location" ADD COLUMN "versioning" BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE "location" ADD COLUMN "max_versions" INTEGER;
+ALTER TABLE "location" ADD COLUMN "max_version_age" INTEGER;
+
+-- CreateTable
+CREATE TABLE "file_version" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "materialized_path" TEXT NOT NULL,
+    "location_id" INTEGER NOT NULL,
+    "content_hash" TEXT NOT NULL,
+    "size_in_bytes" TEXT NOT NULL,
+    "date_modified" DATETIME NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "file_version_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "file_version_location_id_materialized_path_idx" ON "file_version"("location_id", "materialized_path");
+
+-- CreateIndex
+CREATE INDEX "file_version_content_hash_idx" ON "file_version"("content_hash");
@@ -0,0 +1,13 @@
+-- CreateTable
+CREATE TABLE "location_snapshot" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "location_id" INTEGER NOT NULL,
+    "total_size" TEXT NOT NULL,
+    "file_count" INTEGER NOT NULL,
+    "directory_count" INTEGER NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "location_snapshot_location_id_fkey" FOREIGN KEY ("location_id") REFERENCES "location" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "location_snapshot_location_id_date_created_idx" ON "location_snapshot"("location_id", "date_created");
@@ -0,0 +1,220 @@
+use rspc::Type;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+	api::locations::{object_with_file_paths, ExplorerContext, ExplorerData, ExplorerItem},
+	invalidate_query,
+	object::album::{add_to_album, album_object_ids, find_album, reorder_album, set_album_parent},
+	prisma::{album, object, object_in_album},
+};
+
+use super::{utils::LibraryRequest, RouterBuilder};
+
+pub(crate) fn mount() -> RouterBuilder {
+	<RouterBuilder>::new()
+		.library_query("list", |t| {
+			t(
+				|_, _: (), library| async move {
+					Ok(library.db.album().find_many(vec![]).exec().await?)
+				},
+			)
+		})
+		.library_query("get", |t| {
+			t(
+				|_, album_id: i32, library| async move { Ok(find_album(&library.db, album_id).await?) },
+			)
+		})
+		// this lists the objects within an album, in the order that the user put them in
+		.library_query("getExplorerData", |t| {
+			t(|_, album_id: i32, library| async move {
+				let album = find_album(&library.db, album_id).await?;
+				let ids = album_object_ids(&library.db, album_id).await?;
+
+				let mut objects = library
+					.db
+					.object()
+					.find_many(vec![object::id::in_vec(ids.clone())])
+					.include(object_with_file_paths::include())
+					.exec()
+					.await?;
+				objects.sort_by_key(|object| ids.iter().position(|id| *id == object.id));
+
+				Ok(ExplorerData {
+					context: ExplorerContext::Album(album),
+					items: objects
+						.into_iter()
+						.map(|object| ExplorerItem::Object(Box::new(object)))
+						.collect(),
+				})
+			})
+		})
+		.library_mutation("create", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct AlbumCreateArgs {
+				pub name: String,
+				/// the album that the new album is nested under
+				pub parent_id: Option<i32>,
+			}
+
+			t(|_, args: AlbumCreateArgs, library| async move {
+				if let Some(parent_id) = args.parent_id {
+					find_album(&library.db, parent_id).await?;
+				}
+
+				let album = library
+					.db
+					.album()
+					.create(
+						Uuid::new_v4().as_bytes().to_vec(),
+						args.name,
+						vec![album::parent_id::set(args.parent_id)],
+					)
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "albums.list");
+
+				Ok(album)
+			})
+		})
+		.library_mutation("update", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct AlbumUpdateArgs {
+				pub id: i32,
+				pub name: Option<String>,
+				pub is_hidden: Option<bool>,
+			}
+
+			t(|_, args: AlbumUpdateArgs, library| async move {
+				find_album(&library.db, args.id).await?;
+
+				let mut params = vec![album::date_modified::set(chrono::Utc::now().into())];
+				if let Some(name) = args.name {
+					params.push(album::name::set(name));
+				}
+				if let Some(is_hidden) = args.is_hidden {
+					params.push(album::is_hidden::set(is_hidden));
+				}
+
+				let album = library
+					.db
+					.album()
+					.update(album::id::equals(args.id), params)
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "albums.list");
+				invalidate_query!(library, "albums.getExplorerData");
+
+				Ok(album)
+			})
+		})
+		// this nests an album under another, or moves it back to the top level if there's no parent
+		.library_mutation("setParent", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct AlbumSetParentArgs {
+				pub id: i32,
+				pub parent_id: Option<i32>,
+			}
+
+			t(|_, args: AlbumSetParentArgs, library| async move {
+				let album = set_album_parent(&library.db, args.id, args.parent_id).await?;
+
+				invalidate_query!(library, "albums.list");
+
+				Ok(album)
+			})
+		})
+		// the objects within the album are left as they are, as they're only ever referenced by it
+		.library_mutation("delete", |t| {
+			t(|_, album_id: i32, library| async move {
+				let album = find_album(&library.db, album_id).await?;
+
+				// the album's children take its place, rather than all ending up at the top level
+				library
+					.db
+					.album()
+					.update_many(
+						vec![album::parent_id::equals(Some(album_id))],
+						vec![album::parent_id::set(album.parent_id)],
+					)
+					.exec()
+					.await?;
+
+				library
+					.db
+					.object_in_album()
+					.delete_many(vec![object_in_album::album_id::equals(album_id)])
+					.exec()
+					.await?;
+
+				library
+					.db
+					.album()
+					.delete(album::id::equals(album_id))
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "albums.list");
+
+				Ok(())
+			})
+		})
+		.library_mutation("addObjects", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct AlbumObjectsArgs {
+				pub album_id: i32,
+				pub object_ids: Vec<i32>,
+			}
+
+			t(|_, args: AlbumObjectsArgs, library| async move {
+				let added = add_to_album(&library.db, args.album_id, args.object_ids).await?;
+
+				invalidate_query!(library, "albums.getExplorerData");
+
+				Ok(added)
+			})
+		})
+		.library_mutation("removeObjects", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct AlbumObjectsArgs {
+				pub album_id: i32,
+				pub object_ids: Vec<i32>,
+			}
+
+			t(|_, args: AlbumObjectsArgs, library| async move {
+				find_album(&library.db, args.album_id).await?;
+
+				let removed = library
+					.db
+					.object_in_album()
+					.delete_many(vec![
+						object_in_album::album_id::equals(args.album_id),
+						object_in_album::object_id::in_vec(args.object_ids),
+					])
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "albums.getExplorerData");
+
+				Ok(removed)
+			})
+		})
+		// this puts the album's objects in the given order, with any that aren't given kept after them
+		.library_mutation("reorder", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct AlbumReorderArgs {
+				pub album_id: i32,
+				pub object_ids: Vec<i32>,
+			}
+
+			t(|_, args: AlbumReorderArgs, library| async move {
+				reorder_album(&library.db, args.album_id, args.object_ids).await?;
+
+				invalidate_query!(library, "albums.getExplorerData");
+
+				Ok(())
+			})
+		})
+}
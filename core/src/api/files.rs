@@ -1,19 +1,93 @@
 use crate::{
 	invalidate_query,
 	job::Job,
-	location::fetch_location,
-	object::fs::{
-		decrypt::{FileDecryptorJob, FileDecryptorJobInit},
-		encrypt::{FileEncryptorJob, FileEncryptorJobInit},
+	library::{decrypt_gps_location, decrypt_note, set_note, LibraryContext},
+	location::{fetch_location, file_path_helper::parent_materialized_path, remote::RemoteError},
+	node::KeyCapability,
+	object::{
+		fs::{
+			archive::{FileArchiverJob, FileArchiverJobInit},
+			audit::{FileHeaderAuditorJob, FileHeaderAuditorJobInit},
+			copy::{FileCopierJob, FileCopierJobInit},
+			decrypt::{FileDecryptorJob, FileDecryptorJobInit},
+			delete::{FileDeleterJob, FileDeleterJobInit},
+			duplicates::{
+				find_duplicates, DuplicateAction, DuplicateResolverJob, DuplicateResolverJobInit,
+			},
+			encrypt::{FileEncryptorJob, FileEncryptorJobInit},
+			r#move::{FileMoverJob, FileMoverJobInit},
+			trash::{empty_trash, restore_trashed_item},
+			vault::read_vault_file,
+			verify::{FileVerifierJob, FileVerifierJobInit},
+			ConflictStrategy, ObjectTarget,
+		},
+		identifier_job::full_identifier_job::{FullFileIdentifierJob, FullFileIdentifierJobInit},
+		native_attributes,
+		version::restore_version,
 	},
-	prisma::object,
+	p2p::{hosting_node, DelegatedJob},
+	prisma::{file_path, file_version, media_data, object, trashed_item},
 };
 
+use std::{
+	cmp::Reverse,
+	collections::{BTreeMap, HashMap},
+	time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::{operator::or, Direction};
 use rspc::{ErrorCode, Type};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use super::{utils::LibraryRequest, RouterBuilder};
 
+/// This decrypts the GPS location of some media data in place, as long as the key manager is unlocked.
+fn decrypt_media_data(library: &LibraryContext, media_data: &mut media_data::Data) {
+	if let Some((longitude, latitude)) = media_data
+		.encrypted_location
+		.as_deref()
+		.and_then(|location| decrypt_gps_location(library, location))
+	{
+		media_data.longitude = longitude;
+		media_data.latitude = latitude;
+	}
+}
+
+/// This checks that the locations a file operation works within exist, so it fails straight away rather than once its job has started.
+async fn check_locations(
+	library: &LibraryContext,
+	location_ids: &[i32],
+) -> Result<(), rspc::Error> {
+	for location_id in location_ids {
+		if fetch_location(library, *location_id)
+			.exec()
+			.await?
+			.is_none()
+		{
+			return Err(rspc::Error::new(
+				ErrorCode::NotFound,
+				"Location not found".into(),
+			));
+		}
+	}
+
+	Ok(())
+}
+
+/// This checks that a new name for a file is a single valid name, rather than a path that'd move it somewhere else.
+fn check_file_name(name: &str) -> Result<(), rspc::Error> {
+	if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+		return Err(rspc::Error::new(
+			ErrorCode::BadRequest,
+			"A file's name can't be empty or contain a path separator".into(),
+		));
+	}
+
+	Ok(())
+}
+
 pub(crate) fn mount() -> RouterBuilder {
 	<RouterBuilder>::new()
 		.library_query("get", |t| {
@@ -21,14 +95,29 @@ pub(crate) fn mount() -> RouterBuilder {
 			pub struct GetArgs {
 				pub id: i32,
 			}
-			t(|_, args: GetArgs, library| async move {
-				Ok(library
+			t(|ctx, args: GetArgs, library| async move {
+				let mut object = library
 					.db
 					.object()
 					.find_unique(object::id::equals(args.id))
-					.include(object::include!({ file_paths media_data }))
+					.include(object::include!({ file_paths media_data native_attributes }))
 					.exec()
-					.await?)
+					.await?;
+
+				// encrypted fields are decrypted transparently, as long as the key manager is unlocked
+				// they're plaintext once they've been decrypted, so requests that can't reveal keys just get them empty
+				let reveal = ctx.authorize(KeyCapability::Reveal).await.is_ok();
+				if let Some(object) = object.as_mut().filter(|_| reveal) {
+					if let Some(encrypted_note) = &object.encrypted_note {
+						object.note = decrypt_note(&library, encrypted_note);
+					}
+
+					if let Some(media_data) = &mut object.media_data {
+						decrypt_media_data(&library, media_data);
+					}
+				}
+
+				Ok(object)
 			})
 		})
 		.library_mutation("setNote", |t| {
@@ -39,42 +128,71 @@ pub(crate) fn mount() -> RouterBuilder {
 			}
 
 			t(|_, args: SetNoteArgs, library| async move {
+				set_note(&library, args.id, args.note).await?;
+
+				invalidate_query!(library, "locations.getExplorerData");
+				invalidate_query!(library, "savedSearches.getExplorerData");
+				invalidate_query!(library, "tags.getExplorerData");
+
+				Ok(())
+			})
+		})
+		.library_mutation("setFavorite", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct SetFavoriteArgs {
+				pub id: i32,
+				pub favorite: bool,
+			}
+
+			t(|_, args: SetFavoriteArgs, library| async move {
 				library
 					.db
 					.object()
 					.update(
 						object::id::equals(args.id),
-						vec![object::note::set(args.note)],
+						vec![object::favorite::set(args.favorite)],
 					)
 					.exec()
 					.await?;
 
 				invalidate_query!(library, "locations.getExplorerData");
+				invalidate_query!(library, "savedSearches.getExplorerData");
 				invalidate_query!(library, "tags.getExplorerData");
+				invalidate_query!(library, "albums.getExplorerData");
 
 				Ok(())
 			})
 		})
-		.library_mutation("setFavorite", |t| {
+		// the rating is cleared by setting it to `null`
+		.library_mutation("setRating", |t| {
 			#[derive(Type, Deserialize)]
-			pub struct SetFavoriteArgs {
+			pub struct SetRatingArgs {
 				pub id: i32,
-				pub favorite: bool,
+				pub rating: Option<u8>,
 			}
 
-			t(|_, args: SetFavoriteArgs, library| async move {
+			t(|_, args: SetRatingArgs, library| async move {
+				if matches!(args.rating, Some(rating) if !(1..=5).contains(&rating)) {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"A rating must be between 1 and 5 stars".into(),
+					));
+				}
+
 				library
 					.db
 					.object()
 					.update(
 						object::id::equals(args.id),
-						vec![object::favorite::set(args.favorite)],
+						vec![object::rating::set(args.rating.map(Into::into))],
 					)
 					.exec()
 					.await?;
 
 				invalidate_query!(library, "locations.getExplorerData");
+				invalidate_query!(library, "savedSearches.getExplorerData");
 				invalidate_query!(library, "tags.getExplorerData");
+				invalidate_query!(library, "albums.getExplorerData");
 
 				Ok(())
 			})
@@ -89,11 +207,244 @@ pub(crate) fn mount() -> RouterBuilder {
 					.await?;
 
 				invalidate_query!(library, "locations.getExplorerData");
+				invalidate_query!(library, "savedSearches.getExplorerData");
+				Ok(())
+			})
+		})
+		.library_mutation("copyFiles", |t| {
+			t(|_, args: FileCopierJobInit, library| async move {
+				check_locations(&library, &[args.location_id, args.target_location_id]).await?;
+
+				library.spawn_job(Job::new(args, FileCopierJob {})).await;
+
+				Ok(())
+			})
+		})
+		.library_mutation("moveFiles", |t| {
+			t(|_, args: FileMoverJobInit, library| async move {
+				check_locations(&library, &[args.location_id, args.target_location_id]).await?;
+
+				if let Some(new_name) = &args.new_name {
+					if args.targets.len() != 1 {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"Only a single file can be moved with a new name".into(),
+						));
+					}
+
+					check_file_name(new_name)?;
+				}
+
+				library.spawn_job(Job::new(args, FileMoverJob {})).await;
+
 				Ok(())
 			})
 		})
-		.library_mutation("encryptFiles", |t| {
-			t(|_, args: FileEncryptorJobInit, library| async move {
+		// renaming is a move within the same directory, so it's done by the same job
+		.library_mutation("renameFile", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct RenameFileArgs {
+				pub location_id: i32,
+				pub file_path_id: i32,
+				pub new_name: String,
+				#[serde(default)]
+				pub on_conflict: ConflictStrategy,
+			}
+
+			t(|_, args: RenameFileArgs, library| async move {
+				check_file_name(&args.new_name)?;
+
+				let file_path = library
+					.db
+					.file_path()
+					.find_unique(file_path::location_id_id(
+						args.location_id,
+						args.file_path_id,
+					))
+					.exec()
+					.await?
+					.ok_or_else(|| {
+						rspc::Error::new(ErrorCode::NotFound, "File not found".into())
+					})?;
+
+				// without its parent, the file would be moved to the root of the location
+				if file_path.parent_id.is_none()
+					&& parent_materialized_path(&file_path.materialized_path) != "/"
+				{
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"The file's directory hasn't been indexed yet".into(),
+					));
+				}
+
+				library
+					.spawn_job(Job::new(
+						FileMoverJobInit {
+							location_id: args.location_id,
+							targets: vec![ObjectTarget::FilePath(file_path.id)],
+							target_location_id: args.location_id,
+							target_directory_id: file_path.parent_id,
+							new_name: Some(args.new_name),
+							on_conflict: args.on_conflict,
+						},
+						FileMoverJob {},
+					))
+					.await;
+
+				Ok(())
+			})
+		})
+		.library_mutation("deleteFiles", |t| {
+			t(|_, args: FileDeleterJobInit, library| async move {
+				check_locations(&library, &[args.location_id]).await?;
+
+				library.spawn_job(Job::new(args, FileDeleterJob {})).await;
+
+				Ok(())
+			})
+		})
+		.library_query("listTrash", |t| {
+			t(|_, _: (), library| async move {
+				Ok(library
+					.db
+					.trashed_item()
+					.find_many(vec![])
+					.with(trashed_item::object::fetch())
+					.order_by(trashed_item::date_trashed::order(Direction::Desc))
+					.exec()
+					.await?)
+			})
+		})
+		.library_mutation("restoreTrashed", |t| {
+			t(|_, id: i32, library| async move {
+				let item = restore_trashed_item(&library, id).await?;
+
+				// anything that was within a restored directory is recorded without an object
+				if item.is_dir {
+					library
+						.spawn_job(Job::new(
+							FullFileIdentifierJobInit {
+								location_id: item.location_id,
+								sub_path: None,
+							},
+							FullFileIdentifierJob {},
+						))
+						.await;
+				}
+
+				invalidate_query!(library, "files.listTrash");
+				invalidate_query!(library, "locations.getExplorerData");
+				invalidate_query!(library, "savedSearches.getExplorerData");
+
+				Ok(())
+			})
+		})
+		.library_mutation("emptyTrash", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct EmptyTrashArgs {
+				// if this isn't set, everything that was trashed through Spacedrive is deleted
+				pub ids: Option<Vec<i32>>,
+			}
+
+			t(|_, args: EmptyTrashArgs, library| async move {
+				let filters = args
+					.ids
+					.map(|ids| vec![trashed_item::id::in_vec(ids)])
+					.unwrap_or_default();
+
+				empty_trash(&library, filters).await?;
+
+				invalidate_query!(library, "files.listTrash");
+
+				Ok(())
+			})
+		})
+		.library_query("listVersions", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct ListVersionsArgs {
+				pub location_id: i32,
+				pub materialized_path: String,
+			}
+
+			t(|_, args: ListVersionsArgs, library| async move {
+				Ok(library
+					.db
+					.file_version()
+					.find_many(vec![
+						file_version::location_id::equals(args.location_id),
+						file_version::materialized_path::equals(args.materialized_path),
+					])
+					.order_by(file_version::date_created::order(Direction::Desc))
+					.exec()
+					.await?)
+			})
+		})
+		.library_mutation("restoreVersion", |t| {
+			t(|_, id: i32, library| async move {
+				restore_version(&library, id).await?;
+
+				invalidate_query!(library, "files.listVersions");
+				invalidate_query!(library, "locations.getExplorerData");
+				invalidate_query!(library, "savedSearches.getExplorerData");
+
+				Ok(())
+			})
+		})
+		.library_mutation("encrypt", |t| {
+			t(|ctx, args: FileEncryptorJobInit, library| async move {
+				let location = fetch_location(&library, args.location_id)
+					.exec()
+					.await?
+					.ok_or_else(|| {
+						rspc::Error::new(ErrorCode::NotFound, "Location not found".into())
+					})?;
+
+				if let Some(upload) = &args.upload {
+					if args.output_directory.is_some() {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"Encrypted files can either be written to an output directory or uploaded, not both".into(),
+						));
+					}
+
+					if args.erase_original {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"Originals can't be erased when the encrypted files are uploaded, as they can't be verified before they're erased".into(),
+						));
+					}
+
+					fetch_location(&library, upload.location_id)
+						.exec()
+						.await?
+						.filter(|location| location.remote_config.is_some())
+						.ok_or(RemoteError::NotRemote(upload.location_id))?;
+				}
+
+				if !library.key_manager.keymount_contains(args.key_uuid) {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"The key must be mounted before it can be used for encryption".into(),
+					));
+				}
+
+				// locations hosted by another node are only reachable from there, so the job is run by that node instead
+				if let Some(node_id) = hosting_node(&library, &location).await? {
+					let job = DelegatedJob::encrypt(&library, &location, args).await?;
+					ctx.p2p.delegate_job(&library, node_id, job).await?;
+
+					return Ok(());
+				}
+
+				library.spawn_job(Job::new(args, FileEncryptorJob {})).await;
+				invalidate_query!(library, "locations.getExplorerData");
+				invalidate_query!(library, "savedSearches.getExplorerData");
+
+				Ok(())
+			})
+		})
+		.library_mutation("encryptArchive", |t| {
+			t(|_, args: FileArchiverJobInit, library| async move {
 				if fetch_location(&library, args.location_id)
 					.exec()
 					.await?
@@ -105,13 +456,432 @@ pub(crate) fn mount() -> RouterBuilder {
 					));
 				}
 
-				library.spawn_job(Job::new(args, FileEncryptorJob {})).await;
+				if !library.key_manager.keymount_contains(args.key_uuid) {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"The key must be mounted before it can be used for encryption".into(),
+					));
+				}
+
+				library.spawn_job(Job::new(args, FileArchiverJob {})).await;
 				invalidate_query!(library, "locations.getExplorerData");
+				invalidate_query!(library, "savedSearches.getExplorerData");
+
+				Ok(())
+			})
+		})
+		// this finds the objects with the given contents, e.g. to reconcile them with another library's objects
+		.library_query("getByContentHash", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct GetByContentHashArgs {
+				pub content_hashes: Vec<String>,
+			}
+			t(|_, args: GetByContentHashArgs, library| async move {
+				Ok(library
+					.db
+					.object()
+					.find_many(vec![object::content_hash::in_vec(args.content_hashes)])
+					.include(object::include!({ file_paths }))
+					.exec()
+					.await?)
+			})
+		})
+		// this finds objects by the platform-native tags, comments and streams that were found on their files
+		.library_query("searchByAttribute", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct SearchByAttributeArgs {
+				pub query: String,
+				/// e.g. `com.apple.metadata:_kMDItemUserTags` for macOS tags
+				pub name: Option<String>,
+			}
+			t(|_, args: SearchByAttributeArgs, library| async move {
+				Ok(library
+					.db
+					.object()
+					.find_many(native_attributes::search_filters(&args.query, args.name))
+					.include(object::include!({ file_paths native_attributes }))
+					.exec()
+					.await?)
+			})
+		})
+		// this finds photos and videos by when, where and with what they were taken (or by their video properties), and songs by their tags, from the media data that's extracted while indexing
+		.library_query("searchMedia", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct SearchMediaArgs {
+				pub captured_after: Option<DateTime<Utc>>,
+				pub captured_before: Option<DateTime<Utc>>,
+				/// this matches either the make or the model of the camera
+				pub camera: Option<String>,
+				/// GPS locations can't be searched while they're encrypted
+				pub bounds: Option<GeoBounds>,
+				pub min_duration_seconds: Option<i32>,
+				pub max_duration_seconds: Option<i32>,
+				/// e.g. "hevc", which matches any of a video's streams
+				pub codec: Option<String>,
+				/// e.g. "matroska"
+				pub container: Option<String>,
+				/// e.g. 1080, for videos that are at least full HD
+				pub min_height: Option<i32>,
+				/// this matches either the artist or the album artist of a song
+				pub artist: Option<String>,
+				pub album: Option<String>,
+				pub title: Option<String>,
+				pub genre: Option<String>,
+			}
+
+			#[derive(Type, Deserialize)]
+			pub struct GeoBounds {
+				pub min_longitude: f64,
+				pub max_longitude: f64,
+				pub min_latitude: f64,
+				pub max_latitude: f64,
+			}
+
+			t(|ctx, args: SearchMediaArgs, library| async move {
+				let mut filters = vec![];
+
+				if let Some(captured_after) = args.captured_after {
+					filters.push(media_data::capture_date::gte(captured_after.into()));
+				}
+				if let Some(captured_before) = args.captured_before {
+					filters.push(media_data::capture_date::lte(captured_before.into()));
+				}
+				if let Some(camera) = args.camera {
+					filters.push(or(vec![
+						media_data::capture_device_make::contains(camera.clone()),
+						media_data::capture_device_model::contains(camera),
+					]));
+				}
+				if let Some(bounds) = args.bounds {
+					filters.extend([
+						media_data::longitude::gte(bounds.min_longitude),
+						media_data::longitude::lte(bounds.max_longitude),
+						media_data::latitude::gte(bounds.min_latitude),
+						media_data::latitude::lte(bounds.max_latitude),
+					]);
+				}
+				if let Some(min_duration_seconds) = args.min_duration_seconds {
+					filters.push(media_data::duration_seconds::gte(min_duration_seconds));
+				}
+				if let Some(max_duration_seconds) = args.max_duration_seconds {
+					filters.push(media_data::duration_seconds::lte(max_duration_seconds));
+				}
+				if let Some(codec) = args.codec {
+					filters.push(media_data::codecs::contains(codec));
+				}
+				if let Some(container) = args.container {
+					filters.push(media_data::container::contains(container));
+				}
+				if let Some(min_height) = args.min_height {
+					filters.push(media_data::pixel_height::gte(min_height));
+				}
+				if let Some(artist) = args.artist {
+					filters.push(or(vec![
+						media_data::artist::contains(artist.clone()),
+						media_data::album_artist::contains(artist),
+					]));
+				}
+				if let Some(album) = args.album {
+					filters.push(media_data::album::contains(album));
+				}
+				if let Some(title) = args.title {
+					filters.push(media_data::title::contains(title));
+				}
+				if let Some(genre) = args.genre {
+					filters.push(media_data::genre::contains(genre));
+				}
+
+				let mut objects = library
+					.db
+					.object()
+					.find_many(vec![object::media_data::is(filters)])
+					.include(object::include!({ file_paths media_data }))
+					.exec()
+					.await?;
+
+				// locations are only decrypted for requests that can reveal keys, as with `files.get`
+				if ctx.authorize(KeyCapability::Reveal).await.is_ok() {
+					for media_data in objects
+						.iter_mut()
+						.filter_map(|object| object.media_data.as_mut())
+					{
+						decrypt_media_data(&library, media_data);
+					}
+				}
+
+				// the newest photos come first, and those without a capture date come last
+				objects.sort_by_key(|object| {
+					Reverse(
+						object
+							.media_data
+							.as_ref()
+							.and_then(|media_data| media_data.capture_date),
+					)
+				});
+
+				Ok(objects)
+			})
+		})
+		// this groups songs into albums by their tags, so music can be browsed without relying on how the files are named
+		.library_query("getAlbums", |t| {
+			#[derive(Type, Serialize)]
+			pub struct MusicAlbum {
+				pub name: String,
+				pub artist: Option<String>,
+				pub year: Option<i32>,
+				/// the album's songs, in the order of their track numbers
+				pub object_ids: Vec<i32>,
+			}
+
+			t(|_, _: (), library| async move {
+				let mut albums = BTreeMap::<
+					(Option<String>, String),
+					(MusicAlbum, Vec<(Option<i32>, i32)>),
+				>::new();
+
+				for media_data in library
+					.db
+					.media_data()
+					.find_many(vec![media_data::album::not(None)])
+					.exec()
+					.await?
+				{
+					let name = media_data.album.unwrap_or_default();
+					// compilations have many artists, which only share their album artist
+					let artist = media_data.album_artist.or(media_data.artist);
+
+					let (album, tracks) = albums
+						.entry((artist.clone(), name.clone()))
+						.or_insert_with(|| {
+							(
+								MusicAlbum {
+									name,
+									artist,
+									year: None,
+									object_ids: vec![],
+								},
+								vec![],
+							)
+						});
+
+					album.year = album.year.or(media_data.year);
+					tracks.push((media_data.track_number, media_data.id));
+				}
+
+				Ok(albums
+					.into_values()
+					.map(|(mut album, mut tracks)| {
+						tracks.sort();
+						album.object_ids = tracks.into_iter().map(|(_, id)| id).collect();
+						album
+					})
+					.collect::<Vec<_>>())
+			})
+		})
+		.library_query("readVault", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct ReadVaultArgs {
+				pub location_id: i32,
+				pub file_path_id: i32,
+			}
+
+			t(|ctx, args: ReadVaultArgs, library| async move {
+				// this returns plaintext, so it's treated the same as revealing a key
+				ctx.authorize(KeyCapability::Reveal).await?;
+
+				Ok(read_vault_file(&library, args.location_id, args.file_path_id).await?)
+			})
+		})
+		.library_mutation("verifyEncrypted", |t| {
+			t(|ctx, args: FileVerifierJobInit, library| async move {
+				// locations hosted by other nodes are verified by those nodes, so there's a job for each node
+				let mut local_location_ids = vec![];
+				let mut delegated_locations = HashMap::<_, Vec<_>>::new();
+
+				for location_id in args.location_ids {
+					let location = fetch_location(&library, location_id)
+						.exec()
+						.await?
+						.ok_or_else(|| {
+							rspc::Error::new(ErrorCode::NotFound, "Location not found".into())
+						})?;
+
+					match hosting_node(&library, &location).await? {
+						Some(node_id) => delegated_locations
+							.entry(node_id)
+							.or_default()
+							.push(location),
+						None => local_location_ids.push(location_id),
+					}
+				}
+
+				for (node_id, locations) in delegated_locations {
+					ctx.p2p
+						.delegate_job(&library, node_id, DelegatedJob::verify(&locations))
+						.await?;
+				}
+
+				if !local_location_ids.is_empty() {
+					library
+						.spawn_job(Job::new(
+							FileVerifierJobInit {
+								location_ids: local_location_ids,
+							},
+							FileVerifierJob {},
+						))
+						.await;
+				}
+
+				Ok(())
+			})
+		})
+		// this only reads headers, so it reports files that don't match the database (e.g. ones that were re-encrypted elsewhere) without verifying their contents
+		.library_mutation("auditEncrypted", |t| {
+			t(|_, args: FileHeaderAuditorJobInit, library| async move {
+				for location_id in &args.location_ids {
+					if fetch_location(&library, *location_id)
+						.exec()
+						.await?
+						.is_none()
+					{
+						return Err(rspc::Error::new(
+							ErrorCode::NotFound,
+							"Location not found".into(),
+						));
+					}
+				}
+
+				library
+					.spawn_job(Job::new(args, FileHeaderAuditorJob {}))
+					.await;
+
+				Ok(())
+			})
+		})
+		.library_query("getDuplicates", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct GetDuplicatesArgs {
+				/// this is the whole library if it isn't given
+				pub location_ids: Option<Vec<i32>>,
+			}
+			t(|_, args: GetDuplicatesArgs, library| async move {
+				Ok(find_duplicates(&library.db, args.location_ids).await?)
+			})
+		})
+		.library_mutation("resolveDuplicates", |t| {
+			t(|_, args: DuplicateResolverJobInit, library| async move {
+				if let DuplicateAction::MoveToReview(review_dir) = &args.action {
+					if !review_dir.is_absolute() {
+						return Err(rspc::Error::new(
+							ErrorCode::BadRequest,
+							"The review directory must be an absolute path".into(),
+						));
+					}
+				}
+
+				library
+					.spawn_job(Job::new(args, DuplicateResolverJob {}))
+					.await;
+
+				Ok(())
+			})
+		})
+		.library_mutation("openEncrypted", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct OpenEncryptedArgs {
+				pub location_id: i32,
+				pub file_path_id: i32,
+				pub ttl: Option<u64>, // in seconds, the node's default is used if this isn't set
+			}
+
+			t(|ctx, args: OpenEncryptedArgs, library| async move {
+				// this writes plaintext to the disk, so it's treated the same as revealing a key
+				ctx.authorize(KeyCapability::Reveal).await?;
+
+				let ttl = match args.ttl {
+					Some(ttl) => ttl,
+					None => library.config().get().await.decrypted_file_ttl,
+				};
+
+				Ok(library
+					.temp_files()
+					.open(
+						&library,
+						args.location_id,
+						args.file_path_id,
+						Duration::from_secs(ttl),
+					)
+					.await?)
+			})
+		})
+		.library_mutation("shredDecrypted", |t| {
+			t(|_, id: Uuid, library| async move {
+				let temp_files = library.temp_files().clone();
+
+				if !tokio::task::spawn_blocking(move || temp_files.shred(id))
+					.await
+					.map_err(|e| {
+						rspc::Error::with_cause(
+							ErrorCode::InternalServerError,
+							"Failed to erase the decrypted file".into(),
+							e,
+						)
+					})? {
+					return Err(rspc::Error::new(
+						ErrorCode::NotFound,
+						"Decrypted file not found".into(),
+					));
+				}
+
+				Ok(())
+			})
+		})
+		.library_mutation("streamEncrypted", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct StreamEncryptedArgs {
+				pub location_id: i32,
+				pub file_path_id: i32,
+			}
+
+			t(|ctx, args: StreamEncryptedArgs, library| async move {
+				// the stream serves plaintext, so it's treated the same as revealing a key
+				ctx.authorize(KeyCapability::Reveal).await?;
+
+				Ok(library
+					.encrypted_streams()
+					.open(&library, args.location_id, args.file_path_id)
+					.await?)
+			})
+		})
+		.library_mutation("closeStream", |t| {
+			t(|_, id: Uuid, library| async move {
+				if !library.encrypted_streams().close(id) {
+					return Err(rspc::Error::new(
+						ErrorCode::NotFound,
+						"Stream not found".into(),
+					));
+				}
+
+				Ok(())
+			})
+		})
+		.library_query("getThumbnailCacheStats", |t| {
+			t(|_, _: (), library| async move { Ok(library.thumbnail_cache().stats()) })
+		})
+		// this is the most memory (in bytes) that decrypted thumbnails are cached in, which is shared between every library
+		.library_mutation("setThumbnailCacheSize", |t| {
+			t(|ctx, size: u64, library| async move {
+				ctx.config
+					.write(|mut config| config.thumbnail_cache_size = size)
+					.await?;
+
+				library.thumbnail_cache().set_budget(size);
 
+				invalidate_query!(library, "files.getThumbnailCacheStats");
 				Ok(())
 			})
 		})
-		.library_mutation("decryptFiles", |t| {
+		.library_mutation("decrypt", |t| {
 			t(|_, args: FileDecryptorJobInit, library| async move {
 				if fetch_location(&library, args.location_id)
 					.exec()
@@ -126,6 +896,7 @@ pub(crate) fn mount() -> RouterBuilder {
 
 				library.spawn_job(Job::new(args, FileDecryptorJob {})).await;
 				invalidate_query!(library, "locations.getExplorerData");
+				invalidate_query!(library, "savedSearches.getExplorerData");
 
 				Ok(())
 			})
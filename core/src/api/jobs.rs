@@ -1,5 +1,6 @@
 use crate::{
-	job::{Job, JobManager},
+	invalidate_query,
+	job::{get_job_logs, IoLimits, Job, JobConcurrency, JobHistoryArgs, JobManager},
 	location::{fetch_location, LocationError},
 	object::{
 		identifier_job::full_identifier_job::{FullFileIdentifierJob, FullFileIdentifierJobInit},
@@ -11,7 +12,8 @@ use crate::{
 
 use rspc::{ErrorCode, Type};
 use serde::Deserialize;
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
+use uuid::Uuid;
 
 use super::{utils::LibraryRequest, CoreEvent, RouterBuilder};
 
@@ -23,12 +25,133 @@ pub(crate) fn mount() -> RouterBuilder {
 		.library_query("isRunning", |t| {
 			t(|ctx, _: (), _| async move { Ok(!ctx.jobs.get_running().await.is_empty()) })
 		})
+		// these are jobs that won't run until keys are mounted, which clients should prompt for
+		.library_query("getWaitingForKeys", |t| {
+			t(
+				|ctx, _: (), library| async move { Ok(ctx.jobs.get_waiting_for_keys(&library).await) },
+			)
+		})
+		// these are jobs that were sent to the node hosting their locations, and are run there
+		.library_query("getDelegated", |t| {
+			t(|ctx, _: (), library| async move { Ok(ctx.p2p.get_delegated_jobs(library.id).await) })
+		})
 		.library_query("getHistory", |t| {
 			t(|_, _: (), library| async move { Ok(JobManager::get_history(&library).await?) })
 		})
+		.library_query("history", |t| {
+			t(|_, args: JobHistoryArgs, library| async move {
+				Ok(JobManager::history(&library, args).await?)
+			})
+		})
+		.library_query("logs", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct JobLogsArgs {
+				pub job_id: Uuid,
+				pub skip: Option<i64>,
+				pub take: Option<i64>,
+			}
+
+			t(|_, args: JobLogsArgs, library| async move {
+				Ok(get_job_logs(&library, args.job_id, args.skip, args.take).await?)
+			})
+		})
+		.library_mutation("pause", |t| {
+			t(|ctx, job_id: Uuid, library| async move {
+				if !ctx.jobs.pause_job(job_id).await {
+					return Err(job_not_found(job_id));
+				}
+
+				invalidate_query!(library, "jobs.getRunning");
+				Ok(())
+			})
+		})
+		.library_mutation("resume", |t| {
+			t(|ctx, job_id: Uuid, library| async move {
+				if !Arc::clone(&ctx.jobs).resume_job(&library, job_id).await? {
+					return Err(job_not_found(job_id));
+				}
+
+				invalidate_query!(library, "jobs.getHistory");
+				invalidate_query!(library, "jobs.history");
+				Ok(())
+			})
+		})
+		.library_mutation("cancel", |t| {
+			t(|ctx, job_id: Uuid, library| async move {
+				if !ctx.jobs.cancel_job(&library, job_id).await? {
+					return Err(job_not_found(job_id));
+				}
+
+				invalidate_query!(library, "jobs.getRunning");
+				Ok(())
+			})
+		})
+		.library_mutation("retryFailed", |t| {
+			t(|ctx, job_id: Uuid, library| async move {
+				if !Arc::clone(&ctx.jobs).retry_failed(&library, job_id).await? {
+					return Err(rspc::Error::new(
+						ErrorCode::NotFound,
+						format!(
+							"Job <id='{job_id}'> hasn't completed, or has no failed files to retry"
+						),
+					));
+				}
+
+				invalidate_query!(library, "jobs.getRunning");
+				Ok(())
+			})
+		})
+		.library_query("getConcurrency", |t| {
+			t(|ctx, _: (), _| async move { Ok(ctx.jobs.get_concurrency().await) })
+		})
+		.library_mutation("setConcurrency", |t| {
+			t(|ctx, args: JobConcurrency, library| async move {
+				if args.max_workers == 0 || args.limits.values().any(|limit| *limit == 0) {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"Concurrency limits must be at least 1".into(),
+					));
+				}
+
+				ctx.config
+					.write(|mut config| config.job_concurrency = args.clone())
+					.await?;
+
+				Arc::clone(&ctx.jobs).set_concurrency(&library, args).await;
+
+				invalidate_query!(library, "jobs.getConcurrency");
+				invalidate_query!(library, "jobs.getRunning");
+				Ok(())
+			})
+		})
+		.library_query("getIoLimits", |t| {
+			t(|_, _: (), library| async move { Ok(library.io_throttle().limits()) })
+		})
+		.library_mutation("setIoLimits", |t| {
+			t(|ctx, args: IoLimits, library| async move {
+				if args.read == Some(0) || args.write == Some(0) || args.upload == Some(0) {
+					return Err(rspc::Error::new(
+						ErrorCode::BadRequest,
+						"I/O limits must be at least 1 byte per second".into(),
+					));
+				}
+
+				ctx.config
+					.write(|mut config| config.io_limits = args)
+					.await?;
+
+				library.io_throttle().set_limits(args);
+
+				invalidate_query!(library, "jobs.getIoLimits");
+				Ok(())
+			})
+		})
 		.library_mutation("clearAll", |t| {
-			t(|_, _: (), library| async move {
+			t(|ctx, _: (), library| async move {
 				JobManager::clear_all_jobs(&library).await?;
+				ctx.p2p.clear_delegated_jobs(library.id).await;
+
+				invalidate_query!(library, "jobs.getDelegated");
 				Ok(())
 			})
 		})
@@ -138,4 +261,37 @@ pub(crate) fn mount() -> RouterBuilder {
 				}
 			})
 		})
+		// this streams job reports as they change, which can be narrowed down on the server so clients only receive the jobs they're showing
+		// every filter that's set has to match, and filters that aren't set match everything
+		.subscription("events", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct JobEventFilter {
+				pub library_ids: Option<Vec<Uuid>>,
+				pub job_names: Option<Vec<String>>,
+				pub job_ids: Option<Vec<Uuid>>,
+			}
+
+			t(|ctx, filter: JobEventFilter| {
+				let mut event_bus_rx = ctx.event_bus.subscribe();
+				async_stream::stream! {
+					while let Ok(event) = event_bus_rx.recv().await {
+						if let CoreEvent::JobUpdated { library_id, report } = event {
+							if filter.library_ids.as_ref().map_or(true, |ids| ids.contains(&library_id))
+								&& filter.job_names.as_ref().map_or(true, |names| names.contains(&report.name))
+								&& filter.job_ids.as_ref().map_or(true, |ids| ids.contains(&report.id))
+							{
+								yield report;
+							}
+						}
+					}
+				}
+			})
+		})
+}
+
+fn job_not_found(job_id: Uuid) -> rspc::Error {
+	rspc::Error::new(
+		ErrorCode::NotFound,
+		format!("Job <id='{job_id}'> isn't running or paused"),
+	)
 }
@@ -0,0 +1,164 @@
+use prisma_client_rust::QueryError;
+use rspc::{self, ErrorCode, Type};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+	library::{EncryptedFieldError, LibraryManagerError},
+	location::remote::RemoteError,
+};
+
+/// Error type for the key manager routes.
+///
+/// These are serialized into the error's message, so clients are able to branch on the kind of error (and localize it).
+#[derive(Error, Debug, Serialize, Type)]
+#[serde(tag = "kind", content = "details")]
+pub enum KeyRouteError {
+	// Not found errors
+	#[error("The requested key wasn't found")]
+	NotFound,
+
+	// User errors
+	#[error("The key manager is locked")]
+	Locked,
+	#[error("The provided password or secret key is incorrect")]
+	WrongPassword,
+	#[error("The provided password is too weak")]
+	WeakPassword,
+	#[error("The key is already mounted")]
+	AlreadyMounted,
+	#[error("The key isn't mounted")]
+	NotMounted,
+	#[error("A key with this UUID already exists")]
+	AlreadyExists,
+	#[error("The key manager has already been set up")]
+	AlreadyOnboarded,
+	#[error("A TOTP code is required to mount this key")]
+	TotpRequired,
+	#[error("The provided TOTP or recovery code is incorrect")]
+	WrongTotpCode,
+	#[error("Too many incorrect TOTP codes have been provided, try again in {0} seconds")]
+	TotpLockedOut(u64),
+	#[error("TOTP isn't enabled for this key")]
+	TotpNotEnabled,
+	#[error("TOTP is already enabled for this key")]
+	TotpAlreadyEnabled,
+	#[error("The provided key is invalid ({0})")]
+	InvalidKey(String),
+	#[error("The password hint is invalid ({0})")]
+	InvalidHint(String),
+	#[error("The provided data is corrupt (error: {0})")]
+	Corrupt(String),
+
+	// Internal errors
+	#[error("I/O error (error: {0})")]
+	IO(String),
+	#[error("Remote error (error: {0})")]
+	Remote(String),
+	#[error("Database error (error: {0})")]
+	Database(String),
+	#[error("Internal error (error: {0})")]
+	Internal(String),
+}
+
+impl From<sd_crypto::Error> for KeyRouteError {
+	fn from(err: sd_crypto::Error) -> Self {
+		use sd_crypto::Error;
+
+		match err {
+			Error::KeyNotFound | Error::NoDefaultKeySet | Error::NoVerificationKey => {
+				Self::NotFound
+			}
+			Error::NoMasterPassword => Self::Locked,
+			Error::IncorrectPassword
+			| Error::IncorrectKeymanagerDetails
+			| Error::KeystoreMismatch => Self::WrongPassword,
+			Error::WeakPassword => Self::WeakPassword,
+			Error::KeyAlreadyMounted => Self::AlreadyMounted,
+			Error::KeyNotMounted => Self::NotMounted,
+			Error::KeyAlreadyExists => Self::AlreadyExists,
+			Error::TotpRequired => Self::TotpRequired,
+			Error::IncorrectTotpCode => Self::WrongTotpCode,
+			Error::TotpLockedOut(seconds) => Self::TotpLockedOut(seconds),
+			Error::TotpNotEnabled => Self::TotpNotEnabled,
+			Error::TotpAlreadyEnabled => Self::TotpAlreadyEnabled,
+			Error::InvalidKeyfile => Self::InvalidKey(err.to_string()),
+			Error::ExportedKey
+			| Error::FileHeader
+			| Error::Decrypt
+			| Error::MetadataDeSerialization
+			| Error::VecArrSizeMismatch
+			| Error::StringParse(_) => Self::Corrupt(err.to_string()),
+			Error::Io(e) => Self::IO(e.to_string()),
+			_ => Self::Internal(err.to_string()),
+		}
+	}
+}
+
+impl From<QueryError> for KeyRouteError {
+	fn from(err: QueryError) -> Self {
+		Self::Database(err.to_string())
+	}
+}
+
+impl From<LibraryManagerError> for KeyRouteError {
+	fn from(err: LibraryManagerError) -> Self {
+		Self::Internal(err.to_string())
+	}
+}
+
+impl From<EncryptedFieldError> for KeyRouteError {
+	fn from(err: EncryptedFieldError) -> Self {
+		match err {
+			EncryptedFieldError::Crypto(e) => e.into(),
+			EncryptedFieldError::Database(e) => e.into(),
+			EncryptedFieldError::Serialization(e) => Self::Corrupt(e.to_string()),
+		}
+	}
+}
+
+impl From<std::io::Error> for KeyRouteError {
+	fn from(err: std::io::Error) -> Self {
+		Self::IO(err.to_string())
+	}
+}
+
+impl From<RemoteError> for KeyRouteError {
+	fn from(err: RemoteError) -> Self {
+		match err {
+			RemoteError::EncryptedFieldError(e) => e.into(),
+			_ => Self::Remote(err.to_string()),
+		}
+	}
+}
+
+impl From<KeyRouteError> for rspc::Error {
+	fn from(err: KeyRouteError) -> Self {
+		let code = match err {
+			KeyRouteError::NotFound => ErrorCode::NotFound,
+			KeyRouteError::WrongPassword | KeyRouteError::WrongTotpCode => ErrorCode::Unauthorized,
+			KeyRouteError::TotpLockedOut(_) => ErrorCode::Forbidden,
+			KeyRouteError::Locked
+			| KeyRouteError::NotMounted
+			| KeyRouteError::TotpRequired
+			| KeyRouteError::TotpNotEnabled => ErrorCode::PreconditionFailed,
+			KeyRouteError::AlreadyMounted
+			| KeyRouteError::AlreadyExists
+			| KeyRouteError::AlreadyOnboarded
+			| KeyRouteError::TotpAlreadyEnabled => ErrorCode::Conflict,
+			KeyRouteError::WeakPassword
+			| KeyRouteError::InvalidKey(_)
+			| KeyRouteError::InvalidHint(_)
+			| KeyRouteError::Corrupt(_) => ErrorCode::BadRequest,
+			KeyRouteError::IO(_)
+			| KeyRouteError::Remote(_)
+			| KeyRouteError::Database(_)
+			| KeyRouteError::Internal(_) => ErrorCode::InternalServerError,
+		};
+
+		// rspc errors only carry a message, so the serialized error is used as it
+		let message = serde_json::to_string(&err).unwrap_or_else(|_| err.to_string());
+
+		rspc::Error::with_cause(code, message, err)
+	}
+}
@@ -0,0 +1,2240 @@
+use std::io::{Read, Write};
+use std::{future::Future, path::PathBuf, pin::Pin, str::FromStr, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use int_enum::IntEnum;
+use prisma_client_rust::Direction;
+use sd_crypto::keys::keyfile::hash_keyfile;
+use sd_crypto::keys::keymanager::{KeySource, KeystoreBackupDiff, MountSource, StoredKey};
+use sd_crypto::keys::totp::{self, TotpCode};
+use sd_crypto::{
+	crypto::stream::Algorithm,
+	keys::{hashing::HashingAlgorithm, keymanager::KeyManager},
+	primitives::{PassphraseStyle, METADATA_CONTEXT},
+	Protected,
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::job::{DynJob, Job};
+use crate::library::{
+	apply_field_encryption, record_key_audit_event, reencrypt_fields, AutoLockConfig,
+	ContextDefaultKey, EncryptedField, KeyAuditAction, KeyDefaultContext, LibraryContext,
+	LibraryManager,
+};
+use crate::location::remote::{connect, connect_with_credentials, RemoteConfig, RemoteCredentials};
+use crate::node::LibraryNode;
+use crate::node::{ApiToken, KeyCapability};
+use crate::object::fs::decrypt_header::{FileHeaderDecryptorJob, FileHeaderDecryptorJobInit};
+use crate::object::fs::rekey::{FileRekeyerJob, FileRekeyerJobInit};
+use crate::util::db::{
+	storedkey_params, totp_params, update_storedkey_in_db, upsert_storedkey_in_db,
+	write_storedkey_to_db, write_totp_to_db,
+};
+use crate::{
+	invalidate_query,
+	prisma::{file_path, key, key_audit_event, key_totp, location, media_data, object},
+};
+
+use super::{utils::LibraryRequest, CoreEvent, Ctx, RouterBuilder};
+
+mod error;
+
+pub use error::*;
+
+#[derive(Type, Deserialize)]
+pub struct KeyAddArgs {
+	algorithm: Algorithm,
+	hashing_algorithm: HashingAlgorithm,
+	key: KeyInput,
+	library_sync: bool,
+	automount: bool,
+}
+
+/// This is a key provided by the user, alongside how it's encoded.
+///
+/// Keys generated by other tools are often hex or base64 encoded, and they're decoded before being added.
+///
+/// A keyfile may be provided instead (either as a path on the node, or as the uploaded contents), and its contents are hashed to form the key.
+#[derive(Type, Deserialize)]
+#[serde(tag = "encoding", content = "value")]
+pub enum KeyInput {
+	Utf8(String),
+	Hex(String),
+	Base64(String),
+	Bytes(Vec<u8>),
+	KeyfilePath(PathBuf),
+	Keyfile(Vec<u8>),
+}
+
+impl KeyInput {
+	fn source(&self) -> KeySource {
+		match self {
+			Self::KeyfilePath(_) | Self::Keyfile(_) => KeySource::Keyfile,
+			_ => KeySource::Typed,
+		}
+	}
+
+	fn decode(self) -> Result<Protected<Vec<u8>>, KeyRouteError> {
+		let key = match self {
+			Self::KeyfilePath(path) => {
+				return Ok(hash_keyfile(&mut std::fs::File::open(path)?)?);
+			}
+			Self::Keyfile(contents) => return Ok(hash_keyfile(&mut contents.as_slice())?),
+			Self::Utf8(key) => key.into_bytes(),
+			Self::Hex(key) => {
+				let key = key.trim();
+				let key = key.strip_prefix("0x").unwrap_or(key);
+
+				if !key.is_ascii() || key.len() % 2 != 0 {
+					return Err(KeyRouteError::InvalidKey("invalid hex".into()));
+				}
+
+				(0..key.len())
+					.step_by(2)
+					.map(|i| u8::from_str_radix(&key[i..i + 2], 16))
+					.collect::<Result<Vec<_>, _>>()
+					.map_err(|_| KeyRouteError::InvalidKey("invalid hex".into()))?
+			}
+			Self::Base64(key) => base64::decode(key.trim())
+				.map_err(|_| KeyRouteError::InvalidKey("invalid base64".into()))?,
+			Self::Bytes(key) => key,
+		};
+
+		if key.is_empty() {
+			return Err(KeyRouteError::InvalidKey("the key is empty".into()));
+		}
+
+		Ok(Protected::new(key))
+	}
+}
+
+#[derive(Type, Deserialize)]
+pub struct KeyRotateArgs {
+	uuid: Uuid,
+	algorithm: Algorithm,
+	hashing_algorithm: HashingAlgorithm,
+	password: Option<String>, // if this isn't provided, the key's current value is kept
+}
+
+#[derive(Type, Deserialize)]
+pub struct KeyRevokeArgs {
+	uuid: Uuid,
+	replacement: Uuid,
+}
+
+/// These are aggregate statistics for the keystore, used for the key manager's dashboard.
+#[derive(Type, Serialize)]
+pub struct KeystoreStats {
+	total: usize,
+	mounted: usize,
+	memory_only: usize,
+	algorithms: Vec<(Algorithm, usize)>,
+	hashing_algorithms: Vec<(HashingAlgorithm, usize)>,
+	last_backup: Option<DateTime<Utc>>,
+	unused_keys: Vec<Uuid>, // keys that aren't associated with any objects or paths
+}
+
+#[derive(Type, Deserialize)]
+pub struct KeyNameUpdateArgs {
+	uuid: Uuid,
+	name: String,
+}
+
+#[derive(Type, Deserialize)]
+pub struct KeyGetArgs {
+	uuid: Uuid,
+	#[serde(default)]
+	reveal: bool, // the plaintext key is only returned if this is explicitly set
+	#[serde(default)]
+	totp: Option<TotpCode>, // required for revealing if the key has TOTP enabled
+}
+
+/// This is a key's value, which is masked unless it was explicitly revealed.
+#[derive(Type, Serialize)]
+pub struct KeyValue {
+	value: String,
+	masked: bool,
+}
+
+/// The masked value is a fixed length, so it doesn't give away the length of the key.
+const MASKED_KEY_VALUE: &str = "••••••••••••••••";
+
+#[derive(Type, Deserialize)]
+pub struct KeyValidateArgs {
+	uuid: Uuid,
+	password: String,
+}
+
+#[derive(Type, Deserialize)]
+pub struct KeyExportArgs {
+	uuid: Uuid,
+	password: String,
+}
+
+#[derive(Type, Deserialize)]
+pub struct KeyImportArgs {
+	key: String, // base64-encoded, as returned from `keys.export`
+	password: String,
+	library_sync: bool,
+	automount: bool,
+}
+
+#[derive(Type, Deserialize)]
+pub struct KeyCopyArgs {
+	key_uuid: Uuid,
+	target_library: Uuid,
+}
+
+#[derive(Type, Deserialize)]
+pub struct SetMasterPasswordArgs {
+	password: String,
+	secret_key: String,
+	#[serde(default)]
+	decrypt_headers: bool, // dispatch header decryption jobs for every automounted key
+}
+
+#[derive(Type, Deserialize)]
+pub struct KeyMountArgs {
+	uuid: Uuid,
+	#[serde(default)]
+	decrypt_headers: bool, // decrypt the metadata/preview media of every file that's associated with this key
+	#[serde(default)]
+	totp: Option<TotpCode>, // required if the key has TOTP enabled
+}
+
+#[derive(Type, Deserialize)]
+pub struct RestoreBackupArgs {
+	password: String,
+	secret_key: String,
+	path: PathBuf,
+	dry_run: bool,
+}
+
+#[derive(Type, Deserialize)]
+pub struct RestoreBackupBytesArgs {
+	password: String,
+	secret_key: String,
+	backup: String, // base64-encoded, as returned from `keys.backupKeystoreToBytes`
+	dry_run: bool,
+}
+
+/// This is a keystore backup that's stored on a remote.
+#[derive(Type, Deserialize)]
+#[serde(tag = "type")]
+pub enum RemoteBackupSource {
+	/// a file within a remote location, which is read with the location's stored credentials
+	Location { location_id: i32, path: String },
+	/// a file on a remote that isn't a location (e.g. when restoring into a new library), which is read with the provided credentials
+	Remote {
+		config: RemoteConfig,
+		credentials: RemoteCredentials,
+		path: String,
+	},
+}
+
+#[derive(Type, Deserialize)]
+pub struct RestoreBackupRemoteArgs {
+	password: String,
+	secret_key: String,
+	source: RemoteBackupSource,
+	dry_run: bool,
+}
+
+/// This is returned from a keystore restore.
+///
+/// If it was a dry run, nothing was written and the diff is returned instead.
+#[derive(Type, Serialize)]
+pub enum RestoreBackupResult {
+	Restored(usize),
+	DryRun(KeystoreBackupDiff),
+}
+
+#[derive(Type, Deserialize)]
+pub struct OnboardingArgs {
+	algorithm: Algorithm,
+	hashing_algorithm: HashingAlgorithm,
+	password: Option<String>, // a user-chosen master password. one is generated if this isn't provided
+	passphrase_style: Option<PassphraseStyle>, // the style of the generated master password
+	hint: Option<String>,
+}
+
+#[derive(Type, Deserialize)]
+pub struct MasterPasswordChangeArgs {
+	password: String,
+	algorithm: Algorithm,
+	hashing_algorithm: HashingAlgorithm,
+	hint: Option<String>, // this replaces the current hint, so it's cleared if this isn't provided
+}
+
+#[derive(Type, Deserialize)]
+pub struct SecretKeyRotationArgs {
+	password: String,
+	secret_key: String, // the current secret key
+}
+
+#[derive(Type, Serialize)]
+pub struct OnboardingKeys {
+	master_password: String,
+	secret_key: String,
+}
+
+#[derive(Type, Deserialize)]
+pub struct AutomountUpdateArgs {
+	uuid: Uuid,
+	status: bool,
+}
+
+/// The outcome of a single key within a batch operation (e.g. `keys.mountMany`).
+///
+/// `error` is `None` if the operation succeeded for this key.
+#[derive(Type, Serialize)]
+pub struct KeyBatchResult {
+	uuid: Uuid,
+	error: Option<String>,
+}
+
+#[derive(Type, Serialize)]
+pub struct MountedKeyInfo {
+	uuid: Uuid,
+	mounted_at: DateTime<Utc>,
+	source: MountSource,
+	idle_ttl: Option<u64>, // seconds until the library auto-locks, if it's enabled
+}
+
+/// This is returned from `setMasterPassword`, once the key manager has been unlocked.
+///
+/// A key failing to automount doesn't fail the unlock, it's reported within `failed` instead.
+#[derive(Type, Serialize)]
+pub struct AutomountResult {
+	mounted: Vec<Uuid>,
+	failed: Vec<AutomountFailure>,
+	jobs: Vec<Uuid>, // the IDs of any dispatched header decryption jobs
+}
+
+#[derive(Type, Deserialize)]
+pub struct KeySetDefaultArgs {
+	uuid: Uuid,
+	context: Option<KeyDefaultContext>, // if this isn't provided, the library's default key is set
+}
+
+#[derive(Type, Deserialize)]
+pub struct KeyAuditLogFilter {
+	from: Option<DateTime<Utc>>,
+	to: Option<DateTime<Utc>>,
+	key_uuid: Option<Uuid>,
+}
+
+#[derive(Type, Deserialize)]
+pub struct KeyAuditLogArgs {
+	filter: KeyAuditLogFilter,
+	skip: Option<i64>,
+	take: Option<i64>, // defaults to `AUDIT_LOG_PAGE_SIZE`
+}
+
+#[derive(Type, Deserialize)]
+pub enum KeyAuditLogFormat {
+	Json,
+	Csv,
+}
+
+#[derive(Type, Deserialize)]
+pub struct KeyAuditLogExportArgs {
+	filter: KeyAuditLogFilter,
+	format: KeyAuditLogFormat,
+}
+
+#[derive(Type, Serialize)]
+pub struct KeyAuditLogEntry {
+	id: i32,
+	action: KeyAuditAction,
+	key_uuid: Option<String>,
+	node: LibraryNode, // the device that performed the action
+	date_created: DateTime<Utc>,
+}
+
+#[derive(Type, Serialize)]
+pub struct KeyAuditLogPage {
+	entries: Vec<KeyAuditLogEntry>,
+	total: i64, // the amount of entries matching the filter, across all pages
+}
+
+#[derive(Type, Deserialize)]
+pub struct CreateApiTokenArgs {
+	name: String,
+	capabilities: Vec<KeyCapability>,
+}
+
+#[derive(Type, Serialize)]
+pub struct ApiTokenInfo {
+	id: Uuid,
+	name: String,
+	capabilities: Vec<KeyCapability>,
+}
+
+/// This is returned when TOTP is enrolled for a key, and it's the only time the secret and recovery codes are shown.
+#[derive(Type, Serialize)]
+pub struct TotpEnrollmentInfo {
+	uri: String,    // an `otpauth://` URI, for showing as a QR code
+	secret: String, // base32-encoded, for entering manually
+	recovery_codes: Vec<String>,
+}
+
+#[derive(Type, Deserialize)]
+pub struct TotpConfirmArgs {
+	uuid: Uuid,
+	code: String,
+}
+
+#[derive(Type, Deserialize)]
+pub struct TotpArgs {
+	uuid: Uuid,
+	code: TotpCode,
+}
+
+#[derive(Type, Serialize)]
+pub struct AutomountFailure {
+	uuid: String, // this is taken straight from the database, as it may not be a valid UUID
+	error: String,
+}
+
+const AUDIT_LOG_PAGE_SIZE: i64 = 100;
+
+/// This is the format of a keystore backup.
+#[derive(Serialize, Deserialize)]
+struct KeystoreBackup {
+	keys: Vec<StoredKey>,
+	#[serde(default)]
+	associations: Vec<KeyAssociations>,
+}
+
+/// Backups created before associations were included are just a list of keys.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeystoreBackupPayload {
+	Current(KeystoreBackup),
+	Legacy(Vec<StoredKey>),
+}
+
+impl From<KeystoreBackupPayload> for KeystoreBackup {
+	fn from(payload: KeystoreBackupPayload) -> Self {
+		match payload {
+			KeystoreBackupPayload::Current(backup) => backup,
+			KeystoreBackupPayload::Legacy(keys) => Self {
+				keys,
+				associations: Vec::new(),
+			},
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyAssociations {
+	key_uuid: String,
+	objects: Vec<String>,               // cas ids
+	file_paths: Vec<(Vec<u8>, String)>, // location pub id, materialized path
+}
+
+key::select!(key_associations {
+	uuid
+	objects: select {
+		cas_id
+	}
+	file_paths: select {
+		materialized_path
+		location: select {
+			pub_id
+		}
+	}
+});
+
+key_audit_event::include!(key_audit_event_with_node { node });
+
+impl From<key_audit_event_with_node::Data> for KeyAuditLogEntry {
+	fn from(data: key_audit_event_with_node::Data) -> Self {
+		Self {
+			id: data.id,
+			action: KeyAuditAction::from_int(data.action).unwrap_or(KeyAuditAction::Unknown),
+			key_uuid: data.key_uuid,
+			node: data.node.into(),
+			date_created: data.date_created.into(),
+		}
+	}
+}
+
+pub(crate) fn mount() -> RouterBuilder {
+	RouterBuilder::new()
+		// API tokens can only be managed from the local device
+		// this returns the token's secret, which can't be retrieved again
+		.mutation("createApiToken", |t| {
+			t(|ctx, args: CreateApiTokenArgs| async move {
+				local_only(&ctx)?;
+
+				let (token, secret) = ApiToken::new(args.name, args.capabilities);
+				ctx.config
+					.write(|mut config| config.api_tokens.push(token))
+					.await?;
+
+				Ok(secret)
+			})
+		})
+		.query("listApiTokens", |t| {
+			t(|ctx, _: ()| async move {
+				local_only(&ctx)?;
+
+				Ok(ctx
+					.config
+					.get()
+					.await
+					.api_tokens
+					.into_iter()
+					.map(|token| ApiTokenInfo {
+						id: token.id,
+						name: token.name,
+						capabilities: token.capabilities,
+					})
+					.collect::<Vec<_>>())
+			})
+		})
+		.mutation("revokeApiToken", |t| {
+			t(|ctx, id: Uuid| async move {
+				local_only(&ctx)?;
+
+				ctx.config
+					.write(|mut config| config.api_tokens.retain(|token| token.id != id))
+					.await?;
+
+				Ok(())
+			})
+		})
+		.library_query("list", |t| {
+			t(scoped(KeyCapability::Read, |_, _: (), library| async move {
+				Ok(library.key_manager.dump_keystore())
+			}))
+		})
+		.library_query("stats", |t| {
+			t(scoped(KeyCapability::Read, |ctx, _: (), library| async move {
+				let stored_keys = library.key_manager.dump_keystore();
+
+				let mut algorithms: Vec<(Algorithm, usize)> = Vec::new();
+				let mut hashing_algorithms: Vec<(HashingAlgorithm, usize)> = Vec::new();
+
+				for key in &stored_keys {
+					match algorithms.iter_mut().find(|(a, _)| *a == key.algorithm) {
+						Some((_, count)) => *count += 1,
+						None => algorithms.push((key.algorithm, 1)),
+					}
+
+					match hashing_algorithms
+						.iter_mut()
+						.find(|(h, _)| *h == key.hashing_algorithm)
+					{
+						Some((_, count)) => *count += 1,
+						None => hashing_algorithms.push((key.hashing_algorithm, 1)),
+					}
+				}
+
+				// memory-only keys aren't in the database, so they can't have any associations
+				let unassociated_keys = library
+					.db
+					.key()
+					.find_many(vec![key::objects::none(vec![]), key::file_paths::none(vec![])])
+					.exec()
+					.await?
+					.into_iter()
+					.map(|k| k.uuid)
+					.collect::<Vec<_>>();
+
+				let unused_keys = stored_keys
+					.iter()
+					.filter(|k| k.memory_only || unassociated_keys.contains(&k.uuid.to_string()))
+					.map(|k| k.uuid)
+					.collect();
+
+				// the context's config is a snapshot from when the library was loaded, so fetch the current one
+				let last_backup = ctx
+					.library_manager
+					.get_ctx(library.id)
+					.await
+					.and_then(|library| library.config.last_keystore_backup);
+
+				Ok(KeystoreStats {
+					total: stored_keys.len(),
+					mounted: library.key_manager.get_mounted_uuids().len(),
+					memory_only: stored_keys.iter().filter(|k| k.memory_only).count(),
+					algorithms,
+					hashing_algorithms,
+					last_backup,
+					unused_keys,
+				})
+			}))
+		})
+		// this is available while the library is locked, as that's when it's needed
+		.library_query("getPasswordHint", |t| {
+			t(scoped(KeyCapability::Read, |ctx, _: (), library| async move {
+				// the context's config is a snapshot from when the library was loaded, so fetch the current one
+				Ok(ctx
+					.library_manager
+					.get_ctx(library.id)
+					.await
+					.and_then(|library| library.config.master_password_hint))
+			}))
+		})
+		// the library needs to be onboarded (with `keys.onboarding`) before the key manager can be unlocked
+		.library_query("isOnboarded", |t| {
+			t(scoped(KeyCapability::Read, |_, _: (), library| async move {
+				Ok(library.key_manager.get_verification_key().is_ok())
+			}))
+		})
+		// do not unlock the key manager until this route returns true
+		.library_query("hasMasterPassword", |t| {
+			t(scoped(KeyCapability::Read, |_, _: (), library| async move {
+				Ok(library.key_manager.has_master_password()?)
+			}))
+		})
+		// these keys need a TOTP code to be mounted
+		.library_query("listTotp", |t| {
+			t(scoped(KeyCapability::Read, |_, _: (), library| async move {
+				Ok(library
+					.key_manager
+					.dump_keystore()
+					.into_iter()
+					.map(|key| key.uuid)
+					.filter(|uuid| library.key_manager.is_totp_enabled(*uuid))
+					.collect::<Vec<_>>())
+			}))
+		})
+		// TOTP isn't enforced until the enrollment is confirmed with `keys.confirmTotp`
+		.library_mutation("enrollTotp", |t| {
+			t(scoped(KeyCapability::Manage, |_, key_uuid: Uuid, library| async move {
+				// the secret is stored alongside the key, so memory-only keys can't have TOTP
+				if library.key_manager.is_memory_only(key_uuid)? {
+					return Err(KeyRouteError::InvalidKey(
+						"TOTP can't be enabled for memory-only keys".into(),
+					));
+				}
+
+				let enrollment = library.key_manager.enroll_totp(key_uuid)?;
+
+				let account = library
+					.key_manager
+					.access_keystore(key_uuid)?
+					.name
+					.unwrap_or_else(|| key_uuid.to_string());
+
+				Ok(TotpEnrollmentInfo {
+					uri: totp::provisioning_uri(
+						enrollment.secret.expose(),
+						&account,
+						"Spacedrive",
+					),
+					secret: totp::encode_secret(enrollment.secret.expose()),
+					recovery_codes: enrollment
+						.recovery_codes
+						.iter()
+						.map(|code| code.expose().clone())
+						.collect(),
+				})
+			}))
+		})
+		.library_mutation("confirmTotp", |t| {
+			t(scoped(KeyCapability::Manage, |_, args: TotpConfirmArgs, library| async move {
+				let stored_totp = library.key_manager.confirm_totp(args.uuid, &args.code)?;
+
+				// the database is updated first, so the keystore is left untouched if this fails
+				write_totp_to_db(library.db.clone(), args.uuid, Some(&stored_totp)).await?;
+				library.key_manager.set_totp(args.uuid, Some(stored_totp))?;
+				record_key_audit_event(&library, KeyAuditAction::TotpEnable, Some(args.uuid)).await;
+
+				invalidate_query!(library, "keys.listTotp");
+				Ok(())
+			}))
+		})
+		// a valid code (or recovery code) is required, so TOTP can't be removed by anyone that just has the library unlocked
+		.library_mutation("disableTotp", |t| {
+			t(scoped(KeyCapability::Manage, |_, args: TotpArgs, library| async move {
+				library.key_manager.verify_totp(args.uuid, &args.code)?;
+
+				if !library.key_manager.is_memory_only(args.uuid)? {
+					write_totp_to_db(library.db.clone(), args.uuid, None).await?;
+				}
+
+				library.key_manager.set_totp(args.uuid, None)?;
+				record_key_audit_event(&library, KeyAuditAction::TotpDisable, Some(args.uuid))
+					.await;
+
+				invalidate_query!(library, "keys.listTotp");
+				Ok(())
+			}))
+		})
+		// this replaces every recovery code, and returns the new ones
+		.library_mutation("regenerateRecoveryCodes", |t| {
+			t(scoped(KeyCapability::Manage, |_, args: TotpArgs, library| async move {
+				let (stored_totp, recovery_codes) = library
+					.key_manager
+					.regenerate_recovery_codes(args.uuid, &args.code)?;
+
+				if !library.key_manager.is_memory_only(args.uuid)? {
+					write_totp_to_db(library.db.clone(), args.uuid, Some(&stored_totp)).await?;
+				}
+
+				library.key_manager.set_totp(args.uuid, Some(stored_totp))?;
+				record_key_audit_event(&library, KeyAuditAction::TotpRecovery, Some(args.uuid))
+					.await;
+
+				Ok(recovery_codes
+					.iter()
+					.map(|code| code.expose().clone())
+					.collect::<Vec<_>>())
+			}))
+		})
+		// this is so we can show the key as mounted in the UI
+		.library_query("listMounted", |t| {
+			t(scoped(KeyCapability::Read, |_, _: (), library| async move {
+				// every key is unmounted once the library auto-locks, so they all share the same TTL
+				let idle_ttl = match library.auto_lock.config().await.minutes {
+					Some(minutes) => Some(
+						Duration::from_secs(u64::from(minutes) * 60)
+							.saturating_sub(library.key_manager.idle_time()?)
+							.as_secs(),
+					),
+					None => None,
+				};
+
+				let mounted_keys = library
+					.key_manager
+					.get_mount_details()
+					.into_iter()
+					.map(|details| MountedKeyInfo {
+						uuid: details.uuid,
+						mounted_at: details.mounted_at.into(),
+						source: details.source,
+						idle_ttl,
+					})
+					.collect::<Vec<_>>();
+
+				Ok(mounted_keys)
+			}))
+		})
+		// the key is masked unless it's explicitly revealed, so it can't be exposed by accident (e.g. by logging)
+		.library_query("getKey", |t| {
+			t(scoped(KeyCapability::Reveal, |_, args: KeyGetArgs, library| async move {
+				// this ensures the key exists, and that the key manager is unlocked
+				let key = library.key_manager.get_key(args.uuid)?;
+
+				if !args.reveal {
+					return Ok(KeyValue {
+						value: MASKED_KEY_VALUE.to_string(),
+						masked: true,
+					});
+				}
+
+				// keys with TOTP enabled require a code to be revealed, just like they do to be mounted
+				if library.key_manager.is_totp_enabled(args.uuid) {
+					let code = args.totp.as_ref().ok_or(KeyRouteError::TotpRequired)?;
+
+					if let Some(stored_totp) = library.key_manager.verify_totp(args.uuid, code)? {
+						if !library.key_manager.is_memory_only(args.uuid)? {
+							write_totp_to_db(library.db.clone(), args.uuid, Some(&stored_totp))
+								.await?;
+						}
+
+						record_key_audit_event(
+							&library,
+							KeyAuditAction::TotpRecovery,
+							Some(args.uuid),
+						)
+						.await;
+					}
+				}
+
+				record_key_audit_event(&library, KeyAuditAction::Reveal, Some(args.uuid)).await;
+
+				let key_string = String::from_utf8(key.expose().clone()).map_err(|_| {
+					KeyRouteError::Internal("Error serializing bytes to String".into())
+				})?;
+
+				Ok(KeyValue {
+					value: key_string,
+					masked: false,
+				})
+			}))
+		})
+		// this does not mount the key, so it can be used to check a password before starting a (potentially large) decryption job
+		.library_query("validate", |t| {
+			t(scoped(KeyCapability::Reveal, |_, args: KeyValidateArgs, library| async move {
+				Ok(library
+					.key_manager
+					.validate_key(args.uuid, Protected::new(args.password.into_bytes()))?)
+			}))
+		})
+		// this is a mutation as it's slow, and it shouldn't be refetched automatically
+		.library_mutation("benchmarkHashing", |t| {
+			t(scoped(KeyCapability::Read, |_, _: (), _| async move {
+				let benchmarks = tokio::task::spawn_blocking(HashingAlgorithm::benchmark)
+					.await
+					.map_err(|_| {
+						KeyRouteError::Internal("Error running hashing benchmark".into())
+					})??;
+
+				Ok(benchmarks)
+			}))
+		})
+		// this returns the ID of the header decryption job, if one was dispatched
+		.library_mutation("mount", |t| {
+			t(scoped(KeyCapability::Mount, |_, args: KeyMountArgs, library| async move {
+				// keys with TOTP enabled can only be mounted with a valid code, this is enforced by the key manager
+				match &args.totp {
+					Some(code) => {
+						let updated_totp =
+							library
+								.key_manager
+								.mount_with_totp(args.uuid, code, MountSource::Manual)?;
+
+						// a recovery code was used up
+						if let Some(stored_totp) = updated_totp {
+							if !library.key_manager.is_memory_only(args.uuid)? {
+								write_totp_to_db(library.db.clone(), args.uuid, Some(&stored_totp))
+									.await?;
+							}
+
+							record_key_audit_event(
+								&library,
+								KeyAuditAction::TotpRecovery,
+								Some(args.uuid),
+							)
+							.await;
+						}
+					}
+					None => library.key_manager.mount(args.uuid)?,
+				}
+
+				record_key_audit_event(&library, KeyAuditAction::Mount, Some(args.uuid)).await;
+
+				let job_id = if args.decrypt_headers {
+					dispatch_header_decryption(&library, args.uuid).await
+				} else {
+					None
+				};
+
+				library.resume_jobs_waiting_for_keys().await;
+
+				invalidate_query!(library, "keys.listMounted");
+				Ok(job_id)
+			}))
+		})
+		// this mounts all provided keys, and only invalidates the query once
+		// a failure to mount one key does not affect the others
+		.library_mutation("mountMany", |t| {
+			t(scoped(KeyCapability::Mount, |_, key_uuids: Vec<Uuid>, library| async move {
+				let results = key_uuids
+					.into_iter()
+					.map(|uuid| KeyBatchResult {
+						uuid,
+						error: library.key_manager.mount(uuid).err().map(|e| e.to_string()),
+					})
+					.collect::<Vec<_>>();
+
+				for result in results.iter().filter(|r| r.error.is_none()) {
+					record_key_audit_event(&library, KeyAuditAction::Mount, Some(result.uuid))
+						.await;
+				}
+
+				library.resume_jobs_waiting_for_keys().await;
+
+				invalidate_query!(library, "keys.listMounted");
+				Ok(results)
+			}))
+		})
+		.library_mutation("updateKeyName", |t| {
+			t(scoped(KeyCapability::Manage, |_, args: KeyNameUpdateArgs, library| async move {
+				// memory-only keys aren't in the database, so only the keystore needs updating
+				if !library.key_manager.is_memory_only(args.uuid)? {
+					library
+						.db
+						.key()
+						.update(
+							key::uuid::equals(args.uuid.to_string()),
+							vec![key::SetParam::SetName(Some(args.name.clone()))],
+						)
+						.exec()
+						.await?;
+				}
+
+				library
+					.key_manager
+					.rename_key(args.uuid, Some(args.name.clone()))?;
+
+				library.emit(CoreEvent::KeyRenamed {
+					library_id: library.id,
+					uuid: args.uuid,
+					name: Some(args.name),
+				});
+
+				invalidate_query!(library, "keys.list");
+				Ok(())
+			}))
+		})
+		.library_mutation("unmount", |t| {
+			t(scoped(KeyCapability::Mount, |_, key_uuid: Uuid, library| async move {
+				library.key_manager.unmount(key_uuid)?;
+				library.key_consumers.revoke(key_uuid);
+				record_key_audit_event(&library, KeyAuditAction::Unmount, Some(key_uuid)).await;
+
+				invalidate_query!(library, "keys.listMounted");
+				Ok(())
+			}))
+		})
+		.library_mutation("unmountMany", |t| {
+			t(scoped(KeyCapability::Mount, |_, key_uuids: Vec<Uuid>, library| async move {
+				let results = key_uuids
+					.into_iter()
+					.map(|uuid| {
+						let error = library.key_manager.unmount(uuid).err();
+
+						if error.is_none() {
+							library.key_consumers.revoke(uuid);
+						}
+
+						KeyBatchResult {
+							uuid,
+							error: error.map(|e| e.to_string()),
+						}
+					})
+					.collect::<Vec<_>>();
+
+				for result in results.iter().filter(|r| r.error.is_none()) {
+					record_key_audit_event(&library, KeyAuditAction::Unmount, Some(result.uuid))
+						.await;
+				}
+
+				invalidate_query!(library, "keys.listMounted");
+				Ok(results)
+			}))
+		})
+		.library_query("getAutoLock", |t| {
+			t(scoped(KeyCapability::Read, |_, _: (), library| async move {
+				Ok(library.auto_lock.config().await)
+			}))
+		})
+		.library_mutation("setAutoLock", |t| {
+			t(scoped(KeyCapability::Manage, |ctx, args: AutoLockConfig, library| async move {
+				ctx.library_manager.set_auto_lock(library.id, args).await?;
+
+				invalidate_query!(library, "keys.getAutoLock");
+				Ok(())
+			}))
+		})
+		.library_query("getEncryptedFields", |t| {
+			t(scoped(KeyCapability::Read, |_, _: (), library| async move {
+				Ok(library.config.encrypted_fields.clone())
+			}))
+		})
+		.library_mutation("setEncryptedFields", |t| {
+			t(scoped(
+				KeyCapability::Manage,
+				|ctx, args: Vec<EncryptedField>, library| async move {
+					let current = &library.config.encrypted_fields;
+
+					// existing values are (de)crypted in place, which requires the key manager to be unlocked
+					for field in args.iter().filter(|field| !current.contains(field)) {
+						apply_field_encryption(&library, *field, true).await?;
+					}
+
+					for field in current.iter().filter(|field| !args.contains(field)) {
+						apply_field_encryption(&library, *field, false).await?;
+					}
+
+					ctx.library_manager
+						.update_config(library.id, |config| {
+							config.encrypted_fields = args;
+						})
+						.await?;
+
+					invalidate_query!(library, "keys.getEncryptedFields");
+					invalidate_query!(library, "locations.getExplorerData");
+					invalidate_query!(library, "savedSearches.getExplorerData");
+					invalidate_query!(library, "tags.getExplorerData");
+					Ok(())
+				},
+			))
+		})
+		.library_mutation("clearMasterPassword", |t| {
+			t(scoped(KeyCapability::Unlock, |_, _: (), library| async move {
+				// This technically clears the root key, but it means the same thing to the frontend
+				library.key_manager.clear_root_key()?;
+				library.thumbnail_cache().purge_library(library.id);
+				record_key_audit_event(&library, KeyAuditAction::Lock, None).await;
+
+				library.emit(CoreEvent::KeyManagerLockStateChanged {
+					library_id: library.id,
+					locked: true,
+				});
+
+				invalidate_query!(library, "keys.hasMasterPassword");
+				Ok(())
+			}))
+		})
+		.library_mutation("syncKeyToLibrary", |t| {
+			t(scoped(KeyCapability::Manage, |_, key_uuid: Uuid, library| async move {
+				let key = library.key_manager.save_to_database(key_uuid)?;
+
+				// a stale row may have been left behind for this key, so it's overwritten if it exists
+				upsert_storedkey_in_db(library.db.clone(), &key).await?;
+
+				invalidate_query!(library, "keys.list");
+				Ok(())
+			}))
+		})
+		// this removes the key from the library, but keeps it within the key manager as a memory-only key
+		.library_mutation("unsyncFromLibrary", |t| {
+			t(scoped(KeyCapability::Manage, |_, key_uuid: Uuid, library| async move {
+				library.key_manager.remove_from_database(key_uuid)?;
+
+				// if this fails, the stale row is overwritten once the key is synced again
+				library
+					.db
+					.key()
+					.delete_many(vec![key::uuid::equals(key_uuid.to_string())])
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "keys.list");
+				Ok(())
+			}))
+		})
+		.library_mutation("updateAutomountStatus", |t| {
+			t(scoped(KeyCapability::Manage, |_, args: AutomountUpdateArgs, library| async move {
+				if !library.key_manager.is_memory_only(args.uuid)? {
+					library
+						.key_manager
+						.change_automount_status(args.uuid, args.status)?;
+
+					library
+						.db
+						.key()
+						.update(
+							key::uuid::equals(args.uuid.to_string()),
+							vec![key::SetParam::SetAutomount(args.status)],
+						)
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "keys.list");
+				}
+
+				Ok(())
+			}))
+		})
+		.library_query("getAutomountOrder", |t| {
+			t(scoped(KeyCapability::Read, |_, _: (), library| async move {
+				let keys = library
+					.db
+					.key()
+					.find_many(vec![key::automount::equals(true)])
+					.order_by(key::automount_order::order(Direction::Asc))
+					.order_by(key::id::order(Direction::Asc))
+					.exec()
+					.await?;
+
+				keys.into_iter()
+					.map(|key| {
+						Uuid::from_str(&key.uuid).map_err(|_| {
+							KeyRouteError::Corrupt("Error deserializing UUID from string".into())
+						})
+					})
+					.collect::<Result<Vec<_>, _>>()
+			}))
+		})
+		// the provided keys are automounted in the given order, and any other keys are automounted after them
+		.library_mutation("setAutomountOrder", |t| {
+			t(scoped(KeyCapability::Manage, |_, order: Vec<Uuid>, library| async move {
+				for uuid in &order {
+					// memory-only keys aren't stored in the database, so they can't be ordered
+					if library.key_manager.is_memory_only(*uuid)? {
+						return Err(KeyRouteError::NotFound);
+					}
+				}
+
+				let uuids = order.iter().map(Uuid::to_string).collect::<Vec<_>>();
+
+				library
+					.db
+					.key()
+					.update_many(
+						vec![key::uuid::not_in_vec(uuids.clone())],
+						vec![key::automount_order::set(uuids.len() as i32)],
+					)
+					.exec()
+					.await?;
+
+				library
+					.db
+					._batch(
+						uuids
+							.into_iter()
+							.enumerate()
+							.map(|(i, uuid)| {
+								library.db.key().update(
+									key::uuid::equals(uuid),
+									vec![key::automount_order::set(i as i32)],
+								)
+							})
+							.collect::<Vec<_>>(),
+					)
+					.await?;
+
+				invalidate_query!(library, "keys.getAutomountOrder");
+				Ok(())
+			}))
+		})
+		.library_mutation("deleteFromLibrary", |t| {
+			t(scoped(KeyCapability::Manage, |ctx, key_uuid: Uuid, library| async move {
+				if !library.key_manager.is_memory_only(key_uuid)? {
+					library
+						.db
+						.key()
+						.delete(key::uuid::equals(key_uuid.to_string()))
+						.exec()
+						.await?;
+				}
+
+				library.key_manager.remove_key(key_uuid)?;
+				library.key_consumers.revoke(key_uuid);
+
+				ctx.library_manager
+					.update_config(library.id, |config| {
+						config.context_default_keys.retain(|d| d.key_uuid != key_uuid);
+					})
+					.await?;
+				record_key_audit_event(&library, KeyAuditAction::Delete, Some(key_uuid)).await;
+
+				invalidate_query!(library, "keys.list");
+				invalidate_query!(library, "keys.listMounted");
+				invalidate_query!(library, "keys.getDefault");
+				Ok(())
+			}))
+		})
+		.library_mutation("onboarding", |t| {
+			t(scoped(
+				KeyCapability::ChangeMasterPassword,
+				|ctx, args: OnboardingArgs, library| async move {
+					// onboarding again would replace the root key, and every stored key is wrapped with it
+					if library.key_manager.get_verification_key().is_ok() {
+						return Err(KeyRouteError::AlreadyOnboarded);
+					}
+
+					let bundle = KeyManager::onboarding(
+						args.algorithm,
+						args.hashing_algorithm,
+						args.passphrase_style.unwrap_or_default(),
+						args.password.map(Protected::new),
+					)?;
+
+					// this is checked against the generated password too, so the hint never gives it away
+					if let Some(hint) = &args.hint {
+						validate_password_hint(hint, bundle.master_password.expose())?;
+					}
+
+					let verification_key = bundle.verification_key;
+
+					// remove old nil-id keys if they were set
+					// they possibly won't be, but we CANNOT have multiple
+					library
+						.db
+						.key()
+						.delete_many(vec![key::uuid::equals(Uuid::nil().to_string())])
+						.exec()
+						.await?;
+
+					write_storedkey_to_db(library.db.clone(), &verification_key).await?;
+					library
+						.key_manager
+						.set_verification_key(verification_key)?;
+					invalidate_query!(library, "keys.isOnboarded");
+
+					ctx.library_manager
+						.update_config(library.id, |config| {
+							config.master_password_hint = args.hint;
+						})
+						.await?;
+
+					let keys = OnboardingKeys {
+						master_password: bundle.master_password.expose().clone(),
+						secret_key: bundle.secret_key.expose().clone(),
+					};
+
+					Ok(keys)
+				},
+			))
+		})
+		.library_mutation("setMasterPassword", |t| {
+			t(scoped(KeyCapability::Unlock, |_, args: SetMasterPasswordArgs, library| async move {
+				// if this returns an error, the user MUST re-enter the correct password
+				library.key_manager.set_master_password(
+					Protected::new(args.password),
+					Protected::new(args.secret_key),
+				)?;
+				record_key_audit_event(&library, KeyAuditAction::Unlock, None).await;
+
+				library.emit(CoreEvent::KeyManagerLockStateChanged {
+					library_id: library.id,
+					locked: false,
+				});
+
+				// keys are mounted one at a time, in the user-defined order
+				// a failure is recorded and skipped over, so it doesn't affect the keys before or after it
+				let automount = library
+					.db
+					.key()
+					.find_many(vec![key::automount::equals(true)])
+					.order_by(key::automount_order::order(Direction::Asc))
+					.order_by(key::id::order(Direction::Asc))
+					.exec()
+					.await?;
+
+				let mut result = AutomountResult {
+					mounted: Vec::new(),
+					failed: Vec::new(),
+					jobs: Vec::new(),
+				};
+
+				for key in automount {
+					let mounted = Uuid::from_str(&key.uuid)
+						.map_err(|_| "Error deserializing UUID from string".to_string())
+						.and_then(|uuid| {
+							library
+								.key_manager
+								.mount_with_source(uuid, MountSource::Automount)
+								.map(|_| uuid)
+								.map_err(|e| e.to_string())
+						});
+
+					match mounted {
+						Ok(uuid) => {
+							if args.decrypt_headers {
+								result
+									.jobs
+									.extend(dispatch_header_decryption(&library, uuid).await);
+							}
+
+							result.mounted.push(uuid);
+						}
+						Err(error) => {
+							warn!("Failed to automount key {}: {}", key.uuid, error);
+							result.failed.push(AutomountFailure {
+								uuid: key.uuid,
+								error,
+							});
+						}
+					}
+				}
+
+				library.resume_jobs_waiting_for_keys().await;
+
+				invalidate_query!(library, "keys.hasMasterPassword");
+				invalidate_query!(library, "keys.listMounted");
+
+				Ok(result)
+			}))
+		})
+		// if a context is provided, the key only becomes the default for that location/tag
+		.library_mutation("setDefault", |t| {
+			t(scoped(KeyCapability::Manage, |ctx, args: KeySetDefaultArgs, library| async move {
+				let key_uuid = args.uuid;
+
+				if let Some(context) = args.context {
+					// this ensures the key exists
+					library.key_manager.access_keystore(key_uuid)?;
+
+					ctx.library_manager
+						.update_config(library.id, |config| {
+							config.context_default_keys.retain(|d| d.context != context);
+							config.context_default_keys.push(ContextDefaultKey {
+								context,
+								key_uuid,
+							});
+						})
+						.await?;
+
+					invalidate_query!(library, "keys.getDefault");
+					return Ok(());
+				}
+
+				library.key_manager.set_default(key_uuid)?;
+
+				library
+					.db
+					.key()
+					.update_many(
+						vec![key::default::equals(true)],
+						vec![key::SetParam::SetDefault(false)],
+					)
+					.exec()
+					.await?;
+				library
+					.db
+					.key()
+					.update(
+						key::uuid::equals(key_uuid.to_string()),
+						vec![key::SetParam::SetDefault(true)],
+					)
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "keys.getDefault");
+				Ok(())
+			}))
+		})
+		// this falls back to the library's default key if the context doesn't have one
+		.library_query("getDefault", |t| {
+			t(scoped(
+				KeyCapability::Read,
+				|ctx, context: Option<KeyDefaultContext>, library| async move {
+					if let Some(context) = context {
+						// the context's config is a snapshot from when the library was loaded, so fetch the current one
+						let context_default = ctx
+							.library_manager
+							.get_ctx(library.id)
+							.await
+							.and_then(|library| {
+								library
+									.config
+									.context_default_keys
+									.into_iter()
+									.find(|d| d.context == context)
+							})
+							.map(|d| d.key_uuid);
+
+						// the key may have been removed since it was set as the default
+						if let Some(key_uuid) = context_default {
+							if library.key_manager.access_keystore(key_uuid).is_ok() {
+								return Ok(Some(key_uuid));
+							}
+						}
+					}
+
+					let default = library.key_manager.get_default();
+
+					if let Ok(default_key) = default {
+						Ok(Some(default_key))
+					} else {
+						Ok(None)
+					}
+				},
+			))
+		})
+		.library_mutation("clearContextDefault", |t| {
+			t(scoped(KeyCapability::Manage, |ctx, context: KeyDefaultContext, library| async move {
+				ctx.library_manager
+					.update_config(library.id, |config| {
+						config.context_default_keys.retain(|d| d.context != context);
+					})
+					.await?;
+
+				invalidate_query!(library, "keys.getDefault");
+				Ok(())
+			}))
+		})
+		.library_mutation("unmountAll", |t| {
+			t(scoped(KeyCapability::Mount, |_, _: (), library| async move {
+				library.key_manager.empty_keymount();
+				library.key_consumers.revoke_all();
+				record_key_audit_event(&library, KeyAuditAction::Unmount, None).await;
+
+				invalidate_query!(library, "keys.listMounted");
+				Ok(())
+			}))
+		})
+		// this also mounts the key
+		.library_mutation("add", |t| {
+			t(scoped(KeyCapability::Manage, |_, args: KeyAddArgs, library| async move {
+				let source = args.key.source();
+
+				// register the key with the keymanager
+				let uuid = library.key_manager.add_to_keystore(
+					args.key.decode()?,
+					args.algorithm,
+					args.hashing_algorithm,
+					!args.library_sync,
+					args.automount,
+					None,
+					source,
+				)?;
+
+				let stored_key = library.key_manager.access_keystore(uuid)?;
+
+				if args.library_sync {
+					write_storedkey_to_db(library.db.clone(), &stored_key).await?;
+
+					if args.automount {
+						library
+							.db
+							.key()
+							.update(
+								key::uuid::equals(uuid.to_string()),
+								vec![key::SetParam::SetAutomount(true)],
+							)
+							.exec()
+							.await?;
+					}
+				}
+
+				// mount the key
+				library.key_manager.mount(uuid)?;
+				record_key_audit_event(&library, KeyAuditAction::Add, Some(uuid)).await;
+
+				library.resume_jobs_waiting_for_keys().await;
+
+				invalidate_query!(library, "keys.list");
+				invalidate_query!(library, "keys.listMounted");
+				Ok(())
+			}))
+		})
+		.library_mutation("rotate", |t| {
+			t(scoped(KeyCapability::Manage, |_, args: KeyRotateArgs, library| async move {
+				// the previous hashed key is needed for re-wrapping files that use the key
+				let old_hashed_key = library.key_manager.get_hashed_key(args.uuid)?;
+
+				let rotated_key = library.key_manager.rotate_key(
+					args.uuid,
+					args.algorithm,
+					args.hashing_algorithm,
+					args.password
+						.map(|password| Protected::new(password.into_bytes())),
+				)?;
+
+				// the database is updated first, so the keystore is left untouched if this fails
+				update_storedkey_in_db(library.db.clone(), &rotated_key).await?;
+
+				library.key_manager.update_stored_key(rotated_key)?;
+				library
+					.key_manager
+					.retire_hashed_key(args.uuid, old_hashed_key);
+
+				let job_id = dispatch_rekeying(&library, args.uuid, None).await;
+
+				invalidate_query!(library, "keys.list");
+				invalidate_query!(library, "keys.listMounted");
+				Ok(job_id)
+			}))
+		})
+		// this moves every file that uses a key over to a replacement key, so the revoked key can be deleted afterwards
+		.library_mutation("revoke", |t| {
+			t(scoped(KeyCapability::Manage, |_, args: KeyRevokeArgs, library| async move {
+				if args.uuid == args.replacement {
+					return Err(KeyRouteError::InvalidKey(
+						"a key can't be replaced with itself".into(),
+					));
+				}
+
+				// files are re-associated with the replacement key, so it needs to be stored within the library
+				if library.key_manager.is_memory_only(args.replacement)? {
+					return Err(KeyRouteError::InvalidKey(
+						"the replacement key is memory-only".into(),
+					));
+				}
+
+				// this ensures the revoked key is still usable, as its files can't be re-wrapped otherwise
+				library.key_manager.access_keystore(args.uuid)?;
+
+				Ok(dispatch_rekeying(&library, args.uuid, Some(args.replacement)).await)
+			}))
+		})
+		// this returns the exported key as a base64-encoded string, so it can be saved/shared by the client
+		.library_mutation("export", |t| {
+			t(scoped(KeyCapability::Export, |_, args: KeyExportArgs, library| async move {
+				let exported_key = library
+					.key_manager
+					.export_key(args.uuid, Protected::new(args.password.into_bytes()))?;
+				record_key_audit_event(&library, KeyAuditAction::Export, Some(args.uuid)).await;
+
+				Ok(base64::encode(exported_key))
+			}))
+		})
+		.library_mutation("import", |t| {
+			t(scoped(KeyCapability::Manage, |_, args: KeyImportArgs, library| async move {
+				let exported_key = base64::decode(args.key).map_err(|_| {
+					KeyRouteError::Corrupt("Error decoding exported key".into())
+				})?;
+
+				// this verifies the key and rejects it if it's already in the keystore
+				let uuid = library.key_manager.import_key(
+					&exported_key,
+					Protected::new(args.password.into_bytes()),
+					!args.library_sync,
+					args.automount,
+				)?;
+
+				if args.library_sync {
+					let stored_key = library.key_manager.access_keystore(uuid)?;
+					write_storedkey_to_db(library.db.clone(), &stored_key).await?;
+
+					if args.automount {
+						library
+							.db
+							.key()
+							.update(
+								key::uuid::equals(uuid.to_string()),
+								vec![key::SetParam::SetAutomount(true)],
+							)
+							.exec()
+							.await?;
+					}
+				}
+
+				record_key_audit_event(&library, KeyAuditAction::Add, Some(uuid)).await;
+
+				invalidate_query!(library, "keys.list");
+				Ok(uuid)
+			}))
+		})
+		// both libraries need to be unlocked, as the key is re-wrapped with the target library's root key
+		.library_mutation("copyToLibrary", |t| {
+			t(scoped(KeyCapability::Manage, |ctx, args: KeyCopyArgs, library| async move {
+				if args.target_library == library.id {
+					return Err(KeyRouteError::AlreadyExists);
+				}
+
+				let target = ctx
+					.library_manager
+					.get_ctx(args.target_library)
+					.await
+					.ok_or(KeyRouteError::NotFound)?;
+
+				// this rejects the key if it's already in the target keystore
+				let uuid = target
+					.key_manager
+					.copy_key_from(&library.key_manager, args.key_uuid)?;
+
+				let stored_key = target.key_manager.access_keystore(uuid)?;
+
+				if !stored_key.memory_only {
+					if let Err(e) = write_storedkey_to_db(target.db.clone(), &stored_key).await {
+						// the keystore and database should stay in sync
+						target.key_manager.remove_key(uuid)?;
+						return Err(e.into());
+					}
+
+					if stored_key.automount {
+						target
+							.db
+							.key()
+							.update(
+								key::uuid::equals(uuid.to_string()),
+								vec![key::SetParam::SetAutomount(true)],
+							)
+							.exec()
+							.await?;
+					}
+				}
+
+				record_key_audit_event(&library, KeyAuditAction::Export, Some(uuid)).await;
+				record_key_audit_event(&target, KeyAuditAction::Add, Some(uuid)).await;
+
+				invalidate_query!(target, "keys.list");
+				Ok(())
+			}))
+		})
+		// entries are returned newest first
+		.library_query("auditLog", |t| {
+			t(scoped(KeyCapability::Read, |_, args: KeyAuditLogArgs, library| async move {
+				let total = library
+					.db
+					.key_audit_event()
+					.count(audit_log_filters(&args.filter))
+					.exec()
+					.await?;
+
+				let entries = library
+					.db
+					.key_audit_event()
+					.find_many(audit_log_filters(&args.filter))
+					.order_by(key_audit_event::date_created::order(Direction::Desc))
+					.skip(args.skip.unwrap_or(0))
+					.take(args.take.unwrap_or(AUDIT_LOG_PAGE_SIZE))
+					.include(key_audit_event_with_node::include())
+					.exec()
+					.await?
+					.into_iter()
+					.map(Into::into)
+					.collect();
+
+				Ok(KeyAuditLogPage { entries, total })
+			}))
+		})
+		// this returns every entry that matches the filter, formatted so it can be saved by the client
+		.library_mutation("exportAuditLog", |t| {
+			t(scoped(KeyCapability::Export, |_, args: KeyAuditLogExportArgs, library| async move {
+				let entries = library
+					.db
+					.key_audit_event()
+					.find_many(audit_log_filters(&args.filter))
+					.order_by(key_audit_event::date_created::order(Direction::Desc))
+					.include(key_audit_event_with_node::include())
+					.exec()
+					.await?
+					.into_iter()
+					.map(KeyAuditLogEntry::from)
+					.collect::<Vec<_>>();
+
+				match args.format {
+					KeyAuditLogFormat::Json => serde_json::to_string(&entries).map_err(|_| {
+						KeyRouteError::Internal("Error serializing audit log".into())
+					}),
+					KeyAuditLogFormat::Csv => {
+						let mut csv = String::from("id,date,action,key,node\n");
+
+						for entry in entries {
+							csv.push_str(&format!(
+								"{},{},{:?},{},\"{}\"\n",
+								entry.id,
+								entry.date_created.to_rfc3339(),
+								entry.action,
+								entry.key_uuid.unwrap_or_default(),
+								entry.node.name.replace('"', "\"\"")
+							));
+						}
+
+						Ok(csv)
+					}
+				}
+			}))
+		})
+		.library_mutation("backupKeystore", |t| {
+			t(scoped(KeyCapability::Export, |ctx, path: PathBuf, library| async move {
+				let backup = create_keystore_backup(&ctx.library_manager, &library).await?;
+
+				let mut output_file = std::fs::File::create(path).map_err(|_| {
+					KeyRouteError::IO("Error creating file".into())
+				})?;
+				output_file.write_all(&backup).map_err(|_| {
+					KeyRouteError::IO("Error writing key backup to file".into())
+				})?;
+				Ok(())
+			}))
+		})
+		// this returns the backup to the client (base64-encoded), instead of writing it to a path on the node
+		// this is required for remote clients (e.g. the browser), as they can't access the node's filesystem
+		.library_mutation("backupKeystoreToBytes", |t| {
+			t(scoped(KeyCapability::Export, |ctx, _: (), library| async move {
+				let backup = create_keystore_backup(&ctx.library_manager, &library).await?;
+
+				Ok(base64::encode(backup))
+			}))
+		})
+		.library_mutation("restoreKeystore", |t| {
+			t(scoped(KeyCapability::Manage, |_, args: RestoreBackupArgs, library| async move {
+				let mut input_file = std::fs::File::open(args.path).map_err(|_| {
+					KeyRouteError::IO("Error opening backup file".into())
+				})?;
+
+				let mut backup = Vec::new();
+
+				input_file.read_to_end(&mut backup).map_err(|_| {
+					KeyRouteError::IO("Error reading backup file".into())
+				})?;
+
+				restore_keystore_backup(
+					&library,
+					args.password,
+					args.secret_key,
+					&backup,
+					args.dry_run,
+				)
+				.await
+			}))
+		})
+		.library_mutation("restoreKeystoreFromBytes", |t| {
+			t(scoped(KeyCapability::Manage, |_, args: RestoreBackupBytesArgs, library| async move {
+				let backup = base64::decode(args.backup).map_err(|_| {
+					KeyRouteError::Corrupt("Error decoding backup".into())
+				})?;
+
+				restore_keystore_backup(
+					&library,
+					args.password,
+					args.secret_key,
+					&backup,
+					args.dry_run,
+				)
+				.await
+			}))
+		})
+		// this pulls the backup from a remote (S3, SFTP or WebDAV), so it doesn't have to pass through the client
+		.library_mutation("restoreKeystoreFromRemote", |t| {
+			t(scoped(KeyCapability::Manage, |_, args: RestoreBackupRemoteArgs, library| async move {
+				let (backend, path) = match args.source {
+					RemoteBackupSource::Location { location_id, path } => {
+						(connect(&library, location_id).await?, path)
+					}
+					RemoteBackupSource::Remote {
+						config,
+						credentials,
+						path,
+					} => (connect_with_credentials(&config, credentials)?, path),
+				};
+
+				let mut backup = Vec::new();
+				backend.read(&path, 0, &mut backup).await?;
+
+				restore_keystore_backup(
+					&library,
+					args.password,
+					args.secret_key,
+					&backup,
+					args.dry_run,
+				)
+				.await
+			}))
+		})
+		.library_mutation("changeMasterPassword", |t| {
+			t(scoped(
+				KeyCapability::ChangeMasterPassword,
+				|ctx, args: MasterPasswordChangeArgs, library| async move {
+					let library_id = library.id;
+
+					if let Some(hint) = &args.hint {
+						validate_password_hint(hint, &args.password)?;
+					}
+
+					// encrypted fields use a subkey of the root key, so they need re-encrypting once it changes
+					let old_metadata_key = library.key_manager.derive_subkey(METADATA_CONTEXT)?;
+
+					let bundle = library.key_manager.change_master_password(
+						Protected::new(args.password),
+						args.algorithm,
+						args.hashing_algorithm,
+						|completed, total| {
+							library.emit(CoreEvent::MasterPasswordChangeProgress {
+								library_id,
+								completed,
+								total,
+							})
+						},
+					)?;
+
+					let fields = reencrypt_fields(
+						&library,
+						old_metadata_key,
+						bundle.derive_subkey(METADATA_CONTEXT),
+					)
+					.await?;
+
+					let note_updates = fields
+						.notes
+						.into_iter()
+						.map(|(id, note)| {
+							library
+								.db
+								.object()
+								.update(object::id::equals(id), vec![object::encrypted_note::set(Some(note))])
+						})
+						.collect::<Vec<_>>();
+
+					let media_data_updates = fields
+						.locations
+						.into_iter()
+						.map(|(id, location)| {
+							library.db.media_data().update(
+								media_data::id::equals(id),
+								vec![media_data::encrypted_location::set(Some(location))],
+							)
+						})
+						.collect::<Vec<_>>();
+
+					let location_updates = fields
+						.credentials
+						.into_iter()
+						.map(|(id, credentials)| {
+							library.db.location().update(
+								location::id::equals(id),
+								vec![location::remote_credentials::set(Some(credentials))],
+							)
+						})
+						.collect::<Vec<_>>();
+
+					// every key (and TOTP secret) has been re-wrapped with the new root key
+					// these are all written at once (alongside the re-encrypted fields), so the library is never left with a mix of old and new keys
+					let key_updates = bundle
+						.updated_keys
+						.iter()
+						.filter(|key| !key.memory_only)
+						.map(|key| {
+							library.db.key().update(
+								key::uuid::equals(key.uuid.to_string()),
+								storedkey_params(key),
+							)
+						})
+						.collect::<Vec<_>>();
+
+					// memory-only keys don't have a `key_totp` row, so their updates don't match anything
+					let totp_updates = bundle
+						.updated_totp
+						.iter()
+						.map(|(uuid, stored_totp)| {
+							library.db.key_totp().update_many(
+								vec![key_totp::key::is(vec![key::uuid::equals(uuid.to_string())])],
+								totp_params(stored_totp),
+							)
+						})
+						.collect::<Vec<_>>();
+
+					let verification_key = &bundle.verification_key;
+
+					library
+						.db
+						._batch((
+							// remove old nil-id keys if they were set
+							library
+								.db
+								.key()
+								.delete_many(vec![key::uuid::equals(Uuid::nil().to_string())]),
+							// and write the new verification key
+							library.db.key().create(
+								verification_key.uuid.to_string(),
+								verification_key.algorithm.serialize().to_vec(),
+								verification_key.hashing_algorithm.serialize().to_vec(),
+								verification_key.content_salt.to_vec(),
+								verification_key.master_key.to_vec(),
+								verification_key.master_key_nonce.to_vec(),
+								verification_key.key_nonce.to_vec(),
+								verification_key.key.to_vec(),
+								verification_key.salt.to_vec(),
+								vec![
+									key::name::set(verification_key.name.clone()),
+									key::source::set(verification_key.source.serialize()),
+								],
+							),
+							key_updates,
+							totp_updates,
+							(note_updates, media_data_updates, location_updates),
+						))
+						.await?;
+
+					// the new root key is only used once everything has been written successfully
+					library.key_manager.commit_master_password_change(&bundle)?;
+
+					ctx.library_manager
+						.update_config(library.id, |config| {
+							config.master_password_hint = args.hint;
+						})
+						.await?;
+
+					// lock the key manager, so every client needs to unlock it again with the new details
+					// this prevents stale clients from continuing to operate as if nothing changed
+					library.key_manager.empty_keymount();
+					library.key_consumers.revoke_all();
+					library.key_manager.clear_root_key()?;
+					record_key_audit_event(&library, KeyAuditAction::MasterPasswordChange, None)
+						.await;
+
+					library.emit(CoreEvent::KeyManagerLockStateChanged {
+						library_id: library.id,
+						locked: true,
+					});
+
+					invalidate_query!(library, "keys.hasMasterPassword");
+					invalidate_query!(library, "keys.listMounted");
+
+					Ok(bundle.secret_key.expose().clone())
+				},
+			))
+		})
+		// this returns the new secret key, which the user needs to store
+		// the root key is unchanged, so the library stays unlocked and no other keys need updating
+		.library_mutation("rotateSecretKey", |t| {
+			t(scoped(
+				KeyCapability::ChangeMasterPassword,
+				|_, args: SecretKeyRotationArgs, library| async move {
+					let bundle = library.key_manager.rotate_secret_key(
+						Protected::new(args.password),
+						Protected::new(args.secret_key),
+					)?;
+
+					// this is a single update, so the verification key is never left half-written
+					update_storedkey_in_db(library.db.clone(), &bundle.verification_key).await?;
+
+					library
+						.key_manager
+						.set_verification_key(bundle.verification_key)?;
+
+					record_key_audit_event(&library, KeyAuditAction::SecretKeyRotation, None).await;
+
+					Ok(bundle.secret_key.expose().clone())
+				},
+			))
+		})
+		// this yields `true` when the library is locked, and `false` when it's unlocked
+		.library_subscription("lockState", |t| {
+			t(scoped_subscription(
+				KeyCapability::Read,
+				|ctx, _: (), library_id| {
+					let mut event_bus_rx = ctx.event_bus.subscribe();
+					async_stream::stream! {
+						while let Ok(event) = event_bus_rx.recv().await {
+							match event {
+								CoreEvent::KeyManagerLockStateChanged { library_id: id, locked } if id == library_id => yield locked,
+								_ => {}
+							}
+						}
+					}
+				},
+			))
+		})
+		// this yields the amount of seconds until the library auto-locks, once it's about to
+		.library_subscription("lockingSoon", |t| {
+			t(scoped_subscription(
+				KeyCapability::Read,
+				|ctx, _: (), library_id| {
+					let mut event_bus_rx = ctx.event_bus.subscribe();
+					async_stream::stream! {
+						while let Ok(event) = event_bus_rx.recv().await {
+							match event {
+								CoreEvent::KeyManagerLockingSoon { library_id: id, seconds_remaining } if id == library_id => yield seconds_remaining,
+								_ => {}
+							}
+						}
+					}
+				},
+			))
+		})
+		.library_subscription("changeMasterPasswordProgress", |t| {
+			t(scoped_subscription(
+				KeyCapability::Read,
+				|ctx, _: (), library_id| {
+					let mut event_bus_rx = ctx.event_bus.subscribe();
+					async_stream::stream! {
+						while let Ok(event) = event_bus_rx.recv().await {
+							match event {
+								CoreEvent::MasterPasswordChangeProgress { library_id: id, completed, total } if id == library_id => yield (completed, total),
+								_ => {}
+							}
+						}
+					}
+				},
+			))
+		})
+}
+
+const MAXIMUM_PASSWORD_HINT_LENGTH: usize = 100;
+
+/// This ensures that a master password hint is reasonable, and that it doesn't just contain the password.
+///
+/// Hints are stored unencrypted, so this only discourages the obvious cases.
+fn validate_password_hint(hint: &str, password: &str) -> Result<(), KeyRouteError> {
+	let normalize = |s: &str| {
+		s.chars()
+			.filter(|c| !c.is_whitespace())
+			.flat_map(char::to_lowercase)
+			.collect::<String>()
+	};
+
+	let (hint, password) = (normalize(hint), normalize(password));
+
+	if hint.is_empty() {
+		return Err(KeyRouteError::InvalidHint("the hint is empty".into()));
+	}
+
+	if hint.chars().count() > MAXIMUM_PASSWORD_HINT_LENGTH {
+		return Err(KeyRouteError::InvalidHint(format!(
+			"the hint can't be longer than {MAXIMUM_PASSWORD_HINT_LENGTH} characters"
+		)));
+	}
+
+	// a hint that makes up half of the password gives too much of it away
+	if hint.contains(&password) || (password.contains(&hint) && hint.len() * 2 >= password.len()) {
+		return Err(KeyRouteError::InvalidHint(
+			"the hint can't contain the password".into(),
+		));
+	}
+
+	Ok(())
+}
+
+type ScopedFuture<T> = Pin<Box<dyn Future<Output = Result<T, rspc::Error>> + Send>>;
+
+/// This wraps a key manager route, so the request must be authorized for the given capability before it's resolved.
+///
+/// Any `KeyRouteError` returned by the resolver is converted into an `rspc::Error` here.
+///
+/// See `Ctx::authorize` for how requests are authorized.
+fn scoped<TArg, TResult, TFut>(
+	capability: KeyCapability,
+	resolver: impl Fn(Ctx, TArg, LibraryContext) -> TFut + Send + Sync + 'static,
+) -> impl Fn(Ctx, TArg, LibraryContext) -> ScopedFuture<TResult> + Send + Sync + 'static
+where
+	TArg: Send + 'static,
+	TResult: 'static,
+	TFut: Future<Output = Result<TResult, KeyRouteError>> + Send + 'static,
+{
+	let resolver = Arc::new(resolver);
+
+	move |ctx, arg, library| {
+		let resolver = Arc::clone(&resolver);
+		Box::pin(async move {
+			ctx.authorize(capability).await?;
+			Ok(resolver(ctx, arg, library).await?)
+		})
+	}
+}
+
+type ScopedStream<T> = Pin<Box<dyn Stream<Item = T> + Send + Sync>>;
+
+/// This wraps a key manager subscription in the same way as `scoped`, so nothing is yielded until the request has been authorized.
+///
+/// Subscriptions can't return errors, so one that isn't authorized just ends straight away.
+fn scoped_subscription<TArg, TResult, TStream>(
+	capability: KeyCapability,
+	resolver: impl Fn(Ctx, TArg, Uuid) -> TStream + Send + Sync + 'static,
+) -> impl Fn(Ctx, TArg, Uuid) -> ScopedStream<TResult> + Send + Sync + 'static
+where
+	TArg: Send + Sync + 'static,
+	TResult: Send + Sync + 'static,
+	TStream: Stream<Item = TResult> + Send + Sync + 'static,
+{
+	let resolver = Arc::new(resolver);
+
+	move |ctx, arg, library_id| {
+		let resolver = Arc::clone(&resolver);
+		Box::pin(async_stream::stream! {
+			if let Err(e) = ctx.authorize(capability).await {
+				warn!("Refused key manager subscription: {:?}", e);
+				return;
+			}
+
+			for await item in resolver(ctx, arg, library_id) {
+				yield item;
+			}
+		})
+	}
+}
+
+fn local_only(ctx: &Ctx) -> Result<(), rspc::Error> {
+	if ctx.remote {
+		return Err(rspc::Error::new(
+			rspc::ErrorCode::Forbidden,
+			"API tokens can only be managed from the local device".to_string(),
+		));
+	}
+
+	Ok(())
+}
+
+fn audit_log_filters(filter: &KeyAuditLogFilter) -> Vec<key_audit_event::WhereParam> {
+	let mut filters = Vec::new();
+
+	if let Some(from) = filter.from {
+		filters.push(key_audit_event::date_created::gte(from.into()));
+	}
+
+	if let Some(to) = filter.to {
+		filters.push(key_audit_event::date_created::lte(to.into()));
+	}
+
+	if let Some(key_uuid) = filter.key_uuid {
+		filters.push(key_audit_event::key_uuid::equals(Some(
+			key_uuid.to_string(),
+		)));
+	}
+
+	filters
+}
+
+/// This creates a keystore backup, ready to be written to a file or sent to the client.
+///
+/// It includes all keys that are synced with the library, the verification key at the time of backup, and the objects/file paths that each key is associated with.
+///
+/// The time of the backup is recorded within the library's config.
+pub(crate) async fn create_keystore_backup(
+	library_manager: &LibraryManager,
+	library: &LibraryContext,
+) -> Result<Vec<u8>, KeyRouteError> {
+	// dump all stored keys that are in the key manager (maybe these should be taken from prisma as this will include even "non-sync with library" keys)
+	let mut stored_keys = library.key_manager.dump_keystore();
+	// include the verification key at the time of backup
+	stored_keys.push(library.key_manager.get_verification_key()?);
+	stored_keys.retain(|k| !k.memory_only);
+
+	let associations = library
+		.db
+		.key()
+		.find_many(vec![])
+		.select(key_associations::select())
+		.exec()
+		.await?
+		.into_iter()
+		.filter(|k| !k.objects.is_empty() || !k.file_paths.is_empty())
+		.map(|k| KeyAssociations {
+			key_uuid: k.uuid,
+			objects: k.objects.into_iter().map(|o| o.cas_id).collect(),
+			file_paths: k
+				.file_paths
+				.into_iter()
+				.map(|fp| (fp.location.pub_id, fp.materialized_path))
+				.collect(),
+		})
+		.collect();
+
+	let backup = KeystoreBackup {
+		keys: stored_keys,
+		associations,
+	};
+
+	let backup = serde_json::to_vec(&backup)
+		.map_err(|_| KeyRouteError::Internal("Error serializing keystore".into()))?;
+
+	library_manager
+		.update_config(library.id, |config| {
+			config.last_keystore_backup = Some(Utc::now());
+		})
+		.await?;
+
+	record_key_audit_event(library, KeyAuditAction::Backup, None).await;
+
+	invalidate_query!(library, "keys.stats");
+
+	Ok(backup)
+}
+
+/// This links objects and file paths back up with the keys that they were associated with at the time of a backup.
+///
+/// Anything that no longer exists within the library (or hasn't been indexed yet) is skipped.
+async fn relink_key_associations(
+	library: &LibraryContext,
+	associations: Vec<KeyAssociations>,
+) -> Result<(), KeyRouteError> {
+	for association in associations {
+		let key = match library
+			.db
+			.key()
+			.find_unique(key::uuid::equals(association.key_uuid.clone()))
+			.exec()
+			.await?
+		{
+			Some(key) => key,
+			None => continue,
+		};
+
+		library
+			.db
+			.object()
+			.update_many(
+				vec![object::cas_id::in_vec(association.objects)],
+				vec![object::key_id::set(Some(key.id))],
+			)
+			.exec()
+			.await?;
+
+		for (location_pub_id, materialized_path) in association.file_paths {
+			library
+				.db
+				.file_path()
+				.update_many(
+					vec![
+						file_path::location::is(vec![location::pub_id::equals(location_pub_id)]),
+						file_path::materialized_path::equals(materialized_path),
+					],
+					vec![file_path::key_id::set(Some(key.id))],
+				)
+				.exec()
+				.await?;
+		}
+	}
+
+	Ok(())
+}
+
+/// This restores a keystore backup into the key manager, and writes the restored keys to the library's database.
+///
+/// It returns the amount of keys that were restored.
+///
+/// If `dry_run` is set, the backup is unlocked and compared against the keystore, but nothing is written.
+async fn restore_keystore_backup(
+	library: &LibraryContext,
+	password: String,
+	secret_key: String,
+	backup: &[u8],
+	dry_run: bool,
+) -> Result<RestoreBackupResult, KeyRouteError> {
+	let backup: KeystoreBackup = serde_json::from_slice::<KeystoreBackupPayload>(backup)
+		.map_err(|_| KeyRouteError::Corrupt("Error deserializing backup".into()))?
+		.into();
+
+	let stored_keys = backup.keys;
+
+	if dry_run {
+		let diff = library.key_manager.diff_keystore_backup(
+			Protected::new(password),
+			Protected::new(secret_key),
+			&stored_keys,
+		)?;
+
+		return Ok(RestoreBackupResult::DryRun(diff));
+	}
+
+	let updated_keys = library.key_manager.import_keystore_backup(
+		Protected::new(password),
+		Protected::new(secret_key),
+		&stored_keys,
+	)?;
+
+	for key in &updated_keys {
+		write_storedkey_to_db(library.db.clone(), key).await?;
+	}
+
+	relink_key_associations(library, backup.associations).await?;
+
+	record_key_audit_event(library, KeyAuditAction::Restore, None).await;
+
+	invalidate_query!(library, "keys.list");
+	invalidate_query!(library, "keys.listMounted");
+
+	Ok(RestoreBackupResult::Restored(updated_keys.len()))
+}
+
+/// This dispatches a job that decrypts the metadata and preview media of every file associated with the given key.
+///
+/// The ID of the job is returned, so the client is able to track it.
+async fn dispatch_header_decryption(library: &LibraryContext, key_uuid: Uuid) -> Option<Uuid> {
+	let mut job = Job::new(
+		FileHeaderDecryptorJobInit {
+			key_uuid,
+			metadata: true,
+			preview_media: true,
+		},
+		FileHeaderDecryptorJob {},
+	);
+
+	let job_id = job.report().as_ref().map(|report| report.id);
+
+	library.spawn_job(job).await;
+
+	job_id
+}
+
+/// This dispatches a job that re-wraps every file associated with the given key, once it has been rotated (or revoked).
+///
+/// The ID of the job is returned, so the client is able to track it.
+async fn dispatch_rekeying(
+	library: &LibraryContext,
+	key_uuid: Uuid,
+	replacement_key_uuid: Option<Uuid>,
+) -> Option<Uuid> {
+	let mut job = Job::new(
+		FileRekeyerJobInit {
+			key_uuid,
+			replacement_key_uuid,
+		},
+		FileRekeyerJob {},
+	);
+
+	let job_id = job.report().as_ref().map(|report| report.id);
+
+	library.spawn_job(job).await;
+
+	job_id
+}
@@ -1,15 +1,30 @@
 use crate::{
+	invalidate_query,
+	job::Job,
 	location::{
 		delete_location, fetch_location,
 		indexer::{indexer_job::indexer_job_location, rules::IndexerRuleCreateArgs},
-		relink_location, scan_location, LocationCreateArgs, LocationError, LocationUpdateArgs,
+		relink_location,
+		remote::{RemoteLocationCreateArgs, RemoteTransferJob, RemoteTransferJobInit},
+		scan_location,
+		statistics::location_statistics,
+		LocationCreateArgs, LocationError, LocationUpdateArgs,
+	},
+	object::{
+		fs::policy::{EncryptionRuleCreateArgs, EncryptionRuleError, EncryptionRuleUpdateArgs},
+		preview::THUMBNAIL_CACHE_DIR_NAME,
+		search::SavedSearch,
+	},
+	prisma::{
+		album, encryption_rule, encryption_rule_activity, file_path, indexer_rule,
+		indexer_rules_in_location, location, location_snapshot, object, tag,
 	},
-	object::preview::THUMBNAIL_CACHE_DIR_NAME,
-	prisma::{file_path, indexer_rule, indexer_rules_in_location, location, object, tag},
 };
 
 use std::path::PathBuf;
 
+use chrono::{DateTime, Utc};
+use prisma_client_rust::Direction;
 use rspc::{self, internal::MiddlewareBuilderLike, ErrorCode, Type};
 use serde::{Deserialize, Serialize};
 use tokio::{fs, io};
@@ -21,6 +36,8 @@ use super::{utils::LibraryRequest, Ctx, RouterBuilder};
 pub enum ExplorerContext {
 	Location(location::Data),
 	Tag(tag::Data),
+	SavedSearch(SavedSearch),
+	Album(album::Data),
 	// Space(object_in_space::Data),
 }
 
@@ -163,6 +180,22 @@ pub(crate) fn mount() -> rspc::RouterBuilder<
 				Ok(())
 			})
 		})
+		// the key manager needs to be unlocked, as the remote's credentials are stored encrypted
+		.library_mutation("createRemote", |t| {
+			t(|_, args: RemoteLocationCreateArgs, library| async move {
+				let location = args.create(&library).await?;
+				scan_location(&library, location).await?;
+				Ok(())
+			})
+		})
+		.library_mutation("transferRemote", |t| {
+			t(|_, args: RemoteTransferJobInit, library| async move {
+				library
+					.spawn_job(Job::new(args, RemoteTransferJob {}))
+					.await;
+				Ok(())
+			})
+		})
 		.library_mutation("update", |t| {
 			t(|_, args: LocationUpdateArgs, library| async move {
 				args.update(&library).await.map_err(Into::into)
@@ -191,7 +224,7 @@ pub(crate) fn mount() -> rspc::RouterBuilder<
 		})
 		.library_mutation("fullRescan", |t| {
 			t(|_, location_id: i32, library| async move {
-				// remove existing paths
+				// remove existing paths, so everything is indexed and identified again
 				library
 					.db
 					.file_path()
@@ -211,13 +244,63 @@ pub(crate) fn mount() -> rspc::RouterBuilder<
 				.map_err(Into::into)
 			})
 		})
+		// only what's changed since the last scan is indexed again
 		.library_mutation("quickRescan", |t| {
-			t(|_, _: (), _| async move {
-				#[allow(unreachable_code)]
-				Ok(todo!())
+			t(|_, location_id: i32, library| async move {
+				scan_location(
+					&library,
+					fetch_location(&library, location_id)
+						.include(indexer_job_location::include())
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(location_id))?,
+				)
+				.await
+				.map_err(Into::into)
+			})
+		})
+		.library_query("getStatistics", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct LocationStatisticsArgs {
+				pub location_id: i32,
+				// how many of the largest and most recently added files are returned
+				pub limit: Option<i32>,
+			}
+
+			t(|_, args: LocationStatisticsArgs, library| async move {
+				Ok(location_statistics(
+					&library,
+					args.location_id,
+					args.limit.unwrap_or(10).clamp(1, 100).into(),
+				)
+				.await?)
+			})
+		})
+		// this is charted from the statistics recorded every day, so it's empty until the location has been around for a while
+		.library_query("getGrowth", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct LocationGrowthArgs {
+				pub location_id: i32,
+				pub since: Option<DateTime<Utc>>,
+			}
+
+			t(|_, args: LocationGrowthArgs, library| async move {
+				let mut filters = vec![location_snapshot::location_id::equals(args.location_id)];
+				if let Some(since) = args.since {
+					filters.push(location_snapshot::date_created::gte(since.into()));
+				}
+
+				Ok(library
+					.db
+					.location_snapshot()
+					.find_many(filters)
+					.order_by(location_snapshot::date_created::order(Direction::Asc))
+					.exec()
+					.await?)
 			})
 		})
 		.merge("indexer_rules.", mount_indexer_rule_routes())
+		.merge("encryption_rules.", mount_encryption_rule_routes())
 }
 
 fn mount_indexer_rule_routes() -> RouterBuilder {
@@ -276,3 +359,95 @@ fn mount_indexer_rule_routes() -> RouterBuilder {
 			})
 		})
 }
+
+fn mount_encryption_rule_routes() -> RouterBuilder {
+	<RouterBuilder>::new()
+		.library_mutation("create", |t| {
+			t(|_, args: EncryptionRuleCreateArgs, library| async move {
+				let rule = args.create(&library).await?;
+
+				invalidate_query!(library, "locations.encryption_rules.list");
+
+				Ok(rule)
+			})
+		})
+		.library_mutation("update", |t| {
+			t(|_, args: EncryptionRuleUpdateArgs, library| async move {
+				let rule = args.update(&library).await?;
+
+				invalidate_query!(library, "locations.encryption_rules.list");
+
+				Ok(rule)
+			})
+		})
+		.library_mutation("delete", |t| {
+			t(|_, encryption_rule_id: i32, library| async move {
+				library
+					.db
+					.encryption_rule()
+					.delete(encryption_rule::id::equals(encryption_rule_id))
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "locations.encryption_rules.list");
+
+				Ok(())
+			})
+		})
+		.library_query("get", |t| {
+			t(|_, encryption_rule_id: i32, library| async move {
+				library
+					.db
+					.encryption_rule()
+					.find_unique(encryption_rule::id::equals(encryption_rule_id))
+					.exec()
+					.await?
+					.ok_or_else(|| {
+						rspc::Error::from(EncryptionRuleError::RuleNotFound(encryption_rule_id))
+					})
+			})
+		})
+		.library_query("list", |t| {
+			t(|_, _: (), library| async move {
+				library
+					.db
+					.encryption_rule()
+					.find_many(vec![])
+					.exec()
+					.await
+					.map_err(Into::into)
+			})
+		})
+		.library_query("activity", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct EncryptionRuleActivityArgs {
+				pub rule_id: Option<i32>, // if this isn't set, every rule's activity is returned
+				pub skip: Option<i64>,
+				pub take: Option<i64>, // defaults to `ACTIVITY_PAGE_SIZE`
+			}
+
+			t(|_, args: EncryptionRuleActivityArgs, library| async move {
+				library
+					.db
+					.encryption_rule_activity()
+					.find_many(
+						args.rule_id
+							.map(|rule_id| {
+								vec![encryption_rule_activity::rule_id::equals(Some(rule_id))]
+							})
+							.unwrap_or_default(),
+					)
+					.order_by(encryption_rule_activity::date_created::order(
+						Direction::Desc,
+					))
+					.skip(args.skip.unwrap_or(0))
+					.take(args.take.unwrap_or(ACTIVITY_PAGE_SIZE))
+					.exec()
+					.await
+					.map_err(Into::into)
+			})
+		})
+}
+
+/// The number of entries that are returned from the encryption rule activity log at once.
+const ACTIVITY_PAGE_SIZE: i64 = 100;
@@ -3,14 +3,16 @@ use std::{
 	time::{Duration, Instant},
 };
 
-use rspc::{Config, Type};
+use rspc::{Config, ErrorCode, Type};
 use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
+use uuid::Uuid;
 
 use crate::{
-	job::JobManager,
+	job::{JobManager, JobReport},
 	library::LibraryManager,
-	node::{NodeConfig, NodeConfigManager},
+	node::{KeyCapability, NodeConfig, NodeConfigManager},
+	p2p::P2PManager,
 };
 
 use utils::{InvalidRequests, InvalidateOperationEvent};
@@ -21,9 +23,48 @@ pub(crate) type RouterBuilder = rspc::RouterBuilder<Ctx>;
 /// Represents an internal core event, these are exposed to client via a rspc subscription.
 #[derive(Debug, Clone, Serialize, Type)]
 pub enum CoreEvent {
-	NewThumbnail { cas_id: String },
+	NewThumbnail {
+		cas_id: String,
+	},
 	InvalidateOperation(InvalidateOperationEvent),
 	InvalidateOperationDebounced(InvalidateOperationEvent),
+	/// this is emitted whenever the root key is set or cleared, so every client can lock/unlock its UI at the same time
+	KeyManagerLockStateChanged {
+		library_id: Uuid,
+		locked: bool,
+	},
+	KeyRenamed {
+		library_id: Uuid,
+		uuid: Uuid,
+		name: Option<String>,
+	},
+	MasterPasswordChangeProgress {
+		library_id: Uuid,
+		completed: usize,
+		total: usize,
+	},
+	/// this is emitted once before the library auto-locks due to inactivity, so clients can warn the user
+	KeyManagerLockingSoon {
+		library_id: Uuid,
+		seconds_remaining: u64,
+	},
+	/// this is emitted when a job is waiting for keys to be mounted, so clients can prompt for them (the job runs once they are)
+	KeysRequired {
+		library_id: Uuid,
+		job_id: Uuid,
+		job_name: String,
+		key_uuids: Vec<Uuid>,
+	},
+	/// this is emitted when a peer asks to pair with this node, so clients can ask for the preshared key that's shown on the peer's device
+	PeerPairingRequest {
+		peer_id: String,
+		name: String,
+	},
+	/// this is emitted whenever a job starts, makes progress or stops, and is filtered by `jobs.events` for each client
+	JobUpdated {
+		library_id: Uuid,
+		report: JobReport,
+	},
 }
 
 /// Is provided when executing the router from the request.
@@ -31,15 +72,67 @@ pub struct Ctx {
 	pub library_manager: Arc<LibraryManager>,
 	pub config: Arc<NodeConfigManager>,
 	pub jobs: Arc<JobManager>,
+	pub p2p: Arc<P2PManager>,
 	pub event_bus: broadcast::Sender<CoreEvent>,
+	/// remote is true if the request didn't come from the local device (e.g. the HTTP server).
+	pub remote: bool,
+	/// token is the API token which was provided alongside the request, if any.
+	pub token: Option<String>,
 }
 
+impl Ctx {
+	/// authorize checks that the request is allowed to use the given key manager capability.
+	///
+	/// Remote requests always require a valid API token, while local requests are only restricted if they provide one.
+	pub(crate) async fn authorize(&self, capability: KeyCapability) -> Result<(), rspc::Error> {
+		let secret = match &self.token {
+			Some(secret) => secret,
+			None if !self.remote => return Ok(()),
+			None => {
+				return Err(rspc::Error::new(
+					ErrorCode::Unauthorized,
+					"An API token is required to access the key manager remotely".to_string(),
+				))
+			}
+		};
+
+		let config = self.config.get().await;
+		let token = config
+			.api_tokens
+			.iter()
+			.find(|token| token.matches(secret))
+			.ok_or_else(|| {
+				rspc::Error::new(
+					ErrorCode::Unauthorized,
+					"The provided API token is invalid".to_string(),
+				)
+			})?;
+
+		if !token.allows(capability) {
+			return Err(rspc::Error::new(
+				ErrorCode::Forbidden,
+				format!(
+					"The API token '{}' doesn't have the {:?} capability",
+					token.name, capability
+				),
+			));
+		}
+
+		Ok(())
+	}
+}
+
+mod albums;
 mod files;
 mod jobs;
-mod keys;
+pub(crate) mod keys;
 mod libraries;
 mod locations;
 mod normi;
+mod p2p;
+mod saved_searches;
+mod schedules;
+mod search;
 mod tags;
 pub mod utils;
 pub mod volumes;
@@ -91,10 +184,15 @@ pub(crate) fn mount() -> Arc<Router> {
 		.merge("library.", libraries::mount())
 		.merge("volumes.", volumes::mount())
 		.merge("tags.", tags::mount())
+		.merge("albums.", albums::mount())
 		.merge("keys.", keys::mount())
 		.merge("locations.", locations::mount())
 		.merge("files.", files::mount())
+		.merge("search.", search::mount())
+		.merge("savedSearches.", saved_searches::mount())
 		.merge("jobs.", jobs::mount())
+		.merge("schedules.", schedules::mount())
+		.merge("p2p.", p2p::mount())
 		// TODO: Scope the invalidate queries to a specific library (filtered server side)
 		.subscription("invalidateQuery", |t| {
 			t(|ctx, _: ()| {
@@ -1,78 +1,50 @@
-// use std::collections::HashMap;
-
-// use p2p::PeerId;
-// use rspc::Type;
-// use serde::Deserialize;
-
-// use super::{LibraryArgs, RouterBuilder};
-
-// #[derive(Type, Deserialize)]
-// pub struct AcceptPairingRequestArgs {
-// 	pub peer_id: PeerId,
-// 	pub preshared_key: String,
-// }
-
-// pub(crate) fn mount() -> RouterBuilder {
-// 	RouterBuilder::new()
-// 		.query("getNodes", |ctx, arg: LibraryArgs<()>| async move {
-// 			let (_, library) = arg.get_library(&ctx).await?;
-
-// 			Ok(
-// 				library.db.node().find_many(vec![]).exec().await?, // TODO: Make this work
-// 				                                                   // .into_iter()
-// 				                                                   // .filter_map(|v| {
-// 				                                                   // 	if v.id == ctx.node_local_id {
-// 				                                                   // 		None
-// 				                                                   // 	} else {
-// 				                                                   // 		Some(v.into())
-// 				                                                   // 	}
-// 				                                                   // })
-// 				                                                   // .collect::<Vec<LibraryNode>>()
-// 			)
-// 		})
-// 		.query("connectedPeers", |ctx, _: ()| async move {
-// 			ctx.p2p
-// 				.nm
-// 				.connected_peers()
-// 				.into_iter()
-// 				.map(|(_, v)| (v.id, v.metadata))
-// 				.collect::<HashMap<_, _>>()
-// 		})
-// 		.query("discoveredPeers", |ctx, _: ()| async move {
-// 			ctx.p2p
-// 				.nm
-// 				.discovered_peers()
-// 				.into_iter()
-// 				// TODO: Make this better
-// 				.map(|(_, v)| v)
-// 				.collect::<Vec<_>>()
-// 		})
-// 		.mutation("pairNode", |ctx, arg: LibraryArgs<PeerId>| async move {
-// 			let (peer_id, library) = arg.get_library(&ctx).await?;
-
-// 			let preshared_key = ctx.p2p.pair(&library, peer_id).await.unwrap();
-
-// 			// TODO: These aren't library queries so they can't be invalidated with the current system. We can fix this with the normalised cache!
-// 			// invalidate_query!(ctx, "p2p.discoveredPeers": (), ());
-// 			// invalidate_query!(ctx, "p2p.connectedPeers": (), ());
-
-// 			Ok(preshared_key)
-// 		})
-// 		.mutation(
-// 			"unpairNode",
-// 			|_, _: LibraryArgs<PeerId>| async move { todo!() },
-// 		)
-// 		.mutation(
-// 			"acceptPairingRequest",
-// 			|ctx, arg: AcceptPairingRequestArgs| async move {
-// 				ctx.p2p
-// 					.pairing_requests
-// 					.lock()
-// 					.unwrap()
-// 					.remove(&arg.peer_id)
-// 					.unwrap()
-// 					.send(Ok(arg.preshared_key))
-// 					.unwrap(); // TODO: Remove unwrap
-// 			},
-// 		)
-// }
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+
+use super::{CoreEvent, RouterBuilder};
+
+#[derive(Type, Deserialize)]
+pub struct AcceptPairingRequestArgs {
+	pub peer_id: String,
+	pub preshared_key: String,
+}
+
+#[derive(Type, Serialize)]
+pub struct PairingRequest {
+	pub peer_id: String,
+	pub name: String,
+}
+
+pub(crate) fn mount() -> RouterBuilder {
+	<RouterBuilder>::new()
+		.query("discoveredPeers", |t| {
+			t(|ctx, _: ()| ctx.p2p.discovered_peers())
+		})
+		.query("connectedPeers", |t| {
+			t(|ctx, _: ()| ctx.p2p.connected_peers())
+		})
+		// this returns the preshared key, which has to be entered on the peer's device to accept the pairing request
+		.mutation("pair", |t| {
+			t(|ctx, peer_id: String| async move { Ok(ctx.p2p.pair(&peer_id).await?) })
+		})
+		.mutation("acceptPairingRequest", |t| {
+			t(|ctx, args: AcceptPairingRequestArgs| async move {
+				Ok(ctx
+					.p2p
+					.accept_pairing_request(&args.peer_id, args.preshared_key)?)
+			})
+		})
+		.subscription("pairingRequests", |t| {
+			t(|ctx, _: ()| {
+				let mut event_bus_rx = ctx.event_bus.subscribe();
+				async_stream::stream! {
+					while let Ok(event) = event_bus_rx.recv().await {
+						match event {
+							CoreEvent::PeerPairingRequest { peer_id, name } => yield PairingRequest { peer_id, name },
+							_ => {}
+						}
+					}
+				}
+			})
+		})
+}
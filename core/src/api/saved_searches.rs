@@ -0,0 +1,159 @@
+use rspc::Type;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+	api::locations::{object_with_file_paths, ExplorerContext, ExplorerData, ExplorerItem},
+	invalidate_query,
+	object::search::{ObjectSearch, SavedSearch, SavedSearchError},
+	prisma::{object, saved_search},
+};
+
+use super::{utils::LibraryRequest, RouterBuilder};
+
+pub(crate) fn mount() -> RouterBuilder {
+	<RouterBuilder>::new()
+		.library_query("list", |t| {
+			t(|_, _: (), library| async move {
+				library
+					.db
+					.saved_search()
+					.find_many(vec![])
+					.exec()
+					.await?
+					.into_iter()
+					.map(|data| SavedSearch::try_from(data).map_err(Into::into))
+					.collect::<Result<Vec<_>, rspc::Error>>()
+			})
+		})
+		.library_query("get", |t| {
+			t(|_, saved_search_id: i32, library| async move {
+				let data = library
+					.db
+					.saved_search()
+					.find_unique(saved_search::id::equals(saved_search_id))
+					.exec()
+					.await?
+					.ok_or(SavedSearchError::NotFound(saved_search_id))?;
+
+				Ok(SavedSearch::try_from(data)?)
+			})
+		})
+		// this lists the objects that currently match a saved search, as if it were a folder
+		.library_query("getExplorerData", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct SavedSearchExplorerArgs {
+				pub id: i32,
+				pub take: Option<i32>,
+				pub skip: Option<i32>,
+			}
+
+			t(|_, args: SavedSearchExplorerArgs, library| async move {
+				let saved_search = SavedSearch::try_from(
+					library
+						.db
+						.saved_search()
+						.find_unique(saved_search::id::equals(args.id))
+						.exec()
+						.await?
+						.ok_or(SavedSearchError::NotFound(args.id))?,
+				)?;
+
+				let ids = ObjectSearch {
+					take: args.take,
+					skip: args.skip,
+					..saved_search.search.clone()
+				}
+				.object_ids(&library.db)
+				.await?;
+
+				let mut objects = library
+					.db
+					.object()
+					.find_many(vec![object::id::in_vec(ids.clone())])
+					.include(object_with_file_paths::include())
+					.exec()
+					.await?;
+				objects.sort_by_key(|object| ids.iter().position(|id| *id == object.id));
+
+				Ok(ExplorerData {
+					context: ExplorerContext::SavedSearch(saved_search),
+					items: objects
+						.into_iter()
+						.map(|object| ExplorerItem::Object(Box::new(object)))
+						.collect(),
+				})
+			})
+		})
+		.library_mutation("create", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct SavedSearchCreateArgs {
+				pub name: String,
+				pub search: ObjectSearch,
+			}
+
+			t(|_, args: SavedSearchCreateArgs, library| async move {
+				let data = library
+					.db
+					.saved_search()
+					.create(
+						Uuid::new_v4().as_bytes().to_vec(),
+						args.name,
+						serde_json::to_string(&args.search).map_err(SavedSearchError::from)?,
+						vec![],
+					)
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "savedSearches.list");
+
+				Ok(SavedSearch::try_from(data)?)
+			})
+		})
+		.library_mutation("update", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct SavedSearchUpdateArgs {
+				pub id: i32,
+				pub name: Option<String>,
+				pub search: Option<ObjectSearch>,
+			}
+
+			t(|_, args: SavedSearchUpdateArgs, library| async move {
+				let mut params = vec![saved_search::date_modified::set(chrono::Utc::now().into())];
+				if let Some(name) = args.name {
+					params.push(saved_search::name::set(name));
+				}
+				if let Some(search) = args.search {
+					params.push(saved_search::search::set(
+						serde_json::to_string(&search).map_err(SavedSearchError::from)?,
+					));
+				}
+
+				let data = library
+					.db
+					.saved_search()
+					.update(saved_search::id::equals(args.id), params)
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "savedSearches.list");
+				invalidate_query!(library, "savedSearches.getExplorerData");
+
+				Ok(SavedSearch::try_from(data)?)
+			})
+		})
+		.library_mutation("delete", |t| {
+			t(|_, saved_search_id: i32, library| async move {
+				library
+					.db
+					.saved_search()
+					.delete(saved_search::id::equals(saved_search_id))
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "savedSearches.list");
+
+				Ok(())
+			})
+		})
+}
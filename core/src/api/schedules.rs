@@ -0,0 +1,83 @@
+use crate::{
+	invalidate_query,
+	job::{
+		run_schedule, Schedule, ScheduleCreateArgs, ScheduleError, ScheduleUpdateArgs,
+		ScheduledTask,
+	},
+	node::KeyCapability,
+	prisma::schedule,
+};
+
+use super::{utils::LibraryRequest, RouterBuilder};
+
+pub(crate) fn mount() -> RouterBuilder {
+	<RouterBuilder>::new()
+		.library_query("list", |t| {
+			t(|_, _: (), library| async move {
+				library
+					.db
+					.schedule()
+					.find_many(vec![])
+					.exec()
+					.await?
+					.into_iter()
+					.map(|data| Schedule::try_from(data).map_err(Into::into))
+					.collect::<Result<Vec<_>, rspc::Error>>()
+			})
+		})
+		.library_mutation("create", |t| {
+			t(|ctx, args: ScheduleCreateArgs, library| async move {
+				// a keystore backup is written to the node's disk, so it's treated the same as exporting the keystore
+				if matches!(args.task, ScheduledTask::KeystoreBackup { .. }) {
+					ctx.authorize(KeyCapability::Export).await?;
+				}
+
+				let schedule = args.create(&library).await?;
+
+				invalidate_query!(library, "schedules.list");
+
+				Ok(schedule)
+			})
+		})
+		.library_mutation("update", |t| {
+			t(|ctx, args: ScheduleUpdateArgs, library| async move {
+				if matches!(args.task, Some(ScheduledTask::KeystoreBackup { .. })) {
+					ctx.authorize(KeyCapability::Export).await?;
+				}
+
+				let schedule = args.update(&library).await?;
+
+				invalidate_query!(library, "schedules.list");
+
+				Ok(schedule)
+			})
+		})
+		.library_mutation("delete", |t| {
+			t(|_, schedule_id: i32, library| async move {
+				library
+					.db
+					.schedule()
+					.delete(schedule::id::equals(schedule_id))
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "schedules.list");
+
+				Ok(())
+			})
+		})
+		// this runs a schedule straight away, whether or not it's enabled, and pushes its next run back
+		.library_mutation("runNow", |t| {
+			t(|ctx, schedule_id: i32, library| async move {
+				let data = library
+					.db
+					.schedule()
+					.find_unique(schedule::id::equals(schedule_id))
+					.exec()
+					.await?
+					.ok_or(ScheduleError::ScheduleNotFound(schedule_id))?;
+
+				Ok(run_schedule(&ctx.library_manager, &library, data).await?)
+			})
+		})
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+
+use crate::{api::locations::object_with_file_paths, object::search::ObjectSearch, prisma::object};
+
+use super::{utils::LibraryRequest, RouterBuilder};
+
+#[derive(Serialize, Type, Debug)]
+pub struct FullTextSearchResult {
+	pub object: object_with_file_paths::Data,
+	pub score: f32,
+	/// the best matching fragment of the object's text, as HTML with the matched terms in `<b>` tags
+	pub snippet: String,
+}
+
+pub(crate) fn mount() -> RouterBuilder {
+	<RouterBuilder>::new()
+		.library_query("objects", |t| {
+			t(|_, args: ObjectSearch, library| async move {
+				let ids = args.object_ids(&library.db).await?;
+
+				let mut objects = library
+					.db
+					.object()
+					.find_many(vec![object::id::in_vec(ids.clone())])
+					.include(object_with_file_paths::include())
+					.exec()
+					.await?
+					.into_iter()
+					.map(|object| (object.id, object))
+					.collect::<HashMap<_, _>>();
+
+				// the objects are returned in the order that the search put them in
+				Ok(ids
+					.into_iter()
+					.filter_map(|id| objects.remove(&id))
+					.collect::<Vec<_>>())
+			})
+		})
+		.library_query("fullText", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct FullTextArgs {
+				/// e.g. `invoice 2022`, `"exact phrase"` or `report -draft`
+				pub query: String,
+				pub take: Option<i32>,
+				pub skip: Option<i32>,
+			}
+
+			t(|_, args: FullTextArgs, library| async move {
+				// results are ordered by their relevance, so the best matches come first
+				let hits = library.search_index.search(
+					&args.query,
+					args.take.unwrap_or(50).clamp(1, 500) as usize,
+					args.skip.unwrap_or_default().max(0) as usize,
+				)?;
+
+				// the index isn't updated when objects are deleted, so hits for those are dropped here
+				let mut objects = library
+					.db
+					.object()
+					.find_many(vec![object::id::in_vec(
+						hits.iter().map(|hit| hit.object_id).collect(),
+					)])
+					.include(object_with_file_paths::include())
+					.exec()
+					.await?
+					.into_iter()
+					.map(|object| (object.id, object))
+					.collect::<HashMap<_, _>>();
+
+				Ok(hits
+					.into_iter()
+					.filter_map(|hit| {
+						Some(FullTextSearchResult {
+							object: objects.remove(&hit.object_id)?,
+							score: hit.score,
+							snippet: hit.snippet,
+						})
+					})
+					.collect::<Vec<_>>())
+			})
+		})
+}
@@ -6,8 +6,15 @@ use uuid::Uuid;
 use crate::{
 	api::locations::{object_with_file_paths, ExplorerContext, ExplorerData, ExplorerItem},
 	invalidate_query,
-	object::preview::THUMBNAIL_CACHE_DIR_NAME,
-	prisma::{object, tag, tag_on_object},
+	job::Job,
+	object::{
+		preview::THUMBNAIL_CACHE_DIR_NAME,
+		tag::{
+			set_tag_parent, BulkTagAction, BulkTagJob, BulkTagJobInit, BulkTagTargets, TagError,
+			TagRuleCreateArgs, TagRuleError, TagRuleJob, TagRuleJobInit, TagRuleUpdateArgs,
+		},
+	},
+	prisma::{object, tag, tag_change, tag_on_object, tag_rule},
 };
 
 use super::{utils::LibraryRequest, RouterBuilder};
@@ -102,9 +109,21 @@ pub(crate) fn mount() -> RouterBuilder {
 			pub struct TagCreateArgs {
 				pub name: String,
 				pub color: String,
+				/// the tag that the new tag is nested under
+				pub parent_id: Option<i32>,
 			}
 
 			t(|_, args: TagCreateArgs, library| async move {
+				if let Some(parent_id) = args.parent_id {
+					library
+						.db
+						.tag()
+						.find_unique(tag::id::equals(parent_id))
+						.exec()
+						.await?
+						.ok_or(TagError::NotFound(parent_id))?;
+				}
+
 				let created_tag = library
 					.db
 					.tag()
@@ -113,6 +132,7 @@ pub(crate) fn mount() -> RouterBuilder {
 						vec![
 							tag::name::set(Some(args.name)),
 							tag::color::set(Some(args.color)),
+							tag::parent_id::set(args.parent_id),
 						],
 					)
 					.exec()
@@ -153,10 +173,85 @@ pub(crate) fn mount() -> RouterBuilder {
 				}
 
 				invalidate_query!(library, "tags.getForObject");
+				invalidate_query!(library, "savedSearches.getExplorerData");
 
 				Ok(())
 			})
 		})
+		// this changes the tags of many objects at once in a job, and returns the token that undoes the change
+		.library_mutation("assignBulk", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct TagAssignBulkArgs {
+				pub tag_ids: Vec<i32>,
+				pub targets: BulkTagTargets,
+				pub unassign: bool,
+			}
+
+			t(|_, args: TagAssignBulkArgs, library| async move {
+				for tag_id in &args.tag_ids {
+					library
+						.db
+						.tag()
+						.find_unique(tag::id::equals(*tag_id))
+						.exec()
+						.await?
+						.ok_or(TagError::NotFound(*tag_id))?;
+				}
+
+				let undo_token = Uuid::new_v4();
+				let action = if args.unassign {
+					BulkTagAction::Unassign {
+						tag_ids: args.tag_ids,
+						targets: args.targets,
+					}
+				} else {
+					BulkTagAction::Assign {
+						tag_ids: args.tag_ids,
+						targets: args.targets,
+					}
+				};
+
+				library
+					.spawn_job(Job::new(
+						BulkTagJobInit { action, undo_token },
+						BulkTagJob {},
+					))
+					.await;
+
+				Ok(undo_token)
+			})
+		})
+		// this reverts exactly what a bulk change did, and returns the token that undoes the undo
+		.library_mutation("undoBulk", |t| {
+			t(|_, undo_token: Uuid, library| async move {
+				let changes = library
+					.db
+					.tag_change()
+					.count(vec![tag_change::change_id::equals(
+						undo_token.as_bytes().to_vec(),
+					)])
+					.exec()
+					.await?;
+
+				if changes == 0 {
+					return Err(TagError::NothingToUndo(undo_token).into());
+				}
+
+				let redo_token = Uuid::new_v4();
+
+				library
+					.spawn_job(Job::new(
+						BulkTagJobInit {
+							action: BulkTagAction::Undo(undo_token),
+							undo_token: redo_token,
+						},
+						BulkTagJob {},
+					))
+					.await;
+
+				Ok(redo_token)
+			})
+		})
 		.library_mutation("update", |t| {
 			#[derive(Type, Deserialize)]
 			pub struct TagUpdateArgs {
@@ -181,8 +276,44 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(())
 			})
 		})
+		// this nests a tag under another, or moves it back to the top level if there's no parent
+		.library_mutation("setParent", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct TagSetParentArgs {
+				pub id: i32,
+				pub parent_id: Option<i32>,
+			}
+
+			t(|_, args: TagSetParentArgs, library| async move {
+				let tag = set_tag_parent(&library.db, args.id, args.parent_id).await?;
+
+				invalidate_query!(library, "tags.list");
+				invalidate_query!(library, "savedSearches.getExplorerData");
+
+				Ok(tag)
+			})
+		})
 		.library_mutation("delete", |t| {
 			t(|_, tag_id: i32, library| async move {
+				let tag = library
+					.db
+					.tag()
+					.find_unique(tag::id::equals(tag_id))
+					.exec()
+					.await?
+					.ok_or(TagError::NotFound(tag_id))?;
+
+				// the tag's children take its place, rather than all ending up at the top level
+				library
+					.db
+					.tag()
+					.update_many(
+						vec![tag::parent_id::equals(Some(tag_id))],
+						vec![tag::parent_id::set(tag.parent_id)],
+					)
+					.exec()
+					.await?;
+
 				library
 					.db
 					.tag()
@@ -195,4 +326,89 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(())
 			})
 		})
+		.merge("rules.", mount_tag_rule_routes())
+}
+
+fn mount_tag_rule_routes() -> RouterBuilder {
+	<RouterBuilder>::new()
+		.library_mutation("create", |t| {
+			t(|_, args: TagRuleCreateArgs, library| async move {
+				let rule = args.create(&library).await?;
+
+				invalidate_query!(library, "tags.rules.list");
+
+				Ok(rule)
+			})
+		})
+		.library_mutation("update", |t| {
+			t(|_, args: TagRuleUpdateArgs, library| async move {
+				let rule = args.update(&library).await?;
+
+				invalidate_query!(library, "tags.rules.list");
+
+				Ok(rule)
+			})
+		})
+		.library_mutation("delete", |t| {
+			t(|_, tag_rule_id: i32, library| async move {
+				library
+					.db
+					.tag_rule()
+					.delete(tag_rule::id::equals(tag_rule_id))
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "tags.rules.list");
+
+				Ok(())
+			})
+		})
+		// rules are only applied to objects as their location is indexed, so this applies them to every object that's already been indexed
+		.library_mutation("apply", |t| {
+			t(|_, tag_rule_id: Option<i32>, library| async move {
+				if let Some(tag_rule_id) = tag_rule_id {
+					library
+						.db
+						.tag_rule()
+						.find_unique(tag_rule::id::equals(tag_rule_id))
+						.exec()
+						.await?
+						.ok_or(TagRuleError::RuleNotFound(tag_rule_id))?;
+				}
+
+				library
+					.spawn_job(Job::new(
+						TagRuleJobInit {
+							location_id: None,
+							rule_id: tag_rule_id,
+						},
+						TagRuleJob {},
+					))
+					.await;
+
+				Ok(())
+			})
+		})
+		.library_query("get", |t| {
+			t(|_, tag_rule_id: i32, library| async move {
+				library
+					.db
+					.tag_rule()
+					.find_unique(tag_rule::id::equals(tag_rule_id))
+					.exec()
+					.await?
+					.ok_or_else(|| rspc::Error::from(TagRuleError::RuleNotFound(tag_rule_id)))
+			})
+		})
+		.library_query("list", |t| {
+			t(|_, _: (), library| async move {
+				library
+					.db
+					.tag_rule()
+					.find_many(vec![])
+					.exec()
+					.await
+					.map_err(Into::into)
+			})
+		})
 }
@@ -18,6 +18,9 @@ use crate::{api::Ctx, library::LibraryContext};
 pub(crate) struct LibraryArgs<T> {
 	pub library_id: Uuid,
 	pub arg: T,
+	/// token is an optional API token, which is required when accessing the key manager remotely.
+	#[serde(default)]
+	pub token: Option<String>,
 }
 
 // WARNING: This is system is using internal API's which means it will break between rspc release. I would avoid copying it unless you understand the cost of maintaining it!
@@ -102,6 +105,11 @@ where
 							)
 						})?;
 
+					let ctx = Ctx {
+						token: arg.token,
+						..ctx
+					};
+
 					Ok(resolver(ctx, arg.arg, library)
 						.into_request_future()?
 						.exec()
@@ -148,6 +156,11 @@ where
 							)
 						})?;
 
+					let ctx = Ctx {
+						token: arg.token,
+						..ctx
+					};
+
 					Ok(resolver(ctx, arg.arg, library)
 						.into_request_future()?
 						.exec()
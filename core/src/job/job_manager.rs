@@ -1,17 +1,40 @@
 use crate::{
+	api::CoreEvent,
 	invalidate_query,
-	job::{worker::Worker, DynJob, Job, JobError},
+	job::{worker::Worker, DynJob, Job, JobError, QueuedJob, WorkerCommand},
 	library::LibraryContext,
-	location::indexer::indexer_job::{IndexerJob, INDEXER_JOB_NAME},
+	location::{
+		indexer::indexer_job::{IndexerJob, INDEXER_JOB_NAME},
+		remote::{
+			RemoteIndexerJob, RemoteTransferJob, REMOTE_INDEXER_JOB_NAME, REMOTE_TRANSFER_JOB_NAME,
+		},
+	},
 	object::{
+		fs::{
+			archive::{FileArchiverJob, FILE_ARCHIVER_JOB_NAME},
+			audit::{FileHeaderAuditorJob, HEADER_AUDITOR_JOB_NAME},
+			copy::{FileCopierJob, FILE_COPIER_JOB_NAME},
+			decrypt::{FileDecryptorJob, FILE_DECRYPTOR_JOB_NAME},
+			decrypt_header::{FileHeaderDecryptorJob, HEADER_DECRYPTOR_JOB_NAME},
+			delete::{FileDeleterJob, FILE_DELETER_JOB_NAME},
+			duplicates::{DuplicateResolverJob, DUPLICATE_RESOLVER_JOB_NAME},
+			encrypt::{FileEncryptorJob, FILE_ENCRYPTOR_JOB_NAME},
+			r#move::{FileMoverJob, FILE_MOVER_JOB_NAME},
+			rekey::{FileRekeyerJob, FILE_REKEYER_JOB_NAME},
+			verify::{FileVerifierJob, FILE_VERIFIER_JOB_NAME},
+		},
 		identifier_job::full_identifier_job::{FullFileIdentifierJob, FULL_IDENTIFIER_JOB_NAME},
-		preview::{ThumbnailJob, THUMBNAIL_JOB_NAME},
+		preview::{MediaDataJob, ThumbnailJob, MEDIA_DATA_JOB_NAME, THUMBNAIL_JOB_NAME},
+		tag::{BulkTagJob, TagRuleJob, BULK_TAG_JOB_NAME, TAG_RULE_JOB_NAME},
+		text::{TextIndexerJob, TEXT_INDEXER_JOB_NAME},
 		validation::validator_job::{ObjectValidatorJob, VALIDATOR_JOB_NAME},
+		version::{FileVersionerJob, FILE_VERSIONER_JOB_NAME},
 	},
 	prisma::{job, node},
 };
 
 use std::{
+	cmp::Reverse,
 	collections::{HashMap, HashSet, VecDeque},
 	fmt::Debug,
 	fmt::{Display, Formatter},
@@ -27,14 +50,97 @@ use tokio::{
 	sync::{broadcast, mpsc, Mutex, RwLock},
 	time::sleep,
 };
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-// db is single threaded, nerd
-const MAX_WORKERS: usize = 1;
-
 pub enum JobManagerEvent {
 	IngestJob(LibraryContext, Box<dyn DynJob>),
+	ContinueQueue(LibraryContext),
+}
+
+/// JobPriority decides which queued job is run next, once there's room for it. Jobs of the same priority are run in the order that they were queued.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq, PartialOrd, Ord)]
+pub enum JobPriority {
+	Low,
+	Normal,
+	High,
+}
+
+/// JobConcurrency limits how many jobs are able to run at once, both overall and for each type of job.
+///
+/// This is stored within the node's config, so it's shared between every library.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct JobConcurrency {
+	/// max_workers is the maximum amount of jobs that can be running at once.
+	pub max_workers: u32,
+	/// limits is the maximum amount of jobs of a given type (by job name) that can be running at once.
+	/// Job types without a limit can only have one job running at a time.
+	pub limits: HashMap<String, u32>,
+}
+
+impl Default for JobConcurrency {
+	fn default() -> Self {
+		Self {
+			max_workers: 4,
+			limits: HashMap::from([
+				(THUMBNAIL_JOB_NAME.to_string(), 4),
+				(MEDIA_DATA_JOB_NAME.to_string(), 1),
+				(TEXT_INDEXER_JOB_NAME.to_string(), 1),
+				(TAG_RULE_JOB_NAME.to_string(), 1),
+				(FILE_VERSIONER_JOB_NAME.to_string(), 1),
+				(REMOTE_INDEXER_JOB_NAME.to_string(), 1),
+				(REMOTE_TRANSFER_JOB_NAME.to_string(), 1),
+				(BULK_TAG_JOB_NAME.to_string(), 1),
+				(FILE_ENCRYPTOR_JOB_NAME.to_string(), 1),
+				(FILE_ARCHIVER_JOB_NAME.to_string(), 1),
+				(FILE_DECRYPTOR_JOB_NAME.to_string(), 1),
+				(FILE_REKEYER_JOB_NAME.to_string(), 1),
+				(FILE_VERIFIER_JOB_NAME.to_string(), 1),
+				(HEADER_AUDITOR_JOB_NAME.to_string(), 1),
+				(DUPLICATE_RESOLVER_JOB_NAME.to_string(), 1),
+				(FILE_COPIER_JOB_NAME.to_string(), 1),
+				(FILE_MOVER_JOB_NAME.to_string(), 1),
+				(FILE_DELETER_JOB_NAME.to_string(), 1),
+			]),
+		}
+	}
+}
+
+impl JobConcurrency {
+	pub fn limit(&self, job_name: &str) -> u32 {
+		self.limits.get(job_name).copied().unwrap_or(1)
+	}
+
+	/// can_run returns whether a job is able to start alongside the jobs that are already running.
+	fn can_run(&self, job_name: &str, running_workers: &HashMap<Uuid, RunningWorker>) -> bool {
+		let running_of_type = running_workers
+			.values()
+			.filter(|running| running.name == job_name)
+			.count();
+
+		running_workers.len() < self.max_workers as usize
+			&& running_of_type < self.limit(job_name) as usize
+	}
+}
+
+struct RunningWorker {
+	name: &'static str,
+	worker: Arc<Mutex<Worker>>,
+}
+
+/// A job that's waiting for keys to be mounted before it's able to run.
+struct ParkedJob {
+	library_id: Uuid,
+	key_uuids: Vec<Uuid>,
+	job: Box<dyn DynJob>,
+}
+
+/// A job that's waiting for keys to be mounted, as it's returned by `jobs.getWaitingForKeys`.
+#[derive(Debug, Serialize, Type)]
+pub struct WaitingJob {
+	pub report: JobReport,
+	/// key_uuids are the keys that weren't mounted when the job was about to run
+	pub key_uuids: Vec<Uuid>,
 }
 
 /// JobManager handles queueing and executing jobs using the `DynJob`
@@ -43,19 +149,23 @@ pub enum JobManagerEvent {
 pub struct JobManager {
 	current_jobs_hashes: RwLock<HashSet<u64>>,
 	job_queue: RwLock<VecDeque<Box<dyn DynJob>>>,
-	running_workers: RwLock<HashMap<Uuid, Arc<Mutex<Worker>>>>,
+	parked_jobs: RwLock<Vec<ParkedJob>>,
+	running_workers: RwLock<HashMap<Uuid, RunningWorker>>,
+	concurrency: RwLock<JobConcurrency>,
 	internal_sender: mpsc::UnboundedSender<JobManagerEvent>,
 	shutdown_tx: Arc<broadcast::Sender<()>>,
 }
 
 impl JobManager {
-	pub fn new() -> Arc<Self> {
+	pub fn new(concurrency: JobConcurrency) -> Arc<Self> {
 		let (shutdown_tx, _shutdown_rx) = broadcast::channel(1);
 		let (internal_sender, mut internal_receiver) = mpsc::unbounded_channel();
 		let this = Arc::new(Self {
 			current_jobs_hashes: RwLock::new(HashSet::new()),
 			job_queue: RwLock::new(VecDeque::new()),
+			parked_jobs: RwLock::new(Vec::new()),
 			running_workers: RwLock::new(HashMap::new()),
+			concurrency: RwLock::new(concurrency),
 			internal_sender,
 			shutdown_tx: Arc::new(shutdown_tx),
 		});
@@ -68,6 +178,7 @@ impl JobManager {
 					JobManagerEvent::IngestJob(ctx, job) => {
 						this2.clone().dispatch_job(&ctx, job).await
 					}
+					JobManagerEvent::ContinueQueue(ctx) => this2.clone().continue_queue(&ctx).await,
 				}
 			}
 		});
@@ -116,22 +227,19 @@ impl JobManager {
 		self.current_jobs_hashes.write().await.remove(&job_hash);
 		self.running_workers.write().await.remove(&job_id);
 		// continue queue
-		let job = self.job_queue.write().await.pop_front();
-		if let Some(job) = job {
-			// We can't directly execute `self.ingest` here because it would cause an async cycle.
-			self.internal_sender
-				.send(JobManagerEvent::IngestJob(ctx.clone(), job))
-				.unwrap_or_else(|_| {
-					error!("Failed to ingest job!");
-				});
-		}
+		// We can't directly execute `self.continue_queue` here because it would cause an async cycle.
+		self.internal_sender
+			.send(JobManagerEvent::ContinueQueue(ctx.clone()))
+			.unwrap_or_else(|_| {
+				error!("Failed to continue the job queue!");
+			});
 	}
 
 	pub async fn get_running(&self) -> Vec<JobReport> {
 		let mut ret = vec![];
 
-		for worker in self.running_workers.read().await.values() {
-			let worker = worker.lock().await;
+		for running in self.running_workers.read().await.values() {
+			let worker = running.worker.lock().await;
 			ret.push(worker.report());
 		}
 		ret
@@ -153,12 +261,91 @@ impl JobManager {
 			.collect())
 	}
 
+	/// history returns the jobs that have finished (or any with the given status) which match the filter, alongside statistics for every one of them.
+	///
+	/// The statistics cover every matching job, rather than just the page that's returned.
+	pub async fn history(
+		ctx: &LibraryContext,
+		args: JobHistoryArgs,
+	) -> Result<JobHistory, prisma_client_rust::QueryError> {
+		// the filter is needed for both queries, and the generated where params can't be cloned
+		let filter = || {
+			let mut params = vec![match args.status {
+				Some(status) => job::status::equals(status.int_value()),
+				None => job::status::in_vec(
+					[JobStatus::Completed, JobStatus::Canceled, JobStatus::Failed]
+						.iter()
+						.map(|status| status.int_value())
+						.collect(),
+				),
+			}];
+
+			if let Some(name) = &args.name {
+				params.push(job::name::equals(name.clone()));
+			}
+
+			if let Some(since) = args.since {
+				params.push(job::date_created::gte(since.into()));
+			}
+
+			if let Some(until) = args.until {
+				params.push(job::date_created::lte(until.into()));
+			}
+
+			params
+		};
+
+		let jobs = ctx
+			.db
+			.job()
+			.find_many(filter())
+			.order_by(job::date_created::order(Direction::Desc))
+			.skip(args.skip.unwrap_or_default())
+			.take(args.take.unwrap_or(JOB_HISTORY_PAGE_SIZE))
+			.exec()
+			.await?
+			.into_iter()
+			.map(Into::into)
+			.collect();
+
+		let mut total = JobStats::default();
+		let mut by_name = HashMap::<String, JobStats>::new();
+
+		for job in ctx
+			.db
+			.job()
+			.find_many(filter())
+			.select(job_for_stats::select())
+			.exec()
+			.await?
+		{
+			let status = JobStatus::from_int(job.status).ok();
+			let bytes_processed = job.bytes_processed.parse().unwrap_or_default();
+
+			for stats in [&mut total, by_name.entry(job.name).or_default()] {
+				stats.record(
+					status,
+					job.seconds_elapsed,
+					job.completed_task_count,
+					bytes_processed,
+				);
+			}
+		}
+
+		Ok(JobHistory {
+			jobs,
+			total,
+			by_name,
+		})
+	}
+
 	pub async fn clear_all_jobs(
 		ctx: &LibraryContext,
 	) -> Result<(), prisma_client_rust::QueryError> {
 		ctx.db.job().delete_many(vec![]).exec().await?;
 
 		invalidate_query!(ctx, "jobs.getHistory");
+		invalidate_query!(ctx, "jobs.history");
 		Ok(())
 	}
 
@@ -185,6 +372,38 @@ impl JobManager {
 	}
 
 	pub async fn resume_jobs(self: Arc<Self>, ctx: &LibraryContext) -> Result<(), JobError> {
+		// jobs that are still marked as running were interrupted (e.g. by a crash or power loss), so they're resumed from their last checkpoint
+		let interrupted_jobs = ctx
+			.db
+			.job()
+			.find_many(vec![job::status::equals(JobStatus::Running.int_value())])
+			.exec()
+			.await?;
+
+		for interrupted_job in interrupted_jobs {
+			// a job without any saved state was interrupted before it started, so it can't have left anything behind
+			let status = if interrupted_job.data.is_some() {
+				JobStatus::Paused
+			} else {
+				JobStatus::Failed
+			};
+
+			warn!(
+				"Job was interrupted: {}, id: {:?}",
+				interrupted_job.name,
+				Uuid::from_slice(&interrupted_job.id)
+			);
+
+			ctx.db
+				.job()
+				.update(
+					job::id::equals(interrupted_job.id),
+					vec![job::status::set(status.int_value())],
+				)
+				.exec()
+				.await?;
+		}
+
 		let paused_jobs = ctx
 			.db
 			.job()
@@ -193,47 +412,441 @@ impl JobManager {
 			.await?;
 
 		for paused_job_data in paused_jobs {
-			let paused_job = JobReport::from(paused_job_data);
-
-			info!("Resuming job: {}, id: {}", paused_job.name, paused_job.id);
-			match paused_job.name.as_str() {
-				THUMBNAIL_JOB_NAME => {
-					Arc::clone(&self)
-						.dispatch_job(ctx, Job::resume(paused_job, ThumbnailJob {})?)
-						.await;
-				}
-				INDEXER_JOB_NAME => {
-					Arc::clone(&self)
-						.dispatch_job(ctx, Job::resume(paused_job, IndexerJob {})?)
-						.await;
-				}
-				FULL_IDENTIFIER_JOB_NAME => {
-					Arc::clone(&self)
-						.dispatch_job(ctx, Job::resume(paused_job, FullFileIdentifierJob {})?)
-						.await;
-				}
-				VALIDATOR_JOB_NAME => {
-					Arc::clone(&self)
-						.dispatch_job(ctx, Job::resume(paused_job, ObjectValidatorJob {})?)
-						.await;
+			Arc::clone(&self)
+				.resume_paused_job(ctx, JobReport::from(paused_job_data))
+				.await?;
+		}
+
+		Ok(())
+	}
+
+	/// pause_job pauses a running job, which saves its state so it can be resumed later (even after a restart).
+	///
+	/// This returns false if the job isn't running.
+	pub async fn pause_job(&self, job_id: Uuid) -> bool {
+		match self.running_workers.read().await.get(&job_id) {
+			Some(running) => {
+				running.worker.lock().await.command(WorkerCommand::Pause);
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// resume_job resumes a single paused job.
+	///
+	/// This returns false if the job isn't paused.
+	pub async fn resume_job(
+		self: Arc<Self>,
+		ctx: &LibraryContext,
+		job_id: Uuid,
+	) -> Result<bool, JobError> {
+		let paused_job = ctx
+			.db
+			.job()
+			.find_first(vec![
+				job::id::equals(job_id.as_bytes().to_vec()),
+				job::status::equals(JobStatus::Paused.int_value()),
+			])
+			.exec()
+			.await?;
+
+		match paused_job {
+			Some(paused_job) => {
+				self.resume_paused_job(ctx, JobReport::from(paused_job))
+					.await?;
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	/// cancel_job cancels a job, whether it's running, queued or paused.
+	///
+	/// Running jobs are able to clean up after themselves before they stop. This returns false if the job wasn't found.
+	pub async fn cancel_job(&self, ctx: &LibraryContext, job_id: Uuid) -> Result<bool, JobError> {
+		if let Some(running) = self.running_workers.read().await.get(&job_id) {
+			running.worker.lock().await.command(WorkerCommand::Cancel);
+			return Ok(true);
+		}
+
+		{
+			let mut job_queue = self.job_queue.write().await;
+
+			if let Some(index) = job_queue.iter_mut().position(|job| {
+				job.report()
+					.as_ref()
+					.map_or(false, |report| report.id == job_id)
+			}) {
+				if let Some(job) = job_queue.remove(index) {
+					self.current_jobs_hashes.write().await.remove(&job.hash());
 				}
-				_ => {
-					error!(
-						"Unknown job type: {}, id: {}",
-						paused_job.name, paused_job.id
-					);
-					return Err(JobError::UnknownJobName(paused_job.id, paused_job.name));
+
+				return Ok(true);
+			}
+		}
+
+		let parked = {
+			let mut parked_jobs = self.parked_jobs.write().await;
+
+			parked_jobs
+				.iter_mut()
+				.position(|parked| {
+					parked
+						.job
+						.report()
+						.as_ref()
+						.map_or(false, |report| report.id == job_id)
+				})
+				.map(|index| parked_jobs.remove(index))
+		};
+
+		if let Some(parked) = &parked {
+			self.current_jobs_hashes
+				.write()
+				.await
+				.remove(&parked.job.hash());
+
+			invalidate_query!(ctx, "jobs.getWaitingForKeys");
+		}
+
+		// paused jobs only exist within the database, so they're rebuilt from their saved state to clean up after themselves
+		let paused_job = ctx
+			.db
+			.job()
+			.find_first(vec![
+				job::id::equals(job_id.as_bytes().to_vec()),
+				job::status::equals(JobStatus::Paused.int_value()),
+			])
+			.exec()
+			.await?;
+
+		// a job that was resumed while its keys weren't mounted is still paused within the database
+		let paused_job = match paused_job {
+			Some(paused_job) => JobReport::from(paused_job),
+			None => return Ok(parked.is_some()),
+		};
+
+		match Self::rebuild_job(paused_job) {
+			Ok(mut job) => {
+				if let Err(e) = job.cancel(ctx).await {
+					warn!("Failed to clean up after canceled job: {:#?}", e);
 				}
-			};
+			}
+			Err(e) => warn!("Failed to rebuild canceled job: {:#?}", e),
+		}
+
+		ctx.db
+			.job()
+			.update(
+				job::id::equals(job_id.as_bytes().to_vec()),
+				vec![
+					job::status::set(JobStatus::Canceled.int_value()),
+					job::data::set(None),
+					job::checkpoint::set(None),
+					job::next_jobs::set(None),
+				],
+			)
+			.exec()
+			.await?;
+
+		invalidate_query!(ctx, "jobs.getHistory");
+		invalidate_query!(ctx, "jobs.history");
+
+		Ok(true)
+	}
+
+	/// retry_failed dispatches a new job for the files that failed within a completed job.
+	///
+	/// This returns false if the job hasn't completed, or if there's nothing to retry.
+	pub async fn retry_failed(
+		self: Arc<Self>,
+		ctx: &LibraryContext,
+		job_id: Uuid,
+	) -> Result<bool, JobError> {
+		let completed_job = ctx
+			.db
+			.job()
+			.find_first(vec![
+				job::id::equals(job_id.as_bytes().to_vec()),
+				job::status::equals(JobStatus::Completed.int_value()),
+			])
+			.exec()
+			.await?;
+
+		let job = match completed_job {
+			Some(completed_job) => Self::retry_job(JobReport::from(completed_job))?,
+			None => return Ok(false),
+		};
+
+		match job {
+			Some(job) => {
+				info!("Retrying failed files from job: {}", job_id);
+				self.ingest(ctx, job).await;
+				Ok(true)
+			}
+			None => Ok(false),
 		}
+	}
+
+	/// get_waiting_for_keys returns the jobs within a library that are waiting for keys to be mounted.
+	pub async fn get_waiting_for_keys(&self, ctx: &LibraryContext) -> Vec<WaitingJob> {
+		self.parked_jobs
+			.write()
+			.await
+			.iter_mut()
+			.filter(|parked| parked.library_id == ctx.id)
+			.filter_map(|parked| {
+				Some(WaitingJob {
+					report: parked.job.report().clone()?,
+					key_uuids: parked.key_uuids.clone(),
+				})
+			})
+			.collect()
+	}
+
+	/// resume_jobs_waiting_for_keys runs the jobs within a library that were waiting for keys, once all of those keys are mounted.
+	///
+	/// This should be called whenever keys are mounted.
+	pub async fn resume_jobs_waiting_for_keys(self: Arc<Self>, ctx: &LibraryContext) {
+		let ready = {
+			let mut parked_jobs = self.parked_jobs.write().await;
+
+			let (ready, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut *parked_jobs)
+				.into_iter()
+				.partition(|parked| {
+					parked.library_id == ctx.id
+						&& parked
+							.key_uuids
+							.iter()
+							.all(|uuid| ctx.key_manager.keymount_contains(*uuid))
+				});
+			*parked_jobs = waiting;
+
+			ready
+		};
+
+		if ready.is_empty() {
+			return;
+		}
+
+		invalidate_query!(ctx, "jobs.getWaitingForKeys");
+
+		for parked in ready {
+			info!("Keys were mounted, resuming job: {}", parked.job.name());
+			Arc::clone(&self).dispatch_job(ctx, parked.job).await;
+		}
+	}
+
+	/// park_job holds onto a job until the keys that it needs are mounted, and asks the client for them.
+	async fn park_job(&self, ctx: &LibraryContext, mut job: Box<dyn DynJob>, key_uuids: Vec<Uuid>) {
+		let job_id = job
+			.report()
+			.as_ref()
+			.map(|report| report.id)
+			.expect("critical error: missing job report");
+
+		info!(
+			"Job <name='{}', id='{}'> is waiting for keys to be mounted: {:?}",
+			job.name(),
+			job_id,
+			key_uuids
+		);
+
+		ctx.emit(CoreEvent::KeysRequired {
+			library_id: ctx.id,
+			job_id,
+			job_name: job.name().to_string(),
+			key_uuids: key_uuids.clone(),
+		});
+
+		self.parked_jobs.write().await.push(ParkedJob {
+			library_id: ctx.id,
+			key_uuids,
+			job,
+		});
+
+		invalidate_query!(ctx, "jobs.getWaitingForKeys");
+	}
+
+	pub async fn get_concurrency(&self) -> JobConcurrency {
+		self.concurrency.read().await.clone()
+	}
+
+	/// set_concurrency changes the concurrency limits, and starts any queued jobs that are now able to run.
+	pub async fn set_concurrency(
+		self: Arc<Self>,
+		ctx: &LibraryContext,
+		concurrency: JobConcurrency,
+	) {
+		*self.concurrency.write().await = concurrency;
+
+		self.continue_queue(ctx).await;
+	}
+
+	/// continue_queue starts as many queued jobs as are able to run, e.g. once a job has finished (and may have queued the jobs that depend on it).
+	async fn continue_queue(self: Arc<Self>, ctx: &LibraryContext) {
+		while let Some(job) = self.next_job().await {
+			Arc::clone(&self).dispatch_job(ctx, job).await;
+		}
+	}
+
+	/// next_job takes the queued job with the highest priority that's able to run without exceeding any concurrency limits.
+	async fn next_job(&self) -> Option<Box<dyn DynJob>> {
+		let running_workers = self.running_workers.read().await;
+		let concurrency = self.concurrency.read().await;
+		let mut job_queue = self.job_queue.write().await;
+
+		let index = job_queue
+			.iter()
+			.enumerate()
+			.filter(|(_, job)| concurrency.can_run(job.name(), &running_workers))
+			// jobs of the same priority are taken in the order that they were queued
+			.max_by_key(|(index, job)| (job.priority(), Reverse(*index)))
+			.map(|(index, _)| index)?;
+
+		job_queue.remove(index)
+	}
+
+	async fn resume_paused_job(
+		self: Arc<Self>,
+		ctx: &LibraryContext,
+		paused_job: JobReport,
+	) -> Result<(), JobError> {
+		info!("Resuming job: {}, id: {}", paused_job.name, paused_job.id);
+
+		let job = Self::rebuild_job(paused_job)?;
+		self.dispatch_job(ctx, job).await;
 
 		Ok(())
 	}
 
+	/// rebuild_job creates a job from its saved state and latest checkpoint.
+	fn rebuild_job(paused_job: JobReport) -> Result<Box<dyn DynJob>, JobError> {
+		let next_jobs = paused_job.next_jobs.clone();
+
+		let mut job: Box<dyn DynJob> = match paused_job.name.as_str() {
+			THUMBNAIL_JOB_NAME => Job::resume(paused_job, ThumbnailJob {})?,
+			MEDIA_DATA_JOB_NAME => Job::resume(paused_job, MediaDataJob {})?,
+			TEXT_INDEXER_JOB_NAME => Job::resume(paused_job, TextIndexerJob {})?,
+			TAG_RULE_JOB_NAME => Job::resume(paused_job, TagRuleJob {})?,
+			FILE_VERSIONER_JOB_NAME => Job::resume(paused_job, FileVersionerJob {})?,
+			BULK_TAG_JOB_NAME => Job::resume(paused_job, BulkTagJob {})?,
+			INDEXER_JOB_NAME => Job::resume(paused_job, IndexerJob {})?,
+			REMOTE_INDEXER_JOB_NAME => Job::resume(paused_job, RemoteIndexerJob {})?,
+			REMOTE_TRANSFER_JOB_NAME => Job::resume(paused_job, RemoteTransferJob {})?,
+			FULL_IDENTIFIER_JOB_NAME => Job::resume(paused_job, FullFileIdentifierJob {})?,
+			VALIDATOR_JOB_NAME => Job::resume(paused_job, ObjectValidatorJob {})?,
+			FILE_ENCRYPTOR_JOB_NAME => Job::resume(paused_job, FileEncryptorJob {})?,
+			FILE_DECRYPTOR_JOB_NAME => Job::resume(paused_job, FileDecryptorJob {})?,
+			HEADER_DECRYPTOR_JOB_NAME => Job::resume(paused_job, FileHeaderDecryptorJob {})?,
+			FILE_REKEYER_JOB_NAME => Job::resume(paused_job, FileRekeyerJob {})?,
+			FILE_VERIFIER_JOB_NAME => Job::resume(paused_job, FileVerifierJob {})?,
+			HEADER_AUDITOR_JOB_NAME => Job::resume(paused_job, FileHeaderAuditorJob {})?,
+			FILE_ARCHIVER_JOB_NAME => Job::resume(paused_job, FileArchiverJob {})?,
+			DUPLICATE_RESOLVER_JOB_NAME => Job::resume(paused_job, DuplicateResolverJob {})?,
+			FILE_COPIER_JOB_NAME => Job::resume(paused_job, FileCopierJob {})?,
+			FILE_MOVER_JOB_NAME => Job::resume(paused_job, FileMoverJob {})?,
+			FILE_DELETER_JOB_NAME => Job::resume(paused_job, FileDeleterJob {})?,
+			_ => {
+				error!(
+					"Unknown job type: {}, id: {}",
+					paused_job.name, paused_job.id
+				);
+				return Err(JobError::UnknownJobName(paused_job.id, paused_job.name));
+			}
+		};
+
+		if let Some(next_jobs) = next_jobs {
+			job.set_next_jobs(Self::rebuild_queued_jobs(rmp_serde::from_slice(
+				&next_jobs,
+			)?)?);
+		}
+
+		Ok(job)
+	}
+
+	/// rebuild_queued_jobs creates the jobs that were queued after a paused job, which haven't started yet.
+	fn rebuild_queued_jobs(
+		queued_jobs: Vec<QueuedJob>,
+	) -> Result<VecDeque<Box<dyn DynJob>>, JobError> {
+		queued_jobs
+			.into_iter()
+			.map(|queued_job| {
+				let mut report = JobReport::new(Uuid::new_v4(), queued_job.name);
+				report.data = Some(queued_job.state);
+
+				let mut job = Self::rebuild_job(report)?;
+				job.set_next_jobs(Self::rebuild_queued_jobs(queued_job.next_jobs)?);
+
+				Ok(job)
+			})
+			.collect()
+	}
+
+	/// retry_job creates a job from the `retry` init within a completed job's report, which only covers the files that failed.
+	fn retry_job(completed_job: JobReport) -> Result<Option<Box<dyn DynJob>>, JobError> {
+		#[derive(Deserialize)]
+		struct Retryable<Init> {
+			retry: Option<Init>,
+		}
+
+		fn retry_init<Init: serde::de::DeserializeOwned>(
+			metadata: Option<serde_json::Value>,
+		) -> Result<Option<Init>, JobError> {
+			Ok(match metadata {
+				Some(metadata) => serde_json::from_value::<Retryable<Init>>(metadata)?.retry,
+				None => None,
+			})
+		}
+
+		let metadata = completed_job.metadata;
+
+		let job: Option<Box<dyn DynJob>> = match completed_job.name.as_str() {
+			FILE_ENCRYPTOR_JOB_NAME => retry_init(metadata)?
+				.map(|init| Job::new(init, FileEncryptorJob {}) as Box<dyn DynJob>),
+			FILE_DECRYPTOR_JOB_NAME => retry_init(metadata)?
+				.map(|init| Job::new(init, FileDecryptorJob {}) as Box<dyn DynJob>),
+			FILE_REKEYER_JOB_NAME => retry_init(metadata)?
+				.map(|init| Job::new(init, FileRekeyerJob {}) as Box<dyn DynJob>),
+			_ => None,
+		};
+
+		Ok(job)
+	}
+
 	async fn dispatch_job(self: Arc<Self>, ctx: &LibraryContext, mut job: Box<dyn DynJob>) {
+		// a job that needs keys which aren't mounted waits for them, rather than failing as soon as it starts
+		let missing_keys = match job.required_keys(ctx).await {
+			Ok(key_uuids) => key_uuids
+				.into_iter()
+				.filter(|uuid| !ctx.key_manager.keymount_contains(*uuid))
+				.collect::<Vec<_>>(),
+			Err(e) => {
+				// the job is run anyway, so it fails with its own error (which is kept within the job history)
+				warn!(
+					"Failed to check the keys required by job <name='{}'>: {:#?}",
+					job.name(),
+					e
+				);
+				vec![]
+			}
+		};
+
+		if !missing_keys.is_empty() {
+			self.park_job(ctx, job, missing_keys).await;
+			return;
+		}
+
 		// create worker to process job
 		let mut running_workers = self.running_workers.write().await;
-		if running_workers.len() < MAX_WORKERS {
+		let can_run = self
+			.concurrency
+			.read()
+			.await
+			.can_run(job.name(), &running_workers);
+
+		if can_run {
 			info!("Running job: {:?}", job.name());
 
 			let job_report = job
@@ -242,6 +855,7 @@ impl JobManager {
 				.expect("critical error: missing job on worker");
 
 			let job_id = job_report.id;
+			let name = job.name();
 
 			let worker = Worker::new(job, job_report);
 
@@ -252,7 +866,13 @@ impl JobManager {
 			{
 				error!("Error spawning worker: {:?}", e);
 			} else {
-				running_workers.insert(job_id, wrapped_worker);
+				running_workers.insert(
+					job_id,
+					RunningWorker {
+						name,
+						worker: wrapped_worker,
+					},
+				);
 			}
 		} else {
 			debug!(
@@ -271,6 +891,102 @@ pub enum JobReportUpdate {
 	CompletedTaskCount(usize),
 	Message(String),
 	SecondsElapsed(u64),
+	/// the total number of bytes the job is going to process, for jobs that move file contents around
+	TotalBytes(u64),
+	/// the job has started processing a new file, which is `size` bytes long
+	CurrentFile {
+		name: String,
+		size: u64,
+	},
+	/// the number of bytes processed so far, across the whole job and within the current file
+	///
+	/// These are running totals rather than increments, so a dropped (debounced) update doesn't throw the counts off.
+	BytesProcessed {
+		total: u64,
+		file: u64,
+	},
+}
+
+/// TransferProgress is the progress of a job that's processing file contents (e.g. encrypting or decrypting files).
+///
+/// It's only kept in memory, as it's meaningless once the job stops running.
+#[derive(Debug, Serialize, Deserialize, Type, Clone, Default)]
+pub struct TransferProgress {
+	pub current_file: Option<String>,
+	pub current_file_percentage: u8,
+	pub bytes_processed: u64,
+	pub total_bytes: u64,
+	/// this is averaged over the last few seconds, so it reflects the current speed rather than the overall speed
+	pub bytes_per_second: u64,
+	pub eta_seconds: Option<u64>,
+}
+
+job::select!(job_for_stats {
+	name
+	status
+	seconds_elapsed
+	completed_task_count
+	bytes_processed
+});
+
+/// The most jobs that are returned by `jobs.history` at once, if the client doesn't ask for a different amount.
+const JOB_HISTORY_PAGE_SIZE: i64 = 100;
+
+/// `JobHistoryArgs` is the argument received from the client using rspc to filter the job history.
+///
+/// Only jobs that have finished are included, unless a status is given.
+#[derive(Debug, Deserialize, Type)]
+pub struct JobHistoryArgs {
+	pub name: Option<String>,
+	pub status: Option<JobStatus>,
+	pub since: Option<chrono::DateTime<chrono::Utc>>,
+	pub until: Option<chrono::DateTime<chrono::Utc>>,
+	pub skip: Option<i64>,
+	pub take: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Type)]
+pub struct JobHistory {
+	pub jobs: Vec<JobReport>,
+	pub total: JobStats,
+	/// by_name holds the statistics for each type of job (by job name).
+	pub by_name: HashMap<String, JobStats>,
+}
+
+/// JobStats sums up a set of jobs from the job history.
+#[derive(Debug, Default, Serialize, Type)]
+pub struct JobStats {
+	pub count: u64,
+	pub completed: u64,
+	pub canceled: u64,
+	pub failed: u64,
+	pub seconds_elapsed: u64,
+	/// tasks_completed is usually the amount of files that the jobs have processed
+	pub tasks_completed: u64,
+	pub bytes_processed: u64,
+}
+
+impl JobStats {
+	fn record(
+		&mut self,
+		status: Option<JobStatus>,
+		seconds_elapsed: i32,
+		completed_task_count: i32,
+		bytes_processed: u64,
+	) {
+		self.count += 1;
+
+		match status {
+			Some(JobStatus::Completed) => self.completed += 1,
+			Some(JobStatus::Canceled) => self.canceled += 1,
+			Some(JobStatus::Failed) => self.failed += 1,
+			_ => {}
+		}
+
+		self.seconds_elapsed += u64::try_from(seconds_elapsed).unwrap_or_default();
+		self.tasks_completed += u64::try_from(completed_task_count).unwrap_or_default();
+		self.bytes_processed += bytes_processed;
+	}
 }
 
 #[derive(Debug, Serialize, Deserialize, Type, Clone)]
@@ -278,6 +994,8 @@ pub struct JobReport {
 	pub id: Uuid,
 	pub name: String,
 	pub data: Option<Vec<u8>>,
+	pub checkpoint: Option<Vec<u8>>,
+	pub next_jobs: Option<Vec<u8>>,
 	pub metadata: Option<serde_json::Value>,
 	// client_id: i32,
 	pub date_created: chrono::DateTime<chrono::Utc>,
@@ -291,6 +1009,10 @@ pub struct JobReport {
 	// pub percentage_complete: f64,
 	// #[ts(type = "string")] // TODO: Make this work with specta
 	pub seconds_elapsed: i32,
+	#[serde(default)]
+	pub bytes_processed: u64,
+	#[serde(default)]
+	pub transfer: Option<TransferProgress>,
 }
 
 impl Display for JobReport {
@@ -316,6 +1038,8 @@ impl From<job::Data> for JobReport {
 			date_created: data.date_created.into(),
 			date_modified: data.date_modified.into(),
 			data: data.data,
+			checkpoint: data.checkpoint,
+			next_jobs: data.next_jobs,
 			metadata: data.metadata.and_then(|m| {
 				serde_json::from_slice(&m).unwrap_or_else(|e| -> Option<serde_json::Value> {
 					error!("Failed to deserialize job metadata: {}", e);
@@ -324,6 +1048,8 @@ impl From<job::Data> for JobReport {
 			}),
 			message: String::new(),
 			seconds_elapsed: data.seconds_elapsed,
+			bytes_processed: data.bytes_processed.parse().unwrap_or_default(),
+			transfer: None,
 		}
 	}
 }
@@ -339,10 +1065,14 @@ impl JobReport {
 			status: JobStatus::Queued,
 			task_count: 0,
 			data: None,
+			checkpoint: None,
+			next_jobs: None,
 			metadata: None,
 			completed_task_count: 0,
 			message: String::new(),
 			seconds_elapsed: 0,
+			bytes_processed: 0,
+			transfer: None,
 		}
 	}
 
@@ -354,7 +1084,10 @@ impl JobReport {
 				self.name.clone(),
 				JobStatus::Running as i32,
 				node::id::equals(ctx.node_local_id),
-				vec![job::data::set(self.data.clone())],
+				vec![
+					job::data::set(self.data.clone()),
+					job::next_jobs::set(self.next_jobs.clone()),
+				],
 			)
 			.exec()
 			.await?;
@@ -368,11 +1101,14 @@ impl JobReport {
 				vec![
 					job::status::set(self.status.int_value()),
 					job::data::set(self.data.clone()),
+					job::checkpoint::set(self.checkpoint.clone()),
+					job::next_jobs::set(self.next_jobs.clone()),
 					job::metadata::set(serde_json::to_vec(&self.metadata).ok()),
 					job::task_count::set(self.task_count),
 					job::completed_task_count::set(self.completed_task_count),
 					job::date_modified::set(chrono::Utc::now().into()),
 					job::seconds_elapsed::set(self.seconds_elapsed),
+					job::bytes_processed::set(self.bytes_processed.to_string()),
 				],
 			)
 			.exec()
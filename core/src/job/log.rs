@@ -0,0 +1,105 @@
+use int_enum::IntEnum;
+use prisma_client_rust::{Direction, QueryError};
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+	invalidate_query,
+	library::LibraryContext,
+	prisma::{job, job_log},
+};
+
+/// The most log lines that are returned by `jobs.logs` at once, if the client doesn't ask for a different amount.
+const JOB_LOG_PAGE_SIZE: i64 = 100;
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, Eq, PartialEq, IntEnum)]
+pub enum JobLogLevel {
+	Info = 0,
+	Warning = 1,
+	Error = 2,
+}
+
+/// A line that's been logged by a running job, which is waiting to be written to the database.
+#[derive(Debug)]
+pub struct JobLogEntry {
+	pub level: JobLogLevel,
+	pub message: String,
+	/// path is the file that the line is about, if there is one
+	pub path: Option<String>,
+}
+
+/// A line from a job's log, as it's returned by `jobs.logs`.
+#[derive(Debug, Serialize, Type)]
+pub struct JobLog {
+	pub id: i32,
+	pub level: JobLogLevel,
+	pub message: String,
+	pub path: Option<String>,
+	pub date_created: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<job_log::Data> for JobLog {
+	fn from(data: job_log::Data) -> Self {
+		Self {
+			id: data.id,
+			level: JobLogLevel::from_int(data.level).unwrap_or(JobLogLevel::Info),
+			message: data.message,
+			path: data.path,
+			date_created: data.date_created.into(),
+		}
+	}
+}
+
+/// This writes a job's pending log lines to the database.
+///
+/// Failures are only logged, as a job's log shouldn't prevent it from running.
+pub(super) async fn write_job_logs(
+	library: &LibraryContext,
+	job_id: Uuid,
+	entries: Vec<JobLogEntry>,
+) {
+	if entries.is_empty() {
+		return;
+	}
+
+	let creates = entries
+		.into_iter()
+		.map(|entry| {
+			library.db.job_log().create(
+				entry.level.int_value(),
+				entry.message,
+				job::id::equals(job_id.as_bytes().to_vec()),
+				vec![job_log::path::set(entry.path)],
+			)
+		})
+		.collect::<Vec<_>>();
+
+	match library.db._batch(creates).await {
+		Ok(_) => invalidate_query!(library, "jobs.logs"),
+		Err(e) => error!("Failed to write logs for job <id='{}'>: {:#?}", job_id, e),
+	}
+}
+
+/// This returns a page of a job's log, oldest first.
+pub async fn get_job_logs(
+	library: &LibraryContext,
+	job_id: Uuid,
+	skip: Option<i64>,
+	take: Option<i64>,
+) -> Result<Vec<JobLog>, QueryError> {
+	Ok(library
+		.db
+		.job_log()
+		.find_many(vec![job_log::job_id::equals(job_id.as_bytes().to_vec())])
+		.order_by(job_log::id::order(Direction::Asc))
+		.skip(skip.unwrap_or_default())
+		.take(take.unwrap_or(JOB_LOG_PAGE_SIZE))
+		.exec()
+		.await?
+		.into_iter()
+		.map(Into::into)
+		.collect())
+}
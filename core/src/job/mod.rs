@@ -1,12 +1,20 @@
 use crate::{
-	location::{indexer::IndexerError, LocationError},
-	object::{identifier_job::IdentifierJobError, preview::ThumbnailError},
+	library::{EncryptedFieldError, LibraryContext, SearchIndexError},
+	location::{indexer::IndexerError, remote::RemoteError, LocationError},
+	object::{
+		fs::trash::TrashError,
+		identifier_job::IdentifierJobError,
+		preview::{EncryptedThumbnailError, ThumbnailError},
+		version::VersionError,
+	},
 };
 
 use std::{
 	collections::{hash_map::DefaultHasher, VecDeque},
 	fmt::Debug,
 	hash::{Hash, Hasher},
+	path::PathBuf,
+	time::{Duration, Instant},
 };
 
 use rmp_serde::{decode::Error as DecodeError, encode::Error as EncodeError};
@@ -17,9 +25,15 @@ use tracing::warn;
 use uuid::Uuid;
 
 mod job_manager;
+mod log;
+mod scheduler;
+mod throttle;
 mod worker;
 
 pub use job_manager::*;
+pub use log::*;
+pub use scheduler::*;
+pub use throttle::*;
 pub use worker::*;
 
 #[derive(Error, Debug)]
@@ -43,6 +57,8 @@ pub enum JobError {
 		"Tried to resume a job that doesn't have saved state data: job <name='{1}', uuid='{0}'>"
 	)]
 	MissingJobDataState(Uuid, String),
+	#[error("Failed to save the job's checkpoint")]
+	CheckpointFailed,
 
 	// Specific job errors
 	#[error("Indexer error: {0}")]
@@ -51,8 +67,36 @@ pub enum JobError {
 	LocationError(#[from] LocationError),
 	#[error("Thumbnail error: {0}")]
 	ThumbnailError(#[from] ThumbnailError),
+	#[error("Encrypted thumbnail error: {0}")]
+	EncryptedThumbnailError(#[from] EncryptedThumbnailError),
+	#[error("Encrypted field error: {0}")]
+	EncryptedFieldError(#[from] EncryptedFieldError),
 	#[error("Identifier error: {0}")]
 	IdentifierError(#[from] IdentifierJobError),
+	#[error("Remote location error: {0}")]
+	RemoteError(#[from] RemoteError),
+	#[error("Search index error: {0}")]
+	SearchIndexError(#[from] SearchIndexError),
+	#[error("Trash error: {0}")]
+	TrashError(#[from] TrashError),
+	#[error("Version error: {0}")]
+	VersionError(#[from] VersionError),
+	#[error("Key was unmounted while the job was using it: <uuid='{0}'>")]
+	KeyRevoked(Uuid),
+	#[error("Not enough space on {mount_point:?} (required: {required} bytes, available: {available} bytes)")]
+	InsufficientSpace {
+		mount_point: PathBuf,
+		required: u64,
+		available: u64,
+	},
+	#[error("File's contents don't match the file it was compared with: {0:?}")]
+	ContentChanged(PathBuf),
+	#[error(
+		"Destination directory not found: <location_id={location_id}, file_path_id={directory_id}>"
+	)]
+	DestinationNotFound { location_id: i32, directory_id: i32 },
+	#[error("Directory can't be copied or moved into itself: {0:?}")]
+	DestinationWithinSource(PathBuf),
 
 	// Not errors
 	#[error("Job had a early finish: <name='{name}', reason='{reason}'>")]
@@ -63,6 +107,14 @@ pub enum JobError {
 	JobDataNotFound(String),
 	#[error("Job paused")]
 	Paused(Vec<u8>),
+	#[error("Job canceled")]
+	Canceled,
+}
+
+impl From<JobError> for rspc::Error {
+	fn from(err: JobError) -> Self {
+		rspc::Error::with_cause(rspc::ErrorCode::InternalServerError, err.to_string(), err)
+	}
 }
 
 pub type JobResult = Result<JobMetadata, JobError>;
@@ -75,6 +127,23 @@ pub trait StatefulJob: Send + Sync + Sized {
 	type Step: Serialize + DeserializeOwned + Send + Sync;
 
 	fn name(&self) -> &'static str;
+
+	/// This decides which queued job is run next. Long-running bulk jobs should use a low priority, so they don't hold up jobs the UI is waiting on.
+	fn priority(&self) -> JobPriority {
+		JobPriority::Normal
+	}
+
+	/// This returns the keys that have to be mounted before the job is able to run.
+	///
+	/// If any of them aren't mounted, the job waits for them (and the client is asked for them) rather than failing straight away.
+	async fn required_keys(
+		&self,
+		_ctx: &LibraryContext,
+		_init: &Self::Init,
+	) -> Result<Vec<Uuid>, JobError> {
+		Ok(vec![])
+	}
+
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError>;
 
 	async fn execute_step(
@@ -84,20 +153,44 @@ pub trait StatefulJob: Send + Sync + Sized {
 	) -> Result<(), JobError>;
 
 	async fn finalize(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult;
+
+	/// This is called once the job has been canceled (or has failed), so anything it left behind (e.g. partially-written files) can be cleaned up.
+	///
+	/// Paused jobs are canceled without being resumed, so this may be called with the state from the job's last checkpoint.
+	async fn cancel(
+		&self,
+		_ctx: &LibraryContext,
+		_state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		Ok(())
+	}
 }
 
 #[async_trait::async_trait]
 pub trait DynJob: Send + Sync {
 	fn report(&mut self) -> &mut Option<JobReport>;
 	fn name(&self) -> &'static str;
+	fn priority(&self) -> JobPriority;
+	async fn required_keys(&self, ctx: &LibraryContext) -> Result<Vec<Uuid>, JobError>;
 	async fn run(&mut self, ctx: WorkerContext) -> JobResult;
+	async fn cancel(&mut self, ctx: &LibraryContext) -> Result<(), JobError>;
 	fn hash(&self) -> u64;
+	/// This takes the jobs that are queued once this job has finished successfully (see `Job::queue_next`).
+	fn next_jobs(&mut self) -> VecDeque<Box<dyn DynJob>>;
+	/// This replaces the jobs that are queued once this job has finished, for jobs that are rebuilt after a restart.
+	fn set_next_jobs(&mut self, next_jobs: VecDeque<Box<dyn DynJob>>);
+	/// This serializes the job's state along with the jobs queued after it, so they can be saved alongside the job that they're waiting for.
+	fn queued(&self) -> Result<QueuedJob, JobError>;
 }
 
+/// The full state of a running job is saved at most this often, as it includes every remaining step.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(15);
+
 pub struct Job<SJob: StatefulJob> {
 	report: Option<JobReport>,
 	state: JobState<SJob>,
 	stateful_job: SJob,
+	next_jobs: VecDeque<Box<dyn DynJob>>,
 }
 
 impl<SJob: StatefulJob> Job<SJob> {
@@ -114,9 +207,18 @@ impl<SJob: StatefulJob> Job<SJob> {
 				step_number: 0,
 			},
 			stateful_job,
+			next_jobs: VecDeque::new(),
 		})
 	}
 
+	/// This queues a job once this one has finished successfully, for jobs that depend on its results (e.g. the file identifier needs the indexer's file paths).
+	///
+	/// Jobs that are queued next are saved alongside the job, so they're still run if it's resumed after a restart.
+	pub fn queue_next(mut self: Box<Self>, job: Box<dyn DynJob>) -> Box<Self> {
+		self.next_jobs.push_back(job);
+		self
+	}
+
 	pub fn resume(mut report: JobReport, stateful_job: SJob) -> Result<Box<Self>, JobError> {
 		let job_state_data = if let Some(data) = report.data.take() {
 			data
@@ -124,10 +226,25 @@ impl<SJob: StatefulJob> Job<SJob> {
 			return Err(JobError::MissingJobDataState(report.id, report.name));
 		};
 
+		let mut state: JobState<SJob> = rmp_serde::from_slice(&job_state_data)?;
+
+		if let Some(checkpoint) = report.checkpoint.take() {
+			let checkpoint: JobCheckpoint<SJob::Data> = rmp_serde::from_slice(&checkpoint)?;
+
+			// the checkpoint is newer than the state, so any steps that were finished in between are skipped
+			if checkpoint.step_number >= state.step_number {
+				let finished = (checkpoint.step_number - state.step_number).min(state.steps.len());
+				state.steps.drain(..finished);
+				state.step_number = checkpoint.step_number;
+				state.data = checkpoint.data;
+			}
+		}
+
 		Ok(Box::new(Self {
 			report: Some(report),
-			state: rmp_serde::from_slice(&job_state_data)?,
+			state,
 			stateful_job,
+			next_jobs: VecDeque::new(),
 		}))
 	}
 }
@@ -147,6 +264,21 @@ pub struct JobState<Job: StatefulJob> {
 	pub step_number: usize,
 }
 
+/// QueuedJob is a job that's waiting for another one to finish, as it's saved alongside that job (see `JobManager::rebuild_job`).
+#[derive(Serialize, Deserialize)]
+pub struct QueuedJob {
+	pub(super) name: String,
+	pub(super) state: Vec<u8>,
+	pub(super) next_jobs: Vec<QueuedJob>,
+}
+
+/// JobCheckpoint is a job's progress without its steps, so it can be saved far more often than the full state.
+#[derive(Serialize, Deserialize)]
+pub(super) struct JobCheckpoint<Data> {
+	pub step_number: usize,
+	pub data: Option<Data>,
+}
+
 impl<Job: StatefulJob> Hash for JobState<Job> {
 	fn hash<H: Hasher>(&self, state: &mut H) {
 		self.init.hash(state);
@@ -163,28 +295,55 @@ impl<State: StatefulJob> DynJob for Job<State> {
 		self.stateful_job.name()
 	}
 
+	fn priority(&self) -> JobPriority {
+		self.stateful_job.priority()
+	}
+
+	async fn required_keys(&self, ctx: &LibraryContext) -> Result<Vec<Uuid>, JobError> {
+		self.stateful_job.required_keys(ctx, &self.state.init).await
+	}
+
 	async fn run(&mut self, ctx: WorkerContext) -> JobResult {
 		// Checking if we have a brand new job, or if we are resuming an old one.
 		if self.state.data.is_none() {
 			self.stateful_job.init(ctx.clone(), &mut self.state).await?;
+
+			// an interrupted job can't be resumed (or cleaned up) without its state
+			ctx.snapshot(&self.state).await?;
 		}
 
+		let mut last_snapshot = Instant::now();
+
 		let mut shutdown_rx = ctx.shutdown_rx();
 		let shutdown_rx_fut = shutdown_rx.recv();
 		tokio::pin!(shutdown_rx_fut);
 
+		let mut commands_rx = ctx.commands_rx();
+
 		while !self.state.steps.is_empty() {
 			tokio::select! {
 				step_result = self.stateful_job.execute_step(
 					ctx.clone(),
 					&mut self.state,
 				) => {
-					if matches!(step_result, Err(JobError::EarlyFinish { .. })) {
-						warn!("{}", step_result.unwrap_err());
-						break;
-					} else {
-						step_result?;
-					};
+					match step_result {
+						Err(e @ JobError::EarlyFinish { .. }) => {
+							warn!("{}", e);
+							break;
+						}
+						Err(e) => {
+							// a failed job is cleaned up the same way as a canceled one
+							if let Err(cleanup_error) = self
+								.stateful_job
+								.cancel(&ctx.library_ctx, &mut self.state)
+								.await
+							{
+								warn!("failed to clean up after job: {:#?}", cleanup_error);
+							}
+							return Err(e);
+						}
+						Ok(()) => {}
+					}
 					self.state.steps.pop_front();
 				}
 				_ = &mut shutdown_rx_fut => {
@@ -194,8 +353,32 @@ impl<State: StatefulJob> DynJob for Job<State> {
 						)
 					);
 				}
+				// the step is dropped part of the way through, so jobs need to keep their state consistent between `.await`s
+				Ok(()) = commands_rx.changed() => {
+					let command = *commands_rx.borrow();
+
+					match command {
+						Some(WorkerCommand::Pause) => {
+							return Err(
+								JobError::Paused(
+									rmp_serde::to_vec_named(&self.state)?
+								)
+							);
+						}
+						Some(WorkerCommand::Cancel) => {
+							self.stateful_job.cancel(&ctx.library_ctx, &mut self.state).await?;
+							return Err(JobError::Canceled);
+						}
+						None => continue,
+					}
+				}
 			}
 			self.state.step_number += 1;
+
+			if !self.state.steps.is_empty() && last_snapshot.elapsed() >= SNAPSHOT_INTERVAL {
+				ctx.snapshot(&self.state).await?;
+				last_snapshot = Instant::now();
+			}
 		}
 
 		self.stateful_job
@@ -203,9 +386,33 @@ impl<State: StatefulJob> DynJob for Job<State> {
 			.await
 	}
 
+	async fn cancel(&mut self, ctx: &LibraryContext) -> Result<(), JobError> {
+		self.stateful_job.cancel(ctx, &mut self.state).await
+	}
+
 	fn hash(&self) -> u64 {
 		let mut hasher = DefaultHasher::new();
 		Hash::hash(self, &mut hasher);
 		hasher.finish()
 	}
+
+	fn next_jobs(&mut self) -> VecDeque<Box<dyn DynJob>> {
+		std::mem::take(&mut self.next_jobs)
+	}
+
+	fn set_next_jobs(&mut self, next_jobs: VecDeque<Box<dyn DynJob>>) {
+		self.next_jobs = next_jobs;
+	}
+
+	fn queued(&self) -> Result<QueuedJob, JobError> {
+		Ok(QueuedJob {
+			name: self.name().to_string(),
+			state: rmp_serde::to_vec_named(&self.state)?,
+			next_jobs: self
+				.next_jobs
+				.iter()
+				.map(|job| job.queued())
+				.collect::<Result<_, _>>()?,
+		})
+	}
 }
@@ -0,0 +1,433 @@
+use std::{path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::QueryError;
+use rspc::{ErrorCode, Type};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::time::interval;
+use tracing::{error, info};
+
+use crate::{
+	api::keys::create_keystore_backup,
+	invalidate_query,
+	job::Job,
+	library::{LibraryContext, LibraryManager},
+	location::statistics::record_due_statistics,
+	object::{
+		fs::verify::{FileVerifierJob, FileVerifierJobInit},
+		identifier_job::full_identifier_job::{FullFileIdentifierJob, FullFileIdentifierJobInit},
+	},
+	prisma::{location, schedule},
+};
+
+/// How often the scheduler checks for schedules that are due. Schedules can't run more often than this.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Error, Debug)]
+pub enum ScheduleError {
+	#[error("Schedule not found: <id={0}>")]
+	ScheduleNotFound(i32),
+	#[error("Location not found: <id={0}>")]
+	LocationNotFound(i32),
+	#[error("Invalid cron expression: {0}")]
+	InvalidCron(#[from] cron::error::Error),
+	#[error("The cron expression never matches")]
+	CronNeverMatches,
+	#[error("Interval must be at least {} seconds", CHECK_INTERVAL.as_secs())]
+	IntervalTooShort,
+	#[error("Failed to back up the keystore: {0}")]
+	KeystoreBackup(String),
+	#[error("I/O error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("Database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("Serialization error: {0}")]
+	Serialization(#[from] serde_json::Error),
+}
+
+impl From<ScheduleError> for rspc::Error {
+	fn from(err: ScheduleError) -> Self {
+		match err {
+			ScheduleError::ScheduleNotFound(_) | ScheduleError::LocationNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			ScheduleError::InvalidCron(_)
+			| ScheduleError::CronNeverMatches
+			| ScheduleError::IntervalTooShort => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// ScheduledTask is something that a schedule runs. Anything that's done by a job is queued like any other job, so it follows the node's concurrency limits.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(tag = "type")]
+pub enum ScheduledTask {
+	/// this backs the library's keystore up into a directory on this node, with the time of the backup in its name
+	KeystoreBackup { directory: PathBuf },
+	/// this verifies every encrypted file within the given locations (or every location, if there aren't any)
+	VerifyEncryptedFiles { location_ids: Vec<i32> },
+	/// this identifies the files within a location, which is what links duplicate files to the same object
+	IdentifyFiles { location_id: i32 },
+}
+
+/// ScheduleTiming is how often a schedule runs.
+///
+/// Cron expressions are in the usual five field format (minute, hour, day of month, month, day of week), and are evaluated in UTC.
+/// In case of doubt about cron expressions, consult <https://docs.rs/cron/latest/cron/>
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+#[serde(tag = "type")]
+pub enum ScheduleTiming {
+	Interval { seconds: u32 },
+	Cron { expression: String },
+}
+
+impl ScheduleTiming {
+	/// next_run returns when the schedule should next run, if it were to run right now.
+	fn next_run(&self, now: DateTime<Utc>) -> Result<DateTime<Utc>, ScheduleError> {
+		match self {
+			Self::Interval { seconds } => {
+				if u64::from(*seconds) < CHECK_INTERVAL.as_secs() {
+					return Err(ScheduleError::IntervalTooShort);
+				}
+
+				Ok(now + chrono::Duration::seconds(i64::from(*seconds)))
+			}
+			Self::Cron { expression } => {
+				// the cron crate expects a seconds field, which people don't usually write
+				let expression = match expression.split_whitespace().count() {
+					5 => format!("0 {expression}"),
+					_ => expression.clone(),
+				};
+
+				cron::Schedule::from_str(&expression)?
+					.after(&now)
+					.next()
+					.ok_or(ScheduleError::CronNeverMatches)
+			}
+		}
+	}
+
+	fn from_data(data: &schedule::Data) -> Self {
+		match (&data.cron, data.interval) {
+			(Some(expression), _) => Self::Cron {
+				expression: expression.clone(),
+			},
+			(None, interval) => Self::Interval {
+				seconds: interval.and_then(|i| u32::try_from(i).ok()).unwrap_or(0),
+			},
+		}
+	}
+
+	fn params(&self) -> Vec<schedule::SetParam> {
+		match self {
+			Self::Interval { seconds } => vec![
+				schedule::interval::set(Some(*seconds as i32)),
+				schedule::cron::set(None),
+			],
+			Self::Cron { expression } => vec![
+				schedule::interval::set(None),
+				schedule::cron::set(Some(expression.clone())),
+			],
+		}
+	}
+}
+
+/// A schedule, as it's returned to the client.
+#[derive(Debug, Serialize, Type)]
+pub struct Schedule {
+	pub id: i32,
+	pub name: String,
+	pub task: ScheduledTask,
+	pub timing: ScheduleTiming,
+	pub enabled: bool,
+	pub last_run: Option<DateTime<Utc>>,
+	pub last_error: Option<String>,
+	pub next_run: DateTime<Utc>,
+}
+
+impl TryFrom<schedule::Data> for Schedule {
+	type Error = ScheduleError;
+
+	fn try_from(data: schedule::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: data.id,
+			task: serde_json::from_str(&data.task)?,
+			timing: ScheduleTiming::from_data(&data),
+			name: data.name,
+			enabled: data.enabled,
+			last_run: data.last_run.map(Into::into),
+			last_error: data.last_error,
+			next_run: data.next_run.into(),
+		})
+	}
+}
+
+/// `ScheduleCreateArgs` is the argument received from the client using rspc to create a new schedule.
+#[derive(Type, Deserialize)]
+pub struct ScheduleCreateArgs {
+	pub name: String,
+	pub task: ScheduledTask,
+	pub timing: ScheduleTiming,
+}
+
+impl ScheduleCreateArgs {
+	pub async fn create(self, library: &LibraryContext) -> Result<Schedule, ScheduleError> {
+		validate_task(library, &self.task).await?;
+		let next_run = self.timing.next_run(Utc::now())?;
+
+		library
+			.db
+			.schedule()
+			.create(
+				self.name,
+				serde_json::to_string(&self.task)?,
+				next_run.into(),
+				self.timing.params(),
+			)
+			.exec()
+			.await?
+			.try_into()
+	}
+}
+
+/// `ScheduleUpdateArgs` is the argument received from the client using rspc to update a schedule.
+///
+/// Anything that isn't set is left as-is.
+#[derive(Type, Deserialize)]
+pub struct ScheduleUpdateArgs {
+	pub id: i32,
+	pub name: Option<String>,
+	pub task: Option<ScheduledTask>,
+	pub timing: Option<ScheduleTiming>,
+	pub enabled: Option<bool>,
+}
+
+impl ScheduleUpdateArgs {
+	pub async fn update(self, library: &LibraryContext) -> Result<Schedule, ScheduleError> {
+		let existing = library
+			.db
+			.schedule()
+			.find_unique(schedule::id::equals(self.id))
+			.exec()
+			.await?
+			.ok_or(ScheduleError::ScheduleNotFound(self.id))?;
+
+		let mut params = vec![schedule::date_modified::set(Utc::now().into())];
+
+		if let Some(name) = self.name {
+			params.push(schedule::name::set(name));
+		}
+
+		if let Some(task) = self.task {
+			validate_task(library, &task).await?;
+			params.push(schedule::task::set(serde_json::to_string(&task)?));
+		}
+
+		// the next run is worked out again, as it may be a long way off with the old timing (or have been missed while the schedule was disabled)
+		if self.timing.is_some() || self.enabled == Some(true) && !existing.enabled {
+			let timing = self
+				.timing
+				.unwrap_or_else(|| ScheduleTiming::from_data(&existing));
+
+			params.push(schedule::next_run::set(timing.next_run(Utc::now())?.into()));
+			params.extend(timing.params());
+		}
+
+		if let Some(enabled) = self.enabled {
+			params.push(schedule::enabled::set(enabled));
+		}
+
+		library
+			.db
+			.schedule()
+			.update(schedule::id::equals(self.id), params)
+			.exec()
+			.await?
+			.try_into()
+	}
+}
+
+/// This checks that anything a task refers to exists, so a schedule can't be created that'll always fail.
+async fn validate_task(
+	library: &LibraryContext,
+	task: &ScheduledTask,
+) -> Result<(), ScheduleError> {
+	let location_ids = match task {
+		ScheduledTask::KeystoreBackup { .. } => return Ok(()),
+		ScheduledTask::VerifyEncryptedFiles { location_ids } => location_ids.clone(),
+		ScheduledTask::IdentifyFiles { location_id } => vec![*location_id],
+	};
+
+	for location_id in location_ids {
+		if library
+			.db
+			.location()
+			.count(vec![location::id::equals(location_id)])
+			.exec()
+			.await? == 0
+		{
+			return Err(ScheduleError::LocationNotFound(location_id));
+		}
+	}
+
+	Ok(())
+}
+
+/// This starts the scheduler, which runs every library's schedules once they're due (and records the statistics of their locations).
+///
+/// Schedules that were missed while the node wasn't running are run once it starts, rather than once for every time they were missed.
+pub(crate) fn spawn_scheduler(library_manager: Arc<LibraryManager>) {
+	tokio::spawn(async move {
+		let mut interval = interval(CHECK_INTERVAL);
+
+		loop {
+			interval.tick().await;
+
+			for library in library_manager.get_all_libraries_ctx().await {
+				if let Err(e) = run_due_schedules(&library_manager, &library).await {
+					error!(
+						"Failed to run schedules for library <id='{}'>: {:#?}",
+						library.id, e
+					);
+				}
+
+				if let Err(e) = record_due_statistics(&library).await {
+					error!(
+						"Failed to record location statistics for library <id='{}'>: {:#?}",
+						library.id, e
+					);
+				}
+			}
+		}
+	});
+}
+
+async fn run_due_schedules(
+	library_manager: &LibraryManager,
+	library: &LibraryContext,
+) -> Result<(), ScheduleError> {
+	let due = library
+		.db
+		.schedule()
+		.find_many(vec![
+			schedule::enabled::equals(true),
+			schedule::next_run::lte(Utc::now().into()),
+		])
+		.exec()
+		.await?;
+
+	for data in due {
+		run_schedule(library_manager, library, data).await?;
+	}
+
+	Ok(())
+}
+
+/// This runs a schedule straight away, and works out when it should next run.
+pub(crate) async fn run_schedule(
+	library_manager: &LibraryManager,
+	library: &LibraryContext,
+	data: schedule::Data,
+) -> Result<(), ScheduleError> {
+	let now = Utc::now();
+	let timing = ScheduleTiming::from_data(&data);
+
+	info!("Running schedule '{}'", data.name);
+
+	let result = match serde_json::from_str::<ScheduledTask>(&data.task) {
+		Ok(task) => run_task(library_manager, library, task).await,
+		Err(e) => Err(e.into()),
+	};
+
+	if let Err(e) = &result {
+		error!("Schedule '{}' failed: {:#?}", data.name, e);
+	}
+
+	// a schedule whose timing is no longer valid is disabled, rather than being retried every time the scheduler checks
+	let mut params = vec![
+		schedule::last_run::set(Some(now.into())),
+		schedule::last_error::set(result.err().map(|e| e.to_string())),
+	];
+
+	match timing.next_run(now) {
+		Ok(next_run) => params.push(schedule::next_run::set(next_run.into())),
+		Err(e) => {
+			error!("Disabling schedule '{}': {:#?}", data.name, e);
+			params.push(schedule::enabled::set(false));
+		}
+	}
+
+	library
+		.db
+		.schedule()
+		.update(schedule::id::equals(data.id), params)
+		.exec()
+		.await?;
+
+	invalidate_query!(library, "schedules.list");
+
+	Ok(())
+}
+
+async fn run_task(
+	library_manager: &LibraryManager,
+	library: &LibraryContext,
+	task: ScheduledTask,
+) -> Result<(), ScheduleError> {
+	match task {
+		ScheduledTask::KeystoreBackup { directory } => {
+			let backup = create_keystore_backup(library_manager, library)
+				.await
+				.map_err(|e| ScheduleError::KeystoreBackup(e.to_string()))?;
+
+			tokio::fs::create_dir_all(&directory).await?;
+			tokio::fs::write(
+				directory.join(format!(
+					"keystore-backup-{}.json",
+					Utc::now().format("%Y-%m-%dT%H-%M-%SZ")
+				)),
+				backup,
+			)
+			.await?;
+		}
+		ScheduledTask::VerifyEncryptedFiles { location_ids } => {
+			let location_ids = if location_ids.is_empty() {
+				library
+					.db
+					.location()
+					.find_many(vec![])
+					.exec()
+					.await?
+					.into_iter()
+					.map(|location| location.id)
+					.collect()
+			} else {
+				location_ids
+			};
+
+			library
+				.spawn_job(Job::new(
+					FileVerifierJobInit { location_ids },
+					FileVerifierJob,
+				))
+				.await;
+		}
+		ScheduledTask::IdentifyFiles { location_id } => {
+			library
+				.spawn_job(Job::new(
+					FullFileIdentifierJobInit {
+						location_id,
+						sub_path: None,
+					},
+					FullFileIdentifierJob {},
+				))
+				.await;
+		}
+	}
+
+	Ok(())
+}
@@ -0,0 +1,245 @@
+use std::{
+	future::Future,
+	io::{self, Read, Write},
+	pin::Pin,
+	sync::{Arc, Mutex},
+	task::{ready, Context, Poll},
+	time::{Duration, Instant},
+};
+
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tokio::{
+	io::{AsyncRead, ReadBuf},
+	time::Sleep,
+};
+
+/// IoLimits are the rates (in bytes per second) that background jobs are able to read from and write to the disk at, and upload to remote locations at.
+///
+/// This is stored within the node's config, so it's shared between every library. A limit that isn't set means there's no limit.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Type)]
+pub struct IoLimits {
+	pub read: Option<u64>,
+	pub write: Option<u64>,
+	#[serde(default)]
+	pub upload: Option<u64>,
+}
+
+/// IoThrottle keeps background jobs within the node's `IoLimits`, so they don't saturate the disk while it's being used for other things.
+///
+/// The limits are shared between every running job, rather than applying to each of them, and changes to them apply straight away.
+pub struct IoThrottle {
+	read: RateLimiter,
+	write: RateLimiter,
+	upload: RateLimiter,
+}
+
+impl IoThrottle {
+	pub fn new(limits: IoLimits) -> Arc<Self> {
+		Arc::new(Self {
+			read: RateLimiter::new(limits.read),
+			write: RateLimiter::new(limits.write),
+			upload: RateLimiter::new(limits.upload),
+		})
+	}
+
+	pub fn limits(&self) -> IoLimits {
+		IoLimits {
+			read: self.read.rate(),
+			write: self.write.rate(),
+			upload: self.upload.rate(),
+		}
+	}
+
+	pub fn set_limits(&self, limits: IoLimits) {
+		self.read.set_rate(limits.read);
+		self.write.set_rate(limits.write);
+		self.upload.set_rate(limits.upload);
+	}
+
+	/// read waits until `bytes` are able to be read, which should be called alongside each read.
+	pub async fn read(&self, bytes: u64) {
+		sleep(self.read.take(bytes)).await;
+	}
+
+	/// write waits until `bytes` are able to be written, which should be called alongside each write.
+	pub async fn write(&self, bytes: u64) {
+		sleep(self.write.take(bytes)).await;
+	}
+
+	/// read_blocking is the same as `read`, but for code that's running on a blocking thread.
+	pub fn read_blocking(&self, bytes: u64) {
+		std::thread::sleep(self.read.take(bytes));
+	}
+
+	/// write_blocking is the same as `write`, but for code that's running on a blocking thread.
+	pub fn write_blocking(&self, bytes: u64) {
+		std::thread::sleep(self.write.take(bytes));
+	}
+}
+
+async fn sleep(delay: Duration) {
+	if !delay.is_zero() {
+		tokio::time::sleep(delay).await;
+	}
+}
+
+/// RateLimiter is a token bucket that's allowed to go into debt, so a large read or write is let through straight away and the next one waits for it to be paid off.
+///
+/// Up to a second's worth of bytes are able to build up while it's idle.
+struct RateLimiter {
+	state: Mutex<RateLimiterState>,
+}
+
+struct RateLimiterState {
+	rate: Option<u64>,
+	available: f64,
+	last_refill: Instant,
+}
+
+impl RateLimiter {
+	fn new(rate: Option<u64>) -> Self {
+		Self {
+			state: Mutex::new(RateLimiterState {
+				rate,
+				available: rate.unwrap_or_default() as f64,
+				last_refill: Instant::now(),
+			}),
+		}
+	}
+
+	fn rate(&self) -> Option<u64> {
+		self.lock().rate
+	}
+
+	fn set_rate(&self, rate: Option<u64>) {
+		let mut state = self.lock();
+		state.rate = rate;
+		state.available = rate.unwrap_or_default() as f64;
+		state.last_refill = Instant::now();
+	}
+
+	/// take spends `bytes`, and returns how long the caller should wait before going ahead.
+	fn take(&self, bytes: u64) -> Duration {
+		let mut state = self.lock();
+
+		let rate = match state.rate {
+			Some(rate) if rate > 0 => rate as f64,
+			_ => return Duration::ZERO,
+		};
+
+		let now = Instant::now();
+		state.available = (state.available
+			+ now.duration_since(state.last_refill).as_secs_f64() * rate)
+			.min(rate);
+		state.last_refill = now;
+		state.available -= bytes as f64;
+
+		if state.available >= 0.0 {
+			Duration::ZERO
+		} else {
+			Duration::from_secs_f64(-state.available / rate)
+		}
+	}
+
+	fn lock(&self) -> std::sync::MutexGuard<'_, RateLimiterState> {
+		self.state
+			.lock()
+			.expect("critical error: rate limiter mutex poisoned")
+	}
+}
+
+/// ThrottledReader keeps reads from the inner reader within the node's read limit, for code that's running on a blocking thread.
+pub struct ThrottledReader<R> {
+	inner: R,
+	throttle: Arc<IoThrottle>,
+}
+
+impl<R: Read> ThrottledReader<R> {
+	pub fn new(inner: R, throttle: Arc<IoThrottle>) -> Self {
+		Self { inner, throttle }
+	}
+}
+
+impl<R: Read> Read for ThrottledReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		self.throttle.read_blocking(read as u64);
+		Ok(read)
+	}
+}
+
+/// ThrottledWriter keeps writes to the inner writer within the node's write limit, for code that's running on a blocking thread.
+pub struct ThrottledWriter<W> {
+	inner: W,
+	throttle: Arc<IoThrottle>,
+}
+
+impl<W: Write> ThrottledWriter<W> {
+	pub fn new(inner: W, throttle: Arc<IoThrottle>) -> Self {
+		Self { inner, throttle }
+	}
+
+	pub fn get_mut(&mut self) -> &mut W {
+		&mut self.inner
+	}
+}
+
+impl<W: Write> Write for ThrottledWriter<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let written = self.inner.write(buf)?;
+		self.throttle.write_blocking(written as u64);
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+/// ThrottledUpload keeps reads from the inner reader within the node's upload limit, for streams that are being uploaded to a remote location.
+///
+/// Each read is let through straight away, and the next one waits until it's been paid off.
+pub struct ThrottledUpload<R> {
+	inner: R,
+	throttle: Arc<IoThrottle>,
+	delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R: AsyncRead + Unpin> ThrottledUpload<R> {
+	pub fn new(inner: R, throttle: Arc<IoThrottle>) -> Self {
+		Self {
+			inner,
+			throttle,
+			delay: None,
+		}
+	}
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ThrottledUpload<R> {
+	fn poll_read(
+		self: Pin<&mut Self>,
+		cx: &mut Context<'_>,
+		buf: &mut ReadBuf<'_>,
+	) -> Poll<io::Result<()>> {
+		let this = self.get_mut();
+
+		if let Some(delay) = &mut this.delay {
+			ready!(delay.as_mut().poll(cx));
+			this.delay = None;
+		}
+
+		let filled = buf.filled().len();
+		ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+
+		let delay = this
+			.throttle
+			.upload
+			.take((buf.filled().len() - filled) as u64);
+		if !delay.is_zero() {
+			this.delay = Some(Box::pin(tokio::time::sleep(delay)));
+		}
+
+		Poll::Ready(Ok(()))
+	}
+}
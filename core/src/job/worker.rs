@@ -1,19 +1,29 @@
+use crate::api::CoreEvent;
 use crate::invalidate_query;
 use crate::job::{DynJob, JobError, JobManager, JobReportUpdate, JobStatus};
 use crate::library::LibraryContext;
-use std::{sync::Arc, time::Duration};
+use std::{collections::VecDeque, sync::Arc, time::Duration};
 use tokio::sync::oneshot;
 use tokio::{
 	sync::{
 		broadcast,
 		mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
-		Mutex,
+		watch, Mutex,
 	},
 	time::{interval_at, Instant},
 };
 use tracing::{error, info, warn};
 
-use super::{JobMetadata, JobReport};
+use super::{
+	log::write_job_logs, JobCheckpoint, JobLogEntry, JobLogLevel, JobMetadata, JobReport, JobState,
+	StatefulJob, TransferProgress,
+};
+
+/// Log lines are written to the database in batches of (at most) this many, and whenever the job checkpoints or stops.
+const LOG_BATCH_SIZE: usize = 100;
+
+/// How far back throughput is averaged over, so it follows changes in speed without jumping around between updates.
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(5);
 
 // used to update the worker state from inside the worker thread
 #[derive(Debug)]
@@ -22,9 +32,24 @@ pub enum WorkerEvent {
 		updates: Vec<JobReportUpdate>,
 		debounce: bool,
 	},
+	/// the job's full state and/or its latest checkpoint should be saved, and `done_tx` is sent to once they have been
+	Checkpointed {
+		state: Option<Vec<u8>>,
+		checkpoint: Option<Vec<u8>>,
+		done_tx: oneshot::Sender<()>,
+	},
+	Logged(JobLogEntry),
 	Completed(oneshot::Sender<()>, JobMetadata),
 	Failed(oneshot::Sender<()>),
 	Paused(Vec<u8>, oneshot::Sender<()>),
+	Canceled(oneshot::Sender<()>),
+}
+
+/// WorkerCommand is sent to a running job by the job manager (e.g. when the user pauses it).
+#[derive(Debug, Clone, Copy)]
+pub enum WorkerCommand {
+	Pause,
+	Cancel,
 }
 
 #[derive(Clone)]
@@ -32,6 +57,7 @@ pub struct WorkerContext {
 	pub library_ctx: LibraryContext,
 	events_tx: UnboundedSender<WorkerEvent>,
 	shutdown_tx: Arc<broadcast::Sender<()>>,
+	commands_rx: watch::Receiver<Option<WorkerCommand>>,
 }
 
 impl WorkerContext {
@@ -43,6 +69,17 @@ impl WorkerContext {
 			})
 			.expect("critical error: failed to send worker worker progress event updates");
 	}
+	/// log adds a line to the job's log, which the user is able to read back with `jobs.logs` (e.g. to find out why a file was skipped).
+	pub fn log(&self, level: JobLogLevel, message: impl Into<String>, path: Option<String>) {
+		self.events_tx
+			.send(WorkerEvent::Logged(JobLogEntry {
+				level,
+				message: message.into(),
+				path,
+			}))
+			.expect("critical error: failed to send worker log event");
+	}
+
 	pub fn progress_debounced(&self, updates: Vec<JobReportUpdate>) {
 		self.events_tx
 			.send(WorkerEvent::Progressed {
@@ -52,9 +89,55 @@ impl WorkerContext {
 			.expect("critical error: failed to send worker worker progress event updates");
 	}
 
+	/// checkpoint saves the job's progress (but not its steps), and waits until it's been written to the database.
+	///
+	/// Jobs should checkpoint before doing anything that can't be safely redone (or cleaned up) if they were interrupted, such as creating an output file.
+	pub async fn checkpoint<SJob: StatefulJob>(
+		&self,
+		state: &JobState<SJob>,
+	) -> Result<(), JobError> {
+		let checkpoint = rmp_serde::to_vec_named(&JobCheckpoint {
+			step_number: state.step_number,
+			data: state.data.as_ref(),
+		})?;
+
+		self.save(None, Some(checkpoint)).await
+	}
+
+	/// snapshot saves the job's full state, which replaces any checkpoint that was saved before it.
+	pub(super) async fn snapshot<SJob: StatefulJob>(
+		&self,
+		state: &JobState<SJob>,
+	) -> Result<(), JobError> {
+		self.save(Some(rmp_serde::to_vec_named(state)?), None).await
+	}
+
+	async fn save(
+		&self,
+		state: Option<Vec<u8>>,
+		checkpoint: Option<Vec<u8>>,
+	) -> Result<(), JobError> {
+		let (done_tx, done_rx) = oneshot::channel();
+
+		self.events_tx
+			.send(WorkerEvent::Checkpointed {
+				state,
+				checkpoint,
+				done_tx,
+			})
+			.expect("critical error: failed to send worker checkpoint event");
+
+		// the sender is dropped if the checkpoint couldn't be written
+		done_rx.await.map_err(|_| JobError::CheckpointFailed)
+	}
+
 	pub fn shutdown_rx(&self) -> broadcast::Receiver<()> {
 		self.shutdown_tx.subscribe()
 	}
+
+	pub fn commands_rx(&self) -> watch::Receiver<Option<WorkerCommand>> {
+		self.commands_rx.clone()
+	}
 }
 
 // a worker is a dedicated thread that runs a single job
@@ -64,23 +147,31 @@ pub struct Worker {
 	report: JobReport,
 	worker_events_tx: UnboundedSender<WorkerEvent>,
 	worker_events_rx: Option<UnboundedReceiver<WorkerEvent>>,
+	commands_tx: watch::Sender<Option<WorkerCommand>>,
 }
 
 impl Worker {
 	pub fn new(job: Box<dyn DynJob>, report: JobReport) -> Self {
 		let (worker_events_tx, worker_events_rx) = unbounded_channel();
+		let (commands_tx, _) = watch::channel(None);
 
 		Self {
 			job: Some(job),
 			report,
 			worker_events_tx,
 			worker_events_rx: Some(worker_events_rx),
+			commands_tx,
 		}
 	}
 
 	pub fn report(&self) -> JobReport {
 		self.report.clone()
 	}
+
+	/// command sends a command to the running job, which is handled once its current step reaches an `.await`.
+	pub fn command(&self, command: WorkerCommand) {
+		self.commands_tx.send_replace(Some(command));
+	}
 	// spawns a thread and extracts channel sender to communicate with it
 	pub async fn spawn(
 		job_manager: Arc<JobManager>,
@@ -103,14 +194,22 @@ impl Worker {
 		let job_hash = job.hash();
 		let job_id = worker.report.id;
 		let old_status = worker.report.status;
+		let commands_rx = worker.commands_tx.subscribe();
 
 		worker.report.status = JobStatus::Running;
 
 		if matches!(old_status, JobStatus::Queued) {
+			// the jobs queued after this one are saved alongside it, so they're still run if it's resumed after a restart
+			let next_jobs = job.queued()?.next_jobs;
+			if !next_jobs.is_empty() {
+				worker.report.next_jobs = Some(rmp_serde::to_vec_named(&next_jobs)?);
+			}
+
 			worker.report.create(&ctx).await?;
 		} else {
 			worker.report.update(&ctx).await?;
 		}
+		emit_report(&ctx, &worker.report);
 		drop(worker);
 
 		invalidate_query!(ctx, "jobs.isRunning");
@@ -129,6 +228,7 @@ impl Worker {
 				library_ctx,
 				events_tx: worker_events_tx,
 				shutdown_tx: job_manager.shutdown_tx(),
+				commands_rx,
 			};
 
 			// track time
@@ -156,6 +256,11 @@ impl Worker {
 
 			match job.run(worker_ctx.clone()).await {
 				Ok(metadata) => {
+					// jobs that depend on this one are queued before it completes, so they're able to start straight away
+					for next_job in job.next_jobs() {
+						job_manager.ingest_queue(next_job).await;
+					}
+
 					// handle completion
 					worker_ctx
 						.events_tx
@@ -168,8 +273,15 @@ impl Worker {
 						.send(WorkerEvent::Paused(state, done_tx))
 						.expect("critical error: failed to send worker pause event");
 				}
+				Err(JobError::Canceled) => {
+					worker_ctx
+						.events_tx
+						.send(WorkerEvent::Canceled(done_tx))
+						.expect("critical error: failed to send worker cancel event");
+				}
 				Err(e) => {
 					error!("job '{}' failed with error: {:#?}", job_id, e);
+					worker_ctx.log(JobLogLevel::Error, e.to_string(), None);
 					worker_ctx
 						.events_tx
 						.send(WorkerEvent::Failed(done_tx))
@@ -192,11 +304,28 @@ impl Worker {
 		library: LibraryContext,
 	) {
 		let mut last = Instant::now();
+		let mut tracker = TransferTracker::default();
+		let mut logs = Vec::new();
 
 		while let Some(command) = worker_events_rx.recv().await {
 			let mut worker = worker.lock().await;
 
+			// anything that's been logged is written before the job's report, so the log is complete once the job has stopped
+			if !matches!(
+				command,
+				WorkerEvent::Progressed { .. } | WorkerEvent::Logged(_)
+			) {
+				write_job_logs(&library, worker.report.id, std::mem::take(&mut logs)).await;
+			}
+
 			match command {
+				WorkerEvent::Logged(entry) => {
+					logs.push(entry);
+
+					if logs.len() >= LOG_BATCH_SIZE {
+						write_job_logs(&library, worker.report.id, std::mem::take(&mut logs)).await;
+					}
+				}
 				WorkerEvent::Progressed { updates, debounce } => {
 					if debounce {
 						let current = Instant::now();
@@ -224,22 +353,69 @@ impl Worker {
 							JobReportUpdate::SecondsElapsed(seconds) => {
 								worker.report.seconds_elapsed += seconds as i32;
 							}
+							JobReportUpdate::TotalBytes(total_bytes) => {
+								transfer(&mut worker.report).total_bytes = total_bytes;
+							}
+							JobReportUpdate::CurrentFile { name, size } => {
+								tracker.file_size = size;
+
+								let transfer = transfer(&mut worker.report);
+								transfer.current_file = Some(name);
+								transfer.current_file_percentage = 0;
+							}
+							JobReportUpdate::BytesProcessed { total, file } => {
+								let bytes_per_second = tracker.record(total);
+								let percentage = tracker.file_percentage(file);
+
+								worker.report.bytes_processed = total;
+
+								let transfer = transfer(&mut worker.report);
+								transfer.bytes_processed = total;
+								transfer.current_file_percentage = percentage;
+								transfer.bytes_per_second = bytes_per_second;
+								transfer.eta_seconds = (bytes_per_second > 0).then(|| {
+									transfer.total_bytes.saturating_sub(total) / bytes_per_second
+								});
+							}
 						}
 					}
 
 					invalidate_query!(library, "jobs.getRunning");
+					emit_report(&library, &worker.report);
+				}
+				WorkerEvent::Checkpointed {
+					state,
+					checkpoint,
+					done_tx,
+				} => {
+					if let Some(state) = state {
+						worker.report.data = Some(state);
+					}
+					worker.report.checkpoint = checkpoint;
+
+					match worker.report.update(&library).await {
+						Ok(()) => {
+							// the job may have already been dropped, if it was paused while waiting
+							done_tx.send(()).ok();
+						}
+						Err(e) => error!("failed to save job checkpoint: {:#?}", e),
+					}
 				}
 				WorkerEvent::Completed(done_tx, metadata) => {
 					worker.report.status = JobStatus::Completed;
 					worker.report.data = None;
+					worker.report.checkpoint = None;
+					worker.report.next_jobs = None;
 					worker.report.metadata = metadata;
 					if let Err(e) = worker.report.update(&library).await {
 						error!("failed to update job report: {:#?}", e);
 					}
+					emit_report(&library, &worker.report);
 
 					invalidate_query!(library, "jobs.isRunning");
 					invalidate_query!(library, "jobs.getRunning");
 					invalidate_query!(library, "jobs.getHistory");
+					invalidate_query!(library, "jobs.history");
 
 					info!("{}", worker.report);
 
@@ -252,9 +428,12 @@ impl Worker {
 				WorkerEvent::Failed(done_tx) => {
 					worker.report.status = JobStatus::Failed;
 					worker.report.data = None;
+					worker.report.checkpoint = None;
+					worker.report.next_jobs = None;
 					if let Err(e) = worker.report.update(&library).await {
 						error!("failed to update job report: {:#?}", e);
 					}
+					emit_report(&library, &worker.report);
 
 					invalidate_query!(library, "library.list");
 
@@ -269,13 +448,39 @@ impl Worker {
 				WorkerEvent::Paused(state, done_tx) => {
 					worker.report.status = JobStatus::Paused;
 					worker.report.data = Some(state);
+					worker.report.checkpoint = None;
+					if let Err(e) = worker.report.update(&library).await {
+						error!("failed to update job report: {:#?}", e);
+					}
+					emit_report(&library, &worker.report);
+
+					info!("{}", worker.report);
+
+					invalidate_query!(library, "jobs.getHistory");
+					invalidate_query!(library, "jobs.history");
+
+					done_tx
+						.send(())
+						.expect("critical error: failed to send worker completion");
+
+					break;
+				}
+				WorkerEvent::Canceled(done_tx) => {
+					worker.report.status = JobStatus::Canceled;
+					worker.report.data = None;
+					worker.report.checkpoint = None;
+					worker.report.next_jobs = None;
 					if let Err(e) = worker.report.update(&library).await {
 						error!("failed to update job report: {:#?}", e);
 					}
+					emit_report(&library, &worker.report);
 
 					info!("{}", worker.report);
 
+					invalidate_query!(library, "jobs.isRunning");
+					invalidate_query!(library, "jobs.getRunning");
 					invalidate_query!(library, "jobs.getHistory");
+					invalidate_query!(library, "jobs.history");
 
 					done_tx
 						.send(())
@@ -287,3 +492,65 @@ impl Worker {
 		}
 	}
 }
+
+/// This sends a job's report to the clients that are subscribed to `jobs.events`.
+///
+/// The job's state, checkpoint and queued jobs aren't included, as they can be large and are no use to clients.
+fn emit_report(library: &LibraryContext, report: &JobReport) {
+	library.emit(CoreEvent::JobUpdated {
+		library_id: library.id,
+		report: JobReport {
+			data: None,
+			checkpoint: None,
+			next_jobs: None,
+			..report.clone()
+		},
+	});
+}
+
+fn transfer(report: &mut JobReport) -> &mut TransferProgress {
+	report.transfer.get_or_insert_with(Default::default)
+}
+
+/// TransferTracker keeps the recent byte counts of a running job, which its throughput is worked out from.
+#[derive(Default)]
+struct TransferTracker {
+	samples: VecDeque<(Instant, u64)>,
+	file_size: u64,
+}
+
+impl TransferTracker {
+	/// record adds the job's running total of bytes processed, and returns its throughput in bytes per second.
+	fn record(&mut self, bytes: u64) -> u64 {
+		let now = Instant::now();
+
+		// the total going backwards means part of the work is being redone, so the older samples no longer apply
+		if matches!(self.samples.back(), Some((_, last)) if *last > bytes) {
+			self.samples.clear();
+		}
+
+		self.samples.push_back((now, bytes));
+
+		// the oldest sample is kept once it falls outside of the window, so the window is always covered
+		while self.samples.len() > 2 && now.duration_since(self.samples[1].0) >= THROUGHPUT_WINDOW {
+			self.samples.pop_front();
+		}
+
+		let (start, start_bytes) = self.samples[0];
+		let elapsed = now.duration_since(start).as_secs_f64();
+
+		if elapsed > 0.0 {
+			((bytes - start_bytes) as f64 / elapsed) as u64
+		} else {
+			0
+		}
+	}
+
+	fn file_percentage(&self, bytes: u64) -> u8 {
+		if self.file_size == 0 {
+			return 100;
+		}
+
+		(bytes.min(self.file_size) * 100 / self.file_size) as u8
+	}
+}
@@ -1,8 +1,16 @@
 use api::{CoreEvent, Ctx, Router};
-use job::JobManager;
+use job::{IoThrottle, JobManager};
 use library::LibraryManager;
 use location::{LocationManager, LocationManagerError};
-use node::NodeConfigManager;
+use node::{KeyCapability, NodeConfigManager};
+use object::{
+	fs::{
+		stream::{EncryptedStreamError, EncryptedStreams},
+		temp::TempFileManager,
+	},
+	preview::{read_encrypted_thumbnail, ThumbnailCache, ENCRYPTED_THUMBNAIL_EXTENSION},
+};
+use p2p::P2PManager;
 
 use std::{path::Path, sync::Arc};
 use thiserror::Error;
@@ -13,6 +21,9 @@ use tokio::{
 };
 use tracing::{error, info};
 use tracing_subscriber::{prelude::*, EnvFilter};
+use uuid::Uuid;
+
+pub use library::SystemEvent;
 
 pub mod api;
 pub(crate) mod job;
@@ -20,6 +31,7 @@ pub(crate) mod library;
 pub(crate) mod location;
 pub(crate) mod node;
 pub(crate) mod object;
+pub(crate) mod p2p;
 pub(crate) mod util;
 pub(crate) mod volume;
 
@@ -30,6 +42,10 @@ pub struct NodeContext {
 	pub config: Arc<NodeConfigManager>,
 	pub jobs: Arc<JobManager>,
 	pub location_manager: Arc<LocationManager>,
+	pub temp_files: Arc<TempFileManager>,
+	pub encrypted_streams: Arc<EncryptedStreams>,
+	pub io_throttle: Arc<IoThrottle>,
+	pub thumbnail_cache: Arc<ThumbnailCache>,
 	pub event_bus_tx: broadcast::Sender<CoreEvent>,
 }
 
@@ -37,6 +53,9 @@ pub struct Node {
 	config: Arc<NodeConfigManager>,
 	library_manager: Arc<LibraryManager>,
 	jobs: Arc<JobManager>,
+	p2p: Arc<P2PManager>,
+	temp_files: Arc<TempFileManager>,
+	encrypted_streams: Arc<EncryptedStreams>,
 	event_bus: (broadcast::Sender<CoreEvent>, broadcast::Receiver<CoreEvent>),
 }
 
@@ -96,7 +115,8 @@ impl Node {
 			    // ),
 		);
 		#[cfg(not(feature = "android"))]
-		let subscriber = subscriber.with(tracing_subscriber::fmt::layer().with_filter(CONSOLE_LOG_FILTER));
+		let subscriber =
+			subscriber.with(tracing_subscriber::fmt::layer().with_filter(CONSOLE_LOG_FILTER));
 		#[cfg(feature = "android")]
 		let subscriber = subscriber.with(tracing_android::layer("com.spacedrive.app").unwrap()); // TODO: This is not working
 		subscriber
@@ -111,25 +131,33 @@ impl Node {
 		let event_bus = broadcast::channel(1024);
 		let config = NodeConfigManager::new(data_dir.to_path_buf()).await?;
 
-		let jobs = JobManager::new();
+		let jobs = JobManager::new(config.get().await.job_concurrency);
+		let io_throttle = IoThrottle::new(config.get().await.io_limits);
 		let location_manager = LocationManager::new();
+		let temp_files = TempFileManager::new(&data_dir);
+		let encrypted_streams = EncryptedStreams::new();
+		let thumbnail_cache = ThumbnailCache::new(config.get().await.thumbnail_cache_size);
 		let library_manager = LibraryManager::new(
 			data_dir.join("libraries"),
 			NodeContext {
 				config: Arc::clone(&config),
 				jobs: Arc::clone(&jobs),
 				location_manager: Arc::clone(&location_manager),
+				temp_files: Arc::clone(&temp_files),
+				encrypted_streams: Arc::clone(&encrypted_streams),
+				io_throttle,
+				thumbnail_cache,
 				event_bus_tx: event_bus.0.clone(),
 			},
 		)
 		.await?;
 
-		// Adding already existing locations for location management
+		// Adding already existing locations for location management (remote locations aren't on the disk, so they aren't watched)
 		for library_ctx in library_manager.get_all_libraries_ctx().await {
 			for location in library_ctx
 				.db
 				.location()
-				.find_many(vec![])
+				.find_many(vec![prisma::location::remote_config::equals(None)])
 				.exec()
 				.await
 				.unwrap_or_else(|e| {
@@ -156,11 +184,24 @@ impl Node {
 			}
 		});
 
+		job::spawn_scheduler(Arc::clone(&library_manager));
+
+		let p2p = P2PManager::new(
+			&config,
+			Arc::clone(&library_manager),
+			Arc::clone(&jobs),
+			event_bus.0.clone(),
+		)
+		.await;
+
 		let router = api::mount();
 		let node = Node {
 			config,
 			library_manager,
 			jobs,
+			p2p,
+			temp_files,
+			encrypted_streams,
 			event_bus,
 		};
 
@@ -173,13 +214,27 @@ impl Node {
 			library_manager: Arc::clone(&self.library_manager),
 			config: Arc::clone(&self.config),
 			jobs: Arc::clone(&self.jobs),
+			p2p: Arc::clone(&self.p2p),
 			event_bus: self.event_bus.0.clone(),
+			remote: false,
+			token: None,
+		}
+	}
+
+	/// get_remote_request_context should be used for requests coming from outside of the local device (e.g. the HTTP server), so access to the key manager requires an API token.
+	pub fn get_remote_request_context(&self) -> Ctx {
+		Ctx {
+			remote: true,
+			..self.get_request_context()
 		}
 	}
 
 	// Note: this system doesn't use chunked encoding which could prove a problem with large files but I can't see an easy way to do chunked encoding with Tauri custom URIs.
+	///
+	/// `ctx` should come from `get_request_context()` or `get_remote_request_context()`, as encrypted thumbnails are only decrypted for requests that are allowed to reveal them.
 	pub async fn handle_custom_uri(
 		&self,
+		ctx: &Ctx,
 		path: Vec<&str>,
 	) -> (
 		u16,     /* Status Code */
@@ -210,7 +265,27 @@ impl Node {
 						file.read_to_end(&mut buf).await.unwrap();
 						(200, "image/webp", buf)
 					}
-					Err(_) => (404, "text/html", b"File Not Found".to_vec()),
+					// thumbnails of encrypted files are only decrypted on demand, while their key is mounted
+					Err(_) => {
+						let encrypted_filename =
+							filename.with_extension(ENCRYPTED_THUMBNAIL_EXTENSION);
+
+						if fs::metadata(&encrypted_filename).await.is_err() {
+							return (404, "text/html", b"File Not Found".to_vec());
+						}
+
+						// they're plaintext once they've been decrypted, so they're treated the same as `files.streamEncrypted`
+						if ctx.authorize(KeyCapability::Reveal).await.is_err() {
+							return (403, "text/html", b"Forbidden".to_vec());
+						}
+
+						match read_encrypted_thumbnail(&self.library_manager, &encrypted_filename)
+							.await
+						{
+							Ok(media) => (200, "image/webp", media.expose().clone()),
+							Err(_) => (404, "text/html", b"File Not Found".to_vec()),
+						}
+					}
 				}
 			}
 			_ => (
@@ -221,9 +296,78 @@ impl Node {
 		}
 	}
 
+	/// handle_encrypted_stream serves a decrypted range of an encrypted file (at `spacedrive://encrypted/<id>`), which has been opened with `files.streamEncrypted`.
+	///
+	/// `range` is the request's `Range` header, if it has one. Responses are always `206 Partial Content`, so media players know they're able to seek.
+	pub async fn handle_encrypted_stream(
+		&self,
+		id: &str,
+		range: Option<&str>,
+	) -> (
+		u16,                         /* Status Code */
+		&'static str,                /* Content-Type */
+		Vec<(&'static str, String)>, /* Headers */
+		Vec<u8>,                     /* Body */
+	) {
+		let id = match Uuid::parse_str(id) {
+			Ok(id) => id,
+			Err(_) => {
+				return (
+					400,
+					"text/html",
+					vec![],
+					b"Bad Request: Invalid stream ID".to_vec(),
+				)
+			}
+		};
+
+		match self
+			.encrypted_streams
+			.read(&self.library_manager, id, range)
+			.await
+		{
+			Ok(range) => (
+				206,
+				range.content_type,
+				vec![
+					("Accept-Ranges", "bytes".to_string()),
+					(
+						"Content-Range",
+						format!("bytes {}-{}/{}", range.start, range.end, range.total_size),
+					),
+				],
+				range.body,
+			),
+			Err(EncryptedStreamError::RangeNotSatisfiable(total_size)) => (
+				416,
+				"text/html",
+				vec![("Content-Range", format!("bytes */{total_size}"))],
+				b"Range Not Satisfiable".to_vec(),
+			),
+			Err(EncryptedStreamError::StreamNotFound(_)) => {
+				(404, "text/html", vec![], b"Stream Not Found".to_vec())
+			}
+			Err(e) => {
+				error!("Failed to read encrypted stream: {:#?}", e);
+				(500, "text/html", vec![], b"Internal Server Error".to_vec())
+			}
+		}
+	}
+
+	/// system_event should be called by the host application when an OS-level event occurs (e.g. the screensaver starting).
+	pub async fn system_event(&self, event: SystemEvent) {
+		self.library_manager.handle_system_event(event).await;
+	}
+
 	pub async fn shutdown(&self) {
 		info!("Spacedrive shutting down...");
 		self.jobs.pause().await;
+
+		let temp_files = Arc::clone(&self.temp_files);
+		if let Err(e) = tokio::task::spawn_blocking(move || temp_files.shred_all()).await {
+			error!("Failed to erase decrypted files: {:#?}", e);
+		}
+
 		info!("Spacedrive Core shutdown successful!");
 	}
 }
@@ -0,0 +1,206 @@
+use std::{
+	sync::Arc,
+	time::{Duration, SystemTime},
+};
+
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::RwLock, time::interval};
+use tracing::{debug, error};
+
+use crate::{api::CoreEvent, invalidate_query};
+
+use super::{record_key_audit_event, KeyAuditAction, LibraryContext};
+
+/// How often the auto-lock task checks whether the key manager should be locked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// If the wall clock moves forward by more than this between two checks, we assume the system was asleep.
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(60);
+
+/// The default amount of seconds before an idle lock that clients are warned.
+const DEFAULT_WARNING_SECONDS: u32 = 60;
+
+/// AutoLockConfig holds the auto-lock settings for a library. This is stored within the library's config.
+#[derive(Debug, Serialize, Deserialize, Clone, Type)]
+pub struct AutoLockConfig {
+	/// minutes of key inactivity before the library is locked. `None` disables idle locking.
+	pub minutes: Option<u32>,
+	/// warning_seconds is how long before an idle lock that a `KeyManagerLockingSoon` event is emitted. `0` disables the warning.
+	#[serde(default = "default_warning_seconds")]
+	pub warning_seconds: u32,
+	/// lock_on_sleep locks the library once the system resumes from sleep.
+	pub lock_on_sleep: bool,
+	/// lock_on_screensaver locks the library when the host application reports that the screensaver started.
+	pub lock_on_screensaver: bool,
+}
+
+impl Default for AutoLockConfig {
+	fn default() -> Self {
+		Self {
+			minutes: None,
+			warning_seconds: DEFAULT_WARNING_SECONDS,
+			lock_on_sleep: false,
+			lock_on_screensaver: false,
+		}
+	}
+}
+
+fn default_warning_seconds() -> u32 {
+	DEFAULT_WARNING_SECONDS
+}
+
+/// SystemEvent represents an OS-level event that is reported to the core by the host application.
+#[derive(Debug, Clone, Copy)]
+pub enum SystemEvent {
+	Sleep,
+	Screensaver,
+}
+
+/// AutoLock tracks a library's auto-lock settings, and locks the key manager when they're triggered.
+pub struct AutoLock {
+	config: RwLock<AutoLockConfig>,
+}
+
+impl AutoLock {
+	pub fn new(config: AutoLockConfig) -> Arc<Self> {
+		Arc::new(Self {
+			config: RwLock::new(config),
+		})
+	}
+
+	pub async fn config(&self) -> AutoLockConfig {
+		self.config.read().await.clone()
+	}
+
+	pub async fn set_config(&self, config: AutoLockConfig) {
+		*self.config.write().await = config;
+	}
+
+	/// handle_system_event locks the library if it's configured to do so for the given event.
+	pub async fn handle_system_event(&self, library: &LibraryContext, event: SystemEvent) {
+		let config = self.config().await;
+
+		let should_lock = match event {
+			SystemEvent::Sleep => config.lock_on_sleep,
+			SystemEvent::Screensaver => config.lock_on_screensaver,
+		};
+
+		if should_lock {
+			debug!("Locking library {} due to {:?}", library.id, event);
+			lock_key_manager(library).await;
+		}
+	}
+
+	/// spawn starts the background task which locks the key manager after the configured period of inactivity.
+	pub(crate) fn spawn(self: &Arc<Self>, library: LibraryContext) {
+		let this = Arc::clone(self);
+
+		tokio::spawn(async move {
+			let mut interval = interval(CHECK_INTERVAL);
+			let mut last_check = SystemTime::now();
+			// this is reset once there's activity, so the warning is only emitted once per idle period
+			let mut warned = false;
+
+			loop {
+				interval.tick().await;
+
+				// the monotonic clock that drives `interval` doesn't advance while the system is suspended, but the wall clock does
+				let now = SystemTime::now();
+				let slept = now
+					.duration_since(last_check)
+					.map(|elapsed| elapsed > CHECK_INTERVAL + SLEEP_THRESHOLD)
+					.unwrap_or(false);
+				last_check = now;
+
+				if !library.key_manager.has_master_password().unwrap_or(false) {
+					warned = false;
+					continue;
+				}
+
+				if slept {
+					this.handle_system_event(&library, SystemEvent::Sleep).await;
+					continue;
+				}
+
+				let config = this.config().await;
+
+				let minutes = match config.minutes {
+					Some(minutes) => minutes,
+					None => continue,
+				};
+
+				// mounted keys report the same TTL (see `keys.listMounted`), so the warning lines up with what clients show
+				let timeout = Duration::from_secs(u64::from(minutes) * 60);
+
+				match library.key_manager.idle_time() {
+					Ok(idle_time) if idle_time >= timeout => {
+						debug!(
+							"Locking library {} after {:?} of inactivity",
+							library.id, idle_time
+						);
+						lock_key_manager(&library).await;
+						warned = false;
+					}
+					Ok(idle_time) => {
+						let remaining = timeout - idle_time;
+
+						if remaining <= Duration::from_secs(u64::from(config.warning_seconds)) {
+							if !warned {
+								debug!(
+									"Library {} will lock in {:?} due to inactivity",
+									library.id, remaining
+								);
+
+								library.emit(CoreEvent::KeyManagerLockingSoon {
+									library_id: library.id,
+									seconds_remaining: remaining.as_secs(),
+								});
+
+								warned = true;
+							}
+						} else {
+							warned = false;
+						}
+
+						// the lock shouldn't be late by up to a whole interval, so wait for exactly the remaining time if it's shorter
+						if remaining < CHECK_INTERVAL {
+							tokio::time::sleep(remaining).await;
+						}
+					}
+					Err(e) => error!("Failed to get key manager idle time: {:#?}", e),
+				}
+			}
+		});
+	}
+}
+
+/// lock_key_manager unmounts all keys and clears the root key, and lets every client know that the library was locked.
+pub(crate) async fn lock_key_manager(library: &LibraryContext) {
+	library.key_manager.empty_keymount();
+	library.key_consumers.revoke_all();
+
+	// copies are erased by their key being revoked, but this makes sure nothing decrypted from this library outlives the lock
+	let temp_files = Arc::clone(library.temp_files());
+	let library_id = library.id;
+	if let Err(e) = tokio::task::spawn_blocking(move || temp_files.shred_library(library_id)).await
+	{
+		error!("Failed to erase decrypted files: {:#?}", e);
+	}
+	library.thumbnail_cache().purge_library(library.id);
+
+	if let Err(e) = library.key_manager.clear_root_key() {
+		error!("Failed to clear the root key: {:#?}", e);
+		return;
+	}
+
+	record_key_audit_event(library, KeyAuditAction::Lock, None).await;
+
+	library.emit(CoreEvent::KeyManagerLockStateChanged {
+		library_id: library.id,
+		locked: true,
+	});
+
+	invalidate_query!(library, "keys.hasMasterPassword");
+	invalidate_query!(library, "keys.listMounted");
+}
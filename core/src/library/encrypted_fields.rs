@@ -0,0 +1,368 @@
+use crate::prisma::{location, media_data, object};
+
+use prisma_client_rust::QueryError;
+use rspc::Type;
+use sd_crypto::{
+	crypto::stream::{Algorithm, StreamDecryption, StreamEncryption},
+	primitives::{generate_nonce, KEY_LEN, METADATA_CONTEXT},
+	Protected,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+use tracing::warn;
+
+use super::LibraryContext;
+
+const FIELD_ALGORITHM: Algorithm = Algorithm::XChaCha20Poly1305;
+
+/// EncryptedField is a piece of object metadata that can be encrypted within the library database.
+///
+/// Encrypted fields are decrypted transparently when they're read, as long as the key manager is unlocked.
+/// While it's locked, they're returned as `null` (alongside their encrypted value, so clients know they're hidden).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Type, PartialEq, Eq)]
+pub enum EncryptedField {
+	/// the object's note
+	Note,
+	/// the GPS coordinates within an object's media data (e.g. from EXIF)
+	GpsLocation,
+}
+
+#[derive(Error, Debug)]
+pub enum EncryptedFieldError {
+	#[error("Database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("Crypto error: {0}")]
+	Crypto(#[from] sd_crypto::Error),
+	#[error("Failed to (de)serialize an encrypted field: {0}")]
+	Serialization(#[from] serde_json::Error),
+}
+
+impl From<EncryptedFieldError> for rspc::Error {
+	fn from(err: EncryptedFieldError) -> Self {
+		match err {
+			// the key manager needs to be unlocked before encrypted fields can be written
+			EncryptedFieldError::Crypto(sd_crypto::Error::NoMasterPassword) => {
+				rspc::Error::with_cause(rspc::ErrorCode::Forbidden, err.to_string(), err)
+			}
+			_ => {
+				rspc::Error::with_cause(rspc::ErrorCode::InternalServerError, err.to_string(), err)
+			}
+		}
+	}
+}
+
+impl LibraryContext {
+	/// is_field_encrypted returns whether the given field should be encrypted within this library.
+	pub(crate) fn is_field_encrypted(&self, field: EncryptedField) -> bool {
+		self.config.encrypted_fields.contains(&field)
+	}
+
+	/// metadata_key derives the library's metadata subkey, which is only available while the key manager is unlocked.
+	fn metadata_key(&self) -> Result<Protected<[u8; KEY_LEN]>, sd_crypto::Error> {
+		self.key_manager.derive_subkey(METADATA_CONTEXT)
+	}
+}
+
+fn encrypt_value(
+	key: Protected<[u8; KEY_LEN]>,
+	value: &impl Serialize,
+) -> Result<Vec<u8>, EncryptedFieldError> {
+	let nonce = generate_nonce(FIELD_ALGORITHM);
+	let encrypted = StreamEncryption::encrypt_bytes(
+		key,
+		&nonce,
+		FIELD_ALGORITHM,
+		&serde_json::to_vec(value)?,
+		&[],
+	)?;
+
+	Ok([nonce, encrypted].concat())
+}
+
+fn decrypt_value<T: for<'de> Deserialize<'de>>(
+	key: Protected<[u8; KEY_LEN]>,
+	bytes: &[u8],
+) -> Result<T, EncryptedFieldError> {
+	let nonce_len = FIELD_ALGORITHM.nonce_len();
+
+	if bytes.len() <= nonce_len {
+		return Err(sd_crypto::Error::Decrypt.into());
+	}
+
+	let (nonce, encrypted) = bytes.split_at(nonce_len);
+	let value = StreamDecryption::decrypt_bytes(key, nonce, FIELD_ALGORITHM, encrypted, &[])?;
+
+	Ok(serde_json::from_slice(value.expose())?)
+}
+
+/// This sets an object's note, encrypting it if note encryption is enabled.
+pub(crate) async fn set_note(
+	library: &LibraryContext,
+	object_id: i32,
+	note: Option<String>,
+) -> Result<(), EncryptedFieldError> {
+	let params = match note {
+		Some(note) if library.is_field_encrypted(EncryptedField::Note) => vec![
+			object::note::set(None),
+			object::encrypted_note::set(Some(encrypt_value(library.metadata_key()?, &note)?)),
+		],
+		note => vec![object::note::set(note), object::encrypted_note::set(None)],
+	};
+
+	library
+		.db
+		.object()
+		.update(object::id::equals(object_id), params)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// This returns the params that set the GPS coordinates of some media data, encrypting them if location encryption is enabled.
+pub(crate) fn gps_location_params(
+	library: &LibraryContext,
+	longitude: Option<f64>,
+	latitude: Option<f64>,
+) -> Result<Vec<media_data::SetParam>, EncryptedFieldError> {
+	Ok(
+		if (longitude.is_some() || latitude.is_some())
+			&& library.is_field_encrypted(EncryptedField::GpsLocation)
+		{
+			vec![
+				media_data::longitude::set(None),
+				media_data::latitude::set(None),
+				media_data::encrypted_location::set(Some(encrypt_value(
+					library.metadata_key()?,
+					&(longitude, latitude),
+				)?)),
+			]
+		} else {
+			vec![
+				media_data::longitude::set(longitude),
+				media_data::latitude::set(latitude),
+				media_data::encrypted_location::set(None),
+			]
+		},
+	)
+}
+
+/// This decrypts an object's encrypted note, and returns `None` if the key manager is locked.
+pub(crate) fn decrypt_note(library: &LibraryContext, encrypted_note: &[u8]) -> Option<String> {
+	let key = library.metadata_key().ok()?;
+
+	decrypt_value(key, encrypted_note)
+		.map_err(|e| warn!("Failed to decrypt an object's note: {:#?}", e))
+		.ok()
+}
+
+/// This decrypts the encrypted GPS coordinates of some media data (as longitude and latitude), and returns `None` if the key manager is locked.
+pub(crate) fn decrypt_gps_location(
+	library: &LibraryContext,
+	encrypted_location: &[u8],
+) -> Option<(Option<f64>, Option<f64>)> {
+	let key = library.metadata_key().ok()?;
+
+	decrypt_value(key, encrypted_location)
+		.map_err(|e| warn!("Failed to decrypt a GPS location: {:#?}", e))
+		.ok()
+}
+
+/// This encrypts a remote location's credentials, so they can be stored within the library database.
+///
+/// Unlike the other fields, credentials are always encrypted, so the key manager needs to be unlocked.
+pub(crate) fn encrypt_credentials(
+	library: &LibraryContext,
+	credentials: &impl Serialize,
+) -> Result<Vec<u8>, EncryptedFieldError> {
+	encrypt_value(library.metadata_key()?, credentials)
+}
+
+/// This decrypts a remote location's credentials, which needs the key manager to be unlocked.
+pub(crate) fn decrypt_credentials<T: DeserializeOwned>(
+	library: &LibraryContext,
+	encrypted_credentials: &[u8],
+) -> Result<T, EncryptedFieldError> {
+	decrypt_value(library.metadata_key()?, encrypted_credentials)
+}
+
+/// This encrypts or decrypts every existing value of a field, e.g. after it has been enabled or disabled.
+///
+/// The key manager needs to be unlocked.
+pub(crate) async fn apply_field_encryption(
+	library: &LibraryContext,
+	field: EncryptedField,
+	encrypt: bool,
+) -> Result<(), EncryptedFieldError> {
+	let key = library.metadata_key()?;
+
+	match field {
+		EncryptedField::Note => {
+			let objects = library
+				.db
+				.object()
+				.find_many(if encrypt {
+					vec![object::note::not(None)]
+				} else {
+					vec![object::encrypted_note::not(None)]
+				})
+				.exec()
+				.await?;
+
+			let updates = objects
+				.into_iter()
+				.map(|object| {
+					let params = if encrypt {
+						vec![
+							object::note::set(None),
+							object::encrypted_note::set(Some(encrypt_value(
+								key.clone(),
+								&object.note,
+							)?)),
+						]
+					} else {
+						vec![
+							object::note::set(decrypt_value(
+								key.clone(),
+								object.encrypted_note.as_deref().unwrap_or_default(),
+							)?),
+							object::encrypted_note::set(None),
+						]
+					};
+
+					Ok(library
+						.db
+						.object()
+						.update(object::id::equals(object.id), params))
+				})
+				.collect::<Result<Vec<_>, EncryptedFieldError>>()?;
+
+			library.db._batch(updates).await?;
+		}
+		EncryptedField::GpsLocation => {
+			let media_data = library
+				.db
+				.media_data()
+				.find_many(if encrypt {
+					vec![prisma_client_rust::operator::or(vec![
+						media_data::longitude::not(None),
+						media_data::latitude::not(None),
+					])]
+				} else {
+					vec![media_data::encrypted_location::not(None)]
+				})
+				.exec()
+				.await?;
+
+			let updates = media_data
+				.into_iter()
+				.map(|media_data| {
+					let params = if encrypt {
+						vec![
+							media_data::longitude::set(None),
+							media_data::latitude::set(None),
+							media_data::encrypted_location::set(Some(encrypt_value(
+								key.clone(),
+								&(media_data.longitude, media_data.latitude),
+							)?)),
+						]
+					} else {
+						let (longitude, latitude): (Option<f64>, Option<f64>) = decrypt_value(
+							key.clone(),
+							media_data.encrypted_location.as_deref().unwrap_or_default(),
+						)?;
+
+						vec![
+							media_data::longitude::set(longitude),
+							media_data::latitude::set(latitude),
+							media_data::encrypted_location::set(None),
+						]
+					};
+
+					Ok(library
+						.db
+						.media_data()
+						.update(media_data::id::equals(media_data.id), params))
+				})
+				.collect::<Result<Vec<_>, EncryptedFieldError>>()?;
+
+			library.db._batch(updates).await?;
+		}
+	}
+
+	Ok(())
+}
+
+/// These are the re-encrypted values of every encrypted field (by row ID), which need to be written alongside the re-wrapped keys.
+pub(crate) struct ReencryptedFields {
+	pub notes: Vec<(i32, Vec<u8>)>,
+	pub locations: Vec<(i32, Vec<u8>)>,
+	pub credentials: Vec<(i32, Vec<u8>)>,
+}
+
+/// This re-encrypts every encrypted field with a new metadata key, which is needed whenever the root key changes.
+///
+/// Nothing is written here, so the new values can be written within the same batch as the root key change.
+pub(crate) async fn reencrypt_fields(
+	library: &LibraryContext,
+	old_key: Protected<[u8; KEY_LEN]>,
+	new_key: Protected<[u8; KEY_LEN]>,
+) -> Result<ReencryptedFields, EncryptedFieldError> {
+	let notes = library
+		.db
+		.object()
+		.find_many(vec![object::encrypted_note::not(None)])
+		.exec()
+		.await?
+		.into_iter()
+		.map(|object| {
+			let note: String = decrypt_value(
+				old_key.clone(),
+				object.encrypted_note.as_deref().unwrap_or_default(),
+			)?;
+
+			Ok((object.id, encrypt_value(new_key.clone(), &note)?))
+		})
+		.collect::<Result<Vec<_>, EncryptedFieldError>>()?;
+
+	let locations = library
+		.db
+		.media_data()
+		.find_many(vec![media_data::encrypted_location::not(None)])
+		.exec()
+		.await?
+		.into_iter()
+		.map(|media_data| {
+			let location: (Option<f64>, Option<f64>) = decrypt_value(
+				old_key.clone(),
+				media_data.encrypted_location.as_deref().unwrap_or_default(),
+			)?;
+
+			Ok((media_data.id, encrypt_value(new_key.clone(), &location)?))
+		})
+		.collect::<Result<Vec<_>, EncryptedFieldError>>()?;
+
+	// credentials are re-encrypted as they are, so this doesn't need to know which kind of remote they're for
+	let credentials = library
+		.db
+		.location()
+		.find_many(vec![location::remote_credentials::not(None)])
+		.exec()
+		.await?
+		.into_iter()
+		.map(|location| {
+			let credentials: serde_json::Value = decrypt_value(
+				old_key.clone(),
+				location.remote_credentials.as_deref().unwrap_or_default(),
+			)?;
+
+			Ok((location.id, encrypt_value(new_key.clone(), &credentials)?))
+		})
+		.collect::<Result<Vec<_>, EncryptedFieldError>>()?;
+
+	Ok(ReencryptedFields {
+		notes,
+		locations,
+		credentials,
+	})
+}
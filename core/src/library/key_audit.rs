@@ -0,0 +1,62 @@
+use int_enum::IntEnum;
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{invalidate_query, prisma::node};
+
+use super::LibraryContext;
+
+/// KeyAuditAction is a security-relevant action that was performed on the key manager, and is recorded within the key audit log.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, Eq, PartialEq, IntEnum)]
+pub enum KeyAuditAction {
+	Unknown = 0,
+	/// the library was unlocked with the master password
+	Unlock = 1,
+	/// the library was locked, either manually or by auto-lock
+	Lock = 2,
+	Mount = 3,
+	Unmount = 4,
+	/// a key's plaintext value was retrieved
+	Reveal = 5,
+	Add = 6,
+	Delete = 7,
+	Export = 8,
+	Backup = 9,
+	Restore = 10,
+	MasterPasswordChange = 11,
+	SecretKeyRotation = 12,
+	TotpEnable = 13,
+	TotpDisable = 14,
+	/// a TOTP recovery code was used (or the recovery codes were regenerated)
+	TotpRecovery = 15,
+}
+
+/// This records an action within the library's key audit log, alongside the node that performed it.
+///
+/// Failures are only logged, as the audit log shouldn't prevent the key manager from being used.
+pub(crate) async fn record_key_audit_event(
+	library: &LibraryContext,
+	action: KeyAuditAction,
+	key_uuid: Option<Uuid>,
+) {
+	use crate::prisma::key_audit_event::*;
+
+	let result = library
+		.db
+		.key_audit_event()
+		.create(
+			action.int_value(),
+			node::id::equals(library.node_local_id),
+			vec![key_uuid::set(key_uuid.map(|uuid| uuid.to_string()))],
+		)
+		.exec()
+		.await;
+
+	match result {
+		Ok(_) => invalidate_query!(library, "keys.auditLog"),
+		Err(e) => warn!("Failed to record key audit event {:?}: {:#?}", action, e),
+	}
+}
@@ -0,0 +1,174 @@
+use std::{
+	collections::HashMap,
+	sync::{
+		atomic::{AtomicBool, AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+};
+
+use tracing::debug;
+use uuid::Uuid;
+
+type RevokeCallback = Box<dyn FnOnce() + Send>;
+
+struct Consumer {
+	id: u64,
+	revoked: Arc<AtomicBool>,
+	on_revoke: Option<RevokeCallback>,
+}
+
+/// KeyConsumers keeps track of everything that is currently using a mounted key (e.g. decryption jobs, or cached decrypted data).
+///
+/// When a key is unmounted, every consumer of it is revoked, so access to the key's data is actually taken away.
+#[derive(Default)]
+pub struct KeyConsumers {
+	consumers: Mutex<HashMap<Uuid, Vec<Consumer>>>,
+	next_id: AtomicU64,
+}
+
+impl KeyConsumers {
+	pub fn new() -> Arc<Self> {
+		Arc::new(Self::default())
+	}
+
+	/// register marks the caller as a consumer of the given key, until the returned `KeyConsumer` is dropped.
+	pub fn register(self: &Arc<Self>, key_uuid: Uuid) -> KeyConsumer {
+		self.register_inner(key_uuid, None)
+	}
+
+	/// register_with_purge is the same as `register`, but `on_revoke` is called once the key is unmounted.
+	///
+	/// This should be used for purging any cached data that was decrypted with the key.
+	pub fn register_with_purge(
+		self: &Arc<Self>,
+		key_uuid: Uuid,
+		on_revoke: impl FnOnce() + Send + 'static,
+	) -> KeyConsumer {
+		self.register_inner(key_uuid, Some(Box::new(on_revoke)))
+	}
+
+	/// on_revoke registers a callback that's called once the key is unmounted, without needing to hold a `KeyConsumer`.
+	///
+	/// This is useful for data that outlives the work that created it, e.g. decrypted preview media written by a job.
+	pub fn on_revoke(&self, key_uuid: Uuid, on_revoke: impl FnOnce() + Send + 'static) {
+		let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+		self.consumers
+			.lock()
+			.expect("critical error: key consumers mutex poisoned")
+			.entry(key_uuid)
+			.or_default()
+			.push(Consumer {
+				id,
+				revoked: Arc::new(AtomicBool::new(false)),
+				on_revoke: Some(Box::new(on_revoke)),
+			});
+	}
+
+	fn register_inner(
+		self: &Arc<Self>,
+		key_uuid: Uuid,
+		on_revoke: Option<RevokeCallback>,
+	) -> KeyConsumer {
+		let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+		let revoked = Arc::new(AtomicBool::new(false));
+
+		self.consumers
+			.lock()
+			.expect("critical error: key consumers mutex poisoned")
+			.entry(key_uuid)
+			.or_default()
+			.push(Consumer {
+				id,
+				revoked: Arc::clone(&revoked),
+				on_revoke,
+			});
+
+		KeyConsumer {
+			key_uuid,
+			id,
+			revoked,
+			registry: Arc::clone(self),
+		}
+	}
+
+	/// revoke revokes every consumer of the given key. This should be called whenever a key is unmounted.
+	pub fn revoke(&self, key_uuid: Uuid) {
+		let consumers = self
+			.consumers
+			.lock()
+			.expect("critical error: key consumers mutex poisoned")
+			.remove(&key_uuid);
+
+		if let Some(consumers) = consumers {
+			debug!(
+				"Revoking {} consumer(s) of key {}",
+				consumers.len(),
+				key_uuid
+			);
+			consumers.into_iter().for_each(Consumer::revoke);
+		}
+	}
+
+	/// revoke_all revokes every consumer of every key, e.g. when the key manager is locked.
+	pub fn revoke_all(&self) {
+		let consumers = std::mem::take(
+			&mut *self
+				.consumers
+				.lock()
+				.expect("critical error: key consumers mutex poisoned"),
+		);
+
+		consumers.into_values().flatten().for_each(Consumer::revoke);
+	}
+
+	fn deregister(&self, key_uuid: Uuid, id: u64) {
+		let mut consumers = self
+			.consumers
+			.lock()
+			.expect("critical error: key consumers mutex poisoned");
+
+		if let Some(key_consumers) = consumers.get_mut(&key_uuid) {
+			key_consumers.retain(|consumer| consumer.id != id);
+
+			if key_consumers.is_empty() {
+				consumers.remove(&key_uuid);
+			}
+		}
+	}
+}
+
+impl Consumer {
+	fn revoke(self) {
+		self.revoked.store(true, Ordering::Release);
+
+		if let Some(on_revoke) = self.on_revoke {
+			on_revoke();
+		}
+	}
+}
+
+/// KeyConsumer is held by anything that is using a mounted key, and it's deregistered once dropped.
+pub struct KeyConsumer {
+	key_uuid: Uuid,
+	id: u64,
+	revoked: Arc<AtomicBool>,
+	registry: Arc<KeyConsumers>,
+}
+
+impl KeyConsumer {
+	pub fn key_uuid(&self) -> Uuid {
+		self.key_uuid
+	}
+
+	/// is_revoked returns true once the key has been unmounted, and the consumer should stop using it.
+	pub fn is_revoked(&self) -> bool {
+		self.revoked.load(Ordering::Acquire)
+	}
+}
+
+impl Drop for KeyConsumer {
+	fn drop(&mut self) {
+		self.registry.deregister(self.key_uuid, self.id);
+	}
+}
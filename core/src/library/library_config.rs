@@ -11,7 +11,7 @@ use uuid::Uuid;
 
 use crate::node::ConfigMetadata;
 
-use super::LibraryManagerError;
+use super::{AutoLockConfig, EncryptedField, LibraryManagerError};
 
 /// LibraryConfig holds the configuration for a specific library. This is stored as a '{uuid}.sdlibrary' file.
 #[derive(Debug, Serialize, Deserialize, Clone, Type, Default)]
@@ -22,6 +22,35 @@ pub struct LibraryConfig {
 	pub name: String,
 	/// description is a user set description of the library. This is used in the UI and is set by the user.
 	pub description: String,
+	/// auto_lock holds the settings for automatically locking the library's key manager.
+	#[serde(default)]
+	pub auto_lock: AutoLockConfig,
+	/// last_keystore_backup is the last time the library's keystore was backed up.
+	#[serde(default)]
+	pub last_keystore_backup: Option<chrono::DateTime<chrono::Utc>>,
+	/// master_password_hint is shown to the user when unlocking the library. It's stored unencrypted.
+	#[serde(default)]
+	pub master_password_hint: Option<String>,
+	/// context_default_keys holds the default keys for specific locations and tags, which take priority over the library's default key.
+	#[serde(default)]
+	pub context_default_keys: Vec<ContextDefaultKey>,
+	/// encrypted_fields holds the object metadata fields that are encrypted within the library database.
+	#[serde(default)]
+	pub encrypted_fields: Vec<EncryptedField>,
+}
+
+/// KeyDefaultContext is something that can have its own default key.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Type, PartialEq, Eq)]
+pub enum KeyDefaultContext {
+	Location(i32),
+	Tag(i32),
+}
+
+/// ContextDefaultKey is the default key for a specific location or tag.
+#[derive(Debug, Serialize, Deserialize, Clone, Type)]
+pub struct ContextDefaultKey {
+	pub context: KeyDefaultContext,
+	pub key_uuid: Uuid,
 }
 
 impl LibraryConfig {
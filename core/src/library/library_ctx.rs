@@ -1,6 +1,14 @@
 use crate::{
-	api::CoreEvent, job::DynJob, location::LocationManager, node::NodeConfigManager,
-	prisma::PrismaClient, NodeContext,
+	api::CoreEvent,
+	job::{DynJob, IoThrottle},
+	location::LocationManager,
+	node::NodeConfigManager,
+	object::{
+		fs::{stream::EncryptedStreams, temp::TempFileManager},
+		preview::ThumbnailCache,
+	},
+	prisma::PrismaClient,
+	NodeContext,
 };
 
 use std::{
@@ -12,7 +20,7 @@ use sd_crypto::keys::keymanager::KeyManager;
 use tracing::warn;
 use uuid::Uuid;
 
-use super::LibraryConfig;
+use super::{AutoLock, KeyConsumers, LibraryConfig, SearchIndex};
 
 /// LibraryContext holds context for a library which can be passed around the application.
 #[derive(Clone)]
@@ -25,6 +33,12 @@ pub struct LibraryContext {
 	pub db: Arc<PrismaClient>,
 	/// key manager that provides encryption keys to functions that require them
 	pub key_manager: Arc<KeyManager>,
+	/// auto_lock locks the key manager once the library's auto-lock settings are triggered
+	pub auto_lock: Arc<AutoLock>,
+	/// key_consumers tracks everything using a mounted key, so it can be revoked once the key is unmounted
+	pub key_consumers: Arc<KeyConsumers>,
+	/// search_index is the full-text index of the library's documents
+	pub search_index: Arc<SearchIndex>,
 	/// node_local_id holds the local ID of the node which is running the library.
 	pub node_local_id: i32,
 	/// node_context holds the node context for the node which this library is running on.
@@ -49,8 +63,13 @@ impl LibraryContext {
 		self.node_context.jobs.clone().ingest(self, job).await;
 	}
 
-	pub(crate) async fn queue_job(&self, job: Box<dyn DynJob>) {
-		self.node_context.jobs.ingest_queue(job).await;
+	/// This runs any jobs that were waiting for keys which have now been mounted, so it should be called whenever keys are mounted.
+	pub(crate) async fn resume_jobs_waiting_for_keys(&self) {
+		self.node_context
+			.jobs
+			.clone()
+			.resume_jobs_waiting_for_keys(self)
+			.await;
 	}
 
 	pub(crate) fn emit(&self, event: CoreEvent) {
@@ -66,4 +85,20 @@ impl LibraryContext {
 	pub(crate) fn location_manager(&self) -> &Arc<LocationManager> {
 		&self.node_context.location_manager
 	}
+
+	pub(crate) fn temp_files(&self) -> &Arc<TempFileManager> {
+		&self.node_context.temp_files
+	}
+
+	pub(crate) fn encrypted_streams(&self) -> &Arc<EncryptedStreams> {
+		&self.node_context.encrypted_streams
+	}
+
+	pub(crate) fn io_throttle(&self) -> &Arc<IoThrottle> {
+		&self.node_context.io_throttle
+	}
+
+	pub(crate) fn thumbnail_cache(&self) -> &Arc<ThumbnailCache> {
+		&self.node_context.thumbnail_cache
+	}
 }
@@ -1,7 +1,7 @@
 use crate::{
 	invalidate_query,
 	node::Platform,
-	prisma::{key, node, PrismaClient},
+	prisma::{key_totp, node, PrismaClient},
 	util::{
 		db::load_and_migrate,
 		seeder::{indexer_rules_seeder, SeederError},
@@ -12,8 +12,9 @@ use crate::{
 use sd_crypto::{
 	crypto::stream::Algorithm,
 	keys::{
-		hashing::{HashingAlgorithm, Params},
-		keymanager::{KeyManager, StoredKey},
+		hashing::HashingAlgorithm,
+		keymanager::{KeyManager, KeySource, StoredKey},
+		totp::StoredTotp,
 	},
 	primitives::to_array,
 };
@@ -27,7 +28,10 @@ use thiserror::Error;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
-use super::{LibraryConfig, LibraryConfigWrapped, LibraryContext};
+use super::{
+	AutoLock, AutoLockConfig, KeyConsumers, LibraryConfig, LibraryConfigWrapped, LibraryContext,
+	SearchIndex, SearchIndexError, SystemEvent,
+};
 
 /// LibraryManager is a singleton that manages all libraries for a node.
 pub struct LibraryManager {
@@ -59,6 +63,8 @@ pub enum LibraryManagerError {
 	Seeder(#[from] SeederError),
 	#[error("failed to initialise the key manager")]
 	KeyManager(#[from] sd_crypto::Error),
+	#[error("failed to open the search index: {0}")]
+	SearchIndex(#[from] SearchIndexError),
 }
 
 impl From<LibraryManagerError> for rspc::Error {
@@ -71,51 +77,22 @@ impl From<LibraryManagerError> for rspc::Error {
 	}
 }
 
+key_totp::select!(key_totp_with_uuid {
+	algorithm
+	salt
+	nonce
+	secret
+	recovery_codes
+	key: select {
+		uuid
+	}
+});
+
 pub async fn create_keymanager(client: &PrismaClient) -> Result<KeyManager, LibraryManagerError> {
 	// retrieve all stored keys from the DB
 	let key_manager = KeyManager::new(vec![])?;
 
-	// BRXKEN128: REMOVE THIS ONCE ONBOARDING HAS BEEN DONE
-	// this is so if there's no verification key set, we set one so users can use the key manager
-	// it will be done during onboarding, but for now things are statically set (unless they were changed)
-	if client
-		.key()
-		.find_many(vec![key::uuid::equals(uuid::Uuid::nil().to_string())])
-		.exec()
-		.await?
-		.is_empty()
-	{
-		client
-			.key()
-			.delete_many(vec![key::uuid::equals(uuid::Uuid::nil().to_string())])
-			.exec()
-			.await?;
-		// BRXKEN128: REMOVE THIS ONCE ONBOARDING HAS BEEN DONE
-		let verification_key = KeyManager::onboarding(
-			Algorithm::XChaCha20Poly1305,
-			HashingAlgorithm::Argon2id(Params::Standard),
-		)?
-		.verification_key;
-
-		// BRXKEN128: REMOVE THIS ONCE ONBOARDING HAS BEEN DONE
-		client
-			.key()
-			.create(
-				verification_key.uuid.to_string(),
-				verification_key.algorithm.serialize().to_vec(),
-				verification_key.hashing_algorithm.serialize().to_vec(),
-				verification_key.content_salt.to_vec(),
-				verification_key.master_key.to_vec(),
-				verification_key.master_key_nonce.to_vec(),
-				verification_key.key_nonce.to_vec(),
-				verification_key.key.to_vec(),
-				verification_key.salt.to_vec(),
-				vec![],
-			)
-			.exec()
-			.await?;
-	}
-
+	// libraries don't have a verification key until they've been onboarded (with `keys.onboarding`)
 	let db_stored_keys = client.key().find_many(vec![]).exec().await?;
 
 	let mut default = Uuid::nil();
@@ -145,6 +122,8 @@ pub async fn create_keymanager(client: &PrismaClient) -> Result<KeyManager, Libr
 				salt: to_array(key.salt)?,
 				memory_only: false,
 				automount: key.automount,
+				name: key.name,
+				source: KeySource::deserialize(key.source)?,
 			};
 
 			Ok(stored_key)
@@ -160,6 +139,26 @@ pub async fn create_keymanager(client: &PrismaClient) -> Result<KeyManager, Libr
 		key_manager.set_default(default)?;
 	}
 
+	// keys with TOTP enabled can't be mounted without a code
+	let db_totp = client
+		.key_totp()
+		.find_many(vec![])
+		.select(key_totp_with_uuid::select())
+		.exec()
+		.await?;
+
+	for totp in db_totp {
+		let stored_totp = StoredTotp {
+			algorithm: Algorithm::deserialize(to_array(totp.algorithm)?)?,
+			salt: to_array(totp.salt)?,
+			nonce: totp.nonce,
+			secret: totp.secret,
+			recovery_codes: StoredTotp::deserialize_recovery_codes(&totp.recovery_codes)?,
+		};
+
+		key_manager.set_totp(Uuid::from_str(&totp.key.uuid)?, Some(stored_totp))?;
+	}
+
 	Ok(key_manager)
 }
 
@@ -290,6 +289,52 @@ impl LibraryManager {
 		Ok(())
 	}
 
+	/// update_config applies the given changes to a library's config, and saves it to disk.
+	pub(crate) async fn update_config(
+		&self,
+		id: Uuid,
+		update: impl FnOnce(&mut LibraryConfig),
+	) -> Result<LibraryConfig, LibraryManagerError> {
+		let mut libraries = self.libraries.write().await;
+		let library = libraries
+			.iter_mut()
+			.find(|lib| lib.id == id)
+			.ok_or(LibraryManagerError::LibraryNotFound)?;
+
+		update(&mut library.config);
+
+		LibraryConfig::save(
+			Path::new(&self.libraries_dir).join(format!("{id}.sdlibrary")),
+			&library.config,
+		)
+		.await?;
+
+		Ok(library.config.clone())
+	}
+
+	pub(crate) async fn set_auto_lock(
+		&self,
+		id: Uuid,
+		auto_lock: AutoLockConfig,
+	) -> Result<(), LibraryManagerError> {
+		let config = self
+			.update_config(id, |config| config.auto_lock = auto_lock)
+			.await?;
+
+		if let Some(library) = self.get_ctx(id).await {
+			library.auto_lock.set_config(config.auto_lock).await;
+		}
+
+		Ok(())
+	}
+
+	/// handle_system_event passes an OS-level event (e.g. the system going to sleep) to every library, so they can lock if configured to.
+	pub(crate) async fn handle_system_event(&self, event: SystemEvent) {
+		for library in self.libraries.read().await.iter() {
+			library.auto_lock.handle_system_event(library, event).await;
+		}
+	}
+
 	pub async fn delete_library(&self, id: Uuid) -> Result<(), LibraryManagerError> {
 		let mut libraries = self.libraries.write().await;
 
@@ -300,6 +345,13 @@ impl LibraryManager {
 
 		fs::remove_file(Path::new(&self.libraries_dir).join(format!("{}.db", library.id)))?;
 		fs::remove_file(Path::new(&self.libraries_dir).join(format!("{}.sdlibrary", library.id)))?;
+		// the search index can be rebuilt, so it's fine if it was never created
+		match fs::remove_dir_all(
+			Path::new(&self.libraries_dir).join(format!("{}.search", library.id)),
+		) {
+			Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+			_ => {}
+		}
 
 		invalidate_query!(library, "library.list");
 
@@ -367,13 +419,24 @@ impl LibraryManager {
 
 		let key_manager = Arc::new(create_keymanager(&db).await?);
 
-		Ok(LibraryContext {
+		let auto_lock = AutoLock::new(config.auto_lock.clone());
+
+		let search_index = SearchIndex::open(db_path.with_extension("search"))?;
+
+		let library = LibraryContext {
 			id,
 			config,
 			db,
 			key_manager,
+			auto_lock,
+			key_consumers: KeyConsumers::new(),
+			search_index,
 			node_local_id: node_data.id,
 			node_context,
-		})
+		};
+
+		library.auto_lock.spawn(library.clone());
+
+		Ok(library)
 	}
 }
@@ -1,7 +1,17 @@
+mod auto_lock;
+mod encrypted_fields;
+mod key_audit;
+mod key_consumers;
 mod library_config;
 mod library_ctx;
 mod library_manager;
+mod search_index;
 
+pub use auto_lock::*;
+pub use encrypted_fields::*;
+pub use key_audit::*;
+pub use key_consumers::*;
 pub use library_config::*;
 pub use library_ctx::*;
 pub use library_manager::*;
+pub use search_index::*;
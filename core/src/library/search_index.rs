@@ -0,0 +1,158 @@
+use std::{
+	fs, io,
+	path::Path,
+	sync::{Arc, Mutex},
+};
+
+use tantivy::{
+	collector::TopDocs,
+	directory::{error::OpenDirectoryError, MmapDirectory},
+	doc,
+	query::{QueryParser, QueryParserError},
+	schema::{Field, Schema, INDEXED, STORED, TEXT},
+	Index, IndexReader, IndexWriter, ReloadPolicy, SnippetGenerator, TantivyError, Term,
+};
+use thiserror::Error;
+
+/// The memory that the index writer may use before it has to flush what it's indexed to disk.
+const WRITER_MEMORY_BYTES: usize = 20 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum SearchIndexError {
+	#[error("I/O error: {0}")]
+	IO(#[from] io::Error),
+	#[error("Failed to open the search index's directory: {0}")]
+	OpenDirectory(#[from] OpenDirectoryError),
+	#[error("Search index error: {0}")]
+	Tantivy(#[from] TantivyError),
+	#[error("Invalid search query: {0}")]
+	Query(#[from] QueryParserError),
+}
+
+impl From<SearchIndexError> for rspc::Error {
+	fn from(err: SearchIndexError) -> Self {
+		match err {
+			SearchIndexError::Query(_) => {
+				rspc::Error::with_cause(rspc::ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => {
+				rspc::Error::with_cause(rspc::ErrorCode::InternalServerError, err.to_string(), err)
+			}
+		}
+	}
+}
+
+/// SearchHit is an object whose contents matched a full-text search.
+#[derive(Debug)]
+pub struct SearchHit {
+	pub object_id: i32,
+	pub score: f32,
+	/// the best matching fragment of the object's contents, as HTML with the matched terms in `<b>` tags
+	pub snippet: String,
+}
+
+/// SearchIndex is the full-text index of a library's documents, which is stored alongside its database.
+///
+/// Every object is indexed at most once, and objects without any text are indexed with empty contents, so they aren't extracted again.
+/// The index may contain objects that have since been deleted, so hits need to be checked against the database.
+pub struct SearchIndex {
+	index: Index,
+	reader: IndexReader,
+	writer: Mutex<IndexWriter>,
+	object_id: Field,
+	content: Field,
+}
+
+impl SearchIndex {
+	/// This opens the index at the given directory, creating it if it doesn't exist.
+	pub fn open(path: impl AsRef<Path>) -> Result<Arc<Self>, SearchIndexError> {
+		fs::create_dir_all(&path)?;
+
+		let mut schema = Schema::builder();
+		let object_id = schema.add_i64_field("object_id", INDEXED | STORED);
+		let content = schema.add_text_field("content", TEXT | STORED);
+
+		let index = Index::open_or_create(MmapDirectory::open(path)?, schema.build())?;
+		let reader = index
+			.reader_builder()
+			.reload_policy(ReloadPolicy::OnCommit)
+			.try_into()?;
+		let writer = index.writer_with_num_threads(1, WRITER_MEMORY_BYTES)?;
+
+		Ok(Arc::new(Self {
+			index,
+			reader,
+			writer: Mutex::new(writer),
+			object_id,
+			content,
+		}))
+	}
+
+	/// This returns whether an object has already been indexed.
+	pub fn contains(&self, object_id: i32) -> Result<bool, SearchIndexError> {
+		Ok(self
+			.reader
+			.searcher()
+			.doc_freq(&Term::from_field_i64(self.object_id, object_id.into()))?
+			> 0)
+	}
+
+	/// This indexes the contents of some objects (replacing whatever was indexed for them before), and commits them.
+	///
+	/// This blocks, so it should be run on a blocking thread.
+	pub fn index(&self, documents: Vec<(i32, String)>) -> Result<(), SearchIndexError> {
+		let mut writer = self
+			.writer
+			.lock()
+			.expect("critical error: search index writer lock poisoned");
+
+		for (object_id, content) in documents {
+			writer.delete_term(Term::from_field_i64(self.object_id, object_id.into()));
+			writer.add_document(doc!(
+				self.object_id => i64::from(object_id),
+				self.content => content,
+			))?;
+		}
+
+		writer.commit()?;
+
+		Ok(())
+	}
+
+	/// This finds the objects whose contents best match a query, alongside a snippet of each.
+	///
+	/// Queries support tantivy's query syntax, e.g. `"exact phrase"` and `+required -excluded`.
+	pub fn search(
+		&self,
+		query: &str,
+		take: usize,
+		skip: usize,
+	) -> Result<Vec<SearchHit>, SearchIndexError> {
+		let searcher = self.reader.searcher();
+		let query = QueryParser::for_index(&self.index, vec![self.content]).parse_query(query)?;
+
+		let top_docs =
+			searcher.search(&query, &TopDocs::with_limit(take.max(1)).and_offset(skip))?;
+		let snippets = SnippetGenerator::create(&searcher, &*query, self.content)?;
+
+		top_docs
+			.into_iter()
+			.filter_map(|(score, address)| {
+				let document = match searcher.doc(address) {
+					Ok(document) => document,
+					Err(e) => return Some(Err(e.into())),
+				};
+
+				let object_id = document
+					.get_first(self.object_id)
+					.and_then(|value| value.as_i64())?;
+
+				Some(Ok(SearchHit {
+					object_id: object_id as i32,
+					score,
+					snippet: snippets.snippet_from_doc(&document).to_html(),
+				}))
+			})
+			.collect()
+	}
+}
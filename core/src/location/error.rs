@@ -38,6 +38,14 @@ pub enum LocationError {
 	MetadataNotFound(PathBuf),
 	#[error("Location already exists (path: {0:?})")]
 	LocationAlreadyExists(PathBuf),
+	#[error("Vault key must be mounted and stored in the library (uuid: {0})")]
+	InvalidVaultKey(Uuid),
+	#[error("Invalid ignore patterns: {0}")]
+	InvalidIgnorePatterns(String),
+	#[error("Vault locations can't be versioned (id: {0})")]
+	VaultVersioning(i32),
+	#[error("A file must keep at least one version")]
+	InvalidVersionRetention,
 
 	// Internal Errors
 	#[error("Location metadata error (error: {0:?})")]
@@ -74,7 +82,11 @@ impl From<LocationError> for rspc::Error {
 			LocationError::NotDirectory(_)
 			| LocationError::MissingLocalPath(_)
 			| LocationError::NeedRelink { .. }
-			| LocationError::AddLibraryToMetadata(_) => {
+			| LocationError::AddLibraryToMetadata(_)
+			| LocationError::InvalidVaultKey(_)
+			| LocationError::InvalidIgnorePatterns(_)
+			| LocationError::VaultVersioning(_)
+			| LocationError::InvalidVersionRetention => {
 				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
 			}
 
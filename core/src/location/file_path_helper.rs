@@ -1,9 +1,12 @@
-use crate::{library::LibraryContext, prisma::file_path};
+use crate::{
+	library::LibraryContext,
+	prisma::{file_path, location, object},
+};
 
 use std::sync::atomic::{AtomicI32, Ordering};
 
 use chrono::{DateTime, Utc};
-use prisma_client_rust::{Direction, QueryError};
+use prisma_client_rust::{raw, Direction, PrismaValue, QueryError};
 
 static LAST_FILE_PATH_ID: AtomicI32 = AtomicI32::new(0);
 
@@ -90,7 +93,12 @@ pub struct FilePathBatchCreateEntry {
 	pub extension: Option<String>,
 	pub parent_id: Option<i32>,
 	pub is_dir: bool,
+	pub is_symlink: bool,
 	pub created_at: DateTime<Utc>,
+	pub modified_at: DateTime<Utc>,
+	pub size_in_bytes: u64,
+	pub inode: Option<u64>,
+	pub fingerprint: Option<String>,
 }
 
 pub async fn create_many_file_paths(
@@ -112,7 +120,12 @@ pub async fn create_many_file_paths(
 					     extension,
 					     parent_id,
 					     is_dir,
+					     is_symlink,
 					     created_at,
+					     modified_at,
+					     size_in_bytes,
+					     inode,
+					     fingerprint,
 					 }| {
 						// If this new file_path is a directory, materialized_path must end with "/"
 						if is_dir && !materialized_path.ends_with('/') {
@@ -126,9 +139,14 @@ pub async fn create_many_file_paths(
 							name,
 							vec![
 								file_path::is_dir::set(is_dir),
+								file_path::is_symlink::set(is_symlink),
 								file_path::parent_id::set(parent_id),
 								file_path::extension::set(extension),
 								file_path::date_created::set(created_at.into()),
+								file_path::date_modified::set(modified_at.into()),
+								file_path::size_in_bytes::set(Some(size_in_bytes.to_string())),
+								file_path::inode::set(inode.map(|inode| inode.to_string())),
+								file_path::fingerprint::set(fingerprint),
 							],
 						)
 					},
@@ -139,3 +157,298 @@ pub async fn create_many_file_paths(
 		.exec()
 		.await
 }
+
+/// This returns the materialized path of the directory that a path is within, which is `/` (the location's root) for paths at the top of the location.
+pub fn parent_materialized_path(materialized_path: &str) -> String {
+	match materialized_path.trim_end_matches('/').rsplit_once('/') {
+		Some((parent, _)) => format!("{parent}/"),
+		None => "/".to_string(),
+	}
+}
+
+/// This splits a path into the name and extension that its file path is stored with, in the same way as the indexer.
+fn file_path_name(materialized_path: &str, is_dir: bool) -> (String, Option<String>) {
+	let file_name = materialized_path
+		.trim_end_matches('/')
+		.rsplit('/')
+		.next()
+		.unwrap_or_default();
+
+	if is_dir {
+		return (file_name.to_string(), None);
+	}
+
+	// dotfiles (e.g. `.bashrc`) don't have an extension
+	match file_name.rsplit_once('.') {
+		Some((name, extension)) if !name.is_empty() => {
+			(name.to_string(), Some(extension.to_lowercase()))
+		}
+		_ => (file_name.to_string(), Some(String::new())),
+	}
+}
+
+/// This records a file (or directory) that a job has written within a location, so it shows up straight away rather than on the location's next scan.
+///
+/// If the location already has a file path there (e.g. the file was overwritten), it's linked to the new contents' object instead.
+pub async fn record_file_path(
+	library_ctx: &LibraryContext,
+	location_id: i32,
+	materialized_path: &str,
+	is_dir: bool,
+	object_id: Option<i32>,
+	size_in_bytes: u64,
+) -> Result<(), QueryError> {
+	let db = &library_ctx.db;
+
+	let materialized_path = if is_dir {
+		format!("{}/", materialized_path.trim_end_matches('/'))
+	} else {
+		materialized_path.to_string()
+	};
+
+	if let Some(existing) = db
+		.file_path()
+		.find_first(vec![
+			file_path::location_id::equals(location_id),
+			file_path::materialized_path::equals(materialized_path.clone()),
+		])
+		.exec()
+		.await?
+	{
+		if existing.is_dir == is_dir && existing.object_id != object_id {
+			db.file_path()
+				.update(
+					file_path::location_id_id(location_id, existing.id),
+					vec![
+						file_path::object_id::set(object_id),
+						file_path::size_in_bytes::set(Some(size_in_bytes.to_string())),
+						file_path::date_modified::set(Utc::now().into()),
+					],
+				)
+				.exec()
+				.await?;
+
+			delete_orphaned_objects(library_ctx, existing.object_id.into_iter().collect()).await?;
+		}
+
+		return Ok(());
+	}
+
+	let parent_id = db
+		.file_path()
+		.find_first(vec![
+			file_path::location_id::equals(location_id),
+			file_path::materialized_path::equals(parent_materialized_path(&materialized_path)),
+		])
+		.select(file_path_id_only::select())
+		.exec()
+		.await?
+		.map(|parent| parent.id);
+
+	let (name, extension) = file_path_name(&materialized_path, is_dir);
+
+	let mut params = vec![
+		file_path::is_dir::set(is_dir),
+		file_path::parent_id::set(parent_id),
+		file_path::extension::set(extension),
+		file_path::size_in_bytes::set(Some(size_in_bytes.to_string())),
+	];
+	if let Some(object_id) = object_id {
+		params.push(file_path::object::connect(object::id::equals(object_id)));
+	}
+
+	let id = get_max_file_path_id(library_ctx).await? + 1;
+
+	db.file_path()
+		.create(
+			id,
+			location::id::equals(location_id),
+			materialized_path,
+			name,
+			params,
+		)
+		.exec()
+		.await?;
+
+	set_max_file_path_id(id);
+
+	Ok(())
+}
+
+/// This moves a file path (and everything within it, if it's a directory) to another path, which may be within another location.
+///
+/// File path IDs are unique across every location, so they're kept as they are, alongside the objects that they're linked to.
+/// Anything that was already recorded at the new path has been replaced, so it's removed.
+pub async fn move_file_path(
+	library_ctx: &LibraryContext,
+	location_id: i32,
+	materialized_path: &str,
+	target_location_id: i32,
+	target_materialized_path: &str,
+	is_dir: bool,
+) -> Result<(), QueryError> {
+	let db = &library_ctx.db;
+
+	let (materialized_path, target_materialized_path) = if is_dir {
+		(
+			format!("{}/", materialized_path.trim_end_matches('/')),
+			format!("{}/", target_materialized_path.trim_end_matches('/')),
+		)
+	} else {
+		(
+			materialized_path.to_string(),
+			target_materialized_path.to_string(),
+		)
+	};
+
+	// paths that were never indexed are picked up by the location's next scan
+	let file_path = match db
+		.file_path()
+		.find_first(vec![
+			file_path::location_id::equals(location_id),
+			file_path::materialized_path::equals(materialized_path.clone()),
+		])
+		.exec()
+		.await?
+	{
+		Some(file_path) => file_path,
+		None => return Ok(()),
+	};
+
+	let replaced = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(target_location_id),
+			file_path::materialized_path::equals(target_materialized_path.clone()),
+		])
+		.exec()
+		.await?;
+	db.file_path()
+		.delete_many(vec![
+			file_path::location_id::equals(target_location_id),
+			file_path::id::in_vec(replaced.iter().map(|file_path| file_path.id).collect()),
+		])
+		.exec()
+		.await?;
+	delete_orphaned_objects(
+		library_ctx,
+		replaced
+			.into_iter()
+			.filter_map(|file_path| file_path.object_id)
+			.collect(),
+	)
+	.await?;
+
+	// everything within a directory keeps its path relative to it, which is replaced at the start of their paths
+	// (SQLite counts characters rather than bytes)
+	if is_dir {
+		let prefix_len = materialized_path.chars().count() as i64;
+
+		db._execute_raw(raw!(
+			"UPDATE file_path SET location_id = {}, materialized_path = {} || SUBSTR(materialized_path, {}) WHERE location_id = {} AND SUBSTR(materialized_path, 1, {}) = {}",
+			PrismaValue::Int(target_location_id as i64),
+			PrismaValue::String(target_materialized_path.clone()),
+			PrismaValue::Int(prefix_len + 1),
+			PrismaValue::Int(location_id as i64),
+			PrismaValue::Int(prefix_len),
+			PrismaValue::String(materialized_path)
+		))
+		.exec()
+		.await?;
+	}
+
+	let parent_id = db
+		.file_path()
+		.find_first(vec![
+			file_path::location_id::equals(target_location_id),
+			file_path::materialized_path::equals(parent_materialized_path(
+				&target_materialized_path,
+			)),
+		])
+		.select(file_path_id_only::select())
+		.exec()
+		.await?
+		.map(|parent| parent.id);
+
+	let (name, extension) = file_path_name(&target_materialized_path, is_dir);
+
+	db.file_path()
+		.update(
+			file_path::location_id_id(
+				if is_dir {
+					target_location_id
+				} else {
+					location_id
+				},
+				file_path.id,
+			),
+			vec![
+				file_path::location::connect(location::id::equals(target_location_id)),
+				file_path::materialized_path::set(target_materialized_path),
+				file_path::name::set(name),
+				file_path::extension::set(extension),
+				file_path::parent_id::set(parent_id),
+			],
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// This deletes the objects that are no longer linked to any file paths, e.g. once their last file has been deleted.
+pub async fn delete_orphaned_objects(
+	library_ctx: &LibraryContext,
+	object_ids: Vec<i32>,
+) -> Result<(), QueryError> {
+	if object_ids.is_empty() {
+		return Ok(());
+	}
+
+	library_ctx
+		.db
+		.object()
+		.delete_many(vec![
+			object::id::in_vec(object_ids),
+			// https://www.prisma.io/docs/reference/api-reference/prisma-client-reference#none
+			object::file_paths::none(vec![]),
+			// trashed objects are kept, so they can be restored
+			object::trashed_items::none(vec![]),
+		])
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parent_materialized_path() {
+		assert_eq!(
+			parent_materialized_path("photos/2022/a.jpg"),
+			"photos/2022/"
+		);
+		assert_eq!(parent_materialized_path("photos/2022/"), "photos/");
+		assert_eq!(parent_materialized_path("a.jpg"), "/");
+		assert_eq!(parent_materialized_path("photos/"), "/");
+	}
+
+	#[test]
+	fn test_file_path_name() {
+		assert_eq!(
+			file_path_name("photos/a.tar.GZ", false),
+			("a.tar".to_string(), Some("gz".to_string()))
+		);
+		assert_eq!(
+			file_path_name("photos/.bashrc", false),
+			(".bashrc".to_string(), Some(String::new()))
+		);
+		assert_eq!(
+			file_path_name("photos/2022.01/", true),
+			("2022.01".to_string(), None)
+		);
+	}
+}
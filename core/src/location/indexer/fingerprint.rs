@@ -0,0 +1,138 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use blake3::Hasher;
+
+use super::walk::WalkEntry;
+
+/// This computes an aggregate fingerprint for every directory within a walk, from the names and metadata of everything below it.
+///
+/// A directory's fingerprint only stays the same if nothing within it was added, removed, renamed or modified (at any depth),
+/// so a later scan can skip every directory whose fingerprint matches the one it recorded, alongside all of its contents.
+/// The entries must be sorted by their paths, as they are once they've been walked.
+pub(super) fn directory_fingerprints(entries: &[WalkEntry]) -> HashMap<PathBuf, String> {
+	let mut hashers = HashMap::<PathBuf, Hasher>::new();
+	let mut fingerprints = HashMap::new();
+
+	// every path sorts after its ancestors, so going backwards means a directory's contents are all hashed before it's finished
+	for entry in entries.iter().rev() {
+		let fingerprint = entry.is_dir.then(|| {
+			let fingerprint = hashers
+				.remove(&entry.path)
+				.unwrap_or_default()
+				.finalize()
+				.to_hex()
+				.to_string();
+
+			fingerprints.insert(entry.path.clone(), fingerprint.clone());
+
+			fingerprint
+		});
+
+		let (parent, name) = match (entry.path.parent(), entry.path.file_name()) {
+			(Some(parent), Some(name)) => (parent, name),
+			_ => continue,
+		};
+
+		let hasher = hashers.entry(parent.to_path_buf()).or_default();
+
+		hasher.update(name.to_string_lossy().as_bytes());
+		hasher.update(&[entry.is_dir as u8, entry.is_symlink as u8]);
+
+		match fingerprint {
+			Some(fingerprint) => {
+				hasher.update(fingerprint.as_bytes());
+			}
+			None => {
+				hasher.update(&entry.metadata.size_in_bytes.to_le_bytes());
+				hasher.update(&entry.metadata.modified_at.timestamp_nanos().to_le_bytes());
+				hasher.update(&entry.metadata.inode.unwrap_or_default().to_le_bytes());
+			}
+		}
+
+		// names can't contain this, so one entry can't run into the next
+		hasher.update(b"/");
+	}
+
+	fingerprints
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::walk::WalkEntryMetadata;
+	use super::*;
+	use chrono::Duration;
+	use std::path::Path;
+
+	fn entry(path: &str, is_dir: bool) -> WalkEntry {
+		WalkEntry {
+			path: PathBuf::from(path),
+			is_dir,
+			is_symlink: false,
+			metadata: WalkEntryMetadata::default(),
+		}
+	}
+
+	fn tree() -> Vec<WalkEntry> {
+		let mut entries = vec![
+			entry("/location", true),
+			entry("/location/photos", true),
+			entry("/location/photos/photo1.png", false),
+			entry("/location/photos/photo2.png", false),
+			entry("/location/docs", true),
+			entry("/location/docs/notes.txt", false),
+		];
+		entries.sort();
+		entries
+	}
+
+	#[test]
+	fn test_unchanged_tree() {
+		assert_eq!(
+			directory_fingerprints(&tree()),
+			directory_fingerprints(&tree())
+		);
+	}
+
+	#[test]
+	fn test_changes_reach_every_ancestor() {
+		let before = directory_fingerprints(&tree());
+
+		let mut modified = tree();
+		for entry in &mut modified {
+			if entry.path == Path::new("/location/photos/photo1.png") {
+				entry.metadata.modified_at += Duration::seconds(1);
+			}
+		}
+		let after = directory_fingerprints(&modified);
+
+		assert_ne!(
+			before[Path::new("/location")],
+			after[Path::new("/location")]
+		);
+		assert_ne!(
+			before[Path::new("/location/photos")],
+			after[Path::new("/location/photos")]
+		);
+		assert_eq!(
+			before[Path::new("/location/docs")],
+			after[Path::new("/location/docs")]
+		);
+
+		let mut removed = tree();
+		removed.retain(|entry| entry.path != Path::new("/location/docs/notes.txt"));
+		let after = directory_fingerprints(&removed);
+
+		assert_ne!(
+			before[Path::new("/location")],
+			after[Path::new("/location")]
+		);
+		assert_ne!(
+			before[Path::new("/location/docs")],
+			after[Path::new("/location/docs")]
+		);
+		assert_eq!(
+			before[Path::new("/location/photos")],
+			after[Path::new("/location/photos")]
+		);
+	}
+}
@@ -1,5 +1,8 @@
 use crate::{
-	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	job::{
+		JobError, JobPriority, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+	},
+	object::fs::policy::apply_encryption_rules,
 	prisma::{file_path, location},
 };
 
@@ -12,18 +15,22 @@ use std::{
 };
 
 use chrono::{DateTime, Utc};
+use int_enum::IntEnum;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use tokio::time::Instant;
-use tracing::info;
+use tracing::{error, info};
 
 use super::{
 	super::file_path_helper::{
 		create_many_file_paths, get_max_file_path_id, set_max_file_path_id,
 		FilePathBatchCreateEntry,
 	},
+	fingerprint::directory_fingerprints,
 	rules::{IndexerRule, RuleKind},
-	walk::{walk, WalkEntry},
+	sdignore::IgnoreRules,
+	walk::{walk, WalkEntry, WalkEntryMetadata},
+	SymlinkPolicy,
 };
 
 /// BATCH_SIZE is the number of files to index at each step, writing the chunk of files metadata in the database.
@@ -40,12 +47,25 @@ pub enum ScanProgress {
 /// A `IndexerJob` is a stateful job that walks a directory and indexes all files.
 /// First it walks the directory and generates a list of files to index, chunked into
 /// batches of [`BATCH_SIZE`]. Then for each chunk it write the file metadata to the database.
+///
+/// Locations that were indexed before are only diffed against what was recorded: directories whose fingerprint hasn't changed are skipped
+/// (alongside everything within them), files whose size, modification time or inode has changed are unlinked from their objects so they're
+/// identified again, and paths that are gone are removed.
 pub struct IndexerJob;
 
 location::include!(indexer_job_location {
 	indexer_rules: select { indexer_rule }
 });
 file_path::select!(file_path_id_only { id });
+file_path::select!(file_path_for_indexer {
+	id
+	materialized_path
+	is_dir
+	size_in_bytes
+	inode
+	date_modified
+	fingerprint
+});
 
 /// `IndexerJobInit` receives a `location::Data` object to be indexed
 #[derive(Serialize, Deserialize)]
@@ -66,7 +86,14 @@ pub struct IndexerJobData {
 	location_path: PathBuf,
 	db_write_start: DateTime<Utc>,
 	scan_read_time: Duration,
+	/// the number of paths that are new since the last scan
 	total_paths: usize,
+	#[serde(default)]
+	changed_paths: usize,
+	#[serde(default)]
+	removed_paths: usize,
+	#[serde(default)]
+	unchanged_paths: usize,
 }
 
 /// `IndexerJobStep` is a type alias, specifying that each step of the [`IndexerJob`] is a vector of
@@ -79,9 +106,19 @@ pub type IndexerJobStep = Vec<IndexerJobStepEntry>;
 pub struct IndexerJobStepEntry {
 	path: PathBuf,
 	created_at: DateTime<Utc>,
+	#[serde(default)]
+	modified_at: DateTime<Utc>,
+	#[serde(default)]
+	size_in_bytes: u64,
+	#[serde(default)]
+	inode: Option<u64>,
+	#[serde(default)]
+	fingerprint: Option<String>,
 	file_id: i32,
 	parent_id: Option<i32>,
 	is_dir: bool,
+	#[serde(default)]
+	is_symlink: bool,
 }
 
 impl IndexerJobData {
@@ -109,6 +146,10 @@ impl StatefulJob for IndexerJob {
 		INDEXER_JOB_NAME
 	}
 
+	fn priority(&self) -> JobPriority {
+		JobPriority::High
+	}
+
 	/// Creates a vector of valid path buffers from a directory, chunked into batches of `BATCH_SIZE`.
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
 		let location_path = state
@@ -135,9 +176,16 @@ impl StatefulJob for IndexerJob {
 
 		let scan_start = Instant::now();
 		let inner_ctx = ctx.clone();
+		let ignore_rules = IgnoreRules::new(
+			&location_path,
+			state.init.location.ignore_patterns.as_deref(),
+		)?;
+
 		let paths = walk(
 			location_path.clone(),
 			&indexer_rules_by_kind,
+			ignore_rules,
+			SymlinkPolicy::from_int(state.init.location.symlink_policy)?,
 			move |path, total_entries| {
 				IndexerJobData::on_scan_progress(
 					inner_ctx.clone(),
@@ -150,44 +198,168 @@ impl StatefulJob for IndexerJob {
 		)
 		.await?;
 
-		let total_paths = paths.len();
-		let last_file_id = first_file_id + total_paths as i32;
+		let fingerprints = directory_fingerprints(&paths);
+
+		// everything that was indexed by previous scans, keyed by its path on disk
+		let mut indexed = ctx
+			.library_ctx
+			.db
+			.file_path()
+			.find_many(vec![file_path::location_id::equals(state.init.location.id)])
+			.select(file_path_for_indexer::select())
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| {
+				let path = match file_path.materialized_path.trim_end_matches('/') {
+					"" => location_path.clone(),
+					materialized_path => location_path.join(materialized_path),
+				};
 
-		// Setting our global state for file_path ids
-		set_max_file_path_id(last_file_id);
+				(path, file_path)
+			})
+			.collect::<HashMap<_, _>>();
 
-		let mut dirs_ids = HashMap::new();
-		let paths_entries = paths
-			.into_iter()
-			.zip(first_file_id..last_file_id)
-			.map(
-				|(
-					WalkEntry {
-						path,
-						is_dir,
-						created_at,
-					},
-					file_id,
-				)| {
-					let parent_id = if let Some(parent_dir) = path.parent() {
-						dirs_ids.get(parent_dir).copied()
+		let mut dirs_ids = indexed
+			.iter()
+			.filter(|(_, file_path)| file_path.is_dir)
+			.map(|(path, file_path)| (path.clone(), file_path.id))
+			.collect::<HashMap<_, _>>();
+
+		let mut next_file_id = first_file_id + 1;
+		let mut paths_entries = Vec::new();
+		let mut updates = Vec::new();
+		let mut removed_ids = Vec::new();
+		let mut changed_paths = 0;
+		let mut unchanged_paths = 0;
+		let mut unchanged_dir: Option<PathBuf> = None;
+
+		// paths are sorted, so everything within an unchanged directory comes straight after it
+		for WalkEntry {
+			path,
+			is_dir,
+			is_symlink,
+			metadata,
+		} in paths
+		{
+			if let Some(unchanged_dir) = &unchanged_dir {
+				if path.starts_with(unchanged_dir) {
+					indexed.remove(&path);
+					unchanged_paths += 1;
+					continue;
+				}
+			}
+
+			let fingerprint = fingerprints.get(&path).cloned();
+
+			match indexed.remove(&path) {
+				Some(file_path) if file_path.is_dir == is_dir => {
+					let changed = if is_dir {
+						if fingerprint.is_some() && file_path.fingerprint == fingerprint {
+							unchanged_dir = Some(path);
+							unchanged_paths += 1;
+							continue;
+						}
+
+						false
 					} else {
-						None
+						match has_changed(&file_path, &metadata) {
+							Some(false) => {
+								unchanged_paths += 1;
+								continue;
+							}
+							Some(true) => true,
+							// paths that were indexed before their metadata was recorded are assumed to be unchanged, rather than all being identified again
+							None => false,
+						}
 					};
 
-					dirs_ids.insert(path.clone(), file_id);
+					let mut params = vec![
+						file_path::date_modified::set(metadata.modified_at.into()),
+						file_path::size_in_bytes::set(Some(metadata.size_in_bytes.to_string())),
+						file_path::inode::set(metadata.inode.map(|inode| inode.to_string())),
+						file_path::fingerprint::set(fingerprint),
+					];
+
+					// the file's contents may have changed, so it's unlinked from its object to be identified again
+					if changed {
+						params.push(file_path::object_id::set(None));
+						changed_paths += 1;
+					}
+
+					updates.push(ctx.library_ctx.db.file_path().update(
+						file_path::location_id_id(state.init.location.id, file_path.id),
+						params,
+					));
+				}
+				replaced => {
+					// a file that's been replaced by a directory (or the other way around) is indexed again from scratch
+					removed_ids.extend(replaced.map(|file_path| file_path.id));
+
+					let file_id = next_file_id;
+					next_file_id += 1;
+
+					let parent_id = path
+						.parent()
+						.and_then(|parent_dir| dirs_ids.get(parent_dir).copied());
+
+					if is_dir {
+						dirs_ids.insert(path.clone(), file_id);
+					}
 
-					IndexerJobStepEntry {
+					paths_entries.push(IndexerJobStepEntry {
 						path,
-						created_at,
+						created_at: metadata.created_at,
+						modified_at: metadata.modified_at,
+						size_in_bytes: metadata.size_in_bytes,
+						inode: metadata.inode,
+						fingerprint,
 						file_id,
 						parent_id,
 						is_dir,
-					}
-				},
-			)
+						is_symlink,
+					});
+				}
+			}
+		}
+
+		// anything that's left wasn't found by this scan, so it's been removed (or is now ignored)
+		removed_ids.extend(indexed.into_values().map(|file_path| file_path.id));
+
+		// Setting our global state for file_path ids
+		set_max_file_path_id(next_file_id - 1);
+
+		info!(
+			"Scan found {} new, {} changed, {} removed and {} unchanged paths",
+			paths_entries.len(),
+			changed_paths,
+			removed_ids.len(),
+			unchanged_paths
+		);
+
+		let update_chunks = updates
+			.into_iter()
+			.chunks(BATCH_SIZE)
+			.into_iter()
+			.map(|chunk| chunk.collect::<Vec<_>>())
 			.collect::<Vec<_>>();
 
+		for chunk in update_chunks {
+			ctx.library_ctx.db._batch(chunk).await?;
+		}
+
+		for chunk in removed_ids.chunks(BATCH_SIZE) {
+			ctx.library_ctx
+				.db
+				.file_path()
+				.delete_many(vec![
+					file_path::location_id::equals(state.init.location.id),
+					file_path::id::in_vec(chunk.to_vec()),
+				])
+				.exec()
+				.await?;
+		}
+
 		let total_entries = paths_entries.len();
 
 		state.data = Some(IndexerJobData {
@@ -195,6 +367,9 @@ impl StatefulJob for IndexerJob {
 			db_write_start: Utc::now(),
 			scan_read_time: scan_start.elapsed(),
 			total_paths: total_entries,
+			changed_paths,
+			removed_paths: removed_ids.len(),
+			unchanged_paths,
 		});
 
 		state.steps = paths_entries
@@ -268,7 +443,12 @@ impl StatefulJob for IndexerJob {
 					extension,
 					parent_id: entry.parent_id,
 					is_dir: entry.is_dir,
+					is_symlink: entry.is_symlink,
 					created_at: entry.created_at,
+					modified_at: entry.modified_at,
+					size_in_bytes: entry.size_in_bytes,
+					inode: entry.inode,
+					fingerprint: entry.fingerprint.clone(),
 				}
 			})
 			.collect();
@@ -281,25 +461,71 @@ impl StatefulJob for IndexerJob {
 	}
 
 	/// Logs some metadata about the indexer job
-	async fn finalize(&self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+	async fn finalize(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
 		let data = state
 			.data
 			.as_ref()
 			.expect("critical error: missing data on job state");
 		info!(
-			"scan of {} completed in {:?}. {:?} files found. db write completed in {:?}",
+			"scan of {} completed in {:?}. {:?} new, {:?} changed, {:?} removed and {:?} unchanged files found. db write completed in {:?}",
 			state.init.location.local_path.as_ref().unwrap(),
 			data.scan_read_time,
 			data.total_paths,
+			data.changed_paths,
+			data.removed_paths,
+			data.unchanged_paths,
 			(Utc::now() - data.db_write_start)
 				.to_std()
 				.expect("critical error: non-negative duration"),
 		);
 
+		// files within vaults are always encrypted, so they don't need any rules
+		if state.init.location.vault_key_id.is_none() {
+			let location_id = state.init.location.id;
+
+			let file_paths = ctx
+				.library_ctx
+				.db
+				.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(location_id),
+					file_path::is_dir::equals(false),
+				])
+				.exec()
+				.await?;
+
+			if let Err(e) = apply_encryption_rules(
+				&ctx.library_ctx,
+				location_id,
+				&data.location_path,
+				&file_paths,
+			)
+			.await
+			{
+				error!("Failed to apply encryption rules to indexed files: {e:#?}");
+			}
+		}
+
 		Ok(Some(serde_json::to_value(state)?))
 	}
 }
 
+/// This checks whether a file has changed since it was last indexed, or returns `None` if nothing was recorded about it.
+fn has_changed(
+	file_path: &file_path_for_indexer::Data,
+	metadata: &WalkEntryMetadata,
+) -> Option<bool> {
+	let size_in_bytes = file_path.size_in_bytes.as_ref()?;
+
+	// the database only keeps milliseconds
+	Some(
+		*size_in_bytes != metadata.size_in_bytes.to_string()
+			|| file_path.inode != metadata.inode.map(|inode| inode.to_string())
+			|| file_path.date_modified.timestamp_millis()
+				!= metadata.modified_at.timestamp_millis(),
+	)
+}
+
 /// Extract name from OsStr returned by PathBuff
 fn extract_name(os_string: Option<&OsStr>) -> String {
 	os_string
@@ -1,16 +1,33 @@
+mod fingerprint;
 pub mod indexer_job;
 pub mod rules;
+pub mod sdignore;
 mod walk;
 
 use globset::Error;
-use int_enum::IntEnumError;
+use int_enum::{IntEnum, IntEnumError};
 use rmp_serde::{decode::Error as RMPDecodeError, encode::Error as RMPEncodeError};
-use rspc::ErrorCode;
+use rspc::{ErrorCode, Type};
 use rules::RuleKind;
+use serde::{Deserialize, Serialize};
 use serde_json::Error as SerdeJsonError;
 use std::io;
 use thiserror::Error;
 
+/// `SymlinkPolicy` is what the indexer does with the symbolic links within a location.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, Eq, PartialEq, IntEnum, Default)]
+pub enum SymlinkPolicy {
+	/// links aren't indexed at all
+	#[default]
+	Skip = 0,
+	/// the link itself is indexed, but it isn't followed (so its target isn't identified or counted)
+	IndexAsLink = 1,
+	/// links are indexed as whatever they point to. Links to anything within the location (or that's already been followed)
+	/// are skipped, so nothing is indexed twice and looping links can't be walked forever
+	Follow = 2,
+}
+
 /// Error type for the indexer module
 #[derive(Error, Debug)]
 pub enum IndexerError {
@@ -21,8 +38,12 @@ pub enum IndexerError {
 	// User errors
 	#[error("Invalid indexer rule kind integer: {0}")]
 	InvalidRuleKindInt(#[from] IntEnumError<RuleKind>),
+	#[error("Invalid symlink policy integer: {0}")]
+	InvalidSymlinkPolicyInt(#[from] IntEnumError<SymlinkPolicy>),
 	#[error("Glob builder error: {0}")]
 	GlobBuilderError(#[from] Error),
+	#[error("Invalid ignore pattern: {0}")]
+	IgnorePatternError(#[from] ignore::Error),
 
 	// Internal Errors
 	#[error("Database error: {0}")]
@@ -44,7 +65,10 @@ impl From<IndexerError> for rspc::Error {
 				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
 			}
 
-			IndexerError::InvalidRuleKindInt(_) | IndexerError::GlobBuilderError(_) => {
+			IndexerError::InvalidRuleKindInt(_)
+			| IndexerError::InvalidSymlinkPolicyInt(_)
+			| IndexerError::GlobBuilderError(_)
+			| IndexerError::IgnorePatternError(_) => {
 				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
 			}
 
@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use tokio::{fs, io};
+use tracing::warn;
+
+use super::IndexerError;
+
+/// The name of the files that hold ignore patterns (in gitignore syntax) for the directory they're within, and everything below it.
+pub const SDIGNORE_FILE_NAME: &str = ".sdignore";
+
+/// `IgnoreRules` holds the gitignore-style patterns that exclude paths from a location, which are the location's own
+/// `ignore_patterns` alongside the patterns within every `.sdignore` file that's been loaded.
+///
+/// Patterns within deeper `.sdignore` files take precedence, so they're able to re-include (`!pattern`) paths that were ignored above them.
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+	matchers: Vec<Gitignore>,
+}
+
+impl IgnoreRules {
+	/// This builds the location's own patterns (one per line), which are relative to the location's root.
+	pub fn new(root: impl AsRef<Path>, patterns: Option<&str>) -> Result<Self, IndexerError> {
+		let mut rules = Self::default();
+
+		if let Some(patterns) = patterns {
+			let mut builder = GitignoreBuilder::new(root);
+
+			for line in patterns.lines() {
+				builder.add_line(None, line)?;
+			}
+
+			rules.matchers.push(builder.build()?);
+		}
+
+		Ok(rules)
+	}
+
+	/// This loads the `.sdignore` file within a directory, if there is one.
+	///
+	/// Invalid patterns are skipped (with a warning), so a typo doesn't stop the whole location from being indexed.
+	pub async fn load_dir(&mut self, dir: impl AsRef<Path>) {
+		let dir = dir.as_ref();
+		let path = dir.join(SDIGNORE_FILE_NAME);
+
+		let contents = match fs::read_to_string(&path).await {
+			Ok(contents) => contents,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+			Err(e) => {
+				warn!("Unable to read {}: {:#?}", path.display(), e);
+				return;
+			}
+		};
+
+		let mut builder = GitignoreBuilder::new(dir);
+
+		for line in contents.lines() {
+			if let Err(e) = builder.add_line(Some(path.clone()), line) {
+				warn!("Skipping invalid pattern within {}: {}", path.display(), e);
+			}
+		}
+
+		match builder.build() {
+			Ok(matcher) => self.matchers.push(matcher),
+			Err(e) => warn!("Unable to build patterns from {}: {}", path.display(), e),
+		}
+	}
+
+	/// This checks a path against every set of patterns that applies to it.
+	///
+	/// The path's ancestors aren't checked, as the indexer never walks into a directory that's ignored (use [`is_path_ignored`] for a path on its own).
+	pub fn is_ignored(&self, path: impl AsRef<Path>, is_dir: bool) -> bool {
+		let path = path.as_ref();
+
+		// matchers are loaded from the top of the location down, so the deepest ones are last
+		self.matchers
+			.iter()
+			.rev()
+			.filter(|matcher| path.starts_with(matcher.path()))
+			.map(|matcher| matcher.matched(path, is_dir))
+			.find(|matched| !matched.is_none())
+			.map_or(false, |matched| matched.is_ignore())
+	}
+}
+
+/// This checks whether a single path within a location is ignored, either directly or as one of its ancestors is.
+///
+/// The `.sdignore` files along the way are loaded, so this is used for paths that weren't reached by walking the location (e.g. by the watcher).
+pub async fn is_path_ignored(
+	root: impl AsRef<Path>,
+	patterns: Option<&str>,
+	path: impl AsRef<Path>,
+	is_dir: bool,
+) -> Result<bool, IndexerError> {
+	let root = root.as_ref();
+
+	let relative_path = match path.as_ref().strip_prefix(root) {
+		Ok(relative_path) => relative_path,
+		Err(_) => return Ok(false),
+	};
+
+	let mut rules = IgnoreRules::new(root, patterns)?;
+	rules.load_dir(root).await;
+
+	let components = relative_path.components().collect::<Vec<_>>();
+	let mut current_path = root.to_path_buf();
+
+	for (i, component) in components.iter().enumerate() {
+		current_path.push(component);
+
+		let is_last = i == components.len() - 1;
+		let current_is_dir = !is_last || is_dir;
+
+		if rules.is_ignored(&current_path, current_is_dir) {
+			return Ok(true);
+		}
+
+		if !is_last {
+			rules.load_dir(&current_path).await;
+		}
+	}
+
+	Ok(false)
+}
@@ -2,24 +2,61 @@ use chrono::{DateTime, Utc};
 use std::{
 	cmp::Ordering,
 	collections::{HashMap, VecDeque},
+	fs::Metadata,
 	hash::{Hash, Hasher},
+	io,
 	path::{Path, PathBuf},
 };
 use tokio::fs;
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
 use super::{
 	rules::{IndexerRule, RuleKind},
-	IndexerError,
+	sdignore::IgnoreRules,
+	IndexerError, SymlinkPolicy,
 };
 
+/// Directories that are nested deeper than this (below the location's root) aren't walked, as a last resort against looping links.
+const MAX_WALK_DEPTH: usize = 256;
+
 /// `WalkEntry` represents a single path in the filesystem, for any comparison purposes, we only
 /// consider the path itself, not the metadata.
 #[derive(Clone, Debug)]
 pub(super) struct WalkEntry {
 	pub(super) path: PathBuf,
 	pub(super) is_dir: bool,
+	pub(super) is_symlink: bool,
+	pub(super) metadata: WalkEntryMetadata,
+}
+
+/// `WalkEntryMetadata` is what's recorded about each path, so the next scan can tell whether it's changed.
+#[derive(Clone, Debug, Default)]
+pub(super) struct WalkEntryMetadata {
 	pub(super) created_at: DateTime<Utc>,
+	pub(super) modified_at: DateTime<Utc>,
+	pub(super) size_in_bytes: u64,
+	/// this is `None` on platforms that don't expose inodes (e.g. Windows)
+	pub(super) inode: Option<u64>,
+}
+
+impl WalkEntryMetadata {
+	fn new(metadata: &Metadata) -> Result<Self, io::Error> {
+		#[cfg(unix)]
+		let inode = {
+			use std::os::unix::fs::MetadataExt;
+
+			Some(metadata.ino())
+		};
+		#[cfg(not(unix))]
+		let inode = None;
+
+		Ok(Self {
+			created_at: metadata.created()?.into(),
+			modified_at: metadata.modified()?.into(),
+			size_in_bytes: metadata.len(),
+			inode,
+		})
+	}
 }
 
 impl PartialEq for WalkEntry {
@@ -51,16 +88,24 @@ impl Ord for WalkEntry {
 /// This function walks through the filesystem, applying the rules to each entry and then returning
 /// a list of accepted entries. There are some useful comments in the implementation of this function
 /// in case of doubts.
+///
+/// Paths that are ignored (by the location's ignore patterns, or a `.sdignore` file) are skipped before any rules are applied.
 pub(super) async fn walk(
 	root: PathBuf,
 	rules_per_kind: &HashMap<RuleKind, Vec<IndexerRule>>,
+	mut ignore_rules: IgnoreRules,
+	symlink_policy: SymlinkPolicy,
 	update_notifier: impl Fn(&Path, usize),
 ) -> Result<Vec<WalkEntry>, IndexerError> {
 	let mut to_walk = VecDeque::with_capacity(1);
-	to_walk.push_back((root.clone(), None));
+	to_walk.push_back((root.clone(), None, 0));
 	let mut indexed_paths = HashMap::new();
 
-	while let Some((current_path, parent_dir_accepted_by_its_children)) = to_walk.pop_front() {
+	// these are the real paths of everything that's walked, so a followed link is skipped if it points to any of them
+	let mut walked_targets = vec![fs::canonicalize(&root).await?];
+
+	while let Some((current_path, parent_dir_accepted_by_its_children, depth)) = to_walk.pop_front()
+	{
 		let mut read_dir = match fs::read_dir(&current_path).await {
 			Ok(read_dir) => read_dir,
 			Err(e) => {
@@ -73,6 +118,9 @@ pub(super) async fn walk(
 			}
 		};
 
+		// the directory's own `.sdignore` applies to everything within it
+		ignore_rules.load_dir(&current_path).await;
+
 		// Marking with a loop label here in case of rejection or erros, to continue with next entry
 		'entries: loop {
 			let entry = match read_dir.next_entry().await {
@@ -120,13 +168,28 @@ pub(super) async fn walk(
 
 			let metadata = entry.metadata().await?;
 
-			// TODO: Hard ignoring symlinks for now, but this should be configurable
-			if metadata.is_symlink() {
-				continue 'entries;
-			}
+			let (metadata, is_symlink) = if metadata.is_symlink() {
+				match symlink_policy {
+					SymlinkPolicy::Skip => continue 'entries,
+					SymlinkPolicy::IndexAsLink => (metadata, true),
+					SymlinkPolicy::Follow => {
+						match follow_symlink(&current_path, &mut walked_targets).await {
+							Some(metadata) => (metadata, false),
+							None => continue 'entries,
+						}
+					}
+				}
+			} else {
+				(metadata, false)
+			};
 
 			let is_dir = metadata.is_dir();
 
+			if ignore_rules.is_ignored(&current_path, is_dir) {
+				debug!("Path {} ignored", current_path.display());
+				continue 'entries;
+			}
+
 			if is_dir {
 				// If it is a directory, first we check if we must reject it and its children entirely
 				if let Some(reject_by_children_rules) =
@@ -190,7 +253,15 @@ pub(super) async fn walk(
 				}
 
 				// Then we mark this directory the be walked in too
-				to_walk.push_back((entry.path(), accept_by_children_dir));
+				if depth < MAX_WALK_DEPTH {
+					to_walk.push_back((entry.path(), accept_by_children_dir, depth + 1));
+				} else {
+					warn!(
+						"Not walking {}, as it's nested more than {} directories deep",
+						current_path.display(),
+						MAX_WALK_DEPTH
+					);
+				}
 			}
 
 			let mut accept_by_glob = false;
@@ -227,7 +298,8 @@ pub(super) async fn walk(
 					WalkEntry {
 						path: current_path.clone(),
 						is_dir,
-						created_at: metadata.created()?.into(),
+						is_symlink,
+						metadata: WalkEntryMetadata::new(&metadata)?,
 					},
 				);
 
@@ -244,7 +316,8 @@ pub(super) async fn walk(
 							WalkEntry {
 								path: ancestor.to_path_buf(),
 								is_dir: true,
-								created_at: fs::metadata(ancestor).await?.created()?.into(),
+								is_symlink: false,
+								metadata: WalkEntryMetadata::new(&fs::metadata(ancestor).await?)?,
 							},
 						);
 					} else {
@@ -259,11 +332,12 @@ pub(super) async fn walk(
 
 	let mut indexed_paths = indexed_paths.into_values().collect::<Vec<_>>();
 	// Also adding the root location path
-	let root_created_at = fs::metadata(&root).await?.created()?.into();
+	let root_metadata = WalkEntryMetadata::new(&fs::metadata(&root).await?)?;
 	indexed_paths.push(WalkEntry {
 		path: root,
 		is_dir: true,
-		created_at: root_created_at,
+		is_symlink: false,
+		metadata: root_metadata,
 	});
 	// Sorting so we can give each path a crescent id given the filesystem hierarchy
 	indexed_paths.sort();
@@ -271,11 +345,41 @@ pub(super) async fn walk(
 	Ok(indexed_paths)
 }
 
+/// This resolves a link that's being followed, and returns its target's metadata.
+///
+/// It returns `None` if the link should be skipped instead: as it's broken, or its target is within something that's already been walked
+/// (which includes the whole location), so it'd be indexed twice or loop forever.
+async fn follow_symlink(path: &Path, walked_targets: &mut Vec<PathBuf>) -> Option<Metadata> {
+	let target = match fs::canonicalize(path).await {
+		Ok(target) => target,
+		Err(e) => {
+			debug!("Skipping broken link {}: {:#?}", path.display(), e);
+			return None;
+		}
+	};
+
+	if walked_targets
+		.iter()
+		.any(|walked| target.starts_with(walked))
+	{
+		debug!(
+			"Skipping link {}, as {} has already been walked",
+			path.display(),
+			target.display()
+		);
+		return None;
+	}
+
+	let metadata = fs::metadata(&target).await.ok()?;
+	walked_targets.push(target);
+
+	Some(metadata)
+}
+
 #[cfg(test)]
 mod tests {
 	use super::super::rules::ParametersPerKind;
 	use super::*;
-	use chrono::Utc;
 	use globset::Glob;
 	use std::collections::BTreeSet;
 	use tempfile::{tempdir, TempDir};
@@ -344,42 +448,48 @@ mod tests {
 		let root = prepare_location().await;
 		let root_path = root.path();
 
-		let any_datetime = Utc::now();
+		let any_metadata = WalkEntryMetadata::default();
 
 		#[rustfmt::skip]
 		let expected = [
-			WalkEntry { path: root_path.to_path_buf(), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/.git"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/Cargo.toml"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/src"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/src/main.rs"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/target"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/target/debug"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/target/debug/main"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/.git"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/package.json"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/src"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/src/App.tsx"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/node_modules"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/node_modules/react"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/node_modules/react/package.json"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("photos"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("photos/photo1.png"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("photos/photo2.jpg"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("photos/photo3.jpeg"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("photos/text.txt"), is_dir: false, created_at: any_datetime },
+			WalkEntry { path: root_path.to_path_buf(), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/.git"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/Cargo.toml"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/src"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/src/main.rs"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/target"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/target/debug"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/target/debug/main"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/.git"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/package.json"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/src"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/src/App.tsx"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/node_modules"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/node_modules/react"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/node_modules/react/package.json"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos/photo1.png"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos/photo2.jpg"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos/photo3.jpeg"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos/text.txt"), is_dir: false, is_symlink: false, metadata: any_metadata },
 		]
 		.into_iter()
 		.collect::<BTreeSet<_>>();
 
-		let actual = walk(root_path.to_path_buf(), &HashMap::new(), |_, _| {})
-			.await
-			.unwrap()
-			.into_iter()
-			.collect::<BTreeSet<_>>();
+		let actual = walk(
+			root_path.to_path_buf(),
+			&HashMap::new(),
+			IgnoreRules::default(),
+			SymlinkPolicy::Skip,
+			|_, _| {},
+		)
+		.await
+		.unwrap()
+		.into_iter()
+		.collect::<BTreeSet<_>>();
 
 		assert_eq!(actual, expected);
 	}
@@ -390,15 +500,15 @@ mod tests {
 		let root = prepare_location().await;
 		let root_path = root.path();
 
-		let any_datetime = Utc::now();
+		let any_metadata = WalkEntryMetadata::default();
 
 		#[rustfmt::skip]
 		let expected = [
-			WalkEntry { path: root_path.to_path_buf(), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("photos"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("photos/photo1.png"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("photos/photo2.jpg"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("photos/photo3.jpeg"), is_dir: false, created_at: any_datetime },
+			WalkEntry { path: root_path.to_path_buf(), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos/photo1.png"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos/photo2.jpg"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos/photo3.jpeg"), is_dir: false, is_symlink: false, metadata: any_metadata },
 		]
 		.into_iter()
 		.collect::<BTreeSet<_>>();
@@ -414,11 +524,17 @@ mod tests {
 		.into_iter()
 		.collect::<HashMap<_, _>>();
 
-		let actual = walk(root_path.to_path_buf(), &only_photos_rule, |_, _| {})
-			.await
-			.unwrap()
-			.into_iter()
-			.collect::<BTreeSet<_>>();
+		let actual = walk(
+			root_path.to_path_buf(),
+			&only_photos_rule,
+			IgnoreRules::default(),
+			SymlinkPolicy::Skip,
+			|_, _| {},
+		)
+		.await
+		.unwrap()
+		.into_iter()
+		.collect::<BTreeSet<_>>();
 
 		assert_eq!(actual, expected);
 	}
@@ -429,28 +545,28 @@ mod tests {
 		let root = prepare_location().await;
 		let root_path = root.path();
 
-		let any_datetime = Utc::now();
+		let any_metadata = WalkEntryMetadata::default();
 
 		#[rustfmt::skip]
 		let expected = [
-			WalkEntry { path: root_path.to_path_buf(), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/.git"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/Cargo.toml"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/src"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/src/main.rs"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/target"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/target/debug"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/target/debug/main"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/.git"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/package.json"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/src"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/src/App.tsx"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/node_modules"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/node_modules/react"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/node_modules/react/package.json"), is_dir: false, created_at: any_datetime },
+			WalkEntry { path: root_path.to_path_buf(), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/.git"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/Cargo.toml"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/src"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/src/main.rs"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/target"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/target/debug"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/target/debug/main"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/.git"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/package.json"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/src"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/src/App.tsx"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/node_modules"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/node_modules/react"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/node_modules/react/package.json"), is_dir: false, is_symlink: false, metadata: any_metadata },
 		]
 		.into_iter()
 		.collect::<BTreeSet<_>>();
@@ -468,11 +584,17 @@ mod tests {
 		.into_iter()
 		.collect::<HashMap<_, _>>();
 
-		let actual = walk(root_path.to_path_buf(), &git_repos, |_, _| {})
-			.await
-			.unwrap()
-			.into_iter()
-			.collect::<BTreeSet<_>>();
+		let actual = walk(
+			root_path.to_path_buf(),
+			&git_repos,
+			IgnoreRules::default(),
+			SymlinkPolicy::Skip,
+			|_, _| {},
+		)
+		.await
+		.unwrap()
+		.into_iter()
+		.collect::<BTreeSet<_>>();
 
 		assert_eq!(actual, expected);
 	}
@@ -483,22 +605,22 @@ mod tests {
 		let root = prepare_location().await;
 		let root_path = root.path();
 
-		let any_datetime = Utc::now();
+		let any_metadata = WalkEntryMetadata::default();
 
 		#[rustfmt::skip]
 		let expected = [
-			WalkEntry { path: root_path.to_path_buf(), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/.git"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/Cargo.toml"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/src"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("rust_project/src/main.rs"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/.git"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/package.json"), is_dir: false, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/src"), is_dir: true, created_at: any_datetime.clone() },
-			WalkEntry { path: root_path.join("inner/node_project/src/App.tsx"), is_dir: false, created_at: any_datetime },
+			WalkEntry { path: root_path.to_path_buf(), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/.git"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/Cargo.toml"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/src"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/src/main.rs"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/.git"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/package.json"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/src"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/src/App.tsx"), is_dir: false, is_symlink: false, metadata: any_metadata },
 		]
 		.into_iter()
 		.collect::<BTreeSet<_>>();
@@ -540,6 +662,118 @@ mod tests {
 		let actual = walk(
 			root_path.to_path_buf(),
 			&git_repos_no_deps_no_build_dirs,
+			IgnoreRules::default(),
+			SymlinkPolicy::Skip,
+			|_, _| {},
+		)
+		.await
+		.unwrap()
+		.into_iter()
+		.collect::<BTreeSet<_>>();
+
+		assert_eq!(actual, expected);
+	}
+
+	#[tokio::test]
+	#[traced_test]
+	async fn test_sdignore() {
+		let root = prepare_location().await;
+		let root_path = root.path();
+
+		// the location ignores git and build directories (and text files), while the node project ignores its dependencies
+		fs::write(
+			root_path.join("inner/node_project/.sdignore"),
+			"node_modules/\n",
+		)
+		.await
+		.unwrap();
+
+		let any_metadata = WalkEntryMetadata::default();
+
+		#[rustfmt::skip]
+		let expected = [
+			WalkEntry { path: root_path.to_path_buf(), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/Cargo.toml"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/src"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("rust_project/src/main.rs"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/.sdignore"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/package.json"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/src"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("inner/node_project/src/App.tsx"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos/photo1.png"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos/photo2.jpg"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos/photo3.jpeg"), is_dir: false, is_symlink: false, metadata: any_metadata },
+		]
+		.into_iter()
+		.collect::<BTreeSet<_>>();
+
+		let actual = walk(
+			root_path.to_path_buf(),
+			&HashMap::new(),
+			IgnoreRules::new(root_path, Some(".git/\ntarget/\n*.txt")).unwrap(),
+			SymlinkPolicy::Skip,
+			|_, _| {},
+		)
+		.await
+		.unwrap()
+		.into_iter()
+		.collect::<BTreeSet<_>>();
+
+		assert_eq!(actual, expected);
+	}
+
+	#[cfg(unix)]
+	#[tokio::test]
+	#[traced_test]
+	async fn test_follow_symlinks() {
+		use std::os::unix::fs::symlink;
+
+		let root = tempdir().unwrap();
+		let root_path = root.path();
+		let outside = tempdir().unwrap();
+		let outside_path = outside.path();
+
+		fs::create_dir(root_path.join("photos")).await.unwrap();
+		fs::File::create(root_path.join("photos/photo1.png"))
+			.await
+			.unwrap();
+		fs::File::create(outside_path.join("photo2.png"))
+			.await
+			.unwrap();
+
+		// a link back to the location's root would loop forever, and a link within the location would index it twice
+		symlink(root_path, root_path.join("photos/loop")).unwrap();
+		symlink(
+			root_path.join("photos/photo1.png"),
+			root_path.join("duplicate.png"),
+		)
+		.unwrap();
+		// the second link to the same directory outside of the location is also skipped
+		symlink(outside_path, root_path.join("outside")).unwrap();
+		symlink(outside_path, root_path.join("photos/outside_again")).unwrap();
+
+		let any_metadata = WalkEntryMetadata::default();
+
+		#[rustfmt::skip]
+		let expected = [
+			WalkEntry { path: root_path.to_path_buf(), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("outside"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("outside/photo2.png"), is_dir: false, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos"), is_dir: true, is_symlink: false, metadata: any_metadata.clone() },
+			WalkEntry { path: root_path.join("photos/photo1.png"), is_dir: false, is_symlink: false, metadata: any_metadata },
+		]
+		.into_iter()
+		.collect::<BTreeSet<_>>();
+
+		let actual = walk(
+			root_path.to_path_buf(),
+			&HashMap::new(),
+			IgnoreRules::default(),
+			SymlinkPolicy::Follow,
 			|_, _| {},
 		)
 		.await
@@ -1,4 +1,4 @@
-use crate::library::LibraryContext;
+use crate::{library::LibraryContext, location::indexer::IndexerError};
 
 use std::{path::PathBuf, sync::Arc};
 
@@ -44,6 +44,8 @@ pub enum LocationManagerError {
 	DatabaseError(#[from] prisma_client_rust::QueryError),
 	#[error("I/O error: {0}")]
 	IOError(#[from] io::Error),
+	#[error("Indexer error: {0}")]
+	IndexerError(#[from] IndexerError),
 }
 
 #[derive(Debug)]
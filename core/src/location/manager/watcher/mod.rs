@@ -13,7 +13,7 @@ use tokio::{
 	sync::{mpsc, oneshot},
 	task::{block_in_place, JoinHandle},
 };
-use tracing::{debug, error, warn};
+use tracing::{debug, error, trace, warn};
 
 use super::{
 	super::{fetch_location, indexer::indexer_job::indexer_job_location},
@@ -26,7 +26,7 @@ mod windows;
 
 mod utils;
 
-use utils::{check_event, check_location_online};
+use utils::{check_event, check_location_online, is_event_ignored};
 
 #[cfg(target_os = "linux")]
 type Handler = linux::LinuxEventHandler;
@@ -160,12 +160,14 @@ impl LocationWatcher {
 				.exec()
 				.await?
 			{
-				if check_location_online(&location) {
+				if !check_location_online(&location) {
+					warn!("Tried to handle event for offline location: <id='{location_id}'>");
+				} else if is_event_ignored(&location, &event).await? {
+					trace!("Ignoring event for ignored paths: {:?}", event.paths);
+				} else {
 					return event_handler
 						.handle_event(location, library_ctx, event)
 						.await;
-				} else {
-					warn!("Tried to handle event for offline location: <id='{location_id}'>");
 				}
 			} else {
 				warn!("Tried to handle event for unknown location: <id='{location_id}'>");
@@ -1,34 +1,49 @@
 use crate::{
 	invalidate_query,
+	job::Job,
 	library::LibraryContext,
 	location::{
 		delete_directory,
 		file_path_helper::create_file_path,
-		indexer::indexer_job::indexer_job_location,
+		indexer::{indexer_job::indexer_job_location, sdignore::is_path_ignored},
 		manager::{helpers::subtract_location_path, LocationId, LocationManagerError},
 	},
 	object::{
+		fs::{
+			encrypt::{FileEncryptorJob, FileEncryptorJobInit},
+			policy::apply_encryption_rules,
+			ConflictStrategy, ObjectTarget,
+		},
 		identifier_job::{assemble_object_metadata, ObjectCreationMetadata},
 		preview::{
-			can_generate_thumbnail_for_image, generate_image_thumbnail, THUMBNAIL_CACHE_DIR_NAME,
+			can_extract_audio_tags, can_generate_thumbnail_for_image, encrypt_thumbnail,
+			generate_audio_thumbnail, generate_image_thumbnail, EncryptedThumbnailError,
+			THUMBNAIL_CACHE_DIR_NAME,
 		},
 		validation::hash::file_checksum,
+		version::{fetch_versioned_location, snapshot_file},
 	},
-	prisma::{file_path, object},
+	prisma::{file_path, key, object},
 };
 
 use std::{
+	collections::VecDeque,
 	path::{Path, PathBuf},
 	str::FromStr,
 };
 
 use chrono::{FixedOffset, Utc};
 use int_enum::IntEnum;
-use notify::{event::RemoveKind, Event};
+use notify::{
+	event::{CreateKind, RemoveKind},
+	Event, EventKind,
+};
 use prisma_client_rust::{raw, PrismaValue};
-use sd_file_ext::extensions::ImageExtension;
+use sd_crypto::crypto::stream::Algorithm;
+use sd_file_ext::extensions::{AudioExtension, ImageExtension};
 use tokio::{fs, io::ErrorKind};
 use tracing::{error, info, trace, warn};
+use uuid::Uuid;
 
 use super::file_path_with_object;
 
@@ -59,58 +74,167 @@ pub(super) fn check_event(event: &Event) -> bool {
 	true
 }
 
+/// This checks whether every path within an event is ignored by the location (or a `.sdignore` file), in which case the event is dropped.
+///
+/// Paths that no longer exist (e.g. once they've been removed) are checked as files.
+pub(super) async fn is_event_ignored(
+	location: &indexer_job_location::Data,
+	event: &Event,
+) -> Result<bool, LocationManagerError> {
+	let location_local_path = location
+		.local_path
+		.as_ref()
+		.ok_or(LocationManagerError::LocationMissingLocalPath(location.id))?;
+
+	for path in &event.paths {
+		let is_dir = fs::metadata(path)
+			.await
+			.map(|metadata| metadata.is_dir())
+			.unwrap_or(false);
+
+		if !is_path_ignored(
+			location_local_path,
+			location.ignore_patterns.as_deref(),
+			path,
+			is_dir,
+		)
+		.await?
+		{
+			return Ok(false);
+		}
+	}
+
+	Ok(!event.paths.is_empty())
+}
+
 pub(super) async fn create_dir(
 	location: indexer_job_location::Data,
 	event: Event,
 	library_ctx: LibraryContext,
 ) -> Result<(), LocationManagerError> {
 	if let Some(ref location_local_path) = location.local_path {
-		trace!(
-			"Location: <root_path ='{location_local_path}'> creating directory: {}",
-			event.paths[0].display()
-		);
+		if !inner_create_dir(
+			&location,
+			location_local_path,
+			&event.paths[0],
+			&library_ctx,
+		)
+		.await?
+		{
+			return Ok(());
+		}
 
-		if let Some(subpath) = subtract_location_path(location_local_path, &event.paths[0]) {
-			let parent_directory = get_parent_dir(location.id, &subpath, &library_ctx).await?;
-
-			trace!("parent_directory: {:?}", parent_directory);
-
-			if let Some(parent_directory) = parent_directory {
-				let created_path = create_file_path(
-					&library_ctx,
-					location.id,
-					subpath.to_str().expect("Found non-UTF-8 path").to_string(),
-					subpath
-						.file_stem()
-						.unwrap()
-						.to_str()
-						.expect("Found non-UTF-8 path")
-						.to_string(),
-					None,
-					Some(parent_directory.id),
-					true,
-				)
-				.await?;
+		// a directory that's moved (or copied) into the location only dispatches a single event, so its contents are indexed here
+		let mut dirs = VecDeque::from([event.paths[0].clone()]);
+
+		while let Some(dir) = dirs.pop_front() {
+			let mut entries = fs::read_dir(&dir).await?;
 
-				info!("Created path: {}", created_path.materialized_path);
+			while let Some(entry) = entries.next_entry().await? {
+				let path = entry.path();
+				let is_dir = entry.file_type().await?.is_dir();
+
+				if is_path_ignored(
+					location_local_path,
+					location.ignore_patterns.as_deref(),
+					&path,
+					is_dir,
+				)
+				.await?
+				{
+					continue;
+				}
 
-				invalidate_query!(library_ctx, "locations.getExplorerData");
-			} else {
-				warn!("Watcher found a path without parent");
+				if is_dir {
+					if inner_create_dir(&location, location_local_path, &path, &library_ctx).await?
+					{
+						dirs.push_back(path);
+					}
+				} else {
+					// the file may have already dispatched its own event, so it's updated rather than created twice
+					file_creation_or_update(
+						location.clone(),
+						Event::new(EventKind::Create(CreateKind::File)).add_path(path),
+						&library_ctx,
+					)
+					.await?;
+				}
 			}
 		}
+
+		invalidate_query!(library_ctx, "locations.getExplorerData");
+		invalidate_query!(library_ctx, "savedSearches.getExplorerData");
 	}
 
 	Ok(())
 }
 
+/// This creates a single directory's file path, and returns whether it was created (rather than already existing, or not having a parent).
+async fn inner_create_dir(
+	location: &indexer_job_location::Data,
+	location_local_path: &str,
+	path: &Path,
+	library_ctx: &LibraryContext,
+) -> Result<bool, LocationManagerError> {
+	trace!(
+		"Location: <root_path ='{location_local_path}'> creating directory: {}",
+		path.display()
+	);
+
+	if get_existing_file_path(location, path, true, library_ctx)
+		.await?
+		.is_some()
+	{
+		return Ok(false);
+	}
+
+	if let Some(subpath) = subtract_location_path(location_local_path, path) {
+		let parent_directory = get_parent_dir(location.id, &subpath, library_ctx).await?;
+
+		trace!("parent_directory: {:?}", parent_directory);
+
+		if let Some(parent_directory) = parent_directory {
+			let created_path = create_file_path(
+				library_ctx,
+				location.id,
+				subpath.to_str().expect("Found non-UTF-8 path").to_string(),
+				subpath
+					.file_stem()
+					.unwrap()
+					.to_str()
+					.expect("Found non-UTF-8 path")
+					.to_string(),
+				None,
+				Some(parent_directory.id),
+				true,
+			)
+			.await?;
+
+			info!("Created path: {}", created_path.materialized_path);
+
+			return Ok(true);
+		} else {
+			warn!("Watcher found a path without parent");
+		}
+	}
+
+	Ok(false)
+}
+
 pub(super) async fn create_file(
 	location: indexer_job_location::Data,
 	event: Event,
 	library_ctx: LibraryContext,
 ) -> Result<(), LocationManagerError> {
 	if let Some(ref location_local_path) = location.local_path {
-		inner_create_file(location.id, location_local_path, event, &library_ctx).await
+		inner_create_file(
+			location.id,
+			location.vault_key_id,
+			location_local_path,
+			event,
+			&library_ctx,
+		)
+		.await
 	} else {
 		Err(LocationManagerError::LocationMissingLocalPath(location.id))
 	}
@@ -118,6 +242,7 @@ pub(super) async fn create_file(
 
 async fn inner_create_file(
 	location_id: LocationId,
+	vault_key_id: Option<i32>,
 	location_local_path: &str,
 	event: Event,
 	library_ctx: &LibraryContext,
@@ -163,6 +288,7 @@ async fn inner_create_file(
 				size_str,
 				kind,
 				date_created,
+				content_hash,
 			} = assemble_object_metadata(location_local_path, &created_file).await?;
 
 			// upsert object because in can be from a file that previously existed and was moved
@@ -177,6 +303,8 @@ async fn inner_create_file(
 						vec![
 							object::date_created::set(date_created),
 							object::kind::set(kind.int_value()),
+							object::content_hash::set(Some(content_hash.hash)),
+							object::content_hash_kind::set(Some(content_hash.kind.int_value())),
 						],
 					),
 					vec![
@@ -200,13 +328,37 @@ async fn inner_create_file(
 				.await?;
 
 			trace!("object: {:#?}", object);
+
 			if !object.has_thumbnail {
 				if let Some(ref extension) = created_file.extension {
 					generate_thumbnail(extension, &cas_id, &event.paths[0], library_ctx).await;
 				}
 			}
 
+			if let Some(vault_key_id) = vault_key_id {
+				encrypt_vault_file(
+					location_id,
+					vault_key_id,
+					&created_file,
+					&cas_id,
+					library_ctx,
+				)
+				.await?;
+			} else if let Err(e) = apply_encryption_rules(
+				library_ctx,
+				location_id,
+				Path::new(location_local_path),
+				std::slice::from_ref(&created_file),
+			)
+			.await
+			{
+				error!("Failed to apply encryption rules to created file: {e:#?}");
+			}
+
+			snapshot_version(location_id, &created_file.materialized_path, library_ctx).await;
+
 			invalidate_query!(library_ctx, "locations.getExplorerData");
+			invalidate_query!(library_ctx, "savedSearches.getExplorerData");
 		} else {
 			warn!("Watcher found a path without parent");
 		}
@@ -215,6 +367,78 @@ async fn inner_create_file(
 	Ok(())
 }
 
+/// This dispatches a job for encrypting a file that was added to a vault, which replaces the plaintext with an encrypted copy.
+///
+/// Its thumbnail is encrypted too, as it'd leak the file's contents otherwise.
+///
+/// If the vault's key isn't mounted, the file is left as-is (with a warning).
+async fn encrypt_vault_file(
+	location_id: LocationId,
+	vault_key_id: i32,
+	file_path: &file_path::Data,
+	cas_id: &str,
+	library_ctx: &LibraryContext,
+) -> Result<(), LocationManagerError> {
+	if file_path.extension.as_deref() == Some("sdenc") {
+		return Ok(());
+	}
+
+	let key_uuid = match library_ctx
+		.db
+		.key()
+		.find_unique(key::id::equals(vault_key_id))
+		.exec()
+		.await?
+		.and_then(|key| Uuid::from_str(&key.uuid).ok())
+	{
+		Some(uuid) => uuid,
+		None => {
+			warn!("Vault key not found for location: <id='{location_id}'>");
+			return Ok(());
+		}
+	};
+
+	if !library_ctx.key_manager.keymount_contains(key_uuid) {
+		warn!(
+			"Vault key isn't mounted, so {} was left unencrypted",
+			file_path.materialized_path
+		);
+		return Ok(());
+	}
+
+	if let Err(e) = encrypt_thumbnail(library_ctx, key_uuid, cas_id).await {
+		match e {
+			EncryptedThumbnailError::Io(e) if e.kind() == ErrorKind::NotFound => {}
+			e => error!("Failed to encrypt thumbnail for vault file: {e:#?}"),
+		}
+	}
+
+	library_ctx
+		.spawn_job(Job::new(
+			FileEncryptorJobInit {
+				location_id,
+				targets: vec![ObjectTarget::FilePath(file_path.id)],
+				key_uuid,
+				algorithm: Algorithm::XChaCha20Poly1305,
+				metadata: false,
+				preview_media: true,
+				output_directory: None,
+				on_conflict: ConflictStrategy::Error,
+				erase_original: true,
+				encrypt_filename: true,
+				random_filename: false,
+				dry_run: false,
+				only_file_paths: None,
+				rule_id: None,
+				upload: None,
+			},
+			FileEncryptorJob {},
+		))
+		.await;
+
+	Ok(())
+}
+
 pub(super) async fn file_creation_or_update(
 	location: indexer_job_location::Data,
 	event: Event,
@@ -227,7 +451,14 @@ pub(super) async fn file_creation_or_update(
 			inner_update_file(location_local_path, file_path, event, library_ctx).await
 		} else {
 			// We received None because it is a new file
-			inner_create_file(location.id, location_local_path, event, library_ctx).await
+			inner_create_file(
+				location.id,
+				location.vault_key_id,
+				location_local_path,
+				event,
+				library_ctx,
+			)
+			.await
 		}
 	} else {
 		Err(LocationManagerError::LocationMissingLocalPath(location.id))
@@ -245,6 +476,7 @@ pub(super) async fn update_file(
 		{
 			let ret = inner_update_file(location_local_path, file_path, event, library_ctx).await;
 			invalidate_query!(library_ctx, "locations.getExplorerData");
+			invalidate_query!(library_ctx, "savedSearches.getExplorerData");
 			ret
 		} else {
 			Err(LocationManagerError::UpdateNonExistingFile(
@@ -279,6 +511,10 @@ async fn inner_update_file(
 		object_id: file_path.object_id,
 		object: None,
 		parent_id: file_path.parent_id,
+		is_symlink: file_path.is_symlink,
+		size_in_bytes: file_path.size_in_bytes,
+		inode: file_path.inode,
+		fingerprint: file_path.fingerprint,
 		key_id: file_path.key_id,
 		date_created: file_path.date_created,
 		date_modified: file_path.date_modified,
@@ -290,6 +526,7 @@ async fn inner_update_file(
 		size_str,
 		kind,
 		date_created,
+		content_hash,
 	} = assemble_object_metadata(location_local_path, &file_path_only).await?;
 
 	if let Some(ref object) = file_path.object {
@@ -305,6 +542,8 @@ async fn inner_update_file(
 						object::size_in_bytes::set(size_str),
 						object::kind::set(kind.int_value()),
 						object::date_modified::set(date_created),
+						object::content_hash::set(Some(content_hash.hash)),
+						object::content_hash_kind::set(Some(content_hash.kind.int_value())),
 						object::integrity_checksum::set(if object.integrity_checksum.is_some() {
 							// If a checksum was already computed, we need to recompute it
 							Some(file_checksum(&event.paths[0]).await?)
@@ -325,11 +564,38 @@ async fn inner_update_file(
 		}
 	}
 
+	snapshot_version(
+		file_path_only.location_id,
+		&file_path_only.materialized_path,
+		library_ctx,
+	)
+	.await;
+
 	invalidate_query!(library_ctx, "locations.getExplorerData");
+	invalidate_query!(library_ctx, "savedSearches.getExplorerData");
 
 	Ok(())
 }
 
+/// This takes a snapshot of a file that was created or changed, if its location is versioned.
+async fn snapshot_version(
+	location_id: LocationId,
+	materialized_path: &str,
+	library_ctx: &LibraryContext,
+) {
+	let result = match fetch_versioned_location(library_ctx, location_id).await {
+		Ok(Some(location)) => snapshot_file(library_ctx, &location, materialized_path).await,
+		Ok(None) => return,
+		Err(e) => Err(e.into()),
+	};
+
+	match result {
+		Ok(true) => invalidate_query!(library_ctx, "files.listVersions"),
+		Ok(false) => {}
+		Err(e) => error!("Failed to snapshot a version of {materialized_path}: {e:#?}"),
+	}
+}
+
 pub(super) async fn rename_both_event(
 	location: indexer_job_location::Data,
 	event: Event,
@@ -407,6 +673,7 @@ pub(super) async fn rename(
 			.exec()
 			.await?;
 		invalidate_query!(library_ctx, "locations.getExplorerData");
+		invalidate_query!(library_ctx, "savedSearches.getExplorerData");
 	}
 
 	Ok(())
@@ -426,9 +693,9 @@ pub(super) async fn remove_event(
 	{
 		// check file still exists on disk
 		match fs::metadata(&event.paths[0]).await {
-			Ok(_) => {
-				todo!("file has changed in some way, re-identify it")
-			}
+			// some editors save by replacing the file, which dispatches a removal for a path that still exists
+			Ok(_) if file_path.is_dir => return Ok(()),
+			Ok(_) => return file_creation_or_update(location, event, library_ctx).await,
 			Err(e) if e.kind() == ErrorKind::NotFound => {
 				// if is doesn't, we can remove it safely from our db
 				if file_path.is_dir {
@@ -460,6 +727,7 @@ pub(super) async fn remove_event(
 		}
 
 		invalidate_query!(library_ctx, "locations.getExplorerData");
+		invalidate_query!(library_ctx, "savedSearches.getExplorerData");
 	}
 
 	Ok(())
@@ -580,6 +848,14 @@ async fn generate_thumbnail(
 		}
 	}
 
+	if let Ok(extension) = AudioExtension::from_str(extension) {
+		if can_extract_audio_tags(&extension) {
+			if let Err(e) = generate_audio_thumbnail(file_path, &output_path).await {
+				error!("Failed to audio thumbnail on location manager: {e:#?}");
+			}
+		}
+	}
+
 	#[cfg(feature = "ffmpeg")]
 	{
 		use crate::object::preview::{can_generate_thumbnail_for_video, generate_video_thumbnail};
@@ -4,9 +4,17 @@ use crate::{
 	library::LibraryContext,
 	object::{
 		identifier_job::full_identifier_job::{FullFileIdentifierJob, FullFileIdentifierJobInit},
-		preview::{ThumbnailJob, ThumbnailJobInit},
+		preview::{MediaDataJob, MediaDataJobInit, ThumbnailJob, ThumbnailJobInit},
+		tag::{TagRuleJob, TagRuleJobInit},
+		text::{TextIndexerJob, TextIndexerJobInit},
+		version::{
+			delete_unreferenced_snapshots, FileVersionerJob, FileVersionerJobInit, VersionRetention,
+		},
+	},
+	prisma::{
+		file_path, file_version, indexer_rules_in_location, key, location, node, object,
+		trashed_item,
 	},
-	prisma::{file_path, indexer_rules_in_location, location, node, object},
 };
 
 use std::{
@@ -14,11 +22,12 @@ use std::{
 	path::{Path, PathBuf},
 };
 
+use int_enum::IntEnum;
 use prisma_client_rust::QueryError;
 use rspc::Type;
 use serde::Deserialize;
 use tokio::{fs, io};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
 mod error;
@@ -26,11 +35,19 @@ pub mod file_path_helper;
 pub mod indexer;
 mod manager;
 mod metadata;
+pub mod remote;
+pub mod statistics;
 
 pub use error::LocationError;
-use indexer::indexer_job::{indexer_job_location, IndexerJob, IndexerJobInit};
+use file_path_helper::delete_orphaned_objects;
+use indexer::{
+	indexer_job::{indexer_job_location, IndexerJob, IndexerJobInit},
+	sdignore::IgnoreRules,
+	SymlinkPolicy,
+};
 pub use manager::{LocationManager, LocationManagerError};
 use metadata::SpacedriveLocationMetadataFile;
+use remote::{RemoteIndexerJob, RemoteIndexerJobInit};
 
 /// `LocationCreateArgs` is the argument received from the client using `rspc` to create a new location.
 /// It has the actual path and a vector of indexer rules ids, to create many-to-many relationships
@@ -39,6 +56,9 @@ use metadata::SpacedriveLocationMetadataFile;
 pub struct LocationCreateArgs {
 	pub path: PathBuf,
 	pub indexer_rules_ids: Vec<i32>,
+	/// If this is set, the location becomes a vault where every file added to it is encrypted with this key.
+	#[serde(default)]
+	pub vault_key: Option<Uuid>,
 }
 
 impl LocationCreateArgs {
@@ -78,13 +98,16 @@ impl LocationCreateArgs {
 			};
 		}
 
+		let vault_key_id = self.vault_key_id(ctx).await?;
+
 		debug!(
 			"Trying to create new location for '{}'",
 			self.path.display()
 		);
 		let uuid = Uuid::new_v4();
 
-		let location = create_location(ctx, uuid, &self.path, &self.indexer_rules_ids).await?;
+		let location =
+			create_location(ctx, uuid, &self.path, &self.indexer_rules_ids, vault_key_id).await?;
 
 		// Write a location metadata on a .spacedrive file
 		SpacedriveLocationMetadataFile::create_and_save(
@@ -122,9 +145,11 @@ impl LocationCreateArgs {
 			self.path.display()
 		);
 
+		let vault_key_id = self.vault_key_id(ctx).await?;
 		let uuid = Uuid::new_v4();
 
-		let location = create_location(ctx, uuid, &self.path, &self.indexer_rules_ids).await?;
+		let location =
+			create_location(ctx, uuid, &self.path, &self.indexer_rules_ids, vault_key_id).await?;
 
 		metadata
 			.add_library(
@@ -142,6 +167,28 @@ impl LocationCreateArgs {
 
 		Ok(location)
 	}
+
+	/// This checks that a vault's key can be used, and returns its database ID.
+	///
+	/// The key needs to be mounted (so files can be encrypted straight away), and it can't be memory-only.
+	async fn vault_key_id(&self, ctx: &LibraryContext) -> Result<Option<i32>, LocationError> {
+		let uuid = match self.vault_key {
+			Some(uuid) => uuid,
+			None => return Ok(None),
+		};
+
+		if !ctx.key_manager.keymount_contains(uuid) {
+			return Err(LocationError::InvalidVaultKey(uuid));
+		}
+
+		ctx.db
+			.key()
+			.find_unique(key::uuid::equals(uuid.to_string()))
+			.exec()
+			.await?
+			.map(|key| Some(key.id))
+			.ok_or(LocationError::InvalidVaultKey(uuid))
+	}
 }
 
 /// `LocationUpdateArgs` is the argument received from the client using `rspc` to update a location.
@@ -155,6 +202,18 @@ pub struct LocationUpdateArgs {
 	pub id: i32,
 	pub name: Option<String>,
 	pub indexer_rules_ids: Vec<i32>,
+	/// these are gitignore-style patterns (one per line), and an empty string removes them. They're used from the next scan onwards.
+	#[serde(default)]
+	pub ignore_patterns: Option<String>,
+	/// this is also used from the next scan onwards, and links that were already indexed are left alone until then
+	#[serde(default)]
+	pub symlink_policy: Option<SymlinkPolicy>,
+	/// when this is turned on, the first version of every file within the location is taken straight away
+	#[serde(default)]
+	pub versioning: Option<bool>,
+	/// this replaces the location's current retention policy, and versions that expired are deleted from the next change onwards
+	#[serde(default)]
+	pub version_retention: Option<VersionRetention>,
 }
 
 impl LocationUpdateArgs {
@@ -184,6 +243,83 @@ impl LocationUpdateArgs {
 			}
 		}
 
+		if let Some(ignore_patterns) = self.ignore_patterns {
+			let ignore_patterns = (!ignore_patterns.trim().is_empty()).then_some(ignore_patterns);
+
+			// the patterns are checked up front, so a typo can't fail every scan of the location
+			IgnoreRules::new(
+				location.local_path.as_deref().unwrap_or_default(),
+				ignore_patterns.as_deref(),
+			)
+			.map_err(|e| LocationError::InvalidIgnorePatterns(e.to_string()))?;
+
+			ctx.db
+				.location()
+				.update(
+					location::id::equals(self.id),
+					vec![location::ignore_patterns::set(ignore_patterns)],
+				)
+				.exec()
+				.await?;
+		}
+
+		if let Some(symlink_policy) = self.symlink_policy {
+			ctx.db
+				.location()
+				.update(
+					location::id::equals(self.id),
+					vec![location::symlink_policy::set(symlink_policy.int_value())],
+				)
+				.exec()
+				.await?;
+		}
+
+		if let Some(retention) = self.version_retention {
+			if retention.max_versions == Some(0) {
+				return Err(LocationError::InvalidVersionRetention);
+			}
+
+			ctx.db
+				.location()
+				.update(
+					location::id::equals(self.id),
+					vec![
+						location::max_versions::set(retention.max_versions.map(|max| max as i32)),
+						location::max_version_age::set(
+							retention.max_age_days.map(|days| days as i32),
+						),
+					],
+				)
+				.exec()
+				.await?;
+		}
+
+		if let Some(versioning) = self.versioning {
+			// the snapshots would be kept unencrypted, which would defeat the point of a vault
+			if versioning && location.vault_key_id.is_some() {
+				return Err(LocationError::VaultVersioning(self.id));
+			}
+
+			ctx.db
+				.location()
+				.update(
+					location::id::equals(self.id),
+					vec![location::versioning::set(versioning)],
+				)
+				.exec()
+				.await?;
+
+			if versioning && !location.versioning {
+				ctx.spawn_job(Job::new(
+					FileVersionerJobInit {
+						location_id: self.id,
+					},
+					FileVersionerJob {},
+				))
+				.await;
+			}
+		}
+
 		let current_rules_ids = location
 			.indexer_rules
 			.iter()
@@ -251,20 +387,33 @@ pub async fn scan_location(
 	ctx: &LibraryContext,
 	location: indexer_job_location::Data,
 ) -> Result<(), LocationError> {
+	// remote files can't be read from the disk, so remote locations are only indexed
+	if location.remote_config.is_some() {
+		ctx.spawn_job(Job::new(
+			RemoteIndexerJobInit {
+				location_id: location.id,
+			},
+			RemoteIndexerJob {},
+		))
+		.await;
+
+		return Ok(());
+	}
+
 	if location.local_path.is_none() {
 		return Err(LocationError::MissingLocalPath(location.id));
 	};
 
-	ctx.queue_job(Job::new(
+	// each job needs the results of the one before it, so they're queued as each one finishes
+	// (rather than all at once, where they could start while the indexer is still running)
+	let mut identifier_job = Job::new(
 		FullFileIdentifierJobInit {
 			location_id: location.id,
 			sub_path: None,
 		},
 		FullFileIdentifierJob {},
-	))
-	.await;
-
-	ctx.queue_job(Job::new(
+	)
+	.queue_next(Job::new(
 		ThumbnailJobInit {
 			location_id: location.id,
 			root_path: PathBuf::new(),
@@ -272,9 +421,39 @@ pub async fn scan_location(
 		},
 		ThumbnailJob {},
 	))
-	.await;
+	// tag rules are run after the media data extraction, as rules can match the camera a photo was taken with
+	.queue_next(
+		Job::new(
+			MediaDataJobInit {
+				location_id: location.id,
+			},
+			MediaDataJob {},
+		)
+		.queue_next(Job::new(
+			TagRuleJobInit {
+				location_id: Some(location.id),
+				rule_id: None,
+			},
+			TagRuleJob {},
+		)),
+	)
+	.queue_next(Job::new(
+		TextIndexerJobInit {
+			location_id: location.id,
+		},
+		TextIndexerJob {},
+	));
+
+	if location.versioning && location.vault_key_id.is_none() {
+		identifier_job = identifier_job.queue_next(Job::new(
+			FileVersionerJobInit {
+				location_id: location.id,
+			},
+			FileVersionerJob {},
+		));
+	}
 
-	ctx.spawn_job(Job::new(IndexerJobInit { location }, IndexerJob {}))
+	ctx.spawn_job(Job::new(IndexerJobInit { location }, IndexerJob {}).queue_next(identifier_job))
 		.await;
 
 	Ok(())
@@ -316,6 +495,7 @@ async fn create_location(
 	location_pub_id: Uuid,
 	location_path: impl AsRef<Path>,
 	indexer_rules_ids: &[i32],
+	vault_key_id: Option<i32>,
 ) -> Result<indexer_job_location::Data, LocationError> {
 	let location_name = location_path
 		.as_ref()
@@ -341,6 +521,7 @@ async fn create_location(
 						.expect("Found non-UTF-8 path")
 						.to_string(),
 				)),
+				location::vault_key_id::set(vault_key_id),
 			],
 		)
 		.include(indexer_job_location::include())
@@ -372,6 +553,49 @@ pub async fn delete_location(ctx: &LibraryContext, location_id: i32) -> Result<(
 
 	delete_directory(ctx, location_id, None).await?;
 
+	// items trashed from the location can't be restored once it's gone, so their objects are deleted too
+	let trashed_object_ids = ctx
+		.db
+		.trashed_item()
+		.find_many(vec![trashed_item::location_id::equals(location_id)])
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|item| item.object_id)
+		.collect();
+
+	ctx.db
+		.trashed_item()
+		.delete_many(vec![trashed_item::location_id::equals(location_id)])
+		.exec()
+		.await?;
+
+	delete_orphaned_objects(ctx, trashed_object_ids).await?;
+
+	// versions are deleted alongside the location, but their snapshots have to be deleted from the version store
+	let version_hashes = ctx
+		.db
+		.file_version()
+		.find_many(vec![file_version::location_id::equals(location_id)])
+		.exec()
+		.await?
+		.into_iter()
+		.map(|version| version.content_hash)
+		.collect::<HashSet<_>>();
+
+	ctx.db
+		.file_version()
+		.delete_many(vec![file_version::location_id::equals(location_id)])
+		.exec()
+		.await?;
+
+	if let Err(e) = delete_unreferenced_snapshots(ctx, version_hashes).await {
+		warn!(
+			"Failed to delete snapshots of location {}: {:#?}",
+			location_id, e
+		);
+	}
+
 	ctx.db
 		.indexer_rules_in_location()
 		.delete_many(vec![indexer_rules_in_location::location_id::equals(
@@ -445,11 +669,14 @@ pub async fn delete_directory(
 			object::id::in_vec(object_ids),
 			// https://www.prisma.io/docs/reference/api-reference/prisma-client-reference#none
 			object::file_paths::none(vec![]),
+			// trashed objects are kept, so they can be restored
+			object::trashed_items::none(vec![]),
 		])
 		.exec()
 		.await?;
 
 	invalidate_query!(ctx, "locations.getExplorerData");
+	invalidate_query!(ctx, "savedSearches.getExplorerData");
 
 	Ok(())
 }
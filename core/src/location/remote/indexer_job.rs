@@ -0,0 +1,264 @@
+use crate::{
+	invalidate_query,
+	job::{
+		JobError, JobPriority, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+	},
+	library::LibraryContext,
+	location::file_path_helper::{delete_orphaned_objects, record_file_path},
+	prisma::{file_path, location},
+};
+
+use std::collections::{HashSet, VecDeque};
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::QueryError;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::{ancestors, connect, RemoteEntry};
+
+pub const REMOTE_INDEXER_JOB_NAME: &str = "remote_indexer";
+
+/// RemoteIndexerJob indexes a remote location, one page of its listing at a time.
+///
+/// Remote files can't be identified or previewed without downloading them, so they're only recorded as file paths.
+/// Anything that wasn't listed by the scan has been removed from the remote, so its file path is deleted once the scan is done.
+pub struct RemoteIndexerJob {}
+
+#[derive(Serialize, Deserialize, Clone, Hash)]
+pub struct RemoteIndexerJobInit {
+	pub location_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RemoteIndexerJobState {
+	scan_start: DateTime<Utc>,
+	/// the directories that have been recorded by this scan, so they aren't recorded again for every file within them
+	directories: HashSet<String>,
+	indexed_paths: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RemoteIndexerJobStep {
+	/// where the listing continues from, which is only unset for the first page
+	cursor: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for RemoteIndexerJob {
+	type Init = RemoteIndexerJobInit;
+	type Data = RemoteIndexerJobState;
+	type Step = RemoteIndexerJobStep;
+
+	fn name(&self) -> &'static str {
+		REMOTE_INDEXER_JOB_NAME
+	}
+
+	fn priority(&self) -> JobPriority {
+		JobPriority::High
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let library = &ctx.library_ctx;
+		let location_id = state.init.location_id;
+
+		let backend = connect(library, location_id).await?;
+
+		// a remote that can't be reached is marked as offline, rather than everything within it being removed
+		let online = backend.check().await;
+		library
+			.db
+			.location()
+			.update(
+				location::id::equals(location_id),
+				vec![location::is_online::set(online.is_ok())],
+			)
+			.exec()
+			.await?;
+		invalidate_query!(library, "locations.list");
+		online?;
+
+		let scan_start = Utc::now();
+
+		index_entry(
+			library,
+			location_id,
+			&RemoteEntry {
+				path: String::new(),
+				is_dir: true,
+				size: 0,
+				date_modified: None,
+			},
+		)
+		.await?;
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(1),
+			JobReportUpdate::Message("Listing remote location".to_string()),
+		]);
+
+		state.data = Some(RemoteIndexerJobState {
+			scan_start,
+			directories: HashSet::new(),
+			indexed_paths: 0,
+		});
+		state.steps = VecDeque::from([RemoteIndexerJobStep { cursor: None }]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let library = &ctx.library_ctx;
+		let location_id = state.init.location_id;
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		let page = connect(library, location_id)
+			.await?
+			.list(state.steps[0].cursor.clone())
+			.await?;
+
+		for entry in &page.entries {
+			// object storage doesn't always have placeholders for directories, so they're recorded from the paths of what's within them
+			for directory in ancestors(&entry.path) {
+				if data.directories.insert(directory.to_string()) {
+					index_entry(
+						library,
+						location_id,
+						&RemoteEntry {
+							path: directory.to_string(),
+							is_dir: true,
+							size: 0,
+							date_modified: None,
+						},
+					)
+					.await?;
+					data.indexed_paths += 1;
+				}
+			}
+
+			if entry.is_dir && !data.directories.insert(entry.path.clone()) {
+				continue;
+			}
+
+			index_entry(library, location_id, entry).await?;
+			data.indexed_paths += 1;
+		}
+
+		let has_next = page.next.is_some();
+		if let Some(cursor) = page.next {
+			state.steps.push_back(RemoteIndexerJobStep {
+				cursor: Some(cursor),
+			});
+		}
+
+		// the listing's length isn't known up front, so there's always one more page to go until there isn't
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(state.step_number + 1 + has_next as usize),
+			JobReportUpdate::CompletedTaskCount(state.step_number + 1),
+			JobReportUpdate::Message(format!("Indexed {} paths", data.indexed_paths)),
+		]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let library = &ctx.library_ctx;
+		let location_id = state.init.location_id;
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		let removed_object_ids = library
+			.db
+			.file_path()
+			.find_many(removed_filters(location_id, data.scan_start))
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|file_path| file_path.object_id)
+			.collect();
+
+		let removed_paths = library
+			.db
+			.file_path()
+			.delete_many(removed_filters(location_id, data.scan_start))
+			.exec()
+			.await?;
+
+		delete_orphaned_objects(library, removed_object_ids).await?;
+
+		info!(
+			"Finalizing remote indexer job: {} paths indexed, {} removed",
+			data.indexed_paths, removed_paths
+		);
+
+		invalidate_query!(library, "locations.getExplorerData");
+
+		Ok(Some(serde_json::json!({
+			"indexed_paths": data.indexed_paths,
+			"removed_paths": removed_paths,
+		})))
+	}
+}
+
+/// Everything that's still within the location is indexed again by each scan, so anything that wasn't has been removed.
+fn removed_filters(location_id: i32, scan_start: DateTime<Utc>) -> Vec<file_path::WhereParam> {
+	vec![
+		file_path::location_id::equals(location_id),
+		file_path::date_indexed::lt(scan_start.into()),
+	]
+}
+
+/// This records a remote entry as a file path, or updates the one that's already there.
+async fn index_entry(
+	library: &LibraryContext,
+	location_id: i32,
+	entry: &RemoteEntry,
+) -> Result<(), QueryError> {
+	let materialized_path = if entry.is_dir {
+		format!("{}/", entry.path.trim_end_matches('/'))
+	} else {
+		entry.path.clone()
+	};
+
+	record_file_path(
+		library,
+		location_id,
+		&materialized_path,
+		entry.is_dir,
+		None,
+		entry.size,
+	)
+	.await?;
+
+	let mut params = vec![
+		file_path::size_in_bytes::set(Some(entry.size.to_string())),
+		file_path::date_indexed::set(Utc::now().into()),
+	];
+	if let Some(date_modified) = entry.date_modified {
+		params.push(file_path::date_modified::set(date_modified.into()));
+	}
+
+	library
+		.db
+		.file_path()
+		.update_many(
+			vec![
+				file_path::location_id::equals(location_id),
+				file_path::materialized_path::equals(materialized_path),
+			],
+			params,
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
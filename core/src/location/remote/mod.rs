@@ -0,0 +1,377 @@
+use crate::{
+	invalidate_query,
+	library::{decrypt_credentials, encrypt_credentials, EncryptedFieldError, LibraryContext},
+	location::{
+		fetch_location, file_path_helper::record_file_path,
+		indexer::indexer_job::indexer_job_location,
+	},
+	prisma::{location, node},
+};
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::QueryError;
+use rspc::{self, ErrorCode, Type};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tracing::info;
+use uuid::Uuid;
+
+mod indexer_job;
+mod s3;
+mod sftp;
+mod transfer_job;
+mod webdav;
+
+pub use self::s3::S3Config;
+pub use indexer_job::*;
+pub use sftp::SftpConfig;
+pub use transfer_job::*;
+pub use webdav::WebDavConfig;
+
+/// `RemoteConfig` is how a remote location is reached, which is stored alongside the location (as it doesn't contain any secrets).
+#[derive(Serialize, Deserialize, Type, Clone, Debug)]
+#[serde(tag = "type")]
+pub enum RemoteConfig {
+	/// a bucket on S3, or any S3-compatible object storage (e.g. MinIO, Backblaze B2 or Cloudflare R2)
+	S3(S3Config),
+	/// a directory on a server that's reachable over SSH
+	Sftp(SftpConfig),
+	/// a collection on a WebDAV server (e.g. Nextcloud or ownCloud)
+	WebDav(WebDavConfig),
+}
+
+impl RemoteConfig {
+	/// SFTP servers are trusted on first use, so this records their host key if it hasn't been yet (returning whether it was).
+	async fn trust_on_first_use(&mut self) -> Result<bool, RemoteError> {
+		match self {
+			RemoteConfig::Sftp(config) => config.trust_on_first_use().await,
+			_ => Ok(false),
+		}
+	}
+}
+
+/// `RemoteCredentials` are what's needed to sign in to a remote location, which are encrypted with the library's metadata key.
+#[derive(Serialize, Deserialize, Type, Clone)]
+#[serde(tag = "type")]
+pub enum RemoteCredentials {
+	S3 {
+		access_key: String,
+		secret_key: String,
+	},
+	/// either a password or a private key (in PEM format) needs to be set
+	Sftp {
+		username: String,
+		#[serde(default)]
+		password: Option<String>,
+		#[serde(default)]
+		private_key: Option<String>,
+		#[serde(default)]
+		passphrase: Option<String>,
+	},
+	/// for Nextcloud (or ownCloud) accounts with two-factor authentication, this needs to be an app password
+	WebDav { username: String, password: String },
+}
+
+/// `RemoteEntry` is a file or directory within a remote location.
+#[derive(Debug, Clone)]
+pub struct RemoteEntry {
+	/// this is relative to the location's root, without a leading (or trailing) slash
+	pub path: String,
+	pub is_dir: bool,
+	pub size: u64,
+	pub date_modified: Option<DateTime<Utc>>,
+}
+
+/// `ListPage` is one page of a remote location's listing.
+#[derive(Debug)]
+pub struct ListPage {
+	pub entries: Vec<RemoteEntry>,
+	/// this is passed back to list the next page, and is only set if there is one
+	pub next: Option<String>,
+}
+
+/// `RemoteBackend` is implemented for each kind of remote that a location can be on.
+///
+/// Paths are relative to the location's root, so the backend decides where that is (e.g. an S3 location's prefix).
+#[async_trait::async_trait]
+pub trait RemoteBackend: Send + Sync {
+	/// This checks that the remote can be reached with the location's credentials.
+	async fn check(&self) -> Result<(), RemoteError>;
+
+	/// This lists a page of everything within the location (recursively), continuing from `cursor` if it's set.
+	async fn list(&self, cursor: Option<String>) -> Result<ListPage, RemoteError>;
+
+	/// This returns how large a file is, or `None` if there isn't one at `path`.
+	async fn size(&self, path: &str) -> Result<Option<u64>, RemoteError>;
+
+	/// This streams a file's contents into `writer`, starting `offset` bytes into the file (e.g. to continue an interrupted download).
+	async fn read(
+		&self,
+		path: &str,
+		offset: u64,
+		writer: &mut (dyn AsyncWrite + Unpin + Send),
+	) -> Result<(), RemoteError>;
+
+	/// Whether `write` can continue a file part of the way through, so an interrupted upload doesn't have to be started again.
+	fn appendable(&self) -> bool {
+		false
+	}
+
+	/// This streams `size` bytes from `reader` into a file, replacing it if it already exists.
+	///
+	/// If `offset` is set (which is only done if the backend is [`appendable`](Self::appendable)), the first `offset` bytes of the file are kept, and the rest is written after them instead.
+	async fn write(
+		&self,
+		path: &str,
+		offset: u64,
+		reader: &mut (dyn AsyncRead + Unpin + Send),
+		size: u64,
+	) -> Result<(), RemoteError>;
+}
+
+#[derive(Error, Debug)]
+pub enum RemoteError {
+	#[error("Location not found (id: {0})")]
+	LocationNotFound(i32),
+	#[error("Location isn't a remote location (id: {0})")]
+	NotRemote(i32),
+	#[error("Invalid remote location config: {0}")]
+	InvalidConfig(String),
+	#[error("Credentials don't match the kind of remote location")]
+	CredentialsMismatch,
+	#[error("Failed to connect to the remote location: {0}")]
+	ConnectionFailed(String),
+	#[error("The server's host key doesn't match the one that was expected (host: {0})")]
+	HostKeyMismatch(String),
+	#[error("S3 error: {0}")]
+	S3(String),
+	#[error("SFTP error: {0}")]
+	Sftp(String),
+	#[error("WebDAV error: {0}")]
+	WebDav(String),
+	#[error("Encrypted field error: {0}")]
+	EncryptedFieldError(#[from] EncryptedFieldError),
+	#[error("Failed to (de)serialize the remote location's config: {0}")]
+	Serialization(#[from] serde_json::Error),
+	#[error("I/O error: {0}")]
+	IOError(#[from] std::io::Error),
+	#[error("Database error: {0}")]
+	DatabaseError(#[from] QueryError),
+}
+
+impl From<RemoteError> for rspc::Error {
+	fn from(err: RemoteError) -> Self {
+		match err {
+			RemoteError::LocationNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			RemoteError::NotRemote(_)
+			| RemoteError::InvalidConfig(_)
+			| RemoteError::CredentialsMismatch
+			| RemoteError::ConnectionFailed(_)
+			| RemoteError::HostKeyMismatch(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			RemoteError::EncryptedFieldError(err) => err.into(),
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// `RemoteUpload` is where a job's outputs are uploaded to, within a remote location.
+#[derive(Serialize, Deserialize, Type, Clone, Debug, Hash)]
+pub struct RemoteUpload {
+	pub location_id: i32,
+	/// the directory that the outputs are uploaded into, relative to the location's root (which is used if this is empty)
+	#[serde(default)]
+	pub directory: String,
+}
+
+/// `RemoteLocationCreateArgs` is the argument received from the client using `rspc` to create a remote location.
+///
+/// The key manager needs to be unlocked, as the credentials are encrypted before they're stored.
+#[derive(Type, Deserialize)]
+pub struct RemoteLocationCreateArgs {
+	pub name: String,
+	pub config: RemoteConfig,
+	pub credentials: RemoteCredentials,
+}
+
+impl RemoteLocationCreateArgs {
+	pub async fn create(
+		mut self,
+		ctx: &LibraryContext,
+	) -> Result<indexer_job_location::Data, RemoteError> {
+		let encrypted_credentials = encrypt_credentials(ctx, &self.credentials)?;
+
+		self.config.trust_on_first_use().await?;
+
+		// the remote is checked up front, so a typo doesn't leave behind a location that can never be indexed
+		backend(&self.config, self.credentials.clone())?
+			.check()
+			.await
+			.map_err(|e| RemoteError::ConnectionFailed(e.to_string()))?;
+
+		let location = ctx
+			.db
+			.location()
+			.create(
+				Uuid::new_v4().as_bytes().to_vec(),
+				node::id::equals(ctx.node_local_id),
+				vec![
+					location::name::set(Some(self.name)),
+					location::is_online::set(true),
+					location::remote_config::set(Some(serde_json::to_string(&self.config)?)),
+					location::remote_credentials::set(Some(encrypted_credentials)),
+				],
+			)
+			.include(indexer_job_location::include())
+			.exec()
+			.await?;
+
+		invalidate_query!(ctx, "locations.list");
+
+		info!("Created remote location: {location:?}");
+
+		Ok(location)
+	}
+}
+
+/// This connects to a remote location, with its stored credentials.
+pub async fn connect(
+	library: &LibraryContext,
+	location_id: i32,
+) -> Result<Box<dyn RemoteBackend>, RemoteError> {
+	let location = fetch_location(library, location_id)
+		.exec()
+		.await?
+		.ok_or(RemoteError::LocationNotFound(location_id))?;
+
+	match (location.remote_config, location.remote_credentials) {
+		(Some(config), Some(credentials)) => {
+			let mut config: RemoteConfig = serde_json::from_str(&config)?;
+
+			// locations that were added without a host key record it the first time they're connected to
+			if config.trust_on_first_use().await? {
+				library
+					.db
+					.location()
+					.update(
+						location::id::equals(location_id),
+						vec![location::remote_config::set(Some(serde_json::to_string(
+							&config,
+						)?))],
+					)
+					.exec()
+					.await?;
+			}
+
+			backend(&config, decrypt_credentials(library, &credentials)?)
+		}
+		_ => Err(RemoteError::NotRemote(location_id)),
+	}
+}
+
+/// This connects to a remote that isn't a location, with credentials that are only used for this connection (and aren't stored).
+///
+/// As the config isn't stored either, an SFTP server's host key has to be provided up front.
+pub fn connect_with_credentials(
+	config: &RemoteConfig,
+	credentials: RemoteCredentials,
+) -> Result<Box<dyn RemoteBackend>, RemoteError> {
+	backend(config, credentials)
+}
+
+fn backend(
+	config: &RemoteConfig,
+	credentials: RemoteCredentials,
+) -> Result<Box<dyn RemoteBackend>, RemoteError> {
+	match (config, credentials) {
+		(
+			RemoteConfig::S3(config),
+			RemoteCredentials::S3 {
+				access_key,
+				secret_key,
+			},
+		) => Ok(Box::new(self::s3::S3Backend::new(
+			config,
+			&access_key,
+			&secret_key,
+		)?)),
+		(
+			RemoteConfig::Sftp(config),
+			RemoteCredentials::Sftp {
+				username,
+				password,
+				private_key,
+				passphrase,
+			},
+		) => Ok(Box::new(sftp::SftpBackend::new(
+			config,
+			username,
+			password,
+			private_key,
+			passphrase,
+		)?)),
+		(RemoteConfig::WebDav(config), RemoteCredentials::WebDav { username, password }) => Ok(
+			Box::new(webdav::WebDavBackend::new(config, username, password)?),
+		),
+		_ => Err(RemoteError::CredentialsMismatch),
+	}
+}
+
+/// This records a file that's been uploaded (and the directories it's within), so it shows up without waiting for the location's next scan.
+pub(crate) async fn record_upload(
+	library: &LibraryContext,
+	location_id: i32,
+	path: &str,
+	size: u64,
+) -> Result<(), RemoteError> {
+	for directory in ancestors(path) {
+		record_file_path(library, location_id, directory, true, None, 0).await?;
+	}
+	record_file_path(library, location_id, path, false, None, size).await?;
+
+	Ok(())
+}
+
+/// This joins a remote location's path onto a directory within it, where an empty directory (or `/`) is the location's root.
+pub(crate) fn join_remote_path(directory: &str, path: &str) -> String {
+	let directory = directory.trim_matches('/');
+	let path = path.trim_start_matches('/');
+
+	if directory.is_empty() {
+		path.to_string()
+	} else {
+		format!("{directory}/{path}")
+	}
+}
+
+/// This returns the directories that a path is within, from the outermost inwards (e.g. `a` and `a/b` for `a/b/c.txt`).
+fn ancestors(path: &str) -> impl Iterator<Item = &str> {
+	path.match_indices('/')
+		.map(move |(index, _)| &path[..index])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_ancestors() {
+		assert_eq!(ancestors("a/b/c.txt").collect::<Vec<_>>(), vec!["a", "a/b"]);
+		assert!(ancestors("c.txt").next().is_none());
+	}
+
+	#[test]
+	fn test_join_remote_path() {
+		assert_eq!(join_remote_path("", "a.txt"), "a.txt");
+		assert_eq!(join_remote_path("/", "a.txt"), "a.txt");
+		assert_eq!(
+			join_remote_path("photos/", "2023/a.jpg"),
+			"photos/2023/a.jpg"
+		);
+		assert_eq!(join_remote_path("/photos", "/a.jpg"), "photos/a.jpg");
+	}
+}
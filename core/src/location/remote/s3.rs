@@ -0,0 +1,213 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Utc};
+use rspc::Type;
+use s3::{creds::Credentials, error::S3Error, Bucket, Region};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use super::{ListPage, RemoteBackend, RemoteEntry, RemoteError};
+
+/// This many keys are listed in each page, which is the most that S3 returns at once.
+const S3_PAGE_SIZE: usize = 1000;
+
+/// Reads that start part of the way through an object are made in ranges of this many bytes.
+const S3_RANGE_SIZE: u64 = 8 * 1024 * 1024;
+
+/// `S3Config` is the bucket (and the prefix within it) that an S3 location is rooted at.
+#[derive(Serialize, Deserialize, Type, Clone, Debug)]
+pub struct S3Config {
+	pub bucket: String,
+	/// S3-compatible storage often accepts anything here (e.g. `us-east-1`), as the endpoint is what decides where the bucket is
+	pub region: String,
+	/// this is only needed for S3-compatible storage, as AWS's endpoint is derived from the region
+	#[serde(default)]
+	pub endpoint: Option<String>,
+	/// whether the bucket is addressed within the path rather than the host name, which most self-hosted storage needs
+	#[serde(default)]
+	pub path_style: bool,
+	/// the location's root within the bucket, which is the whole bucket if this is empty
+	#[serde(default)]
+	pub prefix: String,
+}
+
+pub(super) struct S3Backend {
+	bucket: Bucket,
+	/// this is either empty or ends with a slash, so keys can be made by appending a path to it
+	prefix: String,
+}
+
+impl S3Backend {
+	pub(super) fn new(
+		config: &S3Config,
+		access_key: &str,
+		secret_key: &str,
+	) -> Result<Self, RemoteError> {
+		let region = match &config.endpoint {
+			Some(endpoint) => Region::Custom {
+				region: config.region.clone(),
+				endpoint: endpoint.clone(),
+			},
+			None => config.region.parse().map_err(|e| {
+				RemoteError::InvalidConfig(format!("Invalid region '{}': {e}", config.region))
+			})?,
+		};
+
+		let credentials = Credentials::new(Some(access_key), Some(secret_key), None, None, None)
+			.map_err(s3_error)?;
+
+		let mut bucket = Bucket::new(&config.bucket, region, credentials).map_err(s3_error)?;
+		if config.path_style {
+			bucket = bucket.with_path_style();
+		}
+
+		let prefix = config.prefix.trim_matches('/');
+
+		Ok(Self {
+			bucket,
+			prefix: if prefix.is_empty() {
+				String::new()
+			} else {
+				format!("{prefix}/")
+			},
+		})
+	}
+
+	fn key(&self, path: &str) -> String {
+		format!("{}{}", self.prefix, path.trim_matches('/'))
+	}
+}
+
+#[async_trait::async_trait]
+impl RemoteBackend for S3Backend {
+	async fn check(&self) -> Result<(), RemoteError> {
+		self.bucket
+			.list_page(
+				self.prefix.clone(),
+				Some("/".to_string()),
+				None,
+				None,
+				Some(1),
+			)
+			.await
+			.map_err(s3_error)?;
+
+		Ok(())
+	}
+
+	async fn list(&self, cursor: Option<String>) -> Result<ListPage, RemoteError> {
+		let (page, _) = self
+			.bucket
+			.list_page(self.prefix.clone(), None, cursor, None, Some(S3_PAGE_SIZE))
+			.await
+			.map_err(s3_error)?;
+
+		let next = if page.is_truncated {
+			page.next_continuation_token
+		} else {
+			None
+		};
+
+		let entries = page
+			.contents
+			.into_iter()
+			.filter_map(|object| {
+				let path = object.key.strip_prefix(&self.prefix)?;
+
+				// S3 doesn't have directories, but keys ending with a slash are used as placeholders for empty ones (e.g. by the S3 console)
+				let (path, is_dir) = match path.strip_suffix('/') {
+					Some(path) => (path, true),
+					None => (path, false),
+				};
+
+				(!path.is_empty()).then(|| RemoteEntry {
+					path: path.to_string(),
+					is_dir,
+					size: if is_dir { 0 } else { object.size },
+					date_modified: DateTime::parse_from_rfc3339(&object.last_modified)
+						.ok()
+						.map(|date| date.with_timezone(&Utc)),
+				})
+			})
+			.collect();
+
+		Ok(ListPage { entries, next })
+	}
+
+	async fn size(&self, path: &str) -> Result<Option<u64>, RemoteError> {
+		match self.bucket.head_object(self.key(path)).await {
+			Ok((head, _)) => Ok(Some(head.content_length.unwrap_or(0) as u64)),
+			Err(S3Error::Http(404, _)) => Ok(None),
+			Err(e) => Err(s3_error(e)),
+		}
+	}
+
+	async fn read(
+		&self,
+		path: &str,
+		offset: u64,
+		mut writer: &mut (dyn AsyncWrite + Unpin + Send),
+	) -> Result<(), RemoteError> {
+		if offset == 0 {
+			self.bucket
+				.get_object_stream(self.key(path), &mut writer)
+				.await
+				.map_err(s3_error)?;
+
+			return Ok(());
+		}
+
+		// the rest of the file is read in ranges, so it doesn't have to be held in memory
+		let size = self
+			.size(path)
+			.await?
+			.ok_or_else(|| RemoteError::S3(format!("Object not found: {path}")))?;
+
+		let mut start = offset;
+		while start < size {
+			let end = (start + S3_RANGE_SIZE).min(size) - 1;
+			let response = self
+				.bucket
+				.get_object_range(self.key(path), start, Some(end))
+				.await
+				.map_err(s3_error)?;
+
+			writer.write_all(response.bytes()).await?;
+			start = end + 1;
+		}
+
+		Ok(())
+	}
+
+	// objects can only be replaced as a whole, so an interrupted upload is started again
+	fn appendable(&self) -> bool {
+		false
+	}
+
+	// large files are uploaded in parts, so the size doesn't need to be known up front
+	async fn write(
+		&self,
+		path: &str,
+		offset: u64,
+		mut reader: &mut (dyn AsyncRead + Unpin + Send),
+		_size: u64,
+	) -> Result<(), RemoteError> {
+		if offset != 0 {
+			return Err(RemoteError::S3(format!(
+				"Objects can't be appended to (key: {})",
+				self.key(path)
+			)));
+		}
+
+		self.bucket
+			.put_object_stream(&mut reader, self.key(path))
+			.await
+			.map_err(s3_error)?;
+
+		Ok(())
+	}
+}
+
+fn s3_error(e: impl Display) -> RemoteError {
+	RemoteError::S3(e.to_string())
+}
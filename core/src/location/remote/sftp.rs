@@ -0,0 +1,480 @@
+use std::{
+	collections::HashMap,
+	fmt::Display,
+	io::{Read, Seek, SeekFrom, Write},
+	net::TcpStream,
+	path::Path,
+	sync::Mutex,
+	time::Duration,
+};
+
+use chrono::{TimeZone, Utc};
+use once_cell::sync::Lazy;
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use ssh2::{ErrorCode, FileType, HashType, OpenFlags, OpenType, Session, Sftp};
+use tokio::{
+	io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+	sync::mpsc,
+	task,
+};
+
+use super::{ancestors, join_remote_path, ListPage, RemoteBackend, RemoteEntry, RemoteError};
+
+/// Files are streamed to and from the server in chunks of this many bytes.
+const SFTP_CHUNK_SIZE: usize = 256 * 1024;
+/// The number of chunks that can be waiting between the connection and whatever's reading (or writing) the file.
+const SFTP_CHANNEL_SIZE: usize = 8;
+/// How many idle connections are kept for each server (and user), so they can be reused rather than connecting again for every request.
+const MAX_IDLE_CONNECTIONS: usize = 4;
+const SFTP_TIMEOUT: Duration = Duration::from_secs(30);
+/// The status that SFTP servers respond with for a path that doesn't exist.
+const SFTP_NO_SUCH_FILE: i32 = 2;
+
+/// Connections are pooled by server and user, as connecting (and authenticating) takes a few round trips.
+static CONNECTION_POOL: Lazy<Mutex<HashMap<String, Vec<SftpConnection>>>> =
+	Lazy::new(Default::default);
+
+/// `SftpConfig` is the server (and the directory on it) that an SFTP location is rooted at.
+#[derive(Serialize, Deserialize, Type, Clone, Debug)]
+pub struct SftpConfig {
+	pub host: String,
+	#[serde(default = "default_port")]
+	pub port: u16,
+	/// the location's root on the server, which is the user's home directory if this is empty
+	#[serde(default)]
+	pub root: String,
+	/// the server's SHA-256 host key fingerprint (as shown by `ssh-keygen -l`), which every connection is checked against
+	///
+	/// if this isn't set, the server's key is recorded the first time it's connected to (trust on first use)
+	#[serde(default)]
+	pub host_key: Option<String>,
+}
+
+fn default_port() -> u16 {
+	22
+}
+
+impl SftpConfig {
+	/// This records the server's host key if one hasn't been set yet, returning whether it was (so the config can be saved).
+	pub(super) async fn trust_on_first_use(&mut self) -> Result<bool, RemoteError> {
+		if self.host_key.is_some() {
+			return Ok(false);
+		}
+
+		let (host, port) = (self.host.clone(), self.port);
+
+		let fingerprint = task::spawn_blocking(move || {
+			let session = handshake(&host, port)?;
+
+			host_key_fingerprint(&session).ok_or_else(|| {
+				RemoteError::ConnectionFailed(format!("{host} didn't provide a host key"))
+			})
+		})
+		.await
+		.map_err(sftp_error)??;
+
+		self.host_key = Some(format!("SHA256:{fingerprint}"));
+
+		Ok(true)
+	}
+}
+
+struct SftpConnection {
+	session: Session,
+	sftp: Sftp,
+}
+
+#[derive(Clone)]
+pub(super) struct SftpBackend {
+	config: SftpConfig,
+	username: String,
+	password: Option<String>,
+	private_key: Option<String>,
+	passphrase: Option<String>,
+}
+
+impl SftpBackend {
+	pub(super) fn new(
+		config: &SftpConfig,
+		username: String,
+		password: Option<String>,
+		private_key: Option<String>,
+		passphrase: Option<String>,
+	) -> Result<Self, RemoteError> {
+		if password.is_none() && private_key.is_none() {
+			return Err(RemoteError::InvalidConfig(
+				"SFTP locations need either a password or a private key".to_string(),
+			));
+		}
+
+		Ok(Self {
+			config: config.clone(),
+			username,
+			password,
+			private_key,
+			passphrase,
+		})
+	}
+
+	fn pool_key(&self) -> String {
+		format!(
+			"{}@{}:{}",
+			self.username, self.config.host, self.config.port
+		)
+	}
+
+	fn remote_path(&self, path: &str) -> String {
+		resolve_path(&self.config.root, path)
+	}
+
+	fn connect(&self) -> Result<SftpConnection, RemoteError> {
+		// the host key is recorded before the backend is created, so a server is never connected to without one
+		let host_key = self.config.host_key.as_deref().ok_or_else(|| {
+			RemoteError::InvalidConfig("SFTP locations need the server's host key".to_string())
+		})?;
+
+		let session = handshake(&self.config.host, self.config.port)?;
+
+		let expected = host_key
+			.strip_prefix("SHA256:")
+			.unwrap_or(host_key)
+			.trim_end_matches('=');
+
+		if host_key_fingerprint(&session).as_deref() != Some(expected) {
+			return Err(RemoteError::HostKeyMismatch(self.config.host.clone()));
+		}
+
+		match (&self.private_key, &self.password) {
+			(Some(private_key), _) => session.userauth_pubkey_memory(
+				&self.username,
+				None,
+				private_key,
+				self.passphrase.as_deref(),
+			),
+			(None, Some(password)) => session.userauth_password(&self.username, password),
+			(None, None) => unreachable!("credentials are checked when the backend is created"),
+		}
+		.map_err(sftp_error)?;
+
+		let sftp = session.sftp().map_err(sftp_error)?;
+
+		Ok(SftpConnection { session, sftp })
+	}
+
+	/// This takes an idle connection from the pool (as long as it's still alive), or connects again if there aren't any.
+	fn checkout(&self) -> Result<SftpConnection, RemoteError> {
+		loop {
+			let idle = CONNECTION_POOL
+				.lock()
+				.expect("critical error: SFTP connection pool lock poisoned")
+				.get_mut(&self.pool_key())
+				.and_then(Vec::pop);
+
+			match idle {
+				// the server may have closed the connection while it was idle, which is checked with a cheap request
+				Some(connection) if connection.sftp.realpath(Path::new(".")).is_ok() => {
+					return Ok(connection)
+				}
+				Some(connection) => {
+					connection.session.disconnect(None, "", None).ok();
+				}
+				None => return self.connect(),
+			}
+		}
+	}
+
+	fn release(&self, connection: SftpConnection) {
+		let mut pool = CONNECTION_POOL
+			.lock()
+			.expect("critical error: SFTP connection pool lock poisoned");
+		let idle = pool.entry(self.pool_key()).or_default();
+
+		if idle.len() < MAX_IDLE_CONNECTIONS {
+			idle.push(connection);
+		}
+	}
+
+	/// This runs `f` with a pooled connection on a blocking thread, as libssh2 is synchronous.
+	///
+	/// The connection is only returned to the pool if `f` succeeds, as it may be broken otherwise.
+	async fn with_connection<T, F>(&self, f: F) -> Result<T, RemoteError>
+	where
+		T: Send + 'static,
+		F: FnOnce(&SftpBackend, &Sftp) -> Result<T, RemoteError> + Send + 'static,
+	{
+		let backend = self.clone();
+
+		task::spawn_blocking(move || {
+			let connection = backend.checkout()?;
+			let result = f(&backend, &connection.sftp);
+
+			if result.is_ok() {
+				backend.release(connection);
+			}
+
+			result
+		})
+		.await
+		.map_err(sftp_error)?
+	}
+}
+
+#[async_trait::async_trait]
+impl RemoteBackend for SftpBackend {
+	async fn check(&self) -> Result<(), RemoteError> {
+		self.with_connection(|backend, sftp| {
+			let root = backend.remote_path("");
+
+			if sftp.stat(Path::new(&root)).map_err(sftp_error)?.is_dir() {
+				Ok(())
+			} else {
+				Err(RemoteError::InvalidConfig(format!(
+					"Not a directory: {root}"
+				)))
+			}
+		})
+		.await
+	}
+
+	// SFTP lists one directory at a time, so the cursor holds the directories that are still to be listed
+	async fn list(&self, cursor: Option<String>) -> Result<ListPage, RemoteError> {
+		let mut pending = match cursor {
+			Some(cursor) => serde_json::from_str::<Vec<String>>(&cursor)?,
+			None => vec![String::new()],
+		};
+
+		let directory = match pending.pop() {
+			Some(directory) => directory,
+			None => {
+				return Ok(ListPage {
+					entries: vec![],
+					next: None,
+				})
+			}
+		};
+
+		let listing = {
+			let directory = directory.clone();
+			self.with_connection(move |backend, sftp| {
+				sftp.readdir(Path::new(&backend.remote_path(&directory)))
+					.map_err(sftp_error)
+			})
+			.await?
+		};
+
+		let mut entries = Vec::with_capacity(listing.len());
+		for (path, stat) in listing {
+			let name = match path.file_name().and_then(|name| name.to_str()) {
+				Some(name) => name,
+				None => continue,
+			};
+
+			// links aren't followed, as they can lead outside of the location (or back into it)
+			let is_dir = match stat.file_type() {
+				FileType::Directory => true,
+				FileType::RegularFile => false,
+				_ => continue,
+			};
+
+			let path = join_remote_path(&directory, name);
+			if is_dir {
+				pending.push(path.clone());
+			}
+
+			entries.push(RemoteEntry {
+				path,
+				is_dir,
+				size: if is_dir { 0 } else { stat.size.unwrap_or(0) },
+				date_modified: stat
+					.mtime
+					.and_then(|mtime| Utc.timestamp_opt(mtime as i64, 0).single()),
+			});
+		}
+
+		Ok(ListPage {
+			entries,
+			next: if pending.is_empty() {
+				None
+			} else {
+				Some(serde_json::to_string(&pending)?)
+			},
+		})
+	}
+
+	async fn size(&self, path: &str) -> Result<Option<u64>, RemoteError> {
+		let path = path.to_string();
+
+		self.with_connection(move |backend, sftp| {
+			match sftp.stat(Path::new(&backend.remote_path(&path))) {
+				Ok(stat) => Ok(Some(stat.size.unwrap_or(0))),
+				Err(e) if e.code() == ErrorCode::SFTP(SFTP_NO_SUCH_FILE) => Ok(None),
+				Err(e) => Err(sftp_error(e)),
+			}
+		})
+		.await
+	}
+
+	async fn read(
+		&self,
+		path: &str,
+		offset: u64,
+		writer: &mut (dyn AsyncWrite + Unpin + Send),
+	) -> Result<(), RemoteError> {
+		let (tx, mut rx) = mpsc::channel::<Vec<u8>>(SFTP_CHANNEL_SIZE);
+		let path = path.to_string();
+
+		let reading = self.with_connection(move |backend, sftp| {
+			let mut file = sftp
+				.open(Path::new(&backend.remote_path(&path)))
+				.map_err(sftp_error)?;
+			file.seek(SeekFrom::Start(offset))?;
+
+			loop {
+				let mut chunk = vec![0; SFTP_CHUNK_SIZE];
+				let read = file.read(&mut chunk)?;
+				if read == 0 {
+					return Ok(());
+				}
+				chunk.truncate(read);
+
+				// the receiver is only dropped if writing failed, which is what's reported
+				if tx.blocking_send(chunk).is_err() {
+					return Ok(());
+				}
+			}
+		});
+
+		let writing = async move {
+			while let Some(chunk) = rx.recv().await {
+				writer.write_all(&chunk).await?;
+			}
+
+			Ok::<_, RemoteError>(())
+		};
+
+		let (read, written) = tokio::join!(reading, writing);
+		written?;
+		read
+	}
+
+	fn appendable(&self) -> bool {
+		true
+	}
+
+	async fn write(
+		&self,
+		path: &str,
+		offset: u64,
+		reader: &mut (dyn AsyncRead + Unpin + Send),
+		_size: u64,
+	) -> Result<(), RemoteError> {
+		let (tx, mut rx) = mpsc::channel::<Vec<u8>>(SFTP_CHANNEL_SIZE);
+		let path = path.to_string();
+
+		let writing = self.with_connection(move |backend, sftp| {
+			// the directories that the file is within aren't created by the server, so they're created first
+			for directory in ancestors(&path) {
+				let directory = backend.remote_path(directory);
+				if sftp.stat(Path::new(&directory)).is_err() {
+					sftp.mkdir(Path::new(&directory), 0o755)
+						.map_err(sftp_error)?;
+				}
+			}
+
+			let remote_path = backend.remote_path(&path);
+			let mut file = if offset == 0 {
+				sftp.create(Path::new(&remote_path))
+			} else {
+				sftp.open_mode(
+					Path::new(&remote_path),
+					OpenFlags::WRITE,
+					0o644,
+					OpenType::File,
+				)
+			}
+			.map_err(sftp_error)?;
+			file.seek(SeekFrom::Start(offset))?;
+
+			while let Some(chunk) = rx.blocking_recv() {
+				file.write_all(&chunk)?;
+			}
+
+			Ok(())
+		});
+
+		let reading = async move {
+			loop {
+				let mut chunk = vec![0; SFTP_CHUNK_SIZE];
+				let read = reader.read(&mut chunk).await?;
+				if read == 0 {
+					return Ok::<_, RemoteError>(());
+				}
+				chunk.truncate(read);
+
+				// the receiver is only dropped if writing failed, which is what's reported
+				if tx.send(chunk).await.is_err() {
+					return Ok(());
+				}
+			}
+		};
+
+		let (written, read) = tokio::join!(writing, reading);
+		read?;
+		written
+	}
+}
+
+/// This resolves a path within a location to its path on the server, where an empty root is the user's home directory.
+fn resolve_path(root: &str, path: &str) -> String {
+	let path = path.trim_matches('/');
+	let is_absolute = root.starts_with('/');
+	let root = root.trim_end_matches('/');
+
+	match (root.is_empty(), path.is_empty()) {
+		(true, true) if is_absolute => "/".to_string(),
+		(true, true) => ".".to_string(),
+		(true, false) if !is_absolute => path.to_string(),
+		(_, true) => root.to_string(),
+		(_, false) => format!("{root}/{path}"),
+	}
+}
+
+/// This connects to the server, without authenticating (so its host key can be checked first).
+fn handshake(host: &str, port: u16) -> Result<Session, RemoteError> {
+	let tcp = TcpStream::connect((host, port))?;
+
+	let mut session = Session::new().map_err(sftp_error)?;
+	session.set_tcp_stream(tcp);
+	session.set_timeout(SFTP_TIMEOUT.as_millis() as u32);
+	session.handshake().map_err(sftp_error)?;
+
+	Ok(session)
+}
+
+/// This is the server's SHA-256 host key fingerprint, in the same format as `ssh-keygen -l` (without the `SHA256:` prefix).
+fn host_key_fingerprint(session: &Session) -> Option<String> {
+	session
+		.host_key_hash(HashType::Sha256)
+		.map(|hash| base64::encode_config(hash, base64::STANDARD_NO_PAD))
+}
+
+fn sftp_error(e: impl Display) -> RemoteError {
+	RemoteError::Sftp(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_resolve_path() {
+		assert_eq!(resolve_path("", ""), ".");
+		assert_eq!(resolve_path("", "a/b.txt"), "a/b.txt");
+		assert_eq!(resolve_path("/", ""), "/");
+		assert_eq!(resolve_path("/", "a/b.txt"), "/a/b.txt");
+		assert_eq!(resolve_path("/srv/files/", ""), "/srv/files");
+		assert_eq!(resolve_path("/srv/files", "a/"), "/srv/files/a");
+		assert_eq!(resolve_path("backups", "a.txt"), "backups/a.txt");
+	}
+}
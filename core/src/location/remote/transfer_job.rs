@@ -0,0 +1,427 @@
+use crate::{
+	invalidate_query,
+	job::{
+		JobError, JobLogLevel, JobReportUpdate, JobResult, JobState, StatefulJob, ThrottledUpload,
+		WorkerContext,
+	},
+	library::LibraryContext,
+	location::file_path_helper::parent_materialized_path,
+	object::fs::FileFailure,
+	prisma::file_path,
+};
+
+use std::{
+	collections::VecDeque,
+	fs,
+	io::{ErrorKind, SeekFrom},
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tracing::{info, warn};
+
+use super::{connect, join_remote_path, record_upload, RemoteError};
+
+pub const REMOTE_TRANSFER_JOB_NAME: &str = "remote_transfer";
+
+/// RemoteTransferJob uploads local files to a remote location, or downloads files from one, streaming them one at a time.
+///
+/// If the job is interrupted part of the way through a file, its download is continued rather than started again (as is its upload, if the backend can append to files).
+pub struct RemoteTransferJob {}
+
+#[derive(Serialize, Deserialize, Type, Clone, Debug, Hash)]
+#[serde(tag = "type")]
+pub enum RemoteTransfer {
+	/// this uploads a local file (or everything within a local directory) into a directory within the location, replacing what's already there
+	Upload {
+		source: PathBuf,
+		#[serde(default)]
+		directory: String,
+		/// if this is set, the source is removed once everything within it has been uploaded
+		#[serde(default)]
+		remove_source: bool,
+	},
+	/// this downloads files (and everything within directories) from the location into a local directory, without replacing what's already there
+	Download {
+		file_path_ids: Vec<i32>,
+		output_directory: PathBuf,
+	},
+}
+
+#[derive(Serialize, Deserialize, Type, Clone, Debug, Hash)]
+pub struct RemoteTransferJobInit {
+	pub location_id: i32,
+	pub transfer: RemoteTransfer,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RemoteTransferJobState {
+	files_transferred: usize,
+	bytes_transferred: u64,
+	errors: Vec<FileFailure>,
+	/// the remote path of the file that's being transferred, which is checkpointed so an interrupted transfer can be continued
+	#[serde(default)]
+	current: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RemoteTransferJobStep {
+	local_path: PathBuf,
+	/// this is relative to the location's root, which is the same as the file's materialized path
+	remote_path: String,
+	size: u64,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for RemoteTransferJob {
+	type Init = RemoteTransferJobInit;
+	type Data = RemoteTransferJobState;
+	type Step = RemoteTransferJobStep;
+
+	fn name(&self) -> &'static str {
+		REMOTE_TRANSFER_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let library = &ctx.library_ctx;
+
+		// this is only connected to up front so a location that isn't remote (or a locked key manager) fails straight away
+		connect(library, state.init.location_id).await?;
+
+		state.steps = match &state.init.transfer {
+			RemoteTransfer::Upload {
+				source, directory, ..
+			} => {
+				let metadata = fs::metadata(source)?;
+
+				if metadata.is_dir() {
+					local_files(source)?
+						.into_iter()
+						.map(|(local_path, size)| {
+							let relative_path = local_path
+								.strip_prefix(source)
+								.expect("critical error: file isn't within the directory it was found in")
+								.components()
+								.map(|component| component.as_os_str().to_string_lossy())
+								.collect::<Vec<_>>()
+								.join("/");
+
+							RemoteTransferJobStep {
+								remote_path: join_remote_path(directory, &relative_path),
+								local_path,
+								size,
+							}
+						})
+						.collect()
+				} else {
+					let file_name = source
+						.file_name()
+						.map(|name| name.to_string_lossy().to_string())
+						.unwrap_or_default();
+
+					VecDeque::from([RemoteTransferJobStep {
+						remote_path: join_remote_path(directory, &file_name),
+						local_path: source.clone(),
+						size: metadata.len(),
+					}])
+				}
+			}
+			RemoteTransfer::Download {
+				file_path_ids,
+				output_directory,
+			} => {
+				let selected = library
+					.db
+					.file_path()
+					.find_many(vec![
+						file_path::location_id::equals(state.init.location_id),
+						file_path::id::in_vec(file_path_ids.clone()),
+					])
+					.exec()
+					.await?;
+
+				let mut steps = VecDeque::new();
+				for selected in selected {
+					// files are downloaded into the output directory as they are, alongside everything within directories
+					let parent = parent_materialized_path(&selected.materialized_path);
+					let parent = parent.trim_start_matches('/');
+
+					let file_paths = if selected.is_dir {
+						library
+							.db
+							.file_path()
+							.find_many(vec![
+								file_path::location_id::equals(state.init.location_id),
+								file_path::materialized_path::starts_with(
+									selected.materialized_path.clone(),
+								),
+								file_path::is_dir::equals(false),
+							])
+							.exec()
+							.await?
+					} else {
+						vec![selected]
+					};
+
+					steps.extend(file_paths.into_iter().map(|file_path| {
+						RemoteTransferJobStep {
+							local_path: output_directory.join(
+								file_path
+									.materialized_path
+									.strip_prefix(parent)
+									.unwrap_or(&file_path.materialized_path),
+							),
+							size: file_path
+								.size_in_bytes
+								.and_then(|size| size.parse().ok())
+								.unwrap_or(0),
+							remote_path: file_path.materialized_path,
+						}
+					}));
+				}
+
+				steps
+			}
+		};
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(state.steps.len()),
+			JobReportUpdate::Message(format!("Transferring {} files", state.steps.len())),
+		]);
+
+		state.data = Some(RemoteTransferJobState::default());
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		// a file that was part of the way through being transferred when the job was interrupted is continued, rather than started again
+		let resume = {
+			let step = &state.steps[0];
+			let data = state
+				.data
+				.as_mut()
+				.expect("critical error: missing data on job state");
+
+			let resume = data.current.as_ref() == Some(&step.remote_path);
+			data.current = Some(step.remote_path.clone());
+
+			resume
+		};
+
+		if !resume {
+			ctx.checkpoint(state).await?;
+		}
+
+		let step = &state.steps[0];
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Transferring {}",
+			step.remote_path
+		))]);
+
+		let result = match &state.init.transfer {
+			RemoteTransfer::Upload { .. } => {
+				upload(&ctx, state.init.location_id, step, resume).await
+			}
+			RemoteTransfer::Download { .. } => {
+				download(&ctx, state.init.location_id, step, resume).await
+			}
+		};
+
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+		data.current = None;
+
+		match result {
+			Ok(()) => {
+				data.files_transferred += 1;
+				data.bytes_transferred += step.size;
+			}
+			Err(e) => {
+				warn!("Failed to transfer {}: {:#?}", step.remote_path, e);
+				ctx.log(
+					JobLogLevel::Warning,
+					format!("Failed to transfer file: {e}"),
+					Some(step.remote_path.clone()),
+				);
+				data.errors.push(FileFailure {
+					path: step.remote_path.clone(),
+					file_path_id: None,
+					error: e.to_string(),
+				});
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		if let RemoteTransfer::Upload {
+			source,
+			remove_source,
+			..
+		} = &state.init.transfer
+		{
+			// the source is kept if anything failed to upload, so nothing is lost
+			if *remove_source && data.errors.is_empty() {
+				if source.is_dir() {
+					fs::remove_dir_all(source)?;
+				} else {
+					fs::remove_file(source)?;
+				}
+			}
+
+			invalidate_query!(ctx.library_ctx, "locations.getExplorerData");
+		}
+
+		info!(
+			"Finalizing remote transfer job: {} files ({} bytes) transferred, {} failed",
+			data.files_transferred,
+			data.bytes_transferred,
+			data.errors.len()
+		);
+
+		Ok(Some(serde_json::to_value(data)?))
+	}
+
+	async fn cancel(
+		&self,
+		_ctx: &LibraryContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		// the file that was being downloaded is incomplete, so it's removed
+		if let (RemoteTransfer::Download { .. }, Some(data), Some(step)) =
+			(&state.init.transfer, &state.data, state.steps.front())
+		{
+			if data.current.as_ref() == Some(&step.remote_path) {
+				match fs::remove_file(&step.local_path) {
+					Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+					_ => {}
+				}
+			}
+		}
+
+		Ok(())
+	}
+}
+
+async fn upload(
+	ctx: &WorkerContext,
+	location_id: i32,
+	step: &RemoteTransferJobStep,
+	resume: bool,
+) -> Result<(), RemoteError> {
+	let library = &ctx.library_ctx;
+	let backend = connect(library, location_id).await?;
+	let mut file = tokio::fs::File::open(&step.local_path).await?;
+
+	// whatever reached the remote before the upload was interrupted is kept, as long as the backend can append to it
+	let offset = if resume && backend.appendable() {
+		backend
+			.size(&step.remote_path)
+			.await?
+			.unwrap_or(0)
+			.min(step.size)
+	} else {
+		0
+	};
+	file.seek(SeekFrom::Start(offset)).await?;
+
+	let mut reader = ThrottledUpload::new(file, Arc::clone(library.io_throttle()));
+	backend
+		.write(&step.remote_path, offset, &mut reader, step.size)
+		.await?;
+
+	record_upload(library, location_id, &step.remote_path, step.size).await
+}
+
+async fn download(
+	ctx: &WorkerContext,
+	location_id: i32,
+	step: &RemoteTransferJobStep,
+	resume: bool,
+) -> Result<(), RemoteError> {
+	if let Some(parent) = step.local_path.parent() {
+		tokio::fs::create_dir_all(parent).await?;
+	}
+
+	// an interrupted download is continued from however much of it was written, and anything else that's already there is left alone
+	let (mut file, offset) = if resume {
+		let file = tokio::fs::OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(&step.local_path)
+			.await?;
+		let offset = file.metadata().await?.len();
+
+		(file, offset)
+	} else {
+		let file = tokio::fs::OpenOptions::new()
+			.write(true)
+			.create_new(true)
+			.open(&step.local_path)
+			.await?;
+
+		(file, 0)
+	};
+
+	let result = async {
+		connect(&ctx.library_ctx, location_id)
+			.await?
+			.read(&step.remote_path, offset, &mut file)
+			.await?;
+
+		file.flush().await?;
+
+		Ok(())
+	}
+	.await;
+
+	// a partial download is removed, so it isn't mistaken for the whole file
+	if result.is_err() {
+		drop(file);
+		tokio::fs::remove_file(&step.local_path).await.ok();
+	}
+
+	result
+}
+
+/// This returns every file within a local directory (recursively), alongside their sizes.
+fn local_files(directory: &Path) -> Result<Vec<(PathBuf, u64)>, std::io::Error> {
+	let mut files = Vec::new();
+	let mut directories = vec![directory.to_path_buf()];
+
+	while let Some(directory) = directories.pop() {
+		for entry in fs::read_dir(directory)? {
+			let entry = entry?;
+			let metadata = entry.metadata()?;
+
+			if metadata.is_dir() {
+				directories.push(entry.path());
+			} else if metadata.is_file() {
+				files.push((entry.path(), metadata.len()));
+			}
+		}
+	}
+
+	Ok(files)
+}
@@ -0,0 +1,449 @@
+use std::{fmt::Display, time::Duration};
+
+use chrono::{DateTime, Utc};
+use futures::{channel::mpsc, SinkExt, StreamExt};
+use once_cell::sync::Lazy;
+use percent_encoding::percent_decode_str;
+use reqwest::{
+	header::{CONTENT_LENGTH, CONTENT_TYPE, RANGE},
+	Body, Client, Method, RequestBuilder, Response, StatusCode, Url,
+};
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::{ancestors, join_remote_path, ListPage, RemoteBackend, RemoteEntry, RemoteError};
+
+/// Files are uploaded in chunks of this many bytes.
+const WEBDAV_CHUNK_SIZE: usize = 256 * 1024;
+/// The number of chunks that can be waiting to be sent to the server.
+const WEBDAV_CHANNEL_SIZE: usize = 8;
+const WEBDAV_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// The namespace that WebDAV's properties (and the rest of a multistatus response) are within.
+const DAV_NAMESPACE: &str = "DAV:";
+
+/// This only asks for the properties that are indexed, as servers can be slow to work out the rest (e.g. quotas).
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+	<d:prop>
+		<d:resourcetype/>
+		<d:getcontentlength/>
+		<d:getlastmodified/>
+	</d:prop>
+</d:propfind>"#;
+
+/// The client is shared by every WebDAV location, so connections to the same server are reused.
+static HTTP_CLIENT: Lazy<Client> = Lazy::new(|| {
+	Client::builder()
+		.connect_timeout(WEBDAV_CONNECT_TIMEOUT)
+		.build()
+		.expect("critical error: failed to build the WebDAV client")
+});
+
+/// `WebDavConfig` is the collection on a WebDAV server (e.g. Nextcloud or ownCloud) that a WebDAV location is rooted at.
+#[derive(Serialize, Deserialize, Type, Clone, Debug)]
+pub struct WebDavConfig {
+	/// the collection's URL, which for Nextcloud is `https://<server>/remote.php/dav/files/<username>/` (followed by a folder, if the location is within one)
+	pub url: String,
+}
+
+pub(super) struct WebDavBackend {
+	/// this always ends with a slash, so paths can be joined onto it
+	root: Url,
+	username: String,
+	password: String,
+}
+
+impl WebDavBackend {
+	pub(super) fn new(
+		config: &WebDavConfig,
+		username: String,
+		password: String,
+	) -> Result<Self, RemoteError> {
+		let mut root = Url::parse(&config.url).map_err(|e| {
+			RemoteError::InvalidConfig(format!("Invalid URL '{}': {e}", config.url))
+		})?;
+
+		if !matches!(root.scheme(), "http" | "https") {
+			return Err(RemoteError::InvalidConfig(format!(
+				"WebDAV locations need an http(s) URL: {}",
+				config.url
+			)));
+		}
+
+		if !root.path().ends_with('/') {
+			root.set_path(&format!("{}/", root.path()));
+		}
+
+		Ok(Self {
+			root,
+			username,
+			password,
+		})
+	}
+
+	/// This returns the URL of a path within the location, where directories end with a slash (as some servers redirect otherwise).
+	fn url(&self, path: &str, is_dir: bool) -> Url {
+		let mut url = self.root.clone();
+
+		{
+			let mut segments = url
+				.path_segments_mut()
+				.expect("critical error: WebDAV URLs are always http(s)");
+			segments.pop_if_empty();
+			segments.extend(path.split('/').filter(|segment| !segment.is_empty()));
+			if is_dir {
+				segments.push("");
+			}
+		}
+
+		url
+	}
+
+	fn request(&self, method: Method, url: Url) -> RequestBuilder {
+		HTTP_CLIENT
+			.request(method, url)
+			.basic_auth(&self.username, Some(&self.password))
+	}
+
+	async fn propfind(&self, path: &str, depth: &str) -> Result<Vec<RemoteEntry>, RemoteError> {
+		let response = self
+			.request(method("PROPFIND"), self.url(path, true))
+			.header("Depth", depth)
+			.header(CONTENT_TYPE, "application/xml; charset=utf-8")
+			.body(PROPFIND_BODY)
+			.send()
+			.await
+			.and_then(Response::error_for_status)
+			.map_err(webdav_error)?;
+
+		parse_multistatus(&self.root, &response.text().await.map_err(webdav_error)?)
+	}
+}
+
+#[async_trait::async_trait]
+impl RemoteBackend for WebDavBackend {
+	async fn check(&self) -> Result<(), RemoteError> {
+		let entries = self.propfind("", "0").await?;
+
+		match entries.first() {
+			Some(entry) if entry.is_dir => Ok(()),
+			_ => Err(RemoteError::InvalidConfig(format!(
+				"Not a collection: {}",
+				self.root
+			))),
+		}
+	}
+
+	// servers often refuse to list everything at once (e.g. Nextcloud doesn't allow `Depth: infinity`), so the cursor holds the directories that are still to be listed
+	async fn list(&self, cursor: Option<String>) -> Result<ListPage, RemoteError> {
+		let mut pending = match cursor {
+			Some(cursor) => serde_json::from_str::<Vec<String>>(&cursor)?,
+			None => vec![String::new()],
+		};
+
+		let directory = match pending.pop() {
+			Some(directory) => directory,
+			None => {
+				return Ok(ListPage {
+					entries: vec![],
+					next: None,
+				})
+			}
+		};
+
+		// the directory itself is included in its listing, so it's left out
+		let entries = self
+			.propfind(&directory, "1")
+			.await?
+			.into_iter()
+			.filter(|entry| entry.path != directory.trim_matches('/'))
+			.collect::<Vec<_>>();
+
+		pending.extend(
+			entries
+				.iter()
+				.filter(|entry| entry.is_dir)
+				.map(|entry| entry.path.clone()),
+		);
+
+		Ok(ListPage {
+			entries,
+			next: if pending.is_empty() {
+				None
+			} else {
+				Some(serde_json::to_string(&pending)?)
+			},
+		})
+	}
+
+	async fn size(&self, path: &str) -> Result<Option<u64>, RemoteError> {
+		let response = self
+			.request(Method::HEAD, self.url(path, false))
+			.send()
+			.await
+			.map_err(webdav_error)?;
+
+		if response.status() == StatusCode::NOT_FOUND {
+			return Ok(None);
+		}
+
+		Ok(response
+			.error_for_status()
+			.map_err(webdav_error)?
+			.content_length())
+	}
+
+	// reads that start part of the way through a file are ranged, so previews and interrupted downloads don't fetch the whole file again
+	async fn read(
+		&self,
+		path: &str,
+		offset: u64,
+		writer: &mut (dyn AsyncWrite + Unpin + Send),
+	) -> Result<(), RemoteError> {
+		let mut request = self.request(Method::GET, self.url(path, false));
+		if offset > 0 {
+			request = request.header(RANGE, format!("bytes={offset}-"));
+		}
+
+		let response = request
+			.send()
+			.await
+			.and_then(Response::error_for_status)
+			.map_err(webdav_error)?;
+
+		// a server that doesn't support ranges responds with the whole file, so whatever's before the offset is skipped
+		let mut skip = if response.status() == StatusCode::PARTIAL_CONTENT {
+			0
+		} else {
+			offset
+		};
+
+		let mut stream = response.bytes_stream();
+		while let Some(chunk) = stream.next().await {
+			let chunk = chunk.map_err(webdav_error)?;
+			let skipped = skip.min(chunk.len() as u64);
+			skip -= skipped;
+
+			writer.write_all(&chunk[skipped as usize..]).await?;
+		}
+
+		Ok(())
+	}
+
+	async fn write(
+		&self,
+		path: &str,
+		offset: u64,
+		reader: &mut (dyn AsyncRead + Unpin + Send),
+		size: u64,
+	) -> Result<(), RemoteError> {
+		debug_assert_eq!(offset, 0, "WebDAV files can't be appended to");
+
+		// the directories that the file is within aren't created by the server, so they're created first
+		for directory in ancestors(path) {
+			let response = self
+				.request(method("MKCOL"), self.url(directory, true))
+				.send()
+				.await
+				.map_err(webdav_error)?;
+
+			// this is the response for a directory that already exists
+			if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+				response.error_for_status().map_err(webdav_error)?;
+			}
+		}
+
+		let (mut tx, rx) = mpsc::channel::<Result<Vec<u8>, std::io::Error>>(WEBDAV_CHANNEL_SIZE);
+
+		// the length is sent up front, as some servers don't accept chunked uploads
+		let writing = self
+			.request(Method::PUT, self.url(path, false))
+			.header(CONTENT_LENGTH, size)
+			.body(Body::wrap_stream(rx))
+			.send();
+
+		let reading = async move {
+			loop {
+				let mut chunk = vec![0; WEBDAV_CHUNK_SIZE];
+				let read = reader.read(&mut chunk).await?;
+				if read == 0 {
+					return Ok::<_, RemoteError>(());
+				}
+				chunk.truncate(read);
+
+				// the receiver is only dropped if the request failed, which is what's reported
+				if tx.send(Ok(chunk)).await.is_err() {
+					return Ok(());
+				}
+			}
+		};
+
+		let (written, read) = tokio::join!(writing, reading);
+		read?;
+		written
+			.and_then(Response::error_for_status)
+			.map_err(webdav_error)?;
+
+		Ok(())
+	}
+}
+
+fn method(name: &'static str) -> Method {
+	Method::from_bytes(name.as_bytes()).expect("critical error: invalid WebDAV method")
+}
+
+/// This parses a multistatus response to a `PROPFIND` request into the entries it describes.
+fn parse_multistatus(root: &Url, xml: &str) -> Result<Vec<RemoteEntry>, RemoteError> {
+	let document = roxmltree::Document::parse(xml).map_err(webdav_error)?;
+
+	let entries = document
+		.descendants()
+		.filter(|node| node.has_tag_name((DAV_NAMESPACE, "response")))
+		.filter_map(|response| {
+			let property = |name: &str| {
+				response
+					.descendants()
+					.filter(|node| node.has_tag_name((DAV_NAMESPACE, name)))
+					.find_map(|node| node.text().map(str::trim).filter(|text| !text.is_empty()))
+			};
+
+			let path = relative_path(root, property("href")?)?;
+			let is_dir = response
+				.descendants()
+				.any(|node| node.has_tag_name((DAV_NAMESPACE, "collection")));
+
+			Some(RemoteEntry {
+				path,
+				is_dir,
+				size: if is_dir {
+					0
+				} else {
+					property("getcontentlength")
+						.and_then(|size| size.parse().ok())
+						.unwrap_or(0)
+				},
+				date_modified: property("getlastmodified")
+					.and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+					.map(|date| date.with_timezone(&Utc)),
+			})
+		})
+		.collect();
+
+	Ok(entries)
+}
+
+/// This returns the path within the location of a `href` from a multistatus response, which can either be a path or a whole URL.
+///
+/// Servers don't all percent-encode the same characters, so paths are compared once they've been decoded.
+fn relative_path(root: &Url, href: &str) -> Option<String> {
+	let url = root.join(href).ok()?;
+
+	let decode = |url: &Url| {
+		url.path_segments()
+			.map(|segments| {
+				segments
+					.filter(|segment| !segment.is_empty())
+					.map(|segment| percent_decode_str(segment).decode_utf8_lossy().to_string())
+					.collect::<Vec<_>>()
+			})
+			.unwrap_or_default()
+	};
+
+	let root_segments = decode(root);
+	let segments = decode(&url);
+
+	segments
+		.strip_prefix(root_segments.as_slice())
+		.map(|segments| {
+			segments.iter().fold(String::new(), |path, segment| {
+				join_remote_path(&path, segment)
+			})
+		})
+}
+
+fn webdav_error(e: impl Display) -> RemoteError {
+	RemoteError::WebDav(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_relative_path() {
+		let root = Url::parse("https://cloud.example.com/remote.php/dav/files/alex/").unwrap();
+
+		assert_eq!(
+			relative_path(&root, "/remote.php/dav/files/alex/").as_deref(),
+			Some("")
+		);
+		assert_eq!(
+			relative_path(&root, "/remote.php/dav/files/alex/Photos/My%20Trip/a.jpg").as_deref(),
+			Some("Photos/My Trip/a.jpg")
+		);
+		assert_eq!(
+			relative_path(
+				&root,
+				"https://cloud.example.com/remote.php/dav/files/alex/Notes/"
+			)
+			.as_deref(),
+			Some("Notes")
+		);
+		assert_eq!(relative_path(&root, "/remote.php/dav/files/sam/"), None);
+	}
+
+	#[test]
+	fn test_parse_multistatus() {
+		let root = Url::parse("https://cloud.example.com/remote.php/dav/files/alex/").unwrap();
+		let xml = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:oc="http://owncloud.org/ns">
+	<d:response>
+		<d:href>/remote.php/dav/files/alex/Photos/</d:href>
+		<d:propstat>
+			<d:prop>
+				<d:resourcetype><d:collection/></d:resourcetype>
+				<d:getlastmodified>Wed, 08 Feb 2023 09:00:00 GMT</d:getlastmodified>
+			</d:prop>
+			<d:status>HTTP/1.1 200 OK</d:status>
+		</d:propstat>
+		<d:propstat>
+			<d:prop>
+				<d:getcontentlength/>
+			</d:prop>
+			<d:status>HTTP/1.1 404 Not Found</d:status>
+		</d:propstat>
+	</d:response>
+	<d:response>
+		<d:href>/remote.php/dav/files/alex/Photos/a%20b.jpg</d:href>
+		<d:propstat>
+			<d:prop>
+				<d:resourcetype/>
+				<d:getcontentlength>1024</d:getcontentlength>
+				<d:getlastmodified>Wed, 08 Feb 2023 10:30:00 GMT</d:getlastmodified>
+			</d:prop>
+			<d:status>HTTP/1.1 200 OK</d:status>
+		</d:propstat>
+	</d:response>
+</d:multistatus>"#;
+
+		let entries = parse_multistatus(&root, xml).unwrap();
+		assert_eq!(entries.len(), 2);
+
+		assert_eq!(entries[0].path, "Photos");
+		assert!(entries[0].is_dir);
+		assert_eq!(entries[0].size, 0);
+
+		assert_eq!(entries[1].path, "Photos/a b.jpg");
+		assert!(!entries[1].is_dir);
+		assert_eq!(entries[1].size, 1024);
+		assert_eq!(
+			entries[1].date_modified,
+			Some(
+				DateTime::parse_from_rfc3339("2023-02-08T10:30:00Z")
+					.unwrap()
+					.with_timezone(&Utc)
+			)
+		);
+	}
+}
@@ -0,0 +1,202 @@
+use crate::{
+	library::LibraryContext,
+	prisma::{file_path, location, location_snapshot},
+};
+
+use std::collections::HashMap;
+
+use chrono::{Duration, Utc};
+use prisma_client_rust::{raw, Direction, PrismaValue, QueryError};
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+
+use super::LocationError;
+
+/// How often a location's statistics are recorded, so its growth can be charted.
+pub const STATISTICS_INTERVAL_HOURS: i64 = 24;
+
+file_path::include!(file_path_with_object { object });
+
+/// `LocationStatistics` summarises what's within a location, as of when it was last indexed.
+#[derive(Serialize, Type, Debug)]
+pub struct LocationStatistics {
+	pub location_id: i32,
+	pub total_size: u64,
+	pub file_count: u64,
+	pub directory_count: u64,
+	/// how many files there are of each kind (see `ObjectKind`), and how much space they take up, from largest to smallest
+	pub by_kind: Vec<KindStatistics>,
+	pub largest_files: Vec<file_path_with_object::Data>,
+	pub recently_added: Vec<file_path_with_object::Data>,
+}
+
+#[derive(Serialize, Type, Debug)]
+pub struct KindStatistics {
+	pub kind: i32,
+	pub file_count: u64,
+	pub total_size: u64,
+}
+
+// Sizes are stored as text, so they're summed by SQLite rather than by loading every file path.
+// Every value is cast back to text, as integers come back from raw queries typed by how large they are.
+
+#[derive(Deserialize)]
+struct TotalsRow {
+	file_count: String,
+	directory_count: String,
+	total_size: String,
+}
+
+#[derive(Deserialize)]
+struct KindRow {
+	kind: String,
+	file_count: String,
+	total_size: String,
+}
+
+#[derive(Deserialize)]
+struct IdRow {
+	id: String,
+}
+
+fn parse_count(value: &str) -> u64 {
+	value.parse().unwrap_or(0)
+}
+
+async fn totals(library: &LibraryContext, location_id: i32) -> Result<TotalsRow, QueryError> {
+	Ok(library
+		.db
+		._query_raw::<TotalsRow>(raw!(
+			"SELECT CAST(COALESCE(SUM(CASE WHEN is_dir = 0 THEN 1 ELSE 0 END), 0) AS TEXT) AS file_count, CAST(COALESCE(SUM(CASE WHEN is_dir = 1 THEN 1 ELSE 0 END), 0) AS TEXT) AS directory_count, CAST(COALESCE(SUM(CASE WHEN is_dir = 0 THEN CAST(size_in_bytes AS INTEGER) ELSE 0 END), 0) AS TEXT) AS total_size FROM file_path WHERE location_id = {}",
+			PrismaValue::Int(location_id as i64)
+		))
+		.exec()
+		.await?
+		.pop()
+		.unwrap_or(TotalsRow {
+			file_count: "0".to_string(),
+			directory_count: "0".to_string(),
+			total_size: "0".to_string(),
+		}))
+}
+
+/// This summarises a location, with its largest and most recently added files (up to `limit` of each).
+pub async fn location_statistics(
+	library: &LibraryContext,
+	location_id: i32,
+	limit: i64,
+) -> Result<LocationStatistics, LocationError> {
+	let db = &library.db;
+
+	if db
+		.location()
+		.count(vec![location::id::equals(location_id)])
+		.exec()
+		.await?
+		== 0
+	{
+		return Err(LocationError::IdNotFound(location_id));
+	}
+
+	let totals = totals(library, location_id).await?;
+
+	// files that haven't been identified yet don't have a kind, so they're counted as unknown
+	let mut by_kind = db
+		._query_raw::<KindRow>(raw!(
+			"SELECT CAST(COALESCE(object.kind, 0) AS TEXT) AS kind, CAST(COUNT(*) AS TEXT) AS file_count, CAST(COALESCE(SUM(CAST(file_path.size_in_bytes AS INTEGER)), 0) AS TEXT) AS total_size FROM file_path LEFT JOIN object ON object.id = file_path.object_id WHERE file_path.location_id = {} AND file_path.is_dir = 0 GROUP BY COALESCE(object.kind, 0)",
+			PrismaValue::Int(location_id as i64)
+		))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|row| KindStatistics {
+			kind: row.kind.parse().unwrap_or(0),
+			file_count: parse_count(&row.file_count),
+			total_size: parse_count(&row.total_size),
+		})
+		.collect::<Vec<_>>();
+	by_kind.sort_by(|a, b| b.total_size.cmp(&a.total_size));
+
+	let largest_ids = db
+		._query_raw::<IdRow>(raw!(
+			"SELECT CAST(id AS TEXT) AS id FROM file_path WHERE location_id = {} AND is_dir = 0 ORDER BY CAST(size_in_bytes AS INTEGER) DESC LIMIT {}",
+			PrismaValue::Int(location_id as i64),
+			PrismaValue::Int(limit)
+		))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|row| row.id.parse().ok())
+		.collect::<Vec<i32>>();
+
+	let mut largest_files = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(location_id),
+			file_path::id::in_vec(largest_ids.clone()),
+		])
+		.include(file_path_with_object::include())
+		.exec()
+		.await?;
+	let positions = largest_ids
+		.iter()
+		.enumerate()
+		.map(|(position, id)| (*id, position))
+		.collect::<HashMap<_, _>>();
+	largest_files.sort_by_key(|file_path| positions.get(&file_path.id).copied());
+
+	let recently_added = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(location_id),
+			file_path::is_dir::equals(false),
+		])
+		.order_by(file_path::date_indexed::order(Direction::Desc))
+		.take(limit)
+		.include(file_path_with_object::include())
+		.exec()
+		.await?;
+
+	Ok(LocationStatistics {
+		location_id,
+		total_size: parse_count(&totals.total_size),
+		file_count: parse_count(&totals.file_count),
+		directory_count: parse_count(&totals.directory_count),
+		by_kind,
+		largest_files,
+		recently_added,
+	})
+}
+
+/// This records the statistics of every location that hasn't had them recorded in the last [`STATISTICS_INTERVAL_HOURS`].
+///
+/// It's run periodically by the scheduler, so a location's growth is charted without anything having to be set up.
+pub async fn record_due_statistics(library: &LibraryContext) -> Result<(), QueryError> {
+	let db = &library.db;
+	let due = Utc::now() - Duration::hours(STATISTICS_INTERVAL_HOURS);
+
+	let locations = db
+		.location()
+		.find_many(vec![location::snapshots::none(vec![
+			location_snapshot::date_created::gt(due.into()),
+		])])
+		.exec()
+		.await?;
+
+	for location in locations {
+		let totals = totals(library, location.id).await?;
+
+		db.location_snapshot()
+			.create(
+				totals.total_size,
+				parse_count(&totals.file_count) as i32,
+				parse_count(&totals.directory_count) as i32,
+				location::id::equals(location.id),
+				vec![],
+			)
+			.exec()
+			.await?;
+	}
+
+	Ok(())
+}
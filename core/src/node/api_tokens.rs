@@ -0,0 +1,70 @@
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// KeyCapability is something that an API token is allowed to do with the key manager.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, PartialEq, Eq)]
+pub enum KeyCapability {
+	/// Read allows listing keys, and viewing the key manager's state. No key material is ever returned.
+	Read,
+	/// Mount allows mounting and unmounting keys.
+	Mount,
+	/// Unlock allows providing (or clearing) the master password.
+	Unlock,
+	/// Reveal allows retrieving a key's plaintext value, or checking a password against it.
+	Reveal,
+	/// Export allows exporting keys and backing up the keystore.
+	Export,
+	/// Manage allows adding, removing, modifying and restoring keys.
+	Manage,
+	/// ChangeMasterPassword allows onboarding, and changing the master password.
+	ChangeMasterPassword,
+}
+
+/// ApiToken allows a remote/headless client to access the key manager, with a limited set of capabilities.
+///
+/// Only a hash of the token's secret is stored, the secret itself is only shown once when it's created.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct ApiToken {
+	pub id: Uuid,
+	pub name: String,
+	pub capabilities: Vec<KeyCapability>,
+	/// hash is the hex-encoded BLAKE3 hash of the token's secret.
+	hash: String,
+}
+
+impl ApiToken {
+	/// new creates an API token with the given capabilities, and returns it alongside its secret.
+	pub fn new(name: String, capabilities: Vec<KeyCapability>) -> (Self, String) {
+		let id = Uuid::new_v4();
+		let secret = format!(
+			"{}.{}",
+			id.simple(),
+			base64::encode_config(
+				sd_crypto::primitives::generate_master_key().expose(),
+				base64::URL_SAFE_NO_PAD
+			)
+		);
+
+		let token = Self {
+			id,
+			name,
+			capabilities,
+			hash: blake3::hash(secret.as_bytes()).to_hex().to_string(),
+		};
+
+		(token, secret)
+	}
+
+	/// matches checks whether the given secret belongs to this token.
+	pub fn matches(&self, secret: &str) -> bool {
+		// `blake3::Hash` compares in constant time
+		blake3::Hash::from_hex(&self.hash)
+			.map(|hash| hash == blake3::hash(secret.as_bytes()))
+			.unwrap_or(false)
+	}
+
+	pub fn allows(&self, capability: KeyCapability) -> bool {
+		self.capabilities.contains(&capability)
+	}
+}
@@ -10,6 +10,10 @@ use thiserror::Error;
 use tokio::sync::{RwLock, RwLockWriteGuard};
 use uuid::Uuid;
 
+use crate::job::{IoLimits, JobConcurrency};
+
+use super::ApiToken;
+
 /// NODE_STATE_CONFIG_NAME is the name of the file which stores the NodeState
 pub const NODE_STATE_CONFIG_NAME: &str = "node_state.sdconfig";
 
@@ -40,6 +44,24 @@ pub struct NodeConfig {
 	pub name: String,
 	// the port this node uses for peer to peer communication. By default a random free port will be chosen each time the application is started.
 	pub p2p_port: Option<u32>,
+	/// api_tokens are the tokens that have been issued for accessing the key manager remotely.
+	#[serde(default)]
+	pub api_tokens: Vec<ApiToken>,
+	/// job_concurrency limits how many jobs can run at once on this node.
+	#[serde(default)]
+	pub job_concurrency: JobConcurrency,
+	/// io_limits limits how quickly background jobs are able to read from and write to the disk on this node.
+	#[serde(default)]
+	pub io_limits: IoLimits,
+	/// decrypted_file_ttl is how long (in seconds) a decrypted copy of an encrypted file is kept for, once it stops being changed.
+	#[serde(default = "default_decrypted_file_ttl")]
+	pub decrypted_file_ttl: u64,
+	/// decrypted_file_quota is the maximum amount of space (in bytes) that decrypted copies are able to take up at once.
+	#[serde(default = "default_decrypted_file_quota")]
+	pub decrypted_file_quota: u64,
+	/// thumbnail_cache_size is the maximum amount of memory (in bytes) that decrypted thumbnails of encrypted files are cached in.
+	#[serde(default = "default_thumbnail_cache_size")]
+	pub thumbnail_cache_size: u64,
 	// /// The P2P identity public key
 	// pub p2p_cert: Vec<u8>,
 	// /// The P2P identity private key
@@ -48,6 +70,18 @@ pub struct NodeConfig {
 	// pub spacetunnel_addr: Option<String>,
 }
 
+fn default_decrypted_file_ttl() -> u64 {
+	5 * 60
+}
+
+fn default_decrypted_file_quota() -> u64 {
+	2 * 1024 * 1024 * 1024
+}
+
+fn default_thumbnail_cache_size() -> u64 {
+	64 * 1024 * 1024
+}
+
 #[derive(Error, Debug)]
 pub enum NodeConfigError {
 	#[error("error saving or loading the config from the filesystem")]
@@ -58,6 +92,16 @@ pub enum NodeConfigError {
 	Migration(String),
 }
 
+impl From<NodeConfigError> for rspc::Error {
+	fn from(error: NodeConfigError) -> Self {
+		rspc::Error::with_cause(
+			rspc::ErrorCode::InternalServerError,
+			error.to_string(),
+			error,
+		)
+	}
+}
+
 impl NodeConfig {
 	fn default() -> Self {
 		NodeConfig {
@@ -71,6 +115,12 @@ impl NodeConfig {
 				}
 			},
 			p2p_port: None,
+			api_tokens: Vec::new(),
+			job_concurrency: JobConcurrency::default(),
+			io_limits: IoLimits::default(),
+			decrypted_file_ttl: default_decrypted_file_ttl(),
+			decrypted_file_quota: default_decrypted_file_quota(),
+			thumbnail_cache_size: default_thumbnail_cache_size(),
 			metadata: ConfigMetadata {
 				version: Some(env!("CARGO_PKG_VERSION").into()),
 			},
@@ -100,7 +150,6 @@ impl NodeConfigManager {
 	}
 
 	/// write allows the user to update the configuration. This is done in a closure while a Mutex lock is held so that the user can't cause a race condition if the config were to be updated in multiple parts of the app at the same time.
-	#[allow(unused)]
 	pub(crate) async fn write<F: FnOnce(RwLockWriteGuard<NodeConfig>)>(
 		&self,
 		mutation_fn: F,
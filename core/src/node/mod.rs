@@ -6,8 +6,10 @@ use rspc::Type;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+mod api_tokens;
 mod config;
 
+pub use api_tokens::*;
 pub use config::*;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
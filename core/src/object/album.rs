@@ -0,0 +1,236 @@
+use crate::prisma::{album, object, object_in_album, PrismaClient};
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+use prisma_client_rust::{Direction, QueryError};
+use rspc::ErrorCode;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AlbumError {
+	#[error("Album not found: <id={0}>")]
+	NotFound(i32),
+	#[error("Album <id={album_id}> can't be nested under <id={parent_id}>, which is itself or one of its descendants")]
+	Cycle { album_id: i32, parent_id: i32 },
+	#[error("Database error: {0}")]
+	Database(#[from] QueryError),
+}
+
+impl From<AlbumError> for rspc::Error {
+	fn from(err: AlbumError) -> Self {
+		match err {
+			AlbumError::NotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			AlbumError::Cycle { .. } => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+album::select!(album_parent { id parent_id });
+
+pub async fn find_album(db: &PrismaClient, album_id: i32) -> Result<album::Data, AlbumError> {
+	db.album()
+		.find_unique(album::id::equals(album_id))
+		.exec()
+		.await?
+		.ok_or(AlbumError::NotFound(album_id))
+}
+
+/// This nests an album under another (or moves it to the top level), as long as that wouldn't nest it within itself.
+pub async fn set_album_parent(
+	db: &PrismaClient,
+	album_id: i32,
+	parent_id: Option<i32>,
+) -> Result<album::Data, AlbumError> {
+	find_album(db, album_id).await?;
+
+	if let Some(parent_id) = parent_id {
+		find_album(db, parent_id).await?;
+
+		let parents = db
+			.album()
+			.find_many(vec![album::parent_id::not(None)])
+			.select(album_parent::select())
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|album| Some((album.id, album.parent_id?)))
+			.collect::<HashMap<_, _>>();
+
+		if is_within(album_id, parent_id, &parents) {
+			return Err(AlbumError::Cycle {
+				album_id,
+				parent_id,
+			});
+		}
+	}
+
+	Ok(db
+		.album()
+		.update(
+			album::id::equals(album_id),
+			vec![
+				album::parent_id::set(parent_id),
+				album::date_modified::set(Utc::now().into()),
+			],
+		)
+		.exec()
+		.await?)
+}
+
+/// This returns whether an album is (or is nested within) another, by walking up from it through its parents.
+fn is_within(ancestor_id: i32, album_id: i32, parents: &HashMap<i32, i32>) -> bool {
+	let mut seen = HashSet::new();
+	let mut current = Some(album_id);
+
+	while let Some(album_id) = current {
+		// cycles are never created, but this still can't loop forever if one was
+		if album_id == ancestor_id || !seen.insert(album_id) {
+			return album_id == ancestor_id;
+		}
+		current = parents.get(&album_id).copied();
+	}
+
+	false
+}
+
+/// This returns the objects within an album, in the order that the user put them in.
+pub async fn album_object_ids(db: &PrismaClient, album_id: i32) -> Result<Vec<i32>, QueryError> {
+	Ok(db
+		.object_in_album()
+		.find_many(vec![object_in_album::album_id::equals(album_id)])
+		.order_by(object_in_album::position::order(Direction::Asc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|object_in_album| object_in_album.object_id)
+		.collect())
+}
+
+/// This adds objects to the end of an album, skipping any that are already within it.
+pub async fn add_to_album(
+	db: &PrismaClient,
+	album_id: i32,
+	object_ids: Vec<i32>,
+) -> Result<usize, AlbumError> {
+	find_album(db, album_id).await?;
+
+	let mut existing = album_object_ids(db, album_id)
+		.await?
+		.into_iter()
+		.collect::<HashSet<_>>();
+
+	// positions may have gaps where objects were removed, so new objects go after the last one
+	let position = db
+		.object_in_album()
+		.find_first(vec![object_in_album::album_id::equals(album_id)])
+		.order_by(object_in_album::position::order(Direction::Desc))
+		.exec()
+		.await?
+		.map_or(0, |last| last.position + 1);
+
+	let objects = db
+		.object()
+		.find_many(vec![object::id::in_vec(object_ids.clone())])
+		.select(object::select!({ id }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|object| object.id)
+		.collect::<HashSet<_>>();
+
+	// the objects keep the order that they were given in
+	let added = object_ids
+		.into_iter()
+		.filter(|object_id| objects.contains(object_id) && existing.insert(*object_id))
+		.collect::<Vec<_>>();
+
+	db._batch(
+		added
+			.iter()
+			.enumerate()
+			.map(|(i, object_id)| {
+				db.object_in_album().create(
+					album::id::equals(album_id),
+					object::id::equals(*object_id),
+					vec![object_in_album::position::set(position + i as i32)],
+				)
+			})
+			.collect::<Vec<_>>(),
+	)
+	.await?;
+
+	Ok(added.len())
+}
+
+/// This puts an album's objects in the given order.
+///
+/// Objects that aren't given are kept after those that are, in the order that they were in.
+pub async fn reorder_album(
+	db: &PrismaClient,
+	album_id: i32,
+	object_ids: Vec<i32>,
+) -> Result<(), AlbumError> {
+	find_album(db, album_id).await?;
+
+	let order = ordered(album_object_ids(db, album_id).await?, object_ids);
+
+	db._batch(
+		order
+			.into_iter()
+			.enumerate()
+			.map(|(position, object_id)| {
+				db.object_in_album().update(
+					object_in_album::album_id_object_id(album_id, object_id),
+					vec![object_in_album::position::set(position as i32)],
+				)
+			})
+			.collect::<Vec<_>>(),
+	)
+	.await?;
+
+	Ok(())
+}
+
+fn ordered(current: Vec<i32>, order: Vec<i32>) -> Vec<i32> {
+	let in_album = current.iter().copied().collect::<HashSet<_>>();
+	let mut seen = HashSet::new();
+
+	order
+		.into_iter()
+		.filter(|object_id| in_album.contains(object_id))
+		.chain(current)
+		.filter(|object_id| seen.insert(*object_id))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_is_within() {
+		// 1 → 2 → 3
+		let parents = HashMap::from([(2, 1), (3, 2)]);
+
+		assert!(is_within(1, 3, &parents));
+		assert!(is_within(3, 3, &parents));
+		assert!(!is_within(3, 1, &parents));
+		assert!(!is_within(4, 3, &parents));
+
+		let cycle = HashMap::from([(1, 2), (2, 1)]);
+		assert!(!is_within(3, 1, &cycle));
+	}
+
+	#[test]
+	fn test_ordered() {
+		assert_eq!(ordered(vec![1, 2, 3, 4], vec![3, 1]), vec![3, 1, 2, 4]);
+		// objects that aren't within the album are ignored
+		assert_eq!(ordered(vec![1, 2], vec![5, 2, 2]), vec![2, 1]);
+	}
+}
@@ -1,4 +1,7 @@
 use blake3::Hasher;
+use int_enum::IntEnum;
+use rspc::Type;
+use serde::{Deserialize, Serialize};
 use std::path::Path;
 use tokio::{
 	fs::File,
@@ -8,6 +11,13 @@ use tokio::{
 static SAMPLE_COUNT: u64 = 4;
 static SAMPLE_SIZE: u64 = 10000;
 
+/// Files up to this size (256 MiB) have their whole contents hashed, and larger ones are sampled.
+pub const FULL_CONTENT_HASH_LIMIT: u64 = 256 * 1024 * 1024;
+/// The size of each of the samples that are hashed from a larger file (1 MiB).
+const CONTENT_SAMPLE_SIZE: u64 = 1024 * 1024;
+/// This is hashed before a sampled digest, so it can never be mistaken for the full digest of a file that happens to contain the same bytes.
+const SAMPLED_DIGEST_CONTEXT: &[u8] = b"spacedrive sampled content hash v1";
+
 async fn read_at(file: &mut File, offset: u64, size: u64) -> Result<Vec<u8>, io::Error> {
 	let mut buf = vec![0u8; size as usize];
 
@@ -47,3 +57,67 @@ pub async fn generate_cas_id(path: impl AsRef<Path>, size: u64) -> Result<String
 	id.truncate(16);
 	Ok(id)
 }
+
+/// `ContentHashKind` is how an object's content hash was computed, as larger files are sampled rather than read in full.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, Eq, PartialEq, IntEnum)]
+pub enum ContentHashKind {
+	/// the BLAKE3 digest of the file's whole contents
+	Full = 0,
+	/// see [`generate_content_hash`] for what's sampled
+	Sampled = 1,
+}
+
+/// `ContentHash` identifies an object's content, so identical files can be found across locations (and libraries) without comparing them byte by byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentHash {
+	pub hash: String,
+	pub kind: ContentHashKind,
+}
+
+/// This computes a file's content hash.
+///
+/// Files up to [`FULL_CONTENT_HASH_LIMIT`] get the BLAKE3 digest of their whole contents. Larger files get a BLAKE3 digest of
+/// [`SAMPLED_DIGEST_CONTEXT`], then the file's size (as a little-endian `u64`), then 1 MiB from the start, middle and end of the file
+/// (the middle sample starts half a sample before the middle). Which one is used only depends on the size, so the same contents always get the same kind of hash.
+///
+/// Unlike the `cas_id`, this is never truncated, so it's what's relied on to decide that two files really are the same.
+pub async fn generate_content_hash(
+	path: impl AsRef<Path>,
+	size: u64,
+) -> Result<ContentHash, io::Error> {
+	let mut file = File::open(path).await?;
+	let mut hasher = Hasher::new();
+
+	let kind = if size <= FULL_CONTENT_HASH_LIMIT {
+		let mut buf = vec![0u8; CONTENT_SAMPLE_SIZE as usize];
+
+		loop {
+			let read = file.read(&mut buf).await?;
+			if read == 0 {
+				break;
+			}
+			hasher.update(&buf[..read]);
+		}
+
+		ContentHashKind::Full
+	} else {
+		hasher.update(SAMPLED_DIGEST_CONTEXT);
+		hasher.update(&size.to_le_bytes());
+
+		for offset in [
+			0,
+			size / 2 - CONTENT_SAMPLE_SIZE / 2,
+			size - CONTENT_SAMPLE_SIZE,
+		] {
+			hasher.update(&read_at(&mut file, offset, CONTENT_SAMPLE_SIZE).await?);
+		}
+
+		ContentHashKind::Sampled
+	};
+
+	Ok(ContentHash {
+		hash: hasher.finalize().to_hex().to_string(),
+		kind,
+	})
+}
@@ -0,0 +1,448 @@
+use std::{
+	collections::VecDeque,
+	fs::{self, File, OpenOptions},
+	io::{self, Write},
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use sd_crypto::{
+	crypto::stream::{Algorithm, StreamEncryption},
+	header::{file::FileHeader, keyslot::Keyslot},
+	primitives::{generate_master_key, BLOCK_SIZE, LATEST_FILE_HEADER, LATEST_KEYSLOT},
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tar::{Builder, Header};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+	job::{
+		IoThrottle, JobError, JobLogLevel, JobPriority, JobReportUpdate, JobResult, JobState,
+		StatefulJob, ThrottledReader, ThrottledWriter, WorkerContext,
+	},
+	library::LibraryContext,
+	prisma::file_path,
+};
+
+use super::{
+	check_free_space, encrypt::create_output, encrypted_size, remove_partial_output,
+	resolve_conflict, resolve_targets, ConflictStrategy, FileFailure, ObjectTarget, OutputConflict,
+};
+
+pub const FILE_ARCHIVER_JOB_NAME: &str = "file_archiver";
+
+/// Tar archives are made up of 512 byte blocks, and each entry's contents are padded to a whole block.
+const TAR_BLOCK_SIZE: u64 = 512;
+
+// The archiver packs a directory into a tar archive, which is encrypted as a single file (e.g. `Photos.tar.sdenc`).
+// The archive is written straight into the encryptor, so the plaintext archive never touches the disk. It's decrypted
+// like any other encrypted file, and can then be extracted with any tar tool.
+pub struct FileArchiverJob;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileArchiverJobState {
+	entries: Vec<ArchiveEntry>,
+	// where the archive is written if nothing's in the way, which is decided up front so it doesn't change if the job is resumed
+	archive_path: PathBuf,
+	// a tar stream can't be continued part of the way through, so an interrupted archive is removed and started again
+	output_path: Option<PathBuf>,
+	report: FileArchiverReport,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ArchiveEntry {
+	path: PathBuf,
+	// the entry's path within the archive, which starts with the directory's own name
+	name: PathBuf,
+	is_dir: bool,
+	size: u64,
+	file_path_id: i32,
+}
+
+/// This is the summary of an archiving job, which is returned once it's finished.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FileArchiverReport {
+	output_path: Option<PathBuf>,
+	total_files: usize,
+	total_bytes: u64,
+	files_archived: usize,
+	bytes_archived: u64,
+	/// files that couldn't be read, which are left out of the archive
+	errors: Vec<FileFailure>,
+	/// the archive's output already existed, and this is what was done about it
+	conflict: Option<OutputConflict>,
+}
+
+/// Only the directory's indexed contents are archived, so indexer rules are respected.
+#[derive(Serialize, Deserialize, Debug, Type, Hash)]
+pub struct FileArchiverJobInit {
+	pub location_id: i32,
+	pub path_id: i32,
+	pub key_uuid: Uuid,
+	pub algorithm: Algorithm,
+	pub output_directory: Option<PathBuf>, // if this isn't set, the archive is written alongside the directory
+	#[serde(default)]
+	pub on_conflict: ConflictStrategy, // what's done when the archive's output already exists
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for FileArchiverJob {
+	type Data = FileArchiverJobState;
+	type Init = FileArchiverJobInit;
+	type Step = ();
+
+	fn name(&self) -> &'static str {
+		FILE_ARCHIVER_JOB_NAME
+	}
+
+	fn priority(&self) -> JobPriority {
+		JobPriority::Low
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let (root_path, items) = resolve_targets(
+			&ctx.library_ctx.db,
+			state.init.location_id,
+			&[ObjectTarget::FilePath(state.init.path_id)],
+		)
+		.await?;
+
+		let directory = items.into_iter().find(|item| item.is_dir).ok_or_else(|| {
+			JobError::JobDataNotFound(format!("directory to archive <id={}>", state.init.path_id))
+		})?;
+
+		let directory_path = root_path.join(&directory.materialized_path);
+		let parent = Path::new(&directory.materialized_path)
+			.parent()
+			.map(Path::to_path_buf)
+			.unwrap_or_default();
+
+		// the database match also includes siblings that share a prefix (e.g. `Photos 2`), so they're filtered out here
+		let mut children = ctx
+			.library_ctx
+			.db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(state.init.location_id),
+				file_path::materialized_path::starts_with(directory.materialized_path.clone()),
+			])
+			.exec()
+			.await?
+			.into_iter()
+			.filter(|child| {
+				Path::new(&child.materialized_path).starts_with(&directory.materialized_path)
+			})
+			.collect::<Vec<_>>();
+
+		// directories are written before their contents
+		children.sort_by(|a, b| a.materialized_path.cmp(&b.materialized_path));
+
+		let entries = children
+			.into_iter()
+			.map(|child| {
+				let path = root_path.join(&child.materialized_path);
+
+				ArchiveEntry {
+					size: if child.is_dir {
+						0
+					} else {
+						fs::metadata(&path).map_or(0, |m| m.len())
+					},
+					name: Path::new(&child.materialized_path)
+						.strip_prefix(&parent)
+						.map(Path::to_path_buf)
+						.unwrap_or_else(|_| PathBuf::from(&child.materialized_path)),
+					path,
+					is_dir: child.is_dir,
+					file_path_id: child.id,
+				}
+			})
+			.collect::<Vec<_>>();
+
+		let mut archive_name = directory_path
+			.file_name()
+			.unwrap_or_default()
+			.to_os_string();
+		archive_name.push(".tar.sdenc");
+
+		let archive_path = match &state.init.output_directory {
+			Some(output_directory) => output_directory.join(archive_name),
+			None => directory_path.with_file_name(archive_name),
+		};
+
+		// every entry has a header block and is padded to a whole block, and the archive ends with two empty blocks
+		check_free_space([(
+			archive_path.clone(),
+			encrypted_size(
+				entries
+					.iter()
+					.map(|entry| entry.size + TAR_BLOCK_SIZE * 2)
+					.sum::<u64>() + TAR_BLOCK_SIZE * 2,
+			),
+		)])?;
+
+		state.data = Some(FileArchiverJobState {
+			report: FileArchiverReport {
+				total_files: entries.iter().filter(|entry| !entry.is_dir).count(),
+				total_bytes: entries.iter().map(|entry| entry.size).sum(),
+				..Default::default()
+			},
+			entries,
+			archive_path,
+			output_path: None,
+		});
+
+		// the whole archive is written by a single step
+		state.steps = VecDeque::from([()]);
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(1)]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let key_manager = &ctx.library_ctx.key_manager;
+		let user_key = key_manager.access_keymount(state.init.key_uuid)?.hashed_key;
+		let user_key_details = key_manager.access_keystore(state.init.key_uuid)?;
+
+		let data = current_data(&mut state.data);
+
+		if let Some(output_path) = data.output_path.take() {
+			warn!(
+				"restarting archive {:?}, as it was interrupted",
+				output_path
+			);
+			ctx.log(
+				JobLogLevel::Info,
+				"Restarting the archive, as it was interrupted",
+				Some(output_path.to_string_lossy().to_string()),
+			);
+			remove_partial_output(&output_path)?;
+		}
+
+		data.report = FileArchiverReport {
+			total_files: data.report.total_files,
+			total_bytes: data.report.total_bytes,
+			..Default::default()
+		};
+
+		if let Some(parent) = data.archive_path.parent() {
+			fs::create_dir_all(parent)?;
+		}
+
+		// this is checked before the output is recorded, as an existing file mustn't be cleaned up as if it were ours
+		let (resolved, decision) =
+			resolve_conflict(data.archive_path.clone(), state.init.on_conflict)?;
+		data.report.conflict = decision.map(|decision| OutputConflict {
+			path: data.archive_path.to_string_lossy().to_string(),
+			output_path: data.archive_path.clone(),
+			decision,
+		});
+
+		let resolved = match resolved {
+			Some(resolved) => resolved,
+			None => return Ok(()),
+		};
+
+		let master_key = generate_master_key();
+		let header = FileHeader::new(
+			LATEST_FILE_HEADER,
+			state.init.algorithm,
+			vec![Keyslot::new(
+				LATEST_KEYSLOT,
+				state.init.algorithm,
+				user_key_details.hashing_algorithm,
+				user_key_details.content_salt,
+				user_key,
+				&master_key,
+			)?],
+		);
+
+		// the output is recorded before it's created, so it can be cleaned up if the job is interrupted
+		data.output_path = Some(resolved.path.clone());
+		ctx.checkpoint(state).await?;
+
+		create_output(&resolved.path, &header, resolved.overwrite)?;
+
+		let writer = ArchiveWriter {
+			encryptor: StreamEncryption::new(master_key, &header.nonce, header.algorithm)?,
+			aad: header.generate_aad(),
+			writer: ThrottledWriter::new(
+				OpenOptions::new().append(true).open(&resolved.path)?,
+				Arc::clone(ctx.library_ctx.io_throttle()),
+			),
+			buffer: Vec::with_capacity(BLOCK_SIZE),
+		};
+
+		let data = current_data(&mut state.data);
+		let mut builder = Builder::new(writer);
+		let throttle = Arc::clone(ctx.library_ctx.io_throttle());
+
+		ctx.progress(vec![JobReportUpdate::TotalBytes(data.report.total_bytes)]);
+
+		// each entry is written on a blocking thread, and the job is able to pause between them
+		for entry in data.entries.clone() {
+			if !entry.is_dir {
+				ctx.progress_debounced(vec![JobReportUpdate::CurrentFile {
+					name: entry.name.to_string_lossy().to_string(),
+					size: entry.size,
+				}]);
+			}
+
+			let throttle = Arc::clone(&throttle);
+			let (returned, result) = tokio::task::spawn_blocking(move || {
+				let result = append_entry(&mut builder, &entry, throttle);
+				(builder, result.map(|readable| (entry, readable)))
+			})
+			.await?;
+			builder = returned;
+
+			match result? {
+				(entry, Ok(())) => {
+					if !entry.is_dir {
+						data.report.files_archived += 1;
+						data.report.bytes_archived += entry.size;
+					}
+				}
+				(entry, Err(e)) => {
+					warn!("archiving is skipping {:?}: {:#?}", entry.path, e);
+					ctx.log(
+						JobLogLevel::Warning,
+						format!("Skipped, as it couldn't be read: {e}"),
+						Some(entry.name.to_string_lossy().to_string()),
+					);
+					data.report.errors.push(FileFailure {
+						path: entry.name.to_string_lossy().to_string(),
+						file_path_id: Some(entry.file_path_id),
+						error: e.to_string(),
+					});
+				}
+			}
+
+			ctx.progress_debounced(vec![JobReportUpdate::BytesProcessed {
+				total: data.report.bytes_archived,
+				file: 0,
+			}]);
+		}
+
+		tokio::task::spawn_blocking(move || builder.into_inner()?.finish()).await??;
+
+		data.output_path = None;
+		data.report.output_path = Some(resolved.path);
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(1)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!("Finalizing archiving job: {:#?}", data.report);
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+
+	async fn cancel(
+		&self,
+		_ctx: &LibraryContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		if let Some(output_path) = state.data.as_mut().and_then(|data| data.output_path.take()) {
+			remove_partial_output(&output_path)?;
+		}
+
+		Ok(())
+	}
+}
+
+fn current_data(data: &mut Option<FileArchiverJobState>) -> &mut FileArchiverJobState {
+	data.as_mut()
+		.expect("critical error: missing data on job state")
+}
+
+/// This appends a single file or directory to the archive.
+///
+/// An entry that can't be read is left out (the inner error), but anything going wrong once it's being written leaves the archive unusable, so that fails the job.
+fn append_entry(
+	builder: &mut Builder<ArchiveWriter>,
+	entry: &ArchiveEntry,
+	throttle: Arc<IoThrottle>,
+) -> Result<io::Result<()>, JobError> {
+	if entry.is_dir {
+		if let Err(e) = fs::metadata(&entry.path) {
+			return Ok(Err(e));
+		}
+
+		builder.append_dir(&entry.name, &entry.path)?;
+	} else {
+		let (file, metadata) = match File::open(&entry.path)
+			.and_then(|file| file.metadata().map(|metadata| (file, metadata)))
+		{
+			Ok(file) => file,
+			Err(e) => return Ok(Err(e)),
+		};
+
+		// this is the same as `append_file`, but the file is read through the throttle
+		let mut header = Header::new_gnu();
+		header.set_metadata(&metadata);
+		builder.append_data(
+			&mut header,
+			&entry.name,
+			ThrottledReader::new(file, throttle),
+		)?;
+	}
+
+	Ok(Ok(()))
+}
+
+/// ArchiveWriter encrypts everything that's written to it, one block at a time.
+///
+/// `StreamEncryption` treats a short block as the final one, so the plaintext is buffered until there's a whole block to encrypt.
+struct ArchiveWriter {
+	encryptor: StreamEncryption,
+	aad: Vec<u8>,
+	writer: ThrottledWriter<File>,
+	buffer: Vec<u8>,
+}
+
+impl ArchiveWriter {
+	/// This encrypts whatever is left as the final block (which may be empty), and syncs the output to the disk.
+	fn finish(mut self) -> Result<(), JobError> {
+		self.encryptor
+			.encrypt_block(self.buffer.as_slice(), &mut self.writer, &self.aad)?;
+		self.writer.get_mut().sync_all()?;
+
+		Ok(())
+	}
+}
+
+impl Write for ArchiveWriter {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let len = buf.len().min(BLOCK_SIZE - self.buffer.len());
+		self.buffer.extend_from_slice(&buf[..len]);
+
+		if self.buffer.len() == BLOCK_SIZE {
+			self.encryptor
+				.encrypt_block(self.buffer.as_slice(), &mut self.writer, &self.aad)
+				.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+			self.buffer.clear();
+		}
+
+		Ok(len)
+	}
+
+	// only whole blocks can be encrypted before the archive is finished, so anything that's buffered stays that way
+	fn flush(&mut self) -> io::Result<()> {
+		self.writer.flush()
+	}
+}
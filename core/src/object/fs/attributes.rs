@@ -0,0 +1,139 @@
+use std::{fs, io, path::Path};
+
+use filetime::FileTime;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// The original attributes of a file, which are stored within its header's metadata when it's encrypted.
+///
+/// They're restored once the file is decrypted, so round-tripping a file through encryption doesn't change the timestamps that backup tools rely on.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FileAttributes {
+	/// these are stored as seconds and nanoseconds since the unix epoch
+	modified: Option<(i64, u32)>,
+	accessed: Option<(i64, u32)>,
+	/// the creation time is kept for reference, but it can't be restored on every platform
+	created: Option<(i64, u32)>,
+	/// the unix permission bits
+	#[serde(default)]
+	mode: Option<u32>,
+	#[serde(default)]
+	readonly: bool,
+	/// extended attributes (unix only), as pairs of raw names and values
+	#[serde(default)]
+	xattrs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl FileAttributes {
+	pub fn read(path: &Path) -> io::Result<Self> {
+		let metadata = fs::metadata(path)?;
+
+		let to_parts = |time: FileTime| (time.unix_seconds(), time.nanoseconds());
+
+		Ok(Self {
+			modified: Some(to_parts(FileTime::from_last_modification_time(&metadata))),
+			accessed: Some(to_parts(FileTime::from_last_access_time(&metadata))),
+			created: FileTime::from_creation_time(&metadata).map(to_parts),
+			mode: mode(&metadata),
+			readonly: metadata.permissions().readonly(),
+			xattrs: read_xattrs(path),
+		})
+	}
+
+	/// This restores everything that the current platform supports.
+	///
+	/// It's best-effort, as the output may be on a filesystem that doesn't support some of the attributes, so failures are only logged.
+	pub fn restore(&self, path: &Path) {
+		// extended attributes are written first, as the permissions may make the file read-only
+		restore_xattrs(path, &self.xattrs);
+
+		if let Some((seconds, nanos)) = self.modified {
+			let modified = FileTime::from_unix_time(seconds, nanos);
+			let accessed = self.accessed.map_or(modified, |(seconds, nanos)| {
+				FileTime::from_unix_time(seconds, nanos)
+			});
+
+			if let Err(e) = filetime::set_file_times(path, accessed, modified) {
+				warn!("Failed to restore the timestamps of {:?}: {:#?}", path, e);
+			}
+		}
+
+		if let Err(e) = self.restore_permissions(path) {
+			warn!("Failed to restore the permissions of {:?}: {:#?}", path, e);
+		}
+	}
+
+	fn restore_permissions(&self, path: &Path) -> io::Result<()> {
+		let mut permissions = fs::metadata(path)?.permissions();
+
+		#[cfg(unix)]
+		if let Some(mode) = self.mode {
+			use std::os::unix::fs::PermissionsExt;
+
+			permissions.set_mode(mode);
+			return fs::set_permissions(path, permissions);
+		}
+
+		permissions.set_readonly(self.readonly);
+		fs::set_permissions(path, permissions)
+	}
+}
+
+#[cfg(unix)]
+fn mode(metadata: &fs::Metadata) -> Option<u32> {
+	use std::os::unix::fs::PermissionsExt;
+
+	Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode(_metadata: &fs::Metadata) -> Option<u32> {
+	None
+}
+
+#[cfg(unix)]
+fn read_xattrs(path: &Path) -> Vec<(Vec<u8>, Vec<u8>)> {
+	use std::os::unix::ffi::OsStrExt;
+
+	match xattr::list(path) {
+		Ok(names) => names
+			.filter_map(|name| match xattr::get(path, &name) {
+				Ok(value) => Some((name.as_bytes().to_vec(), value.unwrap_or_default())),
+				Err(e) => {
+					warn!("Failed to read extended attribute {:?}: {:#?}", name, e);
+					None
+				}
+			})
+			.collect(),
+		// plenty of filesystems don't support extended attributes at all
+		Err(_) => Vec::new(),
+	}
+}
+
+#[cfg(not(unix))]
+fn read_xattrs(_path: &Path) -> Vec<(Vec<u8>, Vec<u8>)> {
+	Vec::new()
+}
+
+#[cfg(unix)]
+fn restore_xattrs(path: &Path, xattrs: &[(Vec<u8>, Vec<u8>)]) {
+	use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+	for (name, value) in xattrs {
+		let name = OsStr::from_bytes(name);
+
+		if let Err(e) = xattr::set(path, name, value) {
+			warn!(
+				"Failed to restore extended attribute {:?} of {:?}: {:#?}",
+				name, path, e
+			);
+		}
+	}
+}
+
+#[cfg(not(unix))]
+fn restore_xattrs(_path: &Path, xattrs: &[(Vec<u8>, Vec<u8>)]) {
+	if !xattrs.is_empty() {
+		warn!("Extended attributes can't be restored on this platform");
+	}
+}
@@ -0,0 +1,388 @@
+use std::{collections::VecDeque, fs::File, io, path::PathBuf};
+
+use sd_crypto::header::file::FileHeader;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+	job::{
+		JobError, JobLogLevel, JobPriority, JobReportUpdate, JobResult, JobState, StatefulJob,
+		WorkerContext,
+	},
+	prisma::{file_path, location, object},
+};
+
+use super::{encrypt::Metadata, FileFailure};
+
+pub const HEADER_AUDITOR_JOB_NAME: &str = "encrypted_header_auditor";
+
+location::include!(location_with_vault_key { vault_key });
+
+file_path::select!(file_path_for_auditor {
+	id
+	materialized_path
+	key: select { uuid }
+	object: select { key: select { uuid } }
+});
+
+// The auditor reads the header of every encrypted file within the selected locations (and every file that the database says is encrypted),
+// and cross-checks it against what the library has recorded. Nothing is decrypted other than the header's metadata, so it's far quicker
+// than the verifier, and nothing is fixed automatically as the database may well be the side that's wrong.
+pub struct FileHeaderAuditorJob;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileHeaderAuditorJobState {
+	report: FileHeaderAuditReport,
+}
+
+#[derive(Serialize, Deserialize, Debug, Type, Hash)]
+pub struct FileHeaderAuditorJobInit {
+	pub location_ids: Vec<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileHeaderAuditorJobStep {
+	location_id: i32,
+	file_path_id: i32,
+	obj_name: String,
+	obj_path: PathBuf,
+	/// the keys that the file's path and object are associated with
+	recorded_keys: Vec<Uuid>,
+}
+
+/// This is the summary of an audit, which is returned once it's finished.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FileHeaderAuditReport {
+	total_files: usize,
+	files_consistent: usize,
+	mismatches: Vec<FileHeaderAuditIssue>,
+	/// headers with metadata that none of the mounted keys are able to decrypt, so their checksums weren't checked
+	checksums_unchecked: usize,
+	/// files that couldn't be read at all
+	skipped: Vec<FileFailure>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileHeaderAuditIssue {
+	location_id: i32,
+	file_path_id: i32,
+	path: String,
+	problems: Vec<HeaderMismatch>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum HeaderMismatch {
+	/// the file is in the database, but not on disk
+	Missing,
+	/// the database says the file is encrypted, but it doesn't have a valid header
+	NotEncrypted(String),
+	/// none of the header's keyslots belong to a key within this library, e.g. as it was re-encrypted outside of Spacedrive
+	UnknownKeys,
+	/// the database associates the file with a key that none of its keyslots belong to
+	KeyMismatch { recorded: Uuid, found: Vec<Uuid> },
+	/// the header belongs to a key within this library, but the database doesn't associate the file with any key
+	MissingKeyRecord { found: Vec<Uuid> },
+	/// a keyslot has the same content salt as a key, but was hashed with a different algorithm
+	HashingAlgorithmMismatch { key_uuid: Uuid },
+	/// a keyslot uses a different encryption algorithm to the rest of the header
+	AlgorithmMismatch,
+	/// the plaintext checksum within the header's metadata differs from the one recorded for the original object
+	ChecksumMismatch { recorded: String, header: String },
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for FileHeaderAuditorJob {
+	type Data = FileHeaderAuditorJobState;
+	type Init = FileHeaderAuditorJobInit;
+	type Step = FileHeaderAuditorJobStep;
+
+	fn name(&self) -> &'static str {
+		HEADER_AUDITOR_JOB_NAME
+	}
+
+	fn priority(&self) -> JobPriority {
+		JobPriority::Low
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let locations = ctx
+			.library_ctx
+			.db
+			.location()
+			.find_many(vec![location::id::in_vec(state.init.location_ids.clone())])
+			.include(location_with_vault_key::include())
+			.exec()
+			.await?;
+
+		state.steps = VecDeque::new();
+
+		for location in locations {
+			let root_path = match &location.local_path {
+				Some(local_path) => PathBuf::from(local_path),
+				None => {
+					warn!(
+						"audit is skipping location {} as it isn't available locally",
+						location.id
+					);
+					ctx.log(
+						JobLogLevel::Warning,
+						format!(
+							"Skipped location {}, as it isn't available locally",
+							location.id
+						),
+						None,
+					);
+					continue;
+				}
+			};
+
+			let mut params = vec![
+				file_path::location_id::equals(location.id),
+				file_path::is_dir::equals(false),
+			];
+
+			// every file within a vault is encrypted, but they don't have the usual extension
+			if location.vault_key.is_none() {
+				params.push(prisma_client_rust::operator::or(vec![
+					file_path::extension::equals(Some("sdenc".to_string())),
+					file_path::key_id::not(None),
+					file_path::object::is(vec![object::key_id::not(None)]),
+				]));
+			}
+
+			for item in ctx
+				.library_ctx
+				.db
+				.file_path()
+				.find_many(params)
+				.select(file_path_for_auditor::select())
+				.exec()
+				.await?
+			{
+				let mut recorded_keys = item
+					.key
+					.iter()
+					.chain(item.object.iter().filter_map(|object| object.key.as_ref()))
+					.filter_map(|key| Uuid::parse_str(&key.uuid).ok())
+					.collect::<Vec<_>>();
+				recorded_keys.dedup();
+
+				state.steps.push_back(FileHeaderAuditorJobStep {
+					location_id: location.id,
+					file_path_id: item.id,
+					obj_path: root_path.join(&item.materialized_path),
+					obj_name: item.materialized_path,
+					recorded_keys,
+				});
+			}
+		}
+
+		state.data = Some(FileHeaderAuditorJobState {
+			report: FileHeaderAuditReport {
+				total_files: state.steps.len(),
+				..Default::default()
+			},
+		});
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Auditing {}",
+			step.obj_name
+		))]);
+
+		let result = audit_file(&ctx, step).await;
+
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		match result {
+			Ok((problems, checksum_checked)) => {
+				if !checksum_checked {
+					data.report.checksums_unchecked += 1;
+				}
+
+				if problems.is_empty() {
+					data.report.files_consistent += 1;
+				} else {
+					warn!(
+						"audit found mismatches for {}: {:?}",
+						step.obj_name, problems
+					);
+					ctx.log(
+						JobLogLevel::Warning,
+						format!("Header doesn't match the database: {problems:?}"),
+						Some(step.obj_name.clone()),
+					);
+					data.report.mismatches.push(FileHeaderAuditIssue {
+						location_id: step.location_id,
+						file_path_id: step.file_path_id,
+						path: step.obj_name.clone(),
+						problems,
+					});
+				}
+			}
+			Err(e) => {
+				warn!("audit couldn't read {}: {:#?}", step.obj_name, e);
+				ctx.log(
+					JobLogLevel::Warning,
+					format!("Skipped, as it couldn't be read: {e}"),
+					Some(step.obj_name.clone()),
+				);
+				data.report.skipped.push(FileFailure {
+					path: step.obj_name.clone(),
+					file_path_id: Some(step.file_path_id),
+					error: e.to_string(),
+				});
+			}
+		}
+
+		ctx.progress(vec![
+			JobReportUpdate::CompletedTaskCount(state.step_number + 1),
+			JobReportUpdate::Message(format!(
+				"Audited {} of {} files ({} mismatched)",
+				state.step_number + 1,
+				data.report.total_files,
+				data.report.mismatches.len()
+			)),
+		]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!("Finalizing header audit job: {:#?}", data.report);
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
+
+/// This reads a file's header and compares it against the database, and returns every mismatch that was found.
+///
+/// It also returns whether the header's checksum was checked (or there wasn't one to check).
+/// I/O errors (other than the file being missing) are returned as errors, as they say nothing about the file's header.
+async fn audit_file(
+	ctx: &WorkerContext,
+	step: &FileHeaderAuditorJobStep,
+) -> Result<(Vec<HeaderMismatch>, bool), JobError> {
+	let mut reader = match File::open(&step.obj_path) {
+		Ok(reader) => reader,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => {
+			return Ok((vec![HeaderMismatch::Missing], true))
+		}
+		Err(e) => return Err(e.into()),
+	};
+
+	let header = match FileHeader::deserialize(&mut reader) {
+		Ok((header, _)) => header,
+		Err(sd_crypto::Error::Io(e)) if e.kind() != io::ErrorKind::UnexpectedEof => {
+			return Err(e.into())
+		}
+		Err(e) => return Ok((vec![HeaderMismatch::NotEncrypted(e.to_string())], true)),
+	};
+
+	let key_manager = &ctx.library_ctx.key_manager;
+	let library_keys = key_manager
+		.dump_keystore()
+		.into_iter()
+		.filter(|key| !key.memory_only)
+		.collect::<Vec<_>>();
+
+	let mut problems = Vec::new();
+	let mut found = Vec::new();
+
+	if header
+		.keyslots
+		.iter()
+		.any(|keyslot| keyslot.algorithm != header.algorithm)
+	{
+		problems.push(HeaderMismatch::AlgorithmMismatch);
+	}
+
+	for keyslot in &header.keyslots {
+		// a keyslot's content salt is copied from the key it was created with, so it identifies the key without needing to unlock it
+		if let Some(key) = library_keys
+			.iter()
+			.find(|key| key.content_salt == keyslot.content_salt)
+		{
+			if key.hashing_algorithm != keyslot.hashing_algorithm {
+				problems.push(HeaderMismatch::HashingAlgorithmMismatch { key_uuid: key.uuid });
+			}
+
+			found.push(key.uuid);
+		}
+	}
+
+	if found.is_empty() {
+		problems.push(HeaderMismatch::UnknownKeys);
+	} else if step.recorded_keys.is_empty() {
+		problems.push(HeaderMismatch::MissingKeyRecord {
+			found: found.clone(),
+		});
+	}
+
+	for recorded in &step.recorded_keys {
+		if !found.contains(recorded) {
+			problems.push(HeaderMismatch::KeyMismatch {
+				recorded: *recorded,
+				found: found.clone(),
+			});
+		}
+	}
+
+	if header.metadata.is_none() {
+		return Ok((problems, true));
+	}
+
+	let metadata = match found.iter().find_map(|uuid| {
+		let hashed_key = key_manager.access_keymount(*uuid).ok()?.hashed_key;
+
+		header
+			.decrypt_metadata_from_prehashed::<Metadata>(vec![hashed_key])
+			.ok()
+	}) {
+		Some(metadata) => metadata,
+		None => return Ok((problems, false)),
+	};
+
+	// the original object may have been deleted since, in which case there's nothing to compare against
+	if let Some(header_checksum) = metadata.checksum {
+		if let Some(recorded) = ctx
+			.library_ctx
+			.db
+			.object()
+			.find_unique(object::id::equals(metadata.object_id))
+			.exec()
+			.await?
+			.and_then(|object| object.integrity_checksum)
+		{
+			if recorded != header_checksum {
+				problems.push(HeaderMismatch::ChecksumMismatch {
+					recorded,
+					header: header_checksum,
+				});
+			}
+		}
+	}
+
+	Ok((problems, true))
+}
@@ -0,0 +1,448 @@
+use std::{
+	collections::VecDeque,
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tracing::{info, warn};
+
+use crate::{
+	invalidate_query,
+	job::{
+		JobError, JobLogLevel, JobPriority, JobReportUpdate, JobResult, JobState, StatefulJob,
+		WorkerContext,
+	},
+	library::LibraryContext,
+	location::file_path_helper::record_file_path,
+	prisma::file_path,
+};
+
+use super::{
+	check_free_space, is_location_root, plan_output, resolve_destination, resolve_targets,
+	walk_directory, ConflictStrategy, Destination, EntryKind, FileFailure, ObjectTarget,
+	OutputConflict,
+};
+
+pub const FILE_COPIER_JOB_NAME: &str = "file_copier";
+
+/// The size of the chunks that files are copied in, so the job can be paused or canceled part of the way through a large file.
+const COPY_CHUNK_SIZE: usize = 1024 * 1024;
+
+// The copier copies files and directories into a directory within a location, which may be within another location (and on another volume).
+// Copies are linked to the same objects as the originals, as they have the same contents, so they're shown straight away with their tags and metadata.
+pub struct FileCopierJob;
+
+#[derive(Serialize, Deserialize, Debug, Type, Hash, Clone)]
+pub struct FileCopierJobInit {
+	pub location_id: i32,
+	pub targets: Vec<ObjectTarget>,
+	pub target_location_id: i32,
+	pub target_directory_id: Option<i32>, // if this isn't set, the targets are copied into the root of the location
+	#[serde(default)]
+	pub on_conflict: ConflictStrategy, // what's done when a target already exists within the destination
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileCopierJobState {
+	source_root: PathBuf,
+	destination: Destination,
+	// the file that's currently being written, which is incomplete if the job was paused (or interrupted) part of the way through it
+	current_output: Option<PathBuf>,
+	total_bytes: u64,
+	bytes_copied: u64,
+	report: FileCopierReport,
+}
+
+/// This is the summary of a copier job, which is returned once it's finished.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FileCopierReport {
+	files_copied: usize,
+	/// targets that already existed within the destination, and what was done about it
+	conflicts: Vec<OutputConflict>,
+	failures: Vec<FileFailure>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileCopierJobStep {
+	/// the path within the source location
+	source: String,
+	/// the path within the destination location
+	output: String,
+	kind: EntryKind,
+	size: u64,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for FileCopierJob {
+	type Data = FileCopierJobState;
+	type Init = FileCopierJobInit;
+	type Step = FileCopierJobStep;
+
+	fn name(&self) -> &'static str {
+		FILE_COPIER_JOB_NAME
+	}
+
+	fn priority(&self) -> JobPriority {
+		JobPriority::Low
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let db = &ctx.library_ctx.db;
+
+		let (source_root, items) =
+			resolve_targets(db, state.init.location_id, &state.init.targets).await?;
+		let destination = resolve_destination(
+			db,
+			state.init.target_location_id,
+			state.init.target_directory_id,
+		)
+		.await?;
+
+		let mut report = FileCopierReport::default();
+		let mut steps = VecDeque::new();
+
+		for item in items.into_iter().filter(|item| !is_location_root(item)) {
+			let source = item.materialized_path.trim_end_matches('/').to_string();
+			let source_path = source_root.join(&source);
+
+			match plan_copy(
+				&source,
+				&source_path,
+				&destination,
+				state.init.on_conflict,
+				&mut report.conflicts,
+			) {
+				Ok(item_steps) => steps.extend(item_steps),
+				Err(e) => {
+					warn!("copier is skipping {}: {:#?}", source, e);
+					ctx.log(
+						JobLogLevel::Warning,
+						format!("Not copied: {e}"),
+						Some(source.clone()),
+					);
+					report.failures.push(FileFailure {
+						path: source,
+						file_path_id: Some(item.id),
+						error: e.to_string(),
+					});
+				}
+			}
+		}
+
+		let total_bytes = steps.iter().map(|step| step.size).sum();
+		check_free_space([(destination.path.clone(), total_bytes)])?;
+
+		info!(
+			"Copying {} files and directories ({} bytes) into {}",
+			steps.len(),
+			total_bytes,
+			destination.path.display()
+		);
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(steps.len()),
+			JobReportUpdate::TotalBytes(total_bytes),
+		]);
+
+		state.data = Some(FileCopierJobState {
+			source_root,
+			destination,
+			current_output: None,
+			total_bytes,
+			bytes_copied: 0,
+			report,
+		});
+		state.steps = steps;
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		// a paused (or interrupted) job copies the file it was working on again from the start
+		if let Some(output) = data.current_output.take() {
+			remove_partial_copy(&output)?;
+		}
+
+		if let Err(e) = copy_entry(&ctx, state.init.location_id, step, data).await {
+			if let Some(output) = data.current_output.take() {
+				remove_partial_copy(&output)?;
+			}
+
+			warn!("copying failed for {}: {:#?}", step.source, e);
+			ctx.log(
+				JobLogLevel::Warning,
+				format!("Copying failed: {e}"),
+				Some(step.source.clone()),
+			);
+			data.report.failures.push(FileFailure {
+				path: step.source.clone(),
+				file_path_id: None,
+				error: e.to_string(),
+			});
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!("Finalizing copier job: {:#?}", data.report);
+
+		invalidate_query!(ctx.library_ctx, "locations.getExplorerData");
+		invalidate_query!(ctx.library_ctx, "savedSearches.getExplorerData");
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+
+	async fn cancel(
+		&self,
+		_ctx: &LibraryContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		if let Some(output) = state
+			.data
+			.as_mut()
+			.and_then(|data| data.current_output.take())
+		{
+			remove_partial_copy(&output)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// This lists the steps that copy a target, which is everything within it if it's a directory.
+fn plan_copy(
+	source: &str,
+	source_path: &Path,
+	destination: &Destination,
+	strategy: ConflictStrategy,
+	conflicts: &mut Vec<OutputConflict>,
+) -> Result<Vec<FileCopierJobStep>, JobError> {
+	let file_name = source_path
+		.file_name()
+		.and_then(|name| name.to_str())
+		.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+	let output = match plan_output(source_path, destination, file_name, strategy, conflicts)? {
+		Some(output) => output,
+		None => return Ok(vec![]),
+	};
+
+	// the output may have been renamed, to avoid a conflict
+	let output_name = output
+		.path
+		.file_name()
+		.and_then(|name| name.to_str())
+		.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+	let output = destination.child(output_name);
+
+	let metadata = source_path.symlink_metadata()?;
+	if !metadata.is_dir() {
+		return Ok(vec![FileCopierJobStep {
+			source: source.to_string(),
+			output,
+			kind: if metadata.is_symlink() {
+				EntryKind::Symlink
+			} else {
+				EntryKind::File
+			},
+			size: metadata.len(),
+		}]);
+	}
+
+	let mut steps = vec![FileCopierJobStep {
+		source: source.to_string(),
+		output: output.clone(),
+		kind: EntryKind::Directory,
+		size: 0,
+	}];
+
+	for entry in walk_directory(source_path)? {
+		// paths that aren't UTF-8 can't be stored within the database
+		let path = match entry.path.to_str() {
+			Some(path) => path.replace('\\', "/"),
+			None => {
+				warn!("copier is skipping {}", entry.path.display());
+				continue;
+			}
+		};
+
+		steps.push(FileCopierJobStep {
+			source: format!("{source}/{path}"),
+			output: format!("{output}/{path}"),
+			kind: entry.kind,
+			size: entry.size,
+		});
+	}
+
+	Ok(steps)
+}
+
+/// This copies a single file or directory, and records the copy within the destination location.
+async fn copy_entry(
+	ctx: &WorkerContext,
+	location_id: i32,
+	step: &FileCopierJobStep,
+	data: &mut FileCopierJobState,
+) -> Result<(), JobError> {
+	let source_path = data.source_root.join(&step.source);
+	let output_path = data.destination.path.join(
+		step.output
+			.strip_prefix(&data.destination.materialized_path)
+			.unwrap_or(&step.output),
+	);
+
+	match step.kind {
+		EntryKind::Directory => {
+			fs::create_dir_all(&output_path)?;
+
+			record_file_path(
+				&ctx.library_ctx,
+				data.destination.location_id,
+				&step.output,
+				true,
+				None,
+				0,
+			)
+			.await?;
+		}
+		EntryKind::File => {
+			ctx.progress(vec![
+				// this is sent with every file, as a resumed job doesn't run `init` again
+				JobReportUpdate::TotalBytes(data.total_bytes),
+				JobReportUpdate::CurrentFile {
+					name: step.source.clone(),
+					size: step.size,
+				},
+			]);
+
+			data.current_output = Some(output_path.clone());
+			let copied = copy_file(ctx, &source_path, &output_path, data.bytes_copied).await?;
+			data.current_output = None;
+			data.bytes_copied += copied;
+			data.report.files_copied += 1;
+
+			// the copy has the same contents as the original, so it's the same object
+			let object_id = ctx
+				.library_ctx
+				.db
+				.file_path()
+				.find_first(vec![
+					file_path::location_id::equals(location_id),
+					file_path::materialized_path::equals(step.source.clone()),
+				])
+				.exec()
+				.await?
+				.and_then(|file_path| file_path.object_id);
+
+			record_file_path(
+				&ctx.library_ctx,
+				data.destination.location_id,
+				&step.output,
+				false,
+				object_id,
+				copied,
+			)
+			.await?;
+		}
+		// links aren't recorded, as whether they're indexed depends on the location's symlink policy
+		EntryKind::Symlink => copy_symlink(&source_path, &output_path)?,
+	}
+
+	Ok(())
+}
+
+/// This copies a file in chunks, and returns how many bytes were copied.
+///
+/// The progress is reported as a running total across the job, starting from `bytes_before`.
+pub(super) async fn copy_file(
+	ctx: &WorkerContext,
+	source: &Path,
+	output: &Path,
+	bytes_before: u64,
+) -> Result<u64, JobError> {
+	let throttle = ctx.library_ctx.io_throttle();
+
+	let mut reader = tokio::fs::File::open(source).await?;
+	let mut writer = tokio::fs::File::create(output).await?;
+
+	let mut buf = vec![0u8; COPY_CHUNK_SIZE];
+	let mut copied = 0;
+
+	loop {
+		let read = reader.read(&mut buf).await?;
+		if read == 0 {
+			break;
+		}
+
+		throttle.read(read as u64).await;
+		writer.write_all(&buf[..read]).await?;
+		throttle.write(read as u64).await;
+
+		copied += read as u64;
+		ctx.progress_debounced(vec![JobReportUpdate::BytesProcessed {
+			total: bytes_before + copied,
+			file: copied,
+		}]);
+	}
+
+	writer.sync_all().await?;
+	fs::set_permissions(output, fs::metadata(source)?.permissions())?;
+
+	Ok(copied)
+}
+
+/// This creates a link that points to the same place as another one.
+pub(super) fn copy_symlink(source: &Path, output: &Path) -> Result<(), io::Error> {
+	let target = fs::read_link(source)?;
+
+	#[cfg(unix)]
+	std::os::unix::fs::symlink(&target, output)?;
+	// windows has different links for files and directories
+	#[cfg(windows)]
+	{
+		if fs::metadata(source).map_or(false, |metadata| metadata.is_dir()) {
+			std::os::windows::fs::symlink_dir(&target, output)?;
+		} else {
+			std::os::windows::fs::symlink_file(&target, output)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// This removes a file or directory that was only partially copied.
+pub(super) fn remove_partial_copy(path: &Path) -> Result<(), JobError> {
+	let result = match path.symlink_metadata() {
+		Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+		Ok(_) => fs::remove_file(path),
+		Err(e) => Err(e),
+	};
+
+	match result {
+		Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+		_ => Ok(()),
+	}
+}
@@ -1,34 +1,103 @@
-use std::{collections::VecDeque, path::PathBuf};
+use std::{
+	collections::VecDeque,
+	fs::{File, OpenOptions},
+	io::{Seek, SeekFrom},
+	path::{Path, PathBuf},
+	str::FromStr,
+};
 
-use sd_crypto::{crypto::stream::StreamDecryption, header::file::FileHeader, Protected};
+use sd_crypto::{
+	crypto::stream::StreamDecryption,
+	fs::filename::decrypt_filename,
+	header::file::FileHeader,
+	keys::keymanager::KeySource,
+	primitives::{AEAD_TAG_SIZE, BLOCK_SIZE, KEY_LEN},
+	Protected,
+};
 use serde::{Deserialize, Serialize};
 use specta::Type;
+use tracing::warn;
+use uuid::Uuid;
 
 use crate::{
-	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
-	prisma::{file_path, location},
+	invalidate_query,
+	job::{
+		IoThrottle, JobError, JobLogLevel, JobPriority, JobReportUpdate, JobResult, JobState,
+		StatefulJob, WorkerContext,
+	},
+	library::{KeyConsumer, LibraryContext},
+	prisma::{key, object},
+	util::db::write_storedkey_to_db,
+};
+
+use super::{
+	check_free_space, encrypt::Metadata, failed_file_paths, is_selected, record_key_association,
+	remove_partial_output, resolve_conflict, resolve_targets, sparse::SparseWriter,
+	ConflictStrategy, FileFailure, ObjectTarget, OutputConflict, SourceStamp,
 };
+
 pub struct FileDecryptorJob;
 #[derive(Serialize, Deserialize, Debug)]
-pub struct FileDecryptorJobState {}
+pub struct FileDecryptorJobState {
+	// the output that's currently being written, which is incomplete if the job was paused (or interrupted) part of the way through it
+	#[serde(default)]
+	current_output: Option<PathBuf>,
+	// the number of blocks of `current_output` that had been written (and synced) as of the last checkpoint
+	#[serde(default)]
+	blocks_written: u32,
+	// the encrypted file's size and modification time when `current_output` was started, as one that's changed since can't be continued
+	#[serde(default)]
+	current_source: Option<SourceStamp>,
+	// these are only used for reporting progress, and are measured in encrypted bytes
+	#[serde(default)]
+	total_bytes: u64,
+	#[serde(default)]
+	bytes_decrypted: u64,
+	#[serde(default)]
+	report: FileDecryptorReport,
+}
+
+/// This is the summary of a decryption job, which is returned once it's finished.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FileDecryptorReport {
+	/// files whose output already existed, and what was done about it
+	conflicts: Vec<OutputConflict>,
+	#[serde(default)]
+	errors: Vec<FileFailure>,
+	/// this is what `jobs.retryFailed` runs, which only decrypts the files that failed (using the mounted keys, as the password isn't kept)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	retry: Option<FileDecryptorJobInit>,
+}
+
+/// The number of blocks that are decrypted between checkpoints.
+const CHECKPOINT_BLOCKS: u32 = 64;
 
 // decrypt could have an option to restore metadata (and another specific option for file name? - would turn "output file" into "output path" in the UI)
-#[derive(Serialize, Deserialize, Debug, Type, Hash)]
+#[derive(Serialize, Deserialize, Debug, Type, Hash, Clone)]
 pub struct FileDecryptorJobInit {
 	pub location_id: i32,
-	pub object_id: i32,
-	pub output_path: Option<PathBuf>,
+	pub targets: Vec<ObjectTarget>,
+	pub output_directory: Option<PathBuf>, // if this isn't set, decrypted files are written alongside the originals
+	#[serde(default)]
+	pub on_conflict: ConflictStrategy, // what's done when a file's output already exists
 	pub password: Option<String>, // if this is set, we can assume the user chose password decryption
 	pub save_to_library: Option<bool>,
+	#[serde(default)]
+	pub only_file_paths: Option<Vec<i32>>, // if this is set, only these files (from within the targets) are decrypted
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FileDecryptorJobStep {
 	obj_name: String,
 	obj_path: PathBuf,
+	object_id: Option<i32>,
+	#[serde(default)]
+	size: u64,
+	#[serde(default)]
+	file_path_id: Option<i32>,
 }
 
-const JOB_NAME: &str = "file_decryptor";
+pub const FILE_DECRYPTOR_JOB_NAME: &str = "file_decryptor";
 
 #[async_trait::async_trait]
 impl StatefulJob for FileDecryptorJob {
@@ -37,47 +106,104 @@ impl StatefulJob for FileDecryptorJob {
 	type Step = FileDecryptorJobStep;
 
 	fn name(&self) -> &'static str {
-		JOB_NAME
+		FILE_DECRYPTOR_JOB_NAME
 	}
 
-	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
-		// enumerate files to decrypt
-		// populate the steps with them (local file paths)
-		let location = ctx
-			.library_ctx
-			.db
-			.location()
-			.find_unique(location::id::equals(state.init.location_id))
-			.exec()
-			.await?
-			.expect("critical error: can't find location");
+	fn priority(&self) -> JobPriority {
+		JobPriority::Low
+	}
+
+	/// These are the keys that the targets are known to have been encrypted with.
+	/// Files without a known key are tried against every mounted key, so they don't need any key in particular.
+	async fn required_keys(
+		&self,
+		ctx: &LibraryContext,
+		init: &Self::Init,
+	) -> Result<Vec<Uuid>, JobError> {
+		// password decryption doesn't use the key manager
+		if init.password.is_some() {
+			return Ok(vec![]);
+		}
+
+		let (_, items) = resolve_targets(&ctx.db, init.location_id, &init.targets).await?;
 
-		let root_path = location
-			.local_path
-			.as_ref()
-			.map(PathBuf::from)
-			.expect("critical error: issue getting local path as pathbuf");
+		let object_ids = items
+			.into_iter()
+			.filter(|item| is_selected(&init.only_file_paths, item.id))
+			.filter_map(|item| item.object_id)
+			.collect();
 
-		let item = ctx
-			.library_ctx
+		Ok(ctx
 			.db
-			.file_path()
-			.find_first(vec![file_path::object_id::equals(Some(
-				state.init.object_id,
-			))])
+			.key()
+			.find_many(vec![key::objects::some(vec![object::id::in_vec(
+				object_ids,
+			)])])
 			.exec()
 			.await?
-			.expect("critical error: can't find object");
+			.into_iter()
+			.filter_map(|key| Uuid::from_str(&key.uuid).ok())
+			.collect())
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		// enumerate files to decrypt
+		// populate the steps with them (local file paths)
+		let (root_path, items) = resolve_targets(
+			&ctx.library_ctx.db,
+			state.init.location_id,
+			&state.init.targets,
+		)
+		.await?;
+
+		state.steps = items
+			.into_iter()
+			.filter(|item| {
+				if item.is_dir {
+					warn!(
+						"decryption is skipping {} as it isn't a file",
+						item.materialized_path
+					);
+					ctx.log(
+						JobLogLevel::Info,
+						"Skipped, as it isn't a file",
+						Some(item.materialized_path.clone()),
+					);
+				}
+
+				!item.is_dir && is_selected(&state.init.only_file_paths, item.id)
+			})
+			.map(|item| {
+				let obj_path = root_path.join(&item.materialized_path);
+
+				FileDecryptorJobStep {
+					size: std::fs::metadata(&obj_path).map_or(0, |m| m.len()),
+					obj_path,
+					obj_name: item.materialized_path,
+					object_id: item.object_id,
+					file_path_id: Some(item.id),
+				}
+			})
+			.collect::<VecDeque<_>>();
 
-		let obj_name = item.materialized_path;
+		// the plaintext is always smaller than the encrypted file, so its size is a safe estimate
+		check_free_space(state.steps.iter().map(|step| {
+			let path = match &state.init.output_directory {
+				Some(directory) => directory.clone(),
+				None => step.obj_path.clone(),
+			};
 
-		let mut obj_path = root_path.clone();
-		obj_path.push(obj_name.clone());
+			(path, step.size)
+		}))?;
 
-		state.steps = VecDeque::new();
-		state
-			.steps
-			.push_back(FileDecryptorJobStep { obj_name, obj_path });
+		state.data = Some(FileDecryptorJobState {
+			current_output: None,
+			blocks_written: 0,
+			current_source: None,
+			total_bytes: state.steps.iter().map(|step| step.size).sum(),
+			bytes_decrypted: 0,
+			report: FileDecryptorReport::default(),
+		});
 
 		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
 
@@ -89,84 +215,434 @@ impl StatefulJob for FileDecryptorJob {
 		ctx: WorkerContext,
 		state: &mut JobState<Self>,
 	) -> Result<(), JobError> {
-		let step = &state.steps[0];
-		// handle overwriting checks, and making sure there's enough available space
+		if let Err(e) = decrypt_file(&ctx, state).await {
+			let step = &state.steps[0];
+			let data = current_data(&mut state.data);
 
-		let output_path = if let Some(path) = state.init.output_path.clone() {
-			path
-		} else {
-			let mut path = step.obj_path.clone();
-
-			// i really can't decide on the functionality of this
-			// maybe we should open a dialog in JS, and have the default as the file name without the ".sdenc",
-			// we don't do any overwriting checks as of yet, maybe these should be front-end though
-			let extension = if let Some(ext) = path.extension() {
-				// allow this with clippy as this won't be the final behaviour
-				#[allow(clippy::if_same_then_else)]
-				if ext == ".sdenc" {
-					"decrypted"
-				} else {
-					"decrypted"
+			// don't leave a partially-written file behind
+			if let Some(output_path) = data.current_output.take() {
+				remove_partial_output(&output_path)?;
+			}
+			data.blocks_written = 0;
+
+			warn!("decryption failed for {}: {:#?}", step.obj_name, e);
+			ctx.log(
+				JobLogLevel::Warning,
+				format!("Decryption failed: {e}"),
+				Some(step.obj_name.clone()),
+			);
+			data.report.errors.push(FileFailure {
+				path: step.obj_name.clone(),
+				file_path_id: step.file_path_id,
+				error: e.to_string(),
+			});
+
+			ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+				state.step_number + 1,
+			)]);
+		}
+
+		Ok(())
+	}
+
+	async fn finalize(&self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		data.report.retry =
+			failed_file_paths(&data.report.errors).map(|only_file_paths| FileDecryptorJobInit {
+				password: None,
+				save_to_library: None,
+				only_file_paths: Some(only_file_paths),
+				..state.init.clone()
+			});
+
+		// the init isn't returned, as it may contain a password
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+
+	async fn cancel(
+		&self,
+		_ctx: &LibraryContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		if let Some(output_path) = state
+			.data
+			.as_mut()
+			.and_then(|data| data.current_output.take())
+		{
+			remove_partial_output(&output_path)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// This decrypts the file at the front of the job's steps, continuing from the last checkpoint if it was interrupted part of the way through.
+async fn decrypt_file(
+	ctx: &WorkerContext,
+	state: &mut JobState<FileDecryptorJob>,
+) -> Result<(), JobError> {
+	let step = &state.steps[0];
+	let data = state
+		.data
+		.as_mut()
+		.expect("critical error: missing data on job state");
+
+	// an encrypted file that's changed since its output was started may no longer follow on from what was written, so it's decrypted from the start again
+	if !data
+		.current_source
+		.map_or(false, |source| source.matches(&step.obj_path))
+	{
+		if let Some(output_path) = data.current_output.take() {
+			warn!(
+				"restarting decryption of {}, as the encrypted file has changed",
+				step.obj_name
+			);
+			ctx.log(
+				JobLogLevel::Info,
+				"Restarting decryption, as the encrypted file has changed",
+				Some(step.obj_name.clone()),
+			);
+			remove_partial_output(&output_path)?;
+		}
+	}
+
+	// a paused (or interrupted) job continues the file it was working on from its last checkpoint, as long as the partial output is still there
+	// it stays recorded until we're done with it, so it's still cleaned up if the job fails before then
+	let resume_path = data.current_output.clone().filter(|path| path.exists());
+	let resuming = resume_path.is_some();
+
+	if !resuming {
+		data.current_output = None;
+		data.blocks_written = 0;
+		data.current_source = Some(SourceStamp::of(&step.obj_path)?);
+	}
+
+	ctx.progress(vec![
+		// this is sent with every file, as a resumed job doesn't run `init` again
+		JobReportUpdate::TotalBytes(data.total_bytes),
+		JobReportUpdate::CurrentFile {
+			name: step.obj_name.clone(),
+			size: step.size,
+		},
+	]);
+
+	let mut output_path = match (&state.init.output_directory, step.obj_path.file_name()) {
+		(Some(directory), Some(file_name)) => directory.join(file_name),
+		_ => step.obj_path.clone(),
+	};
+
+	let mut reader = std::fs::File::open(step.obj_path.clone())?;
+	let (header, aad) = FileHeader::deserialize(&mut reader)?;
+
+	let (master_key, key_uuid) = if let Some(password) = state.init.password.clone() {
+		if let Some(save_to_library) = state.init.save_to_library {
+			let password = Protected::new(password.into_bytes());
+
+			// we can do this first, as `find_key_index` requires a successful decryption (just like `decrypt_master_key`)
+			// if we're resuming, the key was already saved before the job was interrupted
+			let key_uuid = if save_to_library && !resuming {
+				let index = header.find_key_index(password.clone())?;
+
+				// inherit the encryption algorithm from the keyslot
+				let uuid = ctx.library_ctx.key_manager.add_to_keystore(
+					password.clone(),
+					header.algorithm,
+					header.keyslots[index].hashing_algorithm,
+					false,
+					false,
+					Some(header.keyslots[index].salt),
+					KeySource::Typed,
+				)?;
+
+				let stored_key = ctx.library_ctx.key_manager.access_keystore(uuid)?;
+
+				if let Err(e) = write_storedkey_to_db(ctx.library_ctx.db.clone(), &stored_key).await
+				{
+					ctx.library_ctx.key_manager.remove_key(uuid)?;
+					return Err(e.into());
 				}
+
+				invalidate_query!(ctx.library_ctx, "keys.list");
+
+				Some(uuid)
 			} else {
-				"decrypted"
+				None
 			};
-			path.set_extension(extension);
-			path
-		};
-
-		let mut reader = std::fs::File::open(step.obj_path.clone())?;
-		let mut writer = std::fs::File::create(output_path)?;
-
-		let (header, aad) = FileHeader::deserialize(&mut reader)?;
-
-		let master_key = if let Some(password) = state.init.password.clone() {
-			if let Some(save_to_library) = state.init.save_to_library {
-				let password = Protected::new(password.into_bytes());
-
-				// we can do this first, as `find_key_index` requires a successful decryption (just like `decrypt_master_key`)
-				if save_to_library {
-					let index = header.find_key_index(password.clone())?;
-
-					// inherit the encryption algorithm from the keyslot
-					ctx.library_ctx.key_manager.add_to_keystore(
-						password.clone(),
-						header.algorithm,
-						header.keyslots[index].hashing_algorithm,
-						false,
-						false,
-						Some(header.keyslots[index].salt),
-					)?;
-				}
 
-				header.decrypt_master_key(password)?
-			} else {
-				return Err(JobError::JobDataNotFound(String::from(
-					"Password decryption selected, but save to library boolean was not included",
-				)));
-			}
+			(header.decrypt_master_key(password)?, key_uuid)
 		} else {
-			let keys = ctx.library_ctx.key_manager.enumerate_hashed_keys();
+			return Err(JobError::JobDataNotFound(String::from(
+				"Password decryption selected, but save to library boolean was not included",
+			)));
+		}
+	} else {
+		// try each mounted key against the keyslots, so we know which key the file belongs to
+		ctx.library_ctx
+			.key_manager
+			.get_mounted_uuids()
+			.into_iter()
+			.find_map(|uuid| {
+				let hashed_key = ctx
+					.library_ctx
+					.key_manager
+					.access_keymount(uuid)
+					.ok()?
+					.hashed_key;
 
-			header.decrypt_master_key_from_prehashed(keys)?
-		};
+				header
+					.decrypt_master_key_from_prehashed(vec![hashed_key])
+					.ok()
+					.map(|master_key| (master_key, Some(uuid)))
+			})
+			.ok_or(sd_crypto::Error::IncorrectPassword)?
+	};
 
-		let decryptor = StreamDecryption::new(master_key, &header.nonce, header.algorithm)?;
+	// the metadata holds the original's attributes, and its name if the output was given a random one
+	let metadata = if header.metadata.is_some() {
+		header
+			.decrypt_metadata_with_master_key::<Metadata>(master_key.clone())
+			.map_err(|e| {
+				warn!(
+					"decryption is skipping the metadata for {}: {:#?}",
+					step.obj_name, e
+				);
+				ctx.log(
+					JobLogLevel::Warning,
+					format!("Skipped the file's metadata: {e}"),
+					Some(step.obj_name.clone()),
+				);
+			})
+			.ok()
+	} else {
+		None
+	};
 
-		decryptor.decrypt_streams(&mut reader, &mut writer, &aad)?;
+	// a name from the metadata can't be trusted to stay within the directory, so only its final component is used
+	let original_name = metadata
+		.as_ref()
+		.and_then(|metadata| metadata.file_name.as_deref())
+		.and_then(|name| Path::new(name).file_name());
 
-		// need to decrypt preview media/metadata, and maybe add an option in the UI so the user can chosoe to restore these values
-		// for now this can't easily be implemented, as we don't know what the new object id for the file will be (we know the old one, but it may differ)
+	// `file.txt.sdenc` becomes `file.txt`, anything else just gets a `.decrypted` extension
+	if let Some(name) = original_name {
+		output_path.set_file_name(name);
+	} else if output_path.extension().map_or(false, |ext| ext == "sdenc") {
+		output_path.set_extension("");
 
-		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
-			state.step_number + 1,
-		)]);
+		// files within vaults have encrypted names
+		if let Some(name) = key_uuid.and_then(|uuid| {
+			let hashed_key = ctx
+				.library_ctx
+				.key_manager
+				.access_keymount(uuid)
+				.ok()?
+				.hashed_key;
 
-		Ok(())
+			decrypt_filename(hashed_key, output_path.file_name()?.to_str()?).ok()
+		}) {
+			output_path.set_file_name(name);
+		}
+	} else {
+		output_path.set_extension("decrypted");
 	}
 
-	async fn finalize(&self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
-		// mark job as successful
-		Ok(Some(serde_json::to_value(&state.init)?))
+	if let Some(path) = resume_path {
+		output_path = path;
+	}
+
+	// if the key is unmounted while we're decrypting, the output is removed
+	let consumer = key_uuid.map(|uuid| ctx.library_ctx.key_consumers.register(uuid));
+
+	// this is checked before the output is recorded, as an existing file mustn't be cleaned up as if it were ours
+	let mut overwrite = false;
+	if !resuming {
+		let (resolved, decision) = resolve_conflict(output_path.clone(), state.init.on_conflict)?;
+
+		if let Some(decision) = decision {
+			data.report.conflicts.push(OutputConflict {
+				path: step.obj_name.clone(),
+				output_path: output_path.clone(),
+				decision,
+			});
+		}
+
+		match resolved {
+			Some(resolved) => {
+				output_path = resolved.path;
+				overwrite = resolved.overwrite;
+			}
+			None => {
+				// the existing output is left alone
+				ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+					state.step_number + 1,
+				)]);
+
+				return Ok(());
+			}
+		}
+	}
+
+	// the output is recorded before it's created, so it can be cleaned up if the job is interrupted
+	data.current_output = Some(output_path.clone());
+	ctx.checkpoint(state).await?;
+
+	if !resuming {
+		if let Err(e) = OpenOptions::new()
+			.write(true)
+			.create(true)
+			.truncate(true)
+			.create_new(!overwrite)
+			.open(&output_path)
+		{
+			current_data(&mut state.data).current_output = None;
+			return Err(e.into());
+		}
+	}
+
+	let source = state.steps[0].obj_path.clone();
+	let size = state.steps[0].size;
+
+	loop {
+		let data = current_data(&mut state.data);
+
+		match decrypt_contents(
+			master_key.clone(),
+			&header,
+			&aad,
+			&source,
+			&output_path,
+			&mut data.blocks_written,
+			ctx.library_ctx.io_throttle(),
+		)
+		.await
+		{
+			Ok(true) => break,
+			Ok(false) => {
+				let file_bytes = (u64::from(data.blocks_written)
+					* (BLOCK_SIZE + AEAD_TAG_SIZE) as u64)
+					.min(size);
+
+				ctx.progress_debounced(vec![JobReportUpdate::BytesProcessed {
+					total: data.bytes_decrypted + file_bytes,
+					file: file_bytes,
+				}]);
+
+				ctx.checkpoint(state).await?;
+			}
+			Err(e) => {
+				std::fs::remove_file(&output_path)?;
+				data.current_output = None;
+				return Err(e);
+			}
+		}
+	}
+
+	let step = &state.steps[0];
+	let data = current_data(&mut state.data);
+	data.current_output = None;
+	data.blocks_written = 0;
+	data.current_source = None;
+	data.bytes_decrypted += step.size;
+
+	let bytes_decrypted = data.bytes_decrypted;
+
+	if let Some(consumer) = consumer.filter(KeyConsumer::is_revoked) {
+		std::fs::remove_file(&output_path)?;
+		return Err(JobError::KeyRevoked(consumer.key_uuid()));
 	}
+
+	// this happens once the output is complete, as writing to it would change its modification time
+	if let Some(attributes) = metadata.and_then(|metadata| metadata.attributes) {
+		attributes.restore(&output_path);
+	}
+
+	// the encrypted file is associated with the key that decrypted it, so its header can be decrypted when the key is mounted
+	if let Some(key_uuid) =
+		key_uuid.filter(|_| step.object_id.is_some() || step.file_path_id.is_some())
+	{
+		let file_path = step
+			.file_path_id
+			.map(|file_path_id| (state.init.location_id, file_path_id));
+
+		// the output is removed if this fails, so the file is retried as a whole rather than being half-recorded
+		if let Err(e) = record_key_association(ctx, key_uuid, step.object_id, file_path).await {
+			std::fs::remove_file(&output_path)?;
+			return Err(e);
+		}
+	}
+
+	// need to decrypt preview media/metadata, and maybe add an option in the UI so the user can chosoe to restore these values
+	// for now this can't easily be implemented, as we don't know what the new object id for the file will be (we know the old one, but it may differ)
+
+	ctx.progress(vec![
+		JobReportUpdate::CompletedTaskCount(state.step_number + 1),
+		JobReportUpdate::BytesProcessed {
+			total: bytes_decrypted,
+			file: step.size,
+		},
+	]);
+
+	Ok(())
+}
+
+fn current_data(data: &mut Option<FileDecryptorJobState>) -> &mut FileDecryptorJobState {
+	data.as_mut()
+		.expect("critical error: missing data on job state")
+}
+
+/// This decrypts up to `CHECKPOINT_BLOCKS` blocks of a file, continuing from the last block that was written, and returns whether it's finished.
+///
+/// The plaintext is synced to the disk before returning, so it's safe to checkpoint. This means huge files (e.g. on slow remote storage)
+/// don't need to be decrypted from the start again if the job is interrupted.
+///
+/// Blocks of zeros are skipped rather than written, so sparse files (e.g. VM disk images) stay sparse.
+async fn decrypt_contents(
+	master_key: Protected<[u8; KEY_LEN]>,
+	header: &FileHeader,
+	aad: &[u8],
+	source: &Path,
+	output_path: &Path,
+	blocks_written: &mut u32,
+	throttle: &IoThrottle,
+) -> Result<bool, JobError> {
+	let mut reader = File::open(source)?;
+	FileHeader::deserialize(&mut reader)?;
+
+	let blocks = u64::from(*blocks_written);
+	let offset = reader.stream_position()? + blocks * (BLOCK_SIZE + AEAD_TAG_SIZE) as u64;
+	reader.seek(SeekFrom::Start(offset))?;
+
+	// anything after the last complete block is discarded (and a trailing hole is filled in)
+	let mut writer = OpenOptions::new().write(true).open(output_path)?;
+	let plaintext_offset = blocks * BLOCK_SIZE as u64;
+	writer.set_len(plaintext_offset)?;
+	writer.seek(SeekFrom::Start(plaintext_offset))?;
+
+	let mut decryptor = StreamDecryption::new(master_key, &header.nonce, header.algorithm)?;
+	decryptor.seek_to_block(*blocks_written);
+
+	let mut sparse_writer = SparseWriter::new(&mut writer);
+
+	for _ in 0..CHECKPOINT_BLOCKS {
+		if decryptor.decrypt_block(&mut reader, &mut sparse_writer, aad)? {
+			sparse_writer.finish()?;
+			writer.sync_all()?;
+			return Ok(true);
+		}
+
+		*blocks_written += 1;
+
+		throttle.read((BLOCK_SIZE + AEAD_TAG_SIZE) as u64).await;
+		throttle.write(BLOCK_SIZE as u64).await;
+		tokio::task::yield_now().await;
+	}
+
+	sparse_writer.finish()?;
+	writer.sync_data()?;
+
+	Ok(false)
 }
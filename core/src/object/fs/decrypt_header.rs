@@ -0,0 +1,159 @@
+use std::{collections::VecDeque, path::PathBuf};
+
+use sd_crypto::header::file::FileHeader;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+	job::{
+		JobError, JobLogLevel, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+	},
+	library::set_note,
+	object::preview::write_encrypted_thumbnail,
+	prisma::{file_path, key, object},
+};
+
+use super::encrypt::Metadata;
+
+pub const HEADER_DECRYPTOR_JOB_NAME: &str = "file_header_decryptor";
+
+// The header decryptor is dispatched when a key is mounted, and it decrypts the metadata and preview media
+// stored within the headers of every file that's associated with that key.
+// Preview media is re-encrypted for the thumbnail cache, so it never touches the disk in plaintext.
+pub struct FileHeaderDecryptorJob;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileHeaderDecryptorJobState {}
+
+#[derive(Serialize, Deserialize, Debug, Type, Hash)]
+pub struct FileHeaderDecryptorJobInit {
+	pub key_uuid: Uuid,
+	pub metadata: bool,
+	pub preview_media: bool,
+}
+
+file_path::select!(file_path_for_header_decryptor {
+	materialized_path
+	location: select {
+		local_path
+	}
+	object: select {
+		id
+		cas_id
+	}
+});
+
+#[async_trait::async_trait]
+impl StatefulJob for FileHeaderDecryptorJob {
+	type Data = FileHeaderDecryptorJobState;
+	type Init = FileHeaderDecryptorJobInit;
+	type Step = file_path_for_header_decryptor::Data;
+
+	fn name(&self) -> &'static str {
+		HEADER_DECRYPTOR_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		state.steps = ctx
+			.library_ctx
+			.db
+			.file_path()
+			.find_many(vec![
+				file_path::is_dir::equals(false),
+				file_path::object::is(vec![object::key::is(vec![key::uuid::equals(
+					state.init.key_uuid.to_string(),
+				)])]),
+			])
+			.select(file_path_for_header_decryptor::select())
+			.exec()
+			.await?
+			.into_iter()
+			.collect::<VecDeque<_>>();
+
+		state.data = Some(FileHeaderDecryptorJobState {});
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+
+		let (root_path, object) = match (&step.location.local_path, &step.object) {
+			(Some(root_path), Some(object)) => (PathBuf::from(root_path), object),
+			_ => {
+				warn!(
+					"header decryption is skipping {} as it isn't available locally",
+					step.materialized_path
+				);
+				ctx.log(
+					JobLogLevel::Warning,
+					"Skipped, as it isn't available locally",
+					Some(step.materialized_path.clone()),
+				);
+				return Ok(());
+			}
+		};
+
+		let key_uuid = state.init.key_uuid;
+
+		let hashed_key = ctx
+			.library_ctx
+			.key_manager
+			.access_keymount(key_uuid)?
+			.hashed_key;
+
+		let mut reader = std::fs::File::open(root_path.join(&step.materialized_path))?;
+		let (header, _) = FileHeader::deserialize(&mut reader)?;
+
+		if state.init.metadata && header.metadata.is_some() {
+			let metadata: Metadata =
+				header.decrypt_metadata_from_prehashed(vec![hashed_key.clone()])?;
+
+			ctx.library_ctx
+				.db
+				.object()
+				.update(
+					object::id::equals(object.id),
+					vec![
+						object::hidden::set(metadata.hidden),
+						object::favorite::set(metadata.favourite),
+						object::important::set(metadata.important),
+						object::date_created::set(metadata.date_created),
+						object::date_modified::set(metadata.date_modified),
+					],
+				)
+				.exec()
+				.await?;
+
+			// the note is written separately, as it may need to be encrypted within the database
+			set_note(&ctx.library_ctx, object.id, metadata.note).await?;
+		}
+
+		// the preview media is kept encrypted within the thumbnail cache, and it's only decrypted on demand
+		if state.init.preview_media && header.preview_media.is_some() {
+			let media = header.decrypt_preview_media_from_prehashed(vec![hashed_key])?;
+
+			write_encrypted_thumbnail(&ctx.library_ctx, key_uuid, &object.cas_id, media.expose())
+				.await?;
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		// mark job as successful
+		Ok(Some(serde_json::to_value(&state.init)?))
+	}
+}
@@ -0,0 +1,236 @@
+use std::{
+	collections::VecDeque,
+	io,
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::fs;
+use tracing::{info, warn};
+
+use crate::{
+	invalidate_query,
+	job::{
+		JobError, JobLogLevel, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+	},
+	location::{delete_directory, file_path_helper::delete_orphaned_objects},
+	prisma::{file_path, location, object, trashed_item},
+};
+
+use super::{is_location_root, resolve_targets, trash::move_to_trash, FileFailure, ObjectTarget};
+
+pub const FILE_DELETER_JOB_NAME: &str = "file_deleter";
+
+// The deleter moves files and directories (and everything within them) to the trash, or deletes them permanently, along with their file paths.
+// Objects that no longer have any file paths are deleted as well, in the same way as when the location watcher sees a file being deleted,
+// except for the objects of trashed items, which are kept until they're restored or the trash is emptied.
+pub struct FileDeleterJob;
+
+#[derive(Serialize, Deserialize, Debug, Type, Hash, Clone)]
+pub struct FileDeleterJobInit {
+	pub location_id: i32,
+	pub targets: Vec<ObjectTarget>,
+	#[serde(default)]
+	pub permanent: bool, // if this isn't set, the targets are moved to the trash
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileDeleterJobState {
+	root_path: PathBuf,
+	report: FileDeleterReport,
+}
+
+/// This is the summary of a deleter job, which is returned once it's finished.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FileDeleterReport {
+	deleted: usize,
+	failures: Vec<FileFailure>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileDeleterJobStep {
+	file_path_id: i32,
+	materialized_path: String,
+	object_id: Option<i32>,
+	is_dir: bool,
+	size_in_bytes: String,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for FileDeleterJob {
+	type Data = FileDeleterJobState;
+	type Init = FileDeleterJobInit;
+	type Step = FileDeleterJobStep;
+
+	fn name(&self) -> &'static str {
+		FILE_DELETER_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let (root_path, items) = resolve_targets(
+			&ctx.library_ctx.db,
+			state.init.location_id,
+			&state.init.targets,
+		)
+		.await?;
+
+		state.steps = items
+			.into_iter()
+			.filter(|item| !is_location_root(item))
+			.map(|item| FileDeleterJobStep {
+				file_path_id: item.id,
+				materialized_path: item.materialized_path,
+				object_id: item.object_id,
+				is_dir: item.is_dir,
+				size_in_bytes: item.size_in_bytes.unwrap_or_else(|| "0".to_string()),
+			})
+			.collect::<VecDeque<_>>();
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		state.data = Some(FileDeleterJobState {
+			root_path,
+			report: FileDeleterReport::default(),
+		});
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"{} {}",
+			if state.init.permanent {
+				"Deleting"
+			} else {
+				"Trashing"
+			},
+			step.materialized_path
+		))]);
+
+		match delete_entry(&ctx, &state.init, &data.root_path, step).await {
+			Ok(()) => data.report.deleted += 1,
+			Err(e) => {
+				warn!("deleting failed for {}: {:#?}", step.materialized_path, e);
+				ctx.log(
+					JobLogLevel::Warning,
+					format!("Deleting failed: {e}"),
+					Some(step.materialized_path.clone()),
+				);
+				data.report.failures.push(FileFailure {
+					path: step.materialized_path.clone(),
+					file_path_id: Some(step.file_path_id),
+					error: e.to_string(),
+				});
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!("Finalizing deleter job: {:#?}", data.report);
+
+		invalidate_query!(ctx.library_ctx, "locations.getExplorerData");
+		invalidate_query!(ctx.library_ctx, "savedSearches.getExplorerData");
+		if !state.init.permanent {
+			invalidate_query!(ctx.library_ctx, "files.listTrash");
+		}
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
+
+/// This deletes a single file or directory, and then its file paths.
+///
+/// The database is only updated once the target is gone from its location, so it always matches the disk.
+async fn delete_entry(
+	ctx: &WorkerContext,
+	init: &FileDeleterJobInit,
+	root_path: &Path,
+	step: &FileDeleterJobStep,
+) -> Result<(), JobError> {
+	let path = root_path.join(step.materialized_path.trim_end_matches('/'));
+
+	match fs::symlink_metadata(&path).await {
+		Ok(_) if init.permanent => {
+			if step.is_dir {
+				fs::remove_dir_all(&path).await?;
+			} else {
+				fs::remove_file(&path).await?;
+			}
+		}
+		Ok(_) => {
+			move_to_trash(&path)?;
+
+			let mut params = vec![
+				trashed_item::is_dir::set(step.is_dir),
+				trashed_item::size_in_bytes::set(step.size_in_bytes.clone()),
+			];
+			if let Some(object_id) = step.object_id {
+				params.push(trashed_item::object::connect(object::id::equals(object_id)));
+			}
+
+			ctx.library_ctx
+				.db
+				.trashed_item()
+				.create(
+					step.materialized_path.clone(),
+					location::id::equals(init.location_id),
+					params,
+				)
+				.exec()
+				.await?;
+		}
+		// a file that's already gone is deleted from the database all the same, so it matches the disk
+		Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+		Err(e) => return Err(e.into()),
+	}
+
+	delete_file_path(ctx, init.location_id, step).await
+}
+
+async fn delete_file_path(
+	ctx: &WorkerContext,
+	location_id: i32,
+	step: &FileDeleterJobStep,
+) -> Result<(), JobError> {
+	if step.is_dir {
+		delete_directory(
+			&ctx.library_ctx,
+			location_id,
+			Some(step.materialized_path.clone()),
+		)
+		.await?;
+	} else {
+		ctx.library_ctx
+			.db
+			.file_path()
+			.delete(file_path::location_id_id(location_id, step.file_path_id))
+			.exec()
+			.await?;
+
+		delete_orphaned_objects(&ctx.library_ctx, step.object_id.into_iter().collect()).await?;
+	}
+
+	Ok(())
+}
@@ -0,0 +1,491 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	fs::{self, File},
+	io::{self, Read},
+	path::{Path, PathBuf},
+};
+
+use prisma_client_rust::QueryError;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::{info, warn};
+
+use crate::{
+	invalidate_query,
+	job::{
+		JobError, JobLogLevel, JobPriority, JobReportUpdate, JobResult, JobState, StatefulJob,
+		WorkerContext,
+	},
+	prisma::{file_path, location, object, PrismaClient},
+};
+
+use super::{resolve_conflict, ConflictStrategy, FileFailure};
+
+pub const DUPLICATE_RESOLVER_JOB_NAME: &str = "duplicate_resolver";
+
+/// The size of the chunks that duplicates are compared in, before anything is done to them.
+const COMPARE_CHUNK_SIZE: usize = 1024 * 1024;
+
+file_path::select!(file_path_for_duplicates {
+	id
+	location_id
+	materialized_path
+	object: select { id content_hash size_in_bytes }
+});
+
+/// These are files with the same contents, which may be spread across locations.
+#[derive(Serialize, Debug, Type)]
+pub struct DuplicateGroup {
+	pub content_hash: String,
+	pub size_in_bytes: u64,
+	/// this is the space that'd be freed by only keeping one of the files
+	pub wasted_bytes: u64,
+	pub files: Vec<DuplicateFile>,
+}
+
+#[derive(Serialize, Debug, Type)]
+pub struct DuplicateFile {
+	pub location_id: i32,
+	pub file_path_id: i32,
+	pub object_id: i32,
+	pub path: String,
+}
+
+#[derive(Serialize, Debug, Type, Default)]
+pub struct DuplicatesReport {
+	/// these are sorted by how much space they waste, so the largest are first
+	pub groups: Vec<DuplicateGroup>,
+	pub total_wasted_bytes: u64,
+}
+
+/// This groups every file within the given locations (or the whole library) by its content hash, and returns the groups with more than one file.
+///
+/// Files are matched by the content hash rather than their object, so files that ended up with different objects (e.g. as their `cas_id`s collided) are still found.
+pub async fn find_duplicates(
+	db: &PrismaClient,
+	location_ids: Option<Vec<i32>>,
+) -> Result<DuplicatesReport, QueryError> {
+	let mut params = vec![
+		file_path::is_dir::equals(false),
+		file_path::object::is(vec![object::content_hash::not(None)]),
+	];
+	params.extend(location_ids.map(file_path::location_id::in_vec));
+
+	let mut files_by_hash = HashMap::<String, (u64, Vec<DuplicateFile>)>::new();
+
+	for file_path in db
+		.file_path()
+		.find_many(params)
+		.select(file_path_for_duplicates::select())
+		.exec()
+		.await?
+	{
+		let object = match file_path.object {
+			Some(object) => object,
+			None => continue,
+		};
+
+		let content_hash = match object.content_hash {
+			Some(content_hash) => content_hash,
+			None => continue,
+		};
+
+		files_by_hash
+			.entry(content_hash)
+			.or_insert_with(|| (object.size_in_bytes.parse().unwrap_or_default(), Vec::new()))
+			.1
+			.push(DuplicateFile {
+				location_id: file_path.location_id,
+				file_path_id: file_path.id,
+				object_id: object.id,
+				path: file_path.materialized_path,
+			});
+	}
+
+	let mut groups = files_by_hash
+		.into_iter()
+		.filter(|(_, (_, files))| files.len() > 1)
+		.map(|(content_hash, (size_in_bytes, files))| DuplicateGroup {
+			content_hash,
+			size_in_bytes,
+			wasted_bytes: size_in_bytes * (files.len() as u64 - 1),
+			files,
+		})
+		.collect::<Vec<_>>();
+
+	groups.sort_by(|a, b| b.wasted_bytes.cmp(&a.wasted_bytes));
+
+	Ok(DuplicatesReport {
+		total_wasted_bytes: groups.iter().map(|group| group.wasted_bytes).sum(),
+		groups,
+	})
+}
+
+/// This is what's done with each duplicate, once it's been checked against the file that's being kept.
+#[derive(Serialize, Deserialize, Debug, Type, Hash, Clone)]
+pub enum DuplicateAction {
+	Delete,
+	/// each duplicate is replaced by a hard link to the kept file, or a symbolic link if a hard link can't be made (e.g. as they're on different volumes)
+	ReplaceWithLink,
+	/// the duplicates are moved into this directory, so they can be reviewed before they're deleted
+	MoveToReview(PathBuf),
+}
+
+#[derive(Serialize, Deserialize, Debug, Type, Hash, Clone, PartialEq, Eq)]
+pub struct DuplicateFileRef {
+	pub location_id: i32,
+	pub file_path_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Type, Hash)]
+pub struct DuplicateResolution {
+	/// this file is left as it is
+	pub keep: DuplicateFileRef,
+	pub duplicates: Vec<DuplicateFileRef>,
+}
+
+// The resolver keeps one file from each group of duplicates, and deletes, links or moves the rest.
+// Every duplicate is compared with the kept file byte by byte first, so nothing is lost if either of them has changed since they were identified.
+pub struct DuplicateResolverJob;
+
+#[derive(Serialize, Deserialize, Debug, Type, Hash)]
+pub struct DuplicateResolverJobInit {
+	pub resolutions: Vec<DuplicateResolution>,
+	pub action: DuplicateAction,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DuplicateResolverJobState {
+	report: DuplicateResolverReport,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DuplicateResolverJobStep {
+	location_id: i32,
+	file_path_id: i32,
+	obj_name: String,
+	obj_path: PathBuf,
+	keep_path: PathBuf,
+}
+
+/// This is the summary of a resolver job, which is returned once it's finished.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DuplicateResolverReport {
+	total_files: usize,
+	files_resolved: usize,
+	/// the space that was freed by deleting (or linking) duplicates, which doesn't include duplicates that were moved
+	freed_bytes: u64,
+	failures: Vec<FileFailure>,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for DuplicateResolverJob {
+	type Data = DuplicateResolverJobState;
+	type Init = DuplicateResolverJobInit;
+	type Step = DuplicateResolverJobStep;
+
+	fn name(&self) -> &'static str {
+		DUPLICATE_RESOLVER_JOB_NAME
+	}
+
+	fn priority(&self) -> JobPriority {
+		JobPriority::Low
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let db = &ctx.library_ctx.db;
+
+		if let DuplicateAction::MoveToReview(review_dir) = &state.init.action {
+			fs::create_dir_all(review_dir)?;
+		}
+
+		state.steps = VecDeque::new();
+
+		for resolution in &state.init.resolutions {
+			let keep_path = match local_path(db, &resolution.keep).await? {
+				Some((keep_path, _)) => keep_path,
+				None => {
+					warn!(
+						"duplicate resolver is skipping {:?}, as the file to keep can't be found",
+						resolution.keep
+					);
+					ctx.log(
+						JobLogLevel::Warning,
+						format!(
+							"Skipped the duplicates of file path {}, as it can't be found",
+							resolution.keep.file_path_id
+						),
+						None,
+					);
+					continue;
+				}
+			};
+
+			for duplicate in &resolution.duplicates {
+				if *duplicate == resolution.keep {
+					continue;
+				}
+
+				match local_path(db, duplicate).await? {
+					Some((obj_path, obj_name)) => state.steps.push_back(DuplicateResolverJobStep {
+						location_id: duplicate.location_id,
+						file_path_id: duplicate.file_path_id,
+						obj_name,
+						obj_path,
+						keep_path: keep_path.clone(),
+					}),
+					None => warn!("skipping {:?} as it can't be found", duplicate),
+				}
+			}
+		}
+
+		state.data = Some(DuplicateResolverJobState {
+			report: DuplicateResolverReport {
+				total_files: state.steps.len(),
+				..Default::default()
+			},
+		});
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Resolving {}",
+			step.obj_name
+		))]);
+
+		let result = resolve_duplicate(&ctx, step, &state.init.action).await;
+
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		match result {
+			Ok(freed) => {
+				data.report.files_resolved += 1;
+				data.report.freed_bytes += freed;
+			}
+			Err(e) => {
+				warn!("duplicate resolver failed for {}: {:#?}", step.obj_name, e);
+				ctx.log(
+					JobLogLevel::Warning,
+					format!("Left as it is: {e}"),
+					Some(step.obj_name.clone()),
+				);
+				data.report.failures.push(FileFailure {
+					path: step.obj_name.clone(),
+					file_path_id: Some(step.file_path_id),
+					error: e.to_string(),
+				});
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!("Finalizing duplicate resolver job: {:#?}", data.report);
+
+		invalidate_query!(ctx.library_ctx, "locations.getExplorerData");
+		invalidate_query!(ctx.library_ctx, "savedSearches.getExplorerData");
+		invalidate_query!(ctx.library_ctx, "files.getDuplicates");
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
+
+/// This finds where a file path is on disk, alongside its materialized path.
+async fn local_path(
+	db: &PrismaClient,
+	file: &DuplicateFileRef,
+) -> Result<Option<(PathBuf, String)>, JobError> {
+	let location = match db
+		.location()
+		.find_unique(location::id::equals(file.location_id))
+		.exec()
+		.await?
+	{
+		Some(location) => location,
+		None => return Ok(None),
+	};
+
+	let root_path = match location.local_path {
+		Some(local_path) => PathBuf::from(local_path),
+		None => return Ok(None),
+	};
+
+	Ok(db
+		.file_path()
+		.find_unique(file_path::location_id_id(
+			file.location_id,
+			file.file_path_id,
+		))
+		.exec()
+		.await?
+		.filter(|file_path| !file_path.is_dir)
+		.map(|file_path| {
+			(
+				root_path.join(&file_path.materialized_path),
+				file_path.materialized_path,
+			)
+		}))
+}
+
+/// This deletes, links or moves a duplicate once it's been compared with the kept file, and returns how much space was freed.
+async fn resolve_duplicate(
+	ctx: &WorkerContext,
+	step: &DuplicateResolverJobStep,
+	action: &DuplicateAction,
+) -> Result<u64, JobError> {
+	let size = fs::metadata(&step.obj_path)?.len();
+
+	let (obj_path, keep_path) = (step.obj_path.clone(), step.keep_path.clone());
+	if !tokio::task::spawn_blocking(move || files_match(&obj_path, &keep_path)).await?? {
+		return Err(JobError::ContentChanged(step.obj_path.clone()));
+	}
+
+	let db = &ctx.library_ctx.db;
+	let file_path_id = file_path::location_id_id(step.location_id, step.file_path_id);
+
+	match action {
+		DuplicateAction::Delete => {
+			fs::remove_file(&step.obj_path)?;
+			db.file_path().delete(file_path_id).exec().await?;
+
+			Ok(size)
+		}
+		DuplicateAction::ReplaceWithLink => {
+			let is_symlink = replace_with_link(&step.obj_path, &step.keep_path)?;
+
+			// symbolic links aren't identified, so it's no longer linked to its object
+			if is_symlink {
+				db.file_path()
+					.update(
+						file_path_id,
+						vec![
+							file_path::is_symlink::set(true),
+							file_path::object_id::set(None),
+						],
+					)
+					.exec()
+					.await?;
+			}
+
+			Ok(size)
+		}
+		DuplicateAction::MoveToReview(review_dir) => {
+			let file_name = step
+				.obj_path
+				.file_name()
+				.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+			// the review directory may already have a file with the same name, from another duplicate
+			let (output, _) =
+				resolve_conflict(review_dir.join(file_name), ConflictStrategy::Rename)?;
+			let output = output.expect("critical error: renamed outputs are never skipped");
+
+			// renaming only works within a volume, so it's copied otherwise
+			if fs::rename(&step.obj_path, &output.path).is_err() {
+				fs::copy(&step.obj_path, &output.path)?;
+				fs::remove_file(&step.obj_path)?;
+			}
+
+			db.file_path().delete(file_path_id).exec().await?;
+
+			Ok(0)
+		}
+	}
+}
+
+/// This compares two files byte by byte.
+fn files_match(a: &Path, b: &Path) -> Result<bool, io::Error> {
+	let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+
+	if a.metadata()?.len() != b.metadata()?.len() {
+		return Ok(false);
+	}
+
+	let mut buf_a = vec![0u8; COMPARE_CHUNK_SIZE];
+	let mut buf_b = vec![0u8; COMPARE_CHUNK_SIZE];
+
+	loop {
+		let read = read_chunk(&mut a, &mut buf_a)?;
+
+		if read != read_chunk(&mut b, &mut buf_b)? || buf_a[..read] != buf_b[..read] {
+			return Ok(false);
+		}
+
+		if read == 0 {
+			return Ok(true);
+		}
+	}
+}
+
+/// This fills as much of the buffer as it can, as a single read may return less than was asked for.
+fn read_chunk(file: &mut File, buf: &mut [u8]) -> Result<usize, io::Error> {
+	let mut filled = 0;
+
+	while filled < buf.len() {
+		match file.read(&mut buf[filled..])? {
+			0 => break,
+			read => filled += read,
+		}
+	}
+
+	Ok(filled)
+}
+
+/// This replaces a file with a link to another one, and returns whether it had to be a symbolic link.
+///
+/// The link is created alongside the file first and then renamed over it, so the file is never missing.
+fn replace_with_link(path: &Path, target: &Path) -> Result<bool, io::Error> {
+	let file_name = path
+		.file_name()
+		.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+	let temp_path = path.with_file_name(format!(".{}.sdlink", file_name.to_string_lossy()));
+
+	let is_symlink = match fs::hard_link(target, &temp_path) {
+		Ok(()) => false,
+		Err(e) => {
+			warn!(
+				"Unable to hard link {} to {}, so a symbolic link is used: {:#?}",
+				path.display(),
+				target.display(),
+				e
+			);
+
+			#[cfg(unix)]
+			std::os::unix::fs::symlink(target, &temp_path)?;
+			#[cfg(windows)]
+			std::os::windows::fs::symlink_file(target, &temp_path)?;
+
+			true
+		}
+	};
+
+	if let Err(e) = fs::rename(&temp_path, path) {
+		fs::remove_file(&temp_path).ok();
+		return Err(e);
+	}
+
+	Ok(is_symlink)
+}
@@ -1,47 +1,206 @@
-use std::{collections::VecDeque, path::PathBuf};
+use std::{
+	collections::{HashSet, VecDeque},
+	fs::{File, OpenOptions},
+	io::{self, Cursor, Seek, SeekFrom, Write},
+	num::NonZeroUsize,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
 
 use chrono::FixedOffset;
 use sd_crypto::{
-	crypto::stream::{Algorithm, StreamEncryption},
+	crypto::stream::{Algorithm, StreamDecryption, StreamEncryption},
+	fs::{
+		erase::{erase, DEFAULT_ERASE_PASSES},
+		filename::encrypt_filename,
+	},
 	header::{file::FileHeader, keyslot::Keyslot},
-	primitives::{generate_master_key, LATEST_FILE_HEADER, LATEST_KEYSLOT, LATEST_METADATA},
+	primitives::{
+		generate_master_key, AEAD_TAG_SIZE, BLOCK_SIZE, KEY_LEN, LATEST_FILE_HEADER,
+		LATEST_KEYSLOT, LATEST_METADATA, LATEST_PREVIEW_MEDIA,
+	},
+	Protected,
 };
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tracing::warn;
+use tokio::{
+	io::{AsyncWrite, AsyncWriteExt},
+	sync::mpsc,
+};
+use tracing::{info, warn};
+use uuid::Uuid;
 
 use crate::{
-	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
-	prisma::{file_path, location, object},
+	invalidate_query,
+	job::{
+		IoThrottle, JobError, JobLogLevel, JobPriority, JobReportUpdate, JobResult, JobState,
+		StatefulJob, ThrottledUpload, WorkerContext,
+	},
+	library::{decrypt_note, LibraryContext},
+	location::remote::{connect, join_remote_path, record_upload, RemoteUpload},
+	object::preview::{read_thumbnail, EncryptedThumbnailError},
+	prisma::{file_path, object},
+};
+
+use super::{
+	attributes::FileAttributes,
+	check_free_space, encrypted_size, failed_file_paths, is_selected,
+	pipeline::WorkerPool,
+	policy::{record_rule_activity, EncryptionRuleOutcome, RuleActivity},
+	remove_partial_output, resolve_conflict, resolve_targets, ConflictStrategy, FileFailure,
+	ObjectTarget, OutputConflict, SourceStamp,
 };
 
 pub struct FileEncryptorJob;
 
 #[derive(Serialize, Deserialize, Debug)]
-enum ObjectType {
-	File,
-	Directory,
+pub struct FileEncryptorJobState {
+	report: FileEncryptorReport,
+	#[serde(default)]
+	current: Option<FileEncryptorProgress>,
+	// the outputs of the batch that's being encrypted by the pipeline, which is redone from scratch if the job is interrupted part of the way through it
+	#[serde(default)]
+	batch: Vec<PathBuf>,
+	#[serde(default)]
+	upload: Option<FileUploadProgress>,
+	// the files that were encrypted, which are only kept for jobs that were dispatched by an encryption rule
+	#[serde(default)]
+	encrypted: Vec<String>,
+}
+
+/// This tracks the file that's currently being encrypted, so the job can be paused (and resumed) part of the way through it.
+///
+/// It's checkpointed before the output is created and every `CHECKPOINT_BLOCKS` blocks, so an interrupted job knows what it left behind.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileEncryptorProgress {
+	output_path: PathBuf,
+	blocks_written: u32,
+	// the original's size and modification time when its output was started, as an original that's changed since can't be continued
+	#[serde(default)]
+	source: Option<SourceStamp>,
+	// whether the output has been verified, which is only done before the original is erased
+	#[serde(default)]
+	complete: bool,
+}
+
+/// This tracks the file that's currently being uploaded, so an interrupted upload can be continued with the same header (and so the same master key).
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileUploadProgress {
+	remote_path: String,
+	header: Vec<u8>,
+	// the original's size and modification time when the upload was started, as an original that's changed since can't be continued
+	#[serde(default)]
+	source: Option<SourceStamp>,
+}
+
+/// The number of blocks that are encrypted between checkpoints.
+const CHECKPOINT_BLOCKS: u32 = 64;
+
+/// The space that's set aside for each file's header when checking there's enough space, which is plenty for its metadata and preview media.
+const ESTIMATED_HEADER_SIZE: u64 = 16 * 1024;
+
+/// Files up to this size are encrypted in batches by the pipeline, as they're small enough to hold in memory (and aren't worth checkpointing part of the way through).
+const PIPELINE_MAX_FILE_SIZE: u64 = 4 * BLOCK_SIZE as u64;
+/// The maximum number of files in each batch, as an interrupted batch has to be redone.
+const PIPELINE_BATCH_SIZE: usize = 256;
+/// The number of files that can be waiting between each stage of the pipeline.
+const PIPELINE_QUEUE_SIZE: usize = 16;
+/// Reading and writing are mostly spent waiting on the disk, so they get a fixed number of workers (encryption gets one per core).
+const PIPELINE_IO_WORKERS: usize = 4;
+
+/// This is the summary of an encryption job, which is returned once it's finished.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FileEncryptorReport {
+	total_files: usize,
+	total_bytes: u64,
+	files_encrypted: usize,
+	bytes_encrypted: u64,
+	errors: Vec<FileFailure>,
+	/// files whose output already existed, and what was done about it
+	#[serde(default)]
+	conflicts: Vec<OutputConflict>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	dry_run: Option<FileEncryptorDryRun>,
+	/// this is what `jobs.retryFailed` runs, which only encrypts the files that failed
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	retry: Option<FileEncryptorJobInit>,
+}
+
+/// This is what an encryption job would do, which is reported instead of doing it when the job is a dry run.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileEncryptorDryRun {
+	key_uuid: Uuid,
+	key_name: Option<String>,
+	algorithm: Algorithm,
+	/// this doesn't include metadata or preview media, as they aren't known until each file is encrypted
+	estimated_output_bytes: u64,
+	files: Vec<FileEncryptorDryRunFile>,
+	conflicts: Vec<FileEncryptorConflict>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileEncryptorDryRunFile {
+	path: String,
+	output_path: PathBuf,
+	size: u64,
+	estimated_size: u64,
+}
+
+/// A file that would fail to encrypt, or that would change something other than its own output.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileEncryptorConflict {
+	path: String,
+	output_path: PathBuf,
+	conflict: EncryptionConflict,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct FileEncryptorJobState {}
+pub enum EncryptionConflict {
+	/// the output already exists, so the job's conflict strategy decides what happens to the file
+	OutputExists(ConflictStrategy),
+	/// another file within the job would be encrypted to the same output
+	DuplicateOutput,
+	/// the original couldn't be opened
+	Unreadable(String),
+}
 
-#[derive(Serialize, Deserialize, Type, Hash)]
+/// Directory targets are encrypted recursively, using the location's indexed file paths (so indexer rules are respected).
+#[derive(Serialize, Deserialize, Debug, Type, Hash, Clone)]
 pub struct FileEncryptorJobInit {
 	pub location_id: i32,
-	pub object_id: i32,
-	pub key_uuid: uuid::Uuid,
+	pub targets: Vec<ObjectTarget>,
+	pub key_uuid: Uuid,
 	pub algorithm: Algorithm,
 	pub metadata: bool,
 	pub preview_media: bool,
-	pub output_path: Option<PathBuf>,
+	pub output_directory: Option<PathBuf>, // if this isn't set, encrypted files are written alongside the originals
+	#[serde(default)]
+	pub on_conflict: ConflictStrategy, // what's done when a file's output already exists
+	#[serde(default)]
+	pub erase_original: bool, // if this is set, originals are securely erased once their encrypted copy has been verified
+	#[serde(default)]
+	pub encrypt_filename: bool, // if this is set, the output's file name is encrypted too (directory names are left as-is)
+	#[serde(default)]
+	pub random_filename: bool, // if this is set, the output is given a random name, and the original name is only kept within the (encrypted) metadata
+	#[serde(default)]
+	pub dry_run: bool, // if this is set, nothing is encrypted, and the job's report describes what would have been done instead
+	#[serde(default)]
+	pub only_file_paths: Option<Vec<i32>>, // if this is set, only these files (from within the targets) are encrypted
+	#[serde(default)]
+	pub rule_id: Option<i32>, // if this is set, the job was dispatched by an encryption rule, and the files it processes are recorded within the rule's activity log
+	#[serde(default)]
+	pub upload: Option<RemoteUpload>, // if this is set, the outputs are streamed straight into uploads to a remote location, so they're never written locally
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FileEncryptorJobStep {
 	obj_name: String,
 	obj_path: PathBuf,
-	obj_type: ObjectType,
+	relative_path: PathBuf, // used for preserving a directory's structure within the output directory
+	object_id: Option<i32>,
+	size: u64,
+	#[serde(default)]
+	file_path_id: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -54,9 +213,18 @@ pub struct Metadata {
 	pub note: Option<String>,
 	pub date_created: chrono::DateTime<FixedOffset>,
 	pub date_modified: chrono::DateTime<FixedOffset>,
+	// the original's integrity checksum (if it had one), so the plaintext can be verified without the original
+	#[serde(default)]
+	pub checksum: Option<String>,
+	// the original file's timestamps, permissions and extended attributes, which are restored when it's decrypted
+	#[serde(default)]
+	pub attributes: Option<FileAttributes>,
+	// the original's file name, which is only set if the output was given a random name
+	#[serde(default)]
+	pub file_name: Option<String>,
 }
 
-const JOB_NAME: &str = "file_encryptor";
+pub const FILE_ENCRYPTOR_JOB_NAME: &str = "file_encryptor";
 
 #[async_trait::async_trait]
 impl StatefulJob for FileEncryptorJob {
@@ -65,57 +233,119 @@ impl StatefulJob for FileEncryptorJob {
 	type Step = FileEncryptorJobStep;
 
 	fn name(&self) -> &'static str {
-		JOB_NAME
+		FILE_ENCRYPTOR_JOB_NAME
+	}
+
+	fn priority(&self) -> JobPriority {
+		JobPriority::Low
+	}
+
+	async fn required_keys(
+		&self,
+		_ctx: &LibraryContext,
+		init: &Self::Init,
+	) -> Result<Vec<Uuid>, JobError> {
+		Ok(vec![init.key_uuid])
 	}
 
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
 		// enumerate files to encrypt
 		// populate the steps with them (local file paths)
-		let location = ctx
-			.library_ctx
-			.db
-			.location()
-			.find_unique(location::id::equals(state.init.location_id))
-			.exec()
-			.await?
-			.expect("critical error: can't find location");
+		let (root_path, items) = resolve_targets(
+			&ctx.library_ctx.db,
+			state.init.location_id,
+			&state.init.targets,
+		)
+		.await?;
 
-		let root_path = location
-			.local_path
-			.as_ref()
-			.map(PathBuf::from)
-			.expect("critical error: issue getting local path as pathbuf");
+		state.steps = VecDeque::new();
 
-		let item = ctx
-			.library_ctx
-			.db
-			.file_path()
-			.find_first(vec![file_path::object_id::equals(Some(
-				state.init.object_id,
-			))])
-			.exec()
-			.await?
-			.expect("critical error: can't find object");
+		for item in items {
+			if !item.is_dir {
+				if !is_selected(&state.init.only_file_paths, item.id) {
+					continue;
+				}
 
-		let obj_name = item.materialized_path;
+				let relative_path = Path::new(&item.materialized_path)
+					.file_name()
+					.map(PathBuf::from)
+					.unwrap_or_default();
 
-		let mut obj_path = root_path.clone();
-		obj_path.push(obj_name.clone());
+				state
+					.steps
+					.push_back(FileEncryptorJobStep::new(&root_path, item, relative_path));
 
-		// i don't know if this covers symlinks
-		let obj_type = if item.is_dir {
-			ObjectType::Directory
-		} else {
-			ObjectType::File
-		};
+				continue;
+			}
 
-		state.steps = VecDeque::new();
-		state.steps.push_back(FileEncryptorJobStep {
-			obj_name,
-			obj_path,
-			obj_type,
+			// the directory itself is kept in the relative path, so `a/dir/b.txt` is output as `dir/b.txt`
+			let parent = Path::new(&item.materialized_path)
+				.parent()
+				.map(Path::to_path_buf)
+				.unwrap_or_default();
+
+			let children = ctx
+				.library_ctx
+				.db
+				.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(state.init.location_id),
+					file_path::materialized_path::starts_with(item.materialized_path),
+					file_path::is_dir::equals(false),
+				])
+				.exec()
+				.await?;
+
+			for child in children {
+				// files that are already encrypted are left alone
+				if child.materialized_path.ends_with(".sdenc")
+					|| !is_selected(&state.init.only_file_paths, child.id)
+				{
+					continue;
+				}
+
+				let relative_path = Path::new(&child.materialized_path)
+					.strip_prefix(&parent)
+					.map(Path::to_path_buf)
+					.unwrap_or_else(|_| PathBuf::from(&child.materialized_path));
+
+				state
+					.steps
+					.push_back(FileEncryptorJobStep::new(&root_path, child, relative_path));
+			}
+		}
+
+		state.data = Some(FileEncryptorJobState {
+			report: FileEncryptorReport {
+				total_files: state.steps.len(),
+				total_bytes: state.steps.iter().map(|step| step.size).sum(),
+				..Default::default()
+			},
+			current: None,
+			batch: Vec::new(),
+			upload: None,
+			encrypted: Vec::new(),
 		});
 
+		if state.init.dry_run {
+			let dry_run = dry_run(&ctx, &state.init, &state.steps)?;
+
+			info!(
+				"Encryption dry run found {} files ({} conflicts)",
+				dry_run.files.len(),
+				dry_run.conflicts.len()
+			);
+
+			if let Some(data) = state.data.as_mut() {
+				data.report.dry_run = Some(dry_run);
+			}
+
+			// the job goes straight to `finalize`, so nothing is touched
+			state.steps.clear();
+		} else {
+			check_free_space(free_space_required(&state.init, &state.steps))?;
+		}
+
 		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
 
 		Ok(())
@@ -126,118 +356,1243 @@ impl StatefulJob for FileEncryptorJob {
 		ctx: WorkerContext,
 		state: &mut JobState<Self>,
 	) -> Result<(), JobError> {
+		// the key being unavailable fails the job, rather than every remaining file
+		let user_key = ctx
+			.library_ctx
+			.key_manager
+			.access_keymount(state.init.key_uuid)?
+			.hashed_key;
+
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		// a batch that was interrupted is redone from scratch, so anything it wrote is removed first
+		for output_path in std::mem::take(&mut data.batch) {
+			remove_partial_output(&output_path)?;
+		}
+
+		// erasing originals can't be redone, so those jobs always encrypt one file at a time (as do uploads, which are streamed)
+		if !state.init.erase_original
+			&& state.init.upload.is_none()
+			&& data.current.is_none()
+			&& state.steps[0].size <= PIPELINE_MAX_FILE_SIZE
+		{
+			let finished = encrypt_batch(&ctx, state, user_key).await?;
+
+			// the job only removes the first step once this returns, so the rest of the batch is removed here
+			state.steps.drain(1..finished);
+			state.step_number += finished - 1;
+
+			return Ok(());
+		}
+
+		ctx.progress(vec![
+			JobReportUpdate::Message(format!("Encrypting {}", state.steps[0].obj_name)),
+			// this is sent with every file, as a resumed job doesn't run `init` again
+			JobReportUpdate::TotalBytes(data.report.total_bytes),
+			JobReportUpdate::CurrentFile {
+				name: state.steps[0].obj_name.clone(),
+				size: state.steps[0].size,
+			},
+		]);
+
+		let result = if state.init.upload.is_some() {
+			upload_file(&ctx, state, user_key).await
+		} else {
+			encrypt_file(&ctx, state, user_key).await
+		};
+
 		let step = &state.steps[0];
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
 
-		match step.obj_type {
-			ObjectType::File => {
-				// handle overwriting checks, and making sure there's enough available space
-
-				let user_key = ctx
-					.library_ctx
-					.key_manager
-					.access_keymount(state.init.key_uuid)?
-					.hashed_key;
-
-				let user_key_details = ctx
-					.library_ctx
-					.key_manager
-					.access_keystore(state.init.key_uuid)?;
-
-				let output_path = if let Some(path) = state.init.output_path.clone() {
-					path
-				} else {
-					let mut path = step.obj_path.clone();
-					let extension = if let Some(ext) = path.extension() {
-						ext.to_str()
-							.expect("critical error: path is not valid utf-8")
-							.to_string() + ".sdenc"
-					} else {
-						"sdenc".to_string()
-					};
-					path.set_extension(extension);
-					path
-				};
-
-				let mut reader = std::fs::File::open(step.obj_path.clone())?;
-				let mut writer = std::fs::File::create(output_path)?;
-
-				let master_key = generate_master_key();
-
-				// i can't decide if the key's encryption should be inherited from the keymanager, or from the file's encryption type
-				// currently it's the file's encryption type
-				let keyslots = vec![Keyslot::new(
-					LATEST_KEYSLOT,
-					state.init.algorithm,
-					user_key_details.hashing_algorithm,
-					user_key_details.content_salt,
-					user_key,
-					&master_key,
-				)?];
-
-				let mut header =
-					FileHeader::new(LATEST_FILE_HEADER, state.init.algorithm, keyslots);
-
-				if state.init.metadata || state.init.preview_media {
-					// if any are requested, we can make the query as it'll be used at least once
-					let object = ctx
-						.library_ctx
-						.db
-						.object()
-						.find_unique(object::id::equals(state.init.object_id))
-						.exec()
-						.await?
-						.expect("critical error: can't get object info");
-
-					if state.init.metadata {
-						let metadata = Metadata {
-							object_id: state.init.object_id,
-							name: step.obj_name.clone(),
-							hidden: object.hidden,
-							favourite: object.favorite,
-							important: object.important,
-							note: object.note,
-							date_created: object.date_created,
-							date_modified: object.date_modified,
-						};
-
-						header.add_metadata(
-							LATEST_METADATA,
-							state.init.algorithm,
-							&master_key,
-							&metadata,
-						)?;
-					}
+		match result {
+			Ok(true) => {
+				data.report.files_encrypted += 1;
+				data.report.bytes_encrypted += step.size;
+
+				if state.init.rule_id.is_some() {
+					data.encrypted.push(step.obj_name.clone());
+				}
+			}
+			// the file was skipped, as its output already exists
+			Ok(false) => {}
+			Err(e) => {
+				// don't leave a partially-written file behind
+				if let Some(progress) = data.current.take().filter(|progress| !progress.complete) {
+					remove_partial_output(&progress.output_path)?;
+				}
+				data.upload = None;
+
+				warn!("encryption failed for {}: {:#?}", step.obj_name, e);
+				ctx.log(
+					JobLogLevel::Warning,
+					format!("Encryption failed: {e}"),
+					Some(step.obj_name.clone()),
+				);
+				data.report.errors.push(FileFailure {
+					path: step.obj_name.clone(),
+					file_path_id: step.file_path_id,
+					error: e.to_string(),
+				});
+			}
+		}
+
+		ctx.progress(vec![
+			JobReportUpdate::CompletedTaskCount(state.step_number + 1),
+			JobReportUpdate::BytesProcessed {
+				total: data.report.bytes_encrypted,
+				file: step.size,
+			},
+			JobReportUpdate::Message(format!(
+				"Encrypted {} of {} files ({} of {} bytes)",
+				data.report.files_encrypted,
+				data.report.total_files,
+				data.report.bytes_encrypted,
+				data.report.total_bytes
+			)),
+		]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		if let Some(rule_id) = state.init.rule_id {
+			let activity = std::mem::take(&mut data.encrypted)
+				.into_iter()
+				.map(|path| RuleActivity {
+					path,
+					outcome: EncryptionRuleOutcome::Encrypted,
+					error: None,
+				})
+				.chain(data.report.errors.iter().map(|failure| RuleActivity {
+					path: failure.path.clone(),
+					outcome: EncryptionRuleOutcome::Failed,
+					error: Some(failure.error.clone()),
+				}))
+				.collect();
+
+			record_rule_activity(&ctx.library_ctx, rule_id, state.init.location_id, activity).await;
+		}
+
+		data.report.retry =
+			failed_file_paths(&data.report.errors).map(|only_file_paths| FileEncryptorJobInit {
+				only_file_paths: Some(only_file_paths),
+				..state.init.clone()
+			});
+
+		if state.init.upload.is_some() && data.report.files_encrypted > 0 {
+			invalidate_query!(ctx.library_ctx, "locations.getExplorerData");
+		}
+
+		info!("Finalizing encryption job: {:#?}", data.report);
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+
+	async fn cancel(
+		&self,
+		_ctx: &LibraryContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		// the file that was being encrypted is incomplete, so it's removed
+		if let Some(data) = state.data.as_mut() {
+			if let Some(progress) = data.current.take().filter(|progress| !progress.complete) {
+				remove_partial_output(&progress.output_path)?;
+			}
+
+			for output_path in std::mem::take(&mut data.batch) {
+				remove_partial_output(&output_path)?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+impl FileEncryptorJobStep {
+	fn new(root_path: &Path, file_path: file_path::Data, relative_path: PathBuf) -> Self {
+		let obj_path = root_path.join(&file_path.materialized_path);
+
+		Self {
+			size: std::fs::metadata(&obj_path).map_or(0, |m| m.len()),
+			obj_name: file_path.materialized_path,
+			obj_path,
+			relative_path,
+			object_id: file_path.object_id,
+			file_path_id: Some(file_path.id),
+		}
+	}
+}
+
+/// This encrypts the file at the front of the job's steps, continuing from the last checkpoint if it was interrupted part of the way through.
+///
+/// It returns whether the file was encrypted, as it's skipped if its output already exists (and the job's conflict strategy says to).
+async fn encrypt_file(
+	ctx: &WorkerContext,
+	state: &mut JobState<FileEncryptorJob>,
+	user_key: Protected<[u8; KEY_LEN]>,
+) -> Result<bool, JobError> {
+	let init = &state.init;
+	let step = &state.steps[0];
+
+	// anything written after the last checkpoint may not have reached the disk, so an output that's behind is started again
+	// (as is one whose original has changed since, which is given a fresh header rather than mixing both versions of the file)
+	let current = current_progress(&mut state.data);
+	if let Some(progress) = current.as_ref().filter(|progress| !progress.complete) {
+		let reason = if !progress
+			.source
+			.map_or(false, |source| source.matches(&step.obj_path))
+		{
+			Some("the original has changed")
+		} else if !output_is_intact(progress).unwrap_or(false) {
+			Some("the output was incomplete")
+		} else {
+			None
+		};
+
+		if let Some(reason) = reason {
+			warn!("restarting encryption of {}, as {reason}", step.obj_name);
+			ctx.log(
+				JobLogLevel::Info,
+				format!("Restarting encryption, as {reason}"),
+				Some(step.obj_name.clone()),
+			);
+			remove_partial_output(&progress.output_path)?;
+			*current = None;
+		}
+	}
+
+	if current.is_none() {
+		let (prepared, conflict) = prepare_header(ctx, init, step, user_key.clone()).await?;
+
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+		data.report.conflicts.extend(conflict);
+
+		let prepared = match prepared {
+			Some(prepared) => prepared,
+			None => return Ok(false),
+		};
+
+		data.current = Some(FileEncryptorProgress {
+			output_path: prepared.path.clone(),
+			blocks_written: 0,
+			source: Some(SourceStamp::of(&step.obj_path)?),
+			complete: false,
+		});
+
+		// the output is recorded before it's created, so it can be cleaned up if the job is interrupted
+		ctx.checkpoint(state).await?;
+
+		if let Err(e) = create_output(&prepared.path, &prepared.header, prepared.overwrite) {
+			*current_progress(&mut state.data) = None;
+			return Err(e);
+		}
+	}
+
+	loop {
+		let progress = current_progress(&mut state.data)
+			.as_mut()
+			.expect("critical error: missing encryption progress");
+
+		if progress.complete
+			|| encrypt_contents(
+				&step.obj_path,
+				progress,
+				user_key.clone(),
+				ctx.library_ctx.io_throttle(),
+			)
+			.await?
+		{
+			break;
+		}
+
+		let file_bytes = (u64::from(progress.blocks_written) * BLOCK_SIZE as u64).min(step.size);
+		let bytes_encrypted = state
+			.data
+			.as_ref()
+			.map_or(0, |data| data.report.bytes_encrypted);
+
+		ctx.progress_debounced(vec![JobReportUpdate::BytesProcessed {
+			total: bytes_encrypted + file_bytes,
+			file: file_bytes,
+		}]);
+
+		ctx.checkpoint(state).await?;
+	}
+
+	if init.erase_original {
+		let progress = current_progress(&mut state.data)
+			.as_mut()
+			.expect("critical error: missing encryption progress");
+
+		if !progress.complete {
+			// the original is only touched once we know the encrypted copy can be decrypted
+			verify_encrypted_file(&progress.output_path, user_key)?;
+			progress.complete = true;
+
+			// the original can't be brought back once it's erased, so the job can't be resumed from before this point
+			ctx.checkpoint(state).await?;
+		}
+
+		// the original may have already been erased, if the job was interrupted after doing so
+		if step.obj_path.exists() {
+			erase_file(&step.obj_path)?;
+		}
+	}
+
+	*current_progress(&mut state.data) = None;
+
+	Ok(true)
+}
+
+/// This encrypts the file at the front of the job's steps straight into an upload to the job's remote location, so nothing is written locally.
+///
+/// The header is uploaded at the start of the file, as it is locally. It's checkpointed before the upload starts, so if the job is interrupted
+/// (and the remote can append to files), the rest of the file is encrypted with the same master key and appended to what was already uploaded.
+///
+/// Otherwise (or if the original has changed since), the upload is started again with a fresh header, so the same master key and nonce are never used for two different uploads.
+async fn upload_file(
+	ctx: &WorkerContext,
+	state: &mut JobState<FileEncryptorJob>,
+	user_key: Protected<[u8; KEY_LEN]>,
+) -> Result<bool, JobError> {
+	let upload = state
+		.init
+		.upload
+		.as_ref()
+		.expect("critical error: missing upload on job init");
+	let library = &ctx.library_ctx;
+
+	let backend = connect(library, upload.location_id).await?;
+
+	let step = &state.steps[0];
+	let previous = state
+		.data
+		.as_mut()
+		.expect("critical error: missing data on job state")
+		.upload
+		.take();
+
+	let resumed = previous.as_ref().map_or(false, |progress| {
+		backend.appendable()
+			&& progress
+				.source
+				.map_or(false, |source| source.matches(&step.obj_path))
+	});
+
+	let progress = match previous {
+		Some(progress) if resumed => progress,
+		previous => {
+			// an upload that can't be continued replaces whatever reached the remote, so it keeps the same path
+			let remote_path = match previous {
+				Some(progress) => {
+					warn!(
+						"restarting upload of {}, as it can't be continued",
+						step.obj_name
+					);
+					ctx.log(
+						JobLogLevel::Info,
+						"Restarting upload, as it can't be continued",
+						Some(step.obj_name.clone()),
+					);
+
+					progress.remote_path
+				}
+				None => remote_output_path(&state.init, upload, step, user_key.clone())?,
+			};
+
+			FileUploadProgress {
+				remote_path,
+				header: build_header(ctx, &state.init, step, user_key.clone())
+					.await?
+					.serialize()?,
+				source: Some(SourceStamp::of(&step.obj_path)?),
+			}
+		}
+	};
+
+	state
+		.data
+		.as_mut()
+		.expect("critical error: missing data on job state")
+		.upload = Some(progress);
+
+	if !resumed {
+		ctx.checkpoint(state).await?;
+	}
+
+	let step = &state.steps[0];
+	let data = state
+		.data
+		.as_ref()
+		.expect("critical error: missing data on job state");
+	let progress = data
+		.upload
+		.as_ref()
+		.expect("critical error: missing upload progress");
+	let bytes_encrypted = data.report.bytes_encrypted;
+
+	let header_len = progress.header.len() as u64;
+	let size = header_len + encrypted_size(step.size);
+
+	// whatever reached the remote before the upload was interrupted is kept, as it was encrypted with the same header
+	let offset = if resumed {
+		backend
+			.size(&progress.remote_path)
+			.await?
+			.unwrap_or(0)
+			.min(size)
+	} else {
+		0
+	};
+
+	if offset < size {
+		let (mut writer, reader) = tokio::io::duplex(BLOCK_SIZE + AEAD_TAG_SIZE);
+		let mut reader = ThrottledUpload::new(reader, Arc::clone(library.io_throttle()));
+
+		let encrypt = encrypt_into(
+			&step.obj_path,
+			&progress.header,
+			offset,
+			user_key,
+			library.io_throttle(),
+			&mut writer,
+			|blocks| {
+				let file_bytes = (u64::from(blocks) * BLOCK_SIZE as u64).min(step.size);
+
+				ctx.progress_debounced(vec![JobReportUpdate::BytesProcessed {
+					total: bytes_encrypted + file_bytes,
+					file: file_bytes,
+				}]);
+			},
+		);
+		let write = async {
+			backend
+				.write(&progress.remote_path, offset, &mut reader, size)
+				.await
+				.map_err(JobError::from)
+		};
+
+		tokio::try_join!(encrypt, write)?;
+	}
+
+	record_upload(library, upload.location_id, &progress.remote_path, size).await?;
+
+	state
+		.data
+		.as_mut()
+		.expect("critical error: missing data on job state")
+		.upload = None;
+
+	Ok(true)
+}
+
+/// This encrypts a file (using its serialized header) into `writer`, skipping the first `offset` bytes of the encrypted file.
+///
+/// `on_block` is given the number of blocks that have been encrypted, after each one.
+async fn encrypt_into(
+	source: &Path,
+	header: &[u8],
+	offset: u64,
+	user_key: Protected<[u8; KEY_LEN]>,
+	throttle: &IoThrottle,
+	mut writer: impl AsyncWrite + Unpin,
+	mut on_block: impl FnMut(u32),
+) -> Result<(), JobError> {
+	let (file_header, aad) = FileHeader::deserialize(&mut Cursor::new(header))?;
+	let master_key = file_header.decrypt_master_key_from_prehashed(vec![user_key])?;
+
+	let header_len = header.len() as u64;
+	if offset < header_len {
+		writer.write_all(&header[offset as usize..]).await?;
+	}
 
-					if state.init.preview_media
-						&& (object.has_thumbnail
-							|| object.has_video_preview || object.has_thumbstrip)
-					{
-						// need to find the preview media, read it and return it as Some()
-						// not currently able to do this as thumnails don't generate
+	// the encryption continues from the block that `offset` is within, and the part of it that was already uploaded is skipped
+	let contents_offset = offset.saturating_sub(header_len);
+	let mut blocks = (contents_offset / (BLOCK_SIZE + AEAD_TAG_SIZE) as u64) as u32;
+	let mut skip = (contents_offset % (BLOCK_SIZE + AEAD_TAG_SIZE) as u64) as usize;
+
+	let mut reader = File::open(source)?;
+	reader.seek(SeekFrom::Start(u64::from(blocks) * BLOCK_SIZE as u64))?;
+
+	let mut encryptor =
+		StreamEncryption::new(master_key, &file_header.nonce, file_header.algorithm)?;
+	encryptor.seek_to_block(blocks);
+
+	let mut buffer = Vec::with_capacity(BLOCK_SIZE + AEAD_TAG_SIZE);
+	loop {
+		buffer.clear();
+		let finished = encryptor.encrypt_block(&mut reader, &mut buffer, &aad)?;
+
+		throttle.read(BLOCK_SIZE as u64).await;
+
+		writer.write_all(&buffer[skip.min(buffer.len())..]).await?;
+		skip = 0;
+
+		blocks += 1;
+		on_block(blocks);
+
+		if finished {
+			break;
+		}
+	}
+
+	writer.shutdown().await?;
+
+	Ok(())
+}
+
+fn current_progress(
+	data: &mut Option<FileEncryptorJobState>,
+) -> &mut Option<FileEncryptorProgress> {
+	&mut data
+		.as_mut()
+		.expect("critical error: missing data on job state")
+		.current
+}
+
+/// This checks that an output contains everything up to its last checkpoint.
+fn output_is_intact(progress: &FileEncryptorProgress) -> Result<bool, JobError> {
+	let mut reader = File::open(&progress.output_path)?;
+	FileHeader::deserialize(&mut reader)?;
+
+	let expected_len = reader.stream_position()?
+		+ u64::from(progress.blocks_written) * (BLOCK_SIZE + AEAD_TAG_SIZE) as u64;
+
+	Ok(reader.metadata()?.len() >= expected_len)
+}
+
+/// A file that's passing through the pipeline, whose `data` is its plaintext once it's been read, and its encrypted output once it's been encrypted.
+struct PipelineItem {
+	index: usize,
+	source: PathBuf,
+	output_path: PathBuf,
+	overwrite: bool,
+	header: FileHeader,
+	data: Vec<u8>,
+}
+
+type PipelineResult = Result<PipelineItem, (usize, JobError)>;
+
+/// This encrypts a batch of small files from the front of the job's steps, and returns how many steps it covered.
+///
+/// Each file is read, encrypted and written by separate worker pools, so the disk and CPU are kept busy at the same time rather than taking turns.
+/// The outputs are checkpointed before any are created, so if the job is interrupted the whole batch is removed and redone.
+async fn encrypt_batch(
+	ctx: &WorkerContext,
+	state: &mut JobState<FileEncryptorJob>,
+	user_key: Protected<[u8; KEY_LEN]>,
+) -> Result<usize, JobError> {
+	let count = state
+		.steps
+		.iter()
+		.take(PIPELINE_BATCH_SIZE)
+		.take_while(|step| step.size <= PIPELINE_MAX_FILE_SIZE)
+		.count();
+
+	ctx.progress(vec![
+		JobReportUpdate::Message(format!("Encrypting {} files", count)),
+		JobReportUpdate::TotalBytes(
+			state
+				.data
+				.as_ref()
+				.map_or(0, |data| data.report.total_bytes),
+		),
+	]);
+
+	let mut items = Vec::with_capacity(count);
+	let mut errors = Vec::new();
+	let mut skipped = HashSet::new();
+	let mut conflicts = Vec::new();
+
+	// headers are prepared up front, as they may need the database
+	for (index, step) in state.steps.iter().take(count).enumerate() {
+		match prepare_header(ctx, &state.init, step, user_key.clone()).await {
+			Ok((prepared, conflict)) => {
+				conflicts.extend(conflict);
+
+				match prepared {
+					Some(prepared) => items.push(PipelineItem {
+						index,
+						source: step.obj_path.clone(),
+						output_path: prepared.path,
+						overwrite: prepared.overwrite,
+						header: prepared.header,
+						data: Vec::new(),
+					}),
+					None => {
+						skipped.insert(index);
 					}
 				}
+			}
+			Err(e) => errors.push((index, e)),
+		}
+	}
 
-				header.write(&mut writer)?;
+	let outputs = items
+		.iter()
+		.map(|item| (item.index, item.output_path.clone()))
+		.collect::<Vec<_>>();
 
-				let encryptor = StreamEncryption::new(master_key, &header.nonce, header.algorithm)?;
+	// the outputs are recorded before any are created, so they can be cleaned up if the job is interrupted
+	state
+		.data
+		.as_mut()
+		.expect("critical error: missing data on job state")
+		.batch = outputs.iter().map(|(_, path)| path.clone()).collect();
+	ctx.checkpoint(state).await?;
 
-				encryptor.encrypt_streams(&mut reader, &mut writer, &header.generate_aad())?;
+	let encryptors = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+	let throttle = Arc::clone(ctx.library_ctx.io_throttle());
+
+	let (input_tx, input_rx) = mpsc::channel(PIPELINE_QUEUE_SIZE);
+	let (read_rx, _readers) =
+		WorkerPool::spawn(PIPELINE_IO_WORKERS, PIPELINE_QUEUE_SIZE, input_rx, {
+			let throttle = Arc::clone(&throttle);
+			move |item| read_item(item, &throttle)
+		});
+	let (encrypt_rx, _encryptors) =
+		WorkerPool::spawn(encryptors, PIPELINE_QUEUE_SIZE, read_rx, move |item| {
+			encrypt_item(item, user_key.clone())
+		});
+	let (mut write_rx, _writers) = WorkerPool::spawn(
+		PIPELINE_IO_WORKERS,
+		PIPELINE_QUEUE_SIZE,
+		encrypt_rx,
+		move |item| write_item(item, &throttle),
+	);
+
+	let feed = async move {
+		for item in items {
+			if input_tx.send(item).await.is_err() {
+				break;
 			}
-			_ => warn!(
-				"encryption is skipping {} as it isn't a file",
-				step.obj_name
-			),
 		}
+	};
 
-		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
-			state.step_number + 1,
-		)]);
+	let steps = &state.steps;
+	let data = state
+		.data
+		.as_ref()
+		.expect("critical error: missing data on job state");
+	let step_number = state.step_number;
 
-		Ok(())
+	let collect = async {
+		let mut results = Vec::with_capacity(outputs.len());
+		let mut bytes_encrypted = data.report.bytes_encrypted;
+
+		while let Some(result) = write_rx.recv().await {
+			if let Ok(index) = &result {
+				let step = &steps[*index];
+				bytes_encrypted += step.size;
+
+				ctx.progress_debounced(vec![
+					JobReportUpdate::CompletedTaskCount(step_number + results.len() + 1),
+					JobReportUpdate::CurrentFile {
+						name: step.obj_name.clone(),
+						size: step.size,
+					},
+					JobReportUpdate::BytesProcessed {
+						total: bytes_encrypted,
+						file: step.size,
+					},
+				]);
+			}
+
+			results.push(result);
+		}
+
+		results
+	};
+
+	let ((), results) = tokio::join!(feed, collect);
+
+	let mut finished = vec![false; count];
+
+	for result in results {
+		match result {
+			Ok(index) => finished[index] = true,
+			Err((index, e)) => {
+				// this is marked as finished, as the writer has already removed anything it created
+				finished[index] = true;
+				errors.push((index, e));
+			}
+		}
 	}
 
-	async fn finalize(&self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
-		// mark job as successful
-		Ok(Some(serde_json::to_value(&state.init)?))
+	// anything that didn't make it through (e.g. a worker panicked) may have been partially written
+	for (index, output_path) in &outputs {
+		if !finished[*index] {
+			remove_partial_output(output_path)?;
+			errors.push((
+				*index,
+				io::Error::new(
+					io::ErrorKind::Other,
+					"the file was dropped by the encryption pipeline",
+				)
+				.into(),
+			));
+		}
 	}
+
+	let data = state
+		.data
+		.as_mut()
+		.expect("critical error: missing data on job state");
+
+	data.batch.clear();
+	data.report.conflicts.append(&mut conflicts);
+
+	for (index, step) in state.steps.iter().take(count).enumerate() {
+		if skipped.contains(&index) {
+			continue;
+		}
+
+		match errors.iter().position(|(i, _)| *i == index) {
+			Some(position) => {
+				let (_, e) = errors.swap_remove(position);
+
+				warn!("encryption failed for {}: {:#?}", step.obj_name, e);
+				ctx.log(
+					JobLogLevel::Warning,
+					format!("Encryption failed: {e}"),
+					Some(step.obj_name.clone()),
+				);
+				data.report.errors.push(FileFailure {
+					path: step.obj_name.clone(),
+					file_path_id: step.file_path_id,
+					error: e.to_string(),
+				});
+			}
+			None => {
+				data.report.files_encrypted += 1;
+				data.report.bytes_encrypted += step.size;
+
+				if state.init.rule_id.is_some() {
+					data.encrypted.push(step.obj_name.clone());
+				}
+			}
+		}
+	}
+
+	ctx.progress(vec![
+		JobReportUpdate::CompletedTaskCount(state.step_number + count),
+		JobReportUpdate::BytesProcessed {
+			total: data.report.bytes_encrypted,
+			file: 0,
+		},
+		JobReportUpdate::Message(format!(
+			"Encrypted {} of {} files ({} of {} bytes)",
+			data.report.files_encrypted,
+			data.report.total_files,
+			data.report.bytes_encrypted,
+			data.report.total_bytes
+		)),
+	]);
+
+	Ok(count)
+}
+
+fn read_item(mut item: PipelineItem, throttle: &IoThrottle) -> PipelineResult {
+	match std::fs::read(&item.source) {
+		Ok(data) => {
+			throttle.read_blocking(data.len() as u64);
+			item.data = data;
+			Ok(item)
+		}
+		Err(e) => Err((item.index, e.into())),
+	}
+}
+
+fn encrypt_item(item: PipelineResult, user_key: Protected<[u8; KEY_LEN]>) -> PipelineResult {
+	let mut item = item?;
+
+	let encrypt = |item: &PipelineItem| -> Result<Vec<u8>, JobError> {
+		let master_key = item
+			.header
+			.decrypt_master_key_from_prehashed(vec![user_key])?;
+
+		let mut output = item.header.serialize()?;
+		output.reserve(item.data.len() + AEAD_TAG_SIZE * (item.data.len() / BLOCK_SIZE + 1));
+
+		StreamEncryption::new(master_key, &item.header.nonce, item.header.algorithm)?
+			.encrypt_streams(
+				item.data.as_slice(),
+				&mut output,
+				&item.header.generate_aad(),
+			)?;
+
+		Ok(output)
+	};
+
+	match encrypt(&item) {
+		Ok(output) => {
+			// the plaintext is replaced straight away, so it isn't kept around any longer than it needs to be
+			item.data = output;
+			Ok(item)
+		}
+		Err(e) => Err((item.index, e)),
+	}
+}
+
+fn write_item(item: PipelineResult, throttle: &IoThrottle) -> Result<usize, (usize, JobError)> {
+	let item = item?;
+
+	throttle.write_blocking(item.data.len() as u64);
+
+	let mut writer = OpenOptions::new()
+		.write(true)
+		.create(true)
+		.truncate(true)
+		.create_new(!item.overwrite)
+		.open(&item.output_path)
+		.map_err(|e| (item.index, e.into()))?;
+
+	if let Err(e) = writer
+		.write_all(&item.data)
+		.and_then(|()| writer.sync_all())
+	{
+		drop(writer);
+		remove_partial_output(&item.output_path).map_err(|e| (item.index, e))?;
+		return Err((item.index, e.into()));
+	}
+
+	Ok(item.index)
+}
+
+/// A file's output, once its header has been built and any conflict with an existing file has been resolved.
+struct PreparedOutput {
+	path: PathBuf,
+	overwrite: bool,
+	header: FileHeader,
+}
+
+/// This decides where the output will be written, and builds its header.
+///
+/// Everything that needs an `.await` happens here, before the output is created, so a paused job never leaves an output without a header.
+/// If the output already exists, the job's conflict strategy is applied and returned alongside it (the output is `None` if the file should be skipped).
+async fn prepare_header(
+	ctx: &WorkerContext,
+	init: &FileEncryptorJobInit,
+	step: &FileEncryptorJobStep,
+	user_key: Protected<[u8; KEY_LEN]>,
+) -> Result<(Option<PreparedOutput>, Option<OutputConflict>), JobError> {
+	let output_path = output_path(init, step, user_key.clone())?;
+
+	if init.output_directory.is_some() {
+		if let Some(parent) = output_path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+	}
+
+	// this is checked before the output is recorded, as an existing file mustn't be cleaned up as if it were ours
+	let (resolved, decision) = resolve_conflict(output_path.clone(), init.on_conflict)?;
+	let conflict = decision.map(|decision| OutputConflict {
+		path: step.obj_name.clone(),
+		output_path,
+		decision,
+	});
+
+	let resolved = match resolved {
+		Some(resolved) => resolved,
+		None => return Ok((None, conflict)),
+	};
+
+	Ok((
+		Some(PreparedOutput {
+			path: resolved.path,
+			overwrite: resolved.overwrite,
+			header: build_header(ctx, init, step, user_key).await?,
+		}),
+		conflict,
+	))
+}
+
+/// This builds a file's header, with a new master key (and the file's metadata and preview media, if they're requested).
+async fn build_header(
+	ctx: &WorkerContext,
+	init: &FileEncryptorJobInit,
+	step: &FileEncryptorJobStep,
+	user_key: Protected<[u8; KEY_LEN]>,
+) -> Result<FileHeader, JobError> {
+	let user_key_details = ctx.library_ctx.key_manager.access_keystore(init.key_uuid)?;
+
+	let master_key = generate_master_key();
+
+	// i can't decide if the key's encryption should be inherited from the keymanager, or from the file's encryption type
+	// currently it's the file's encryption type
+	let keyslots = vec![Keyslot::new(
+		LATEST_KEYSLOT,
+		init.algorithm,
+		user_key_details.hashing_algorithm,
+		user_key_details.content_salt,
+		user_key.clone(),
+		&master_key,
+	)?];
+
+	let mut header = FileHeader::new(LATEST_FILE_HEADER, init.algorithm, keyslots);
+
+	// the original name would be lost without the metadata, which needs the file's object
+	if init.random_filename && step.object_id.is_none() {
+		return Err(JobError::JobDataNotFound(format!(
+			"{} hasn't been identified yet, so it can't be given a random name",
+			step.obj_name
+		)));
+	}
+
+	let metadata = init.metadata || init.random_filename;
+
+	if let (true, Some(object_id)) = (metadata || init.preview_media, step.object_id) {
+		// if any are requested, we can make the query as it'll be used at least once
+		let object = ctx
+			.library_ctx
+			.db
+			.object()
+			.find_unique(object::id::equals(object_id))
+			.exec()
+			.await?
+			.ok_or_else(|| JobError::JobDataNotFound(format!("object {object_id} not found")))?;
+
+		if metadata {
+			let metadata = Metadata {
+				object_id,
+				name: step.obj_name.clone(),
+				hidden: object.hidden,
+				favourite: object.favorite,
+				important: object.important,
+				// the note may be encrypted within the database, but the header has its own encryption
+				note: match &object.encrypted_note {
+					Some(encrypted_note) => decrypt_note(&ctx.library_ctx, encrypted_note),
+					None => object.note,
+				},
+				date_created: object.date_created,
+				date_modified: object.date_modified,
+				checksum: object.integrity_checksum.clone(),
+				attributes: FileAttributes::read(&step.obj_path)
+					.map_err(|e| {
+						warn!(
+							"encryption is skipping the attributes for {}: {:#?}",
+							step.obj_name, e
+						);
+						ctx.log(
+							JobLogLevel::Warning,
+							format!("Skipped the file's attributes: {e}"),
+							Some(step.obj_name.clone()),
+						);
+					})
+					.ok(),
+				file_name: init.random_filename.then(|| {
+					step.obj_path
+						.file_name()
+						.unwrap_or_default()
+						.to_string_lossy()
+						.to_string()
+				}),
+			};
+
+			header.add_metadata(LATEST_METADATA, init.algorithm, &master_key, &metadata)?;
+		}
+
+		if init.preview_media {
+			// thumbnails aren't always flagged on the object (e.g. when generated by the watcher), so we just try to read one
+			match read_thumbnail(&ctx.library_ctx, &object.cas_id).await {
+				Ok(media) => header.add_preview_media(
+					LATEST_PREVIEW_MEDIA,
+					init.algorithm,
+					&master_key,
+					&media,
+				)?,
+				Err(EncryptedThumbnailError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {}
+				Err(e) => {
+					warn!(
+						"encryption is skipping the preview media for {}: {:#?}",
+						step.obj_name, e
+					);
+					ctx.log(
+						JobLogLevel::Warning,
+						format!("Skipped the file's preview media: {e}"),
+						Some(step.obj_name.clone()),
+					);
+				}
+			}
+		}
+	}
+
+	Ok(header)
+}
+
+/// This estimates how much is written where, for checking that there's enough space before the job starts.
+fn free_space_required(
+	init: &FileEncryptorJobInit,
+	steps: &VecDeque<FileEncryptorJobStep>,
+) -> Vec<(PathBuf, u64)> {
+	// uploads are streamed, so nothing is written locally
+	if init.upload.is_some() {
+		return Vec::new();
+	}
+
+	let outputs = steps.iter().map(|step| {
+		let path = match &init.output_directory {
+			Some(directory) => directory.clone(),
+			None => step.obj_path.clone(),
+		};
+
+		(path, ESTIMATED_HEADER_SIZE + encrypted_size(step.size))
+	});
+
+	if init.erase_original {
+		// each original is erased before the next file is encrypted, so only the largest output needs to fit at once
+		outputs.max_by_key(|(_, size)| *size).into_iter().collect()
+	} else {
+		outputs.collect()
+	}
+}
+
+/// This decides where a file's encrypted output will be written.
+fn output_path(
+	init: &FileEncryptorJobInit,
+	step: &FileEncryptorJobStep,
+	user_key: Protected<[u8; KEY_LEN]>,
+) -> Result<PathBuf, JobError> {
+	let output_path = match &init.output_directory {
+		Some(directory) => directory.join(&step.relative_path),
+		None => step.obj_path.clone(),
+	};
+
+	encrypted_output_name(init, output_path, user_key)
+}
+
+/// This decides where a file's encrypted output will be uploaded to, within the remote location.
+fn remote_output_path(
+	init: &FileEncryptorJobInit,
+	upload: &RemoteUpload,
+	step: &FileEncryptorJobStep,
+	user_key: Protected<[u8; KEY_LEN]>,
+) -> Result<String, JobError> {
+	let relative_path = encrypted_output_name(init, step.relative_path.clone(), user_key)?;
+
+	// remote paths always use `/`, whatever the local platform uses
+	let relative_path = relative_path
+		.iter()
+		.map(|component| component.to_string_lossy())
+		.collect::<Vec<_>>()
+		.join("/");
+
+	Ok(join_remote_path(&upload.directory, &relative_path))
+}
+
+/// This renames an output, depending on how the job names its outputs (it keeps the original name with `.sdenc` appended by default).
+fn encrypted_output_name(
+	init: &FileEncryptorJobInit,
+	mut output_path: PathBuf,
+	user_key: Protected<[u8; KEY_LEN]>,
+) -> Result<PathBuf, JobError> {
+	if init.random_filename {
+		output_path.set_file_name(format!("{}.sdenc", Uuid::new_v4()));
+	} else if init.encrypt_filename {
+		let file_name = output_path
+			.file_name()
+			.and_then(|name| name.to_str())
+			.expect("critical error: path is not valid utf-8");
+
+		let encrypted_name = encrypt_filename(user_key, file_name)?;
+		output_path.set_file_name(encrypted_name + ".sdenc");
+	} else {
+		let extension = if let Some(ext) = output_path.extension() {
+			ext.to_str()
+				.expect("critical error: path is not valid utf-8")
+				.to_string() + ".sdenc"
+		} else {
+			"sdenc".to_string()
+		};
+		output_path.set_extension(extension);
+	}
+
+	Ok(output_path)
+}
+
+/// This works out what the job would do with its steps, without reading (or writing) any file contents.
+fn dry_run(
+	ctx: &WorkerContext,
+	init: &FileEncryptorJobInit,
+	steps: &VecDeque<FileEncryptorJobStep>,
+) -> Result<FileEncryptorDryRun, JobError> {
+	let key_manager = &ctx.library_ctx.key_manager;
+	let user_key = key_manager.access_keymount(init.key_uuid)?.hashed_key;
+	let user_key_details = key_manager.access_keystore(init.key_uuid)?;
+
+	// every header is at least this long, but metadata and preview media are only known once the file is encrypted
+	let header_len = FileHeader::new(
+		LATEST_FILE_HEADER,
+		init.algorithm,
+		vec![Keyslot::new(
+			LATEST_KEYSLOT,
+			init.algorithm,
+			user_key_details.hashing_algorithm,
+			user_key_details.content_salt,
+			user_key.clone(),
+			&generate_master_key(),
+		)?],
+	)
+	.serialize()?
+	.len() as u64;
+
+	let mut dry_run = FileEncryptorDryRun {
+		key_uuid: init.key_uuid,
+		key_name: user_key_details.name,
+		algorithm: init.algorithm,
+		estimated_output_bytes: 0,
+		files: Vec::with_capacity(steps.len()),
+		conflicts: Vec::new(),
+	};
+
+	let mut outputs = HashSet::new();
+
+	for step in steps {
+		let output_path = match &init.upload {
+			Some(upload) => {
+				PathBuf::from(remote_output_path(init, upload, step, user_key.clone())?)
+			}
+			None => output_path(init, step, user_key.clone())?,
+		};
+		let estimated_size = header_len + encrypted_size(step.size);
+
+		let conflict = if let Err(e) = File::open(&step.obj_path) {
+			Some(EncryptionConflict::Unreadable(e.to_string()))
+		} else if !outputs.insert(output_path.clone()) {
+			Some(EncryptionConflict::DuplicateOutput)
+		} else if init.upload.is_none() && output_path.exists() {
+			Some(EncryptionConflict::OutputExists(init.on_conflict))
+		} else {
+			None
+		};
+
+		if let Some(conflict) = conflict {
+			dry_run.conflicts.push(FileEncryptorConflict {
+				path: step.obj_name.clone(),
+				output_path: output_path.clone(),
+				conflict,
+			});
+		}
+
+		dry_run.estimated_output_bytes += estimated_size;
+		dry_run.files.push(FileEncryptorDryRunFile {
+			path: step.obj_name.clone(),
+			output_path,
+			size: step.size,
+			estimated_size,
+		});
+	}
+
+	Ok(dry_run)
+}
+
+pub(super) fn create_output(
+	output_path: &Path,
+	header: &FileHeader,
+	overwrite: bool,
+) -> Result<(), JobError> {
+	let mut writer = OpenOptions::new()
+		.write(true)
+		.create(true)
+		.truncate(true)
+		.create_new(!overwrite)
+		.open(output_path)?;
+
+	if let Err(e) = header.write(&mut writer) {
+		drop(writer);
+		std::fs::remove_file(output_path)?;
+		return Err(e.into());
+	}
+
+	writer.sync_all()?;
+
+	Ok(())
+}
+
+/// This encrypts up to `CHECKPOINT_BLOCKS` blocks of a file, continuing from the last block that was written, and returns whether it's finished.
+///
+/// The blocks are synced to the disk before returning, so they're safe to checkpoint.
+/// The job can only be paused at an `.await`, so it's given the chance to between every block.
+async fn encrypt_contents(
+	source: &Path,
+	progress: &mut FileEncryptorProgress,
+	user_key: Protected<[u8; KEY_LEN]>,
+	throttle: &IoThrottle,
+) -> Result<bool, JobError> {
+	let mut writer = OpenOptions::new()
+		.read(true)
+		.write(true)
+		.open(&progress.output_path)?;
+
+	let (header, aad) = FileHeader::deserialize(&mut writer)?;
+	let master_key = header.decrypt_master_key_from_prehashed(vec![user_key])?;
+
+	// anything after the last complete block is discarded
+	let blocks_written = u64::from(progress.blocks_written);
+	let offset = writer.stream_position()? + blocks_written * (BLOCK_SIZE + AEAD_TAG_SIZE) as u64;
+	writer.set_len(offset)?;
+	writer.seek(SeekFrom::Start(offset))?;
+
+	let mut reader = File::open(source)?;
+	reader.seek(SeekFrom::Start(blocks_written * BLOCK_SIZE as u64))?;
+
+	let mut encryptor = StreamEncryption::new(master_key, &header.nonce, header.algorithm)?;
+	encryptor.seek_to_block(progress.blocks_written);
+
+	for _ in 0..CHECKPOINT_BLOCKS {
+		if encryptor.encrypt_block(&mut reader, &mut writer, &aad)? {
+			writer.sync_all()?;
+			return Ok(true);
+		}
+
+		progress.blocks_written += 1;
+
+		throttle.read(BLOCK_SIZE as u64).await;
+		throttle.write((BLOCK_SIZE + AEAD_TAG_SIZE) as u64).await;
+		tokio::task::yield_now().await;
+	}
+
+	writer.sync_data()?;
+
+	Ok(false)
+}
+
+/// This reads an encrypted file back from the disk, and decrypts it without writing the plaintext anywhere.
+fn verify_encrypted_file(path: &Path, user_key: Protected<[u8; KEY_LEN]>) -> Result<(), JobError> {
+	let mut reader = File::open(path)?;
+	let (header, aad) = FileHeader::deserialize(&mut reader)?;
+
+	let master_key = header.decrypt_master_key_from_prehashed(vec![user_key])?;
+	let decryptor = StreamDecryption::new(master_key, &header.nonce, header.algorithm)?;
+
+	decryptor.decrypt_streams(&mut reader, io::sink(), &aad)?;
+
+	Ok(())
+}
+
+fn erase_file(path: &Path) -> Result<(), JobError> {
+	let mut file = OpenOptions::new().write(true).open(path)?;
+	let size = usize::try_from(file.metadata()?.len()).unwrap_or(usize::MAX);
+
+	erase(&mut file, size, DEFAULT_ERASE_PASSES)?;
+	file.sync_all()?;
+	drop(file);
+
+	std::fs::remove_file(path)?;
+
+	Ok(())
 }
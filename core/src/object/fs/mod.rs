@@ -1,2 +1,549 @@
+use std::{
+	collections::HashMap,
+	io,
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use sd_crypto::{
+	header::file::FileHeader,
+	keys::keymanager::KeyManager,
+	primitives::{AEAD_TAG_SIZE, BLOCK_SIZE, KEY_LEN},
+	Protected,
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+	invalidate_query,
+	job::{JobError, WorkerContext},
+	location::LocationError,
+	prisma::{file_path, key, location, object, PrismaClient},
+	volume::available_space,
+};
+
+pub mod archive;
+pub mod attributes;
+pub mod audit;
+pub mod copy;
 pub mod decrypt;
+pub mod decrypt_header;
+pub mod delete;
+pub mod duplicates;
 pub mod encrypt;
+pub mod r#move;
+pub mod pipeline;
+pub mod policy;
+pub mod rekey;
+pub mod sparse;
+pub mod stream;
+pub mod temp;
+pub mod trash;
+pub mod vault;
+pub mod verify;
+
+/// This is something for a filesystem job to operate on, either by the object or the specific file path.
+///
+/// If an object has multiple paths within the location, the first one is used.
+#[derive(Serialize, Deserialize, Debug, Type, Hash, Clone)]
+pub enum ObjectTarget {
+	Object(i32),
+	FilePath(i32),
+}
+
+/// A file that a bulk job couldn't process. These don't fail the job, so one unreadable file doesn't stop a whole directory.
+///
+/// The file path's ID is kept, so the files that failed can be retried on their own.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileFailure {
+	pub path: String,
+	#[serde(default)]
+	pub file_path_id: Option<i32>,
+	pub error: String,
+}
+
+/// This checks a file path against a job's `only_file_paths`, which limits the job to some of the files within its targets (e.g. when retrying the ones that failed).
+fn is_selected(only_file_paths: &Option<Vec<i32>>, file_path_id: i32) -> bool {
+	only_file_paths
+		.as_ref()
+		.map_or(true, |ids| ids.contains(&file_path_id))
+}
+
+/// This builds the `only_file_paths` for retrying a job's failed files, or returns `None` if there's nothing to retry.
+fn failed_file_paths(failures: &[FileFailure]) -> Option<Vec<i32>> {
+	let ids = failures
+		.iter()
+		.filter_map(|failure| failure.file_path_id)
+		.collect::<Vec<_>>();
+
+	(!ids.is_empty()).then_some(ids)
+}
+
+/// This decides what a job does when an output it's about to write already exists.
+#[derive(Serialize, Deserialize, Debug, Type, Hash, Clone, Copy, Default)]
+pub enum ConflictStrategy {
+	/// the file is skipped, and the existing output is left alone
+	Skip,
+	Overwrite,
+	/// the output is written alongside the existing one, with a numbered suffix (e.g. `file (1).txt`)
+	Rename,
+	/// the file fails, which doesn't stop the rest of the job
+	#[default]
+	Error,
+}
+
+/// This is what was done about an existing output, which is recorded within the job's report.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ConflictDecision {
+	Skipped,
+	Overwritten,
+	Renamed(PathBuf),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OutputConflict {
+	pub path: String,
+	pub output_path: PathBuf,
+	pub decision: ConflictDecision,
+}
+
+/// This is where a job should write an output, once any conflict with an existing file has been resolved.
+pub(super) struct ResolvedOutput {
+	pub path: PathBuf,
+	pub overwrite: bool,
+}
+
+/// The number of suffixes that are tried when renaming an output, before giving up.
+const MAX_RENAME_ATTEMPTS: usize = 1000;
+
+/// This checks whether an output already exists, and resolves the conflict using the job's strategy.
+///
+/// It returns `None` if the file should be skipped, alongside the decision that was made (if there was a conflict).
+pub(super) fn resolve_conflict(
+	path: PathBuf,
+	strategy: ConflictStrategy,
+) -> Result<(Option<ResolvedOutput>, Option<ConflictDecision>), JobError> {
+	if !path.exists() {
+		return Ok((
+			Some(ResolvedOutput {
+				path,
+				overwrite: false,
+			}),
+			None,
+		));
+	}
+
+	match strategy {
+		ConflictStrategy::Skip => Ok((None, Some(ConflictDecision::Skipped))),
+		ConflictStrategy::Overwrite => Ok((
+			Some(ResolvedOutput {
+				path,
+				overwrite: true,
+			}),
+			Some(ConflictDecision::Overwritten),
+		)),
+		ConflictStrategy::Rename => {
+			let renamed = available_path(&path)?;
+
+			Ok((
+				Some(ResolvedOutput {
+					path: renamed.clone(),
+					overwrite: false,
+				}),
+				Some(ConflictDecision::Renamed(renamed)),
+			))
+		}
+		ConflictStrategy::Error => Err(io::Error::from(io::ErrorKind::AlreadyExists).into()),
+	}
+}
+
+/// This finds the first path with a numbered suffix that doesn't exist yet.
+///
+/// The suffix goes before every extension, so `file.txt.sdenc` becomes `file (1).txt.sdenc` (and is still decrypted to a `.txt`).
+fn available_path(path: &Path) -> Result<PathBuf, JobError> {
+	let file_name = path
+		.file_name()
+		.and_then(|name| name.to_str())
+		.unwrap_or_default();
+
+	// dotfiles (e.g. `.bashrc`) don't have an extension
+	let (stem, extensions) = match file_name.split_once('.') {
+		Some((stem, extensions)) if !stem.is_empty() => (stem, Some(extensions)),
+		_ => (file_name, None),
+	};
+
+	for i in 1..=MAX_RENAME_ATTEMPTS {
+		let candidate = path.with_file_name(match extensions {
+			Some(extensions) => format!("{stem} ({i}).{extensions}"),
+			None => format!("{stem} ({i})"),
+		});
+
+		if !candidate.exists() {
+			return Ok(candidate);
+		}
+	}
+
+	Err(io::Error::from(io::ErrorKind::AlreadyExists).into())
+}
+
+/// This resolves each target to its file path within the location, alongside the location's local path.
+///
+/// Any targets that can't be found are skipped (with a warning), so one stale ID doesn't fail the whole job.
+pub(super) async fn resolve_targets(
+	db: &PrismaClient,
+	location_id: i32,
+	targets: &[ObjectTarget],
+) -> Result<(PathBuf, Vec<file_path::Data>), JobError> {
+	let location = db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	let root_path = location
+		.local_path
+		.as_ref()
+		.map(PathBuf::from)
+		.ok_or(LocationError::MissingLocalPath(location.id))?;
+
+	let mut paths = Vec::with_capacity(targets.len());
+
+	for target in targets {
+		let item = match target {
+			ObjectTarget::Object(object_id) => {
+				db.file_path()
+					.find_first(vec![
+						file_path::location_id::equals(location.id),
+						file_path::object_id::equals(Some(*object_id)),
+					])
+					.exec()
+					.await?
+			}
+			ObjectTarget::FilePath(path_id) => {
+				db.file_path()
+					.find_unique(file_path::location_id_id(location.id, *path_id))
+					.exec()
+					.await?
+			}
+		};
+
+		match item {
+			Some(item) => paths.push(item),
+			None => warn!("skipping {:?} as it can't be found", target),
+		}
+	}
+
+	Ok((root_path, paths))
+}
+
+/// This is a directory within a location, which a job copies or moves its targets into.
+#[derive(Serialize, Deserialize, Debug)]
+pub(super) struct Destination {
+	pub location_id: i32,
+	pub path: PathBuf,
+	/// the directory's path within the location, which is empty for the location's root
+	pub materialized_path: String,
+}
+
+impl Destination {
+	/// This returns the path within the location of something that's directly within the destination.
+	pub fn child(&self, name: &str) -> String {
+		format!("{}{}", self.materialized_path, name)
+	}
+}
+
+/// This finds a directory within a location, or the location's root if there isn't one.
+pub(super) async fn resolve_destination(
+	db: &PrismaClient,
+	location_id: i32,
+	directory_id: Option<i32>,
+) -> Result<Destination, JobError> {
+	let location = db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	let root_path = location
+		.local_path
+		.as_ref()
+		.map(PathBuf::from)
+		.ok_or(LocationError::MissingLocalPath(location.id))?;
+
+	let materialized_path = match directory_id {
+		Some(directory_id) => db
+			.file_path()
+			.find_unique(file_path::location_id_id(location_id, directory_id))
+			.exec()
+			.await?
+			.filter(|file_path| file_path.is_dir)
+			.ok_or(JobError::DestinationNotFound {
+				location_id,
+				directory_id,
+			})?
+			.materialized_path
+			.trim_start_matches('/')
+			.to_string(),
+		None => String::new(),
+	};
+
+	Ok(Destination {
+		location_id,
+		path: match materialized_path.as_str() {
+			"" => root_path,
+			materialized_path => root_path.join(materialized_path),
+		},
+		materialized_path,
+	})
+}
+
+/// This decides where a target is written within a destination, once any conflict with an existing file has been resolved.
+///
+/// It returns `None` if the target is skipped, and records what was done about a conflict.
+pub(super) fn plan_output(
+	source: &Path,
+	destination: &Destination,
+	file_name: &str,
+	strategy: ConflictStrategy,
+	conflicts: &mut Vec<OutputConflict>,
+) -> Result<Option<ResolvedOutput>, JobError> {
+	let output_path = destination.path.join(file_name);
+
+	// a directory can't be copied (or moved) into itself, as it'd never finish
+	if output_path != source && destination.path.starts_with(source) {
+		return Err(JobError::DestinationWithinSource(source.to_path_buf()));
+	}
+
+	// overwriting a file with itself would truncate it before it's read, so there's nothing to do
+	let (output, decision) =
+		if output_path == source && matches!(strategy, ConflictStrategy::Overwrite) {
+			(None, Some(ConflictDecision::Skipped))
+		} else {
+			resolve_conflict(output_path.clone(), strategy)?
+		};
+
+	if let Some(decision) = decision {
+		conflicts.push(OutputConflict {
+			path: source.to_string_lossy().to_string(),
+			output_path,
+			decision,
+		});
+	}
+
+	Ok(output)
+}
+
+/// This is what's found within a directory that's being copied or moved.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum EntryKind {
+	Directory,
+	File,
+	/// links are recreated rather than followed, as following them could copy far more than the directory (or loop forever)
+	Symlink,
+}
+
+#[derive(Debug)]
+pub(super) struct DirectoryEntry {
+	/// the path relative to the directory
+	pub path: PathBuf,
+	pub kind: EntryKind,
+	pub size: u64,
+}
+
+/// This lists everything within a directory, with each directory before its contents.
+pub(super) fn walk_directory(root: &Path) -> Result<Vec<DirectoryEntry>, io::Error> {
+	let mut entries = Vec::new();
+	let mut to_walk = vec![PathBuf::new()];
+
+	while let Some(relative_dir) = to_walk.pop() {
+		let mut children =
+			std::fs::read_dir(root.join(&relative_dir))?.collect::<Result<Vec<_>, _>>()?;
+		children.sort_by_key(|child| child.file_name());
+
+		for child in children {
+			let path = relative_dir.join(child.file_name());
+			let metadata = child.path().symlink_metadata()?;
+
+			let kind = if metadata.is_symlink() {
+				EntryKind::Symlink
+			} else if metadata.is_dir() {
+				to_walk.push(path.clone());
+				EntryKind::Directory
+			} else {
+				EntryKind::File
+			};
+
+			entries.push(DirectoryEntry {
+				path,
+				kind,
+				size: if kind == EntryKind::File {
+					metadata.len()
+				} else {
+					0
+				},
+			});
+		}
+	}
+
+	Ok(entries)
+}
+
+/// This returns whether a file path is its location's root, which jobs mustn't move or delete (as that's what the location is).
+fn is_location_root(file_path: &file_path::Data) -> bool {
+	file_path.materialized_path.trim_matches('/').is_empty()
+}
+
+/// This tries each mounted key against a header's keyslots, and returns the master key alongside the key that unlocked it (and its hashed key).
+fn unlock_with_mounted_keys(
+	key_manager: &KeyManager,
+	header: &FileHeader,
+) -> Option<(Protected<[u8; KEY_LEN]>, Uuid, Protected<[u8; KEY_LEN]>)> {
+	key_manager
+		.get_mounted_uuids()
+		.into_iter()
+		.find_map(|uuid| {
+			let hashed_key = key_manager.access_keymount(uuid).ok()?.hashed_key;
+
+			header
+				.decrypt_master_key_from_prehashed(vec![hashed_key.clone()])
+				.ok()
+				.map(|master_key| (master_key, uuid, hashed_key))
+		})
+}
+
+/// This is the size of a file's encrypted contents (excluding its header), as every block has its own tag.
+fn encrypted_size(size: u64) -> u64 {
+	size + (size / BLOCK_SIZE as u64 + 1) * AEAD_TAG_SIZE as u64
+}
+
+/// This checks that every volume a job writes to has enough space for its outputs, so it fails before starting rather than part of the way through.
+///
+/// Each output is a path that's written to (or within), alongside an estimate of how much is written there.
+/// Outputs on volumes that can't be found (e.g. some network shares) aren't checked.
+fn check_free_space(outputs: impl IntoIterator<Item = (PathBuf, u64)>) -> Result<(), JobError> {
+	let volumes = available_space();
+	let mut required = HashMap::<&Path, u64>::new();
+
+	for (path, size) in outputs {
+		if let Some(mount_point) = mount_point(&volumes, &path) {
+			*required.entry(mount_point).or_default() += size;
+		}
+	}
+
+	for (mount_point, available) in &volumes {
+		if let Some(&required) = required.get(mount_point.as_path()) {
+			if required > *available {
+				return Err(JobError::InsufficientSpace {
+					mount_point: mount_point.clone(),
+					required,
+					available: *available,
+				});
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// This returns the mount point of the volume that a path is on.
+fn mount_point<'a>(volumes: &'a [(PathBuf, u64)], path: &Path) -> Option<&'a Path> {
+	// volumes can be mounted within each other, so the closest one is used
+	volumes
+		.iter()
+		.filter(|(mount_point, _)| path.starts_with(mount_point))
+		.max_by_key(|(mount_point, _)| mount_point.components().count())
+		.map(|(mount_point, _)| mount_point.as_path())
+}
+
+/// This removes an output that was only partially written.
+///
+/// Outputs are recorded before they're created, so it's fine if it doesn't exist (e.g. if the job was interrupted before creating it).
+fn remove_partial_output(path: &Path) -> Result<(), JobError> {
+	match std::fs::remove_file(path) {
+		Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+		_ => Ok(()),
+	}
+}
+
+/// `SourceStamp` is the size and modification time of a job's source file, which is recorded alongside its progress.
+///
+/// A source that's changed since then can't be continued from part of the way through, as what was already written no longer matches it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceStamp {
+	size: u64,
+	modified: Option<DateTime<Utc>>,
+}
+
+impl SourceStamp {
+	fn of(path: &Path) -> Result<Self, JobError> {
+		let metadata = std::fs::metadata(path)?;
+
+		Ok(Self {
+			size: metadata.len(),
+			modified: metadata.modified().ok().map(Into::into),
+		})
+	}
+
+	/// This checks that the file at `path` hasn't changed since the stamp was taken (a file that can't be read counts as changed).
+	fn matches(&self, path: &Path) -> bool {
+		Self::of(path).map_or(false, |stamp| stamp == *self)
+	}
+}
+
+/// This records which key an encrypted file is encrypted with, on both its object and its file path.
+///
+/// Both rows are updated within a single transaction, so they can't disagree. This must only be called once the file's header has been fully written,
+/// and jobs undo their change to the file if it fails, so the database never claims a file is encrypted with a key that it isn't.
+async fn record_key_association(
+	ctx: &WorkerContext,
+	key_uuid: Uuid,
+	object_id: Option<i32>,
+	file_path: Option<(i32, i32)>, // (location ID, file path ID)
+) -> Result<(), JobError> {
+	// memory-only keys aren't in the database, so there's nothing to associate
+	if ctx
+		.library_ctx
+		.key_manager
+		.access_keystore(key_uuid)?
+		.memory_only
+	{
+		return Ok(());
+	}
+
+	let db = &ctx.library_ctx.db;
+
+	let object_updates = object_id
+		.into_iter()
+		.map(|object_id| {
+			db.object().update(
+				object::id::equals(object_id),
+				vec![object::key::connect(key::uuid::equals(
+					key_uuid.to_string(),
+				))],
+			)
+		})
+		.collect::<Vec<_>>();
+
+	let file_path_updates = file_path
+		.into_iter()
+		.map(|(location_id, file_path_id)| {
+			db.file_path().update(
+				file_path::location_id_id(location_id, file_path_id),
+				vec![file_path::key::connect(key::uuid::equals(
+					key_uuid.to_string(),
+				))],
+			)
+		})
+		.collect::<Vec<_>>();
+
+	db._batch((object_updates, file_path_updates)).await?;
+
+	invalidate_query!(ctx.library_ctx, "locations.getExplorerData");
+	invalidate_query!(ctx.library_ctx, "savedSearches.getExplorerData");
+
+	Ok(())
+}
@@ -0,0 +1,349 @@
+use std::{
+	collections::VecDeque,
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::{info, warn};
+
+use crate::{
+	invalidate_query,
+	job::{
+		JobError, JobLogLevel, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+	},
+	library::LibraryContext,
+	location::file_path_helper::move_file_path,
+	volume::available_space,
+};
+
+use super::{
+	check_free_space,
+	copy::{copy_file, copy_symlink, remove_partial_copy},
+	is_location_root, mount_point, plan_output, resolve_destination, resolve_targets,
+	walk_directory, ConflictStrategy, Destination, EntryKind, FileFailure, ObjectTarget,
+	OutputConflict,
+};
+
+pub const FILE_MOVER_JOB_NAME: &str = "file_mover";
+
+/// `ERROR_NOT_SAME_DEVICE` on Windows, and `EXDEV` everywhere else.
+#[cfg(windows)]
+const CROSS_DEVICE_ERROR: i32 = 17;
+#[cfg(not(windows))]
+const CROSS_DEVICE_ERROR: i32 = 18;
+
+// The mover moves (or renames) files and directories into a directory within a location, which may be within another location.
+// Moves within a volume are a single rename, while moves across volumes are copied and then deleted from where they were.
+// The moved file paths keep their IDs and objects, so nothing has to be indexed or identified again.
+pub struct FileMoverJob;
+
+#[derive(Serialize, Deserialize, Debug, Type, Hash, Clone)]
+pub struct FileMoverJobInit {
+	pub location_id: i32,
+	pub targets: Vec<ObjectTarget>,
+	pub target_location_id: i32,
+	pub target_directory_id: Option<i32>, // if this isn't set, the targets are moved into the root of the location
+	#[serde(default)]
+	pub new_name: Option<String>, // if this is set, the (only) target is renamed to it
+	#[serde(default)]
+	pub on_conflict: ConflictStrategy, // what's done when a target already exists within the destination
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileMoverJobState {
+	source_root: PathBuf,
+	destination: Destination,
+	// the copy that's currently being written (for moves across volumes), which is incomplete if the job was paused (or interrupted) part of the way through it
+	current_output: Option<PathBuf>,
+	total_bytes: u64,
+	bytes_copied: u64,
+	report: FileMoverReport,
+}
+
+/// This is the summary of a mover job, which is returned once it's finished.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FileMoverReport {
+	moved: usize,
+	/// targets that already existed within the destination, and what was done about it
+	conflicts: Vec<OutputConflict>,
+	failures: Vec<FileFailure>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileMoverJobStep {
+	/// the path within the source location
+	source: String,
+	file_path_id: i32,
+	file_name: String,
+	is_dir: bool,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for FileMoverJob {
+	type Data = FileMoverJobState;
+	type Init = FileMoverJobInit;
+	type Step = FileMoverJobStep;
+
+	fn name(&self) -> &'static str {
+		FILE_MOVER_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let db = &ctx.library_ctx.db;
+
+		let (source_root, items) =
+			resolve_targets(db, state.init.location_id, &state.init.targets).await?;
+		let destination = resolve_destination(
+			db,
+			state.init.target_location_id,
+			state.init.target_directory_id,
+		)
+		.await?;
+
+		let mut steps = VecDeque::with_capacity(items.len());
+
+		for item in items.into_iter().filter(|item| !is_location_root(item)) {
+			let source = item.materialized_path.trim_end_matches('/').to_string();
+
+			let file_name = match &state.init.new_name {
+				Some(new_name) => Some(new_name.clone()),
+				None => Path::new(&source)
+					.file_name()
+					.and_then(|name| name.to_str())
+					.map(ToString::to_string),
+			};
+
+			match file_name {
+				Some(file_name) => steps.push_back(FileMoverJobStep {
+					source,
+					file_path_id: item.id,
+					file_name,
+					is_dir: item.is_dir,
+				}),
+				None => warn!("mover is skipping {} as it doesn't have a name", source),
+			}
+		}
+
+		// only moves across volumes need any space, as they're copied
+		let volumes = available_space();
+		let destination_volume = mount_point(&volumes, &destination.path);
+		let mut total_bytes = 0;
+
+		for step in &steps {
+			let path = source_root.join(&step.source);
+
+			if mount_point(&volumes, &path) != destination_volume {
+				total_bytes += if step.is_dir {
+					walk_directory(&path)?
+						.into_iter()
+						.map(|entry| entry.size)
+						.sum::<u64>()
+				} else {
+					fs::metadata(&path).map_or(0, |metadata| metadata.len())
+				};
+			}
+		}
+
+		check_free_space([(destination.path.clone(), total_bytes)])?;
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(steps.len()),
+			JobReportUpdate::TotalBytes(total_bytes),
+		]);
+
+		state.data = Some(FileMoverJobState {
+			source_root,
+			destination,
+			current_output: None,
+			total_bytes,
+			bytes_copied: 0,
+			report: FileMoverReport::default(),
+		});
+		state.steps = steps;
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		// a paused (or interrupted) move across volumes is copied again from the start, as the original is still there
+		if let Some(output) = data.current_output.take() {
+			remove_partial_copy(&output)?;
+		}
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Moving {}",
+			step.source
+		))]);
+
+		match move_entry(&ctx, &state.init, step, data).await {
+			Ok(true) => data.report.moved += 1,
+			Ok(false) => {}
+			Err(e) => {
+				if let Some(output) = data.current_output.take() {
+					remove_partial_copy(&output)?;
+				}
+
+				warn!("moving failed for {}: {:#?}", step.source, e);
+				ctx.log(
+					JobLogLevel::Warning,
+					format!("Moving failed: {e}"),
+					Some(step.source.clone()),
+				);
+				data.report.failures.push(FileFailure {
+					path: step.source.clone(),
+					file_path_id: Some(step.file_path_id),
+					error: e.to_string(),
+				});
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!("Finalizing mover job: {:#?}", data.report);
+
+		invalidate_query!(ctx.library_ctx, "locations.getExplorerData");
+		invalidate_query!(ctx.library_ctx, "savedSearches.getExplorerData");
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+
+	async fn cancel(
+		&self,
+		_ctx: &LibraryContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		if let Some(output) = state
+			.data
+			.as_mut()
+			.and_then(|data| data.current_output.take())
+		{
+			remove_partial_copy(&output)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// This moves a single target, and returns whether it was moved (rather than skipped).
+///
+/// The database is only updated once the target has been moved, so it always matches where the target actually is.
+async fn move_entry(
+	ctx: &WorkerContext,
+	init: &FileMoverJobInit,
+	step: &FileMoverJobStep,
+	data: &mut FileMoverJobState,
+) -> Result<bool, JobError> {
+	let source_path = data.source_root.join(&step.source);
+
+	// conflicts are resolved now rather than when the job started, as earlier targets may have been moved into the destination since
+	let output = match plan_output(
+		&source_path,
+		&data.destination,
+		&step.file_name,
+		init.on_conflict,
+		&mut data.report.conflicts,
+	)? {
+		Some(output) => output,
+		None => return Ok(false),
+	};
+
+	// directories would be merged rather than replaced, which isn't what overwriting means
+	if output.overwrite && step.is_dir {
+		return Err(io::Error::from(io::ErrorKind::AlreadyExists).into());
+	}
+
+	match fs::rename(&source_path, &output.path) {
+		Ok(()) => {}
+		Err(e) if e.raw_os_error() == Some(CROSS_DEVICE_ERROR) => {
+			data.current_output = Some(output.path.clone());
+			copy_across_volumes(ctx, &source_path, &output.path, data).await?;
+			data.current_output = None;
+
+			if step.is_dir {
+				fs::remove_dir_all(&source_path)?;
+			} else {
+				fs::remove_file(&source_path)?;
+			}
+		}
+		Err(e) => return Err(e.into()),
+	}
+
+	let output_name = output
+		.path
+		.file_name()
+		.and_then(|name| name.to_str())
+		.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+	move_file_path(
+		&ctx.library_ctx,
+		init.location_id,
+		&step.source,
+		data.destination.location_id,
+		&data.destination.child(output_name),
+		step.is_dir,
+	)
+	.await?;
+
+	Ok(true)
+}
+
+/// This copies a file (or a directory and everything within it) to another volume, as it can't be renamed there.
+async fn copy_across_volumes(
+	ctx: &WorkerContext,
+	source: &Path,
+	output: &Path,
+	data: &mut FileMoverJobState,
+) -> Result<(), JobError> {
+	let metadata = source.symlink_metadata()?;
+
+	// this is sent with every move, as a resumed job doesn't run `init` again
+	ctx.progress(vec![JobReportUpdate::TotalBytes(data.total_bytes)]);
+
+	if metadata.is_symlink() {
+		return Ok(copy_symlink(source, output)?);
+	}
+
+	if !metadata.is_dir() {
+		data.bytes_copied += copy_file(ctx, source, output, data.bytes_copied).await?;
+		return Ok(());
+	}
+
+	fs::create_dir(output)?;
+
+	for entry in walk_directory(source)? {
+		let (source, output) = (source.join(&entry.path), output.join(&entry.path));
+
+		match entry.kind {
+			EntryKind::Directory => fs::create_dir(&output)?,
+			EntryKind::File => {
+				data.bytes_copied += copy_file(ctx, &source, &output, data.bytes_copied).await?;
+			}
+			EntryKind::Symlink => copy_symlink(&source, &output)?,
+		}
+	}
+
+	Ok(())
+}
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use tokio::{
+	sync::{mpsc, Mutex},
+	task::JoinHandle,
+};
+use tracing::error;
+
+/// WorkerPool is a set of workers that take items from a shared queue, and pass what they make of them on to the next queue.
+///
+/// Pools are chained together through bounded queues, so each stage only runs as far ahead of the next as its queue allows.
+/// This lets jobs that process lots of files overlap reading, encrypting and writing them, while keeping a bound on how much is held in memory.
+///
+/// The workers are aborted once the pool is dropped, e.g. when the job that's using it is paused.
+pub struct WorkerPool {
+	handles: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+	/// This spawns `workers` workers, which each run `work` on items from `input` (on a blocking thread) and send the output to the returned queue.
+	///
+	/// The returned queue is closed once `input` has been closed and every item has been processed.
+	pub fn spawn<I, O, F>(
+		workers: usize,
+		capacity: usize,
+		input: mpsc::Receiver<I>,
+		work: F,
+	) -> (mpsc::Receiver<O>, Self)
+	where
+		I: Send + 'static,
+		O: Send + 'static,
+		F: Fn(I) -> O + Send + Sync + 'static,
+	{
+		let (output_tx, output_rx) = mpsc::channel(capacity);
+		let input = Arc::new(Mutex::new(input));
+		let work = Arc::new(work);
+
+		let handles = (0..workers.max(1))
+			.map(|_| {
+				let input = Arc::clone(&input);
+				let output_tx = output_tx.clone();
+				let work = Arc::clone(&work);
+
+				tokio::spawn(async move {
+					loop {
+						// the lock is only held while waiting for an item, so the workers take turns receiving them
+						let item = match input.lock().await.recv().await {
+							Some(item) => item,
+							None => break,
+						};
+
+						let work = Arc::clone(&work);
+						let output = match tokio::task::spawn_blocking(move || work(item)).await {
+							Ok(output) => output,
+							Err(e) => {
+								error!("worker pool task failed: {:#?}", e);
+								continue;
+							}
+						};
+
+						if output_tx.send(output).await.is_err() {
+							break;
+						}
+					}
+				})
+			})
+			.collect();
+
+		(output_rx, Self { handles })
+	}
+}
+
+impl Drop for WorkerPool {
+	fn drop(&mut self) {
+		for handle in &self.handles {
+			handle.abort();
+		}
+	}
+}
@@ -0,0 +1,338 @@
+use std::{collections::HashSet, path::Path};
+
+use chrono::Utc;
+use globset::Glob;
+use int_enum::IntEnum;
+use prisma_client_rust::QueryError;
+use rspc::{ErrorCode, Type};
+use sd_crypto::{crypto::stream::Algorithm, primitives::to_array};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+	invalidate_query,
+	job::Job,
+	library::LibraryContext,
+	prisma::{encryption_rule, encryption_rule_activity, file_path, key, location},
+};
+
+use super::{
+	encrypt::{FileEncryptorJob, FileEncryptorJobInit},
+	ConflictStrategy, ObjectTarget,
+};
+
+encryption_rule::include!(encryption_rule_with_key {
+	key: select { uuid }
+});
+
+#[derive(Error, Debug)]
+pub enum EncryptionRuleError {
+	#[error("Encryption rule not found: <id={0}>")]
+	RuleNotFound(i32),
+	#[error("Key not found: <uuid={0}>")]
+	KeyNotFound(Uuid),
+	#[error("Glob builder error: {0}")]
+	GlobBuilderError(#[from] globset::Error),
+	#[error("Database error: {0}")]
+	DatabaseError(#[from] QueryError),
+	#[error("Crypto error: {0}")]
+	CryptoError(#[from] sd_crypto::Error),
+	#[error("Invalid UUID: {0}")]
+	UuidError(#[from] uuid::Error),
+}
+
+impl From<EncryptionRuleError> for rspc::Error {
+	fn from(err: EncryptionRuleError) -> Self {
+		match err {
+			EncryptionRuleError::RuleNotFound(_) | EncryptionRuleError::KeyNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			EncryptionRuleError::GlobBuilderError(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// `EncryptionRuleCreateArgs` is the argument received from the client using rspc to create a new encryption rule.
+///
+/// The glob is matched against paths relative to their location, e.g. `Taxes/**` or `**/*.kdbx`.
+/// In case of doubt about globs, consult <https://docs.rs/globset/latest/globset/#syntax>
+#[derive(Type, Deserialize)]
+pub struct EncryptionRuleCreateArgs {
+	pub name: String,
+	pub glob: String,
+	pub location_id: Option<i32>, // if this isn't set, the rule applies to every location
+	pub key_uuid: Uuid,
+	pub algorithm: Algorithm,
+	pub erase_original: bool,
+}
+
+impl EncryptionRuleCreateArgs {
+	pub async fn create(
+		self,
+		library: &LibraryContext,
+	) -> Result<encryption_rule::Data, EncryptionRuleError> {
+		// the glob is validated up front, so a rule can't be stored that'll never match
+		Glob::new(&self.glob)?;
+
+		let key = library
+			.db
+			.key()
+			.find_unique(key::uuid::equals(self.key_uuid.to_string()))
+			.exec()
+			.await?
+			.ok_or(EncryptionRuleError::KeyNotFound(self.key_uuid))?;
+
+		let mut params = vec![encryption_rule::erase_original::set(self.erase_original)];
+
+		if let Some(location_id) = self.location_id {
+			params.push(encryption_rule::location::connect(location::id::equals(
+				location_id,
+			)));
+		}
+
+		library
+			.db
+			.encryption_rule()
+			.create(
+				self.name,
+				self.glob,
+				self.algorithm.serialize().to_vec(),
+				key::id::equals(key.id),
+				params,
+			)
+			.exec()
+			.await
+			.map_err(Into::into)
+	}
+}
+
+/// `EncryptionRuleUpdateArgs` is the argument received from the client using rspc to update an encryption rule.
+///
+/// Anything that isn't set is left as-is.
+#[derive(Type, Deserialize)]
+pub struct EncryptionRuleUpdateArgs {
+	pub id: i32,
+	pub name: Option<String>,
+	pub glob: Option<String>,
+	pub erase_original: Option<bool>,
+}
+
+impl EncryptionRuleUpdateArgs {
+	pub async fn update(
+		self,
+		library: &LibraryContext,
+	) -> Result<encryption_rule::Data, EncryptionRuleError> {
+		if library
+			.db
+			.encryption_rule()
+			.find_unique(encryption_rule::id::equals(self.id))
+			.exec()
+			.await?
+			.is_none()
+		{
+			return Err(EncryptionRuleError::RuleNotFound(self.id));
+		}
+
+		let mut params = vec![encryption_rule::date_modified::set(Utc::now().into())];
+
+		if let Some(name) = self.name {
+			params.push(encryption_rule::name::set(name));
+		}
+
+		if let Some(glob) = self.glob {
+			Glob::new(&glob)?;
+			params.push(encryption_rule::glob::set(glob));
+		}
+
+		if let Some(erase_original) = self.erase_original {
+			params.push(encryption_rule::erase_original::set(erase_original));
+		}
+
+		library
+			.db
+			.encryption_rule()
+			.update(encryption_rule::id::equals(self.id), params)
+			.exec()
+			.await
+			.map_err(Into::into)
+	}
+}
+
+/// EncryptionRuleOutcome is what happened to a file that matched an encryption rule, and is recorded within the rule's activity log.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, Eq, PartialEq, IntEnum)]
+pub enum EncryptionRuleOutcome {
+	/// the file was encrypted (and its original was erased, if the rule says to)
+	Encrypted = 0,
+	Failed = 1,
+	/// the rule's key wasn't mounted, so the file was left unencrypted
+	KeyNotMounted = 2,
+}
+
+/// A file that an encryption rule processed, which is waiting to be recorded within the activity log.
+pub(crate) struct RuleActivity {
+	pub path: String,
+	pub outcome: EncryptionRuleOutcome,
+	pub error: Option<String>,
+}
+
+/// This records files that an encryption rule has processed within its activity log.
+///
+/// Failures are only logged, as the activity log shouldn't prevent files from being encrypted.
+pub(crate) async fn record_rule_activity(
+	library: &LibraryContext,
+	rule_id: i32,
+	location_id: i32,
+	activity: Vec<RuleActivity>,
+) {
+	if activity.is_empty() {
+		return;
+	}
+
+	let creates = activity
+		.into_iter()
+		.map(|activity| {
+			library.db.encryption_rule_activity().create(
+				activity.path,
+				activity.outcome.int_value(),
+				location::id::equals(location_id),
+				vec![
+					encryption_rule_activity::rule::connect(encryption_rule::id::equals(rule_id)),
+					encryption_rule_activity::error::set(activity.error),
+				],
+			)
+		})
+		.collect::<Vec<_>>();
+
+	match library.db._batch(creates).await {
+		Ok(_) => invalidate_query!(library, "locations.encryption_rules.activity"),
+		Err(e) => warn!(
+			"Failed to record activity for encryption rule <id={}>: {:#?}",
+			rule_id, e
+		),
+	}
+}
+
+/// This dispatches encryption jobs for any of the given file paths that match the encryption rules for their location.
+///
+/// Files that are already encrypted (or have an encrypted copy alongside them) are skipped, so this can be called every time a location is indexed.
+/// A file is only encrypted by the first rule it matches, and rules whose key isn't mounted are skipped with a warning.
+pub(crate) async fn apply_encryption_rules(
+	library: &LibraryContext,
+	location_id: i32,
+	local_path: &Path,
+	file_paths: &[file_path::Data],
+) -> Result<(), EncryptionRuleError> {
+	let rules = library
+		.db
+		.encryption_rule()
+		.find_many(vec![])
+		.include(encryption_rule_with_key::include())
+		.exec()
+		.await?
+		.into_iter()
+		.filter(|rule| rule.location_id.map_or(true, |id| id == location_id));
+
+	let mut claimed = HashSet::new();
+
+	for rule in rules {
+		let matcher = match Glob::new(&rule.glob) {
+			Ok(glob) => glob.compile_matcher(),
+			Err(e) => {
+				warn!("Skipping encryption rule <id={}>: {:#?}", rule.id, e);
+				continue;
+			}
+		};
+
+		let targets = file_paths
+			.iter()
+			.filter(|file_path| {
+				!file_path.is_dir
+					&& file_path.extension.as_deref() != Some("sdenc")
+					&& !claimed.contains(&file_path.id)
+					&& matcher.is_match(&file_path.materialized_path)
+					&& needs_encrypting(&local_path.join(&file_path.materialized_path))
+			})
+			.collect::<Vec<_>>();
+
+		if targets.is_empty() {
+			continue;
+		}
+
+		claimed.extend(targets.iter().map(|file_path| file_path.id));
+
+		let key_uuid = Uuid::parse_str(&rule.key.uuid)?;
+
+		if !library.key_manager.keymount_contains(key_uuid) {
+			warn!(
+				"{} files matching encryption rule '{}' were left unencrypted, as its key isn't mounted",
+				targets.len(),
+				rule.name
+			);
+
+			record_rule_activity(
+				library,
+				rule.id,
+				location_id,
+				targets
+					.iter()
+					.map(|file_path| RuleActivity {
+						path: file_path.materialized_path.clone(),
+						outcome: EncryptionRuleOutcome::KeyNotMounted,
+						error: None,
+					})
+					.collect(),
+			)
+			.await;
+
+			continue;
+		}
+
+		info!(
+			"Encrypting {} files matching encryption rule '{}'",
+			targets.len(),
+			rule.name
+		);
+
+		library
+			.spawn_job(Job::new(
+				FileEncryptorJobInit {
+					location_id,
+					targets: targets
+						.iter()
+						.map(|file_path| ObjectTarget::FilePath(file_path.id))
+						.collect(),
+					key_uuid,
+					algorithm: Algorithm::deserialize(to_array(rule.algorithm)?)?,
+					metadata: true,
+					preview_media: true,
+					output_directory: None,
+					on_conflict: ConflictStrategy::Error,
+					erase_original: rule.erase_original,
+					encrypt_filename: false,
+					random_filename: false,
+					dry_run: false,
+					only_file_paths: None,
+					rule_id: Some(rule.id),
+					upload: None,
+				},
+				FileEncryptorJob {},
+			))
+			.await;
+	}
+
+	Ok(())
+}
+
+/// This checks that a file still exists, and that it hasn't already been encrypted (the output is written alongside it, e.g. `file.txt.sdenc`).
+fn needs_encrypting(path: &Path) -> bool {
+	let mut encrypted_name = path.file_name().unwrap_or_default().to_os_string();
+	encrypted_name.push(".sdenc");
+
+	path.exists() && !path.with_file_name(encrypted_name).exists()
+}
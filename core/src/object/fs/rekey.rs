@@ -0,0 +1,311 @@
+use std::{
+	collections::VecDeque,
+	fs::OpenOptions,
+	io::{Seek, SeekFrom, Write},
+	path::{Path, PathBuf},
+};
+
+use sd_crypto::{
+	header::{file::FileHeader, keyslot::Keyslot},
+	keys::keymanager::{MountSource, StoredKey},
+	primitives::{to_array, KEY_LEN, LATEST_KEYSLOT},
+	Protected,
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+	job::{
+		JobError, JobLogLevel, JobPriority, JobReportUpdate, JobResult, JobState, StatefulJob,
+		WorkerContext,
+	},
+	prisma::{file_path, key, object},
+};
+
+use super::record_key_association;
+
+pub const FILE_REKEYER_JOB_NAME: &str = "file_rekeyer";
+
+// The rekeyer is dispatched once a key has been rotated or revoked, and it re-wraps the keyslot of every file
+// that's associated with the old key. The file's contents (and its metadata/preview media) are encrypted with
+// the file's own master key, so only the keyslot needs replacing and the files are updated in place.
+pub struct FileRekeyerJob;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileRekeyerJobState {
+	report: FileRekeyerReport,
+}
+
+/// This is the summary of a rekeying job, which is returned once it's finished.
+///
+/// Unreachable files (e.g. within offline locations) still use the old key, so the job should be dispatched again once they're available.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FileRekeyerReport {
+	total_files: usize,
+	files_rekeyed: usize,
+	unreachable: Vec<String>,
+	errors: Vec<FileRekeyerError>,
+	/// files that were rekeyed are no longer associated with the old key, so retrying the job only picks up the rest
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	retry: Option<FileRekeyerJobInit>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileRekeyerError {
+	path: String,
+	error: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Type, Hash, Clone)]
+pub struct FileRekeyerJobInit {
+	pub key_uuid: Uuid,
+	/// if this is set, the key has been revoked and files are moved over to this key instead
+	pub replacement_key_uuid: Option<Uuid>,
+}
+
+file_path::select!(file_path_for_rekeyer {
+	id
+	location_id
+	materialized_path
+	location: select {
+		local_path
+	}
+	object_id
+});
+
+#[async_trait::async_trait]
+impl StatefulJob for FileRekeyerJob {
+	type Data = FileRekeyerJobState;
+	type Init = FileRekeyerJobInit;
+	type Step = file_path_for_rekeyer::Data;
+
+	fn name(&self) -> &'static str {
+		FILE_REKEYER_JOB_NAME
+	}
+
+	fn priority(&self) -> JobPriority {
+		JobPriority::Low
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let key_manager = &ctx.library_ctx.key_manager;
+
+		// keys are mounted up front, so they only need hashing once
+		// a revoked key is still in the keystore, but a rotated key's previous hashed keys are only held in memory
+		let mut required_keys = vec![state.init.key_uuid];
+		required_keys.extend(state.init.replacement_key_uuid);
+
+		for uuid in required_keys {
+			if !key_manager.keymount_contains(uuid) {
+				key_manager.mount_with_source(uuid, MountSource::Job)?;
+			}
+		}
+
+		state.steps = ctx
+			.library_ctx
+			.db
+			.file_path()
+			.find_many(vec![
+				file_path::is_dir::equals(false),
+				file_path::object::is(vec![object::key::is(vec![key::uuid::equals(
+					state.init.key_uuid.to_string(),
+				)])]),
+			])
+			.select(file_path_for_rekeyer::select())
+			.exec()
+			.await?
+			.into_iter()
+			.collect::<VecDeque<_>>();
+
+		state.data = Some(FileRekeyerJobState {
+			report: FileRekeyerReport {
+				total_files: state.steps.len(),
+				..Default::default()
+			},
+		});
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		let path = match &step.location.local_path {
+			Some(root_path) => PathBuf::from(root_path).join(&step.materialized_path),
+			None => PathBuf::new(),
+		};
+
+		// files within offline (or remote) locations can't be rekeyed until they're available again
+		if !path.is_file() {
+			warn!(
+				"rekeying is skipping {} as it isn't available locally",
+				step.materialized_path
+			);
+			ctx.log(
+				JobLogLevel::Warning,
+				"Skipped, as it isn't available locally",
+				Some(step.materialized_path.clone()),
+			);
+			data.report.unreachable.push(step.materialized_path.clone());
+		} else {
+			let key_manager = &ctx.library_ctx.key_manager;
+			let target_uuid = state
+				.init
+				.replacement_key_uuid
+				.unwrap_or(state.init.key_uuid);
+
+			let old_keys = match state.init.replacement_key_uuid {
+				Some(_) => vec![key_manager.access_keymount(state.init.key_uuid)?.hashed_key],
+				None => key_manager.retired_hashed_keys(state.init.key_uuid),
+			};
+
+			let new_key = key_manager.access_keymount(target_uuid)?.hashed_key;
+			let new_key_details = key_manager.access_keystore(target_uuid)?;
+
+			ctx.progress(vec![JobReportUpdate::Message(format!(
+				"Rekeying {}",
+				step.materialized_path
+			))]);
+
+			let mut result = rekey_file(&path, &old_keys, new_key.clone(), &new_key_details);
+
+			if let (Ok(()), Some(replacement)) = (&result, state.init.replacement_key_uuid) {
+				if let Err(e) = record_key_association(
+					&ctx,
+					replacement,
+					step.object_id,
+					Some((step.location_id, step.id)),
+				)
+				.await
+				{
+					// the file is moved back to the revoked key, as that's what the database still says it's encrypted with
+					let old_key_details = key_manager.access_keystore(state.init.key_uuid)?;
+					rekey_file(&path, &[new_key], old_keys[0].clone(), &old_key_details)?;
+
+					result = Err(e);
+				}
+			}
+
+			match result {
+				Ok(()) => data.report.files_rekeyed += 1,
+				Err(e) => {
+					warn!("rekeying failed for {}: {:#?}", step.materialized_path, e);
+					ctx.log(
+						JobLogLevel::Warning,
+						format!("Rekeying failed: {e}"),
+						Some(step.materialized_path.clone()),
+					);
+					data.report.errors.push(FileRekeyerError {
+						path: step.materialized_path.clone(),
+						error: e.to_string(),
+					});
+				}
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		// retired keys are kept while any files still depend on them, so the job can be dispatched again later
+		if state.init.replacement_key_uuid.is_none()
+			&& data.report.unreachable.is_empty()
+			&& data.report.errors.is_empty()
+		{
+			ctx.library_ctx
+				.key_manager
+				.clear_retired_keys(state.init.key_uuid);
+		}
+
+		if !data.report.unreachable.is_empty() || !data.report.errors.is_empty() {
+			data.report.retry = Some(state.init.clone());
+		}
+
+		info!("Finalizing rekeying job: {:#?}", data.report);
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
+
+/// This replaces the keyslot that one of `old_keys` unlocks with a keyslot for `new_key`, and writes the header back in place.
+///
+/// Keyslots are a fixed size, so the header's length (and the offset of the encrypted data) never changes.
+fn rekey_file(
+	path: &Path,
+	old_keys: &[Protected<[u8; KEY_LEN]>],
+	new_key: Protected<[u8; KEY_LEN]>,
+	new_key_details: &StoredKey,
+) -> Result<(), JobError> {
+	let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+	let (mut header, _) = FileHeader::deserialize(&mut file)?;
+	let header_len = file.stream_position()?;
+
+	let unlocked = header
+		.keyslots
+		.iter()
+		.enumerate()
+		.find_map(|(index, keyslot)| {
+			old_keys.iter().find_map(|key| {
+				keyslot
+					.decrypt_master_key_from_prehashed(key.clone())
+					.ok()
+					.map(|master_key| (index, master_key))
+			})
+		});
+
+	let (index, master_key) = match unlocked {
+		Some(unlocked) => unlocked,
+		// the file may have been rekeyed already (e.g. by an earlier run of this job)
+		None if header
+			.decrypt_master_key_from_prehashed(vec![new_key])
+			.is_ok() =>
+		{
+			return Ok(())
+		}
+		None => return Err(sd_crypto::Error::IncorrectPassword.into()),
+	};
+
+	let master_key = Protected::new(to_array(master_key.expose().clone())?);
+
+	header.keyslots[index] = Keyslot::new(
+		LATEST_KEYSLOT,
+		header.algorithm,
+		new_key_details.hashing_algorithm,
+		new_key_details.content_salt,
+		new_key,
+		&master_key,
+	)?;
+
+	let serialized = header.serialize()?;
+
+	if serialized.len() as u64 != header_len {
+		return Err(sd_crypto::Error::FileHeader.into());
+	}
+
+	file.seek(SeekFrom::Start(0))?;
+	file.write_all(&serialized)?;
+	file.sync_all()?;
+
+	Ok(())
+}
@@ -0,0 +1,61 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// SparseWriter skips over writes that are entirely zeros, so the filesystem is able to leave a hole in their place.
+///
+/// Decryption writes whole blocks at a time, so long runs of zeros (e.g. within a VM disk image) are recreated as holes instead of being written out in full.
+/// Filesystems that don't support sparse files fill the holes with zeros, so the output is identical either way.
+///
+/// The writer must be positioned at the end of the file (e.g. it was just created or truncated), otherwise skipped zeros would leave the old contents in place.
+pub struct SparseWriter<W: Write + Seek> {
+	inner: W,
+	// the number of zeros that have been skipped since the last write
+	pending: u64,
+}
+
+impl<W: Write + Seek> SparseWriter<W> {
+	pub fn new(inner: W) -> Self {
+		Self { inner, pending: 0 }
+	}
+
+	/// This must be called once everything has been written, as a trailing hole only exists once something has been written after it.
+	pub fn finish(mut self) -> io::Result<W> {
+		if self.pending > 0 {
+			// the final zero is written, so the file is extended over the rest of the hole
+			self.pending -= 1;
+			self.skip_pending()?;
+			self.inner.write_all(&[0])?;
+		}
+
+		self.inner.flush()?;
+
+		Ok(self.inner)
+	}
+
+	fn skip_pending(&mut self) -> io::Result<()> {
+		if self.pending > 0 {
+			let offset = i64::try_from(self.pending)
+				.map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+			self.inner.seek(SeekFrom::Current(offset))?;
+			self.pending = 0;
+		}
+
+		Ok(())
+	}
+}
+
+impl<W: Write + Seek> Write for SparseWriter<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		if buf.iter().all(|byte| *byte == 0) {
+			self.pending += buf.len() as u64;
+			return Ok(buf.len());
+		}
+
+		self.skip_pending()?;
+		self.inner.write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
@@ -0,0 +1,347 @@
+use std::{
+	collections::HashMap,
+	fs::File,
+	io::{self, Read, Seek, SeekFrom},
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use prisma_client_rust::QueryError;
+use rspc::{ErrorCode, Type};
+use sd_crypto::{
+	crypto::stream::StreamDecryption,
+	header::file::FileHeader,
+	primitives::{AEAD_TAG_SIZE, BLOCK_SIZE},
+};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::task::JoinError;
+use uuid::Uuid;
+
+use crate::{
+	library::{record_key_audit_event, KeyAuditAction, LibraryContext, LibraryManager},
+	location::LocationError,
+	prisma::{file_path, location},
+};
+
+/// Streams that haven't been read from for this long are forgotten, so their URLs stop working.
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// The most that's decrypted for a single request. Players ask for open-ended ranges, and are fine with receiving less than they asked for.
+const MAX_RANGE_LEN: u64 = 4 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum EncryptedStreamError {
+	#[error("Stream not found (id: {0})")]
+	StreamNotFound(Uuid),
+	#[error("File path not found (id: {0})")]
+	FilePathNotFound(i32),
+	#[error("Library not found (id: {0})")]
+	LibraryNotFound(Uuid),
+	#[error("None of the mounted keys are able to decrypt this file")]
+	NoMountedKey,
+	#[error("The requested range isn't within the file")]
+	RangeNotSatisfiable(u64),
+	#[error("Location error: {0}")]
+	Location(#[from] LocationError),
+	#[error("Crypto error: {0}")]
+	Crypto(#[from] sd_crypto::Error),
+	#[error("I/O error: {0}")]
+	Io(#[from] io::Error),
+	#[error("Database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("Failed to join blocking task: {0}")]
+	Join(#[from] JoinError),
+}
+
+impl From<EncryptedStreamError> for rspc::Error {
+	fn from(err: EncryptedStreamError) -> Self {
+		match err {
+			EncryptedStreamError::StreamNotFound(_)
+			| EncryptedStreamError::FilePathNotFound(_)
+			| EncryptedStreamError::LibraryNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			EncryptedStreamError::NoMountedKey | EncryptedStreamError::RangeNotSatisfiable(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			EncryptedStreamError::Location(err) => err.into(),
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// An encrypted file that's able to be streamed, at `spacedrive://encrypted/<id>`.
+#[derive(Serialize, Type)]
+pub struct EncryptedStream {
+	pub id: Uuid,
+	pub content_type: &'static str,
+}
+
+/// A decrypted range of an encrypted file, which is returned as a `206 Partial Content` response.
+pub struct EncryptedRange {
+	pub content_type: &'static str,
+	pub start: u64,
+	pub end: u64, // inclusive, as with the `Content-Range` header
+	pub total_size: u64,
+	pub body: Vec<u8>,
+}
+
+struct TrackedStream {
+	library_id: Uuid,
+	path: PathBuf,
+	content_type: &'static str,
+	last_activity: Instant,
+}
+
+/// EncryptedStreams serves decrypted byte ranges of encrypted files, so media players are able to seek through encrypted videos without them being decrypted to the disk.
+///
+/// Files are only streamed once they've been opened through rspc (which checks that the client is allowed to see plaintext), and each stream has a random ID so its URL can't be guessed.
+/// Only the blocks that cover each range are decrypted, using whichever mounted key is able to, so a stream stops working as soon as its key is unmounted.
+#[derive(Default)]
+pub struct EncryptedStreams {
+	streams: Mutex<HashMap<Uuid, TrackedStream>>,
+}
+
+impl EncryptedStreams {
+	pub fn new() -> Arc<Self> {
+		Arc::new(Self::default())
+	}
+
+	/// open checks that an encrypted file can be decrypted with one of the mounted keys, and returns a stream for it.
+	pub async fn open(
+		&self,
+		library: &LibraryContext,
+		location_id: i32,
+		file_path_id: i32,
+	) -> Result<EncryptedStream, EncryptedStreamError> {
+		let location = library
+			.db
+			.location()
+			.find_unique(location::id::equals(location_id))
+			.exec()
+			.await?
+			.ok_or(LocationError::IdNotFound(location_id))?;
+
+		let local_path = location
+			.local_path
+			.ok_or(LocationError::MissingLocalPath(location_id))?;
+
+		let file_path = library
+			.db
+			.file_path()
+			.find_unique(file_path::location_id_id(location_id, file_path_id))
+			.exec()
+			.await?
+			.ok_or(EncryptedStreamError::FilePathNotFound(file_path_id))?;
+
+		let path = Path::new(&local_path).join(&file_path.materialized_path);
+
+		// this fails early if the file can't be decrypted, rather than once the player requests it
+		let key_uuid = {
+			let mut reader = File::open(&path)?;
+			let (header, _) = FileHeader::deserialize(&mut reader)?;
+
+			super::unlock_with_mounted_keys(&library.key_manager, &header)
+				.ok_or(EncryptedStreamError::NoMountedKey)?
+				.1
+		};
+
+		// the plaintext is about to be served, so this is treated the same as revealing a key
+		record_key_audit_event(library, KeyAuditAction::Reveal, Some(key_uuid)).await;
+
+		let id = Uuid::new_v4();
+		let content_type = content_type(&path);
+
+		let mut streams = self.lock();
+		streams.retain(|_, stream| stream.last_activity.elapsed() < STREAM_IDLE_TIMEOUT);
+		streams.insert(
+			id,
+			TrackedStream {
+				library_id: library.id,
+				path,
+				content_type,
+				last_activity: Instant::now(),
+			},
+		);
+
+		Ok(EncryptedStream { id, content_type })
+	}
+
+	/// close forgets a stream straight away, and returns whether it existed.
+	pub fn close(&self, id: Uuid) -> bool {
+		self.lock().remove(&id).is_some()
+	}
+
+	/// read decrypts the range of a stream that's been requested with a `Range` header (or the start of the file, if there isn't one).
+	pub async fn read(
+		&self,
+		library_manager: &LibraryManager,
+		id: Uuid,
+		range: Option<&str>,
+	) -> Result<EncryptedRange, EncryptedStreamError> {
+		let (library_id, path, content_type) = {
+			let mut streams = self.lock();
+
+			let stream = streams
+				.get_mut(&id)
+				.filter(|stream| stream.last_activity.elapsed() < STREAM_IDLE_TIMEOUT)
+				.ok_or(EncryptedStreamError::StreamNotFound(id))?;
+			stream.last_activity = Instant::now();
+
+			(stream.library_id, stream.path.clone(), stream.content_type)
+		};
+
+		let library = library_manager
+			.get_ctx(library_id)
+			.await
+			.ok_or(EncryptedStreamError::LibraryNotFound(library_id))?;
+
+		let range = range.and_then(parse_range);
+
+		tokio::task::spawn_blocking(move || {
+			let mut range = read_range(&library, &path, range)?;
+			range.content_type = content_type;
+			Ok(range)
+		})
+		.await?
+	}
+
+	fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, TrackedStream>> {
+		self.streams
+			.lock()
+			.expect("critical error: encrypted streams mutex poisoned")
+	}
+}
+
+/// A requested range, which is either `bytes=start-end`, `bytes=start-` or `bytes=-suffix_len`.
+#[derive(Debug, Clone, Copy)]
+enum RequestedRange {
+	From(u64, Option<u64>),
+	Suffix(u64),
+}
+
+/// This parses a `Range` header. Only a single range is supported, which is all that media players ask for.
+fn parse_range(header: &str) -> Option<RequestedRange> {
+	let (start, end) = header.trim().strip_prefix("bytes=")?.split_once('-')?;
+
+	if start.is_empty() {
+		return end.trim().parse().ok().map(RequestedRange::Suffix);
+	}
+
+	let start = start.trim().parse().ok()?;
+	let end = match end.trim() {
+		"" => None,
+		end => Some(end.parse().ok()?),
+	};
+
+	Some(RequestedRange::From(start, end))
+}
+
+/// This decrypts the blocks that cover a range of the plaintext, and returns the range.
+fn read_range(
+	library: &LibraryContext,
+	path: &Path,
+	range: Option<RequestedRange>,
+) -> Result<EncryptedRange, EncryptedStreamError> {
+	let mut reader = File::open(path)?;
+	let (header, aad) = FileHeader::deserialize(&mut reader)?;
+	let header_len = reader.stream_position()?;
+
+	let (master_key, ..) = super::unlock_with_mounted_keys(&library.key_manager, &header)
+		.ok_or(EncryptedStreamError::NoMountedKey)?;
+
+	// every block is `BLOCK_SIZE` bytes of plaintext followed by its tag, apart from the final block which may be shorter (or empty)
+	let encrypted_block_size = (BLOCK_SIZE + AEAD_TAG_SIZE) as u64;
+	let encrypted_len = reader.metadata()?.len().saturating_sub(header_len);
+	let total_size = (encrypted_len / encrypted_block_size) * BLOCK_SIZE as u64
+		+ (encrypted_len % encrypted_block_size).saturating_sub(AEAD_TAG_SIZE as u64);
+
+	let (start, end) = match range {
+		None => (0, total_size.saturating_sub(1)),
+		Some(RequestedRange::From(start, end)) => (
+			start,
+			end.map_or(total_size.saturating_sub(1), |end| {
+				end.min(total_size.saturating_sub(1))
+			}),
+		),
+		Some(RequestedRange::Suffix(len)) => {
+			(total_size.saturating_sub(len), total_size.saturating_sub(1))
+		}
+	};
+
+	if start >= total_size || start > end {
+		return Err(EncryptedStreamError::RangeNotSatisfiable(total_size));
+	}
+
+	let end = end.min(start + MAX_RANGE_LEN - 1);
+
+	let first_block = start / BLOCK_SIZE as u64;
+	let last_block = end / BLOCK_SIZE as u64;
+
+	let mut decryptor = StreamDecryption::new(master_key, &header.nonce, header.algorithm)?;
+	decryptor.seek_to_block(
+		u32::try_from(first_block).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?,
+	);
+	reader.seek(SeekFrom::Start(
+		header_len + first_block * encrypted_block_size,
+	))?;
+
+	let mut plaintext = Vec::with_capacity(((last_block - first_block + 1) as usize) * BLOCK_SIZE);
+
+	for _ in first_block..=last_block {
+		// a block is read in full, as a short read would be mistaken for the final block
+		let mut block = Vec::with_capacity(BLOCK_SIZE + AEAD_TAG_SIZE);
+		(&mut reader)
+			.take(encrypted_block_size)
+			.read_to_end(&mut block)?;
+
+		if decryptor.decrypt_block(block.as_slice(), &mut plaintext, &aad)? {
+			break;
+		}
+	}
+
+	let offset = (start - first_block * BLOCK_SIZE as u64) as usize;
+	let len = (end - start + 1) as usize;
+
+	Ok(EncryptedRange {
+		content_type: "application/octet-stream",
+		start,
+		end,
+		total_size,
+		body: plaintext
+			.get(offset..offset + len)
+			.map(<[u8]>::to_vec)
+			.ok_or(EncryptedStreamError::RangeNotSatisfiable(total_size))?,
+	})
+}
+
+/// This guesses the content type of an encrypted file from the extension it had before it was encrypted (e.g. `movie.mp4.sdenc`).
+fn content_type(path: &Path) -> &'static str {
+	let name = path
+		.file_name()
+		.and_then(|name| name.to_str())
+		.unwrap_or_default();
+	let name = name.strip_suffix(".sdenc").unwrap_or(name);
+
+	match Path::new(name)
+		.extension()
+		.and_then(|ext| ext.to_str())
+		.map(str::to_lowercase)
+		.as_deref()
+	{
+		Some("mp4" | "m4v") => "video/mp4",
+		Some("webm") => "video/webm",
+		Some("mkv") => "video/x-matroska",
+		Some("mov") => "video/quicktime",
+		Some("avi") => "video/x-msvideo",
+		Some("mp3") => "audio/mpeg",
+		Some("m4a") => "audio/mp4",
+		Some("flac") => "audio/flac",
+		Some("wav") => "audio/wav",
+		Some("ogg" | "oga") => "audio/ogg",
+		Some("opus") => "audio/opus",
+		_ => "application/octet-stream",
+	}
+}
@@ -0,0 +1,451 @@
+use std::{
+	collections::HashMap,
+	fs::{self, DirBuilder, File, OpenOptions},
+	io,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::{Duration, Instant, SystemTime},
+};
+
+use prisma_client_rust::QueryError;
+use rspc::{ErrorCode, Type};
+use sd_crypto::{
+	crypto::stream::StreamDecryption,
+	fs::{
+		erase::{erase, DEFAULT_ERASE_PASSES},
+		filename::decrypt_filename,
+	},
+	header::file::FileHeader,
+	keys::keymanager::KeyManager,
+};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::task::JoinError;
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+use crate::{
+	library::{record_key_audit_event, KeyAuditAction, LibraryContext},
+	location::LocationError,
+	prisma::{file_path, location},
+};
+
+use super::sparse::SparseWriter;
+
+/// TEMP_DIRECTORY_NAME is the name of the directory (within the node's data directory) that decrypted copies are written to.
+pub const TEMP_DIRECTORY_NAME: &str = "decrypted";
+
+/// How often temporary files are checked for changes, and erased if they've expired.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Error, Debug)]
+pub enum TempFileError {
+	#[error("File path not found (id: {0})")]
+	FilePathNotFound(i32),
+	#[error("File path is a directory (id: {0})")]
+	IsDirectory(i32),
+	#[error("None of the mounted keys are able to decrypt this file")]
+	NoMountedKey,
+	#[error("Not enough space for decrypted files (required: {required} bytes, available: {available} bytes)")]
+	QuotaExceeded { required: u64, available: u64 },
+	#[error("Location error: {0}")]
+	Location(#[from] LocationError),
+	#[error("Crypto error: {0}")]
+	Crypto(#[from] sd_crypto::Error),
+	#[error("I/O error: {0}")]
+	Io(#[from] io::Error),
+	#[error("Database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("Failed to join blocking task: {0}")]
+	Join(#[from] JoinError),
+}
+
+impl From<TempFileError> for rspc::Error {
+	fn from(err: TempFileError) -> Self {
+		match err {
+			TempFileError::FilePathNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			TempFileError::IsDirectory(_)
+			| TempFileError::NoMountedKey
+			| TempFileError::QuotaExceeded { .. } => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			TempFileError::Location(err) => err.into(),
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// A decrypted copy of an encrypted file, which can be handed to another app.
+#[derive(Serialize, Type)]
+pub struct TempFile {
+	pub id: Uuid,
+	pub path: PathBuf,
+	/// how long (in seconds) the copy is kept once it stops being changed
+	pub ttl: u64,
+}
+
+struct TrackedFile {
+	library_id: Uuid,
+	path: PathBuf,
+	size: u64,
+	ttl: Duration,
+	last_activity: Instant,
+	last_modified: Option<SystemTime>,
+}
+
+/// TempFileManager decrypts encrypted files into a private temporary directory, so they can be opened with other apps.
+///
+/// Decrypted data is only ever written within this directory (which only the current user is able to access), rather than the system's temp directory.
+/// We can't tell when another app has finished with a file, so each copy is securely erased once it hasn't changed for its TTL.
+/// Copies are also erased as soon as the key that decrypted them is unmounted, their library is locked, or the node shuts down, and any that are left over (e.g. after a crash) are erased on startup.
+pub struct TempFileManager {
+	directory: PathBuf,
+	files: Mutex<HashMap<Uuid, TrackedFile>>,
+	/// the space that's been set aside for copies that are still being decrypted
+	reserved: AtomicU64,
+}
+
+impl TempFileManager {
+	pub fn new(data_dir: &Path) -> Arc<Self> {
+		let directory = data_dir.join(TEMP_DIRECTORY_NAME);
+
+		match shred_directory(&directory) {
+			Ok(()) => debug!("Erased leftover decrypted files"),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+			Err(e) => error!("Failed to erase leftover decrypted files: {:#?}", e),
+		}
+
+		let manager = Arc::new(Self {
+			directory,
+			files: Mutex::new(HashMap::new()),
+			reserved: AtomicU64::new(0),
+		});
+
+		tokio::spawn(Arc::clone(&manager).watch());
+
+		manager
+	}
+
+	/// open decrypts a file with whichever mounted key is able to, and returns the path to its temporary copy.
+	pub async fn open(
+		self: &Arc<Self>,
+		library: &LibraryContext,
+		location_id: i32,
+		file_path_id: i32,
+		ttl: Duration,
+	) -> Result<TempFile, TempFileError> {
+		let location = library
+			.db
+			.location()
+			.find_unique(location::id::equals(location_id))
+			.exec()
+			.await?
+			.ok_or(LocationError::IdNotFound(location_id))?;
+
+		let local_path = location
+			.local_path
+			.ok_or(LocationError::MissingLocalPath(location_id))?;
+
+		let file_path = library
+			.db
+			.file_path()
+			.find_unique(file_path::location_id_id(location_id, file_path_id))
+			.exec()
+			.await?
+			.ok_or(TempFileError::FilePathNotFound(file_path_id))?;
+
+		if file_path.is_dir {
+			return Err(TempFileError::IsDirectory(file_path_id));
+		}
+
+		let id = Uuid::new_v4();
+		let source = Path::new(&local_path).join(&file_path.materialized_path);
+		let directory = self.directory.join(id.to_string());
+		let key_manager = Arc::clone(&library.key_manager);
+
+		// the encrypted file is slightly larger than its plaintext, so this is a safe estimate of the space that's needed
+		let size = fs::metadata(&source)?.len();
+		self.reserve(size, library.config().get().await.decrypted_file_quota)?;
+
+		let (path, key_uuid) = tokio::task::spawn_blocking(move || {
+			decrypt_to_directory(&key_manager, &source, &directory)
+		})
+		.await
+		.map_err(TempFileError::from)
+		.and_then(|result| result)
+		.map_err(|e| {
+			self.reserved.fetch_sub(size, Ordering::SeqCst);
+			e
+		})?;
+
+		// the plaintext is now on the disk, so this is treated the same as revealing a key
+		record_key_audit_event(library, KeyAuditAction::Reveal, Some(key_uuid)).await;
+
+		{
+			let mut files = self
+				.files
+				.lock()
+				.expect("critical error: temp files mutex poisoned");
+
+			files.insert(
+				id,
+				TrackedFile {
+					library_id: library.id,
+					last_modified: modified_time(&path),
+					size: modified_size(&path).unwrap_or(size),
+					path: path.clone(),
+					ttl,
+					last_activity: Instant::now(),
+				},
+			);
+
+			// the copy now counts towards the quota itself
+			self.reserved.fetch_sub(size, Ordering::SeqCst);
+		}
+
+		let manager = Arc::clone(self);
+		library.key_consumers.on_revoke(key_uuid, move || {
+			// erasing large files takes a while, so it's done in the background where possible
+			match tokio::runtime::Handle::try_current() {
+				Ok(handle) => {
+					handle.spawn_blocking(move || manager.shred(id));
+				}
+				Err(_) => {
+					manager.shred(id);
+				}
+			}
+		});
+
+		Ok(TempFile {
+			id,
+			path,
+			ttl: ttl.as_secs(),
+		})
+	}
+
+	/// shred securely erases a temporary copy straight away, and returns whether it existed.
+	pub fn shred(&self, id: Uuid) -> bool {
+		let file = self
+			.files
+			.lock()
+			.expect("critical error: temp files mutex poisoned")
+			.remove(&id);
+
+		match file {
+			Some(file) => {
+				// each copy has its own directory, which is named after its ID
+				if let Err(e) = shred_directory(&self.directory.join(id.to_string())) {
+					error!("Failed to erase decrypted file {:?}: {:#?}", file.path, e);
+				}
+
+				true
+			}
+			None => false,
+		}
+	}
+
+	/// shred_library securely erases every temporary copy that was decrypted from within a library, e.g. once it's locked.
+	pub fn shred_library(&self, library_id: Uuid) {
+		let ids = self
+			.files
+			.lock()
+			.expect("critical error: temp files mutex poisoned")
+			.iter()
+			.filter(|(_, file)| file.library_id == library_id)
+			.map(|(id, _)| *id)
+			.collect::<Vec<_>>();
+
+		for id in ids {
+			self.shred(id);
+		}
+	}
+
+	/// shred_all securely erases every temporary copy, e.g. when the node is shutting down.
+	pub fn shred_all(&self) {
+		let ids = self
+			.files
+			.lock()
+			.expect("critical error: temp files mutex poisoned")
+			.keys()
+			.copied()
+			.collect::<Vec<_>>();
+
+		for id in ids {
+			self.shred(id);
+		}
+	}
+
+	/// reserve sets aside space for a new copy, as long as it fits within the quota alongside every other copy.
+	fn reserve(&self, size: u64, quota: u64) -> Result<(), TempFileError> {
+		// the lock is held while checking, so two copies can't both claim the last of the space
+		let files = self
+			.files
+			.lock()
+			.expect("critical error: temp files mutex poisoned");
+
+		let used = files.values().map(|file| file.size).sum::<u64>()
+			+ self.reserved.load(Ordering::SeqCst);
+		let available = quota.saturating_sub(used);
+
+		if size > available {
+			return Err(TempFileError::QuotaExceeded {
+				required: size,
+				available,
+			});
+		}
+
+		self.reserved.fetch_add(size, Ordering::SeqCst);
+
+		Ok(())
+	}
+
+	async fn watch(self: Arc<Self>) {
+		let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+		loop {
+			interval.tick().await;
+
+			for id in self.expired() {
+				let manager = Arc::clone(&self);
+				if let Err(e) = tokio::task::spawn_blocking(move || manager.shred(id)).await {
+					error!("Failed to erase expired decrypted file: {:#?}", e);
+				}
+			}
+		}
+	}
+
+	/// expired returns the files that haven't changed for their TTL.
+	///
+	/// A file that's being changed is clearly still in use, so its TTL is restarted.
+	fn expired(&self) -> Vec<Uuid> {
+		let mut files = self
+			.files
+			.lock()
+			.expect("critical error: temp files mutex poisoned");
+
+		files
+			.iter_mut()
+			.filter_map(|(id, file)| {
+				let modified = modified_time(&file.path);
+
+				if modified != file.last_modified {
+					file.size = modified_size(&file.path).unwrap_or(file.size);
+
+					warn!(
+						"Decrypted file {:?} was changed, these changes won't be saved to the encrypted original",
+						file.path
+					);
+
+					file.last_modified = modified;
+					file.last_activity = Instant::now();
+				}
+
+				(file.last_activity.elapsed() >= file.ttl).then_some(*id)
+			})
+			.collect()
+	}
+}
+
+/// This decrypts a file into its own private directory, and returns the path to the decrypted copy alongside the key that decrypted it.
+fn decrypt_to_directory(
+	key_manager: &KeyManager,
+	source: &Path,
+	directory: &Path,
+) -> Result<(PathBuf, Uuid), TempFileError> {
+	let mut reader = File::open(source)?;
+	let (header, aad) = FileHeader::deserialize(&mut reader)?;
+
+	let (master_key, key_uuid, hashed_key) =
+		super::unlock_with_mounted_keys(key_manager, &header).ok_or(TempFileError::NoMountedKey)?;
+
+	// the copy keeps its original name, so the other app knows what it's opening (files within vaults have encrypted names)
+	let file_name = source
+		.file_name()
+		.and_then(|name| name.to_str())
+		.unwrap_or_default();
+	let file_name = file_name.strip_suffix(".sdenc").unwrap_or(file_name);
+	let file_name =
+		decrypt_filename(hashed_key, file_name).unwrap_or_else(|_| file_name.to_string());
+
+	// a decrypted name can't be trusted to stay within the directory
+	let file_name = Path::new(&file_name)
+		.file_name()
+		.map_or_else(|| PathBuf::from("decrypted"), PathBuf::from);
+
+	let mut builder = DirBuilder::new();
+	builder.recursive(true);
+	#[cfg(unix)]
+	std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+	builder.create(directory)?;
+
+	let path = directory.join(file_name);
+
+	let mut options = OpenOptions::new();
+	options.write(true).create_new(true);
+	#[cfg(unix)]
+	std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+	let result =
+		options
+			.open(&path)
+			.map_err(TempFileError::from)
+			.and_then(|writer| {
+				let mut writer = SparseWriter::new(writer);
+
+				StreamDecryption::new(master_key, &header.nonce, header.algorithm)?
+					.decrypt_streams(&mut reader, &mut writer, &aad)?;
+
+				writer.finish()?;
+
+				Ok(())
+			});
+
+	if let Err(e) = result {
+		if let Err(cleanup_error) = shred_directory(directory) {
+			error!(
+				"Failed to erase partially decrypted file: {:#?}",
+				cleanup_error
+			);
+		}
+
+		return Err(e);
+	}
+
+	Ok((path, key_uuid))
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+	fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn modified_size(path: &Path) -> Option<u64> {
+	fs::metadata(path).map(|m| m.len()).ok()
+}
+
+/// This securely erases every file within a directory (recursively), and then removes it.
+fn shred_directory(directory: &Path) -> io::Result<()> {
+	for entry in fs::read_dir(directory)? {
+		let path = entry?.path();
+
+		if path.is_dir() {
+			shred_directory(&path)?;
+		} else {
+			let mut file = OpenOptions::new().write(true).open(&path)?;
+			let size = usize::try_from(file.metadata()?.len()).unwrap_or(usize::MAX);
+
+			erase(&mut file, size, DEFAULT_ERASE_PASSES)
+				.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+			file.sync_all()?;
+			drop(file);
+
+			fs::remove_file(&path)?;
+		}
+	}
+
+	fs::remove_dir(directory)
+}
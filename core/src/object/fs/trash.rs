@@ -0,0 +1,274 @@
+use std::path::{Path, PathBuf};
+
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::{
+	library::LibraryContext,
+	location::file_path_helper::{delete_orphaned_objects, record_file_path},
+	prisma::trashed_item,
+};
+
+use super::{walk_directory, EntryKind};
+use os::{purge_from_trash, restore_from_trash};
+
+// Items are moved to the trash of the OS (the Recycle Bin on Windows), so they can be restored from outside Spacedrive too.
+// Listing, restoring and purging items in the trash is only possible on Windows and Linux (and other freedesktop.org systems),
+// as there isn't an API for it on macOS.
+
+trashed_item::include!(trashed_item_with_location { location });
+
+#[derive(Error, Debug)]
+pub enum TrashError {
+	#[error("The trash isn't supported on this platform")]
+	Unsupported,
+	#[error("Trashed item not found: <id='{0}'>")]
+	NotFound(i32),
+	#[error("Location of trashed item isn't local: <id='{0}'>")]
+	MissingLocalPath(i32),
+	#[error("Item is no longer in the trash: {0:?}")]
+	NotInTrash(PathBuf),
+	#[error("Something already exists where the item would be restored: {0:?}")]
+	RestoreCollision(PathBuf),
+	#[error("Trash error: {0}")]
+	Trash(String),
+	#[error("I/O error: {0}")]
+	IOError(#[from] std::io::Error),
+	#[error("Database error: {0}")]
+	DatabaseError(#[from] QueryError),
+}
+
+impl From<TrashError> for rspc::Error {
+	fn from(err: TrashError) -> Self {
+		match err {
+			TrashError::NotFound(_) | TrashError::NotInTrash(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			TrashError::RestoreCollision(_) => {
+				rspc::Error::with_cause(ErrorCode::Conflict, err.to_string(), err)
+			}
+			TrashError::Unsupported | TrashError::MissingLocalPath(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+#[cfg(not(any(target_os = "ios", target_os = "android")))]
+impl From<trash::Error> for TrashError {
+	fn from(err: trash::Error) -> Self {
+		Self::Trash(err.to_string())
+	}
+}
+
+/// This moves a file or directory (and everything within it) to the trash.
+#[cfg(not(any(target_os = "ios", target_os = "android")))]
+pub(super) fn move_to_trash(path: &Path) -> Result<(), TrashError> {
+	Ok(trash::delete(path)?)
+}
+
+#[cfg(any(target_os = "ios", target_os = "android"))]
+pub(super) fn move_to_trash(_: &Path) -> Result<(), TrashError> {
+	Err(TrashError::Unsupported)
+}
+
+#[cfg(any(
+	windows,
+	all(
+		unix,
+		not(any(target_os = "macos", target_os = "ios", target_os = "android"))
+	)
+))]
+mod os {
+	use std::{
+		collections::{hash_map::Entry, HashMap},
+		path::{Path, PathBuf},
+	};
+
+	use trash::{os_limited, TrashItem};
+
+	use super::TrashError;
+
+	/// This finds the items in the trash that were at these paths, and the most recently trashed one where several were.
+	fn find_in_trash(paths: &[PathBuf]) -> Result<Vec<TrashItem>, TrashError> {
+		let mut found = HashMap::<PathBuf, TrashItem>::new();
+
+		for item in os_limited::list()? {
+			let original_path = item.original_path();
+			if !paths.contains(&original_path) {
+				continue;
+			}
+
+			match found.entry(original_path) {
+				Entry::Occupied(mut entry) => {
+					if item.time_deleted > entry.get().time_deleted {
+						entry.insert(item);
+					}
+				}
+				Entry::Vacant(entry) => {
+					entry.insert(item);
+				}
+			}
+		}
+
+		Ok(found.into_values().collect())
+	}
+
+	pub fn restore_from_trash(path: &Path) -> Result<(), TrashError> {
+		let item = find_in_trash(&[path.to_path_buf()])?
+			.pop()
+			.ok_or_else(|| TrashError::NotInTrash(path.to_path_buf()))?;
+
+		Ok(os_limited::restore_all([item])?)
+	}
+
+	pub fn purge_from_trash(paths: &[PathBuf]) -> Result<(), TrashError> {
+		let items = find_in_trash(paths)?;
+		if items.is_empty() {
+			return Ok(());
+		}
+
+		Ok(os_limited::purge_all(items)?)
+	}
+}
+
+#[cfg(not(any(
+	windows,
+	all(
+		unix,
+		not(any(target_os = "macos", target_os = "ios", target_os = "android"))
+	)
+)))]
+mod os {
+	use std::path::{Path, PathBuf};
+
+	use super::TrashError;
+
+	pub fn restore_from_trash(_: &Path) -> Result<(), TrashError> {
+		Err(TrashError::Unsupported)
+	}
+
+	pub fn purge_from_trash(_: &[PathBuf]) -> Result<(), TrashError> {
+		Err(TrashError::Unsupported)
+	}
+}
+
+fn original_path(item: &trashed_item_with_location::Data) -> Result<PathBuf, TrashError> {
+	item.location
+		.local_path
+		.as_ref()
+		.map(|local_path| Path::new(local_path).join(item.materialized_path.trim_end_matches('/')))
+		.ok_or(TrashError::MissingLocalPath(item.id))
+}
+
+/// This puts a trashed item back where it was, and records it (and everything within it) in its location again.
+///
+/// The item gets its object back, so it keeps its tags, notes etc, while anything within a directory is identified again.
+pub async fn restore_trashed_item(
+	library_ctx: &LibraryContext,
+	id: i32,
+) -> Result<trashed_item_with_location::Data, TrashError> {
+	let db = &library_ctx.db;
+
+	let item = db
+		.trashed_item()
+		.find_unique(trashed_item::id::equals(id))
+		.include(trashed_item_with_location::include())
+		.exec()
+		.await?
+		.ok_or(TrashError::NotFound(id))?;
+
+	let path = original_path(&item)?;
+	if path.symlink_metadata().is_ok() {
+		return Err(TrashError::RestoreCollision(path));
+	}
+
+	restore_from_trash(&path)?;
+
+	let size_in_bytes = item.size_in_bytes.parse().unwrap_or(0);
+	record_file_path(
+		library_ctx,
+		item.location_id,
+		&item.materialized_path,
+		item.is_dir,
+		item.object_id,
+		size_in_bytes,
+	)
+	.await?;
+
+	if item.is_dir {
+		let root = item.materialized_path.trim_end_matches('/');
+
+		for entry in walk_directory(&path)? {
+			// paths that aren't UTF-8 can't be stored within the database, and symlinks aren't indexed
+			let relative_path = match entry.path.to_str() {
+				Some(relative_path) if entry.kind != EntryKind::Symlink => {
+					relative_path.replace('\\', "/")
+				}
+				_ => {
+					warn!("restoring is skipping {}", entry.path.display());
+					continue;
+				}
+			};
+
+			record_file_path(
+				library_ctx,
+				item.location_id,
+				&format!("{root}/{relative_path}"),
+				entry.kind == EntryKind::Directory,
+				None,
+				entry.size,
+			)
+			.await?;
+		}
+	}
+
+	db.trashed_item()
+		.delete(trashed_item::id::equals(id))
+		.exec()
+		.await?;
+
+	Ok(item)
+}
+
+/// This permanently deletes items from the trash, along with their objects.
+///
+/// Items that are no longer in the trash (as they were restored or deleted from outside Spacedrive) are just forgotten.
+pub async fn empty_trash(
+	library_ctx: &LibraryContext,
+	filters: Vec<trashed_item::WhereParam>,
+) -> Result<usize, TrashError> {
+	let db = &library_ctx.db;
+
+	let items = db
+		.trashed_item()
+		.find_many(filters)
+		.include(trashed_item_with_location::include())
+		.exec()
+		.await?;
+
+	let paths = items
+		.iter()
+		.filter_map(|item| original_path(item).ok())
+		.collect::<Vec<_>>();
+
+	purge_from_trash(&paths)?;
+
+	db.trashed_item()
+		.delete_many(vec![trashed_item::id::in_vec(
+			items.iter().map(|item| item.id).collect(),
+		)])
+		.exec()
+		.await?;
+
+	delete_orphaned_objects(
+		library_ctx,
+		items.iter().filter_map(|item| item.object_id).collect(),
+	)
+	.await?;
+
+	Ok(items.len())
+}
@@ -0,0 +1,122 @@
+use std::io::Cursor;
+
+use prisma_client_rust::QueryError;
+use rspc::{ErrorCode, Type};
+use sd_crypto::{
+	crypto::stream::StreamDecryption, fs::filename::decrypt_filename, header::file::FileHeader,
+};
+use serde::Serialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{
+	library::LibraryContext,
+	location::LocationError,
+	prisma::{file_path, location},
+};
+
+location::include!(location_with_vault_key { vault_key });
+
+/// A decrypted file from a vault location.
+#[derive(Serialize, Type)]
+pub struct VaultFile {
+	pub name: String,
+	/// the base64-encoded plaintext
+	pub contents: String,
+}
+
+#[derive(Error, Debug)]
+pub enum VaultError {
+	#[error("Location isn't a vault (id: {0})")]
+	NotAVault(i32),
+	#[error("File path not found (id: {0})")]
+	FilePathNotFound(i32),
+	#[error("Vault key isn't mounted (uuid: {0})")]
+	KeyNotMounted(Uuid),
+	#[error("Location error: {0}")]
+	Location(#[from] LocationError),
+	#[error("Crypto error: {0}")]
+	Crypto(#[from] sd_crypto::Error),
+	#[error("I/O error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("Database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("Invalid UUID: {0}")]
+	Uuid(#[from] uuid::Error),
+}
+
+impl From<VaultError> for rspc::Error {
+	fn from(err: VaultError) -> Self {
+		match err {
+			VaultError::FilePathNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			VaultError::NotAVault(_) | VaultError::KeyNotMounted(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			VaultError::Location(err) => err.into(),
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// This reads a file from a vault location, and decrypts both it and its name with the vault's key.
+pub async fn read_vault_file(
+	library: &LibraryContext,
+	location_id: i32,
+	file_path_id: i32,
+) -> Result<VaultFile, VaultError> {
+	let location = library
+		.db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.include(location_with_vault_key::include())
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	let vault_key = location
+		.vault_key
+		.ok_or(VaultError::NotAVault(location_id))?;
+
+	let local_path = location
+		.local_path
+		.ok_or(LocationError::MissingLocalPath(location_id))?;
+
+	let file_path = library
+		.db
+		.file_path()
+		.find_unique(file_path::location_id_id(location_id, file_path_id))
+		.exec()
+		.await?
+		.ok_or(VaultError::FilePathNotFound(file_path_id))?;
+
+	let key_uuid = Uuid::parse_str(&vault_key.uuid)?;
+
+	let hashed_key = library
+		.key_manager
+		.access_keymount(key_uuid)
+		.map_err(|_| VaultError::KeyNotMounted(key_uuid))?
+		.hashed_key;
+
+	let bytes =
+		tokio::fs::read(std::path::Path::new(&local_path).join(&file_path.materialized_path))
+			.await?;
+
+	let mut reader = Cursor::new(bytes);
+	let (header, aad) = FileHeader::deserialize(&mut reader)?;
+
+	let master_key = header.decrypt_master_key_from_prehashed(vec![hashed_key.clone()])?;
+	let decryptor = StreamDecryption::new(master_key, &header.nonce, header.algorithm)?;
+
+	let mut contents = Vec::new();
+	decryptor.decrypt_streams(&mut reader, &mut contents, &aad)?;
+
+	// files that were added before the location became a vault keep their original names
+	let name = decrypt_filename(hashed_key, &file_path.name).unwrap_or(file_path.name);
+
+	Ok(VaultFile {
+		name,
+		contents: base64::encode(contents),
+	})
+}
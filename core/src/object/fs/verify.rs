@@ -0,0 +1,335 @@
+use std::{
+	collections::VecDeque,
+	fs::File,
+	io::{self, Seek},
+	path::PathBuf,
+};
+
+use sd_crypto::{
+	crypto::stream::StreamDecryption,
+	header::file::FileHeader,
+	primitives::{AEAD_TAG_SIZE, BLOCK_SIZE, KEY_LEN},
+	Protected,
+};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::{info, warn};
+
+use crate::{
+	job::{
+		IoThrottle, JobError, JobLogLevel, JobPriority, JobReportUpdate, JobResult, JobState,
+		StatefulJob, WorkerContext,
+	},
+	prisma::{file_path, location},
+};
+
+use super::encrypt::Metadata;
+
+pub const FILE_VERIFIER_JOB_NAME: &str = "encrypted_file_verifier";
+
+location::include!(location_with_vault_key { vault_key });
+
+// The verifier walks every encrypted file within the selected locations, and decrypts them without writing the plaintext anywhere.
+// This checks the header, every block's tag and (if the header's metadata has one) the plaintext's checksum, so bit-rot is caught
+// while the original may still be around.
+pub struct FileVerifierJob;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileVerifierJobState {
+	report: FileVerifierReport,
+}
+
+#[derive(Serialize, Deserialize, Debug, Type, Hash)]
+pub struct FileVerifierJobInit {
+	pub location_ids: Vec<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileVerifierJobStep {
+	location_id: i32,
+	obj_name: String,
+	obj_path: PathBuf,
+}
+
+/// This is the summary of a verification job, which is returned once it's finished.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FileVerifierReport {
+	total_files: usize,
+	files_verified: usize,
+	/// files that are damaged, and should be restored from a backup
+	failures: Vec<FileVerifierIssue>,
+	/// files that couldn't be checked, e.g. as none of the mounted keys are able to decrypt them
+	skipped: Vec<FileVerifierIssue>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileVerifierIssue {
+	location_id: i32,
+	path: String,
+	problem: VerificationProblem,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum VerificationProblem {
+	/// the file couldn't be read at all
+	Unreadable(String),
+	/// the header (or its metadata) couldn't be deserialized
+	InvalidHeader(String),
+	/// the file ends part of the way through its contents
+	Truncated,
+	/// one of the blocks (or the header's AAD) failed authentication
+	Corrupted,
+	/// every block decrypted, but the plaintext doesn't match the checksum stored within the header
+	ChecksumMismatch,
+	NoMountedKey,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for FileVerifierJob {
+	type Data = FileVerifierJobState;
+	type Init = FileVerifierJobInit;
+	type Step = FileVerifierJobStep;
+
+	fn name(&self) -> &'static str {
+		FILE_VERIFIER_JOB_NAME
+	}
+
+	fn priority(&self) -> JobPriority {
+		JobPriority::Low
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let locations = ctx
+			.library_ctx
+			.db
+			.location()
+			.find_many(vec![location::id::in_vec(state.init.location_ids.clone())])
+			.include(location_with_vault_key::include())
+			.exec()
+			.await?;
+
+		state.steps = VecDeque::new();
+
+		for location in locations {
+			let root_path = match &location.local_path {
+				Some(local_path) => PathBuf::from(local_path),
+				None => {
+					warn!(
+						"verification is skipping location {} as it isn't available locally",
+						location.id
+					);
+					ctx.log(
+						JobLogLevel::Warning,
+						format!(
+							"Skipped location {}, as it isn't available locally",
+							location.id
+						),
+						None,
+					);
+					continue;
+				}
+			};
+
+			let mut params = vec![
+				file_path::location_id::equals(location.id),
+				file_path::is_dir::equals(false),
+			];
+
+			// every file within a vault is encrypted, but they don't have the usual extension
+			if location.vault_key.is_none() {
+				params.push(file_path::extension::equals(Some("sdenc".to_string())));
+			}
+
+			for item in ctx
+				.library_ctx
+				.db
+				.file_path()
+				.find_many(params)
+				.exec()
+				.await?
+			{
+				state.steps.push_back(FileVerifierJobStep {
+					location_id: location.id,
+					obj_path: root_path.join(&item.materialized_path),
+					obj_name: item.materialized_path,
+				});
+			}
+		}
+
+		state.data = Some(FileVerifierJobState {
+			report: FileVerifierReport {
+				total_files: state.steps.len(),
+				..Default::default()
+			},
+		});
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Verifying {}",
+			step.obj_name
+		))]);
+
+		let key_manager = &ctx.library_ctx.key_manager;
+		let hashed_keys = key_manager
+			.get_mounted_uuids()
+			.into_iter()
+			.filter_map(|uuid| key_manager.access_keymount(uuid).ok())
+			.map(|mount| mount.hashed_key)
+			.collect::<Vec<_>>();
+
+		let result = verify_file(step, hashed_keys, ctx.library_ctx.io_throttle()).await;
+
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		match result {
+			Ok(None) => data.report.files_verified += 1,
+			Ok(Some(problem)) => {
+				let issue = FileVerifierIssue {
+					location_id: step.location_id,
+					path: step.obj_name.clone(),
+					problem,
+				};
+
+				if matches!(issue.problem, VerificationProblem::NoMountedKey) {
+					ctx.log(
+						JobLogLevel::Info,
+						"Skipped, as none of the mounted keys are able to decrypt it",
+						Some(issue.path.clone()),
+					);
+					data.report.skipped.push(issue);
+				} else {
+					warn!(
+						"verification failed for {}: {:?}",
+						issue.path, issue.problem
+					);
+					ctx.log(
+						JobLogLevel::Warning,
+						format!("Verification failed: {:?}", issue.problem),
+						Some(issue.path.clone()),
+					);
+					data.report.failures.push(issue);
+				}
+			}
+			Err(e) => {
+				warn!("verification couldn't read {}: {:#?}", step.obj_name, e);
+				ctx.log(
+					JobLogLevel::Warning,
+					format!("Skipped, as it couldn't be read: {e}"),
+					Some(step.obj_name.clone()),
+				);
+				data.report.skipped.push(FileVerifierIssue {
+					location_id: step.location_id,
+					path: step.obj_name.clone(),
+					problem: VerificationProblem::Unreadable(e.to_string()),
+				});
+			}
+		}
+
+		ctx.progress(vec![
+			JobReportUpdate::CompletedTaskCount(state.step_number + 1),
+			JobReportUpdate::Message(format!(
+				"Verified {} of {} files ({} failed)",
+				data.report.files_verified,
+				data.report.total_files,
+				data.report.failures.len()
+			)),
+		]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!("Finalizing verification job: {:#?}", data.report);
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
+
+/// This decrypts a file block-by-block (discarding the plaintext), and returns what's wrong with it, if anything.
+///
+/// I/O errors are returned as errors, as they say nothing about the file's contents.
+async fn verify_file(
+	step: &FileVerifierJobStep,
+	hashed_keys: Vec<Protected<[u8; KEY_LEN]>>,
+	throttle: &IoThrottle,
+) -> Result<Option<VerificationProblem>, io::Error> {
+	let mut reader = File::open(&step.obj_path)?;
+
+	let (header, aad) = match FileHeader::deserialize(&mut reader) {
+		Ok(header) => header,
+		Err(sd_crypto::Error::Io(e)) if e.kind() != io::ErrorKind::UnexpectedEof => return Err(e),
+		Err(e) => return Ok(Some(VerificationProblem::InvalidHeader(e.to_string()))),
+	};
+
+	// every valid file ends with a partial block (which may be empty), so anything else has been cut short
+	let content_len = reader.metadata()?.len() - reader.stream_position()?;
+	let remainder = content_len % (BLOCK_SIZE + AEAD_TAG_SIZE) as u64;
+	let truncated = remainder == 0 || remainder < AEAD_TAG_SIZE as u64;
+
+	let (master_key, hashed_key) = match hashed_keys.into_iter().find_map(|hashed_key| {
+		header
+			.decrypt_master_key_from_prehashed(vec![hashed_key.clone()])
+			.ok()
+			.map(|master_key| (master_key, hashed_key))
+	}) {
+		Some(keys) => keys,
+		None => return Ok(Some(VerificationProblem::NoMountedKey)),
+	};
+
+	let checksum = if header.metadata.is_some() {
+		match header.decrypt_metadata_from_prehashed::<Metadata>(vec![hashed_key]) {
+			Ok(metadata) => metadata.checksum,
+			Err(e) => return Ok(Some(VerificationProblem::InvalidHeader(e.to_string()))),
+		}
+	} else {
+		None
+	};
+
+	let mut decryptor = match StreamDecryption::new(master_key, &header.nonce, header.algorithm) {
+		Ok(decryptor) => decryptor,
+		Err(e) => return Ok(Some(VerificationProblem::InvalidHeader(e.to_string()))),
+	};
+
+	let mut hasher = blake3::Hasher::new();
+
+	loop {
+		match decryptor.decrypt_block(&mut reader, &mut hasher, &aad) {
+			Ok(true) => break,
+			Ok(false) => {}
+			Err(sd_crypto::Error::Io(e)) => return Err(e),
+			Err(_) if truncated => return Ok(Some(VerificationProblem::Truncated)),
+			Err(_) => return Ok(Some(VerificationProblem::Corrupted)),
+		}
+
+		throttle.read((BLOCK_SIZE + AEAD_TAG_SIZE) as u64).await;
+		tokio::task::yield_now().await;
+	}
+
+	// the checksum is only stored if the original had one when it was encrypted
+	if let Some(checksum) = checksum {
+		if hasher.finalize().to_hex().as_str() != checksum {
+			return Ok(Some(VerificationProblem::ChecksumMismatch));
+		}
+	}
+
+	Ok(None)
+}
@@ -182,6 +182,7 @@ impl StatefulJob for FullFileIdentifierJob {
 		]);
 
 		invalidate_query!(ctx.library_ctx, "locations.getExplorerData");
+		invalidate_query!(ctx.library_ctx, "savedSearches.getExplorerData");
 
 		// let _remaining = count_orphan_file_paths(&ctx.core_ctx, location_id.into()).await?;
 		Ok(())
@@ -203,6 +204,8 @@ fn orphan_path_filters(location_id: i32, file_path_id: Option<i32>) -> Vec<file_
 	let mut params = vec![
 		file_path::object_id::equals(None),
 		file_path::is_dir::equals(false),
+		// links that weren't followed are left without an object, so their targets aren't counted
+		file_path::is_symlink::equals(false),
 		file_path::location_id::equals(location_id),
 	];
 	// this is a workaround for the cursor not working properly
@@ -222,6 +225,7 @@ async fn count_orphan_file_paths(
 		.count(vec![
 			file_path::object_id::equals(None),
 			file_path::is_dir::equals(false),
+			file_path::is_symlink::equals(false),
 			file_path::location_id::equals(location_id),
 		])
 		.exec()
@@ -1,7 +1,10 @@
 use crate::{
 	job::JobError,
 	library::LibraryContext,
-	object::cas::generate_cas_id,
+	object::{
+		cas::{generate_cas_id, generate_content_hash, ContentHash},
+		native_attributes::{read_native_attributes, record_native_attributes},
+	},
 	prisma::{file_path, object},
 };
 use chrono::{DateTime, FixedOffset};
@@ -39,6 +42,7 @@ pub struct ObjectCreationMetadata {
 	pub size_str: String,
 	pub kind: ObjectKind,
 	pub date_created: DateTime<FixedOffset>,
+	pub content_hash: ContentHash,
 }
 
 pub async fn assemble_object_metadata(
@@ -73,6 +77,7 @@ pub async fn assemble_object_metadata(
 	let size = metadata.len();
 
 	let cas_id = generate_cas_id(&path, size).await?;
+	let content_hash = generate_content_hash(&path, size).await?;
 
 	info!("Analyzed file: {:?} {:?} {:?}", path, cas_id, object_kind);
 
@@ -81,6 +86,7 @@ pub async fn assemble_object_metadata(
 		size_str: size.to_string(),
 		kind: object_kind,
 		date_created: file_path.date_created,
+		content_hash,
 	})
 }
 
@@ -116,11 +122,108 @@ async fn batch_update_file_paths(
 	library.db._batch(file_path_updates).await
 }
 
+/// This makes sure that file paths are only linked to existing objects with the same content, as `cas_id`s are sampled (and truncated) so they can collide.
+///
+/// File paths with different content are left unlinked, and existing objects that don't have a content hash yet get the one from their first file path.
+async fn check_content_hashes(
+	library: &LibraryContext,
+	existing_objects: &[object::Data],
+	content_hashes: &HashMap<i32, ContentHash>,
+	cas_id_lookup: &mut HashMap<String, Vec<i32>>,
+) -> Result<(), QueryError> {
+	let mut content_hash_updates = Vec::new();
+
+	for object in existing_objects {
+		let file_path_ids = match cas_id_lookup.get_mut(&object.cas_id) {
+			Some(file_path_ids) => file_path_ids,
+			None => continue,
+		};
+
+		match &object.content_hash {
+			Some(object_content_hash) => file_path_ids.retain(|file_path_id| {
+				let matches = content_hashes
+					.get(file_path_id)
+					.map_or(false, |content_hash| content_hash.hash == *object_content_hash);
+
+				if !matches {
+					error!(
+						"Not linking <file_path_id = '{}'> to <object_id = '{}'>, as they share a cas_id but their contents differ",
+						file_path_id, object.id
+					);
+				}
+
+				matches
+			}),
+			None => {
+				if let Some(content_hash) = file_path_ids
+					.first()
+					.and_then(|file_path_id| content_hashes.get(file_path_id))
+				{
+					content_hash_updates.push(library.db.object().update(
+						object::id::equals(object.id),
+						vec![
+							object::content_hash::set(Some(content_hash.hash.clone())),
+							object::content_hash_kind::set(Some(content_hash.kind.int_value())),
+						],
+					));
+				}
+			}
+		}
+	}
+
+	if !content_hash_updates.is_empty() {
+		library.db._batch(content_hash_updates).await?;
+	}
+
+	Ok(())
+}
+
+/// This reads the native attributes (e.g. macOS tags) of the files that were just linked to each object, and records them against it.
+///
+/// Failing to record them doesn't fail the step, as the objects themselves have already been identified.
+async fn link_native_attributes(
+	library: &LibraryContext,
+	location_path: &Path,
+	file_paths: &[file_path::Data],
+	objects: &[object::Data],
+	cas_id_lookup: &HashMap<String, Vec<i32>>,
+) {
+	let attributes = objects
+		.iter()
+		.map(|object| {
+			let file_path_ids = cas_id_lookup
+				.get(&object.cas_id)
+				.map(Vec::as_slice)
+				.unwrap_or_default();
+
+			(
+				object.id,
+				file_paths
+					.iter()
+					.filter(|file_path| file_path_ids.contains(&file_path.id))
+					.flat_map(|file_path| {
+						read_native_attributes(&location_path.join(&file_path.materialized_path))
+					})
+					.collect(),
+			)
+		})
+		.collect::<Vec<_>>();
+
+	if let Err(e) = record_native_attributes(library, attributes).await {
+		error!("Error recording native attributes: {:#?}", e);
+	}
+}
+
+/// This generates an object for each file path, alongside each file path's content hash.
 async fn generate_provisional_objects(
 	location_path: impl AsRef<Path>,
 	file_paths: &[file_path::Data],
-) -> HashMap<i32, (String, String, Vec<object::SetParam>)> {
+) -> (
+	HashMap<i32, (String, String, Vec<object::SetParam>)>,
+	HashMap<i32, ContentHash>,
+) {
 	let mut provisional_objects = HashMap::with_capacity(file_paths.len());
+	let mut content_hashes = HashMap::with_capacity(file_paths.len());
 
 	// analyze each file_path
 	let location_path = location_path.as_ref();
@@ -139,7 +242,10 @@ async fn generate_provisional_objects(
 				size_str,
 				kind,
 				date_created,
+				content_hash,
 			}) => {
+				content_hashes.insert(file_path_id, content_hash.clone());
+
 				// create entry into chunks for created file data
 				provisional_objects.insert(
 					file_path_id,
@@ -149,6 +255,8 @@ async fn generate_provisional_objects(
 						vec![
 							object::date_created::set(date_created),
 							object::kind::set(kind.int_value()),
+							object::content_hash::set(Some(content_hash.hash)),
+							object::content_hash_kind::set(Some(content_hash.kind.int_value())),
 						],
 					),
 				);
@@ -159,7 +267,7 @@ async fn generate_provisional_objects(
 			}
 		};
 	}
-	provisional_objects
+	(provisional_objects, content_hashes)
 }
 
 async fn identifier_job_step(
@@ -171,7 +279,8 @@ async fn identifier_job_step(
 	let location_path = location_path.as_ref();
 
 	// generate objects for all file paths
-	let provisional_objects = generate_provisional_objects(location_path, file_paths).await;
+	let (provisional_objects, content_hashes) =
+		generate_provisional_objects(location_path, file_paths).await;
 
 	let unique_cas_ids = provisional_objects
 		.values()
@@ -206,6 +315,14 @@ async fn identifier_job_step(
 		existing_objects.len()
 	);
 
+	check_content_hashes(
+		library,
+		&existing_objects,
+		&content_hashes,
+		&mut cas_id_lookup,
+	)
+	.await?;
+
 	let existing_objects_linked = if !existing_objects.is_empty() {
 		// link file_path.object_id to existing objects
 		batch_update_file_paths(library, location_id, &existing_objects, &cas_id_lookup)
@@ -215,6 +332,15 @@ async fn identifier_job_step(
 		0
 	};
 
+	link_native_attributes(
+		library,
+		location_path,
+		file_paths,
+		&existing_objects,
+		&cas_id_lookup,
+	)
+	.await;
+
 	let existing_object_cas_ids = existing_objects
 		.iter()
 		.map(|object| object.cas_id.clone())
@@ -280,6 +406,15 @@ async fn identifier_job_step(
 
 		if !created_files.is_empty() {
 			batch_update_file_paths(library, location_id, &created_files, &cas_id_lookup).await?;
+
+			link_native_attributes(
+				library,
+				location_path,
+				file_paths,
+				&created_files,
+				&cas_id_lookup,
+			)
+			.await;
 		}
 	}
 
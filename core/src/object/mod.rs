@@ -1,8 +1,14 @@
+pub mod album;
 pub mod cas;
 pub mod fs;
 pub mod identifier_job;
+pub mod native_attributes;
 pub mod preview;
+pub mod search;
+pub mod tag;
+pub mod text;
 pub mod validation;
+pub mod version;
 
 // Objects are primarily created by the identifier from Paths
 // Some Objects are purely virtual, unless they have one or more associated Paths, which refer to a file found in a Location
@@ -0,0 +1,251 @@
+use crate::{
+	library::LibraryContext,
+	prisma::{native_attribute, object},
+};
+
+use std::{collections::HashSet, path::Path};
+
+use int_enum::IntEnum;
+use prisma_client_rust::QueryError;
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// Values that are larger than this aren't kept, as they're almost always binary data rather than something a user would search for.
+const MAX_VALUE_LEN: usize = 4096;
+
+/// macOS keeps Finder tags (and their colours) within this attribute, as a property list of strings.
+const MACOS_TAGS: &str = "com.apple.metadata:_kMDItemUserTags";
+/// macOS keeps Finder comments within this attribute, as a property list containing a single string.
+const MACOS_FINDER_COMMENT: &str = "com.apple.metadata:kMDItemFinderComment";
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, Eq, PartialEq, Hash, IntEnum)]
+pub enum NativeAttributeKind {
+	ExtendedAttribute = 0,
+	/// an NTFS alternate data stream (e.g. `Zone.Identifier`, which records where a file was downloaded from)
+	AlternateDataStream = 1,
+}
+
+/// This is a platform-native piece of metadata that was found on a file, decoded to text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NativeAttribute {
+	pub kind: NativeAttributeKind,
+	pub name: String,
+	pub value: String,
+}
+
+/// This reads the native attributes that are worth keeping from a file: macOS tags and Finder comments, Linux `user.*` attributes and NTFS alternate data streams.
+///
+/// It's best-effort, as plenty of filesystems don't support any of them, so nothing that can't be read (or decoded to text) is returned.
+pub fn read_native_attributes(path: &Path) -> Vec<NativeAttribute> {
+	let mut attributes = read_xattrs(path);
+	attributes.extend(read_alternate_data_streams(path));
+
+	attributes
+		.retain(|attribute| !attribute.value.is_empty() && attribute.value.len() <= MAX_VALUE_LEN);
+
+	attributes
+}
+
+#[cfg(unix)]
+fn read_xattrs(path: &Path) -> Vec<NativeAttribute> {
+	let names = match xattr::list(path) {
+		Ok(names) => names,
+		Err(_) => return Vec::new(),
+	};
+
+	names
+		.filter_map(|name| {
+			let name = name.to_str()?.to_string();
+
+			if !name.starts_with("user.") && name != MACOS_TAGS && name != MACOS_FINDER_COMMENT {
+				return None;
+			}
+
+			let raw = xattr::get(path, &name).ok()??;
+
+			let value = match name.as_str() {
+				// each tag is followed by its colour's index (e.g. `Work\n6`), which isn't kept
+				MACOS_TAGS => plist::from_bytes::<Vec<String>>(&raw)
+					.ok()?
+					.iter()
+					.filter_map(|tag| tag.split('\n').next())
+					.collect::<Vec<_>>()
+					.join("\n"),
+				MACOS_FINDER_COMMENT => plist::from_bytes::<String>(&raw).ok()?,
+				_ => match String::from_utf8(raw) {
+					Ok(value) => value,
+					Err(_) => {
+						debug!("Skipping binary extended attribute {} of {:?}", name, path);
+						return None;
+					}
+				},
+			};
+
+			Some(NativeAttribute {
+				kind: NativeAttributeKind::ExtendedAttribute,
+				name,
+				value,
+			})
+		})
+		.collect()
+}
+
+#[cfg(not(unix))]
+fn read_xattrs(_path: &Path) -> Vec<NativeAttribute> {
+	Vec::new()
+}
+
+#[cfg(windows)]
+fn read_alternate_data_streams(path: &Path) -> Vec<NativeAttribute> {
+	use std::{ffi::OsString, os::windows::ffi::OsStrExt, os::windows::ffi::OsStringExt};
+
+	use windows_sys::Win32::{
+		Foundation::INVALID_HANDLE_VALUE,
+		Storage::FileSystem::{
+			FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard,
+			WIN32_FIND_STREAM_DATA,
+		},
+	};
+
+	let wide_path = path
+		.as_os_str()
+		.encode_wide()
+		.chain(Some(0))
+		.collect::<Vec<_>>();
+
+	let mut names = Vec::new();
+
+	// SAFETY: the path is null-terminated, and the stream data is only read once the call has succeeded
+	unsafe {
+		let mut data = std::mem::zeroed::<WIN32_FIND_STREAM_DATA>();
+
+		let handle = FindFirstStreamW(
+			wide_path.as_ptr(),
+			FindStreamInfoStandard,
+			&mut data as *mut _ as *mut _,
+			0,
+		);
+
+		if handle == INVALID_HANDLE_VALUE {
+			return Vec::new();
+		}
+
+		loop {
+			let len = data
+				.cStreamName
+				.iter()
+				.position(|&c| c == 0)
+				.unwrap_or(data.cStreamName.len());
+
+			names.push(OsString::from_wide(&data.cStreamName[..len]));
+
+			if FindNextStreamW(handle, &mut data as *mut _ as *mut _) == 0 {
+				break;
+			}
+		}
+
+		FindClose(handle);
+	}
+
+	names
+		.into_iter()
+		.filter_map(|name| {
+			// stream names look like `:Zone.Identifier:$DATA`, and the file's main contents are the unnamed `::$DATA` stream
+			let name = name
+				.to_str()?
+				.strip_prefix(':')?
+				.strip_suffix(":$DATA")?
+				.to_string();
+
+			if name.is_empty() {
+				return None;
+			}
+
+			let mut stream_path = path.as_os_str().to_os_string();
+			stream_path.push(format!(":{name}"));
+
+			let value = String::from_utf8(std::fs::read(stream_path).ok()?).ok()?;
+
+			Some(NativeAttribute {
+				kind: NativeAttributeKind::AlternateDataStream,
+				name,
+				value,
+			})
+		})
+		.collect()
+}
+
+#[cfg(not(windows))]
+fn read_alternate_data_streams(_path: &Path) -> Vec<NativeAttribute> {
+	Vec::new()
+}
+
+/// This replaces the native attributes that are recorded for each object with the ones that were just read from its files.
+///
+/// An object keeps the attributes of all of the files it was given, and an attribute that's on more than one of them keeps the first value.
+pub(crate) async fn record_native_attributes(
+	library: &LibraryContext,
+	attributes: Vec<(i32, Vec<NativeAttribute>)>, // (object ID, attributes)
+) -> Result<(), QueryError> {
+	if attributes.is_empty() {
+		return Ok(());
+	}
+
+	let object_ids = attributes
+		.iter()
+		.map(|(object_id, _)| *object_id)
+		.collect::<Vec<_>>();
+
+	let mut seen = HashSet::new();
+
+	let creates = attributes
+		.into_iter()
+		.flat_map(|(object_id, attributes)| {
+			attributes
+				.into_iter()
+				.map(move |attribute| (object_id, attribute))
+		})
+		.filter(|(object_id, attribute)| {
+			seen.insert((*object_id, attribute.kind, attribute.name.clone()))
+		})
+		.map(|(object_id, attribute)| {
+			library.db.native_attribute().create(
+				attribute.kind.int_value(),
+				attribute.name,
+				attribute.value,
+				object::id::equals(object_id),
+				vec![],
+			)
+		})
+		.collect::<Vec<_>>();
+
+	debug!(
+		"Recording {} native attributes for {} objects",
+		creates.len(),
+		object_ids.len()
+	);
+
+	// the old attributes are only removed alongside the new ones being written, so they aren't lost if this fails
+	library
+		.db
+		._batch((
+			library
+				.db
+				.native_attribute()
+				.delete_many(vec![native_attribute::object_id::in_vec(object_ids)]),
+			creates,
+		))
+		.await?;
+
+	Ok(())
+}
+
+/// This finds the objects with a native attribute containing the given text, optionally limited to attributes with the given name.
+pub(crate) fn search_filters(query: &str, name: Option<String>) -> Vec<object::WhereParam> {
+	let mut params = vec![native_attribute::value::contains(query.to_string())];
+	params.extend(name.map(native_attribute::name::equals));
+
+	vec![object::native_attributes::some(params)]
+}
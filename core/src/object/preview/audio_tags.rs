@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use lofty::{Accessor, AudioFile, ItemKey, LoftyError, PictureType, Tag, TaggedFile};
+use sd_file_ext::extensions::AudioExtension;
+
+/// AudioTags are the tags of a song (from its ID3, Vorbis, APE or MP4 tags), which music is browsed by.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AudioTags {
+	pub title: Option<String>,
+	pub artist: Option<String>,
+	pub album: Option<String>,
+	pub album_artist: Option<String>,
+	pub genre: Option<String>,
+	pub track_number: Option<u32>,
+	pub year: Option<i32>,
+	pub duration_seconds: Option<i32>,
+}
+
+/// This returns whether the tags of an audio format can be read.
+pub fn can_extract_audio_tags(audio_extension: &AudioExtension) -> bool {
+	use AudioExtension::*;
+	matches!(
+		audio_extension,
+		Mp3 | M4a | Wav | Aiff | Aif | Flac | Ogg | Oga | Opus | Aac | Wv
+	)
+}
+
+pub fn extract_audio_tags(path: impl AsRef<Path>) -> Result<AudioTags, LoftyError> {
+	let tagged_file = lofty::read_from_path(path)?;
+	let duration = tagged_file.properties().duration();
+
+	let mut tags = AudioTags {
+		duration_seconds: (!duration.is_zero()).then(|| duration.as_secs() as i32),
+		..Default::default()
+	};
+
+	if let Some(tag) = primary_tag(&tagged_file) {
+		tags.title = non_empty(tag.title().map(|title| title.to_string()));
+		tags.artist = non_empty(tag.artist().map(|artist| artist.to_string()));
+		tags.album = non_empty(tag.album().map(|album| album.to_string()));
+		tags.album_artist = non_empty(tag.get_string(&ItemKey::AlbumArtist).map(Into::into));
+		tags.genre = non_empty(tag.genre().map(|genre| genre.to_string()));
+		tags.track_number = tag.track().filter(|track| *track > 0);
+		// the year is sometimes only recorded as part of the full recording date (e.g. "2009-03-20")
+		tags.year = tag
+			.get_string(&ItemKey::Year)
+			.or_else(|| tag.get_string(&ItemKey::RecordingDate))
+			.and_then(|date| date.trim().get(..4))
+			.and_then(|year| year.parse().ok());
+	}
+
+	Ok(tags)
+}
+
+/// This returns the artwork that's embedded within an audio file, preferring its front cover.
+pub fn extract_audio_artwork(path: impl AsRef<Path>) -> Result<Option<Vec<u8>>, LoftyError> {
+	let tagged_file = lofty::read_from_path(path)?;
+
+	Ok(primary_tag(&tagged_file).and_then(|tag| {
+		let pictures = tag.pictures();
+
+		pictures
+			.iter()
+			.find(|picture| picture.pic_type() == PictureType::CoverFront)
+			.or_else(|| pictures.first())
+			.map(|picture| picture.data().to_vec())
+	}))
+}
+
+fn primary_tag(tagged_file: &TaggedFile) -> Option<&Tag> {
+	tagged_file
+		.primary_tag()
+		.or_else(|| tagged_file.first_tag())
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+	value
+		.map(|value| value.trim().to_string())
+		.filter(|value| !value.is_empty())
+}
@@ -0,0 +1,220 @@
+use crate::library::{LibraryContext, LibraryManager};
+
+use std::path::{Path, PathBuf};
+
+use sd_crypto::{
+	crypto::stream::{Algorithm, StreamDecryption, StreamEncryption},
+	fs::erase::{erase, DEFAULT_ERASE_PASSES},
+	primitives::{derive_key, generate_nonce, KEY_LEN, THUMBNAIL_CONTEXT},
+	Protected,
+};
+use thiserror::Error;
+use tokio::fs;
+use uuid::Uuid;
+
+use super::THUMBNAIL_CACHE_DIR_NAME;
+
+/// Encrypted thumbnails are stored alongside regular ones, but with this extension instead of `webp`.
+pub const ENCRYPTED_THUMBNAIL_EXTENSION: &str = "sdthumb";
+
+const THUMBNAIL_ALGORITHM: Algorithm = Algorithm::XChaCha20Poly1305;
+
+/// The library and key UUIDs are stored before the nonce, so we know how to decrypt the thumbnail.
+const PREFIX_LEN: usize = 32;
+
+#[derive(Error, Debug)]
+pub enum EncryptedThumbnailError {
+	#[error("I/O error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("Crypto error: {0}")]
+	Crypto(#[from] sd_crypto::Error),
+	#[error("Invalid encrypted thumbnail")]
+	InvalidFormat,
+	#[error("Library not found: <id = '{0}'>")]
+	LibraryNotFound(Uuid),
+}
+
+pub fn encrypted_thumbnail_path(data_directory: &Path, cas_id: &str) -> PathBuf {
+	data_directory
+		.join(THUMBNAIL_CACHE_DIR_NAME)
+		.join(cas_id)
+		.with_extension(ENCRYPTED_THUMBNAIL_EXTENSION)
+}
+
+/// This derives the thumbnail key for a mounted key, which is unique to this library.
+fn thumbnail_key(
+	library: &LibraryContext,
+	key_uuid: Uuid,
+) -> Result<Protected<[u8; KEY_LEN]>, sd_crypto::Error> {
+	let hashed_key = library.key_manager.access_keymount(key_uuid)?.hashed_key;
+
+	Ok(derive_key(
+		hashed_key,
+		*library.id.as_bytes(),
+		THUMBNAIL_CONTEXT,
+	))
+}
+
+/// This encrypts a thumbnail and writes it to the thumbnail cache.
+///
+/// The key must be mounted, and it'll need to be mounted again for the thumbnail to be decrypted.
+pub async fn write_encrypted_thumbnail(
+	library: &LibraryContext,
+	key_uuid: Uuid,
+	cas_id: &str,
+	media: &[u8],
+) -> Result<(), EncryptedThumbnailError> {
+	let nonce = generate_nonce(THUMBNAIL_ALGORITHM);
+
+	let encrypted = StreamEncryption::encrypt_bytes(
+		thumbnail_key(library, key_uuid)?,
+		&nonce,
+		THUMBNAIL_ALGORITHM,
+		media,
+		cas_id.as_bytes(),
+	)?;
+
+	let bytes = [
+		library.id.as_bytes().as_slice(),
+		key_uuid.as_bytes().as_slice(),
+		nonce.as_slice(),
+		encrypted.as_slice(),
+	]
+	.concat();
+
+	fs::write(
+		encrypted_thumbnail_path(&library.config().data_directory(), cas_id),
+		bytes,
+	)
+	.await?;
+
+	// a cached copy could have been decrypted with a different key
+	library.thumbnail_cache().remove(cas_id);
+
+	Ok(())
+}
+
+/// This replaces a plaintext thumbnail with an encrypted one, and securely erases the original.
+pub async fn encrypt_thumbnail(
+	library: &LibraryContext,
+	key_uuid: Uuid,
+	cas_id: &str,
+) -> Result<(), EncryptedThumbnailError> {
+	let path = library
+		.config()
+		.data_directory()
+		.join(THUMBNAIL_CACHE_DIR_NAME)
+		.join(cas_id)
+		.with_extension("webp");
+
+	let media = fs::read(&path).await?;
+	write_encrypted_thumbnail(library, key_uuid, cas_id, &media).await?;
+
+	let mut file = std::fs::OpenOptions::new().write(true).open(&path)?;
+	erase(&mut file, media.len(), DEFAULT_ERASE_PASSES)?;
+	file.sync_all()?;
+	drop(file);
+
+	fs::remove_file(&path).await?;
+
+	Ok(())
+}
+
+/// This decrypts a thumbnail from the cache, using whichever library it belongs to.
+///
+/// This will fail if the key that was used for encrypting it isn't currently mounted.
+pub async fn read_encrypted_thumbnail(
+	library_manager: &LibraryManager,
+	path: &Path,
+) -> Result<Protected<Vec<u8>>, EncryptedThumbnailError> {
+	let bytes = fs::read(path).await?;
+	let (library_id, ..) = split_encrypted_thumbnail(&bytes)?;
+
+	let library = library_manager
+		.get_ctx(library_id)
+		.await
+		.ok_or(EncryptedThumbnailError::LibraryNotFound(library_id))?;
+
+	decrypt_thumbnail(&library, path, &bytes)
+}
+
+/// This reads one of the library's thumbnails from the cache, decrypting it if needed.
+pub async fn read_thumbnail(
+	library: &LibraryContext,
+	cas_id: &str,
+) -> Result<Vec<u8>, EncryptedThumbnailError> {
+	let data_directory = library.config().data_directory();
+
+	let path = data_directory
+		.join(THUMBNAIL_CACHE_DIR_NAME)
+		.join(cas_id)
+		.with_extension("webp");
+
+	match fs::read(&path).await {
+		Ok(media) => Ok(media),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+			let path = encrypted_thumbnail_path(&data_directory, cas_id);
+			let bytes = fs::read(&path).await?;
+
+			Ok(decrypt_thumbnail(library, &path, &bytes)?.expose().clone())
+		}
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// This splits an encrypted thumbnail into its library ID, key UUID, nonce and encrypted bytes.
+fn split_encrypted_thumbnail(
+	bytes: &[u8],
+) -> Result<(Uuid, Uuid, &[u8], &[u8]), EncryptedThumbnailError> {
+	let nonce_len = THUMBNAIL_ALGORITHM.nonce_len();
+
+	if bytes.len() <= PREFIX_LEN + nonce_len {
+		return Err(EncryptedThumbnailError::InvalidFormat);
+	}
+
+	let (prefix, rest) = bytes.split_at(PREFIX_LEN);
+	let (nonce, encrypted) = rest.split_at(nonce_len);
+
+	let library_id =
+		Uuid::from_slice(&prefix[..16]).map_err(|_| EncryptedThumbnailError::InvalidFormat)?;
+	let key_uuid =
+		Uuid::from_slice(&prefix[16..]).map_err(|_| EncryptedThumbnailError::InvalidFormat)?;
+
+	Ok((library_id, key_uuid, nonce, encrypted))
+}
+
+fn decrypt_thumbnail(
+	library: &LibraryContext,
+	path: &Path,
+	bytes: &[u8],
+) -> Result<Protected<Vec<u8>>, EncryptedThumbnailError> {
+	let (library_id, key_uuid, nonce, encrypted) = split_encrypted_thumbnail(bytes)?;
+
+	if library_id != library.id {
+		return Err(EncryptedThumbnailError::LibraryNotFound(library_id));
+	}
+
+	// the cas_id is authenticated, so thumbnails can't be swapped around
+	let cas_id = path
+		.file_stem()
+		.and_then(|stem| stem.to_str())
+		.ok_or(EncryptedThumbnailError::InvalidFormat)?;
+
+	let thumbnail_cache = library.thumbnail_cache();
+
+	if let Some(media) = thumbnail_cache.get(library.id, cas_id) {
+		return Ok(Protected::new(media));
+	}
+
+	let media = StreamDecryption::decrypt_bytes(
+		thumbnail_key(library, key_uuid)?,
+		nonce,
+		THUMBNAIL_ALGORITHM,
+		encrypted,
+		cas_id.as_bytes(),
+	)?;
+
+	thumbnail_cache.insert(library, key_uuid, cas_id, media.clone());
+
+	Ok(media)
+}
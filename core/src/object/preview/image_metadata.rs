@@ -0,0 +1,487 @@
+use std::{
+	fs::File,
+	io::{self, BufRead, BufReader, Read, Seek},
+	path::Path,
+};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone};
+use exif::{Exif, In, Reader, Tag, Value};
+use image::DynamicImage;
+
+/// XMP packets are almost always near the start of a file, so only this much of it is searched for one.
+const XMP_SEARCH_LIMIT: u64 = 4 * 1024 * 1024;
+const XMP_START: &[u8] = b"<x:xmpmeta";
+const XMP_END: &[u8] = b"</x:xmpmeta>";
+
+/// JPEGs store Photoshop's image resources (including the IPTC block) within an APP13 segment, after this signature.
+const PHOTOSHOP_SIGNATURE: &[u8] = b"Photoshop 3.0\0";
+const IPTC_RESOURCE_ID: u16 = 0x0404;
+
+/// ImageMetadata is what's known about how and where a photo was taken, from its EXIF, XMP and IPTC metadata.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ImageMetadata {
+	/// dates that were recorded without an offset are in the camera's local time, so they're kept as if they were UTC
+	pub capture_date: Option<DateTime<FixedOffset>>,
+	pub camera_make: Option<String>,
+	pub camera_model: Option<String>,
+	pub camera_software: Option<String>,
+	/// the EXIF orientation (1-8)
+	pub orientation: Option<u32>,
+	pub longitude: Option<f64>,
+	pub latitude: Option<f64>,
+	/// the dimensions of the photo once it's been oriented
+	pub width: Option<u32>,
+	pub height: Option<u32>,
+}
+
+impl ImageMetadata {
+	/// This fills in anything that's missing from another source, so whatever was found first takes precedence.
+	fn merge(&mut self, other: ImageMetadata) {
+		self.capture_date = self.capture_date.or(other.capture_date);
+		self.camera_make = self.camera_make.take().or(other.camera_make);
+		self.camera_model = self.camera_model.take().or(other.camera_model);
+		self.camera_software = self.camera_software.take().or(other.camera_software);
+		self.orientation = self.orientation.or(other.orientation);
+
+		// the coordinates only make sense together
+		if self.longitude.is_none() || self.latitude.is_none() {
+			self.longitude = other.longitude;
+			self.latitude = other.latitude;
+		}
+	}
+}
+
+/// This extracts the metadata of a photo, preferring EXIF to XMP and XMP to IPTC whenever they disagree.
+///
+/// Missing or malformed metadata isn't an error, it's just left out.
+pub fn extract_image_metadata(path: impl AsRef<Path>) -> io::Result<ImageMetadata> {
+	let path = path.as_ref();
+	let mut file = BufReader::new(File::open(path)?);
+
+	let mut metadata = read_exif(&mut file).map_or_else(Default::default, |exif| parse_exif(&exif));
+
+	file.rewind()?;
+	if let Some(xmp) = find_xmp_packet(&mut file)? {
+		metadata.merge(parse_xmp(&xmp));
+	}
+
+	file.rewind()?;
+	if let Some(iptc) = find_iptc_block(&mut file) {
+		metadata.merge(parse_iptc(&iptc));
+	}
+
+	if let Ok((width, height)) = image::image_dimensions(path) {
+		// orientations 5 through 8 turn the photo on its side
+		let (width, height) = if matches!(metadata.orientation, Some(5..=8)) {
+			(height, width)
+		} else {
+			(width, height)
+		};
+
+		metadata.width = Some(width);
+		metadata.height = Some(height);
+	}
+
+	Ok(metadata)
+}
+
+/// This reads just the EXIF orientation of a photo, so it can be displayed the right way up.
+pub fn read_orientation(path: impl AsRef<Path>) -> Option<u32> {
+	let exif = read_exif(&mut BufReader::new(File::open(path).ok()?))?;
+
+	exif_orientation(&exif)
+}
+
+/// This rotates and flips an image by its EXIF orientation.
+pub fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+	match orientation {
+		2 => image.fliph(),
+		3 => image.rotate180(),
+		4 => image.flipv(),
+		5 => image.rotate90().fliph(),
+		6 => image.rotate90(),
+		7 => image.rotate270().fliph(),
+		8 => image.rotate270(),
+		_ => image,
+	}
+}
+
+fn read_exif(reader: &mut (impl BufRead + Seek)) -> Option<Exif> {
+	Reader::new().read_from_container(reader).ok()
+}
+
+fn parse_exif(exif: &Exif) -> ImageMetadata {
+	let (longitude, latitude) = match (
+		exif_coordinate(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W'),
+		exif_coordinate(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S'),
+	) {
+		(Some(longitude), Some(latitude)) => (Some(longitude), Some(latitude)),
+		_ => (None, None),
+	};
+
+	ImageMetadata {
+		capture_date: exif_date(exif, Tag::DateTimeOriginal, Tag::OffsetTimeOriginal)
+			.or_else(|| exif_date(exif, Tag::DateTimeDigitized, Tag::OffsetTimeDigitized))
+			.or_else(|| exif_date(exif, Tag::DateTime, Tag::OffsetTime)),
+		camera_make: exif_string(exif, Tag::Make),
+		camera_model: exif_string(exif, Tag::Model),
+		camera_software: exif_string(exif, Tag::Software),
+		orientation: exif_orientation(exif),
+		longitude,
+		latitude,
+		..Default::default()
+	}
+}
+
+fn exif_orientation(exif: &Exif) -> Option<u32> {
+	exif.get_field(Tag::Orientation, In::PRIMARY)
+		.and_then(|field| field.value.get_uint(0))
+		.filter(|orientation| (1..=8).contains(orientation))
+}
+
+fn exif_string(exif: &Exif, tag: Tag) -> Option<String> {
+	match &exif.get_field(tag, In::PRIMARY)?.value {
+		Value::Ascii(values) => values.first().and_then(|value| trimmed(value)),
+		_ => None,
+	}
+}
+
+fn exif_date(exif: &Exif, date_tag: Tag, offset_tag: Tag) -> Option<DateTime<FixedOffset>> {
+	let mut date = match &exif.get_field(date_tag, In::PRIMARY)?.value {
+		Value::Ascii(values) => exif::DateTime::from_ascii(values.first()?).ok()?,
+		_ => return None,
+	};
+
+	if let Some(Value::Ascii(values)) = exif
+		.get_field(offset_tag, In::PRIMARY)
+		.map(|field| &field.value)
+	{
+		if let Some(offset) = values.first() {
+			// the offset is optional, so a malformed one is just ignored
+			let _ = date.parse_offset(offset);
+		}
+	}
+
+	let naive = NaiveDate::from_ymd_opt(date.year.into(), date.month.into(), date.day.into())?
+		.and_hms_nano_opt(
+			date.hour.into(),
+			date.minute.into(),
+			date.second.into(),
+			date.nanosecond.unwrap_or_default(),
+		)?;
+
+	FixedOffset::east_opt(i32::from(date.offset.unwrap_or_default()) * 60)?
+		.from_local_datetime(&naive)
+		.single()
+}
+
+fn exif_coordinate(exif: &Exif, tag: Tag, ref_tag: Tag, negative_ref: u8) -> Option<f64> {
+	let degrees = match &exif.get_field(tag, In::PRIMARY)?.value {
+		Value::Rational(parts) if parts.len() >= 3 => {
+			parts[0].to_f64() + parts[1].to_f64() / 60.0 + parts[2].to_f64() / 3600.0
+		}
+		_ => return None,
+	};
+
+	let is_negative = matches!(
+		&exif.get_field(ref_tag, In::PRIMARY)?.value,
+		Value::Ascii(values) if values.first().and_then(|value| value.first()) == Some(&negative_ref)
+	);
+
+	degrees
+		.is_finite()
+		.then_some(if is_negative { -degrees } else { degrees })
+}
+
+fn find_xmp_packet(reader: &mut impl Read) -> io::Result<Option<String>> {
+	let mut bytes = Vec::new();
+	reader
+		.by_ref()
+		.take(XMP_SEARCH_LIMIT)
+		.read_to_end(&mut bytes)?;
+
+	Ok(find(&bytes, XMP_START).and_then(|start| {
+		let end = start + find(&bytes[start..], XMP_END)? + XMP_END.len();
+
+		Some(String::from_utf8_lossy(&bytes[start..end]).into_owned())
+	}))
+}
+
+fn parse_xmp(xmp: &str) -> ImageMetadata {
+	let (longitude, latitude) = match (
+		xmp_property(xmp, "exif:GPSLongitude").and_then(parse_xmp_coordinate),
+		xmp_property(xmp, "exif:GPSLatitude").and_then(parse_xmp_coordinate),
+	) {
+		(Some(longitude), Some(latitude)) => (Some(longitude), Some(latitude)),
+		_ => (None, None),
+	};
+
+	ImageMetadata {
+		capture_date: [
+			"exif:DateTimeOriginal",
+			"photoshop:DateCreated",
+			"xmp:CreateDate",
+		]
+		.into_iter()
+		.find_map(|name| xmp_property(xmp, name).and_then(parse_xmp_date)),
+		camera_make: xmp_property(xmp, "tiff:Make").and_then(|value| trimmed(value.as_bytes())),
+		camera_model: xmp_property(xmp, "tiff:Model").and_then(|value| trimmed(value.as_bytes())),
+		camera_software: xmp_property(xmp, "xmp:CreatorTool")
+			.and_then(|value| trimmed(value.as_bytes())),
+		orientation: xmp_property(xmp, "tiff:Orientation")
+			.and_then(|value| value.parse().ok())
+			.filter(|orientation| (1..=8).contains(orientation)),
+		longitude,
+		latitude,
+		..Default::default()
+	}
+}
+
+/// This finds a simple XMP property, which is either written as an attribute or as an element.
+fn xmp_property<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+	let attribute = format!("{}=\"", name);
+	if let Some(start) = xmp.find(&attribute) {
+		let value = &xmp[start + attribute.len()..];
+		return value.find('"').map(|end| value[..end].trim());
+	}
+
+	let element = format!("<{}>", name);
+	let start = xmp.find(&element)? + element.len();
+	let value = &xmp[start..];
+	value.find('<').map(|end| value[..end].trim())
+}
+
+fn parse_xmp_date(value: &str) -> Option<DateTime<FixedOffset>> {
+	DateTime::parse_from_rfc3339(value).ok().or_else(|| {
+		let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+			.or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M"))
+			.ok()
+			.or_else(|| {
+				NaiveDate::parse_from_str(value, "%Y-%m-%d")
+					.ok()?
+					.and_hms_opt(0, 0, 0)
+			})?;
+
+		without_offset(naive)
+	})
+}
+
+/// XMP coordinates are written as degrees and minutes (and optionally seconds), followed by their direction, e.g. "51,30.4N".
+fn parse_xmp_coordinate(value: &str) -> Option<f64> {
+	let direction = value.chars().last()?;
+	let parts = value[..value.len() - direction.len_utf8()].split(',');
+
+	let degrees = parts
+		.zip([1.0, 60.0, 3600.0])
+		.map(|(part, divisor)| part.trim().parse::<f64>().map(|part| part / divisor))
+		.sum::<Result<f64, _>>()
+		.ok()
+		.filter(|degrees| degrees.is_finite())?;
+
+	match direction {
+		'N' | 'E' => Some(degrees),
+		'S' | 'W' => Some(-degrees),
+		_ => None,
+	}
+}
+
+/// This finds the IPTC block of a JPEG, which is stored as a Photoshop image resource.
+fn find_iptc_block(reader: &mut impl Read) -> Option<Vec<u8>> {
+	let mut marker = [0; 2];
+	reader.read_exact(&mut marker).ok()?;
+	if marker != [0xFF, 0xD8] {
+		return None;
+	}
+
+	loop {
+		reader.read_exact(&mut marker).ok()?;
+		// metadata segments all come before the image data (which starts at SOS) or the end of the image
+		if marker[0] != 0xFF || marker[1] == 0xDA || marker[1] == 0xD9 {
+			return None;
+		}
+
+		let mut length = [0; 2];
+		reader.read_exact(&mut length).ok()?;
+		let length = u64::from(u16::from_be_bytes(length).saturating_sub(2));
+		let mut segment = reader.by_ref().take(length);
+
+		// only APP13 segments can contain image resources
+		if marker[1] != 0xED {
+			io::copy(&mut segment, &mut io::sink()).ok()?;
+			continue;
+		}
+
+		let mut bytes = Vec::new();
+		segment.read_to_end(&mut bytes).ok()?;
+
+		if let Some(block) = bytes
+			.strip_prefix(PHOTOSHOP_SIGNATURE)
+			.and_then(|resources| find_photoshop_resource(resources, IPTC_RESOURCE_ID))
+		{
+			return Some(block.to_vec());
+		}
+	}
+}
+
+fn find_photoshop_resource(mut resources: &[u8], id: u16) -> Option<&[u8]> {
+	while let Some(resource) = resources.strip_prefix(b"8BIM") {
+		let resource_id = u16::from_be_bytes(resource.get(0..2)?.try_into().ok()?);
+
+		// the name is a pascal string, padded so that it (alongside its length) has an even length
+		let size_offset = 2 + ((*resource.get(2)? as usize + 2) & !1);
+		let size = u32::from_be_bytes(
+			resource
+				.get(size_offset..size_offset + 4)?
+				.try_into()
+				.ok()?,
+		) as usize;
+		let data_offset = size_offset + 4;
+		let data = resource.get(data_offset..data_offset + size)?;
+
+		if resource_id == id {
+			return Some(data);
+		}
+
+		// the data is padded to an even length too
+		resources = resource.get(data_offset + size + size % 2..)?;
+	}
+
+	None
+}
+
+fn parse_iptc(mut block: &[u8]) -> ImageMetadata {
+	let (mut date, mut time) = (None, None);
+
+	while let [0x1C, record, dataset, size_high, size_low, rest @ ..] = block {
+		// extended datasets (with the high bit set) are only used for large values, which we don't need
+		if size_high & 0x80 != 0 {
+			break;
+		}
+
+		let size = u16::from_be_bytes([*size_high, *size_low]) as usize;
+		let value = match rest.get(..size) {
+			Some(value) => value,
+			None => break,
+		};
+
+		match (record, dataset) {
+			// date created, as "CCYYMMDD"
+			(2, 55) => date = Some(value),
+			// time created, as "HHMMSS±HHMM"
+			(2, 60) => time = Some(value),
+			_ => {}
+		}
+
+		block = &rest[size..];
+	}
+
+	ImageMetadata {
+		capture_date: date.and_then(|date| parse_iptc_date(date, time)),
+		..Default::default()
+	}
+}
+
+fn parse_iptc_date(date: &[u8], time: Option<&[u8]>) -> Option<DateTime<FixedOffset>> {
+	let date = std::str::from_utf8(date).ok()?;
+
+	time.and_then(|time| std::str::from_utf8(time).ok())
+		.and_then(|time| {
+			let date_time = format!("{}{}", date, time);
+
+			DateTime::parse_from_str(&date_time, "%Y%m%d%H%M%S%z")
+				.ok()
+				.or_else(|| {
+					without_offset(NaiveDateTime::parse_from_str(&date_time, "%Y%m%d%H%M%S").ok()?)
+				})
+		})
+		.or_else(|| {
+			without_offset(
+				NaiveDate::parse_from_str(date, "%Y%m%d")
+					.ok()?
+					.and_hms_opt(0, 0, 0)?,
+			)
+		})
+}
+
+/// Dates without an offset are in whatever the local time was, which is kept as is.
+fn without_offset(date: NaiveDateTime) -> Option<DateTime<FixedOffset>> {
+	FixedOffset::east_opt(0)?
+		.from_local_datetime(&date)
+		.single()
+}
+
+fn trimmed(value: &[u8]) -> Option<String> {
+	let value = String::from_utf8_lossy(value)
+		.trim_matches(|c: char| c == '\0' || c.is_whitespace())
+		.to_string();
+
+	(!value.is_empty()).then_some(value)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+	haystack
+		.windows(needle.len())
+		.position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::GenericImageView;
+
+	#[test]
+	fn test_parse_xmp() {
+		let xmp = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF><rdf:Description
+			xmp:CreateDate="2021-06-01T09:30:00"
+			exif:DateTimeOriginal="2021-05-31T18:45:12+02:00"
+			exif:GPSLatitude="51,30.5N"
+			exif:GPSLongitude="0,7.5W">
+			<tiff:Make>Canon</tiff:Make>
+			<tiff:Orientation>6</tiff:Orientation>
+		</rdf:Description></rdf:RDF></x:xmpmeta>"#;
+
+		let metadata = parse_xmp(xmp);
+
+		assert_eq!(
+			metadata.capture_date,
+			Some(DateTime::parse_from_rfc3339("2021-05-31T18:45:12+02:00").unwrap())
+		);
+		assert_eq!(metadata.camera_make.as_deref(), Some("Canon"));
+		assert_eq!(metadata.orientation, Some(6));
+		assert_eq!(metadata.latitude, Some(51.0 + 30.5 / 60.0));
+		assert_eq!(metadata.longitude, Some(-0.125));
+	}
+
+	#[test]
+	fn test_parse_iptc_resource() {
+		let mut iptc = Vec::new();
+		for (dataset, value) in [(55, &b"20190704"[..]), (60, &b"120000-0500"[..])] {
+			iptc.extend([0x1C, 2, dataset, 0, value.len() as u8]);
+			iptc.extend(value);
+		}
+
+		let mut resources = Vec::new();
+		// an unrelated resource, with an odd length and a name
+		resources.extend(b"8BIM\x03\xED\x03abc\x00\x00\x00\x01x\x00");
+		resources.extend(b"8BIM\x04\x04\x00\x00");
+		resources.extend((iptc.len() as u32).to_be_bytes());
+		resources.extend(&iptc);
+
+		let block = find_photoshop_resource(&resources, IPTC_RESOURCE_ID).unwrap();
+
+		assert_eq!(
+			parse_iptc(block).capture_date,
+			Some(DateTime::parse_from_rfc3339("2019-07-04T12:00:00-05:00").unwrap())
+		);
+	}
+
+	#[test]
+	fn test_apply_orientation() {
+		let image = DynamicImage::new_rgb8(4, 2);
+
+		assert_eq!(apply_orientation(image.clone(), 1).dimensions(), (4, 2));
+		assert_eq!(apply_orientation(image.clone(), 3).dimensions(), (4, 2));
+		assert_eq!(apply_orientation(image.clone(), 6).dimensions(), (2, 4));
+		assert_eq!(apply_orientation(image, 7).dimensions(), (2, 4));
+	}
+}
@@ -1,140 +1,163 @@
-// #[cfg(feature = "ffmpeg")]
-// use std::{ffi::OsStr, path::PathBuf};
-//
-// #[cfg(feature = "ffmpeg")]
-// use ffmpeg_next::{codec::context::Context, format, media::Type};
-//
-// #[derive(Default, Debug)]
-// pub struct MediaItem {
-// 	pub created_at: Option<String>,
-// 	pub brand: Option<String>,
-// 	pub model: Option<String>,
-// 	pub duration_seconds: i32,
-// 	pub best_video_stream_index: usize,
-// 	pub best_audio_stream_index: usize,
-// 	pub best_subtitle_stream_index: usize,
-// 	pub steams: Vec<Stream>,
-// }
-//
-// #[derive(Debug)]
-// pub struct Stream {
-// 	pub codec: String,
-// 	pub frames: f64,
-// 	pub duration_seconds: f64,
-// 	#[cfg(feature = "ffmpeg")]
-// 	pub kind: Option<StreamKind>,
-// }
-//
-// #[cfg(feature = "ffmpeg")]
-// #[derive(Debug, PartialEq, Eq)]
-// pub enum StreamKind {
-// 	Video(VideoStream),
-// 	Audio(AudioStream),
-// }
-//
-// #[derive(Debug, PartialEq, Eq)]
-// pub struct VideoStream {
-// 	pub width: u32,
-// 	pub height: u32,
-// 	pub aspect_ratio: String,
-// 	#[cfg(feature = "ffmpeg")]
-// 	pub format: format::Pixel,
-// 	pub bitrate: usize,
-// }
-//
-// #[derive(Debug, PartialEq, Eq)]
-// pub struct AudioStream {
-// 	pub channels: u16,
-// 	#[cfg(feature = "ffmpeg")]
-// 	pub format: format::Sample,
-// 	pub bitrate: usize,
-// 	pub rate: u32,
-// }
-//
-// #[cfg(feature = "ffmpeg")]
-// fn extract(iter: &mut ffmpeg_next::dictionary::Iter, key: &str) -> Option<String> {
-// 	iter.find(|k| k.0.contains(key)).map(|k| k.1.to_string())
-// }
-
-// #[cfg(feature = "ffmpeg")]
-// pub fn extract_media_data(path: &PathBuf) -> Result<MediaItem, ffmpeg_next::Error> {
-// 	use chrono::NaiveDateTime;
-//
-// 	ffmpeg_next::init().unwrap();
-//
-// 	let mut name = path
-// 		.file_name()
-// 		.and_then(OsStr::to_str)
-// 		.map(ToString::to_string)
-// 		.unwrap_or_default();
-//
-// 	// strip to exact potential date length and attempt to parse
-// 	name = name.chars().take(19).collect();
-// 	// specifically OBS uses this format for time, other checks could be added
-// 	let potential_date = NaiveDateTime::parse_from_str(&name, "%Y-%m-%d %H-%M-%S");
-//
-// 	let context = format::input(&path)?;
-//
-// 	let mut media_item = MediaItem::default();
-// 	let metadata = context.metadata();
-// 	let mut iter = metadata.iter();
-//
-// 	// creation_time is usually the creation date of the file
-// 	media_item.created_at = extract(&mut iter, "creation_time");
-// 	// apple photos use "com.apple.quicktime.creationdate", which we care more about than the creation_time
-// 	media_item.created_at = extract(&mut iter, "creationdate");
-// 	// fallback to potential time if exists
-// 	if media_item.created_at.is_none() {
-// 		media_item.created_at = potential_date.map(|d| d.to_string()).ok();
-// 	}
-// 	// origin metadata
-// 	media_item.brand = extract(&mut iter, "major_brand");
-// 	media_item.brand = extract(&mut iter, "make");
-// 	media_item.model = extract(&mut iter, "model");
-//
-// 	if let Some(stream) = context.streams().best(Type::Video) {
-// 		media_item.best_video_stream_index = stream.index();
-// 	}
-// 	if let Some(stream) = context.streams().best(Type::Audio) {
-// 		media_item.best_audio_stream_index = stream.index();
-// 	}
-// 	if let Some(stream) = context.streams().best(Type::Subtitle) {
-// 		media_item.best_subtitle_stream_index = stream.index();
-// 	}
-// 	media_item.duration_seconds = context.duration() as i32 / ffmpeg_next::ffi::AV_TIME_BASE;
-//
-// 	for stream in context.streams() {
-// 		let codec = Context::from_parameters(stream.parameters())?;
-//
-// 		let mut stream_item = Stream {
-// 			codec: codec.id().name().to_string(),
-// 			frames: stream.frames() as f64,
-// 			duration_seconds: stream.duration() as f64 * f64::from(stream.time_base()),
-// 			kind: None,
-// 		};
-//
-// 		if codec.medium() == Type::Video {
-// 			if let Ok(video) = codec.decoder().video() {
-// 				stream_item.kind = Some(StreamKind::Video(VideoStream {
-// 					bitrate: video.bit_rate(),
-// 					format: video.format(),
-// 					width: video.width(),
-// 					height: video.height(),
-// 					aspect_ratio: video.aspect_ratio().to_string(),
-// 				}));
-// 			}
-// 		} else if codec.medium() == Type::Audio {
-// 			if let Ok(audio) = codec.decoder().audio() {
-// 				stream_item.kind = Some(StreamKind::Audio(AudioStream {
-// 					channels: audio.channels(),
-// 					bitrate: audio.bit_rate(),
-// 					rate: audio.rate(),
-// 					format: audio.format(),
-// 				}));
-// 			}
-// 		}
-// 		media_item.steams.push(stream_item);
-// 	}
-//
-// 	Ok(media_item)
-// }
+#![cfg(feature = "ffmpeg")]
+
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset};
+use ffmpeg_next::{codec::context::Context, format, media::Type, DictionaryRef};
+
+/// VideoMetadata is what's known about a video's container and streams.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct VideoMetadata {
+	/// the name of the demuxer that was able to read the video, e.g. "matroska,webm"
+	pub container: Option<String>,
+	pub duration_seconds: Option<i32>,
+	/// the dimensions and frame rate of the best video stream
+	pub width: Option<u32>,
+	pub height: Option<u32>,
+	pub fps: Option<i32>,
+	/// the codecs of every stream, in order, e.g. "h264,aac"
+	pub codecs: Option<String>,
+	pub streams: i32,
+	pub capture_date: Option<DateTime<FixedOffset>>,
+	pub camera_make: Option<String>,
+	pub camera_model: Option<String>,
+	pub camera_software: Option<String>,
+	pub longitude: Option<f64>,
+	pub latitude: Option<f64>,
+}
+
+/// This reads the metadata of a video with ffmpeg, without decoding any of it.
+pub fn extract_video_metadata(path: impl AsRef<Path>) -> Result<VideoMetadata, ffmpeg_next::Error> {
+	ffmpeg_next::init()?;
+
+	let context = format::input(&path.as_ref())?;
+	let metadata = context.metadata();
+
+	let mut video = VideoMetadata {
+		container: Some(context.format().name().to_string()),
+		// the duration is in AV_TIME_BASE units, and is negative if it isn't known
+		duration_seconds: (context.duration() >= 0)
+			.then(|| (context.duration() / i64::from(ffmpeg_next::ffi::AV_TIME_BASE)) as i32),
+		streams: context.streams().count() as i32,
+		capture_date: find_tag(
+			&metadata,
+			&["com.apple.quicktime.creationdate", "creation_time"],
+		)
+		.and_then(parse_video_date),
+		camera_make: find_tag(
+			&metadata,
+			&[
+				"com.apple.quicktime.make",
+				"com.android.manufacturer",
+				"make",
+			],
+		),
+		camera_model: find_tag(
+			&metadata,
+			&["com.apple.quicktime.model", "com.android.model", "model"],
+		),
+		camera_software: find_tag(
+			&metadata,
+			&["com.apple.quicktime.software", "com.android.version"],
+		),
+		..Default::default()
+	};
+
+	if let Some((latitude, longitude)) = find_tag(
+		&metadata,
+		&["com.apple.quicktime.location.ISO6709", "location"],
+	)
+	.and_then(|location| parse_iso6709(&location))
+	{
+		video.latitude = Some(latitude);
+		video.longitude = Some(longitude);
+	}
+
+	let codecs = context
+		.streams()
+		.filter_map(|stream| Context::from_parameters(stream.parameters()).ok())
+		.map(|codec| codec.id().name().to_string())
+		.collect::<Vec<_>>();
+	video.codecs = (!codecs.is_empty()).then(|| codecs.join(","));
+
+	if let Some(stream) = context.streams().best(Type::Video) {
+		let frame_rate = f64::from(stream.avg_frame_rate());
+		video.fps = (frame_rate.is_finite() && frame_rate > 0.0).then(|| frame_rate.round() as i32);
+
+		if let Ok(decoder) =
+			Context::from_parameters(stream.parameters()).and_then(|codec| codec.decoder().video())
+		{
+			video.width = Some(decoder.width()).filter(|width| *width > 0);
+			video.height = Some(decoder.height()).filter(|height| *height > 0);
+		}
+	}
+
+	Ok(video)
+}
+
+fn find_tag(metadata: &DictionaryRef, keys: &[&str]) -> Option<String> {
+	keys.iter()
+		.find_map(|key| metadata.get(key))
+		.map(str::trim)
+		.filter(|value| !value.is_empty())
+		.map(ToString::to_string)
+}
+
+/// QuickTime dates have an offset without a colon (e.g. "2021-06-01T12:00:00+0200"), while `creation_time` is always in UTC.
+fn parse_video_date(value: String) -> Option<DateTime<FixedOffset>> {
+	DateTime::parse_from_rfc3339(&value)
+		.or_else(|_| DateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S%z"))
+		.ok()
+}
+
+/// This parses the latitude and longitude of an ISO 6709 location (e.g. "+37.3349-122.0090+010.000/"), ignoring its altitude.
+fn parse_iso6709(location: &str) -> Option<(f64, f64)> {
+	let location = location.trim_end_matches('/');
+
+	// every coordinate starts with its sign
+	let starts = location
+		.match_indices(|c| c == '+' || c == '-')
+		.map(|(start, _)| start)
+		.chain([location.len()])
+		.collect::<Vec<_>>();
+	let mut coordinates = starts
+		.windows(2)
+		.map(|bounds| location[bounds[0]..bounds[1]].parse::<f64>().ok());
+
+	let latitude = coordinates.next()??;
+	let longitude = coordinates.next()??;
+
+	((-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude))
+		.then_some((latitude, longitude))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_iso6709() {
+		assert_eq!(
+			parse_iso6709("+37.3349-122.0090+010.000/"),
+			Some((37.3349, -122.0090))
+		);
+		assert_eq!(
+			parse_iso6709("-33.8688+151.2093/"),
+			Some((-33.8688, 151.2093))
+		);
+		assert_eq!(parse_iso6709("+91.0000+000.0000/"), None);
+		assert_eq!(parse_iso6709("garbage"), None);
+	}
+
+	#[test]
+	fn test_parse_video_date() {
+		assert_eq!(
+			parse_video_date("2021-06-01T12:00:00+0200".to_string()),
+			DateTime::parse_from_rfc3339("2021-06-01T12:00:00+02:00").ok()
+		);
+		assert_eq!(
+			parse_video_date("2021-06-01T10:00:00.000000Z".to_string()),
+			DateTime::parse_from_rfc3339("2021-06-01T10:00:00Z").ok()
+		);
+	}
+}
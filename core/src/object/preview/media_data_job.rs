@@ -0,0 +1,388 @@
+use crate::{
+	invalidate_query,
+	job::{
+		JobError, JobLogLevel, JobPriority, JobReportUpdate, JobResult, JobState, StatefulJob,
+		WorkerContext,
+	},
+	library::gps_location_params,
+	prisma::{file_path, location, media_data},
+};
+
+use std::{
+	collections::{HashSet, VecDeque},
+	path::{Path, PathBuf},
+};
+
+use sd_file_ext::extensions::Extension;
+use serde::{Deserialize, Serialize};
+use tokio::task::spawn_blocking;
+use tracing::{info, warn};
+
+use super::{
+	can_extract_audio_tags, extract_audio_tags, extract_image_metadata, AudioTags, ImageMetadata,
+	ThumbnailError,
+};
+#[cfg(feature = "ffmpeg")]
+use super::{extract_video_metadata, VideoMetadata};
+
+pub const MEDIA_DATA_JOB_NAME: &str = "media_data_extractor";
+
+/// MediaDataJob extracts the metadata of the media within a location (e.g. when and where a photo was taken, how long a video is, or which album a song is from), for objects that don't have any yet.
+pub struct MediaDataJob {}
+
+#[derive(Serialize, Deserialize, Clone, Hash)]
+pub struct MediaDataJobInit {
+	pub location_id: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MediaDataJobState {
+	root_path: PathBuf,
+	extracted: usize,
+}
+
+file_path::select!(file_path_for_media_data {
+	materialized_path
+	object_id
+});
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+enum MediaDataJobStepKind {
+	Image,
+	Audio,
+	#[cfg(feature = "ffmpeg")]
+	Video,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MediaDataJobStep {
+	object_id: i32,
+	materialized_path: String,
+	kind: MediaDataJobStepKind,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for MediaDataJob {
+	type Init = MediaDataJobInit;
+	type Data = MediaDataJobState;
+	type Step = MediaDataJobStep;
+
+	fn name(&self) -> &'static str {
+		MEDIA_DATA_JOB_NAME
+	}
+
+	fn priority(&self) -> JobPriority {
+		JobPriority::Low
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let db = &ctx.library_ctx.db;
+
+		let location = db
+			.location()
+			.find_unique(location::id::equals(state.init.location_id))
+			.exec()
+			.await?
+			.ok_or(ThumbnailError::MissingLocation(state.init.location_id))?;
+
+		let root_path = location
+			.local_path
+			.map(PathBuf::from)
+			.ok_or(ThumbnailError::LocationLocalPath(location.id))?;
+
+		state.data = Some(MediaDataJobState {
+			root_path,
+			extracted: 0,
+		});
+
+		// the metadata of files within a vault would be stored in plaintext, so it's never extracted
+		if location.vault_key_id.is_some() {
+			info!(
+				"Skipping media data extraction for vault location {}",
+				location.id
+			);
+			return Ok(());
+		}
+
+		#[cfg_attr(not(feature = "ffmpeg"), allow(unused_mut))]
+		let mut kinds = vec![
+			(
+				MediaDataJobStepKind::Image,
+				sd_file_ext::extensions::ALL_IMAGE_EXTENSIONS
+					.iter()
+					.map(|extension| Extension::Image(*extension))
+					.collect::<Vec<_>>(),
+			),
+			(
+				MediaDataJobStepKind::Audio,
+				sd_file_ext::extensions::ALL_AUDIO_EXTENSIONS
+					.iter()
+					.filter(|extension| can_extract_audio_tags(extension))
+					.map(|extension| Extension::Audio(*extension))
+					.collect(),
+			),
+		];
+		#[cfg(feature = "ffmpeg")]
+		kinds.push((
+			MediaDataJobStepKind::Video,
+			sd_file_ext::extensions::ALL_VIDEO_EXTENSIONS
+				.iter()
+				.map(|extension| Extension::Video(*extension))
+				.collect(),
+		));
+
+		state.steps = VecDeque::new();
+
+		for (kind, extensions) in kinds {
+			let file_paths = db
+				.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(state.init.location_id),
+					file_path::is_dir::equals(false),
+					file_path::extension::in_vec(
+						extensions.iter().map(ToString::to_string).collect(),
+					),
+					file_path::object_id::not(None),
+				])
+				.select(file_path_for_media_data::select())
+				.exec()
+				.await?;
+
+			let object_ids = file_paths
+				.iter()
+				.filter_map(|file_path| file_path.object_id)
+				.collect::<HashSet<_>>();
+
+			// objects that already have media data are skipped, as are the duplicate file paths of an object
+			let mut seen = db
+				.media_data()
+				.find_many(vec![media_data::id::in_vec(
+					object_ids.into_iter().collect(),
+				)])
+				.exec()
+				.await?
+				.into_iter()
+				.map(|media_data| media_data.id)
+				.collect::<HashSet<_>>();
+
+			state
+				.steps
+				.extend(file_paths.into_iter().filter_map(|file_path| {
+					let object_id = file_path.object_id?;
+
+					seen.insert(object_id).then_some(MediaDataJobStep {
+						object_id,
+						materialized_path: file_path.materialized_path,
+						kind,
+					})
+				}));
+		}
+
+		info!(
+			"Found {} files without media data in location {}",
+			state.steps.len(),
+			location.id
+		);
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(state.steps.len()),
+			JobReportUpdate::Message(format!(
+				"Preparing to extract media data from {} files",
+				state.steps.len()
+			)),
+		]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Extracting media data from {}",
+			step.materialized_path
+		))]);
+
+		let path = data.root_path.join(&step.materialized_path);
+
+		let extracted = match step.kind {
+			MediaDataJobStepKind::Image => {
+				let extracted = {
+					let path = path.clone();
+					spawn_blocking(move || extract_image_metadata(path)).await?
+				};
+
+				extracted
+					.map(|metadata| image_media_data_params(&ctx, &path, metadata))
+					.map_err(|e| e.to_string())
+			}
+			MediaDataJobStepKind::Audio => {
+				let extracted = {
+					let path = path.clone();
+					spawn_blocking(move || extract_audio_tags(path)).await?
+				};
+
+				extracted
+					.map(audio_media_data_params)
+					.map_err(|e| e.to_string())
+			}
+			#[cfg(feature = "ffmpeg")]
+			MediaDataJobStepKind::Video => {
+				let extracted = {
+					let path = path.clone();
+					spawn_blocking(move || extract_video_metadata(path)).await?
+				};
+
+				extracted
+					.map(|metadata| Some(video_media_data_params(&ctx, &path, metadata)))
+					.map_err(|e| e.to_string())
+			}
+		};
+
+		match extracted {
+			Ok(Some(params)) => {
+				ctx.library_ctx
+					.db
+					.media_data()
+					.upsert(
+						media_data::id::equals(step.object_id),
+						(step.object_id, params.clone()),
+						params,
+					)
+					.exec()
+					.await?;
+
+				data.extracted += 1;
+			}
+			Ok(None) => {}
+			Err(e) => {
+				warn!("Failed to extract media data from {:?}: {}", path, e);
+				ctx.log(
+					JobLogLevel::Warning,
+					format!("Failed to extract media data: {}", e),
+					Some(path.display().to_string()),
+				);
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!(
+			"Finished extracting media data for location {}, {} files had media data",
+			state.init.location_id, data.extracted
+		);
+
+		if data.extracted > 0 {
+			invalidate_query!(ctx.library_ctx, "files.searchMedia");
+		}
+
+		Ok(Some(serde_json::json!({ "extracted": data.extracted })))
+	}
+}
+
+/// This returns the media data params for a photo, or `None` if there wasn't any metadata to store.
+fn image_media_data_params(
+	ctx: &WorkerContext,
+	path: &Path,
+	metadata: ImageMetadata,
+) -> Option<Vec<media_data::SetParam>> {
+	if metadata == ImageMetadata::default() {
+		return None;
+	}
+
+	let mut params = vec![
+		media_data::pixel_width::set(metadata.width.map(|width| width as i32)),
+		media_data::pixel_height::set(metadata.height.map(|height| height as i32)),
+		media_data::capture_date::set(metadata.capture_date),
+		media_data::capture_device_make::set(metadata.camera_make),
+		media_data::capture_device_model::set(metadata.camera_model),
+		media_data::capture_device_software::set(metadata.camera_software),
+		media_data::orientation::set(metadata.orientation.map(|orientation| orientation as i32)),
+	];
+	params.extend(gps_params(ctx, path, metadata.longitude, metadata.latitude));
+
+	Some(params)
+}
+
+/// This returns the media data params for a song, or `None` if it didn't have any tags.
+fn audio_media_data_params(tags: AudioTags) -> Option<Vec<media_data::SetParam>> {
+	if tags == AudioTags::default() {
+		return None;
+	}
+
+	Some(vec![
+		media_data::title::set(tags.title),
+		media_data::artist::set(tags.artist),
+		media_data::album::set(tags.album),
+		media_data::album_artist::set(tags.album_artist),
+		media_data::genre::set(tags.genre),
+		media_data::track_number::set(tags.track_number.map(|track_number| track_number as i32)),
+		media_data::year::set(tags.year),
+		media_data::duration_seconds::set(tags.duration_seconds),
+	])
+}
+
+#[cfg(feature = "ffmpeg")]
+fn video_media_data_params(
+	ctx: &WorkerContext,
+	path: &Path,
+	metadata: VideoMetadata,
+) -> Vec<media_data::SetParam> {
+	let mut params = vec![
+		media_data::pixel_width::set(metadata.width.map(|width| width as i32)),
+		media_data::pixel_height::set(metadata.height.map(|height| height as i32)),
+		media_data::fps::set(metadata.fps),
+		media_data::duration_seconds::set(metadata.duration_seconds),
+		media_data::codecs::set(metadata.codecs),
+		media_data::streams::set(Some(metadata.streams)),
+		media_data::container::set(metadata.container),
+		media_data::capture_date::set(metadata.capture_date),
+		media_data::capture_device_make::set(metadata.camera_make),
+		media_data::capture_device_model::set(metadata.camera_model),
+		media_data::capture_device_software::set(metadata.camera_software),
+	];
+	params.extend(gps_params(ctx, path, metadata.longitude, metadata.latitude));
+
+	params
+}
+
+fn gps_params(
+	ctx: &WorkerContext,
+	path: &Path,
+	longitude: Option<f64>,
+	latitude: Option<f64>,
+) -> Vec<media_data::SetParam> {
+	gps_location_params(&ctx.library_ctx, longitude, latitude).unwrap_or_else(|e| {
+		// the location can't be stored in plaintext when it should be encrypted, so it's left out
+		warn!("Failed to store the GPS location of {:?}: {:#?}", path, e);
+		ctx.log(
+			JobLogLevel::Warning,
+			format!(
+				"Skipped the GPS location, as it couldn't be encrypted: {}",
+				e
+			),
+			Some(path.display().to_string()),
+		);
+
+		vec![]
+	})
+}
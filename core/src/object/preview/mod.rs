@@ -1,5 +1,15 @@
+mod audio_tags;
+mod encrypted_thumb;
+mod image_metadata;
 mod media_data;
+mod media_data_job;
 mod thumb;
+mod thumbnail_cache;
 
+pub use audio_tags::*;
+pub use encrypted_thumb::*;
+pub use image_metadata::*;
 pub use media_data::*;
+pub use media_data_job::*;
 pub use thumb::*;
+pub use thumbnail_cache::*;
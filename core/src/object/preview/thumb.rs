@@ -3,7 +3,7 @@ use crate::{
 	invalidate_query,
 	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
 	library::LibraryContext,
-	prisma::{file_path, location},
+	prisma::{file_path, key, location},
 };
 
 use std::{
@@ -19,8 +19,13 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::{fs, task::block_in_place};
 use tracing::{error, info, trace, warn};
+use uuid::Uuid;
 use webp::Encoder;
 
+use super::{
+	apply_orientation, encrypt_thumbnail, read_orientation, ENCRYPTED_THUMBNAIL_EXTENSION,
+};
+
 static THUMBNAIL_SIZE_FACTOR: f32 = 0.2;
 static THUMBNAIL_QUALITY: f32 = 30.0;
 pub static THUMBNAIL_CACHE_DIR_NAME: &str = "thumbnails";
@@ -39,6 +44,8 @@ pub struct ThumbnailJobInit {
 pub struct ThumbnailJobState {
 	thumbnail_dir: PathBuf,
 	root_path: PathBuf,
+	#[serde(default)]
+	vault_key: Option<Uuid>, // thumbnails within vault locations are encrypted with the vault's key
 }
 
 #[derive(Error, Debug)]
@@ -57,6 +64,7 @@ file_path::select!(file_path_id_only { id });
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 enum ThumbnailJobStepKind {
 	Image,
+	Audio,
 	#[cfg(feature = "ffmpeg")]
 	Video,
 }
@@ -132,6 +140,37 @@ impl StatefulJob for ThumbnailJob {
 			.map(PathBuf::from)
 			.ok_or(ThumbnailError::LocationLocalPath(location.id))?;
 
+		let vault_key = match location.vault_key_id {
+			Some(vault_key_id) => {
+				let uuid = ctx
+					.library_ctx
+					.db
+					.key()
+					.find_unique(key::id::equals(vault_key_id))
+					.exec()
+					.await?
+					.and_then(|key| Uuid::parse_str(&key.uuid).ok());
+
+				match uuid {
+					Some(uuid) if ctx.library_ctx.key_manager.keymount_contains(uuid) => Some(uuid),
+					// thumbnails can't be generated in plaintext within a vault, so there's nothing we can do
+					_ => {
+						info!(
+							"Skipping thumbnails for vault location {} as its key isn't mounted",
+							location.id
+						);
+						state.data = Some(ThumbnailJobState {
+							thumbnail_dir,
+							root_path,
+							vault_key: None,
+						});
+						return Ok(());
+					}
+				}
+			}
+			None => None,
+		};
+
 		// query database for all image files in this location that need thumbnails
 		let image_files = get_files_by_extensions(
 			&ctx.library_ctx,
@@ -148,6 +187,27 @@ impl StatefulJob for ThumbnailJob {
 		.await?;
 		info!("Found {:?} image files", image_files.len());
 
+		// query database for all audio files in this location, as they may have artwork
+		let audio_files = get_files_by_extensions(
+			&ctx.library_ctx,
+			state.init.location_id,
+			parent_directory_id,
+			&sd_file_ext::extensions::ALL_AUDIO_EXTENSIONS
+				.iter()
+				.map(Clone::clone)
+				.filter(can_extract_audio_tags)
+				.map(Extension::Audio)
+				.collect::<Vec<_>>(),
+			ThumbnailJobStepKind::Audio,
+		)
+		.await?;
+		info!("Found {:?} audio files", audio_files.len());
+
+		let files = image_files
+			.into_iter()
+			.chain(audio_files.into_iter())
+			.collect::<Vec<_>>();
+
 		#[cfg(feature = "ffmpeg")]
 		let all_files = {
 			// query database for all video files in this location that need thumbnails
@@ -166,13 +226,13 @@ impl StatefulJob for ThumbnailJob {
 			.await?;
 			info!("Found {:?} video files", video_files.len());
 
-			image_files
+			files
 				.into_iter()
 				.chain(video_files.into_iter())
 				.collect::<VecDeque<_>>()
 		};
 		#[cfg(not(feature = "ffmpeg"))]
-		let all_files = { image_files.into_iter().collect::<VecDeque<_>>() };
+		let all_files = { files.into_iter().collect::<VecDeque<_>>() };
 
 		ctx.progress(vec![
 			JobReportUpdate::TaskCount(all_files.len()),
@@ -182,6 +242,7 @@ impl StatefulJob for ThumbnailJob {
 		state.data = Some(ThumbnailJobState {
 			thumbnail_dir,
 			root_path,
+			vault_key,
 		});
 		state.steps = all_files;
 
@@ -223,7 +284,14 @@ impl StatefulJob for ThumbnailJob {
 		let output_path = data.thumbnail_dir.join(&cas_id).with_extension("webp");
 
 		// check if file exists at output path
-		if !output_path.try_exists().unwrap() {
+		let exists = output_path.try_exists().unwrap()
+			|| (data.vault_key.is_some()
+				&& output_path
+					.with_extension(ENCRYPTED_THUMBNAIL_EXTENSION)
+					.try_exists()
+					.unwrap());
+
+		if !exists {
 			info!("Writing {:?} to {:?}", path, output_path);
 
 			match step.kind {
@@ -232,47 +300,33 @@ impl StatefulJob for ThumbnailJob {
 						error!("Error generating thumb for image {:#?}", e);
 					}
 				}
+				ThumbnailJobStepKind::Audio => {
+					match generate_audio_thumbnail(&path, &output_path).await {
+						Ok(true) => {}
+						// most songs don't have any artwork, so there's nothing else to do
+						Ok(false) => {
+							trace!("No artwork found in {:?}", path);
+							ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+								state.step_number + 1,
+							)]);
+							return Ok(());
+						}
+						Err(e) => {
+							error!("Error generating thumb for audio: {:?} {:#?}", &path, e);
+						}
+					}
+				}
 				#[cfg(feature = "ffmpeg")]
 				ThumbnailJobStepKind::Video => {
-					// use crate::{
-					// 	object::preview::{extract_media_data, StreamKind},
-					// 	prisma::media_data,
-					// };
-
-					// use
 					if let Err(e) = generate_video_thumbnail(&path, &output_path).await {
 						error!("Error generating thumb for video: {:?} {:#?}", &path, e);
 					}
-					// extract MediaData from video and put in the database
-					// TODO: this is bad here, maybe give it its own job?
-					// if let Ok(media_data) = extract_media_data(&path) {
-					// 	info!(
-					// 		"Extracted media data for object {}: {:?}",
-					// 		step.object_id, media_data
-					// 	);
-
-					// 	// let primary_video_stream = media_data
-					// 	// 	.steams
-					// 	// 	.iter()
-					// 	// 	.find(|s| s.kind == Some(StreamKind::Video(_)));
-
-					// 	let params = vec![
-					// 		media_data::duration_seconds::set(Some(media_data.duration_seconds)),
-					// 		// media_data::pixel_width::set(Some(media_data.width)),
-					// 		// media_data::pixel_height::set(Some(media_data.height)),
-					// 	];
-					// 	let _ = ctx
-					// 		.library_ctx()
-					// 		.db
-					// 		.media_data()
-					// 		.upsert(
-					// 			media_data::id::equals(step.object_id),
-					// 			params.clone(),
-					// 			params,
-					// 		)
-					// 		.exec()
-					// 		.await?;
-					// }
+				}
+			}
+
+			if let Some(vault_key) = data.vault_key {
+				if let Err(e) = encrypt_thumbnail(&ctx.library_ctx, vault_key, &cas_id).await {
+					error!("Error encrypting thumb for vault file {:#?}", e);
 				}
 			}
 
@@ -282,6 +336,7 @@ impl StatefulJob for ThumbnailJob {
 
 			// With this invalidate query, we update the user interface to show each new thumbnail
 			invalidate_query!(ctx.library_ctx, "locations.getExplorerData");
+			invalidate_query!(ctx.library_ctx, "savedSearches.getExplorerData");
 		} else {
 			info!("Thumb exists, skipping... {}", output_path.display());
 		}
@@ -316,30 +371,60 @@ pub async fn generate_image_thumbnail<P: AsRef<Path>>(
 	// Webp creation has blocking code
 	let webp = block_in_place(|| -> Result<Vec<u8>, Box<dyn Error>> {
 		// Using `image` crate, open the included .jpg file
-		let img = image::open(file_path)?;
-		let (w, h) = img.dimensions();
-		// Optionally, resize the existing photo and convert back into DynamicImage
-		let img = DynamicImage::ImageRgba8(imageops::resize(
-			&img,
-			// FIXME : Think of a better heuristic to get the thumbnail size
-			(w as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
-			(h as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
-			imageops::FilterType::Triangle,
-		));
-		// Create the WebP encoder for the above image
-		let encoder = Encoder::from_image(&img)?;
-
-		// Encode the image at a specified quality 0-100
-
-		// Type WebPMemory is !Send, which makes the Future in this function !Send,
-		// this make us `deref` to have a `&[u8]` and then `to_owned` to make a Vec<u8>
-		// which implies on a unwanted clone...
-		Ok(encoder.encode(THUMBNAIL_QUALITY).deref().to_owned())
+		let img = image::open(&file_path)?;
+		// photos are often stored sideways, with their EXIF orientation saying how to turn them
+		let img = match read_orientation(&file_path) {
+			Some(orientation) => apply_orientation(img, orientation),
+			None => img,
+		};
+
+		encode_thumbnail(img)
 	})?;
 
 	fs::write(output_path, &webp).await.map_err(Into::into)
 }
 
+/// This generates a thumbnail from the artwork that's embedded within an audio file, and returns whether it had any.
+pub async fn generate_audio_thumbnail<P: AsRef<Path>>(
+	file_path: P,
+	output_path: P,
+) -> Result<bool, Box<dyn Error>> {
+	let webp = block_in_place(|| -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+		extract_audio_artwork(&file_path)?
+			.map(|artwork| encode_thumbnail(image::load_from_memory(&artwork)?))
+			.transpose()
+	})?;
+
+	match webp {
+		Some(webp) => {
+			fs::write(output_path, &webp).await?;
+			Ok(true)
+		}
+		None => Ok(false),
+	}
+}
+
+fn encode_thumbnail(img: DynamicImage) -> Result<Vec<u8>, Box<dyn Error>> {
+	let (w, h) = img.dimensions();
+	// Optionally, resize the existing photo and convert back into DynamicImage
+	let img = DynamicImage::ImageRgba8(imageops::resize(
+		&img,
+		// FIXME : Think of a better heuristic to get the thumbnail size
+		(w as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
+		(h as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
+		imageops::FilterType::Triangle,
+	));
+	// Create the WebP encoder for the above image
+	let encoder = Encoder::from_image(&img)?;
+
+	// Encode the image at a specified quality 0-100
+
+	// Type WebPMemory is !Send, which makes the Future in this function !Send,
+	// this make us `deref` to have a `&[u8]` and then `to_owned` to make a Vec<u8>
+	// which implies on a unwanted clone...
+	Ok(encoder.encode(THUMBNAIL_QUALITY).deref().to_owned())
+}
+
 #[cfg(feature = "ffmpeg")]
 pub async fn generate_video_thumbnail<P: AsRef<Path>>(
 	file_path: P,
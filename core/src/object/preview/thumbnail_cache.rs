@@ -0,0 +1,217 @@
+use crate::library::LibraryContext;
+
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	sync::{Arc, Mutex, Weak},
+};
+
+use rspc::Type;
+use sd_crypto::Protected;
+use serde::Serialize;
+use tracing::debug;
+use uuid::Uuid;
+
+struct CachedThumbnail {
+	library_id: Uuid,
+	key_uuid: Uuid,
+	media: Protected<Vec<u8>>,
+	last_used: u64,
+}
+
+#[derive(Default)]
+struct ThumbnailCacheState {
+	budget: u64,
+	size: u64,
+	/// this is incremented every time a thumbnail is used, so the least recently used one is always first within `order`
+	tick: u64,
+	entries: HashMap<String, CachedThumbnail>,
+	order: BTreeMap<u64, String>,
+	/// the keys (and their libraries) that the cache is already purged alongside
+	watched_keys: HashSet<(Uuid, Uuid)>,
+}
+
+/// ThumbnailCacheStats is how much of its budget the decrypted thumbnail cache is using.
+#[derive(Debug, Serialize, Type)]
+pub struct ThumbnailCacheStats {
+	pub entries: usize,
+	pub size: u64,
+	pub budget: u64,
+}
+
+/// ThumbnailCache holds decrypted thumbnails of encrypted files in memory, so they aren't decrypted again every time they're shown.
+///
+/// It's kept within the node's `thumbnail_cache_size`, by evicting the thumbnails that were used least recently.
+/// Thumbnails are purged as soon as the key they were decrypted with is unmounted, or their library is locked, and they're zeroed once they're dropped.
+#[derive(Default)]
+pub struct ThumbnailCache {
+	state: Mutex<ThumbnailCacheState>,
+}
+
+impl ThumbnailCache {
+	pub fn new(budget: u64) -> Arc<Self> {
+		Arc::new(Self {
+			state: Mutex::new(ThumbnailCacheState {
+				budget,
+				..Default::default()
+			}),
+		})
+	}
+
+	/// get returns a cached thumbnail, if it's been decrypted for the given library.
+	pub fn get(&self, library_id: Uuid, cas_id: &str) -> Option<Vec<u8>> {
+		let mut state = self.lock();
+
+		let last_used = match state.entries.get(cas_id) {
+			Some(cached) if cached.library_id == library_id => cached.last_used,
+			_ => return None,
+		};
+
+		state.tick += 1;
+		let tick = state.tick;
+
+		state.order.remove(&last_used);
+		state.order.insert(tick, cas_id.to_string());
+
+		let cached = state.entries.get_mut(cas_id)?;
+		cached.last_used = tick;
+
+		Some(cached.media.expose().clone())
+	}
+
+	/// insert caches a thumbnail that's been decrypted with the given key, and evicts whatever's needed to stay within the budget.
+	///
+	/// Thumbnails that are larger than the whole budget aren't cached.
+	pub fn insert(
+		self: &Arc<Self>,
+		library: &LibraryContext,
+		key_uuid: Uuid,
+		cas_id: &str,
+		media: Protected<Vec<u8>>,
+	) {
+		let len = media.expose().len() as u64;
+
+		let watch = {
+			let mut state = self.lock();
+
+			if len > state.budget {
+				return;
+			}
+
+			state.remove(cas_id);
+
+			state.tick += 1;
+			let tick = state.tick;
+
+			state.size += len;
+			state.order.insert(tick, cas_id.to_string());
+			state.entries.insert(
+				cas_id.to_string(),
+				CachedThumbnail {
+					library_id: library.id,
+					key_uuid,
+					media,
+					last_used: tick,
+				},
+			);
+
+			state.evict();
+
+			state.watched_keys.insert((library.id, key_uuid))
+		};
+
+		// the callback is only registered once for each key, rather than for every thumbnail
+		if watch {
+			let cache = Arc::downgrade(self);
+			let library_id = library.id;
+
+			library.key_consumers.on_revoke(key_uuid, move || {
+				if let Some(cache) = Weak::upgrade(&cache) {
+					cache.purge_key(library_id, key_uuid);
+				}
+			});
+		}
+	}
+
+	/// remove removes a single thumbnail, e.g. once it's been encrypted again.
+	pub fn remove(&self, cas_id: &str) {
+		self.lock().remove(cas_id);
+	}
+
+	/// purge_key removes every thumbnail that was decrypted with the given key. This is called once the key is unmounted.
+	pub fn purge_key(&self, library_id: Uuid, key_uuid: Uuid) {
+		let mut state = self.lock();
+
+		state.watched_keys.remove(&(library_id, key_uuid));
+		state.purge(|cached| cached.library_id == library_id && cached.key_uuid == key_uuid);
+	}
+
+	/// purge_library removes every thumbnail that was decrypted for the given library. This should be called whenever the library is locked.
+	pub fn purge_library(&self, library_id: Uuid) {
+		self.lock().purge(|cached| cached.library_id == library_id);
+	}
+
+	/// set_budget changes the cache's budget (in bytes), and evicts whatever's needed to stay within it.
+	pub fn set_budget(&self, budget: u64) {
+		let mut state = self.lock();
+
+		state.budget = budget;
+		state.evict();
+	}
+
+	pub fn stats(&self) -> ThumbnailCacheStats {
+		let state = self.lock();
+
+		ThumbnailCacheStats {
+			entries: state.entries.len(),
+			size: state.size,
+			budget: state.budget,
+		}
+	}
+
+	fn lock(&self) -> std::sync::MutexGuard<'_, ThumbnailCacheState> {
+		self.state
+			.lock()
+			.expect("critical error: thumbnail cache mutex poisoned")
+	}
+}
+
+impl ThumbnailCacheState {
+	fn remove(&mut self, cas_id: &str) {
+		if let Some(cached) = self.entries.remove(cas_id) {
+			self.order.remove(&cached.last_used);
+			self.size -= cached.media.expose().len() as u64;
+		}
+	}
+
+	fn evict(&mut self) {
+		while self.size > self.budget {
+			let cas_id = match self.order.values().next() {
+				Some(cas_id) => cas_id.clone(),
+				None => break,
+			};
+
+			debug!("Evicting decrypted thumbnail from the cache: {}", cas_id);
+			self.remove(&cas_id);
+		}
+	}
+
+	fn purge(&mut self, filter: impl Fn(&CachedThumbnail) -> bool) {
+		let cas_ids = self
+			.entries
+			.iter()
+			.filter(|(_, cached)| filter(cached))
+			.map(|(cas_id, _)| cas_id.clone())
+			.collect::<Vec<_>>();
+
+		if !cas_ids.is_empty() {
+			debug!(
+				"Purging {} decrypted thumbnail(s) from the cache",
+				cas_ids.len()
+			);
+		}
+
+		for cas_id in cas_ids {
+			self.remove(&cas_id);
+		}
+	}
+}
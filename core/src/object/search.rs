@@ -0,0 +1,506 @@
+use crate::{
+	object::tag::TagTree,
+	prisma::{file_path, object, saved_search, tag_on_object, PrismaClient},
+};
+
+use std::cmp::Ordering;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use prisma_client_rust::{operator, Direction, QueryError};
+use rspc::{ErrorCode, Type};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// At most this many objects are returned by a single search.
+pub const MAX_SEARCH_TAKE: i32 = 500;
+
+/// SearchFilter is a condition on an object, which can be grouped with `And` and `Or` to build up a search.
+#[derive(Serialize, Deserialize, Type, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SearchFilter {
+	/// every filter must match (or there aren't any filters)
+	And(Vec<SearchFilter>),
+	/// any filter must match (or there aren't any filters)
+	Or(Vec<SearchFilter>),
+	/// the object's name contains this
+	Name(String),
+	/// the object is one of these `ObjectKind`s
+	Kind(Vec<i32>),
+	Extension(Vec<String>),
+	Size(SizeRange),
+	DateCreated(DateRange),
+	DateModified(DateRange),
+	DateIndexed(DateRange),
+	/// the object has any of these tags (or, if `include_descendants` is set, any tags nested under them)
+	Tags {
+		tag_ids: Vec<i32>,
+		#[serde(default)]
+		include_descendants: bool,
+	},
+	/// the object has a file path within any of these locations
+	Location(Vec<i32>),
+	Encrypted(bool),
+	HasThumbnail(bool),
+	Favorite(bool),
+	/// the object has been rated within this range, so unrated objects never match
+	Rating(RatingRange),
+}
+
+/// SizeRange is inclusive, and open-ended where a bound isn't given.
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SizeRange {
+	pub min: Option<u64>,
+	pub max: Option<u64>,
+}
+
+/// DateRange is inclusive, and open-ended where a bound isn't given.
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DateRange {
+	pub from: Option<DateTime<Utc>>,
+	pub to: Option<DateTime<Utc>>,
+}
+
+/// RatingRange is inclusive, and open-ended where a bound isn't given.
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RatingRange {
+	pub min: Option<u8>,
+	pub max: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SearchOrderBy {
+	Name,
+	Size,
+	/// unrated objects come before those with the fewest stars
+	Rating,
+	DateCreated,
+	DateModified,
+	#[default]
+	DateIndexed,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SearchDirection {
+	Asc,
+	#[default]
+	Desc,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct ObjectSearch {
+	pub filter: Option<SearchFilter>,
+	#[serde(default)]
+	pub order_by: SearchOrderBy,
+	#[serde(default)]
+	pub direction: SearchDirection,
+	pub take: Option<i32>,
+	pub skip: Option<i32>,
+}
+
+#[derive(Error, Debug)]
+pub enum SavedSearchError {
+	#[error("Saved search not found: <id={0}>")]
+	NotFound(i32),
+	#[error("Database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("Serialization error: {0}")]
+	Serialization(#[from] serde_json::Error),
+}
+
+impl From<SavedSearchError> for rspc::Error {
+	fn from(err: SavedSearchError) -> Self {
+		match err {
+			SavedSearchError::NotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// SavedSearch is a named search, which the explorer shows as a virtual folder of whatever objects currently match it.
+#[derive(Serialize, Deserialize, Type, Debug)]
+pub struct SavedSearch {
+	pub id: i32,
+	pub name: String,
+	/// the page of the search is ignored, as the explorer pages through the results itself
+	pub search: ObjectSearch,
+	pub date_created: DateTime<Utc>,
+	pub date_modified: DateTime<Utc>,
+}
+
+impl TryFrom<saved_search::Data> for SavedSearch {
+	type Error = SavedSearchError;
+
+	fn try_from(data: saved_search::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			id: data.id,
+			name: data.name,
+			search: serde_json::from_str(&data.search)?,
+			date_created: data.date_created.into(),
+			date_modified: data.date_modified.into(),
+		})
+	}
+}
+
+object::select!(object_for_search {
+	id
+	name
+	extension
+	kind
+	size_in_bytes
+	key_id
+	has_thumbnail
+	favorite
+	rating
+	date_created
+	date_modified
+	date_indexed
+	tags: select { tag_id }
+	file_paths: select { location_id }
+});
+
+impl SearchFilter {
+	/// This returns the conditions that the database can check for this filter, or `None` if it can't constrain the objects at all.
+	///
+	/// Sizes are stored as strings, which don't compare like numbers, so they're left to `matches`.
+	/// As filters can't be negated, leaving a condition out only ever matches more objects, which `matches` then narrows down.
+	fn where_param(&self) -> Option<object::WhereParam> {
+		Some(match self {
+			Self::And(filters) => {
+				let params = filters
+					.iter()
+					.filter_map(Self::where_param)
+					.collect::<Vec<_>>();
+				if params.is_empty() {
+					return None;
+				}
+				operator::and(params)
+			}
+			Self::Or(filters) => {
+				// an `Or` that has an unconstrained filter is unconstrained as well
+				let params = filters
+					.iter()
+					.map(Self::where_param)
+					.collect::<Option<Vec<_>>>()?;
+				if params.is_empty() {
+					return None;
+				}
+				operator::or(params)
+			}
+			Self::Name(name) => object::name::contains(name.clone()),
+			Self::Kind(kinds) => object::kind::in_vec(kinds.clone()),
+			Self::Extension(extensions) => object::extension::in_vec(extensions.clone()),
+			Self::Size(_) => return None,
+			Self::DateCreated(range) => {
+				operator::and(range.params(object::date_created::gte, object::date_created::lte))
+			}
+			Self::DateModified(range) => {
+				operator::and(range.params(object::date_modified::gte, object::date_modified::lte))
+			}
+			Self::DateIndexed(range) => {
+				operator::and(range.params(object::date_indexed::gte, object::date_indexed::lte))
+			}
+			Self::Tags { tag_ids, .. } => {
+				object::tags::some(vec![tag_on_object::tag_id::in_vec(tag_ids.clone())])
+			}
+			Self::Location(location_ids) => {
+				object::file_paths::some(vec![file_path::location_id::in_vec(location_ids.clone())])
+			}
+			Self::Encrypted(true) => object::key_id::not(None),
+			Self::Encrypted(false) => object::key_id::equals(None),
+			Self::HasThumbnail(has_thumbnail) => object::has_thumbnail::equals(*has_thumbnail),
+			Self::Favorite(favorite) => object::favorite::equals(*favorite),
+			Self::Rating(range) => operator::and(
+				range
+					.min
+					.map(|min| object::rating::gte(min as i32))
+					.into_iter()
+					.chain(range.max.map(|max| object::rating::lte(max as i32)))
+					.chain([object::rating::not(None)])
+					.collect(),
+			),
+		})
+	}
+
+	/// This returns whether an object matches this filter.
+	fn matches(&self, object: &object_for_search::Data) -> bool {
+		match self {
+			Self::And(filters) => filters.iter().all(|filter| filter.matches(object)),
+			Self::Or(filters) => {
+				filters.is_empty() || filters.iter().any(|filter| filter.matches(object))
+			}
+			// the database matches ASCII case-insensitively, so this does too
+			Self::Name(name) => object.name.as_ref().map_or(false, |object_name| {
+				object_name
+					.to_ascii_lowercase()
+					.contains(&name.to_ascii_lowercase())
+			}),
+			Self::Kind(kinds) => kinds.contains(&object.kind),
+			Self::Extension(extensions) => object
+				.extension
+				.as_ref()
+				.map_or(false, |extension| extensions.contains(extension)),
+			Self::Size(range) => range.contains(size(object)),
+			Self::DateCreated(range) => range.contains(object.date_created),
+			Self::DateModified(range) => range.contains(object.date_modified),
+			Self::DateIndexed(range) => range.contains(object.date_indexed),
+			Self::Tags { tag_ids, .. } => {
+				object.tags.iter().any(|tag| tag_ids.contains(&tag.tag_id))
+			}
+			Self::Location(location_ids) => object
+				.file_paths
+				.iter()
+				.any(|file_path| location_ids.contains(&file_path.location_id)),
+			Self::Encrypted(encrypted) => object.key_id.is_some() == *encrypted,
+			Self::HasThumbnail(has_thumbnail) => object.has_thumbnail == *has_thumbnail,
+			Self::Favorite(favorite) => object.favorite == *favorite,
+			Self::Rating(range) => object.rating.map_or(false, |rating| range.contains(rating)),
+		}
+	}
+
+	fn includes_descendants(&self) -> bool {
+		match self {
+			Self::And(filters) | Self::Or(filters) => {
+				filters.iter().any(Self::includes_descendants)
+			}
+			Self::Tags {
+				include_descendants,
+				..
+			} => *include_descendants,
+			_ => false,
+		}
+	}
+
+	/// This returns the filter with the descendants of its tags added to it, where they're to be included.
+	fn with_descendants(&self, tree: &TagTree) -> Self {
+		match self {
+			Self::And(filters) => Self::And(
+				filters
+					.iter()
+					.map(|filter| filter.with_descendants(tree))
+					.collect(),
+			),
+			Self::Or(filters) => Self::Or(
+				filters
+					.iter()
+					.map(|filter| filter.with_descendants(tree))
+					.collect(),
+			),
+			Self::Tags {
+				tag_ids,
+				include_descendants: true,
+			} => Self::Tags {
+				tag_ids: tree.with_descendants(tag_ids),
+				include_descendants: false,
+			},
+			filter => filter.clone(),
+		}
+	}
+
+	/// This returns whether the database can check the whole filter on its own.
+	fn is_exact(&self) -> bool {
+		match self {
+			Self::And(filters) | Self::Or(filters) => filters.iter().all(Self::is_exact),
+			Self::Size(_) => false,
+			_ => true,
+		}
+	}
+}
+
+impl SizeRange {
+	fn contains(&self, size: u64) -> bool {
+		self.min.map_or(true, |min| size >= min) && self.max.map_or(true, |max| size <= max)
+	}
+}
+
+impl RatingRange {
+	fn contains(&self, rating: i32) -> bool {
+		self.min.map_or(true, |min| rating >= min as i32)
+			&& self.max.map_or(true, |max| rating <= max as i32)
+	}
+}
+
+impl DateRange {
+	fn params(
+		&self,
+		gte: fn(DateTime<FixedOffset>) -> object::WhereParam,
+		lte: fn(DateTime<FixedOffset>) -> object::WhereParam,
+	) -> Vec<object::WhereParam> {
+		self.from
+			.map(|from| gte(from.into()))
+			.into_iter()
+			.chain(self.to.map(|to| lte(to.into())))
+			.collect()
+	}
+
+	fn contains(&self, date: DateTime<FixedOffset>) -> bool {
+		self.from.map_or(true, |from| date >= from) && self.to.map_or(true, |to| date <= to)
+	}
+}
+
+fn size(object: &object_for_search::Data) -> u64 {
+	object.size_in_bytes.parse().unwrap_or_default()
+}
+
+impl ObjectSearch {
+	/// This returns the ids of the page of objects that match the search, in order.
+	///
+	/// When the database can't check every filter or sort the objects itself (as with sizes), every candidate is fetched and the page is taken here instead.
+	pub async fn object_ids(&self, db: &PrismaClient) -> Result<Vec<i32>, QueryError> {
+		let take = self.take.unwrap_or(100).clamp(1, MAX_SEARCH_TAKE) as usize;
+		let skip = self.skip.unwrap_or_default().max(0) as usize;
+
+		self.find_object_ids(db, Some((skip, take))).await
+	}
+
+	/// This returns the ids of every object that matches the search (ignoring its page), e.g. to change them all at once.
+	pub async fn all_object_ids(&self, db: &PrismaClient) -> Result<Vec<i32>, QueryError> {
+		self.find_object_ids(db, None).await
+	}
+
+	async fn find_object_ids(
+		&self,
+		db: &PrismaClient,
+		page: Option<(usize, usize)>,
+	) -> Result<Vec<i32>, QueryError> {
+		let filter = match &self.filter {
+			Some(filter) if filter.includes_descendants() => {
+				Some(filter.with_descendants(&TagTree::load(db).await?))
+			}
+			filter => filter.clone(),
+		};
+
+		let params = filter
+			.as_ref()
+			.and_then(SearchFilter::where_param)
+			.into_iter()
+			.collect::<Vec<_>>();
+
+		let is_exact = filter.as_ref().map_or(true, SearchFilter::is_exact);
+		if is_exact && self.order_by != SearchOrderBy::Size {
+			let direction = match self.direction {
+				SearchDirection::Asc => Direction::Asc,
+				SearchDirection::Desc => Direction::Desc,
+			};
+
+			let mut query = db.object().find_many(params).order_by(match self.order_by {
+				SearchOrderBy::Name => object::name::order(direction),
+				SearchOrderBy::DateCreated => object::date_created::order(direction),
+				SearchOrderBy::DateModified => object::date_modified::order(direction),
+				SearchOrderBy::Rating => object::rating::order(direction),
+				SearchOrderBy::DateIndexed | SearchOrderBy::Size => {
+					object::date_indexed::order(direction)
+				}
+			});
+			if let Some((skip, take)) = page {
+				query = query.skip(skip as i64).take(take as i64);
+			}
+
+			return Ok(query
+				.select(object::select!({ id }))
+				.exec()
+				.await?
+				.into_iter()
+				.map(|object| object.id)
+				.collect());
+		}
+
+		let mut objects = db
+			.object()
+			.find_many(params)
+			.select(object_for_search::select())
+			.exec()
+			.await?
+			.into_iter()
+			.filter(|object| {
+				filter
+					.as_ref()
+					.map_or(true, |filter| filter.matches(object))
+			})
+			.collect::<Vec<_>>();
+
+		objects.sort_by(|a, b| {
+			let ordering = self.compare(a, b);
+			match self.direction {
+				SearchDirection::Asc => ordering,
+				SearchDirection::Desc => ordering.reverse(),
+			}
+		});
+
+		let (skip, take) = page.unwrap_or((0, objects.len()));
+
+		Ok(objects
+			.into_iter()
+			.skip(skip)
+			.take(take)
+			.map(|object| object.id)
+			.collect())
+	}
+
+	fn compare(&self, a: &object_for_search::Data, b: &object_for_search::Data) -> Ordering {
+		match self.order_by {
+			SearchOrderBy::Name => a.name.cmp(&b.name),
+			SearchOrderBy::Size => size(a).cmp(&size(b)),
+			SearchOrderBy::Rating => a.rating.cmp(&b.rating),
+			SearchOrderBy::DateCreated => a.date_created.cmp(&b.date_created),
+			SearchOrderBy::DateModified => a.date_modified.cmp(&b.date_modified),
+			SearchOrderBy::DateIndexed => a.date_indexed.cmp(&b.date_indexed),
+		}
+		// the id keeps the order stable between pages
+		.then_with(|| a.id.cmp(&b.id))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_size_filters_are_not_exact() {
+		let size = SearchFilter::Size(SizeRange {
+			min: Some(1024),
+			max: None,
+		});
+
+		assert!(SearchFilter::Kind(vec![5]).is_exact());
+		assert!(!size.is_exact());
+		assert!(!SearchFilter::Or(vec![SearchFilter::Kind(vec![5]), size.clone()]).is_exact());
+
+		// an `Or` with a size filter can't be narrowed down by the database at all
+		assert!(
+			SearchFilter::Or(vec![SearchFilter::Kind(vec![5]), size.clone()])
+				.where_param()
+				.is_none()
+		);
+		assert!(SearchFilter::And(vec![SearchFilter::Kind(vec![5]), size])
+			.where_param()
+			.is_some());
+	}
+
+	#[test]
+	fn test_ranges() {
+		let range = SizeRange {
+			min: Some(10),
+			max: Some(20),
+		};
+		assert!(range.contains(10));
+		assert!(range.contains(20));
+		assert!(!range.contains(9));
+		assert!(!range.contains(21));
+
+		let range = RatingRange {
+			min: Some(4),
+			max: None,
+		};
+		assert!(range.contains(5));
+		assert!(!range.contains(3));
+
+		let from = "2022-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+		let range = DateRange {
+			from: Some(from),
+			to: None,
+		};
+		assert!(range.contains(DateTime::parse_from_rfc3339("2022-01-01T01:00:00+01:00").unwrap()));
+		assert!(!range.contains(DateTime::parse_from_rfc3339("2021-12-31T23:59:59Z").unwrap()));
+	}
+}
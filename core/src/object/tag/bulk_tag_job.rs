@@ -0,0 +1,300 @@
+use crate::{
+	invalidate_query,
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	object::search::ObjectSearch,
+	prisma::{object, tag, tag_change, tag_on_object},
+};
+
+use std::collections::{HashSet, VecDeque};
+
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use uuid::Uuid;
+
+pub const BULK_TAG_JOB_NAME: &str = "bulk_tagger";
+
+/// This many tags are assigned or unassigned in each step.
+const BULK_TAG_BATCH_SIZE: usize = 500;
+
+/// BulkTagJob assigns tags to (or unassigns them from) many objects at once, recording exactly what it changed so it can be undone.
+pub struct BulkTagJob {}
+
+/// BulkTagTargets are the objects that a bulk change is made to.
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Hash)]
+pub enum BulkTagTargets {
+	Objects(Vec<i32>),
+	/// every object matching the search, ignoring its page
+	Search(ObjectSearch),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Hash)]
+pub enum BulkTagAction {
+	Assign {
+		tag_ids: Vec<i32>,
+		targets: BulkTagTargets,
+	},
+	Unassign {
+		tag_ids: Vec<i32>,
+		targets: BulkTagTargets,
+	},
+	/// this reverts every change that was made under an undo token
+	Undo(Uuid),
+}
+
+#[derive(Serialize, Deserialize, Clone, Hash)]
+pub struct BulkTagJobInit {
+	pub action: BulkTagAction,
+	/// the changes are recorded under this, so they can be undone (including those made by an undo)
+	pub undo_token: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct BulkTagJobState {
+	assigned: usize,
+	unassigned: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct TagChangeStep {
+	tag_id: i32,
+	object_id: i32,
+	assign: bool,
+}
+
+pub type BulkTagJobStep = Vec<TagChangeStep>;
+
+#[async_trait::async_trait]
+impl StatefulJob for BulkTagJob {
+	type Init = BulkTagJobInit;
+	type Data = BulkTagJobState;
+	type Step = BulkTagJobStep;
+
+	fn name(&self) -> &'static str {
+		BULK_TAG_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let db = &ctx.library_ctx.db;
+
+		let changes = match &state.init.action {
+			BulkTagAction::Assign { tag_ids, targets }
+			| BulkTagAction::Unassign { tag_ids, targets } => {
+				let assign = matches!(state.init.action, BulkTagAction::Assign { .. });
+
+				let mut object_ids = match targets {
+					BulkTagTargets::Objects(object_ids) => object_ids.clone(),
+					BulkTagTargets::Search(search) => search.all_object_ids(db).await?,
+				};
+				let mut tag_ids = tag_ids.clone();
+
+				// a tag can't be assigned to the same object twice within a step
+				let mut seen = HashSet::new();
+				object_ids.retain(|object_id| seen.insert(*object_id));
+				let mut seen = HashSet::new();
+				tag_ids.retain(|tag_id| seen.insert(*tag_id));
+
+				object_ids
+					.into_iter()
+					.flat_map(|object_id| {
+						tag_ids
+							.clone()
+							.into_iter()
+							.map(move |tag_id| TagChangeStep {
+								tag_id,
+								object_id,
+								assign,
+							})
+					})
+					.collect::<Vec<_>>()
+			}
+			// every change is reversed
+			BulkTagAction::Undo(undo_token) => db
+				.tag_change()
+				.find_many(vec![tag_change::change_id::equals(
+					undo_token.as_bytes().to_vec(),
+				)])
+				.exec()
+				.await?
+				.into_iter()
+				.map(|change| TagChangeStep {
+					tag_id: change.tag_id,
+					object_id: change.object_id,
+					assign: !change.assigned,
+				})
+				.collect(),
+		};
+
+		info!("Making {} bulk tag changes", changes.len());
+
+		let mut steps = VecDeque::new();
+		let mut changes = changes.into_iter().peekable();
+		while changes.peek().is_some() {
+			steps.push_back(changes.by_ref().take(BULK_TAG_BATCH_SIZE).collect());
+		}
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(steps.len()),
+			JobReportUpdate::Message("Changing tags".to_string()),
+		]);
+
+		state.data = Some(BulkTagJobState::default());
+		state.steps = steps;
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let db = &ctx.library_ctx.db;
+		let step = &state.steps[0];
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		let object_ids = step
+			.iter()
+			.map(|change| change.object_id)
+			.collect::<HashSet<_>>();
+		let tag_ids = step
+			.iter()
+			.map(|change| change.tag_id)
+			.collect::<HashSet<_>>();
+
+		let tagged = db
+			.tag_on_object()
+			.find_many(vec![
+				tag_on_object::object_id::in_vec(object_ids.iter().copied().collect()),
+				tag_on_object::tag_id::in_vec(tag_ids.iter().copied().collect()),
+			])
+			.exec()
+			.await?
+			.into_iter()
+			.map(|tag_on_object| (tag_on_object.tag_id, tag_on_object.object_id))
+			.collect::<HashSet<_>>();
+
+		// objects and tags may have been deleted since the change was made (or requested), so they're skipped
+		let existing_objects = db
+			.object()
+			.find_many(vec![object::id::in_vec(object_ids.into_iter().collect())])
+			.select(object::select!({ id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|object| object.id)
+			.collect::<HashSet<_>>();
+		let existing_tags = db
+			.tag()
+			.find_many(vec![tag::id::in_vec(tag_ids.into_iter().collect())])
+			.select(tag::select!({ id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|tag| tag.id)
+			.collect::<HashSet<_>>();
+
+		// only what's actually changed is recorded, so an undo doesn't touch anything else
+		let (assigns, unassigns): (Vec<_>, Vec<_>) = step
+			.iter()
+			.filter(|change| {
+				let is_tagged = tagged.contains(&(change.tag_id, change.object_id));
+
+				if change.assign {
+					!is_tagged
+						&& existing_objects.contains(&change.object_id)
+						&& existing_tags.contains(&change.tag_id)
+				} else {
+					is_tagged
+				}
+			})
+			.partition(|change| change.assign);
+
+		db._batch(
+			assigns
+				.iter()
+				.map(|change| {
+					db.tag_on_object().create(
+						tag::id::equals(change.tag_id),
+						object::id::equals(change.object_id),
+						vec![],
+					)
+				})
+				.collect::<Vec<_>>(),
+		)
+		.await?;
+
+		db._batch(
+			unassigns
+				.iter()
+				.map(|change| {
+					db.tag_on_object().delete(tag_on_object::tag_id_object_id(
+						change.tag_id,
+						change.object_id,
+					))
+				})
+				.collect::<Vec<_>>(),
+		)
+		.await?;
+
+		db._batch(
+			assigns
+				.iter()
+				.chain(&unassigns)
+				.map(|change| {
+					db.tag_change().create(
+						state.init.undo_token.as_bytes().to_vec(),
+						change.tag_id,
+						change.object_id,
+						change.assign,
+						vec![],
+					)
+				})
+				.collect::<Vec<_>>(),
+		)
+		.await?;
+
+		data.assigned += assigns.len();
+		data.unassigned += unassigns.len();
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let library = &ctx.library_ctx;
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		// the changes that were undone can't be undone again, but the undo itself can be
+		if let BulkTagAction::Undo(undo_token) = &state.init.action {
+			library
+				.db
+				.tag_change()
+				.delete_many(vec![tag_change::change_id::equals(
+					undo_token.as_bytes().to_vec(),
+				)])
+				.exec()
+				.await?;
+		}
+
+		info!(
+			"Finished bulk tagging: {} tags assigned and {} unassigned",
+			data.assigned, data.unassigned
+		);
+
+		invalidate_query!(library, "tags.getExplorerData");
+		invalidate_query!(library, "tags.getForObject");
+		invalidate_query!(library, "savedSearches.getExplorerData");
+
+		Ok(Some(serde_json::to_value(data)?))
+	}
+}
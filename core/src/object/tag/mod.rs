@@ -0,0 +1,175 @@
+mod bulk_tag_job;
+mod rules;
+mod tag_rule_job;
+
+pub use bulk_tag_job::*;
+pub use rules::*;
+pub use tag_rule_job::*;
+
+use crate::prisma::{tag, PrismaClient};
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use prisma_client_rust::QueryError;
+use rspc::ErrorCode;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum TagError {
+	#[error("Tag not found: <id={0}>")]
+	NotFound(i32),
+	#[error("Tag <id={tag_id}> can't be nested under <id={parent_id}>, which is itself or one of its descendants")]
+	Cycle { tag_id: i32, parent_id: i32 },
+	#[error("There's nothing to undo for <undo_token={0}>")]
+	NothingToUndo(Uuid),
+	#[error("Database error: {0}")]
+	Database(#[from] QueryError),
+}
+
+impl From<TagError> for rspc::Error {
+	fn from(err: TagError) -> Self {
+		match err {
+			TagError::NotFound(_) | TagError::NothingToUndo(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			TagError::Cycle { .. } => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+tag::select!(tag_parent { id parent_id });
+
+/// TagTree is how a library's tags are nested, which their descendants are found through.
+#[derive(Debug, Default)]
+pub struct TagTree {
+	children: HashMap<i32, Vec<i32>>,
+}
+
+impl TagTree {
+	pub async fn load(db: &PrismaClient) -> Result<Self, QueryError> {
+		Ok(Self::from_parents(
+			db.tag()
+				.find_many(vec![tag::parent_id::not(None)])
+				.select(tag_parent::select())
+				.exec()
+				.await?
+				.into_iter()
+				.map(|tag| (tag.id, tag.parent_id)),
+		))
+	}
+
+	fn from_parents(tags: impl IntoIterator<Item = (i32, Option<i32>)>) -> Self {
+		let mut children = HashMap::<_, Vec<_>>::new();
+		for (tag_id, parent_id) in tags {
+			if let Some(parent_id) = parent_id {
+				children.entry(parent_id).or_default().push(tag_id);
+			}
+		}
+
+		Self { children }
+	}
+
+	/// This returns every tag nested (however deeply) under a tag, not including itself.
+	pub fn descendants(&self, tag_id: i32) -> Vec<i32> {
+		let mut descendants = vec![];
+		// cycles are never created, but this still can't loop forever if one was
+		let mut seen = HashSet::from([tag_id]);
+		let mut queue = VecDeque::from([tag_id]);
+
+		while let Some(tag_id) = queue.pop_front() {
+			for child_id in self.children.get(&tag_id).into_iter().flatten() {
+				if seen.insert(*child_id) {
+					descendants.push(*child_id);
+					queue.push_back(*child_id);
+				}
+			}
+		}
+
+		descendants
+	}
+
+	/// This returns some tags alongside all of their descendants, without any duplicates.
+	pub fn with_descendants(&self, tag_ids: &[i32]) -> Vec<i32> {
+		let mut seen = HashSet::new();
+		tag_ids
+			.iter()
+			.flat_map(|tag_id| [*tag_id].into_iter().chain(self.descendants(*tag_id)))
+			.filter(|tag_id| seen.insert(*tag_id))
+			.collect()
+	}
+}
+
+/// This nests a tag under another (or moves it to the top level), as long as that wouldn't nest it within itself.
+pub async fn set_tag_parent(
+	db: &PrismaClient,
+	tag_id: i32,
+	parent_id: Option<i32>,
+) -> Result<tag::Data, TagError> {
+	db.tag()
+		.find_unique(tag::id::equals(tag_id))
+		.exec()
+		.await?
+		.ok_or(TagError::NotFound(tag_id))?;
+
+	if let Some(parent_id) = parent_id {
+		db.tag()
+			.find_unique(tag::id::equals(parent_id))
+			.exec()
+			.await?
+			.ok_or(TagError::NotFound(parent_id))?;
+
+		if parent_id == tag_id
+			|| TagTree::load(db)
+				.await?
+				.descendants(tag_id)
+				.contains(&parent_id)
+		{
+			return Err(TagError::Cycle { tag_id, parent_id });
+		}
+	}
+
+	Ok(db
+		.tag()
+		.update(
+			tag::id::equals(tag_id),
+			vec![
+				tag::parent_id::set(parent_id),
+				tag::date_modified::set(chrono::Utc::now().into()),
+			],
+		)
+		.exec()
+		.await?)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_descendants() {
+		// 1 → 2 → 3, 1 → 4, 5
+		let tree = TagTree::from_parents([
+			(1, None),
+			(2, Some(1)),
+			(3, Some(2)),
+			(4, Some(1)),
+			(5, None),
+		]);
+
+		let mut descendants = tree.descendants(1);
+		descendants.sort_unstable();
+		assert_eq!(descendants, vec![2, 3, 4]);
+		assert_eq!(tree.descendants(3), Vec::<i32>::new());
+		assert_eq!(tree.with_descendants(&[2, 3, 5]), vec![2, 3, 5]);
+	}
+
+	#[test]
+	fn test_descendants_with_cycle() {
+		let tree = TagTree::from_parents([(1, Some(2)), (2, Some(1))]);
+		assert_eq!(tree.descendants(1), vec![2]);
+	}
+}
@@ -0,0 +1,316 @@
+use crate::{
+	library::LibraryContext,
+	prisma::{location, object, tag, tag_rule},
+};
+
+use chrono::Utc;
+use globset::{Glob, GlobMatcher};
+use prisma_client_rust::QueryError;
+use rspc::{ErrorCode, Type};
+use serde::Deserialize;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum TagRuleError {
+	#[error("Tag rule not found: <id={0}>")]
+	RuleNotFound(i32),
+	#[error("Tag not found: <id={0}>")]
+	TagNotFound(i32),
+	#[error("A tag rule needs at least one criterion, or it'd tag every object")]
+	NoCriteria,
+	#[error("The minimum size is larger than the maximum size")]
+	InvalidSizeRange,
+	#[error("Glob builder error: {0}")]
+	GlobBuilderError(#[from] globset::Error),
+	#[error("Database error: {0}")]
+	DatabaseError(#[from] QueryError),
+}
+
+impl From<TagRuleError> for rspc::Error {
+	fn from(err: TagRuleError) -> Self {
+		match err {
+			TagRuleError::RuleNotFound(_) | TagRuleError::TagNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			TagRuleError::NoCriteria
+			| TagRuleError::InvalidSizeRange
+			| TagRuleError::GlobBuilderError(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// `TagRuleCreateArgs` is the argument received from the client using rspc to create a new tag rule.
+///
+/// An object is tagged if it matches every criterion that's set, e.g. `extensions: ["cr2", "nef"]` and `camera: "Nikon"`.
+/// The glob is matched against paths relative to their location, in the same way as encryption rules.
+#[derive(Type, Deserialize)]
+pub struct TagRuleCreateArgs {
+	pub name: String,
+	pub tag_id: i32,
+	pub location_id: Option<i32>, // if this isn't set, the rule applies to every location
+	pub extensions: Option<Vec<String>>,
+	pub glob: Option<String>,
+	pub camera: Option<String>,
+	pub min_size: Option<u64>,
+	pub max_size: Option<u64>,
+}
+
+impl TagRuleCreateArgs {
+	pub async fn create(self, library: &LibraryContext) -> Result<tag_rule::Data, TagRuleError> {
+		if self.extensions.as_ref().map_or(true, Vec::is_empty)
+			&& self.glob.is_none()
+			&& self.camera.is_none()
+			&& self.min_size.is_none()
+			&& self.max_size.is_none()
+		{
+			return Err(TagRuleError::NoCriteria);
+		}
+
+		validate_criteria(self.glob.as_deref(), self.min_size, self.max_size)?;
+
+		library
+			.db
+			.tag()
+			.find_unique(tag::id::equals(self.tag_id))
+			.exec()
+			.await?
+			.ok_or(TagRuleError::TagNotFound(self.tag_id))?;
+
+		let mut params = vec![
+			tag_rule::extensions::set(self.extensions.as_deref().and_then(join_extensions)),
+			tag_rule::glob::set(self.glob),
+			tag_rule::camera::set(self.camera),
+			tag_rule::min_size::set(self.min_size.map(|size| size as i64)),
+			tag_rule::max_size::set(self.max_size.map(|size| size as i64)),
+		];
+
+		if let Some(location_id) = self.location_id {
+			params.push(tag_rule::location::connect(location::id::equals(
+				location_id,
+			)));
+		}
+
+		library
+			.db
+			.tag_rule()
+			.create(self.name, tag::id::equals(self.tag_id), params)
+			.exec()
+			.await
+			.map_err(Into::into)
+	}
+}
+
+/// `TagRuleUpdateArgs` is the argument received from the client using rspc to update a tag rule.
+///
+/// Anything that isn't set is left as-is, so criteria are cleared by setting them to an empty value (e.g. `glob: ""`).
+#[derive(Type, Deserialize)]
+pub struct TagRuleUpdateArgs {
+	pub id: i32,
+	pub name: Option<String>,
+	pub enabled: Option<bool>,
+	pub extensions: Option<Vec<String>>,
+	pub glob: Option<String>,
+	pub camera: Option<String>,
+	pub min_size: Option<u64>,
+	pub max_size: Option<u64>,
+}
+
+impl TagRuleUpdateArgs {
+	pub async fn update(self, library: &LibraryContext) -> Result<tag_rule::Data, TagRuleError> {
+		let rule = library
+			.db
+			.tag_rule()
+			.find_unique(tag_rule::id::equals(self.id))
+			.exec()
+			.await?
+			.ok_or(TagRuleError::RuleNotFound(self.id))?;
+
+		// an empty glob clears it
+		let glob = self
+			.glob
+			.map(|glob| Some(glob).filter(|glob| !glob.is_empty()));
+		validate_criteria(
+			glob.as_ref().and_then(Option::as_deref),
+			self.min_size.or(rule.min_size.map(|size| size as u64)),
+			self.max_size.or(rule.max_size.map(|size| size as u64)),
+		)?;
+
+		let mut params = vec![tag_rule::date_modified::set(Utc::now().into())];
+
+		if let Some(name) = self.name {
+			params.push(tag_rule::name::set(name));
+		}
+
+		if let Some(enabled) = self.enabled {
+			params.push(tag_rule::enabled::set(enabled));
+		}
+
+		if let Some(extensions) = self.extensions {
+			params.push(tag_rule::extensions::set(join_extensions(&extensions)));
+		}
+
+		if let Some(glob) = glob {
+			params.push(tag_rule::glob::set(glob));
+		}
+
+		if let Some(camera) = self.camera {
+			params.push(tag_rule::camera::set(
+				Some(camera).filter(|camera| !camera.is_empty()),
+			));
+		}
+
+		if let Some(min_size) = self.min_size {
+			params.push(tag_rule::min_size::set(Some(min_size as i64)));
+		}
+
+		if let Some(max_size) = self.max_size {
+			params.push(tag_rule::max_size::set(Some(max_size as i64)));
+		}
+
+		library
+			.db
+			.tag_rule()
+			.update(tag_rule::id::equals(self.id), params)
+			.exec()
+			.await
+			.map_err(Into::into)
+	}
+}
+
+// the glob is validated up front, so a rule can't be stored that'll never match
+fn validate_criteria(
+	glob: Option<&str>,
+	min_size: Option<u64>,
+	max_size: Option<u64>,
+) -> Result<(), TagRuleError> {
+	if let Some(glob) = glob {
+		Glob::new(glob)?;
+	}
+
+	match (min_size, max_size) {
+		(Some(min_size), Some(max_size)) if min_size > max_size => {
+			Err(TagRuleError::InvalidSizeRange)
+		}
+		_ => Ok(()),
+	}
+}
+
+fn join_extensions(extensions: &[String]) -> Option<String> {
+	let extensions = extensions
+		.iter()
+		.map(|extension| extension.trim().trim_start_matches('.').to_lowercase())
+		.filter(|extension| !extension.is_empty())
+		.collect::<Vec<_>>();
+
+	(!extensions.is_empty()).then(|| extensions.join(","))
+}
+
+object::select!(object_for_tag_rules {
+	id
+	size_in_bytes
+	file_paths: select { location_id materialized_path extension }
+	media_data: select { camera_make camera_model }
+	tags: select { tag_id }
+});
+
+/// TagRuleMatcher is a tag rule that's ready to be matched against objects.
+pub(crate) struct TagRuleMatcher {
+	pub rule_id: i32,
+	pub tag_id: i32,
+	location_id: Option<i32>,
+	extensions: Option<Vec<String>>,
+	glob: Option<GlobMatcher>,
+	camera: Option<String>,
+	min_size: Option<u64>,
+	max_size: Option<u64>,
+}
+
+impl TryFrom<&tag_rule::Data> for TagRuleMatcher {
+	type Error = globset::Error;
+
+	fn try_from(rule: &tag_rule::Data) -> Result<Self, Self::Error> {
+		Ok(Self {
+			rule_id: rule.id,
+			tag_id: rule.tag_id,
+			location_id: rule.location_id,
+			extensions: rule
+				.extensions
+				.as_ref()
+				.map(|extensions| extensions.split(',').map(ToString::to_string).collect()),
+			glob: rule
+				.glob
+				.as_deref()
+				.map(|glob| Glob::new(glob).map(|glob| glob.compile_matcher()))
+				.transpose()?,
+			camera: rule.camera.as_ref().map(|camera| camera.to_lowercase()),
+			min_size: rule.min_size.map(|size| size as u64),
+			max_size: rule.max_size.map(|size| size as u64),
+		})
+	}
+}
+
+impl TagRuleMatcher {
+	/// This returns whether an object matches every criterion of the rule.
+	///
+	/// The path criteria have to be matched by the same file path, as an object can have many of them.
+	pub fn matches(&self, object: &object_for_tag_rules::Data) -> bool {
+		let has_matching_path = object.file_paths.iter().any(|file_path| {
+			self.location_id
+				.map_or(true, |location_id| file_path.location_id == location_id)
+				&& self.extensions.as_ref().map_or(true, |extensions| {
+					file_path.extension.as_ref().map_or(false, |extension| {
+						extensions.contains(&extension.to_lowercase())
+					})
+				}) && self
+				.glob
+				.as_ref()
+				.map_or(true, |glob| glob.is_match(&file_path.materialized_path))
+		});
+
+		let size = object.size_in_bytes.parse::<u64>().unwrap_or_default();
+
+		let has_matching_camera = self.camera.as_ref().map_or(true, |camera| {
+			object.media_data.as_ref().map_or(false, |media_data| {
+				[&media_data.camera_make, &media_data.camera_model]
+					.into_iter()
+					.flatten()
+					.any(|value| value.to_lowercase().contains(camera))
+			})
+		});
+
+		has_matching_path
+			&& has_matching_camera
+			&& self.min_size.map_or(true, |min_size| size >= min_size)
+			&& self.max_size.map_or(true, |max_size| size <= max_size)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_join_extensions() {
+		assert_eq!(
+			join_extensions(&[".JPG".to_string(), " png ".to_string(), "".to_string()]),
+			Some("jpg,png".to_string())
+		);
+		assert_eq!(join_extensions(&[]), None);
+	}
+
+	#[test]
+	fn test_validate_criteria() {
+		assert!(validate_criteria(Some("Photos/**"), Some(1), Some(2)).is_ok());
+		assert!(matches!(
+			validate_criteria(None, Some(2), Some(1)),
+			Err(TagRuleError::InvalidSizeRange)
+		));
+		assert!(matches!(
+			validate_criteria(Some("Photos/[a"), None, None),
+			Err(TagRuleError::GlobBuilderError(_))
+		));
+	}
+}
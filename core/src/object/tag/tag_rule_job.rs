@@ -0,0 +1,244 @@
+use crate::{
+	invalidate_query,
+	job::{
+		JobError, JobLogLevel, JobPriority, JobReportUpdate, JobResult, JobState, StatefulJob,
+		WorkerContext,
+	},
+	prisma::{file_path, object, tag, tag_rule},
+};
+
+use std::collections::HashMap;
+
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::rules::{object_for_tag_rules, TagRuleMatcher};
+
+pub const TAG_RULE_JOB_NAME: &str = "tag_rules";
+
+/// This many objects are matched against the rules in each step.
+const TAG_RULE_BATCH_SIZE: usize = 500;
+
+/// TagRuleJob tags the objects that match the enabled tag rules.
+///
+/// It's queued whenever a location is indexed, and is run for every location to apply new rules to objects that were indexed before them.
+pub struct TagRuleJob {}
+
+#[derive(Serialize, Deserialize, Clone, Hash)]
+pub struct TagRuleJobInit {
+	/// the location whose objects are tagged, or every location if this isn't set
+	pub location_id: Option<i32>,
+	/// the rules that are applied, or every enabled rule if this isn't set
+	pub rule_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TagRuleJobState {
+	rules: Vec<tag_rule::Data>,
+	/// the id of the last object that was matched
+	cursor: i32,
+	total_objects: usize,
+	/// how many objects each tag was added to
+	tagged: HashMap<i32, usize>,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for TagRuleJob {
+	type Init = TagRuleJobInit;
+	type Data = TagRuleJobState;
+	type Step = ();
+
+	fn name(&self) -> &'static str {
+		TAG_RULE_JOB_NAME
+	}
+
+	fn priority(&self) -> JobPriority {
+		JobPriority::Low
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let library = &ctx.library_ctx;
+
+		let mut rule_filters = vec![tag_rule::enabled::equals(true)];
+		if let Some(rule_id) = state.init.rule_id {
+			rule_filters.push(tag_rule::id::equals(rule_id));
+		}
+
+		// rules for other locations would never match any of this location's objects
+		let rules = library
+			.db
+			.tag_rule()
+			.find_many(rule_filters)
+			.exec()
+			.await?
+			.into_iter()
+			.filter(|rule| match (state.init.location_id, rule.location_id) {
+				(Some(location_id), Some(rule_location_id)) => location_id == rule_location_id,
+				_ => true,
+			})
+			.collect::<Vec<_>>();
+
+		let total_objects = if rules.is_empty() {
+			0
+		} else {
+			library
+				.db
+				.object()
+				.count(object_filters(state.init.location_id, None))
+				.exec()
+				.await? as usize
+		};
+
+		let task_count = (total_objects as f64 / TAG_RULE_BATCH_SIZE as f64).ceil() as usize;
+		info!(
+			"Matching {} objects against {} tag rules",
+			total_objects,
+			rules.len()
+		);
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(task_count),
+			JobReportUpdate::Message(format!(
+				"Matching {} objects against {} tag rules",
+				total_objects,
+				rules.len()
+			)),
+		]);
+
+		state.data = Some(TagRuleJobState {
+			rules,
+			cursor: 0,
+			total_objects,
+			tagged: HashMap::new(),
+		});
+		state.steps = (0..task_count).map(|_| ()).collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let library = &ctx.library_ctx;
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		let matchers = data
+			.rules
+			.iter()
+			.filter_map(|rule| match TagRuleMatcher::try_from(rule) {
+				Ok(matcher) => Some(matcher),
+				Err(e) => {
+					warn!("Skipping tag rule <id={}>: {:#?}", rule.id, e);
+					ctx.log(
+						JobLogLevel::Warning,
+						format!("Skipping tag rule '{}': {}", rule.name, e),
+						None,
+					);
+					None
+				}
+			})
+			.collect::<Vec<_>>();
+
+		let objects = library
+			.db
+			.object()
+			.find_many(object_filters(state.init.location_id, Some(data.cursor)))
+			.order_by(object::id::order(Direction::Asc))
+			.take(TAG_RULE_BATCH_SIZE as i64)
+			.select(object_for_tag_rules::select())
+			.exec()
+			.await?;
+
+		if let Some(last) = objects.last() {
+			data.cursor = last.id;
+		}
+
+		let mut creates = vec![];
+		for object in &objects {
+			for matcher in &matchers {
+				// an object may match many rules for the same tag, but is only tagged once
+				if object.tags.iter().any(|tag| tag.tag_id == matcher.tag_id)
+					|| creates.iter().any(|(object_id, tag_id)| {
+						*object_id == object.id && *tag_id == matcher.tag_id
+					}) || !matcher.matches(object)
+				{
+					continue;
+				}
+
+				creates.push((object.id, matcher.tag_id));
+			}
+		}
+
+		for (_, tag_id) in &creates {
+			*data.tagged.entry(*tag_id).or_default() += 1;
+		}
+
+		if !creates.is_empty() {
+			library
+				.db
+				._batch(
+					creates
+						.into_iter()
+						.map(|(object_id, tag_id)| {
+							library.db.tag_on_object().create(
+								tag::id::equals(tag_id),
+								object::id::equals(object_id),
+								vec![],
+							)
+						})
+						.collect::<Vec<_>>(),
+				)
+				.await?;
+
+			invalidate_query!(library, "tags.getExplorerData");
+			invalidate_query!(library, "tags.getForObject");
+			invalidate_query!(library, "savedSearches.getExplorerData");
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!(
+			"Finished matching {} objects against tag rules, adding {} tags to them",
+			data.total_objects,
+			data.tagged.values().sum::<usize>()
+		);
+
+		Ok(Some(serde_json::json!({
+			"total_objects": data.total_objects,
+			"tagged": data.tagged,
+		})))
+	}
+}
+
+fn object_filters(location_id: Option<i32>, cursor: Option<i32>) -> Vec<object::WhereParam> {
+	let mut filters = vec![];
+
+	if let Some(location_id) = location_id {
+		filters.push(object::file_paths::some(vec![
+			file_path::location_id::equals(location_id),
+		]));
+	}
+
+	if let Some(cursor) = cursor {
+		filters.push(object::id::gt(cursor));
+	}
+
+	filters
+}
@@ -0,0 +1,201 @@
+use std::{
+	fs::File,
+	io::{self, Read},
+	path::Path,
+};
+
+use sd_file_ext::extensions::{DocumentExtension, Extension, TextExtension};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zip::ZipArchive;
+
+/// Only this much of a document's text is indexed, which is plenty to find it by.
+pub const MAX_EXTRACTED_TEXT_BYTES: usize = 1024 * 1024;
+/// This limits how much of an office document's XML is read, so a zip bomb can't exhaust our memory.
+const MAX_OFFICE_XML_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum TextExtractionError {
+	#[error("I/O error: {0}")]
+	IO(#[from] io::Error),
+	#[error("Failed to read the document's archive: {0}")]
+	Zip(#[from] zip::result::ZipError),
+	#[error("Failed to read the PDF: {0}")]
+	Pdf(#[from] pdf_extract::OutputError),
+}
+
+/// TextKind is how the text of a document is extracted.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TextKind {
+	Plain,
+	Pdf,
+	Docx,
+	Xlsx,
+	Pptx,
+	/// the OpenDocument formats all store their text in the same way
+	OpenDocument,
+}
+
+impl TextKind {
+	pub fn from_extension(extension: &Extension) -> Option<Self> {
+		use DocumentExtension::*;
+
+		match extension {
+			// RTF is mostly formatting, which we'd just be indexing
+			Extension::Text(TextExtension::Rtf) => None,
+			Extension::Text(_) => Some(Self::Plain),
+			Extension::Document(Pdf) => Some(Self::Pdf),
+			Extension::Document(Docx) => Some(Self::Docx),
+			Extension::Document(Xlsx) => Some(Self::Xlsx),
+			Extension::Document(Pptx) => Some(Self::Pptx),
+			Extension::Document(Odt | Ods | Odp) => Some(Self::OpenDocument),
+			_ => None,
+		}
+	}
+}
+
+/// This extracts the text of a document, truncated to `MAX_EXTRACTED_TEXT_BYTES`.
+///
+/// This blocks, and PDFs can take a while, so it should be run on a blocking thread.
+pub fn extract_text(path: impl AsRef<Path>, kind: TextKind) -> Result<String, TextExtractionError> {
+	let path = path.as_ref();
+
+	let text = match kind {
+		TextKind::Plain => {
+			let mut bytes = Vec::new();
+			File::open(path)?
+				.take(MAX_EXTRACTED_TEXT_BYTES as u64)
+				.read_to_end(&mut bytes)?;
+
+			String::from_utf8_lossy(&bytes).into_owned()
+		}
+		TextKind::Pdf => pdf_extract::extract_text(path)?,
+		TextKind::Docx => office_text(path, |name| name == "word/document.xml")?,
+		TextKind::Xlsx => office_text(path, |name| name == "xl/sharedStrings.xml")?,
+		TextKind::Pptx => office_text(path, |name| {
+			name.starts_with("ppt/slides/slide") && name.ends_with(".xml")
+		})?,
+		TextKind::OpenDocument => office_text(path, |name| name == "content.xml")?,
+	};
+
+	Ok(truncate(text))
+}
+
+/// Office documents are zip archives of XML, so their text is what's between the tags of the given entries.
+fn office_text(
+	path: &Path,
+	is_text_entry: impl Fn(&str) -> bool,
+) -> Result<String, TextExtractionError> {
+	let mut archive = ZipArchive::new(File::open(path)?)?;
+
+	let mut names = archive
+		.file_names()
+		.filter(|name| is_text_entry(*name))
+		.map(ToString::to_string)
+		.collect::<Vec<_>>();
+	// this keeps numbered entries in order, e.g. "slide2.xml" before "slide10.xml"
+	names.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+
+	let mut text = String::new();
+	for name in names {
+		let mut xml = String::new();
+		archive
+			.by_name(&name)?
+			.take(MAX_OFFICE_XML_BYTES)
+			.read_to_string(&mut xml)?;
+
+		text.push_str(&xml_text(&xml));
+		text.push('\n');
+
+		if text.len() > MAX_EXTRACTED_TEXT_BYTES {
+			break;
+		}
+	}
+
+	Ok(text)
+}
+
+/// This returns the text content of some XML, with whitespace wherever a paragraph, cell or line ends.
+fn xml_text(xml: &str) -> String {
+	let mut text = String::with_capacity(xml.len() / 4);
+	let mut rest = xml;
+
+	while let Some(start) = rest.find('<') {
+		text.push_str(&unescape(&rest[..start]));
+
+		let end = match rest[start..].find('>') {
+			Some(end) => start + end,
+			None => break,
+		};
+		let tag = &rest[start + 1..end];
+		rest = &rest[end + 1..];
+
+		// only the local name matters, e.g. "p" from "</w:p>" or "line-break" from "<text:line-break/>"
+		let name = tag
+			.trim_start_matches('/')
+			.split(|c: char| c.is_whitespace() || c == '/')
+			.next()
+			.unwrap_or_default();
+		let local_name = name.rsplit(':').next().unwrap_or_default();
+
+		match local_name {
+			"p" | "si" | "br" | "cr" | "line-break" | "h"
+				if tag.starts_with('/') || tag.ends_with('/') =>
+			{
+				text.push('\n')
+			}
+			"tab" | "s" | "tab-stop" => text.push(' '),
+			_ => {}
+		}
+	}
+
+	text.push_str(&unescape(rest));
+	text
+}
+
+fn unescape(text: &str) -> String {
+	if !text.contains('&') {
+		return text.to_string();
+	}
+
+	text.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&apos;", "'")
+		.replace("&amp;", "&")
+}
+
+fn truncate(mut text: String) -> String {
+	if text.len() > MAX_EXTRACTED_TEXT_BYTES {
+		let mut end = MAX_EXTRACTED_TEXT_BYTES;
+		while !text.is_char_boundary(end) {
+			end -= 1;
+		}
+		text.truncate(end);
+	}
+
+	text
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_xml_text() {
+		let docx = r#"<w:document><w:body><w:p><w:r><w:t>Hello</w:t></w:r><w:r><w:t xml:space="preserve"> wor</w:t></w:r><w:r><w:t>ld</w:t></w:r></w:p><w:p><w:r><w:t>Fish &amp; chips</w:t><w:tab/><w:t>£5</w:t></w:r></w:p></w:body></w:document>"#;
+		assert_eq!(xml_text(docx), "Hello world\nFish & chips £5\n");
+
+		let xlsx = r#"<sst><si><t>first</t></si><si><t>second</t></si></sst>"#;
+		assert_eq!(xml_text(xlsx), "first\nsecond\n");
+	}
+
+	#[test]
+	fn test_truncate() {
+		let text = "é".repeat(MAX_EXTRACTED_TEXT_BYTES);
+		let truncated = truncate(text);
+
+		assert!(truncated.len() <= MAX_EXTRACTED_TEXT_BYTES);
+		assert!(truncated.chars().all(|c| c == 'é'));
+	}
+}
@@ -0,0 +1,5 @@
+mod extract;
+mod text_indexer_job;
+
+pub use extract::*;
+pub use text_indexer_job::*;
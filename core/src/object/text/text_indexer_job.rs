@@ -0,0 +1,247 @@
+use crate::{
+	invalidate_query,
+	job::{
+		JobError, JobLogLevel, JobPriority, JobReportUpdate, JobResult, JobState, StatefulJob,
+		WorkerContext,
+	},
+	location::LocationError,
+	prisma::{file_path, location},
+};
+
+use std::{
+	collections::{HashMap, HashSet, VecDeque},
+	path::PathBuf,
+};
+
+use sd_file_ext::extensions::{Extension, ALL_DOCUMENT_EXTENSIONS, ALL_TEXT_EXTENSIONS};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, task::spawn_blocking};
+use tracing::{info, warn};
+
+use super::{extract_text, TextKind};
+
+pub const TEXT_INDEXER_JOB_NAME: &str = "text_indexer";
+
+/// This many documents are extracted before they're committed to the search index, as every commit creates a new segment.
+const TEXT_INDEXER_BATCH_SIZE: usize = 50;
+/// Larger documents aren't worth extracting, as they're rarely documents that anyone reads.
+const MAX_DOCUMENT_SIZE: u64 = 100 * 1024 * 1024;
+
+/// TextIndexerJob extracts the text of the documents within a location into the library's search index.
+pub struct TextIndexerJob {}
+
+#[derive(Serialize, Deserialize, Clone, Hash)]
+pub struct TextIndexerJobInit {
+	pub location_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TextIndexerJobState {
+	root_path: PathBuf,
+	indexed: usize,
+	failed: usize,
+}
+
+file_path::select!(file_path_for_text_indexer {
+	materialized_path
+	extension
+	object_id
+});
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TextIndexerJobFile {
+	object_id: i32,
+	materialized_path: String,
+	kind: TextKind,
+}
+
+pub type TextIndexerJobStep = Vec<TextIndexerJobFile>;
+
+#[async_trait::async_trait]
+impl StatefulJob for TextIndexerJob {
+	type Init = TextIndexerJobInit;
+	type Data = TextIndexerJobState;
+	type Step = TextIndexerJobStep;
+
+	fn name(&self) -> &'static str {
+		TEXT_INDEXER_JOB_NAME
+	}
+
+	fn priority(&self) -> JobPriority {
+		JobPriority::Low
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let library = &ctx.library_ctx;
+
+		let location = library
+			.db
+			.location()
+			.find_unique(location::id::equals(state.init.location_id))
+			.exec()
+			.await?
+			.ok_or(LocationError::IdNotFound(state.init.location_id))?;
+
+		state.data = Some(TextIndexerJobState {
+			root_path: location
+				.local_path
+				.map(PathBuf::from)
+				.ok_or(LocationError::MissingLocalPath(location.id))?,
+			..Default::default()
+		});
+
+		// the index isn't encrypted, so the contents of files within a vault are never indexed
+		if location.vault_key_id.is_some() {
+			info!("Skipping text indexing for vault location {}", location.id);
+			return Ok(());
+		}
+
+		let kinds = ALL_TEXT_EXTENSIONS
+			.iter()
+			.map(|extension| Extension::Text(*extension))
+			.chain(
+				ALL_DOCUMENT_EXTENSIONS
+					.iter()
+					.map(|extension| Extension::Document(*extension)),
+			)
+			.filter_map(|extension| {
+				TextKind::from_extension(&extension).map(|kind| (extension.to_string(), kind))
+			})
+			.collect::<HashMap<_, _>>();
+
+		let file_paths = library
+			.db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(state.init.location_id),
+				file_path::is_dir::equals(false),
+				file_path::extension::in_vec(kinds.keys().cloned().collect()),
+				file_path::object_id::not(None),
+			])
+			.select(file_path_for_text_indexer::select())
+			.exec()
+			.await?;
+
+		let mut seen = HashSet::new();
+		let mut files = vec![];
+		for file_path in file_paths {
+			let (object_id, kind) = match (
+				file_path.object_id,
+				file_path
+					.extension
+					.as_ref()
+					.and_then(|extension| kinds.get(extension)),
+			) {
+				(Some(object_id), Some(kind)) => (object_id, *kind),
+				_ => continue,
+			};
+
+			// every object is only indexed once, even if it has many file paths
+			if seen.insert(object_id) && !library.search_index.contains(object_id)? {
+				files.push(TextIndexerJobFile {
+					object_id,
+					materialized_path: file_path.materialized_path,
+					kind,
+				});
+			}
+		}
+
+		info!(
+			"Found {} documents to index in location {}",
+			files.len(),
+			location.id
+		);
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(files.len()),
+			JobReportUpdate::Message(format!("Preparing to index {} documents", files.len())),
+		]);
+
+		let mut steps = VecDeque::new();
+		let mut files = files.into_iter().peekable();
+		while files.peek().is_some() {
+			steps.push_back(files.by_ref().take(TEXT_INDEXER_BATCH_SIZE).collect());
+		}
+		state.steps = steps;
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		let mut documents = Vec::with_capacity(step.len());
+
+		for file in step {
+			let path = data.root_path.join(&file.materialized_path);
+
+			ctx.progress(vec![JobReportUpdate::Message(format!(
+				"Extracting text from {}",
+				file.materialized_path
+			))]);
+
+			let extracted = match fs::metadata(&path).await {
+				Ok(metadata) if metadata.len() > MAX_DOCUMENT_SIZE => Ok(String::new()),
+				Ok(_) => {
+					let (path, kind) = (path.clone(), file.kind);
+					// some documents make the extractors panic, which is treated the same as any other failure
+					match spawn_blocking(move || extract_text(path, kind)).await {
+						Ok(extracted) => extracted.map_err(|e| e.to_string()),
+						Err(e) => Err(e.to_string()),
+					}
+				}
+				Err(e) => Err(e.to_string()),
+			};
+
+			// documents without any (readable) text are still indexed, so they aren't extracted again
+			let text = extracted.unwrap_or_else(|e| {
+				warn!("Failed to extract the text of {:?}: {}", path, e);
+				ctx.log(
+					JobLogLevel::Warning,
+					format!("Failed to extract text: {}", e),
+					Some(path.display().to_string()),
+				);
+				data.failed += 1;
+
+				String::new()
+			});
+
+			documents.push((file.object_id, text));
+		}
+
+		data.indexed += documents.len();
+
+		let search_index = ctx.library_ctx.search_index.clone();
+		spawn_blocking(move || search_index.index(documents)).await??;
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(data.indexed)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!(
+			"Finished indexing the text of location {}: {} documents, {} of which couldn't be read",
+			state.init.location_id, data.indexed, data.failed
+		);
+
+		if data.indexed > 0 {
+			invalidate_query!(ctx.library_ctx, "search.fullText");
+		}
+
+		Ok(Some(serde_json::to_value(data)?))
+	}
+}
@@ -3,7 +3,9 @@ use serde::{Deserialize, Serialize};
 use std::{collections::VecDeque, path::PathBuf};
 
 use crate::{
-	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	job::{
+		JobError, JobPriority, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+	},
 	prisma::{file_path, location, object},
 };
 
@@ -51,6 +53,10 @@ impl StatefulJob for ObjectValidatorJob {
 		VALIDATOR_JOB_NAME
 	}
 
+	fn priority(&self) -> JobPriority {
+		JobPriority::Low
+	}
+
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
 		state.steps = ctx
 			.library_ctx
@@ -0,0 +1,466 @@
+mod version_job;
+
+pub use version_job::*;
+
+use crate::{
+	library::LibraryContext,
+	location::file_path_helper::record_file_path,
+	prisma::{file_version, location},
+};
+
+use std::{
+	collections::HashSet,
+	io,
+	path::{Path, PathBuf},
+};
+
+use blake3::Hasher;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use itertools::Itertools;
+use prisma_client_rust::{Direction, QueryError};
+use rspc::{ErrorCode, Type};
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::{
+	fs::{self, File},
+	io::{AsyncReadExt, AsyncWriteExt},
+};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+// Versioning is opt-in for each location. Whenever a file within a versioned location changes (as seen by the watcher, or by the versioner
+// job when the location is scanned), a snapshot of its contents is taken. Snapshots are named by their BLAKE3 digest, so a file that changes
+// back and forth (or identical files) only take up space once. Versions are kept by the file's path within its location, and snapshots
+// that no version refers to anymore are deleted as soon as their last version is.
+
+pub const VERSION_STORE_DIR_NAME: &str = "versions";
+
+const SNAPSHOT_CHUNK_SIZE: usize = 1024 * 1024;
+
+location::select!(location_for_versioning {
+	id
+	local_path
+	vault_key_id
+	versioning
+	max_versions
+	max_version_age
+});
+
+/// `VersionRetention` is how long a location's versions are kept, which is forever unless something is set.
+#[derive(Type, Deserialize)]
+pub struct VersionRetention {
+	/// how many versions are kept of each file
+	pub max_versions: Option<u32>,
+	/// how many days versions are kept for
+	pub max_age_days: Option<u32>,
+}
+
+#[derive(Error, Debug)]
+pub enum VersionError {
+	#[error("Location not found: <id={0}>")]
+	LocationNotFound(i32),
+	#[error("Location isn't local: <id={0}>")]
+	MissingLocalPath(i32),
+	#[error(
+		"Vault locations can't be versioned, as their snapshots wouldn't be encrypted: <id={0}>"
+	)]
+	VaultLocation(i32),
+	#[error("Version not found: <id={0}>")]
+	NotFound(i32),
+	#[error("Version's snapshot is missing from the version store: <id={0}>")]
+	MissingSnapshot(i32),
+	#[error("I/O error: {0}")]
+	IOError(#[from] io::Error),
+	#[error("Database error: {0}")]
+	DatabaseError(#[from] QueryError),
+}
+
+impl From<VersionError> for rspc::Error {
+	fn from(err: VersionError) -> Self {
+		match err {
+			VersionError::LocationNotFound(_) | VersionError::NotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			VersionError::MissingLocalPath(_) | VersionError::VaultLocation(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// This is where the library's snapshots are kept, within the node's data directory.
+pub fn version_store(library: &LibraryContext) -> PathBuf {
+	library
+		.config()
+		.data_directory()
+		.join(VERSION_STORE_DIR_NAME)
+		.join(library.id.to_string())
+}
+
+/// This fetches a location if it's versioned, and can be.
+pub async fn fetch_versioned_location(
+	library: &LibraryContext,
+	location_id: i32,
+) -> Result<Option<location_for_versioning::Data>, QueryError> {
+	Ok(library
+		.db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.select(location_for_versioning::select())
+		.exec()
+		.await?
+		.filter(|location| {
+			location.versioning && location.local_path.is_some() && location.vault_key_id.is_none()
+		}))
+}
+
+/// This takes a snapshot of a file within a versioned location, unless it hasn't changed since its latest version.
+///
+/// It returns whether a new version was taken, and old versions of the file are expired according to the location's retention policy.
+pub async fn snapshot_file(
+	library: &LibraryContext,
+	location: &location_for_versioning::Data,
+	materialized_path: &str,
+) -> Result<bool, VersionError> {
+	let local_path = location
+		.local_path
+		.as_ref()
+		.ok_or(VersionError::MissingLocalPath(location.id))?;
+	let path = Path::new(local_path).join(materialized_path);
+
+	let metadata = fs::metadata(&path).await?;
+	if !metadata.is_file() {
+		return Ok(false);
+	}
+
+	let date_modified: DateTime<FixedOffset> = DateTime::<Utc>::from(metadata.modified()?).into();
+
+	let latest = library
+		.db
+		.file_version()
+		.find_first(vec![
+			file_version::location_id::equals(location.id),
+			file_version::materialized_path::equals(materialized_path.to_string()),
+		])
+		.order_by(file_version::date_created::order(Direction::Desc))
+		.exec()
+		.await?;
+
+	// files whose size and modification time are the same as their latest version's aren't read at all
+	if let Some(latest) = &latest {
+		if latest.size_in_bytes == metadata.len().to_string()
+			&& latest.date_modified.timestamp_millis() == date_modified.timestamp_millis()
+		{
+			return Ok(false);
+		}
+	}
+
+	let (content_hash, size) = store_snapshot(&version_store(library), &path).await?;
+
+	if latest.map_or(false, |latest| latest.content_hash == content_hash) {
+		return Ok(false);
+	}
+
+	library
+		.db
+		.file_version()
+		.create(
+			materialized_path.to_string(),
+			content_hash,
+			size.to_string(),
+			date_modified,
+			location::id::equals(location.id),
+			vec![],
+		)
+		.exec()
+		.await?;
+
+	debug!("Took a snapshot of {}", path.display());
+
+	apply_retention(library, location, Some(vec![materialized_path.to_string()])).await?;
+
+	Ok(true)
+}
+
+/// This copies a file into the version store, and returns its digest and size.
+///
+/// The file is hashed while it's copied (rather than before), so the snapshot is always named by what was actually copied.
+async fn store_snapshot(store: &Path, path: &Path) -> Result<(String, u64), io::Error> {
+	fs::create_dir_all(store).await?;
+
+	let temp_path = store.join(format!(".{}.tmp", Uuid::new_v4()));
+
+	let result = async {
+		let mut source = File::open(path).await?;
+		let mut output = File::create(&temp_path).await?;
+		let mut hasher = Hasher::new();
+		let mut buf = vec![0u8; SNAPSHOT_CHUNK_SIZE];
+		let mut size = 0;
+
+		loop {
+			let read = source.read(&mut buf).await?;
+			if read == 0 {
+				break;
+			}
+			hasher.update(&buf[..read]);
+			output.write_all(&buf[..read]).await?;
+			size += read as u64;
+		}
+
+		output.flush().await?;
+
+		Ok::<_, io::Error>((hasher.finalize().to_hex().to_string(), size))
+	}
+	.await;
+
+	let (content_hash, size) = match result {
+		Ok(snapshot) => snapshot,
+		Err(e) => {
+			fs::remove_file(&temp_path).await.ok();
+			return Err(e);
+		}
+	};
+
+	let snapshot_path = store.join(&content_hash);
+	if fs::metadata(&snapshot_path).await.is_ok() {
+		// these contents were already kept
+		fs::remove_file(&temp_path).await?;
+	} else {
+		fs::rename(&temp_path, &snapshot_path).await?;
+	}
+
+	Ok((content_hash, size))
+}
+
+/// This returns the ids of versions (of a single file, from newest to oldest) that have expired.
+///
+/// The newest version is never expired, so a file always has at least one version to restore.
+fn expired_versions(
+	versions: &[(i32, DateTime<Utc>)],
+	max_versions: Option<usize>,
+	max_age: Option<Duration>,
+	now: DateTime<Utc>,
+) -> Vec<i32> {
+	versions
+		.iter()
+		.enumerate()
+		.skip(1)
+		.filter(|(i, (_, date_created))| {
+			max_versions.map_or(false, |max_versions| *i >= max_versions)
+				|| max_age.map_or(false, |max_age| now - *date_created > max_age)
+		})
+		.map(|(_, (id, _))| *id)
+		.collect()
+}
+
+/// This deletes the versions that have expired according to the location's retention policy, for these files or every file within the location.
+///
+/// It returns how many versions were deleted.
+pub async fn apply_retention(
+	library: &LibraryContext,
+	location: &location_for_versioning::Data,
+	materialized_paths: Option<Vec<String>>,
+) -> Result<usize, VersionError> {
+	if location.max_versions.is_none() && location.max_version_age.is_none() {
+		return Ok(0);
+	}
+
+	let mut filters = vec![file_version::location_id::equals(location.id)];
+	if let Some(materialized_paths) = materialized_paths {
+		filters.push(file_version::materialized_path::in_vec(materialized_paths));
+	}
+
+	let versions = library
+		.db
+		.file_version()
+		.find_many(filters)
+		.order_by(file_version::materialized_path::order(Direction::Asc))
+		.order_by(file_version::date_created::order(Direction::Desc))
+		.exec()
+		.await?;
+
+	let max_versions = location.max_versions.map(|max| max.max(1) as usize);
+	let max_age = location
+		.max_version_age
+		.map(|days| Duration::days(days.into()));
+	let now = Utc::now();
+
+	let mut expired = vec![];
+	for (_, file_versions) in &versions
+		.iter()
+		.group_by(|version| &version.materialized_path)
+	{
+		expired.extend(expired_versions(
+			&file_versions
+				.map(|version| (version.id, DateTime::<Utc>::from(version.date_created)))
+				.collect::<Vec<_>>(),
+			max_versions,
+			max_age,
+			now,
+		));
+	}
+
+	if expired.is_empty() {
+		return Ok(0);
+	}
+
+	let expired_hashes = versions
+		.iter()
+		.filter(|version| expired.contains(&version.id))
+		.map(|version| version.content_hash.clone())
+		.collect();
+
+	library
+		.db
+		.file_version()
+		.delete_many(vec![file_version::id::in_vec(expired.clone())])
+		.exec()
+		.await?;
+
+	delete_unreferenced_snapshots(library, expired_hashes).await?;
+
+	Ok(expired.len())
+}
+
+/// This deletes the snapshots with these digests from the version store, unless a version still refers to them.
+pub async fn delete_unreferenced_snapshots(
+	library: &LibraryContext,
+	content_hashes: HashSet<String>,
+) -> Result<(), VersionError> {
+	if content_hashes.is_empty() {
+		return Ok(());
+	}
+
+	let referenced = library
+		.db
+		.file_version()
+		.find_many(vec![file_version::content_hash::in_vec(
+			content_hashes.iter().cloned().collect(),
+		)])
+		.exec()
+		.await?
+		.into_iter()
+		.map(|version| version.content_hash)
+		.collect::<HashSet<_>>();
+
+	let store = version_store(library);
+	for content_hash in content_hashes.difference(&referenced) {
+		if let Err(e) = fs::remove_file(store.join(content_hash)).await {
+			if e.kind() != io::ErrorKind::NotFound {
+				warn!("Failed to delete snapshot {}: {:#?}", content_hash, e);
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// This puts the contents of a previous version back into its file (which is recreated if it was deleted).
+///
+/// The file's current contents are snapshotted first, and the restored contents become its newest version, so restoring can be undone.
+pub async fn restore_version(library: &LibraryContext, id: i32) -> Result<(), VersionError> {
+	let version = library
+		.db
+		.file_version()
+		.find_unique(file_version::id::equals(id))
+		.exec()
+		.await?
+		.ok_or(VersionError::NotFound(id))?;
+
+	let location = library
+		.db
+		.location()
+		.find_unique(location::id::equals(version.location_id))
+		.select(location_for_versioning::select())
+		.exec()
+		.await?
+		.ok_or(VersionError::LocationNotFound(version.location_id))?;
+	let local_path = location
+		.local_path
+		.as_ref()
+		.ok_or(VersionError::MissingLocalPath(location.id))?;
+	let path = Path::new(local_path).join(&version.materialized_path);
+
+	let snapshot_path = version_store(library).join(&version.content_hash);
+	if fs::metadata(&snapshot_path).await.is_err() {
+		return Err(VersionError::MissingSnapshot(id));
+	}
+
+	let existed = fs::metadata(&path).await.is_ok();
+	if existed {
+		snapshot_file(library, &location, &version.materialized_path).await?;
+	} else if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent).await?;
+	}
+
+	// the snapshot is copied next to the file and then renamed over it, so the file is never left half-written
+	let file_name = path
+		.file_name()
+		.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+	let mut temp_name = file_name.to_os_string();
+	temp_name.push(format!(".{}.tmp", Uuid::new_v4()));
+	let temp_path = path.with_file_name(temp_name);
+
+	if let Err(e) = fs::copy(&snapshot_path, &temp_path).await {
+		fs::remove_file(&temp_path).await.ok();
+		return Err(e.into());
+	}
+	fs::rename(&temp_path, &path).await?;
+
+	snapshot_file(library, &location, &version.materialized_path).await?;
+
+	// a file that already existed is identified again like any other change, while a recreated one has to be recorded
+	if !existed {
+		record_file_path(
+			library,
+			location.id,
+			&version.materialized_path,
+			false,
+			None,
+			version.size_in_bytes.parse().unwrap_or(0),
+		)
+		.await?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn keeps_newest_version() {
+		let now = Utc::now();
+		let versions = [(3, now - Duration::days(30)), (2, now - Duration::days(40))];
+
+		assert_eq!(
+			expired_versions(&versions, Some(1), Some(Duration::days(7)), now),
+			vec![2]
+		);
+	}
+
+	#[test]
+	fn expires_by_count_and_age() {
+		let now = Utc::now();
+		let versions = [
+			(5, now - Duration::hours(1)),
+			(4, now - Duration::days(1)),
+			(3, now - Duration::days(2)),
+			(2, now - Duration::days(10)),
+			(1, now - Duration::days(20)),
+		];
+
+		assert_eq!(expired_versions(&versions, Some(3), None, now), vec![2, 1]);
+		assert_eq!(
+			expired_versions(&versions, None, Some(Duration::days(5)), now),
+			vec![2, 1]
+		);
+		assert_eq!(
+			expired_versions(&versions, Some(2), Some(Duration::days(5)), now),
+			vec![3, 2, 1]
+		);
+		assert!(expired_versions(&versions, None, None, now).is_empty());
+	}
+}
@@ -0,0 +1,181 @@
+use crate::{
+	invalidate_query,
+	job::{
+		JobError, JobLogLevel, JobPriority, JobReportUpdate, JobResult, JobState, StatefulJob,
+		WorkerContext,
+	},
+	prisma::file_path,
+};
+
+use prisma_client_rust::Direction;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::{
+	apply_retention, fetch_versioned_location, location_for_versioning, snapshot_file, VersionError,
+};
+
+pub const FILE_VERSIONER_JOB_NAME: &str = "file_versioner";
+
+/// This many files are checked for changes in each step.
+const VERSIONER_BATCH_SIZE: usize = 100;
+
+file_path::select!(file_path_for_versioner { id materialized_path });
+
+/// FileVersionerJob takes snapshots of the files within a versioned location that changed since their latest version.
+///
+/// It's spawned when versioning is enabled for a location (to take the first version of every file), and queued whenever the location
+/// is scanned, to catch changes that the watcher didn't see. Once it's done, versions that expired are deleted.
+pub struct FileVersionerJob {}
+
+#[derive(Serialize, Deserialize, Clone, Hash)]
+pub struct FileVersionerJobInit {
+	pub location_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileVersionerJobState {
+	location: location_for_versioning::Data,
+	/// the id of the last file path that was checked
+	cursor: i32,
+	snapshots: usize,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for FileVersionerJob {
+	type Init = FileVersionerJobInit;
+	type Data = FileVersionerJobState;
+	type Step = ();
+
+	fn name(&self) -> &'static str {
+		FILE_VERSIONER_JOB_NAME
+	}
+
+	fn priority(&self) -> JobPriority {
+		JobPriority::Low
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let library = &ctx.library_ctx;
+
+		let location = fetch_versioned_location(library, state.init.location_id)
+			.await?
+			.ok_or(JobError::EarlyFinish {
+				name: self.name().to_string(),
+				reason: "Location isn't versioned".to_string(),
+			})?;
+
+		let total_files = library
+			.db
+			.file_path()
+			.count(file_path_filters(location.id, None))
+			.exec()
+			.await? as usize;
+
+		let task_count = (total_files as f64 / VERSIONER_BATCH_SIZE as f64).ceil() as usize;
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(task_count),
+			JobReportUpdate::Message(format!("Checking {total_files} files for changes")),
+		]);
+
+		state.data = Some(FileVersionerJobState {
+			location,
+			cursor: 0,
+			snapshots: 0,
+		});
+		state.steps = (0..task_count).map(|_| ()).collect();
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let library = &ctx.library_ctx;
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		let file_paths = library
+			.db
+			.file_path()
+			.find_many(file_path_filters(data.location.id, Some(data.cursor)))
+			.order_by(file_path::id::order(Direction::Asc))
+			.take(VERSIONER_BATCH_SIZE as i64)
+			.select(file_path_for_versioner::select())
+			.exec()
+			.await?;
+
+		if let Some(last) = file_paths.last() {
+			data.cursor = last.id;
+		}
+
+		for file_path in &file_paths {
+			match snapshot_file(library, &data.location, &file_path.materialized_path).await {
+				Ok(true) => data.snapshots += 1,
+				Ok(false) => {}
+				// files that were deleted since they were indexed have nothing to snapshot
+				Err(VersionError::IOError(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+				Err(e) => {
+					warn!(
+						"Failed to snapshot {}: {:#?}",
+						file_path.materialized_path, e
+					);
+					ctx.log(
+						JobLogLevel::Warning,
+						format!("Failed to snapshot file: {e}"),
+						Some(file_path.materialized_path.clone()),
+					);
+				}
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let library = &ctx.library_ctx;
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		// retention is applied to every file, as versions expire with time even if their file doesn't change
+		let expired = apply_retention(library, &data.location, None).await?;
+
+		info!(
+			"Finalizing versioner job: {} snapshots taken, {} versions expired",
+			data.snapshots, expired
+		);
+
+		if data.snapshots > 0 || expired > 0 {
+			invalidate_query!(library, "files.listVersions");
+		}
+
+		Ok(Some(serde_json::json!({
+			"snapshots": data.snapshots,
+			"expired": expired,
+		})))
+	}
+}
+
+fn file_path_filters(location_id: i32, cursor: Option<i32>) -> Vec<file_path::WhereParam> {
+	let mut filters = vec![
+		file_path::location_id::equals(location_id),
+		file_path::is_dir::equals(false),
+		file_path::is_symlink::equals(false),
+	];
+	if let Some(cursor) = cursor {
+		filters.push(file_path::id::gt(cursor));
+	}
+
+	filters
+}
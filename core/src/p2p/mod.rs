@@ -0,0 +1,362 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::Utc;
+use prisma_client_rust::QueryError;
+use rspc::{ErrorCode, Type};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::{broadcast, RwLock};
+use uuid::Uuid;
+
+use crate::{
+	api::CoreEvent,
+	invalidate_query,
+	job::{JobManager, JobReport, JobStatus},
+	library::{LibraryContext, LibraryManager},
+	node::NodeConfigManager,
+	object::fs::{encrypt::FileEncryptorJobInit, ObjectTarget},
+	prisma::{file_path, location, node},
+};
+
+#[cfg(feature = "p2p")]
+mod network;
+mod proto;
+
+pub use proto::DelegatedJob;
+
+#[cfg(feature = "p2p")]
+use network::Network;
+use proto::DelegationEvent;
+
+#[derive(Error, Debug)]
+pub enum P2PError {
+	#[error("Peer to peer networking isn't available on this node")]
+	Unavailable,
+	#[error("Peer not found (id: {0})")]
+	PeerNotFound(String),
+	#[error("The node hosting this location isn't connected (id: {0})")]
+	NodeNotConnected(Uuid),
+	#[error("The remote node refused the job: {0}")]
+	Refused(String),
+	#[error("Error communicating with the remote node: {0}")]
+	Network(String),
+	#[error("Error loading the P2P config: {0}")]
+	Config(String),
+	#[error("I/O error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("Failed to encode message: {0}")]
+	Encode(#[from] rmp_serde::encode::Error),
+	#[error("Failed to decode message: {0}")]
+	Decode(#[from] rmp_serde::decode::Error),
+	#[error("Database error: {0}")]
+	Database(#[from] QueryError),
+}
+
+impl From<P2PError> for rspc::Error {
+	fn from(err: P2PError) -> Self {
+		match err {
+			P2PError::PeerNotFound(_) => {
+				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
+			}
+			P2PError::Unavailable | P2PError::NodeNotConnected(_) | P2PError::Refused(_) => {
+				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
+			}
+			_ => rspc::Error::with_cause(ErrorCode::InternalServerError, err.to_string(), err),
+		}
+	}
+}
+
+/// A peer which has been discovered on the network, or which is connected to this node.
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct PeerInfo {
+	pub peer_id: String,
+	pub name: String,
+	/// paired peers are the only ones that jobs are able to be delegated to (or from)
+	pub paired: bool,
+	/// node_id is only known once the peer is connected, and has identified itself
+	pub node_id: Option<Uuid>,
+}
+
+/// A job which was sent to the node that hosts its locations. Its report is kept up to date by that node, until the job has finished.
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct DelegatedJobReport {
+	pub node_id: Uuid,
+	pub report: JobReport,
+}
+
+/// Without the `p2p` feature there's no network, so this can't be constructed.
+#[cfg(not(feature = "p2p"))]
+enum Network {}
+
+#[cfg(not(feature = "p2p"))]
+impl Network {
+	fn discovered_peers(&self) -> Vec<PeerInfo> {
+		match *self {}
+	}
+
+	fn connected_peers(&self) -> Vec<PeerInfo> {
+		match *self {}
+	}
+
+	async fn pair(&self, _peer_id: &str) -> Result<String, P2PError> {
+		match *self {}
+	}
+
+	fn accept_pairing_request(
+		&self,
+		_peer_id: &str,
+		_preshared_key: String,
+	) -> Result<(), P2PError> {
+		match *self {}
+	}
+
+	async fn delegate(
+		&self,
+		_node_id: Uuid,
+		_library_id: Uuid,
+		_job: DelegatedJob,
+	) -> Result<tokio::sync::mpsc::UnboundedReceiver<DelegationEvent>, P2PError> {
+		match *self {}
+	}
+}
+
+/// P2PManager connects this node to the other nodes of its libraries, so jobs are able to be delegated to the node that hosts the locations they work on.
+pub struct P2PManager {
+	network: Option<Network>,
+	/// delegated_jobs holds the jobs that this node has delegated, alongside the library they belong to.
+	delegated_jobs: RwLock<HashMap<Uuid, (Uuid, DelegatedJobReport)>>,
+}
+
+impl P2PManager {
+	/// new starts the P2P network. If it fails to start, the node still runs, but it can't delegate jobs.
+	#[cfg_attr(not(feature = "p2p"), allow(unused_variables))]
+	pub async fn new(
+		config: &NodeConfigManager,
+		library_manager: Arc<LibraryManager>,
+		jobs: Arc<JobManager>,
+		event_bus_tx: broadcast::Sender<CoreEvent>,
+	) -> Arc<Self> {
+		#[cfg(feature = "p2p")]
+		let network = Network::start(config, library_manager, jobs, event_bus_tx)
+			.await
+			.map_err(|e| tracing::error!("Failed to start the P2P network: {e:#?}"))
+			.ok();
+		#[cfg(not(feature = "p2p"))]
+		let network = None;
+
+		Arc::new(Self {
+			network,
+			delegated_jobs: RwLock::new(HashMap::new()),
+		})
+	}
+
+	fn network(&self) -> Result<&Network, P2PError> {
+		self.network.as_ref().ok_or(P2PError::Unavailable)
+	}
+
+	pub fn discovered_peers(&self) -> Vec<PeerInfo> {
+		self.network
+			.as_ref()
+			.map(Network::discovered_peers)
+			.unwrap_or_default()
+	}
+
+	pub fn connected_peers(&self) -> Vec<PeerInfo> {
+		self.network
+			.as_ref()
+			.map(Network::connected_peers)
+			.unwrap_or_default()
+	}
+
+	/// pair starts pairing with a peer, and returns the preshared key that has to be entered on that peer's device.
+	pub async fn pair(&self, peer_id: &str) -> Result<String, P2PError> {
+		self.network()?.pair(peer_id).await
+	}
+
+	pub fn accept_pairing_request(
+		&self,
+		peer_id: &str,
+		preshared_key: String,
+	) -> Result<(), P2PError> {
+		self.network()?
+			.accept_pairing_request(peer_id, preshared_key)
+	}
+
+	/// delegate_job sends a job to the node that hosts its locations. Once that node accepts it, its report is kept up to date (see `jobs.getDelegated`) until it's finished.
+	pub(crate) async fn delegate_job(
+		self: &Arc<Self>,
+		library: &LibraryContext,
+		node_id: Uuid,
+		job: DelegatedJob,
+	) -> Result<(), P2PError> {
+		let mut events = self.network()?.delegate(node_id, library.id, job).await?;
+
+		let report = match events.recv().await {
+			Some(DelegationEvent::Accepted(report)) => report,
+			Some(DelegationEvent::Refused { reason }) => return Err(P2PError::Refused(reason)),
+			_ => {
+				return Err(P2PError::Network(
+					"the remote node didn't respond to the job".into(),
+				))
+			}
+		};
+
+		let job_id = report.id;
+		self.update_delegated_job(library, node_id, report).await;
+
+		let this = Arc::clone(self);
+		let library = library.clone();
+		tokio::spawn(async move {
+			while let Some(event) = events.recv().await {
+				match event {
+					DelegationEvent::Progress(report) => {
+						this.update_delegated_job(&library, node_id, report).await;
+					}
+					DelegationEvent::Finished(report) => {
+						this.update_delegated_job(&library, node_id, report).await;
+						invalidate_query!(library, "locations.getExplorerData");
+						return;
+					}
+					_ => {}
+				}
+			}
+
+			// the stream ended before the job finished, so its progress is no longer known (it keeps running on the hosting node)
+			let mut report = match this.delegated_jobs.read().await.get(&job_id) {
+				Some((_, delegated)) => delegated.report.clone(),
+				None => return,
+			};
+			report.status = JobStatus::Failed;
+			report.message = "Lost connection to the node running this job".into();
+			report.date_modified = Utc::now();
+			this.update_delegated_job(&library, node_id, report).await;
+		});
+
+		Ok(())
+	}
+
+	async fn update_delegated_job(
+		&self,
+		library: &LibraryContext,
+		node_id: Uuid,
+		report: JobReport,
+	) {
+		self.delegated_jobs.write().await.insert(
+			report.id,
+			(library.id, DelegatedJobReport { node_id, report }),
+		);
+
+		invalidate_query!(library, "jobs.getDelegated");
+	}
+
+	/// get_delegated_jobs returns the jobs that this node has delegated within a library, newest first.
+	pub async fn get_delegated_jobs(&self, library_id: Uuid) -> Vec<DelegatedJobReport> {
+		let mut jobs = self
+			.delegated_jobs
+			.read()
+			.await
+			.values()
+			.filter(|(id, _)| *id == library_id)
+			.map(|(_, delegated)| delegated.clone())
+			.collect::<Vec<_>>();
+
+		jobs.sort_by(|a, b| b.report.date_created.cmp(&a.report.date_created));
+		jobs
+	}
+
+	/// clear_delegated_jobs forgets the delegated jobs within a library which have finished.
+	pub async fn clear_delegated_jobs(&self, library_id: Uuid) {
+		self.delegated_jobs
+			.write()
+			.await
+			.retain(|_, (id, delegated)| {
+				*id != library_id
+					|| !matches!(
+						delegated.report.status,
+						JobStatus::Completed | JobStatus::Canceled | JobStatus::Failed
+					)
+			});
+	}
+}
+
+/// hosting_node returns the ID of the node that hosts a location, if that isn't this node.
+///
+/// Remote locations (e.g. S3 buckets) are reachable from every node, so they're never hosted elsewhere.
+pub(crate) async fn hosting_node(
+	library: &LibraryContext,
+	location: &location::Data,
+) -> Result<Option<Uuid>, QueryError> {
+	if location.node_id == library.node_local_id || location.remote_config.is_some() {
+		return Ok(None);
+	}
+
+	Ok(library
+		.db
+		.node()
+		.find_unique(node::id::equals(location.node_id))
+		.exec()
+		.await?
+		.and_then(|node| Uuid::from_slice(&node.pub_id).ok()))
+}
+
+impl DelegatedJob {
+	/// encrypt prepares an encryption job to be delegated.
+	///
+	/// Objects are resolved to their file paths within the location first, as objects' IDs are local to each node's database (while file paths are created by the hosting node's indexer).
+	pub(crate) async fn encrypt(
+		library: &LibraryContext,
+		location: &location::Data,
+		mut init: FileEncryptorJobInit,
+	) -> Result<Self, QueryError> {
+		let mut targets = Vec::with_capacity(init.targets.len());
+		for target in init.targets {
+			match target {
+				ObjectTarget::Object(object_id) => targets.extend(
+					library
+						.db
+						.file_path()
+						.find_many(vec![
+							file_path::location_id::equals(location.id),
+							file_path::object_id::equals(Some(object_id)),
+						])
+						.exec()
+						.await?
+						.into_iter()
+						.map(|file_path| ObjectTarget::FilePath(file_path.id)),
+				),
+				target => targets.push(target),
+			}
+		}
+		init.targets = targets;
+
+		let upload_location_pub_id = match &init.upload {
+			Some(upload) => library
+				.db
+				.location()
+				.find_unique(location::id::equals(upload.location_id))
+				.exec()
+				.await?
+				.map(|location| location.pub_id),
+			None => None,
+		};
+
+		// encryption rules are local to this node, so their activity isn't recorded for delegated jobs
+		init.rule_id = None;
+
+		Ok(Self::Encrypt {
+			location_pub_id: location.pub_id.clone(),
+			upload_location_pub_id,
+			init,
+		})
+	}
+
+	/// verify prepares a verification job to be delegated.
+	pub(crate) fn verify(locations: &[location::Data]) -> Self {
+		Self::Verify {
+			location_pub_ids: locations
+				.iter()
+				.map(|location| location.pub_id.clone())
+				.collect(),
+		}
+	}
+}
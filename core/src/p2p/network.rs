@@ -0,0 +1,615 @@
+use std::{
+	collections::{HashMap, HashSet},
+	fs::File,
+	future::Future,
+	io::{BufReader, Write},
+	path::{Path, PathBuf},
+	pin::Pin,
+	sync::{Arc, Mutex, RwLock},
+	time::Duration,
+};
+
+use once_cell::sync::OnceCell;
+use sd_p2p::{
+	quinn::{RecvStream, SendStream},
+	Identity, NetworkManager, NetworkManagerConfig, OperationSystem, P2PManager,
+	PairingParticipantType, Peer, PeerId, PeerMetadata,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	sync::{broadcast, mpsc, oneshot},
+	time::sleep,
+};
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+use crate::{
+	api::CoreEvent,
+	job::{JobManager, JobReport, JobStatus},
+	library::LibraryManager,
+	node::NodeConfigManager,
+	prisma::{job, node},
+};
+
+use super::{
+	proto::{DelegatedJob, DelegationEvent, Request},
+	P2PError, PeerInfo,
+};
+
+/// P2P_CONFIG_NAME is the name of the file which stores the node's P2P identity and the peers it's been paired with.
+const P2P_CONFIG_NAME: &str = "p2p.sdconfig";
+
+/// Messages are length-prefixed, so a stream is able to carry more than one of them. This is the largest message that's accepted.
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// How often a delegated job's report is sent back to the node that delegated it.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// P2PConfig is stored separately to the node's config, as it contains the node's private key (and the node's config is sent to clients).
+#[derive(Serialize, Deserialize)]
+struct P2PConfig {
+	cert: Vec<u8>,
+	key: Vec<u8>,
+	/// paired_peers are the only peers that are able to identify themselves as one of our libraries' nodes, and delegate jobs to us.
+	paired_peers: HashSet<PeerId>,
+}
+
+impl P2PConfig {
+	fn read(path: &Path) -> Result<Self, P2PError> {
+		if path.exists() {
+			return Ok(serde_json::from_reader(BufReader::new(File::open(path)?))
+				.map_err(|e| P2PError::Config(e.to_string()))?);
+		}
+
+		let (cert, key) = Identity::new()
+			.map_err(|e| P2PError::Config(e.to_string()))?
+			.to_raw();
+		let config = Self {
+			cert,
+			key,
+			paired_peers: HashSet::new(),
+		};
+		config.save(path)?;
+
+		Ok(config)
+	}
+
+	fn save(&self, path: &Path) -> Result<(), P2PError> {
+		File::create(path)?.write_all(
+			serde_json::to_string(self)
+				.map_err(|e| P2PError::Config(e.to_string()))?
+				.as_bytes(),
+		)?;
+		Ok(())
+	}
+}
+
+/// Holds everything the P2P network needs from the rest of the node, which is shared with the [`SdP2PManager`] callbacks.
+struct NetworkState {
+	node_id: Uuid,
+	node_name: String,
+	config_path: PathBuf,
+	config: RwLock<P2PConfig>,
+	/// nodes maps the IDs of the nodes we're connected to onto their peers, once they've identified themselves.
+	nodes: RwLock<HashMap<Uuid, PeerId>>,
+	/// pairing_requests hold the pairing requests that are waiting for the user to enter the preshared key.
+	pairing_requests: Mutex<HashMap<PeerId, oneshot::Sender<Result<String, ()>>>>,
+	library_manager: Arc<LibraryManager>,
+	jobs: Arc<JobManager>,
+	event_bus_tx: broadcast::Sender<CoreEvent>,
+	nm: OnceCell<Arc<NetworkManager<SdP2PManager>>>,
+}
+
+impl NetworkState {
+	fn is_paired(&self, peer_id: &PeerId) -> bool {
+		self.config
+			.read()
+			.expect("critical error: the P2P config lock was poisoned")
+			.paired_peers
+			.contains(peer_id)
+	}
+
+	fn node_for_peer(&self, peer_id: &PeerId) -> Option<Uuid> {
+		self.nodes
+			.read()
+			.expect("critical error: the P2P nodes lock was poisoned")
+			.iter()
+			.find(|(_, peer)| *peer == peer_id)
+			.map(|(node_id, _)| *node_id)
+	}
+
+	fn set_paired(&self, peer_id: &PeerId, paired: bool) {
+		let mut config = self
+			.config
+			.write()
+			.expect("critical error: the P2P config lock was poisoned");
+
+		if paired {
+			config.paired_peers.insert(peer_id.clone());
+		} else {
+			config.paired_peers.remove(peer_id);
+		}
+
+		if let Err(e) = config.save(&self.config_path) {
+			error!("Failed to save the P2P config: {e:#?}");
+		}
+	}
+}
+
+#[derive(Clone)]
+pub(super) struct SdP2PManager(Arc<NetworkState>);
+
+impl P2PManager for SdP2PManager {
+	const APPLICATION_NAME: &'static str = "spacedrive";
+
+	fn get_metadata(&self) -> PeerMetadata {
+		PeerMetadata {
+			name: self.0.node_name.clone(),
+			operating_system: Some(OperationSystem::get_os()),
+			version: Some(env!("CARGO_PKG_VERSION").into()),
+		}
+	}
+
+	fn peer_connected(&self, _nm: &NetworkManager<Self>, peer_id: PeerId) {
+		if !self.0.is_paired(&peer_id) {
+			return;
+		}
+
+		let state = Arc::clone(&self.0);
+		tokio::spawn(async move {
+			if let Err(e) = identify(&state, peer_id.clone()).await {
+				warn!("Failed to identify ourselves to peer '{peer_id}': {e:#?}");
+			}
+		});
+	}
+
+	fn peer_disconnected(&self, _nm: &NetworkManager<Self>, peer_id: PeerId) {
+		self.0
+			.nodes
+			.write()
+			.expect("critical error: the P2P nodes lock was poisoned")
+			.retain(|_, peer| *peer != peer_id);
+	}
+
+	fn peer_pairing_request(
+		&self,
+		_nm: &NetworkManager<Self>,
+		peer_id: &PeerId,
+		metadata: &PeerMetadata,
+		_extra_data: &HashMap<String, String>,
+		password_resp: oneshot::Sender<Result<String, ()>>,
+	) {
+		self.0
+			.pairing_requests
+			.lock()
+			.expect("critical error: the P2P pairing requests lock was poisoned")
+			.insert(peer_id.clone(), password_resp);
+
+		if let Err(e) = self.0.event_bus_tx.send(CoreEvent::PeerPairingRequest {
+			peer_id: peer_id.to_string(),
+			name: metadata.name.clone(),
+		}) {
+			warn!("Error sending event to event bus: {e:?}");
+		}
+	}
+
+	fn peer_paired<'a>(
+		&'a self,
+		nm: &'a NetworkManager<Self>,
+		_direction: PairingParticipantType,
+		peer_id: &'a PeerId,
+		_peer_metadata: &'a PeerMetadata,
+		_extra_data: &'a HashMap<String, String>,
+	) -> Pin<Box<dyn Future<Output = Result<(), ()>> + Send + 'a>> {
+		Box::pin(async move {
+			self.0.set_paired(peer_id, true);
+			nm.add_known_peer(peer_id.clone());
+			Ok(())
+		})
+	}
+
+	fn peer_paired_rollback<'a>(
+		&'a self,
+		_nm: &'a NetworkManager<Self>,
+		_direction: PairingParticipantType,
+		peer_id: &'a PeerId,
+		_peer_metadata: &'a PeerMetadata,
+		_extra_data: &'a HashMap<String, String>,
+	) -> Pin<Box<dyn Future<Output = ()> + Send + Sync + 'a>> {
+		Box::pin(async move {
+			self.0.set_paired(peer_id, false);
+		})
+	}
+
+	fn accept_stream(&self, peer: &Peer<Self>, (tx, rx): (SendStream, RecvStream)) {
+		// any peer is able to connect, but only the ones we've been paired with are listened to
+		if !self.0.is_paired(&peer.id) {
+			debug!("Ignoring stream from unpaired peer '{}'", peer.id);
+			return;
+		}
+
+		let state = Arc::clone(&self.0);
+		let peer_id = peer.id.clone();
+		tokio::spawn(async move {
+			if let Err(e) = handle_stream(&state, peer_id.clone(), tx, rx).await {
+				warn!("Failed to handle stream from peer '{peer_id}': {e:#?}");
+			}
+		});
+	}
+}
+
+/// Network is this node's connection to the P2P network.
+pub(super) struct Network(Arc<NetworkState>);
+
+impl Network {
+	pub(super) async fn start(
+		config: &NodeConfigManager,
+		library_manager: Arc<LibraryManager>,
+		jobs: Arc<JobManager>,
+		event_bus_tx: broadcast::Sender<CoreEvent>,
+	) -> Result<Self, P2PError> {
+		let node_config = config.get().await;
+		let config_path = config.data_directory().join(P2P_CONFIG_NAME);
+		let p2p_config = P2PConfig::read(&config_path)?;
+
+		let identity = Identity::from_raw(p2p_config.cert.clone(), p2p_config.key.clone())
+			.map_err(|e| P2PError::Config(e.to_string()))?;
+		let known_peers = p2p_config.paired_peers.clone();
+
+		let state = Arc::new(NetworkState {
+			node_id: node_config.id,
+			node_name: node_config.name,
+			config_path,
+			config: RwLock::new(p2p_config),
+			nodes: RwLock::new(HashMap::new()),
+			pairing_requests: Mutex::new(HashMap::new()),
+			library_manager,
+			jobs,
+			event_bus_tx,
+			nm: OnceCell::new(),
+		});
+
+		let nm = NetworkManager::new(
+			identity,
+			SdP2PManager(Arc::clone(&state)),
+			NetworkManagerConfig {
+				known_peers,
+				listen_port: node_config
+					.p2p_port
+					.and_then(|port| u16::try_from(port).ok()),
+				spacetunnel_url: None,
+			},
+		)
+		.await
+		.map_err(|e| P2PError::Network(e.to_string()))?;
+
+		debug!(
+			"Peer '{}' listening on: {:?}",
+			nm.peer_id(),
+			nm.listen_addr()
+		);
+
+		state.nm.set(nm).ok();
+
+		Ok(Self(state))
+	}
+
+	pub(super) fn discovered_peers(&self) -> Vec<PeerInfo> {
+		self.nm()
+			.discovered_peers()
+			.into_values()
+			.map(|peer| PeerInfo {
+				node_id: self.0.node_for_peer(&peer.id),
+				paired: self.0.is_paired(&peer.id),
+				peer_id: peer.id.to_string(),
+				name: peer.metadata.name,
+			})
+			.collect()
+	}
+
+	pub(super) fn connected_peers(&self) -> Vec<PeerInfo> {
+		self.nm()
+			.connected_peers()
+			.into_values()
+			.map(|peer| PeerInfo {
+				node_id: self.0.node_for_peer(&peer.id),
+				paired: self.0.is_paired(&peer.id),
+				peer_id: peer.id.to_string(),
+				name: peer.metadata.name,
+			})
+			.collect()
+	}
+
+	/// This starts pairing with a peer, and returns the preshared key that has to be entered on the peer's device.
+	pub(super) async fn pair(&self, peer_id: &str) -> Result<String, P2PError> {
+		self.nm()
+			.initiate_pairing_with_peer(parse_peer_id(peer_id)?, HashMap::new())
+			.await
+			.map_err(|e| P2PError::Network(e.to_string()))
+	}
+
+	pub(super) fn accept_pairing_request(
+		&self,
+		peer_id: &str,
+		preshared_key: String,
+	) -> Result<(), P2PError> {
+		self.0
+			.pairing_requests
+			.lock()
+			.expect("critical error: the P2P pairing requests lock was poisoned")
+			.remove(&parse_peer_id(peer_id)?)
+			.ok_or_else(|| P2PError::PeerNotFound(peer_id.to_string()))?
+			.send(Ok(preshared_key))
+			.map_err(|_| P2PError::Network("the pairing request has expired".into()))
+	}
+
+	/// This sends a job to the node which hosts its locations, and returns the events that node sends back while running it.
+	pub(super) async fn delegate(
+		&self,
+		node_id: Uuid,
+		library_id: Uuid,
+		job: DelegatedJob,
+	) -> Result<mpsc::UnboundedReceiver<DelegationEvent>, P2PError> {
+		let peer_id = self
+			.0
+			.nodes
+			.read()
+			.expect("critical error: the P2P nodes lock was poisoned")
+			.get(&node_id)
+			.cloned()
+			.ok_or(P2PError::NodeNotConnected(node_id))?;
+
+		let (mut tx, mut rx) = self
+			.nm()
+			.stream(&peer_id)
+			.await
+			.map_err(|e| P2PError::Network(e.to_string()))?;
+
+		write_message(
+			&mut tx,
+			&Request::DelegateJob {
+				library_id,
+				node_id: self.0.node_id,
+				job,
+			},
+		)
+		.await?;
+
+		let (events_tx, events_rx) = mpsc::unbounded_channel();
+		tokio::spawn(async move {
+			// the send stream is kept open until we're done, as dropping it would close the stream
+			let _tx = tx;
+			loop {
+				match read_message::<DelegationEvent>(&mut rx).await {
+					Ok(event) => {
+						let finished = matches!(
+							event,
+							DelegationEvent::Finished(_) | DelegationEvent::Refused { .. }
+						);
+						if events_tx.send(event).is_err() || finished {
+							break;
+						}
+					}
+					Err(e) => {
+						warn!("Lost the stream of a delegated job from peer '{peer_id}': {e:#?}");
+						break;
+					}
+				}
+			}
+		});
+
+		Ok(events_rx)
+	}
+
+	fn nm(&self) -> &Arc<NetworkManager<SdP2PManager>> {
+		self.0
+			.nm
+			.get()
+			.expect("unreachable error: the network manager is set when the network is started")
+	}
+}
+
+fn parse_peer_id(peer_id: &str) -> Result<PeerId, P2PError> {
+	PeerId::from_string(peer_id.to_string())
+		.map_err(|_| P2PError::PeerNotFound(peer_id.to_string()))
+}
+
+/// This tells a newly connected peer which node we are, and records which node it is.
+async fn identify(state: &NetworkState, peer_id: PeerId) -> Result<(), P2PError> {
+	let nm = state
+		.nm
+		.get()
+		.ok_or_else(|| P2PError::Network("the network hasn't started yet".into()))?;
+
+	let (mut tx, mut rx) = nm
+		.stream(&peer_id)
+		.await
+		.map_err(|e| P2PError::Network(e.to_string()))?;
+
+	write_message(
+		&mut tx,
+		&Request::Identify {
+			node_id: state.node_id,
+		},
+	)
+	.await?;
+
+	let node_id = read_message::<Uuid>(&mut rx).await?;
+	record_node(state, node_id, peer_id);
+
+	Ok(())
+}
+
+fn record_node(state: &NetworkState, node_id: Uuid, peer_id: PeerId) {
+	debug!("Peer '{peer_id}' is node '{node_id}'");
+	state
+		.nodes
+		.write()
+		.expect("critical error: the P2P nodes lock was poisoned")
+		.insert(node_id, peer_id);
+}
+
+async fn handle_stream(
+	state: &NetworkState,
+	peer_id: PeerId,
+	mut tx: SendStream,
+	mut rx: RecvStream,
+) -> Result<(), P2PError> {
+	match read_message::<Request>(&mut rx).await? {
+		Request::Identify { node_id } => {
+			record_node(state, node_id, peer_id);
+			write_message(&mut tx, &state.node_id).await
+		}
+		Request::DelegateJob {
+			library_id,
+			node_id,
+			job,
+		} => {
+			let report = match accept_delegated_job(state, &peer_id, library_id, node_id, job).await
+			{
+				Ok(report) => report,
+				Err(e) => {
+					return write_message(
+						&mut tx,
+						&DelegationEvent::Refused {
+							reason: e.to_string(),
+						},
+					)
+					.await;
+				}
+			};
+
+			write_message(&mut tx, &DelegationEvent::Accepted(report.clone())).await?;
+			report_delegated_job(state, library_id, report, &mut tx).await
+		}
+	}
+}
+
+/// This spawns a job which another node has asked us to run, as long as that node is part of the job's library.
+async fn accept_delegated_job(
+	state: &NetworkState,
+	peer_id: &PeerId,
+	library_id: Uuid,
+	node_id: Uuid,
+	job: DelegatedJob,
+) -> Result<JobReport, P2PError> {
+	// the peer has to have identified itself as the node it's delegating on behalf of
+	if state.node_for_peer(peer_id) != Some(node_id) {
+		return Err(P2PError::Refused(format!(
+			"peer '{peer_id}' hasn't identified itself as node '{node_id}'"
+		)));
+	}
+
+	let library = state
+		.library_manager
+		.get_ctx(library_id)
+		.await
+		.ok_or_else(|| P2PError::Refused(format!("library '{library_id}' isn't on this node")))?;
+
+	if library
+		.db
+		.node()
+		.count(vec![node::pub_id::equals(node_id.as_bytes().to_vec())])
+		.exec()
+		.await?
+		== 0
+	{
+		return Err(P2PError::Refused(format!(
+			"node '{node_id}' isn't part of library '{library_id}'"
+		)));
+	}
+
+	let mut job = job.into_job(&library).await?;
+	let report = job
+		.report()
+		.clone()
+		.expect("critical error: missing job report for a new job");
+
+	library.spawn_job(job).await;
+
+	Ok(report)
+}
+
+/// This sends the report of a delegated job back to the node that delegated it, until the job has finished.
+///
+/// The job keeps running if that node disconnects, as it'd be resumed like any other job.
+async fn report_delegated_job(
+	state: &NetworkState,
+	library_id: Uuid,
+	mut last_report: JobReport,
+	tx: &mut SendStream,
+) -> Result<(), P2PError> {
+	loop {
+		sleep(PROGRESS_INTERVAL).await;
+
+		if let Some(report) = state
+			.jobs
+			.get_running()
+			.await
+			.into_iter()
+			.find(|report| report.id == last_report.id)
+		{
+			if report.status != last_report.status
+				|| report.completed_task_count != last_report.completed_task_count
+				|| report.task_count != last_report.task_count
+				|| report.message != last_report.message
+			{
+				write_message(tx, &DelegationEvent::Progress(report.clone())).await?;
+				last_report = report;
+			}
+			continue;
+		}
+
+		// jobs that aren't running are either still queued (so they aren't in the database yet), paused, or finished
+		let library = match state.library_manager.get_ctx(library_id).await {
+			Some(library) => library,
+			None => return Ok(()),
+		};
+
+		if let Some(report) = library
+			.db
+			.job()
+			.find_unique(job::id::equals(last_report.id.as_bytes().to_vec()))
+			.exec()
+			.await?
+			.map(JobReport::from)
+		{
+			if matches!(
+				report.status,
+				JobStatus::Completed | JobStatus::Canceled | JobStatus::Failed
+			) {
+				return write_message(tx, &DelegationEvent::Finished(report)).await;
+			}
+
+			if report.status != last_report.status {
+				write_message(tx, &DelegationEvent::Progress(report.clone())).await?;
+				last_report = report;
+			}
+		}
+	}
+}
+
+async fn write_message<T: Serialize>(tx: &mut SendStream, message: &T) -> Result<(), P2PError> {
+	let data = rmp_serde::to_vec_named(message)?;
+	// quinn's own `write_all` and `read_exact` have their own error types, so tokio's are used instead
+	AsyncWriteExt::write_all(tx, &(data.len() as u32).to_le_bytes()).await?;
+	AsyncWriteExt::write_all(tx, &data).await?;
+	Ok(())
+}
+
+async fn read_message<T: DeserializeOwned>(rx: &mut RecvStream) -> Result<T, P2PError> {
+	let mut len = [0; 4];
+	AsyncReadExt::read_exact(rx, &mut len).await?;
+
+	let len = u32::from_le_bytes(len) as usize;
+	if len > MAX_MESSAGE_SIZE {
+		return Err(P2PError::Network(format!(
+			"message is too large ({len} bytes)"
+		)));
+	}
+
+	let mut data = vec![0; len];
+	AsyncReadExt::read_exact(rx, &mut data).await?;
+	Ok(rmp_serde::from_slice(&data)?)
+}
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+	job::{DynJob, Job, JobReport},
+	library::LibraryContext,
+	object::fs::{
+		encrypt::{FileEncryptorJob, FileEncryptorJobInit},
+		verify::{FileVerifierJob, FileVerifierJobInit},
+	},
+	prisma::location,
+};
+
+use super::P2PError;
+
+/// Is sent as the first message of every stream between two nodes, to say what the stream is for.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) enum Request {
+	/// This tells the remote node which node we are, so it knows which of the library's locations we host. It's answered with the remote node's own ID.
+	Identify { node_id: Uuid },
+	/// This asks the remote node to run a job on locations that it hosts. It's answered with a stream of [`DelegationEvent`]s.
+	DelegateJob {
+		library_id: Uuid,
+		node_id: Uuid,
+		job: DelegatedJob,
+	},
+}
+
+/// Is sent back by the node running a delegated job, until the job has finished.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) enum DelegationEvent {
+	Accepted(JobReport),
+	Refused { reason: String },
+	Progress(JobReport),
+	Finished(JobReport),
+}
+
+/// A job which is run by the node that hosts the locations it works on, as that's the only node which is able to reach their files.
+///
+/// Locations' IDs are local to each node's database, so they're sent by their public ID and swapped for the hosting node's own IDs before the job is run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DelegatedJob {
+	Encrypt {
+		location_pub_id: Vec<u8>,
+		upload_location_pub_id: Option<Vec<u8>>,
+		init: FileEncryptorJobInit,
+	},
+	Verify {
+		location_pub_ids: Vec<Vec<u8>>,
+	},
+}
+
+impl DelegatedJob {
+	/// This builds the job on the node that hosts its locations.
+	pub(super) async fn into_job(self, ctx: &LibraryContext) -> Result<Box<dyn DynJob>, P2PError> {
+		Ok(match self {
+			Self::Encrypt {
+				location_pub_id,
+				upload_location_pub_id,
+				mut init,
+			} => {
+				init.location_id = local_location_id(ctx, location_pub_id).await?;
+				if let (Some(upload), Some(pub_id)) = (&mut init.upload, upload_location_pub_id) {
+					upload.location_id = local_location_id(ctx, pub_id).await?;
+				}
+
+				Job::new(init, FileEncryptorJob {})
+			}
+			Self::Verify { location_pub_ids } => {
+				let mut location_ids = Vec::with_capacity(location_pub_ids.len());
+				for pub_id in location_pub_ids {
+					location_ids.push(local_location_id(ctx, pub_id).await?);
+				}
+
+				Job::new(FileVerifierJobInit { location_ids }, FileVerifierJob {})
+			}
+		})
+	}
+}
+
+async fn local_location_id(ctx: &LibraryContext, pub_id: Vec<u8>) -> Result<i32, P2PError> {
+	ctx.db
+		.location()
+		.find_unique(location::pub_id::equals(pub_id.clone()))
+		.exec()
+		.await?
+		.map(|location| location.id)
+		.ok_or_else(|| {
+			P2PError::Refused(format!(
+				"location <pub_id='{}'> isn't in this node's library",
+				Uuid::from_slice(&pub_id).unwrap_or_default()
+			))
+		})
+}
@@ -1,9 +1,10 @@
-use crate::prisma::{self, PrismaClient};
+use crate::prisma::{self, key, key_totp, PrismaClient};
 use prisma_client_rust::QueryError;
 use prisma_client_rust::{migrations::*, NewClientError};
-use sd_crypto::keys::keymanager::StoredKey;
+use sd_crypto::keys::{keymanager::StoredKey, totp::StoredTotp};
 use std::sync::Arc;
 use thiserror::Error;
+use uuid::Uuid;
 
 /// MigrationError represents an error that occurring while opening a initialising and running migrations on the database.
 #[derive(Error, Debug)]
@@ -62,6 +63,107 @@ pub async fn write_storedkey_to_db(
 				key.key_nonce.to_vec(),
 				key.key.to_vec(),
 				key.salt.to_vec(),
+				vec![
+					key::name::set(key.name.clone()),
+					key::source::set(key.source.serialize()),
+				],
+			)
+			.exec()
+			.await?;
+	}
+
+	Ok(())
+}
+
+/// This updates an existing `StoredKey` within prisma, e.g. after it has been re-wrapped
+/// If the key is marked as memory-only, it is skipped
+pub async fn update_storedkey_in_db(
+	db: Arc<PrismaClient>,
+	key: &StoredKey,
+) -> Result<(), QueryError> {
+	if !key.memory_only {
+		db.key()
+			.update(
+				key::uuid::equals(key.uuid.to_string()),
+				storedkey_params(key),
+			)
+			.exec()
+			.await?;
+	}
+
+	Ok(())
+}
+
+/// This writes a `StoredKey` to prisma, or updates it if a key with the same UUID already exists
+/// If the key is marked as memory-only, it is skipped
+pub async fn upsert_storedkey_in_db(
+	db: Arc<PrismaClient>,
+	key: &StoredKey,
+) -> Result<(), QueryError> {
+	let exists = db
+		.key()
+		.find_unique(key::uuid::equals(key.uuid.to_string()))
+		.exec()
+		.await?
+		.is_some();
+
+	if exists {
+		update_storedkey_in_db(db, key).await
+	} else {
+		write_storedkey_to_db(db, key).await
+	}
+}
+
+/// This returns the params that update an existing `StoredKey` within prisma, so they can be batched alongside other writes
+pub fn storedkey_params(key: &StoredKey) -> Vec<key::SetParam> {
+	vec![
+		key::algorithm::set(key.algorithm.serialize().to_vec()),
+		key::hashing_algorithm::set(key.hashing_algorithm.serialize().to_vec()),
+		key::content_salt::set(key.content_salt.to_vec()),
+		key::master_key::set(key.master_key.to_vec()),
+		key::master_key_nonce::set(key.master_key_nonce.to_vec()),
+		key::key_nonce::set(key.key_nonce.to_vec()),
+		key::key::set(key.key.to_vec()),
+		key::salt::set(key.salt.to_vec()),
+		key::name::set(key.name.clone()),
+		key::source::set(key.source.serialize()),
+	]
+}
+
+/// This returns the params that replace a key's `StoredTotp` within prisma, e.g. after it has been re-wrapped
+pub fn totp_params(totp: &StoredTotp) -> Vec<key_totp::SetParam> {
+	vec![
+		key_totp::algorithm::set(totp.algorithm.serialize().to_vec()),
+		key_totp::salt::set(totp.salt.to_vec()),
+		key_totp::nonce::set(totp.nonce.clone()),
+		key_totp::secret::set(totp.secret.clone()),
+		key_totp::recovery_codes::set(totp.serialize_recovery_codes()),
+	]
+}
+
+/// This writes a key's `StoredTotp` to prisma, replacing any that already exists
+/// If `None` is provided, TOTP is removed from the key instead
+pub async fn write_totp_to_db(
+	db: Arc<PrismaClient>,
+	uuid: Uuid,
+	totp: Option<&StoredTotp>,
+) -> Result<(), QueryError> {
+	db.key_totp()
+		.delete_many(vec![key_totp::key::is(vec![key::uuid::equals(
+			uuid.to_string(),
+		)])])
+		.exec()
+		.await?;
+
+	if let Some(totp) = totp {
+		db.key_totp()
+			.create(
+				totp.algorithm.serialize().to_vec(),
+				totp.salt.to_vec(),
+				totp.nonce.clone(),
+				totp.secret.clone(),
+				totp.serialize_recovery_codes(),
+				key::uuid::equals(uuid.to_string()),
 				vec![],
 			)
 			.exec()
@@ -2,7 +2,7 @@ use crate::{library::LibraryContext, prisma::volume::*};
 
 use rspc::Type;
 use serde::{Deserialize, Serialize};
-use std::process::Command;
+use std::{path::PathBuf, process::Command};
 use sysinfo::{DiskExt, System, SystemExt};
 use thiserror::Error;
 
@@ -132,6 +132,18 @@ pub fn get_volumes() -> Result<Vec<Volume>, VolumeError> {
 		.collect::<Result<Vec<_>, _>>()
 }
 
+/// This lists the mount point of every volume, alongside how much space (in bytes) is available on it.
+pub fn available_space() -> Vec<(PathBuf, u64)> {
+	let mut system = System::new();
+	system.refresh_disks_list();
+
+	system
+		.disks()
+		.iter()
+		.map(|disk| (disk.mount_point().to_path_buf(), disk.available_space()))
+		.collect()
+}
+
 // #[test]
 // fn test_get_volumes() {
 //   let volumes = get_volumes()?;
@@ -8,12 +8,15 @@ use crate::{
 	Error, Protected, Result,
 };
 use aead::{
-	stream::{DecryptorLE31, EncryptorLE31},
+	stream::{NewStream, StreamLE31, StreamPrimitive},
 	KeyInit, Payload,
 };
 use aes_gcm::Aes256Gcm;
 use chacha20poly1305::XChaCha20Poly1305;
 
+/// Blocks past this position can't be encrypted, as the STREAM counter would overflow.
+const MAX_POSITION: u32 = <StreamLE31<Aes256Gcm> as StreamPrimitive<Aes256Gcm>>::COUNTER_MAX;
+
 /// These are all possible algorithms that can be used for encryption and decryption
 #[derive(Clone, Copy, Eq, PartialEq, Hash)]
 #[cfg_attr(
@@ -38,65 +41,132 @@ impl Algorithm {
 	}
 }
 
-pub enum StreamEncryption {
-	XChaCha20Poly1305(Box<EncryptorLE31<XChaCha20Poly1305>>),
-	Aes256Gcm(Box<EncryptorLE31<Aes256Gcm>>),
+/// The STREAM construction that's shared by encryption and decryption.
+///
+/// Blocks are encrypted by their position, so a stream can be started part of the way through (e.g. when resuming a paused job).
+enum Stream {
+	XChaCha20Poly1305(Box<StreamLE31<XChaCha20Poly1305>>),
+	Aes256Gcm(Box<StreamLE31<Aes256Gcm>>),
 }
 
-pub enum StreamDecryption {
-	Aes256Gcm(Box<DecryptorLE31<Aes256Gcm>>),
-	XChaCha20Poly1305(Box<DecryptorLE31<XChaCha20Poly1305>>),
+pub struct StreamEncryption {
+	stream: Stream,
+	position: u32,
 }
 
-impl StreamEncryption {
-	/// This should be used to initialize a stream encryption object.
-	///
-	/// The master key, a suitable nonce, and a specific algorithm should be provided.
-	#[allow(clippy::needless_pass_by_value)]
-	pub fn new(key: Protected<[u8; KEY_LEN]>, nonce: &[u8], algorithm: Algorithm) -> Result<Self> {
+pub struct StreamDecryption {
+	stream: Stream,
+	position: u32,
+}
+
+impl Stream {
+	fn new(key: &Protected<[u8; KEY_LEN]>, nonce: &[u8], algorithm: Algorithm) -> Result<Self> {
 		if nonce.len() != algorithm.nonce_len() {
 			return Err(Error::NonceLengthMismatch);
 		}
 
-		let encryption_object = match algorithm {
+		let stream = match algorithm {
 			Algorithm::XChaCha20Poly1305 => {
 				let cipher = XChaCha20Poly1305::new_from_slice(key.expose())
 					.map_err(|_| Error::StreamModeInit)?;
 
-				let stream = EncryptorLE31::from_aead(cipher, nonce.into());
-				Self::XChaCha20Poly1305(Box::new(stream))
+				Self::XChaCha20Poly1305(Box::new(StreamLE31::from_aead(cipher, nonce.into())))
 			}
 			Algorithm::Aes256Gcm => {
 				let cipher =
 					Aes256Gcm::new_from_slice(key.expose()).map_err(|_| Error::StreamModeInit)?;
 
-				let stream = EncryptorLE31::from_aead(cipher, nonce.into());
-				Self::Aes256Gcm(Box::new(stream))
+				Self::Aes256Gcm(Box::new(StreamLE31::from_aead(cipher, nonce.into())))
 			}
 		};
 
-		Ok(encryption_object)
+		Ok(stream)
 	}
 
-	fn encrypt_next<'msg, 'aad>(
-		&mut self,
+	fn encrypt<'msg, 'aad>(
+		&self,
+		position: u32,
+		last_block: bool,
 		payload: impl Into<Payload<'msg, 'aad>>,
 	) -> aead::Result<Vec<u8>> {
+		if position >= MAX_POSITION {
+			return Err(aead::Error);
+		}
+
 		match self {
-			Self::XChaCha20Poly1305(s) => s.encrypt_next(payload),
-			Self::Aes256Gcm(s) => s.encrypt_next(payload),
+			Self::XChaCha20Poly1305(s) => s.encrypt(position, last_block, payload),
+			Self::Aes256Gcm(s) => s.encrypt(position, last_block, payload),
 		}
 	}
 
-	fn encrypt_last<'msg, 'aad>(
-		self,
+	fn decrypt<'msg, 'aad>(
+		&self,
+		position: u32,
+		last_block: bool,
 		payload: impl Into<Payload<'msg, 'aad>>,
 	) -> aead::Result<Vec<u8>> {
+		if position >= MAX_POSITION {
+			return Err(aead::Error);
+		}
+
 		match self {
-			Self::XChaCha20Poly1305(s) => s.encrypt_last(payload),
-			Self::Aes256Gcm(s) => s.encrypt_last(payload),
+			Self::XChaCha20Poly1305(s) => s.decrypt(position, last_block, payload),
+			Self::Aes256Gcm(s) => s.decrypt(position, last_block, payload),
 		}
 	}
+}
+
+impl StreamEncryption {
+	/// This should be used to initialize a stream encryption object.
+	///
+	/// The master key, a suitable nonce, and a specific algorithm should be provided.
+	#[allow(clippy::needless_pass_by_value)]
+	pub fn new(key: Protected<[u8; KEY_LEN]>, nonce: &[u8], algorithm: Algorithm) -> Result<Self> {
+		Ok(Self {
+			stream: Stream::new(&key, nonce, algorithm)?,
+			position: 0,
+		})
+	}
+
+	/// This moves the stream to the start of a given block, so encryption can continue from part of the way through a file.
+	///
+	/// Every block except the last is exactly `BLOCK_SIZE` bytes, so block `n` starts at `n * BLOCK_SIZE` within the plaintext.
+	pub fn seek_to_block(&mut self, block: u32) {
+		self.position = block;
+	}
+
+	/// This reads a single block from the reader, encrypts it, and writes it to the writer.
+	///
+	/// It returns `true` once the final block has been written, after which the stream shouldn't be used again.
+	///
+	/// This can be used for encrypting a stream gradually (e.g. so a job can be paused between blocks).
+	pub fn encrypt_block<R, W>(&mut self, mut reader: R, mut writer: W, aad: &[u8]) -> Result<bool>
+	where
+		R: Read,
+		W: Write,
+	{
+		let mut read_buffer = vec![0u8; BLOCK_SIZE].into_boxed_slice();
+		let read_count = reader.read(&mut read_buffer)?;
+
+		// we use `..read_count` in order to only use the read data, and not zeroes also
+		let last_block = read_count != BLOCK_SIZE;
+		let payload = Payload {
+			aad,
+			msg: &read_buffer[..read_count],
+		};
+
+		let encrypted_data = self
+			.stream
+			.encrypt(self.position, last_block, payload)
+			.map_err(|_| Error::Encrypt)?;
+
+		writer.write_all(&encrypted_data)?;
+		writer.flush()?;
+
+		self.position += 1;
+
+		Ok(last_block)
+	}
 
 	/// This function should be used for encrypting large amounts of data.
 	///
@@ -110,33 +180,7 @@ impl StreamEncryption {
 		R: Read,
 		W: Write,
 	{
-		let mut read_buffer = vec![0u8; BLOCK_SIZE].into_boxed_slice();
-		loop {
-			let read_count = reader.read(&mut read_buffer)?;
-			if read_count == BLOCK_SIZE {
-				let payload = Payload {
-					aad,
-					msg: &read_buffer,
-				};
-
-				let encrypted_data = self.encrypt_next(payload).map_err(|_| Error::Encrypt)?;
-
-				writer.write_all(&encrypted_data)?;
-			} else {
-				// we use `..read_count` in order to only use the read data, and not zeroes also
-				let payload = Payload {
-					aad,
-					msg: &read_buffer[..read_count],
-				};
-
-				let encrypted_data = self.encrypt_last(payload).map_err(|_| Error::Encrypt)?;
-				writer.write_all(&encrypted_data)?;
-
-				break;
-			}
-		}
-
-		writer.flush()?;
+		while !self.encrypt_block(&mut reader, &mut writer, aad)? {}
 
 		Ok(())
 	}
@@ -167,48 +211,49 @@ impl StreamDecryption {
 	/// The master key, nonce and algorithm that were used for encryption should be provided.
 	#[allow(clippy::needless_pass_by_value)]
 	pub fn new(key: Protected<[u8; KEY_LEN]>, nonce: &[u8], algorithm: Algorithm) -> Result<Self> {
-		if nonce.len() != algorithm.nonce_len() {
-			return Err(Error::NonceLengthMismatch);
-		}
+		Ok(Self {
+			stream: Stream::new(&key, nonce, algorithm)?,
+			position: 0,
+		})
+	}
 
-		let decryption_object = match algorithm {
-			Algorithm::XChaCha20Poly1305 => {
-				let cipher = XChaCha20Poly1305::new_from_slice(key.expose())
-					.map_err(|_| Error::StreamModeInit)?;
+	/// This moves the stream to the start of a given block, so decryption can continue from part of the way through a file.
+	///
+	/// Every block except the last is exactly `BLOCK_SIZE + AEAD_TAG_SIZE` bytes, so block `n` starts at `n * (BLOCK_SIZE + AEAD_TAG_SIZE)` after the header.
+	pub fn seek_to_block(&mut self, block: u32) {
+		self.position = block;
+	}
 
-				let stream = DecryptorLE31::from_aead(cipher, nonce.into());
-				Self::XChaCha20Poly1305(Box::new(stream))
-			}
-			Algorithm::Aes256Gcm => {
-				let cipher =
-					Aes256Gcm::new_from_slice(key.expose()).map_err(|_| Error::StreamModeInit)?;
+	/// This reads a single block from the reader, decrypts it, and writes it to the writer.
+	///
+	/// It returns `true` once the final block has been written, after which the stream shouldn't be used again.
+	///
+	/// The AAD will be authenticated with the block - if the AAD doesn't match what was used during encryption, an error will be returned.
+	pub fn decrypt_block<R, W>(&mut self, mut reader: R, mut writer: W, aad: &[u8]) -> Result<bool>
+	where
+		R: Read,
+		W: Write,
+	{
+		let mut read_buffer = vec![0u8; BLOCK_SIZE + AEAD_TAG_SIZE].into_boxed_slice();
+		let read_count = reader.read(&mut read_buffer)?;
 
-				let stream = DecryptorLE31::from_aead(cipher, nonce.into());
-				Self::Aes256Gcm(Box::new(stream))
-			}
+		let last_block = read_count != BLOCK_SIZE + AEAD_TAG_SIZE;
+		let payload = Payload {
+			aad,
+			msg: &read_buffer[..read_count],
 		};
 
-		Ok(decryption_object)
-	}
+		let decrypted_data = self
+			.stream
+			.decrypt(self.position, last_block, payload)
+			.map_err(|_| Error::Decrypt)?;
 
-	fn decrypt_next<'msg, 'aad>(
-		&mut self,
-		payload: impl Into<Payload<'msg, 'aad>>,
-	) -> aead::Result<Vec<u8>> {
-		match self {
-			Self::XChaCha20Poly1305(s) => s.decrypt_next(payload),
-			Self::Aes256Gcm(s) => s.decrypt_next(payload),
-		}
-	}
+		writer.write_all(&decrypted_data)?;
+		writer.flush()?;
 
-	fn decrypt_last<'msg, 'aad>(
-		self,
-		payload: impl Into<Payload<'msg, 'aad>>,
-	) -> aead::Result<Vec<u8>> {
-		match self {
-			Self::XChaCha20Poly1305(s) => s.decrypt_last(payload),
-			Self::Aes256Gcm(s) => s.decrypt_last(payload),
-		}
+		self.position += 1;
+
+		Ok(last_block)
 	}
 
 	/// This function should be used for decrypting large amounts of data.
@@ -223,33 +268,7 @@ impl StreamDecryption {
 		R: Read,
 		W: Write,
 	{
-		let mut read_buffer = vec![0u8; BLOCK_SIZE + AEAD_TAG_SIZE].into_boxed_slice();
-
-		loop {
-			let read_count = reader.read(&mut read_buffer)?;
-			if read_count == (BLOCK_SIZE + AEAD_TAG_SIZE) {
-				let payload = Payload {
-					aad,
-					msg: &read_buffer,
-				};
-
-				let decrypted_data = self.decrypt_next(payload).map_err(|_| Error::Decrypt)?;
-
-				writer.write_all(&decrypted_data)?;
-			} else {
-				let payload = Payload {
-					aad,
-					msg: &read_buffer[..read_count],
-				};
-
-				let decrypted_data = self.decrypt_last(payload).map_err(|_| Error::Decrypt)?;
-				writer.write_all(&decrypted_data)?;
-
-				break;
-			}
-		}
-
-		writer.flush()?;
+		while !self.decrypt_block(&mut reader, &mut writer, aad)? {}
 
 		Ok(())
 	}
@@ -273,3 +292,103 @@ impl StreamDecryption {
 			.map_or_else(Err, |_| Ok(Protected::new(writer.into_inner())))
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::primitives::{generate_master_key, generate_nonce};
+
+	const AAD: &[u8] = b"spacedrive";
+
+	/// This is long enough for a few full blocks, and a partial one at the end.
+	fn plaintext() -> Vec<u8> {
+		(0..BLOCK_SIZE * 3 + 1234)
+			.map(|i| u8::try_from(i % 251).unwrap())
+			.collect()
+	}
+
+	#[test]
+	fn block_round_trip() {
+		for algorithm in [Algorithm::XChaCha20Poly1305, Algorithm::Aes256Gcm] {
+			let key = generate_master_key();
+			let nonce = generate_nonce(algorithm);
+			let plaintext = plaintext();
+
+			let mut encryptor = StreamEncryption::new(key.clone(), &nonce, algorithm).unwrap();
+			let mut reader = Cursor::new(&plaintext);
+			let mut ciphertext = Vec::new();
+			let mut blocks = 0;
+			while !encryptor
+				.encrypt_block(&mut reader, &mut ciphertext, AAD)
+				.unwrap()
+			{
+				blocks += 1;
+			}
+			assert_eq!(blocks, 3);
+
+			let mut decryptor = StreamDecryption::new(key, &nonce, algorithm).unwrap();
+			let mut reader = Cursor::new(&ciphertext);
+			let mut decrypted = Vec::new();
+			while !decryptor
+				.decrypt_block(&mut reader, &mut decrypted, AAD)
+				.unwrap()
+			{}
+
+			assert_eq!(decrypted, plaintext);
+		}
+	}
+
+	#[test]
+	fn block_round_trip_wrong_aad() {
+		let algorithm = Algorithm::XChaCha20Poly1305;
+		let key = generate_master_key();
+		let nonce = generate_nonce(algorithm);
+
+		let ciphertext =
+			StreamEncryption::encrypt_bytes(key.clone(), &nonce, algorithm, &plaintext(), AAD)
+				.unwrap();
+
+		let mut decryptor = StreamDecryption::new(key, &nonce, algorithm).unwrap();
+		assert!(decryptor
+			.decrypt_block(Cursor::new(&ciphertext), Vec::new(), b"not the aad")
+			.is_err());
+	}
+
+	#[test]
+	fn seek_to_block_matches_full_encryption() {
+		for algorithm in [Algorithm::XChaCha20Poly1305, Algorithm::Aes256Gcm] {
+			let key = generate_master_key();
+			let nonce = generate_nonce(algorithm);
+			let plaintext = plaintext();
+
+			let full =
+				StreamEncryption::encrypt_bytes(key.clone(), &nonce, algorithm, &plaintext, AAD)
+					.unwrap();
+
+			// this continues from block 2, as a resumed job would
+			let mut encryptor = StreamEncryption::new(key.clone(), &nonce, algorithm).unwrap();
+			encryptor.seek_to_block(2);
+			let mut reader = Cursor::new(&plaintext[BLOCK_SIZE * 2..]);
+			let mut resumed = Vec::new();
+			while !encryptor
+				.encrypt_block(&mut reader, &mut resumed, AAD)
+				.unwrap()
+			{}
+
+			let offset = (BLOCK_SIZE + AEAD_TAG_SIZE) * 2;
+			assert_eq!(resumed, full[offset..]);
+
+			// decryption can also continue from the same block
+			let mut decryptor = StreamDecryption::new(key, &nonce, algorithm).unwrap();
+			decryptor.seek_to_block(2);
+			let mut reader = Cursor::new(&full[offset..]);
+			let mut decrypted = Vec::new();
+			while !decryptor
+				.decrypt_block(&mut reader, &mut decrypted, AAD)
+				.unwrap()
+			{}
+
+			assert_eq!(decrypted, plaintext[BLOCK_SIZE * 2..]);
+		}
+	}
+}
@@ -50,6 +50,8 @@ pub enum Error {
 	TooManyKeyslots,
 	#[error("requested key wasn't found in the key manager")]
 	KeyNotFound,
+	#[error("a key with this UUID already exists in the keystore")]
+	KeyAlreadyExists,
 	#[error("key is already mounted")]
 	KeyAlreadyMounted,
 	#[error("key not mounted")]
@@ -66,10 +68,38 @@ pub enum Error {
 	NoVerificationKey,
 	#[error("key isn't flagged as memory only")]
 	KeyNotMemoryOnly,
+	#[error("key is already flagged as memory only")]
+	KeyAlreadyMemoryOnly,
 	#[error("wrong information provided to the key manager")]
 	IncorrectKeymanagerDetails,
+	#[error("the provided password is too weak")]
+	WeakPassword,
+	#[error("invalid exported key")]
+	ExportedKey,
+	#[error("the keyfile is either empty or too large")]
+	InvalidKeyfile,
+	#[error("invalid key source")]
+	KeySource,
+	#[error("a TOTP code is required to mount this key")]
+	TotpRequired,
+	#[error("the provided TOTP or recovery code is incorrect")]
+	IncorrectTotpCode,
+	#[error(
+		"too many incorrect TOTP codes have been provided for this key, try again in {0} seconds"
+	)]
+	TotpLockedOut(u64),
+	#[error("TOTP is not enabled for this key")]
+	TotpNotEnabled,
+	#[error("TOTP is already enabled for this key")]
+	TotpAlreadyEnabled,
+	#[error("error while generating or verifying a TOTP code")]
+	Totp,
 	#[error("string parse error")]
 	StringParse(#[from] FromUtf8Error),
+	#[error("invalid encrypted file name")]
+	InvalidFilename,
+	#[error("the key exchange for the session key failed")]
+	SessionKey,
 }
 
 impl<T> From<std::sync::PoisonError<T>> for Error {
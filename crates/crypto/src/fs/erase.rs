@@ -0,0 +1,43 @@
+//! This module contains a secure erase function, for overwriting sensitive data before it's removed.
+//!
+//! Be aware that this can't guarantee the data is unrecoverable on SSDs (due to wear levelling) or copy-on-write filesystems.
+use std::io::{Seek, SeekFrom, Write};
+
+use rand::{RngCore, SeedableRng};
+
+use crate::{primitives::BLOCK_SIZE, Result};
+
+/// The default amount of passes, which should be enough for any modern drive.
+pub const DEFAULT_ERASE_PASSES: usize = 2;
+
+/// This overwrites the first `size` bytes of a stream with random data, `passes` times.
+///
+/// The stream is flushed after each pass, but it's up to the caller to sync it to disk (e.g. with `File::sync_all()`) and remove it.
+///
+/// This function uses `ChaCha20Rng` for generating cryptographically-secure random data
+pub fn erase<W>(stream: &mut W, size: usize, passes: usize) -> Result<()>
+where
+	W: Write + Seek,
+{
+	let mut rng = rand_chacha::ChaCha20Rng::from_entropy();
+	let mut buffer = vec![0u8; BLOCK_SIZE].into_boxed_slice();
+
+	for _ in 0..passes {
+		stream.seek(SeekFrom::Start(0))?;
+
+		let mut remaining = size;
+
+		while remaining > 0 {
+			let count = remaining.min(BLOCK_SIZE);
+			rng.fill_bytes(&mut buffer[..count]);
+			stream.write_all(&buffer[..count])?;
+			remaining -= count;
+		}
+
+		stream.flush()?;
+	}
+
+	stream.seek(SeekFrom::Start(0))?;
+
+	Ok(())
+}
@@ -0,0 +1,114 @@
+//! This module contains file name encryption, which is used so that the names within a vault don't leak anything about their contents.
+//!
+//! Encrypted names are hex-encoded, so they're safe to use on any filesystem. They're roughly twice as long as the original, plus 72 characters.
+use crate::{
+	crypto::stream::{Algorithm, StreamDecryption, StreamEncryption},
+	primitives::{derive_key, generate_nonce, FILENAME_CONTEXT, KEY_LEN, SALT_LEN},
+	Error, Protected, Result,
+};
+
+const FILENAME_ALGORITHM: Algorithm = Algorithm::XChaCha20Poly1305;
+
+/// This derives the file name key from a hashed key.
+///
+/// The hashed key has already been salted with the key's content salt, so no additional salt is used.
+fn filename_key(hashed_key: Protected<[u8; KEY_LEN]>) -> Protected<[u8; KEY_LEN]> {
+	derive_key(hashed_key, [0u8; SALT_LEN], FILENAME_CONTEXT)
+}
+
+/// This encrypts a file name with a hashed key (e.g. from `KeyManager::access_keymount()`).
+///
+/// A random nonce is used, so encrypting the same name twice will produce different results.
+pub fn encrypt_filename(hashed_key: Protected<[u8; KEY_LEN]>, name: &str) -> Result<String> {
+	let nonce = generate_nonce(FILENAME_ALGORITHM);
+
+	let encrypted = StreamEncryption::encrypt_bytes(
+		filename_key(hashed_key),
+		&nonce,
+		FILENAME_ALGORITHM,
+		name.as_bytes(),
+		&[],
+	)?;
+
+	Ok(hex::encode([nonce, encrypted].concat()))
+}
+
+/// This decrypts a file name that was encrypted with `encrypt_filename()`.
+///
+/// This will return an error if the name wasn't encrypted, or if it was encrypted with a different key.
+pub fn decrypt_filename(hashed_key: Protected<[u8; KEY_LEN]>, encrypted: &str) -> Result<String> {
+	let bytes = hex::decode(encrypted).map_err(|_| Error::InvalidFilename)?;
+	let nonce_len = FILENAME_ALGORITHM.nonce_len();
+
+	if bytes.len() <= nonce_len {
+		return Err(Error::InvalidFilename);
+	}
+
+	let (nonce, encrypted) = bytes.split_at(nonce_len);
+
+	let name = StreamDecryption::decrypt_bytes(
+		filename_key(hashed_key),
+		nonce,
+		FILENAME_ALGORITHM,
+		encrypted,
+		&[],
+	)?;
+
+	String::from_utf8(name.expose().clone()).map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::primitives::generate_master_key;
+
+	#[test]
+	fn round_trip() {
+		let key = generate_master_key();
+		let name = "holiday photos (2022).jpg";
+
+		let encrypted = encrypt_filename(key.clone(), name).unwrap();
+		assert_ne!(encrypted, name);
+		assert!(encrypted.bytes().all(|b| b.is_ascii_hexdigit()));
+
+		// a random nonce is used each time
+		assert_ne!(encrypt_filename(key.clone(), name).unwrap(), encrypted);
+
+		assert_eq!(decrypt_filename(key, &encrypted).unwrap(), name);
+	}
+
+	#[test]
+	fn decrypt_rejects_a_different_key() {
+		let encrypted = encrypt_filename(generate_master_key(), "notes.txt").unwrap();
+
+		assert!(decrypt_filename(generate_master_key(), &encrypted).is_err());
+	}
+
+	#[test]
+	fn decrypt_rejects_names_that_werent_encrypted() {
+		assert!(matches!(
+			decrypt_filename(generate_master_key(), "notes.txt"),
+			Err(Error::InvalidFilename)
+		));
+	}
+
+	#[test]
+	fn decrypt_rejects_truncated_names() {
+		let key = generate_master_key();
+		let encrypted = encrypt_filename(key.clone(), "notes.txt").unwrap();
+
+		// just the nonce (or less) isn't a valid name
+		let nonce_len = FILENAME_ALGORITHM.nonce_len() * 2;
+		assert!(matches!(
+			decrypt_filename(key.clone(), &encrypted[..nonce_len]),
+			Err(Error::InvalidFilename)
+		));
+		assert!(matches!(
+			decrypt_filename(key.clone(), &encrypted[..nonce_len - 2]),
+			Err(Error::InvalidFilename)
+		));
+
+		// anything cut from the end fails to authenticate
+		assert!(decrypt_filename(key, &encrypted[..encrypted.len() - 2]).is_err());
+	}
+}
@@ -0,0 +1,3 @@
+//! This module contains filesystem-related functions, such as securely erasing files and encrypting file names.
+pub mod erase;
+pub mod filename;
@@ -114,20 +114,7 @@ impl FileHeader {
 	{
 		let master_key = self.decrypt_master_key_from_prehashed(hashed_keys)?;
 
-		// could be an expensive clone (a few MiB at most)
-		if let Some(metadata) = self.metadata.clone() {
-			let metadata = StreamDecryption::decrypt_bytes(
-				master_key,
-				&metadata.metadata_nonce,
-				metadata.algorithm,
-				&metadata.metadata,
-				&[],
-			)?;
-
-			serde_json::from_slice::<T>(&metadata).map_err(|_| Error::MetadataDeSerialization)
-		} else {
-			Err(Error::NoMetadata)
-		}
+		self.decrypt_metadata_with_master_key(master_key)
 	}
 
 	/// This function should be used to retrieve the metadata for a file
@@ -142,20 +129,31 @@ impl FileHeader {
 	{
 		let master_key = self.decrypt_master_key(password)?;
 
-		// could be an expensive clone (a few MiB at most)
-		if let Some(metadata) = self.metadata.clone() {
-			let metadata = StreamDecryption::decrypt_bytes(
-				master_key,
-				&metadata.metadata_nonce,
-				metadata.algorithm,
-				&metadata.metadata,
-				&[],
-			)?;
-
-			serde_json::from_slice::<T>(&metadata).map_err(|_| Error::MetadataDeSerialization)
-		} else {
-			Err(Error::NoMetadata)
-		}
+		self.decrypt_metadata_with_master_key(master_key)
+	}
+
+	/// This function should be used to retrieve the metadata for a file, if the master key has already been decrypted
+	///
+	/// A deserialized data type will be returned from this function
+	#[cfg(feature = "serde")]
+	pub fn decrypt_metadata_with_master_key<T>(
+		&self,
+		master_key: Protected<[u8; KEY_LEN]>,
+	) -> Result<T>
+	where
+		T: serde::de::DeserializeOwned,
+	{
+		let metadata = self.metadata.as_ref().ok_or(Error::NoMetadata)?;
+
+		let metadata = StreamDecryption::decrypt_bytes(
+			master_key,
+			&metadata.metadata_nonce,
+			metadata.algorithm,
+			&metadata.metadata,
+			&[],
+		)?;
+
+		serde_json::from_slice::<T>(&metadata).map_err(|_| Error::MetadataDeSerialization)
 	}
 }
 
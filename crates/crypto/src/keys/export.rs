@@ -0,0 +1,299 @@
+//! This module contains the portable key export format.
+//!
+//! An exported key is a single key from the key manager, wrapped with a user-provided passphrase.
+//!
+//! It can be handed to another key manager (e.g. another library) without needing a full keystore backup.
+//!
+//! The UUID and content salt are preserved, so anything encrypted with the original key can still be decrypted once it's imported.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! let passphrase = Protected::new(b"export passphrase".to_vec());
+//!
+//! // export a key from one key manager
+//! let blob = key_manager.export_key(uuid, passphrase.clone()).unwrap();
+//!
+//! // and import it into another
+//! let uuid = other_key_manager.import_key(&blob, passphrase, false, false).unwrap();
+//! ```
+use std::io::{Cursor, Read};
+
+use uuid::Uuid;
+
+use crate::{
+	crypto::stream::{Algorithm, StreamDecryption, StreamEncryption},
+	keys::hashing::HashingAlgorithm,
+	primitives::{derive_key, generate_nonce, generate_salt, EXPORT_KEY_CONTEXT, SALT_LEN},
+	Error, Protected, Result,
+};
+
+/// These are used to quickly identify exported keys
+/// These currently are set as "sdkeyex"
+pub const EXPORT_MAGIC_BYTES: [u8; 7] = [0x73, 0x64, 0x6B, 0x65, 0x79, 0x65, 0x78];
+
+/// This is a single key, wrapped with a passphrase so it can be moved between key managers.
+///
+/// The `key` is encrypted, and everything before it is authenticated as AAD.
+#[derive(Clone)]
+pub struct ExportedKey {
+	pub version: ExportedKeyVersion,
+	pub uuid: Uuid,
+	pub algorithm: Algorithm,
+	pub hashing_algorithm: HashingAlgorithm,
+	pub content_salt: [u8; SALT_LEN],
+	pub salt: [u8; SALT_LEN], // salt used for hashing the export passphrase
+	pub nonce: Vec<u8>,
+	pub key: Vec<u8>, // encrypted with a key derived from the export passphrase
+}
+
+/// This defines the export format version.
+#[derive(Clone, Copy)]
+pub enum ExportedKeyVersion {
+	V1,
+}
+
+impl ExportedKeyVersion {
+	#[must_use]
+	pub const fn serialize(&self) -> [u8; 2] {
+		match self {
+			Self::V1 => [0x2A, 0x01],
+		}
+	}
+
+	pub const fn deserialize(bytes: [u8; 2]) -> Result<Self> {
+		match bytes {
+			[0x2A, 0x01] => Ok(Self::V1),
+			_ => Err(Error::ExportedKey),
+		}
+	}
+}
+
+impl ExportedKey {
+	/// This wraps a plaintext key with the provided passphrase.
+	///
+	/// The passphrase is hashed with the supplied hashing algorithm, and a freshly generated salt.
+	#[allow(clippy::needless_pass_by_value)]
+	pub fn new(
+		uuid: Uuid,
+		algorithm: Algorithm,
+		hashing_algorithm: HashingAlgorithm,
+		content_salt: [u8; SALT_LEN],
+		key: Protected<Vec<u8>>,
+		passphrase: Protected<Vec<u8>>,
+	) -> Result<Self> {
+		let salt = generate_salt();
+		let nonce = generate_nonce(algorithm);
+
+		let mut exported_key = Self {
+			version: ExportedKeyVersion::V1,
+			uuid,
+			algorithm,
+			hashing_algorithm,
+			content_salt,
+			salt,
+			nonce,
+			key: Vec::new(),
+		};
+
+		let hashed_passphrase = hashing_algorithm.hash(passphrase, salt)?;
+		let derived_key = derive_key(hashed_passphrase, salt, EXPORT_KEY_CONTEXT);
+
+		exported_key.key = StreamEncryption::encrypt_bytes(
+			derived_key,
+			&exported_key.nonce,
+			algorithm,
+			key.expose(),
+			&exported_key.generate_aad(),
+		)?;
+
+		Ok(exported_key)
+	}
+
+	/// This decrypts the wrapped key with the provided passphrase.
+	///
+	/// An error is returned if the passphrase is incorrect, or if the export has been tampered with.
+	#[allow(clippy::needless_pass_by_value)]
+	pub fn decrypt_key(&self, passphrase: Protected<Vec<u8>>) -> Result<Protected<Vec<u8>>> {
+		let hashed_passphrase = self.hashing_algorithm.hash(passphrase, self.salt)?;
+		let derived_key = derive_key(hashed_passphrase, self.salt, EXPORT_KEY_CONTEXT);
+
+		StreamDecryption::decrypt_bytes(
+			derived_key,
+			&self.nonce,
+			self.algorithm,
+			&self.key,
+			&self.generate_aad(),
+		)
+		.map_err(|_| Error::IncorrectPassword)
+	}
+
+	/// This is everything preceding the encrypted key, and it's authenticated during encryption/decryption.
+	#[must_use]
+	pub fn generate_aad(&self) -> Vec<u8> {
+		match self.version {
+			ExportedKeyVersion::V1 => {
+				let mut aad = Vec::new();
+				aad.extend_from_slice(&EXPORT_MAGIC_BYTES); // 7
+				aad.extend_from_slice(&self.version.serialize()); // 9
+				aad.extend_from_slice(self.uuid.as_bytes()); // 25
+				aad.extend_from_slice(&self.algorithm.serialize()); // 27
+				aad.extend_from_slice(&self.hashing_algorithm.serialize()); // 29
+				aad.extend_from_slice(&self.content_salt); // 45
+				aad.extend_from_slice(&self.salt); // 61
+				aad.extend_from_slice(&self.nonce); // 69 OR 81
+				aad.extend_from_slice(&vec![0u8; 24 - self.nonce.len()]); // padded until 85 bytes
+				aad
+			}
+		}
+	}
+
+	/// This serializes the exported key (including the encrypted key) to bytes.
+	#[must_use]
+	pub fn serialize(&self) -> Vec<u8> {
+		match self.version {
+			ExportedKeyVersion::V1 => {
+				let mut bytes = self.generate_aad();
+				bytes.extend_from_slice(&(self.key.len() as u64).to_le_bytes());
+				bytes.extend_from_slice(&self.key);
+				bytes
+			}
+		}
+	}
+
+	/// This deserializes an exported key from bytes.
+	///
+	/// No decryption is done here, use `ExportedKey::decrypt_key()` for that.
+	pub fn deserialize(bytes: &[u8]) -> Result<Self> {
+		let mut reader = Cursor::new(bytes);
+
+		let mut magic_bytes = [0u8; EXPORT_MAGIC_BYTES.len()];
+		reader.read_exact(&mut magic_bytes)?;
+
+		if magic_bytes != EXPORT_MAGIC_BYTES {
+			return Err(Error::ExportedKey);
+		}
+
+		let mut version = [0u8; 2];
+		reader.read_exact(&mut version)?;
+		let version = ExportedKeyVersion::deserialize(version)?;
+
+		match version {
+			ExportedKeyVersion::V1 => {
+				let mut uuid = [0u8; 16];
+				reader.read_exact(&mut uuid)?;
+				let uuid = Uuid::from_bytes(uuid);
+
+				let mut algorithm = [0u8; 2];
+				reader.read_exact(&mut algorithm)?;
+				let algorithm = Algorithm::deserialize(algorithm)?;
+
+				let mut hashing_algorithm = [0u8; 2];
+				reader.read_exact(&mut hashing_algorithm)?;
+				let hashing_algorithm = HashingAlgorithm::deserialize(hashing_algorithm)?;
+
+				let mut content_salt = [0u8; SALT_LEN];
+				reader.read_exact(&mut content_salt)?;
+
+				let mut salt = [0u8; SALT_LEN];
+				reader.read_exact(&mut salt)?;
+
+				let mut nonce = vec![0u8; algorithm.nonce_len()];
+				reader.read_exact(&mut nonce)?;
+
+				// read and discard the padding
+				reader.read_exact(&mut vec![0u8; 24 - nonce.len()])?;
+
+				let mut key_len = [0u8; 8];
+				reader.read_exact(&mut key_len)?;
+				let key_len =
+					usize::try_from(u64::from_le_bytes(key_len)).map_err(|_| Error::ExportedKey)?;
+
+				if key_len > bytes.len() {
+					return Err(Error::ExportedKey);
+				}
+
+				let mut key = vec![0u8; key_len];
+				reader.read_exact(&mut key)?;
+
+				Ok(Self {
+					version,
+					uuid,
+					algorithm,
+					hashing_algorithm,
+					content_salt,
+					salt,
+					nonce,
+					key,
+				})
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{keys::hashing::Params, primitives::generate_master_key};
+
+	fn exported_key(key: &Protected<Vec<u8>>) -> ExportedKey {
+		ExportedKey::new(
+			Uuid::new_v4(),
+			Algorithm::XChaCha20Poly1305,
+			HashingAlgorithm::Argon2id(Params::Standard),
+			generate_salt(),
+			key.clone(),
+			Protected::new(b"export passphrase".to_vec()),
+		)
+		.unwrap()
+	}
+
+	#[test]
+	fn round_trip() {
+		let key = Protected::new(generate_master_key().expose().to_vec());
+		let exported = exported_key(&key);
+
+		let imported = ExportedKey::deserialize(&exported.serialize()).unwrap();
+		assert_eq!(imported.uuid, exported.uuid);
+		assert_eq!(imported.content_salt, exported.content_salt);
+		assert_eq!(imported.serialize(), exported.serialize());
+
+		let decrypted = imported
+			.decrypt_key(Protected::new(b"export passphrase".to_vec()))
+			.unwrap();
+		assert_eq!(decrypted.expose(), key.expose());
+
+		assert!(matches!(
+			imported.decrypt_key(Protected::new(b"wrong passphrase".to_vec())),
+			Err(Error::IncorrectPassword)
+		));
+	}
+
+	#[test]
+	fn deserialize_rejects_bad_magic_bytes_and_version() {
+		let bytes = exported_key(&Protected::new(vec![1u8; 32])).serialize();
+
+		let mut bad_magic = bytes.clone();
+		bad_magic[0] ^= 0xFF;
+		assert!(matches!(
+			ExportedKey::deserialize(&bad_magic),
+			Err(Error::ExportedKey)
+		));
+
+		let mut bad_version = bytes;
+		bad_version[EXPORT_MAGIC_BYTES.len() + 1] = 0xFF;
+		assert!(matches!(
+			ExportedKey::deserialize(&bad_version),
+			Err(Error::ExportedKey)
+		));
+	}
+
+	#[test]
+	fn deserialize_rejects_truncated_input() {
+		let bytes = exported_key(&Protected::new(vec![1u8; 32])).serialize();
+
+		for len in 0..bytes.len() {
+			assert!(ExportedKey::deserialize(&bytes[..len]).is_err());
+		}
+	}
+}
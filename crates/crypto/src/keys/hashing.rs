@@ -11,11 +11,18 @@
 //! let hashed_password = hashing_algorithm.hash(password, salt).unwrap();
 //! ```
 
-use crate::primitives::KEY_LEN;
+use std::time::{Duration, Instant};
+
+use crate::primitives::{generate_master_key, generate_salt, KEY_LEN};
 use crate::Protected;
 use crate::{primitives::SALT_LEN, Error, Result};
 use argon2::Argon2;
 
+/// This is the longest that a recommended hashing preset may take on the current device.
+///
+/// Anything longer than this makes unlocking the library too painful.
+pub const MAXIMUM_RECOMMENDED_HASH_DURATION: Duration = Duration::from_secs(3);
+
 /// These parameters define the password-hashing level.
 ///
 /// The harder the parameter, the longer the password will take to hash.
@@ -44,7 +51,67 @@ pub enum HashingAlgorithm {
 	Argon2id(Params),
 }
 
+/// This is the result of benchmarking a single hashing preset on the current device.
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "rspc", derive(specta::Type))]
+pub struct HashingBenchmark {
+	pub hashing_algorithm: HashingAlgorithm,
+	/// the amount of memory used while hashing, in KiB
+	pub memory_cost: u32,
+	pub milliseconds: u64,
+	/// only the strongest preset that hashes within `MAXIMUM_RECOMMENDED_HASH_DURATION` is recommended
+	pub recommended: bool,
+}
+
 impl HashingAlgorithm {
+	/// This hashes a random password with every available preset, and measures how long each one takes on the current device.
+	///
+	/// This is slow (it can take 10 seconds or more), so it should be run on a blocking thread.
+	pub fn benchmark() -> Result<Vec<HashingBenchmark>> {
+		let mut benchmarks = [Params::Standard, Params::Hardened, Params::Paranoid]
+			.into_iter()
+			.map(|params| {
+				let hashing_algorithm = Self::Argon2id(params);
+				let password = Protected::new(generate_master_key().expose().to_vec());
+
+				let start = Instant::now();
+				hashing_algorithm.hash(password, generate_salt())?;
+				let duration = start.elapsed();
+
+				Ok(HashingBenchmark {
+					hashing_algorithm,
+					memory_cost: params.get_argon2_params().m_cost(),
+					milliseconds: duration.as_millis().try_into().unwrap_or(u64::MAX),
+					recommended: false,
+				})
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		// the slowest preset that's still within the limit is the strongest one that's usable on this device
+		// if they're all too slow, the fastest one is recommended
+		let recommended = benchmarks
+			.iter()
+			.enumerate()
+			.filter(|(_, b)| {
+				u128::from(b.milliseconds) <= MAXIMUM_RECOMMENDED_HASH_DURATION.as_millis()
+			})
+			.max_by_key(|(_, b)| b.milliseconds)
+			.or_else(|| {
+				benchmarks
+					.iter()
+					.enumerate()
+					.min_by_key(|(_, b)| b.milliseconds)
+			})
+			.map(|(i, _)| i);
+
+		if let Some(i) = recommended {
+			benchmarks[i].recommended = true;
+		}
+
+		Ok(benchmarks)
+	}
+
 	/// This function should be used to hash passwords
 	///
 	/// It also handles all the password hashing parameters.
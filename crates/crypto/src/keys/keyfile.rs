@@ -0,0 +1,86 @@
+//! This module contains support for keyfiles.
+//!
+//! A keyfile is any file whose contents are used as a key, instead of a typed password.
+//!
+//! The contents are hashed (so a keyfile of any size may be used), and the hash is what's added to the key manager.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! let mut reader = File::open("keyfile.bin").unwrap();
+//! let key = hash_keyfile(&mut reader).unwrap();
+//!
+//! let uuid = key_manager.add_to_keystore(key, ...).unwrap();
+//! ```
+use std::io::Read;
+
+use crate::{primitives::KEYFILE_CONTEXT, Error, Protected, Result};
+
+/// The largest keyfile that will be accepted. Anything larger than this is almost certainly not intended to be a keyfile.
+pub const MAXIMUM_KEYFILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// This hashes the contents of a keyfile with BLAKE3, in key derivation mode.
+///
+/// The returned key is the hex-encoded hash, so it can still be revealed and re-typed by the user if needed.
+///
+/// An error will be returned if the keyfile is empty, or larger than `MAXIMUM_KEYFILE_SIZE`.
+pub fn hash_keyfile<R>(reader: &mut R) -> Result<Protected<Vec<u8>>>
+where
+	R: Read,
+{
+	let mut hasher = blake3::Hasher::new_derive_key(KEYFILE_CONTEXT);
+
+	let size = std::io::copy(&mut reader.take(MAXIMUM_KEYFILE_SIZE + 1), &mut hasher)?;
+
+	if size == 0 || size > MAXIMUM_KEYFILE_SIZE {
+		return Err(Error::InvalidKeyfile);
+	}
+
+	Ok(Protected::new(
+		hasher.finalize().to_hex().as_bytes().to_vec(),
+	))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Cursor;
+
+	#[test]
+	fn hash_is_deterministic() {
+		let key = hash_keyfile(&mut Cursor::new(b"keyfile contents")).unwrap();
+
+		assert_eq!(key.expose().len(), 64);
+		assert!(key.expose().iter().all(u8::is_ascii_hexdigit));
+		assert_eq!(
+			key.expose(),
+			hash_keyfile(&mut Cursor::new(b"keyfile contents"))
+				.unwrap()
+				.expose()
+		);
+		assert_ne!(
+			key.expose(),
+			hash_keyfile(&mut Cursor::new(b"other contents"))
+				.unwrap()
+				.expose()
+		);
+	}
+
+	#[test]
+	fn rejects_empty_keyfiles() {
+		assert!(matches!(
+			hash_keyfile(&mut Cursor::new(Vec::new())),
+			Err(Error::InvalidKeyfile)
+		));
+	}
+
+	#[test]
+	fn rejects_oversized_keyfiles() {
+		let mut reader = std::io::repeat(0).take(MAXIMUM_KEYFILE_SIZE + 1);
+
+		assert!(matches!(
+			hash_keyfile(&mut reader),
+			Err(Error::InvalidKeyfile)
+		));
+	}
+}
@@ -36,11 +36,13 @@
 //! ```
 
 use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
 
 use crate::crypto::stream::{StreamDecryption, StreamEncryption};
 use crate::primitives::{
-	derive_key, generate_master_key, generate_nonce, generate_passphrase, generate_salt, to_array,
-	KEY_LEN, MASTER_PASSWORD_CONTEXT, ROOT_KEY_CONTEXT,
+	derive_key, generate_master_key, generate_nonce, generate_passphrase, generate_salt,
+	password_strength, to_array, PassphraseStyle, KEY_LEN, MASTER_PASSWORD_CONTEXT,
+	MINIMUM_PASSWORD_SCORE, ROOT_KEY_CONTEXT,
 };
 use crate::{
 	crypto::stream::Algorithm,
@@ -55,7 +57,14 @@ use uuid::Uuid;
 #[cfg(feature = "serde")]
 use serde_big_array::BigArray;
 
-use super::hashing::HashingAlgorithm;
+use super::{
+	export::ExportedKey,
+	hashing::HashingAlgorithm,
+	totp::{
+		generate_recovery_codes, generate_totp_secret, verify_code, StoredTotp, TotpCode,
+		TotpEnrollment, TOTP_LOCKOUT, TOTP_MAX_FAILURES, TOTP_MAX_LOCKOUT,
+	},
+};
 
 // The terminology in this file is very confusing.
 // The `master_key` is specific to the `StoredKey`, and is just used internally for encryption.
@@ -81,6 +90,44 @@ pub struct StoredKey {
 	pub salt: [u8; SALT_LEN],
 	pub memory_only: bool,
 	pub automount: bool,
+	#[cfg_attr(feature = "serde", serde(default))]
+	pub name: Option<String>, // the user-provided name. backups from before this was added won't have one
+	#[cfg_attr(feature = "serde", serde(default))]
+	pub source: KeySource, // how the key was provided. backups from before this was added are assumed to be typed
+}
+
+/// This defines how a key was provided by the user.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize),
+	derive(serde::Deserialize)
+)]
+#[cfg_attr(feature = "rspc", derive(specta::Type))]
+pub enum KeySource {
+	/// the key was typed (or pasted) in, or generated
+	#[default]
+	Typed,
+	/// the key is the hash of a keyfile's contents, see `keys::keyfile` for more information
+	Keyfile,
+}
+
+impl KeySource {
+	#[must_use]
+	pub const fn serialize(&self) -> i32 {
+		match self {
+			Self::Typed => 0,
+			Self::Keyfile => 1,
+		}
+	}
+
+	pub const fn deserialize(value: i32) -> Result<Self> {
+		match value {
+			0 => Ok(Self::Typed),
+			1 => Ok(Self::Keyfile),
+			_ => Err(Error::KeySource),
+		}
+	}
 }
 
 /// This is a mounted key, and needs to be kept somewhat hidden.
@@ -90,6 +137,30 @@ pub struct StoredKey {
 pub struct MountedKey {
 	pub uuid: Uuid, // used for identification. shared with stored keys
 	pub hashed_key: Protected<[u8; KEY_LEN]>, // this is hashed with the content salt, for instant access
+	pub mounted_at: SystemTime,
+	pub source: MountSource,
+}
+
+/// This defines what caused a key to be mounted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize),
+	derive(serde::Deserialize)
+)]
+#[cfg_attr(feature = "rspc", derive(specta::Type))]
+pub enum MountSource {
+	Manual,
+	Automount,
+	Job,
+}
+
+/// These are the details of a mounted key, without the key itself.
+#[derive(Clone)]
+pub struct MountDetails {
+	pub uuid: Uuid,
+	pub mounted_at: SystemTime,
+	pub source: MountSource,
 }
 
 /// This is the key manager itself.
@@ -103,6 +174,18 @@ pub struct KeyManager {
 	keystore: DashMap<Uuid, StoredKey>,
 	keymount: DashMap<Uuid, MountedKey>,
 	default: Mutex<Option<Uuid>>,
+	last_activity: Mutex<Instant>, // the last time the root key or a mounted key was used
+	totp: DashMap<Uuid, TotpGate>, // keys that require a TOTP code to be mounted
+	pending_totp: DashMap<Uuid, StoredTotp>, // TOTP enrollments that haven't been confirmed yet
+	retired: DashMap<Uuid, Vec<Protected<[u8; KEY_LEN]>>>, // hashed keys from before a key was rotated, so files can still be re-wrapped
+}
+
+/// This is used internally to keep track of a TOTP-gated key.
+struct TotpGate {
+	stored_totp: StoredTotp,
+	last_counter: Option<u64>, // the counter of the last accepted code, so codes can't be replayed
+	failures: u32,             // the amount of incorrect codes in a row
+	locked_until: Option<Instant>, // codes aren't checked until this point, once there have been too many failures
 }
 
 // bundle returned during onboarding
@@ -119,9 +202,40 @@ pub struct OnboardingBundle {
 	pub secret_key: Protected<String>, // hex encoded string that is required along with the master password
 }
 
+/// This is returned when previewing a keystore backup restore.
+///
+/// Keys that share a UUID with an existing key are never restored, so `conflicting` keys are those that differ from the key that's already present.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "rspc", derive(specta::Type))]
+pub struct KeystoreBackupDiff {
+	pub new_keys: Vec<Uuid>,            // keys that will be restored
+	pub duplicates: Vec<Uuid>,          // keys that are already present, and identical
+	pub conflicting: Vec<Uuid>,         // keys that are already present, but differ from the backup
+	pub verification_key_changed: bool, // whether the master password/secret key have changed since the backup
+}
+
+/// This is returned when the master password is changed, and it needs to be committed with `KeyManager::commit_master_password_change()` once it has been written to the database.
 pub struct MasterPasswordChangeBundle {
 	pub verification_key: StoredKey, // nil UUID key that is only ever used for verifying the master password is correct
 	pub secret_key: Protected<String>, // hex encoded string that is required along with the master password
+	pub updated_keys: Vec<StoredKey>,  // every key, re-wrapped with the new root key
+	pub updated_totp: Vec<(Uuid, StoredTotp)>, // every TOTP secret, re-wrapped with the new root key
+	root_key: Protected<[u8; KEY_LEN]>, // the new root key, which is only used once the change is committed
+}
+
+impl MasterPasswordChangeBundle {
+	/// This derives a subkey of the new root key, so anything encrypted with a subkey can be re-encrypted before the change is committed.
+	#[must_use]
+	pub fn derive_subkey(&self, context: &str) -> Protected<[u8; KEY_LEN]> {
+		derive_key(self.root_key.clone(), [0u8; SALT_LEN], context)
+	}
+}
+
+/// This is returned when the secret key is rotated.
+pub struct SecretKeyRotationBundle {
+	pub verification_key: StoredKey, // the verification key, re-wrapped with the new secret key
+	pub secret_key: Protected<String>, // hex encoded string that is required along with the master password
 }
 
 /// The `KeyManager` functions should be used for all key-related management.
@@ -138,6 +252,10 @@ impl KeyManager {
 			keystore,
 			keymount,
 			default: Mutex::new(None),
+			last_activity: Mutex::new(Instant::now()),
+			totp: DashMap::new(),
+			pending_totp: DashMap::new(),
+			retired: DashMap::new(),
 		};
 
 		keymanager.populate_keystore(stored_keys)?;
@@ -147,20 +265,29 @@ impl KeyManager {
 
 	/// This should be used to generate everything for the user during onboarding.
 	///
-	/// This will create a master password (a 7-word diceware passphrase), and a secret key (16 bytes, hex encoded)
+	/// This will create a master password (a 7-word diceware passphrase by default), and a secret key (16 bytes, hex encoded)
+	///
+	/// The style/length of the generated master password can be chosen with `passphrase_style`.
+	///
+	/// A user-chosen master password may be provided instead. Either way, the master password must reach `MINIMUM_PASSWORD_SCORE`. The secret key is always generated.
 	///
 	/// It will also generate a verification key, which should be written to the database.
 	#[allow(clippy::needless_pass_by_value)]
 	pub fn onboarding(
 		algorithm: Algorithm,
 		hashing_algorithm: HashingAlgorithm,
+		passphrase_style: PassphraseStyle,
+		master_password: Option<Protected<String>>,
 	) -> Result<OnboardingBundle> {
-		let _master_password = generate_passphrase();
-		let _content_salt = generate_salt(); // secret key
+		let master_password =
+			master_password.unwrap_or_else(|| generate_passphrase(passphrase_style));
+
+		// generated passwords are checked too, so no style/length can produce one that would be rejected if it was chosen
+		if password_strength(&master_password) < MINIMUM_PASSWORD_SCORE {
+			return Err(Error::WeakPassword);
+		}
 
-		// BRXKEN128: REMOVE THIS ONCE ONBOARDING HAS BEEN DONE
-		let master_password = Protected::new("password".to_string());
-		let content_salt = *b"0000000000000000"; // secret key
+		let content_salt = generate_salt(); // secret key
 
 		// Hash the master password
 		let hashed_password = hashing_algorithm.hash(
@@ -209,6 +336,8 @@ impl KeyManager {
 			salt,
 			memory_only: false,
 			automount: false,
+			name: None,
+			source: KeySource::Typed,
 		};
 
 		let secret_key = Self::format_secret_key(&content_salt);
@@ -222,6 +351,12 @@ impl KeyManager {
 		Ok(onboarding_bundle)
 	}
 
+	/// This replaces the verification key, e.g. once the library has been onboarded or the secret key has been rotated.
+	pub fn set_verification_key(&self, verification_key: StoredKey) -> Result<()> {
+		*self.verification_key.lock()? = Some(verification_key);
+		Ok(())
+	}
+
 	/// This function should be used to populate the keystore with multiple stored keys at a time.
 	///
 	/// It's suitable for when you created the key manager without populating it.
@@ -258,18 +393,33 @@ impl KeyManager {
 
 			// remove from keystore
 			self.keystore.remove(&uuid);
+			self.totp.remove(&uuid);
+			self.pending_totp.remove(&uuid);
 		}
 
 		Ok(())
 	}
 
+	/// This is used to change the master password, and it generates a new secret key.
+	///
+	/// A new root key is also generated, and every key within the keystore is re-wrapped with it.
+	///
+	/// `progress` is called with the amount of keys that have been re-wrapped, and the total amount of keys.
+	///
+	/// Nothing is changed within the key manager - the updated keys and the new verification key are returned within the bundle, and they need to be written to the database.
+	///
+	/// Once they've been written, the change should be committed with `KeyManager::commit_master_password_change()`.
 	#[allow(clippy::needless_pass_by_value)]
-	pub fn change_master_password(
+	pub fn change_master_password<F>(
 		&self,
 		master_password: Protected<String>,
 		algorithm: Algorithm,
 		hashing_algorithm: HashingAlgorithm,
-	) -> Result<MasterPasswordChangeBundle> {
+		progress: F,
+	) -> Result<MasterPasswordChangeBundle>
+	where
+		F: Fn(usize, usize),
+	{
 		let content_salt = generate_salt(); // secret key
 
 		let hashed_password = hashing_algorithm.hash(
@@ -283,7 +433,8 @@ impl KeyManager {
 		let master_key = generate_master_key();
 		let master_key_nonce = generate_nonce(algorithm);
 
-		let root_key = self.get_root_key()?;
+		let old_root_key = self.get_root_key()?;
+		let root_key = generate_master_key();
 		let root_key_nonce = generate_nonce(algorithm);
 
 		let salt = generate_salt();
@@ -315,33 +466,260 @@ impl KeyManager {
 			master_key_nonce,
 			key_nonce: root_key_nonce,
 			key: encrypted_root_key,
-			salt: [0u8; SALT_LEN],
+			salt,
 			memory_only: false,
 			automount: false,
+			name: None,
+			source: KeySource::Typed,
 		};
 
-		*self.verification_key.lock()? = Some(verification_key.clone());
+		let keys = self.dump_keystore();
+		let total = keys.len();
 
-		let secret_key = Self::format_secret_key(&salt);
+		// re-wrap every key with the new root key, before committing anything
+		let updated_keys = keys
+			.iter()
+			.enumerate()
+			.map(|(i, key)| {
+				let updated_key = Self::rewrap_stored_key(&old_root_key, root_key.clone(), key)?;
+				progress(i + 1, total);
+				Ok(updated_key)
+			})
+			.collect::<Result<Vec<StoredKey>>>()?;
+
+		let updated_totp = self
+			.totp
+			.iter()
+			.map(|gate| {
+				Ok((
+					*gate.key(),
+					gate.stored_totp
+						.rewrap(old_root_key.clone(), root_key.clone())?,
+				))
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		let secret_key = Self::format_secret_key(&content_salt);
 
 		let mp_change_bundle = MasterPasswordChangeBundle {
 			verification_key,
 			secret_key,
+			updated_keys,
+			updated_totp,
+			root_key,
 		};
 
 		Ok(mp_change_bundle)
 	}
 
+	/// This commits a master password change, once everything within the bundle has been written to the database.
+	///
+	/// The old root key is replaced, so anything that's still wrapped with it can no longer be decrypted.
+	pub fn commit_master_password_change(&self, bundle: &MasterPasswordChangeBundle) -> Result<()> {
+		for key in &bundle.updated_keys {
+			self.keystore.insert(key.uuid, key.clone());
+		}
+
+		for (uuid, stored_totp) in &bundle.updated_totp {
+			if let Some(mut gate) = self.totp.get_mut(uuid) {
+				gate.stored_totp = stored_totp.clone();
+			}
+		}
+
+		// pending enrollments were encrypted with the old root key, so they need to be started again
+		self.pending_totp.clear();
+
+		*self.verification_key.lock()? = Some(bundle.verification_key.clone());
+		*self.root_key.lock()? = Some(bundle.root_key.clone());
+
+		Ok(())
+	}
+
+	/// This generates a new secret key, and re-wraps the root key with it (alongside the current master password).
+	///
+	/// The root key itself is unchanged, so none of the stored keys need to be updated - only the verification key.
+	///
+	/// Nothing is changed within the key manager - the new verification key is returned, and it should be committed with `KeyManager::set_verification_key()` once it has been written to the database.
+	#[allow(clippy::needless_pass_by_value)]
+	pub fn rotate_secret_key(
+		&self,
+		master_password: Protected<String>,
+		secret_key: Protected<String>,
+	) -> Result<SecretKeyRotationBundle> {
+		let verification_key = self.get_verification_key()?;
+
+		// this ensures that the current details are correct
+		let (root_key, ..) = Self::unlock_keystore_backup(
+			master_password.clone(),
+			secret_key,
+			&[verification_key.clone()],
+		)
+		.map_err(|_| Error::IncorrectKeymanagerDetails)?;
+
+		let algorithm = verification_key.algorithm;
+		let content_salt = generate_salt(); // secret key
+
+		let hashed_password = verification_key.hashing_algorithm.hash(
+			Protected::new(master_password.expose().as_bytes().to_vec()),
+			content_salt,
+		)?;
+
+		let master_key = generate_master_key();
+		let master_key_nonce = generate_nonce(algorithm);
+		let root_key_nonce = generate_nonce(algorithm);
+
+		let salt = generate_salt();
+		let derived_key = derive_key(hashed_password, salt, MASTER_PASSWORD_CONTEXT);
+
+		// Encrypt the master key with the hashed master password
+		let encrypted_master_key = to_array::<ENCRYPTED_KEY_LEN>(StreamEncryption::encrypt_bytes(
+			derived_key,
+			&master_key_nonce,
+			algorithm,
+			master_key.expose(),
+			&[],
+		)?)?;
+
+		let encrypted_root_key = StreamEncryption::encrypt_bytes(
+			master_key,
+			&root_key_nonce,
+			algorithm,
+			root_key.expose(),
+			&[],
+		)?;
+
+		Ok(SecretKeyRotationBundle {
+			verification_key: StoredKey {
+				master_key: encrypted_master_key,
+				master_key_nonce,
+				key_nonce: root_key_nonce,
+				key: encrypted_root_key,
+				salt,
+				..verification_key
+			},
+			secret_key: Self::format_secret_key(&content_salt),
+		})
+	}
+
+	/// This is used internally to re-wrap a stored key's master key from one root key to another.
+	///
+	/// The key itself (and the content salt) are unchanged, so anything encrypted with it can still be decrypted.
+	#[allow(clippy::needless_pass_by_value)]
+	fn rewrap_stored_key(
+		old_root_key: &Protected<[u8; KEY_LEN]>,
+		new_root_key: Protected<[u8; KEY_LEN]>,
+		key: &StoredKey,
+	) -> Result<StoredKey> {
+		let master_key = Self::decrypt_backup_master_key(old_root_key, key)?;
+
+		// generate a new nonce
+		let master_key_nonce = generate_nonce(key.algorithm);
+
+		let salt = generate_salt();
+		let derived_key = derive_key(new_root_key, salt, ROOT_KEY_CONTEXT);
+
+		// encrypt the master key with the new root key
+		let encrypted_master_key = to_array(StreamEncryption::encrypt_bytes(
+			derived_key,
+			&master_key_nonce,
+			key.algorithm,
+			master_key.expose(),
+			&[],
+		)?)?;
+
+		let mut updated_key = key.clone();
+		updated_key.master_key_nonce = master_key_nonce;
+		updated_key.master_key = encrypted_master_key;
+		updated_key.salt = salt;
+
+		Ok(updated_key)
+	}
+
 	/// This re-encrypts master keys so they can be imported from a key backup into the current key manager.
 	///
 	/// It returns a `Vec<StoredKey>` so they can be written to Prisma
-	#[allow(clippy::needless_pass_by_value)]
 	pub fn import_keystore_backup(
 		&self,
 		master_password: Protected<String>, // at the time of the backup
 		secret_key: Protected<String>,      // at the time of the backup
 		stored_keys: &[StoredKey],          // from the backup
 	) -> Result<Vec<StoredKey>> {
+		let (old_root_key, _, keys) =
+			Self::unlock_keystore_backup(master_password, secret_key, stored_keys)?;
+
+		let mut reencrypted_keys = Vec::new();
+
+		for key in keys {
+			if self.keystore.contains_key(&key.uuid) {
+				continue;
+			}
+
+			// re-wrap the key with the current root key
+			let updated_key = Self::rewrap_stored_key(&old_root_key, self.get_root_key()?, &key)?;
+
+			reencrypted_keys.push(updated_key.clone());
+			self.keystore.insert(updated_key.uuid, updated_key);
+		}
+
+		Ok(reencrypted_keys)
+	}
+
+	/// This unlocks a key backup and compares it against the current keystore, without changing anything.
+	///
+	/// It can be used to preview what `KeyManager::import_keystore_backup()` would do.
+	pub fn diff_keystore_backup(
+		&self,
+		master_password: Protected<String>, // at the time of the backup
+		secret_key: Protected<String>,      // at the time of the backup
+		stored_keys: &[StoredKey],          // from the backup
+	) -> Result<KeystoreBackupDiff> {
+		let (old_root_key, old_verification_key, keys) =
+			Self::unlock_keystore_backup(master_password, secret_key, stored_keys)?;
+
+		let mut diff = KeystoreBackupDiff {
+			new_keys: Vec::new(),
+			duplicates: Vec::new(),
+			conflicting: Vec::new(),
+			verification_key_changed: self.get_verification_key()? != old_verification_key,
+		};
+
+		for key in keys {
+			// this also verifies that every key within the backup is able to be decrypted
+			let master_key = Self::decrypt_backup_master_key(&old_root_key, &key)?;
+
+			if !self.keystore.contains_key(&key.uuid) {
+				diff.new_keys.push(key.uuid);
+				continue;
+			}
+
+			let backup_key = StreamDecryption::decrypt_bytes(
+				master_key,
+				&key.key_nonce,
+				key.algorithm,
+				&key.key,
+				&[],
+			)?;
+
+			if self.get_key(key.uuid)?.expose() == backup_key.expose() {
+				diff.duplicates.push(key.uuid);
+			} else {
+				diff.conflicting.push(key.uuid);
+			}
+		}
+
+		Ok(diff)
+	}
+
+	/// This is used internally to retrieve the root key from a key backup.
+	///
+	/// It returns the backup's root key, the backup's verification key, and all other keys contained within the backup.
+	#[allow(clippy::needless_pass_by_value)]
+	#[allow(clippy::type_complexity)]
+	fn unlock_keystore_backup(
+		master_password: Protected<String>,
+		secret_key: Protected<String>,
+		stored_keys: &[StoredKey],
+	) -> Result<(Protected<[u8; KEY_LEN]>, StoredKey, Vec<StoredKey>)> {
 		// this backup should contain a verification key, which will tell us the algorithm+hashing algorithm
 		let master_password = Protected::new(master_password.expose().as_bytes().to_vec());
 		let secret_key = Self::convert_secret_key_string(secret_key);
@@ -392,52 +770,27 @@ impl KeyManager {
 
 		let old_root_key = Protected::new(to_array(old_root_key.expose().clone())?);
 
-		let mut reencrypted_keys = Vec::new();
-
-		for key in keys {
-			if self.keystore.contains_key(&key.uuid) {
-				continue;
-			}
-
-			let old_derived_key = derive_key(old_root_key.clone(), key.salt, ROOT_KEY_CONTEXT);
-
-			// decrypt the key's master key
-			let master_key = StreamDecryption::decrypt_bytes(
-				old_derived_key,
-				&key.master_key_nonce,
-				key.algorithm,
-				&key.master_key,
-				&[],
-			)
-			.map_or(Err(Error::IncorrectPassword), |v| {
-				Ok(Protected::new(to_array::<KEY_LEN>(v.expose().clone())?))
-			})?;
-
-			// generate a new nonce
-			let master_key_nonce = generate_nonce(key.algorithm);
-
-			let salt = generate_salt();
-			let derived_key = derive_key(self.get_root_key()?, salt, ROOT_KEY_CONTEXT);
-
-			// encrypt the master key with the current root key
-			let encrypted_master_key = to_array(StreamEncryption::encrypt_bytes(
-				derived_key,
-				&master_key_nonce,
-				key.algorithm,
-				master_key.expose(),
-				&[],
-			)?)?;
-
-			let mut updated_key = key.clone();
-			updated_key.master_key_nonce = master_key_nonce;
-			updated_key.master_key = encrypted_master_key;
-			updated_key.salt = salt;
-
-			reencrypted_keys.push(updated_key.clone());
-			self.keystore.insert(updated_key.uuid, updated_key);
-		}
+		Ok((old_root_key, old_verification_key, keys))
+	}
 
-		Ok(reencrypted_keys)
+	/// This is used internally to decrypt a backed-up key's master key, with the backup's root key.
+	fn decrypt_backup_master_key(
+		old_root_key: &Protected<[u8; KEY_LEN]>,
+		key: &StoredKey,
+	) -> Result<Protected<[u8; KEY_LEN]>> {
+		let old_derived_key = derive_key(old_root_key.clone(), key.salt, ROOT_KEY_CONTEXT);
+
+		// decrypt the key's master key
+		StreamDecryption::decrypt_bytes(
+			old_derived_key,
+			&key.master_key_nonce,
+			key.algorithm,
+			&key.master_key,
+			&[],
+		)
+		.map_or(Err(Error::IncorrectPassword), |v| {
+			Ok(Protected::new(to_array::<KEY_LEN>(v.expose().clone())?))
+		})
 	}
 
 	/// This requires both the master password and the secret key
@@ -491,6 +844,9 @@ impl KeyManager {
 			.clone(),
 		)?));
 
+		// unlocking counts as activity, otherwise the key manager could be locked straight away
+		self.touch()?;
+
 		Ok(())
 	}
 
@@ -502,6 +858,41 @@ impl KeyManager {
 	///
 	/// We could add a log to this, so that the user can view mounts
 	pub fn mount(&self, uuid: Uuid) -> Result<()> {
+		self.mount_with_source(uuid, MountSource::Manual)
+	}
+
+	/// This is the same as `KeyManager::mount()`, but it records what caused the key to be mounted.
+	///
+	/// Keys that have TOTP enabled can't be mounted this way, and need to be mounted with `KeyManager::mount_with_totp()` instead.
+	pub fn mount_with_source(&self, uuid: Uuid, source: MountSource) -> Result<()> {
+		if self.totp.contains_key(&uuid) {
+			return Err(Error::TotpRequired);
+		}
+
+		self.mount_inner(uuid, source)
+	}
+
+	/// This mounts a key that has TOTP enabled, once the provided code has been verified.
+	///
+	/// If a recovery code was used, the updated `StoredTotp` is returned so it can be written to the database.
+	pub fn mount_with_totp(
+		&self,
+		uuid: Uuid,
+		code: &TotpCode,
+		source: MountSource,
+	) -> Result<Option<StoredTotp>> {
+		if self.keymount.get(&uuid).is_some() {
+			return Err(Error::KeyAlreadyMounted);
+		}
+
+		let updated_totp = self.verify_totp(uuid, code)?;
+		self.mount_inner(uuid, source)?;
+
+		Ok(updated_totp)
+	}
+
+	/// This is used internally to mount a key, once it's been determined that it's allowed to be mounted.
+	fn mount_inner(&self, uuid: Uuid, source: MountSource) -> Result<()> {
 		if self.keymount.get(&uuid).is_some() {
 			return Err(Error::KeyAlreadyMounted);
 		}
@@ -540,6 +931,8 @@ impl KeyManager {
 				let mounted_key = MountedKey {
 					uuid: stored_key.uuid,
 					hashed_key,
+					mounted_at: SystemTime::now(),
+					source,
 				};
 
 				self.keymount.insert(uuid, mounted_key);
@@ -589,6 +982,38 @@ impl KeyManager {
 		}
 	}
 
+	/// This function checks whether a provided password matches a stored key, without mounting it.
+	///
+	/// The stored key is decrypted with the root key, so the master password needs to be present.
+	#[allow(clippy::needless_pass_by_value)]
+	pub fn validate_key(&self, uuid: Uuid, password: Protected<Vec<u8>>) -> Result<bool> {
+		let key = self.get_key(uuid)?;
+
+		Ok(key.expose() == password.expose())
+	}
+
+	/// This function exports a single key, wrapped with the provided passphrase.
+	///
+	/// The returned bytes are in the portable export format, see `ExportedKey` for more information.
+	///
+	/// The passphrase is hashed with the key's own hashing algorithm.
+	#[allow(clippy::needless_pass_by_value)]
+	pub fn export_key(&self, uuid: Uuid, passphrase: Protected<Vec<u8>>) -> Result<Vec<u8>> {
+		let stored_key = self.access_keystore(uuid)?;
+		let key = self.get_key(uuid)?;
+
+		let exported_key = ExportedKey::new(
+			stored_key.uuid,
+			stored_key.algorithm,
+			stored_key.hashing_algorithm,
+			stored_key.content_salt,
+			key,
+			passphrase,
+		)?;
+
+		Ok(exported_key.serialize())
+	}
+
 	/// This function is used to add a new key/password to the keystore.
 	///
 	/// You should use this when a new key is added, as it will generate salts/nonces/etc.
@@ -600,7 +1025,10 @@ impl KeyManager {
 	/// You may use the returned ID to identify this key.
 	///
 	/// You may optionally provide a content salt, if not one will be generated.
+	///
+	/// The source is only recorded, the key should already be in its final form (e.g. a keyfile's contents should be hashed with `hash_keyfile()`).
 	#[allow(clippy::needless_pass_by_value)]
+	#[allow(clippy::too_many_arguments)]
 	pub fn add_to_keystore(
 		&self,
 		key: Protected<Vec<u8>>,
@@ -609,16 +1037,185 @@ impl KeyManager {
 		memory_only: bool,
 		automount: bool,
 		content_salt: Option<[u8; SALT_LEN]>,
+		source: KeySource,
 	) -> Result<Uuid> {
 		let uuid = uuid::Uuid::new_v4();
 
+		let content_salt = content_salt.map_or(generate_salt(), |v| v);
+
+		let stored_key = StoredKey {
+			source,
+			..self.wrap_key(
+				uuid,
+				&key,
+				algorithm,
+				hashing_algorithm,
+				memory_only,
+				automount,
+				content_salt,
+			)?
+		};
+
+		// Insert it into the Keystore
+		self.keystore.insert(stored_key.uuid, stored_key);
+
+		// Return the ID so it can be identified
+		Ok(uuid)
+	}
+
+	/// This function is used to import a key that was exported with `KeyManager::export_key()`.
+	///
+	/// The key is decrypted with the provided passphrase (which also verifies it), and then re-wrapped with the current root key.
+	///
+	/// The UUID and content salt of the exported key are preserved.
+	///
+	/// An error will be returned if a key with the same UUID is already in the keystore.
+	///
+	/// Once imported, you will need to use `KeyManager::access_keystore()` to retrieve it and add it to Prisma.
+	#[allow(clippy::needless_pass_by_value)]
+	pub fn import_key(
+		&self,
+		exported_key: &[u8],
+		passphrase: Protected<Vec<u8>>,
+		memory_only: bool,
+		automount: bool,
+	) -> Result<Uuid> {
+		let exported_key = ExportedKey::deserialize(exported_key)?;
+
+		let key = exported_key.decrypt_key(passphrase)?;
+
+		if self.keystore.contains_key(&exported_key.uuid) {
+			return Err(Error::KeyAlreadyExists);
+		}
+
+		let stored_key = self.wrap_key(
+			exported_key.uuid,
+			&key,
+			exported_key.algorithm,
+			exported_key.hashing_algorithm,
+			memory_only,
+			automount,
+			exported_key.content_salt,
+		)?;
+
+		self.keystore.insert(stored_key.uuid, stored_key);
+
+		Ok(exported_key.uuid)
+	}
+
+	/// This function is used to copy a key from another key manager (e.g. another library on the same node) into this one.
+	///
+	/// The key is unwrapped with the other key manager's root key, and re-wrapped with this key manager's root key, so both need to be unlocked.
+	///
+	/// The UUID, content salt, name, source, and memory-only/automount status of the key are preserved.
+	///
+	/// An error will be returned if a key with the same UUID is already in the keystore.
+	///
+	/// Once copied, you will need to use `KeyManager::access_keystore()` to retrieve it and add it to Prisma.
+	pub fn copy_key_from(&self, other: &Self, uuid: Uuid) -> Result<Uuid> {
+		if self.keystore.contains_key(&uuid) {
+			return Err(Error::KeyAlreadyExists);
+		}
+
+		let other_stored_key = other.access_keystore(uuid)?;
+		let key = other.get_key(uuid)?;
+
+		let stored_key = StoredKey {
+			name: other_stored_key.name,
+			source: other_stored_key.source,
+			..self.wrap_key(
+				uuid,
+				&key,
+				other_stored_key.algorithm,
+				other_stored_key.hashing_algorithm,
+				other_stored_key.memory_only,
+				other_stored_key.automount,
+				other_stored_key.content_salt,
+			)?
+		};
+
+		self.keystore.insert(uuid, stored_key);
+
+		Ok(uuid)
+	}
+
+	/// This re-wraps an existing key with new parameters, while preserving its UUID and content salt.
+	///
+	/// If a new password is provided, it replaces the key's current value.
+	///
+	/// Changing the password or hashing algorithm changes the hashed key, so anything encrypted with the old one will no longer be decryptable with this key.
+	///
+	/// Nothing is changed within the key manager - the rotated key is returned, and it should be committed with `KeyManager::update_stored_key()` once it has been written to the database.
+	#[allow(clippy::needless_pass_by_value)]
+	pub fn rotate_key(
+		&self,
+		uuid: Uuid,
+		algorithm: Algorithm,
+		hashing_algorithm: HashingAlgorithm,
+		password: Option<Protected<Vec<u8>>>,
+	) -> Result<StoredKey> {
+		let stored_key = self.access_keystore(uuid)?;
+
+		let key = match password {
+			Some(password) => password,
+			None => self.get_key(uuid)?,
+		};
+
+		let rotated_key = self.wrap_key(
+			uuid,
+			&key,
+			algorithm,
+			hashing_algorithm,
+			stored_key.memory_only,
+			stored_key.automount,
+			stored_key.content_salt,
+		)?;
+
+		Ok(StoredKey {
+			name: stored_key.name,
+			source: stored_key.source,
+			..rotated_key
+		})
+	}
+
+	/// This replaces a key within the keystore with an updated version of itself (e.g. once it has been rotated).
+	///
+	/// If the key is currently mounted, it will be re-mounted so the hashed key reflects the new parameters.
+	pub fn update_stored_key(&self, stored_key: StoredKey) -> Result<()> {
+		if !self.keystore.contains_key(&stored_key.uuid) {
+			return Err(Error::KeyNotFound);
+		}
+
+		let uuid = stored_key.uuid;
+		self.keystore.insert(uuid, stored_key);
+
+		if let Some((_, mounted_key)) = self.keymount.remove(&uuid) {
+			// the key was already mounted, so there's no need to ask for another TOTP code
+			self.mount_inner(uuid, mounted_key.source)?;
+		}
+
+		Ok(())
+	}
+
+	/// This is used internally to wrap a plaintext key with the current root key.
+	///
+	/// It generates all of the salts/nonces/master keys, but does not insert the key into the keystore.
+	#[allow(clippy::too_many_arguments)]
+	fn wrap_key(
+		&self,
+		uuid: Uuid,
+		key: &Protected<Vec<u8>>,
+		algorithm: Algorithm,
+		hashing_algorithm: HashingAlgorithm,
+		memory_only: bool,
+		automount: bool,
+		content_salt: [u8; SALT_LEN],
+	) -> Result<StoredKey> {
 		// Generate items we'll need for encryption
 		let key_nonce = generate_nonce(algorithm);
 		let master_key = generate_master_key();
 		let master_key_nonce = generate_nonce(algorithm);
 
-		let content_salt = content_salt.map_or(generate_salt(), |v| v);
-
 		// salt used for the kdf
 		let salt = generate_salt();
 
@@ -635,10 +1232,10 @@ impl KeyManager {
 
 		// Encrypt the actual key (e.g. user-added/autogenerated, text-encodable)
 		let encrypted_key =
-			StreamEncryption::encrypt_bytes(master_key, &key_nonce, algorithm, &key, &[])?;
+			StreamEncryption::encrypt_bytes(master_key, &key_nonce, algorithm, key, &[])?;
 
 		// Construct the StoredKey
-		let stored_key = StoredKey {
+		Ok(StoredKey {
 			uuid,
 			algorithm,
 			hashing_algorithm,
@@ -650,13 +1247,9 @@ impl KeyManager {
 			salt,
 			memory_only,
 			automount,
-		};
-
-		// Insert it into the Keystore
-		self.keystore.insert(stored_key.uuid, stored_key);
-
-		// Return the ID so it can be identified
-		Ok(uuid)
+			name: None,
+			source: KeySource::Typed,
+		})
 	}
 
 	/// Used internally to convert from a hex-encoded `Protected<String>` to a `Protected<[u8; SALT_LEN]>` in a secretive manner.
@@ -700,11 +1293,50 @@ impl KeyManager {
 	///
 	/// We could add a log to this, so that the user can view accesses
 	pub fn access_keymount(&self, uuid: Uuid) -> Result<MountedKey> {
+		self.touch()?;
+
 		self.keymount
 			.get(&uuid)
 			.map_or(Err(Error::KeyNotFound), |v| Ok(v.clone()))
 	}
 
+	/// This returns a key's hashed key, which is what file keyslots are encrypted with.
+	///
+	/// If the key isn't mounted, it's hashed without being mounted (so this can be slow).
+	pub fn get_hashed_key(&self, uuid: Uuid) -> Result<Protected<[u8; KEY_LEN]>> {
+		if let Some(mounted_key) = self.keymount.get(&uuid) {
+			self.touch()?;
+			return Ok(mounted_key.hashed_key.clone());
+		}
+
+		let stored_key = self.access_keystore(uuid)?;
+
+		stored_key
+			.hashing_algorithm
+			.hash(self.get_key(uuid)?, stored_key.content_salt)
+	}
+
+	/// This keeps a key's previous hashed key in memory, so files that still use it can be re-wrapped once the key has been rotated.
+	///
+	/// Retired keys are never persisted, and they're cleared once the keymount is emptied.
+	pub fn retire_hashed_key(&self, uuid: Uuid, hashed_key: Protected<[u8; KEY_LEN]>) {
+		self.retired.entry(uuid).or_default().push(hashed_key);
+	}
+
+	/// This returns every retired hashed key for a key, with the most recent first.
+	#[must_use]
+	pub fn retired_hashed_keys(&self, uuid: Uuid) -> Vec<Protected<[u8; KEY_LEN]>> {
+		self.retired
+			.get(&uuid)
+			.map(|keys| keys.iter().rev().cloned().collect())
+			.unwrap_or_default()
+	}
+
+	/// This should be called once every file has been re-wrapped after a rotation.
+	pub fn clear_retired_keys(&self, uuid: Uuid) {
+		self.retired.remove(&uuid);
+	}
+
 	/// This function is for accessing a `StoredKey`.
 	pub fn access_keystore(&self, uuid: Uuid) -> Result<StoredKey> {
 		self.keystore
@@ -737,11 +1369,35 @@ impl KeyManager {
 			.map_or(Err(Error::NoDefaultKeySet), |_| Ok(()))
 	}
 
+	/// This derives a subkey from the root key, for encrypting library data that isn't tied to a specific key (e.g. object metadata).
+	///
+	/// The root key is unique to each library, so no salt is used. It's replaced whenever the master password is changed,
+	/// so anything encrypted with a subkey needs to be re-encrypted then.
+	pub fn derive_subkey(&self, context: &str) -> Result<Protected<[u8; KEY_LEN]>> {
+		Ok(derive_key(self.get_root_key()?, [0u8; SALT_LEN], context))
+	}
+
 	/// This should ONLY be used internally.
 	fn get_root_key(&self) -> Result<Protected<[u8; KEY_LEN]>> {
+		self.touch()?;
+
 		self.root_key.lock()?.clone().ok_or(Error::NoMasterPassword)
 	}
 
+	/// This is used internally to record key activity, whenever the root key or a mounted key is used.
+	fn touch(&self) -> Result<()> {
+		*self.last_activity.lock()? = Instant::now();
+
+		Ok(())
+	}
+
+	/// This returns how long it has been since the root key or any mounted key was last used.
+	///
+	/// It can be used for automatically locking the key manager after a period of inactivity.
+	pub fn idle_time(&self) -> Result<Duration> {
+		Ok(self.last_activity.lock()?.elapsed())
+	}
+
 	pub fn get_verification_key(&self) -> Result<StoredKey> {
 		self.verification_key
 			.lock()?
@@ -770,6 +1426,23 @@ impl KeyManager {
 		Ok(())
 	}
 
+	/// This function is for renaming a key within the keystore.
+	///
+	/// The key will need updating within the database too, so the two stay in sync.
+	pub fn rename_key(&self, uuid: Uuid, name: Option<String>) -> Result<()> {
+		let updated_key = self
+			.keystore
+			.get(&uuid)
+			.map_or(Err(Error::KeyNotFound), |v| {
+				let mut updated_key = v.clone();
+				updated_key.name = name;
+				Ok(updated_key)
+			})?;
+
+		self.keystore.insert(uuid, updated_key);
+		Ok(())
+	}
+
 	/// This function is for getting an entire collection of hashed keys.
 	///
 	/// These are ideal for passing over to decryption functions, as each decryption attempt is negligible, performance wise.
@@ -777,6 +1450,9 @@ impl KeyManager {
 	/// This means we don't need to keep super specific track of which key goes to which file, and we can just throw all of them at it.
 	#[must_use]
 	pub fn enumerate_hashed_keys(&self) -> Vec<Protected<[u8; KEY_LEN]>> {
+		// this can't return an error, and a poisoned lock here only means the activity isn't recorded
+		self.touch().ok();
+
 		self.keymount
 			.iter()
 			.map(|mounted_key| mounted_key.hashed_key.clone())
@@ -806,6 +1482,199 @@ impl KeyManager {
 		Ok(updated_key)
 	}
 
+	/// This function is for converting a saved key to a memory-only key, so it no longer syncs to the library.
+	///
+	/// The key will need removing from the database.
+	pub fn remove_from_database(&self, uuid: Uuid) -> Result<()> {
+		if self.is_memory_only(uuid)? {
+			return Err(Error::KeyAlreadyMemoryOnly);
+		}
+
+		let updated_key = self
+			.keystore
+			.get(&uuid)
+			.map_or(Err(Error::KeyNotFound), |v| {
+				let mut updated_key = v.clone();
+				updated_key.memory_only = true;
+				Ok(updated_key)
+			})?;
+
+		self.keystore.remove(&uuid);
+		self.keystore.insert(uuid, updated_key);
+
+		Ok(())
+	}
+
+	/// This starts enabling TOTP for a key, by generating a secret and a set of recovery codes.
+	///
+	/// Nothing is enforced until the enrollment has been confirmed with a valid code, see `KeyManager::confirm_totp()`.
+	pub fn enroll_totp(&self, uuid: Uuid) -> Result<TotpEnrollment> {
+		if !self.keystore.contains_key(&uuid) {
+			return Err(Error::KeyNotFound);
+		}
+
+		if self.totp.contains_key(&uuid) {
+			return Err(Error::TotpAlreadyEnabled);
+		}
+
+		let secret = generate_totp_secret();
+		let recovery_codes = generate_recovery_codes();
+		let stored_totp = StoredTotp::new(self.get_root_key()?, &secret, &recovery_codes)?;
+
+		self.pending_totp.insert(uuid, stored_totp.clone());
+
+		Ok(TotpEnrollment {
+			stored_totp,
+			secret,
+			recovery_codes,
+		})
+	}
+
+	/// This verifies a code against a pending TOTP enrollment.
+	///
+	/// Nothing is changed within the key manager - the returned `StoredTotp` should be committed with `KeyManager::set_totp()` once it has been written to the database.
+	pub fn confirm_totp(&self, uuid: Uuid, code: &str) -> Result<StoredTotp> {
+		let stored_totp = self
+			.pending_totp
+			.get(&uuid)
+			.map(|v| v.clone())
+			.ok_or(Error::TotpNotEnabled)?;
+
+		let secret = stored_totp.decrypt_secret(self.get_root_key()?)?;
+
+		match verify_code(secret.expose(), code)? {
+			Some(_) => Ok(stored_totp),
+			None => Err(Error::IncorrectTotpCode),
+		}
+	}
+
+	/// This verifies a code for a key that has TOTP enabled.
+	///
+	/// A code can't be used more than once, and a recovery code is removed once it's been used.
+	///
+	/// If a recovery code was used, the updated `StoredTotp` is returned so it can be written to the database.
+	pub fn verify_totp(&self, uuid: Uuid, code: &TotpCode) -> Result<Option<StoredTotp>> {
+		let mut gate = self.totp.get_mut(&uuid).ok_or(Error::TotpNotEnabled)?;
+
+		if let Some(locked_until) = gate.locked_until {
+			let now = Instant::now();
+
+			if locked_until > now {
+				return Err(Error::TotpLockedOut((locked_until - now).as_secs() + 1));
+			}
+		}
+
+		let result = match code {
+			TotpCode::Code(code) => {
+				let secret = gate.stored_totp.decrypt_secret(self.get_root_key()?)?;
+
+				match verify_code(secret.expose(), code)? {
+					Some(counter) if gate.last_counter.map_or(true, |last| counter > last) => {
+						gate.last_counter = Some(counter);
+						Ok(None)
+					}
+					_ => Err(Error::IncorrectTotpCode),
+				}
+			}
+			TotpCode::Recovery(code) => {
+				// this ensures the key manager is unlocked, as the code itself doesn't need decrypting
+				self.get_root_key()?;
+
+				if gate.stored_totp.take_recovery_code(code) {
+					Ok(Some(gate.stored_totp.clone()))
+				} else {
+					Err(Error::IncorrectTotpCode)
+				}
+			}
+		};
+
+		match result {
+			Ok(_) => {
+				gate.failures = 0;
+				gate.locked_until = None;
+			}
+			Err(Error::IncorrectTotpCode) => {
+				gate.failures += 1;
+
+				// the lockout doubles with every failure past the limit, so codes can't be brute-forced
+				if gate.failures >= TOTP_MAX_FAILURES {
+					let lockout = 2u64
+						.saturating_pow(gate.failures - TOTP_MAX_FAILURES)
+						.saturating_mul(TOTP_LOCKOUT)
+						.min(TOTP_MAX_LOCKOUT);
+
+					gate.locked_until = Some(Instant::now() + Duration::from_secs(lockout));
+				}
+			}
+			Err(_) => (),
+		}
+
+		result
+	}
+
+	/// This verifies a code for a key that has TOTP enabled, and generates a new set of recovery codes.
+	///
+	/// Nothing is changed within the key manager (other than using up the code) - the returned `StoredTotp` should be committed with `KeyManager::set_totp()` once it has been written to the database.
+	pub fn regenerate_recovery_codes(
+		&self,
+		uuid: Uuid,
+		code: &TotpCode,
+	) -> Result<(StoredTotp, Vec<Protected<String>>)> {
+		let mut stored_totp = self
+			.verify_totp(uuid, code)?
+			.map_or_else(|| self.get_totp(uuid), Ok)?;
+
+		let recovery_codes = stored_totp.regenerate_recovery_codes();
+
+		Ok((stored_totp, recovery_codes))
+	}
+
+	/// This enables (or disables, if `None` is provided) TOTP for a key.
+	///
+	/// This should only be called once the changes have been written to the database.
+	pub fn set_totp(&self, uuid: Uuid, stored_totp: Option<StoredTotp>) -> Result<()> {
+		if !self.keystore.contains_key(&uuid) {
+			return Err(Error::KeyNotFound);
+		}
+
+		self.pending_totp.remove(&uuid);
+
+		match stored_totp {
+			Some(stored_totp) => {
+				// the replay protection and any lockout carry over, as the secret itself may not have changed
+				let (last_counter, failures, locked_until) =
+					self.totp.get(&uuid).map_or((None, 0, None), |gate| {
+						(gate.last_counter, gate.failures, gate.locked_until)
+					});
+
+				self.totp.insert(
+					uuid,
+					TotpGate {
+						stored_totp,
+						last_counter,
+						failures,
+						locked_until,
+					},
+				);
+			}
+			None => {
+				self.totp.remove(&uuid);
+			}
+		}
+
+		Ok(())
+	}
+
+	pub fn get_totp(&self, uuid: Uuid) -> Result<StoredTotp> {
+		self.totp
+			.get(&uuid)
+			.map_or(Err(Error::TotpNotEnabled), |v| Ok(v.stored_totp.clone()))
+	}
+
+	pub fn is_totp_enabled(&self, uuid: Uuid) -> bool {
+		self.totp.contains_key(&uuid)
+	}
+
 	/// This function is for removing a previously-added master password
 	pub fn clear_root_key(&self) -> Result<()> {
 		*self.root_key.lock()? = None;
@@ -831,6 +1700,8 @@ impl KeyManager {
 	/// This function is used for emptying the entire keystore.
 	pub fn empty_keystore(&self) {
 		self.keystore.clear();
+		self.totp.clear();
+		self.pending_totp.clear();
 	}
 
 	/// This function is used for unmounting all keys at once.
@@ -839,6 +1710,7 @@ impl KeyManager {
 		// if it doesn't, we're going to need to find another way to call drop on these values
 		// that way they will be zeroized and removed from memory fully
 		self.keymount.clear();
+		self.retired.clear();
 	}
 
 	/// This function can be used for comparing an array of `StoredKeys` to the currently loaded keystore.
@@ -883,4 +1755,203 @@ impl KeyManager {
 	pub fn get_mounted_uuids(&self) -> Vec<Uuid> {
 		self.keymount.iter().map(|key| key.uuid).collect()
 	}
+
+	/// This returns the details of every mounted key, e.g. when and why it was mounted.
+	///
+	/// This doesn't count as key activity, so it's safe to poll.
+	#[must_use]
+	pub fn get_mount_details(&self) -> Vec<MountDetails> {
+		self.keymount
+			.iter()
+			.map(|key| MountDetails {
+				uuid: key.uuid,
+				mounted_at: key.mounted_at,
+				source: key.source,
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{keys::hashing::Params, primitives::MINIMUM_PASSPHRASE_CHARACTERS};
+
+	#[test]
+	fn onboarding_generates_unlockable_details() {
+		let bundle = KeyManager::onboarding(
+			Algorithm::XChaCha20Poly1305,
+			HashingAlgorithm::Argon2id(Params::Standard),
+			PassphraseStyle::default(),
+			None,
+		)
+		.unwrap();
+
+		assert_ne!(bundle.master_password.expose(), "password");
+		assert_ne!(
+			bundle.secret_key.expose(),
+			KeyManager::format_secret_key(b"0000000000000000").expose()
+		);
+
+		let key_manager = KeyManager::new(vec![bundle.verification_key]).unwrap();
+		key_manager
+			.set_master_password(bundle.master_password, bundle.secret_key)
+			.unwrap();
+
+		assert!(key_manager.has_master_password().unwrap());
+	}
+
+	#[test]
+	fn onboarding_uses_the_chosen_password() {
+		let master_password = Protected::new("correct horse battery staple".to_string());
+
+		let bundle = KeyManager::onboarding(
+			Algorithm::XChaCha20Poly1305,
+			HashingAlgorithm::Argon2id(Params::Standard),
+			PassphraseStyle::default(),
+			Some(master_password.clone()),
+		)
+		.unwrap();
+
+		assert_eq!(bundle.master_password.expose(), master_password.expose());
+
+		let key_manager = KeyManager::new(vec![bundle.verification_key]).unwrap();
+		key_manager
+			.set_master_password(master_password, bundle.secret_key)
+			.unwrap();
+
+		assert!(key_manager.has_master_password().unwrap());
+	}
+
+	#[test]
+	fn onboarding_generates_passwords_in_the_requested_style() {
+		let generate = |style| {
+			KeyManager::onboarding(
+				Algorithm::XChaCha20Poly1305,
+				HashingAlgorithm::Argon2id(Params::Standard),
+				style,
+				None,
+			)
+			.unwrap()
+			.master_password
+		};
+
+		let words = generate(PassphraseStyle::Words(9));
+		assert_eq!(words.expose().split('-').count(), 9);
+
+		// lengths below the minimum are raised to it
+		let characters = generate(PassphraseStyle::Characters(4));
+		assert_eq!(characters.expose().len(), MINIMUM_PASSPHRASE_CHARACTERS);
+	}
+
+	#[test]
+	fn master_password_change_is_only_applied_once_committed() {
+		let bundle = KeyManager::onboarding(
+			Algorithm::XChaCha20Poly1305,
+			HashingAlgorithm::Argon2id(Params::Standard),
+			PassphraseStyle::default(),
+			None,
+		)
+		.unwrap();
+
+		let key_manager = KeyManager::new(vec![bundle.verification_key]).unwrap();
+		key_manager
+			.set_master_password(bundle.master_password, bundle.secret_key)
+			.unwrap();
+
+		let old_subkey = key_manager.derive_subkey("test").unwrap();
+
+		let new_password = generate_passphrase(PassphraseStyle::default());
+		let change = key_manager
+			.change_master_password(
+				new_password.clone(),
+				Algorithm::XChaCha20Poly1305,
+				HashingAlgorithm::Argon2id(Params::Standard),
+				|_, _| {},
+			)
+			.unwrap();
+
+		assert_eq!(
+			key_manager.derive_subkey("test").unwrap().expose(),
+			old_subkey.expose()
+		);
+
+		key_manager.commit_master_password_change(&change).unwrap();
+		assert_eq!(
+			key_manager.derive_subkey("test").unwrap().expose(),
+			change.derive_subkey("test").expose()
+		);
+
+		let key_manager = KeyManager::new(vec![change.verification_key]).unwrap();
+		key_manager
+			.set_master_password(new_password, change.secret_key)
+			.unwrap();
+	}
+
+	#[test]
+	fn totp_codes_are_locked_out_after_too_many_failures() {
+		let bundle = KeyManager::onboarding(
+			Algorithm::XChaCha20Poly1305,
+			HashingAlgorithm::Argon2id(Params::Standard),
+			PassphraseStyle::default(),
+			None,
+		)
+		.unwrap();
+
+		let key_manager = KeyManager::new(vec![bundle.verification_key]).unwrap();
+		key_manager
+			.set_master_password(bundle.master_password, bundle.secret_key)
+			.unwrap();
+
+		let uuid = key_manager
+			.add_to_keystore(
+				Protected::new(
+					generate_passphrase(PassphraseStyle::default())
+						.expose()
+						.as_bytes()
+						.to_vec(),
+				),
+				Algorithm::XChaCha20Poly1305,
+				HashingAlgorithm::Argon2id(Params::Standard),
+				true,
+				false,
+				None,
+				KeySource::default(),
+			)
+			.unwrap();
+
+		let enrollment = key_manager.enroll_totp(uuid).unwrap();
+		key_manager
+			.set_totp(uuid, Some(enrollment.stored_totp))
+			.unwrap();
+
+		let wrong_code = TotpCode::Recovery("not a recovery code".to_string());
+
+		for _ in 0..TOTP_MAX_FAILURES {
+			assert!(matches!(
+				key_manager.mount_with_totp(uuid, &wrong_code, MountSource::Manual),
+				Err(Error::IncorrectTotpCode)
+			));
+		}
+
+		// even a correct code is refused while the key is locked out
+		let correct_code = TotpCode::Recovery(enrollment.recovery_codes[0].expose().clone());
+		assert!(matches!(
+			key_manager.mount_with_totp(uuid, &correct_code, MountSource::Manual),
+			Err(Error::TotpLockedOut(seconds)) if seconds <= TOTP_LOCKOUT + 1
+		));
+	}
+
+	#[test]
+	fn onboarding_rejects_weak_passwords() {
+		assert!(matches!(
+			KeyManager::onboarding(
+				Algorithm::XChaCha20Poly1305,
+				HashingAlgorithm::Argon2id(Params::Standard),
+				PassphraseStyle::default(),
+				Some(Protected::new("password".to_string())),
+			),
+			Err(Error::WeakPassword)
+		));
+	}
 }
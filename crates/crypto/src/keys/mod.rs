@@ -1,3 +1,7 @@
 //! This module contains all key and hashing related functions.
+pub mod export;
 pub mod hashing;
+pub mod keyfile;
 pub mod keymanager;
+pub mod session;
+pub mod totp;
@@ -0,0 +1,416 @@
+//! This module contains session keys, which are used for end-to-end encrypting transfers between paired devices (e.g. Spacedrop).
+//!
+//! Each device has a long-term X25519 identity keypair, and its public key is exchanged when the devices are paired.
+//!
+//! Both devices generate a fresh ephemeral keypair for every transfer, and the session key is derived from three Diffie-Hellman exchanges (ephemeral-ephemeral, and each device's ephemeral key against the other's identity key). This means that:
+//! - only the paired devices are able to derive the session key, as it requires one of their identity keys
+//! - past transfers can't be decrypted if an identity key is compromised later on, as the ephemeral keys are discarded
+//!
+//! The transfer itself is encrypted with the usual stream encryption, so it's secure regardless of the transport.
+//!
+//! # Examples
+//!
+//! ```rust,ignore
+//! // on the sending device (the receiver does the same, with `SessionRole::Receiver`)
+//! let ephemeral = EphemeralKeypair::generate();
+//!
+//! // `ephemeral.public_key()` is sent to the receiver, and the receiver's ephemeral public key is received
+//! let session_key = derive_session_key(
+//!     &identity,
+//!     ephemeral,
+//!     &receiver_identity,
+//!     &receiver_ephemeral,
+//!     SessionRole::Sender,
+//! )
+//! .unwrap();
+//!
+//! encrypt_transfer(session_key, &mut file, &mut stream, &aad).unwrap();
+//! ```
+use std::io::{self, Read, Write};
+
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::{
+	crypto::stream::{Algorithm, StreamDecryption, StreamEncryption},
+	primitives::{generate_master_key, generate_nonce, KEY_LEN, SESSION_KEY_CONTEXT},
+	Error, Protected, Result,
+};
+
+/// The length of an X25519 public key.
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+/// Transfers are always encrypted with XChaCha20-Poly1305, as its nonces are long enough to be randomly generated.
+const TRANSFER_ALGORITHM: Algorithm = Algorithm::XChaCha20Poly1305;
+
+/// This is a device's long-term identity keypair.
+pub struct IdentityKeypair {
+	secret: StaticSecret,
+	public: PublicKey,
+}
+
+/// This is a keypair that's generated for a single transfer, and it's consumed once the session key has been derived.
+///
+/// It uses a `StaticSecret` internally, as it's used for two of the exchanges.
+pub struct EphemeralKeypair {
+	secret: StaticSecret,
+	public: PublicKey,
+}
+
+/// This is which side of the transfer the current device is on, so both devices derive the same session key.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SessionRole {
+	Sender,
+	Receiver,
+}
+
+impl IdentityKeypair {
+	/// This generates a new identity keypair.
+	///
+	/// This function uses `ChaCha20Rng` for generating cryptographically-secure random data
+	#[must_use]
+	pub fn generate() -> Self {
+		Self::from_secret(&generate_master_key())
+	}
+
+	/// This restores an identity keypair from its secret key (e.g. once it's been loaded from the disk).
+	#[must_use]
+	pub fn from_secret(secret: &Protected<[u8; KEY_LEN]>) -> Self {
+		let secret = StaticSecret::from(*secret.expose());
+
+		Self {
+			public: PublicKey::from(&secret),
+			secret,
+		}
+	}
+
+	/// This returns the secret key, so the identity can be stored. It should always be stored encrypted.
+	#[must_use]
+	pub fn secret(&self) -> Protected<[u8; KEY_LEN]> {
+		Protected::new(self.secret.to_bytes())
+	}
+
+	#[must_use]
+	pub fn public_key(&self) -> [u8; PUBLIC_KEY_LEN] {
+		self.public.to_bytes()
+	}
+}
+
+impl EphemeralKeypair {
+	/// This generates a new ephemeral keypair, which should only ever be used for a single transfer.
+	///
+	/// This function uses `ChaCha20Rng` for generating cryptographically-secure random data
+	#[must_use]
+	pub fn generate() -> Self {
+		let secret = StaticSecret::from(*generate_master_key().expose());
+
+		Self {
+			public: PublicKey::from(&secret),
+			secret,
+		}
+	}
+
+	#[must_use]
+	pub fn public_key(&self) -> [u8; PUBLIC_KEY_LEN] {
+		self.public.to_bytes()
+	}
+}
+
+/// This derives the session key for a transfer, from our keypairs and the other device's public keys.
+///
+/// The other device's identity key should be the one that was exchanged during pairing, and never one that was provided alongside the transfer.
+///
+/// This will return an error if any of the exchanges aren't contributory (e.g. if the other device provided a low-order point).
+#[allow(clippy::needless_pass_by_value)] // the ephemeral keypair is consumed, so it can't be reused for another transfer
+pub fn derive_session_key(
+	identity: &IdentityKeypair,
+	ephemeral: EphemeralKeypair,
+	peer_identity: &[u8; PUBLIC_KEY_LEN],
+	peer_ephemeral: &[u8; PUBLIC_KEY_LEN],
+	role: SessionRole,
+) -> Result<Protected<[u8; KEY_LEN]>> {
+	let peer_identity = PublicKey::from(*peer_identity);
+	let peer_ephemeral = PublicKey::from(*peer_ephemeral);
+
+	let ephemeral_ephemeral = ephemeral.secret.diffie_hellman(&peer_ephemeral);
+	let ephemeral_identity = ephemeral.secret.diffie_hellman(&peer_identity);
+	let identity_ephemeral = identity.secret.diffie_hellman(&peer_ephemeral);
+
+	// everything is ordered from the sender's perspective, so both devices end up with the same key material
+	let (sender_receiver, receiver_sender, identities, ephemerals) = match role {
+		SessionRole::Sender => (
+			ephemeral_identity,
+			identity_ephemeral,
+			[identity.public, peer_identity],
+			[ephemeral.public, peer_ephemeral],
+		),
+		SessionRole::Receiver => (
+			identity_ephemeral,
+			ephemeral_identity,
+			[peer_identity, identity.public],
+			[peer_ephemeral, ephemeral.public],
+		),
+	};
+
+	if ![&ephemeral_ephemeral, &sender_receiver, &receiver_sender]
+		.iter()
+		.all(|shared| shared.was_contributory())
+	{
+		return Err(Error::SessionKey);
+	}
+
+	// the public keys are included, so the session key is bound to this exact exchange
+	let key_material = Protected::new(
+		[
+			ephemeral_ephemeral.as_bytes().as_slice(),
+			sender_receiver.as_bytes().as_slice(),
+			receiver_sender.as_bytes().as_slice(),
+			identities[0].as_bytes().as_slice(),
+			identities[1].as_bytes().as_slice(),
+			ephemerals[0].as_bytes().as_slice(),
+			ephemerals[1].as_bytes().as_slice(),
+		]
+		.concat(),
+	);
+
+	Ok(Protected::new(blake3::derive_key(
+		SESSION_KEY_CONTEXT,
+		key_material.expose(),
+	)))
+}
+
+/// This encrypts a transfer with its session key, and the nonce is written before the encrypted data.
+///
+/// The AAD should contain anything that the receiver needs to trust about the transfer, such as the file's name and size.
+///
+/// The writer should be a dedicated stream for the transfer, as the receiver relies on it ending after the final block.
+pub fn encrypt_transfer<R, W>(
+	session_key: Protected<[u8; KEY_LEN]>,
+	reader: R,
+	mut writer: W,
+	aad: &[u8],
+) -> Result<()>
+where
+	R: Read,
+	W: Write,
+{
+	let nonce = generate_nonce(TRANSFER_ALGORITHM);
+	writer.write_all(&nonce)?;
+
+	StreamEncryption::new(session_key, &nonce, TRANSFER_ALGORITHM)?.encrypt_streams(
+		FullReader(reader),
+		writer,
+		aad,
+	)
+}
+
+/// This decrypts a transfer that was encrypted with `encrypt_transfer()`.
+///
+/// The AAD must match what the sender used, otherwise an error will be returned.
+pub fn decrypt_transfer<R, W>(
+	session_key: Protected<[u8; KEY_LEN]>,
+	mut reader: R,
+	writer: W,
+	aad: &[u8],
+) -> Result<()>
+where
+	R: Read,
+	W: Write,
+{
+	let mut nonce = vec![0u8; TRANSFER_ALGORITHM.nonce_len()];
+	reader.read_exact(&mut nonce)?;
+
+	StreamDecryption::new(session_key, &nonce, TRANSFER_ALGORITHM)?.decrypt_streams(
+		FullReader(reader),
+		writer,
+		aad,
+	)
+}
+
+/// The stream functions treat a short read as the final block, but network streams can return less than was asked for at any point.
+///
+/// This keeps reading until the buffer is full, or the stream has ended.
+struct FullReader<R>(R);
+
+impl<R: Read> Read for FullReader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let mut read_count = 0;
+
+		while read_count < buf.len() {
+			match self.0.read(&mut buf[read_count..]) {
+				Ok(0) => break,
+				Ok(count) => read_count += count,
+				Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+				Err(e) => return Err(e),
+			}
+		}
+
+		Ok(read_count)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// This runs both sides of the key exchange, as the sender and receiver would.
+	fn exchange(
+		sender: &IdentityKeypair,
+		receiver: &IdentityKeypair,
+	) -> (Protected<[u8; KEY_LEN]>, Protected<[u8; KEY_LEN]>) {
+		let sender_ephemeral = EphemeralKeypair::generate();
+		let receiver_ephemeral = EphemeralKeypair::generate();
+
+		let sender_ephemeral_public = sender_ephemeral.public_key();
+		let receiver_ephemeral_public = receiver_ephemeral.public_key();
+
+		let sender_key = derive_session_key(
+			sender,
+			sender_ephemeral,
+			&receiver.public_key(),
+			&receiver_ephemeral_public,
+			SessionRole::Sender,
+		)
+		.unwrap();
+
+		let receiver_key = derive_session_key(
+			receiver,
+			receiver_ephemeral,
+			&sender.public_key(),
+			&sender_ephemeral_public,
+			SessionRole::Receiver,
+		)
+		.unwrap();
+
+		(sender_key, receiver_key)
+	}
+
+	#[test]
+	fn both_devices_derive_the_same_key() {
+		let sender = IdentityKeypair::generate();
+		let receiver = IdentityKeypair::generate();
+
+		let (sender_key, receiver_key) = exchange(&sender, &receiver);
+		assert_eq!(sender_key.expose(), receiver_key.expose());
+
+		// fresh ephemeral keys are used for every transfer, so the next one has a different key
+		let (next_key, _) = exchange(&sender, &receiver);
+		assert_ne!(sender_key.expose(), next_key.expose());
+	}
+
+	#[test]
+	fn other_devices_derive_a_different_key() {
+		let sender = IdentityKeypair::generate();
+		let receiver = IdentityKeypair::generate();
+		let impostor = IdentityKeypair::generate();
+
+		let sender_ephemeral = EphemeralKeypair::generate();
+		let receiver_ephemeral = EphemeralKeypair::generate();
+		let sender_ephemeral_public = sender_ephemeral.public_key();
+		let receiver_ephemeral_public = receiver_ephemeral.public_key();
+
+		let sender_key = derive_session_key(
+			&sender,
+			sender_ephemeral,
+			&receiver.public_key(),
+			&receiver_ephemeral_public,
+			SessionRole::Sender,
+		)
+		.unwrap();
+
+		// the impostor has the receiver's ephemeral key, but not its identity key
+		let impostor_key = derive_session_key(
+			&impostor,
+			receiver_ephemeral,
+			&sender.public_key(),
+			&sender_ephemeral_public,
+			SessionRole::Receiver,
+		)
+		.unwrap();
+
+		assert_ne!(sender_key.expose(), impostor_key.expose());
+	}
+
+	#[test]
+	fn identity_is_restored_from_its_secret() {
+		let identity = IdentityKeypair::generate();
+		let restored = IdentityKeypair::from_secret(&identity.secret());
+
+		assert_eq!(identity.public_key(), restored.public_key());
+	}
+
+	#[test]
+	fn transfer_round_trip() {
+		let (sender_key, receiver_key) =
+			exchange(&IdentityKeypair::generate(), &IdentityKeypair::generate());
+
+		let data = vec![7u8; 1_500_000];
+		let mut encrypted = Vec::new();
+		encrypt_transfer(sender_key, data.as_slice(), &mut encrypted, b"file.txt").unwrap();
+
+		let mut decrypted = Vec::new();
+		decrypt_transfer(
+			receiver_key,
+			encrypted.as_slice(),
+			&mut decrypted,
+			b"file.txt",
+		)
+		.unwrap();
+
+		assert_eq!(data, decrypted);
+	}
+
+	#[test]
+	fn transfer_with_wrong_aad_is_rejected() {
+		let (sender_key, receiver_key) =
+			exchange(&IdentityKeypair::generate(), &IdentityKeypair::generate());
+
+		let mut encrypted = Vec::new();
+		encrypt_transfer(
+			sender_key,
+			[1u8; 64].as_slice(),
+			&mut encrypted,
+			b"file.txt",
+		)
+		.unwrap();
+
+		let mut decrypted = Vec::new();
+		assert!(decrypt_transfer(
+			receiver_key,
+			encrypted.as_slice(),
+			&mut decrypted,
+			b"other.txt",
+		)
+		.is_err());
+	}
+
+	#[test]
+	fn low_order_points_are_rejected() {
+		let identity = IdentityKeypair::generate();
+		let peer = IdentityKeypair::generate();
+
+		// the identity point means every exchange with it results in zero
+		let low_order = [0u8; PUBLIC_KEY_LEN];
+
+		assert!(matches!(
+			derive_session_key(
+				&identity,
+				EphemeralKeypair::generate(),
+				&peer.public_key(),
+				&low_order,
+				SessionRole::Sender,
+			),
+			Err(Error::SessionKey)
+		));
+
+		assert!(matches!(
+			derive_session_key(
+				&identity,
+				EphemeralKeypair::generate(),
+				&low_order,
+				&EphemeralKeypair::generate().public_key(),
+				SessionRole::Receiver,
+			),
+			Err(Error::SessionKey)
+		));
+	}
+}
@@ -32,6 +32,19 @@ pub const KEY_LEN: usize = 32;
 
 pub const PASSPHRASE_LEN: usize = 7;
 
+/// These are the minimum lengths for generated passphrases, so they can't be requested with too little entropy.
+///
+/// 7 words from the EFF large wordlist is ~90 bits, and 16 characters from `PASSPHRASE_CHARSET` is ~105 bits.
+pub const MINIMUM_PASSPHRASE_WORDS: usize = 7;
+pub const MINIMUM_PASSPHRASE_CHARACTERS: usize = 16;
+
+/// This is the set of characters used for character-based passphrases (all printable ASCII, excluding whitespace)
+pub const PASSPHRASE_CHARSET: &[u8] =
+	b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+
+/// The minimum `zxcvbn` score (out of 4) that a user-chosen master password needs to reach.
+pub const MINIMUM_PASSWORD_SCORE: u8 = 3;
+
 pub const LATEST_FILE_HEADER: FileHeaderVersion = FileHeaderVersion::V1;
 pub const LATEST_KEYSLOT: KeyslotVersion = KeyslotVersion::V1;
 pub const LATEST_METADATA: MetadataVersion = MetadataVersion::V1;
@@ -41,6 +54,14 @@ pub const ROOT_KEY_CONTEXT: &str = "spacedrive 2022-12-14 12:53:54 root key deri
 pub const MASTER_PASSWORD_CONTEXT: &str =
 	"spacedrive 2022-12-14 15:35:41 master password hash derivation"; // used for deriving keys from the master password hash
 pub const FILE_KEY_CONTEXT: &str = "spacedrive 2022-12-14 12:54:12 file key derivation"; // used for deriving keys from user key/content salt hashes (for file encryption)
+pub const EXPORT_KEY_CONTEXT: &str = "spacedrive 2023-01-09 11:02:37 key export derivation"; // used for deriving keys from an export passphrase hash
+pub const KEYFILE_CONTEXT: &str = "spacedrive 2023-01-12 16:20:44 keyfile derivation"; // used for deriving keys from the contents of a keyfile
+pub const TOTP_SECRET_CONTEXT: &str = "spacedrive 2023-01-13 14:08:31 totp secret derivation"; // used for deriving keys from the root key (for encrypting TOTP secrets)
+pub const RECOVERY_CODE_CONTEXT: &str = "spacedrive 2023-01-13 14:09:02 recovery code hashing"; // used for hashing TOTP recovery codes
+pub const FILENAME_CONTEXT: &str = "spacedrive 2023-01-16 10:31:17 filename encryption"; // used for deriving keys from user key/content salt hashes (for encrypting file names)
+pub const THUMBNAIL_CONTEXT: &str = "spacedrive 2023-01-16 15:42:05 thumbnail encryption"; // used for deriving keys from user key/content salt hashes and the library ID (for encrypting cached thumbnails)
+pub const METADATA_CONTEXT: &str = "spacedrive 2023-01-17 09:12:48 metadata encryption"; // used for deriving keys from the root key (for encrypting object metadata within the library database)
+pub const SESSION_KEY_CONTEXT: &str = "spacedrive 2023-01-18 14:27:16 session key derivation"; // used for deriving session keys from key exchanges (for encrypting transfers between paired devices)
 
 /// This should be used for generating nonces for encryption.
 ///
@@ -107,33 +128,64 @@ pub fn to_array<const I: usize>(bytes: Vec<u8>) -> Result<[u8; I]> {
 	})
 }
 
-/// This generates a 7 word diceware passphrase, separated with `-`
+/// This defines the style of a generated passphrase, along with its length.
+///
+/// Lengths below `MINIMUM_PASSPHRASE_WORDS`/`MINIMUM_PASSPHRASE_CHARACTERS` are raised to the minimum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+	feature = "serde",
+	derive(serde::Serialize),
+	derive(serde::Deserialize)
+)]
+#[cfg_attr(feature = "rspc", derive(specta::Type))]
+pub enum PassphraseStyle {
+	Words(usize),      // diceware words, separated with `-`
+	Characters(usize), // random characters from `PASSPHRASE_CHARSET`
+}
+
+impl Default for PassphraseStyle {
+	fn default() -> Self {
+		Self::Words(PASSPHRASE_LEN)
+	}
+}
+
+/// This generates a passphrase in the requested style (e.g. a 7 word diceware passphrase, separated with `-`)
+#[must_use]
+pub fn generate_passphrase(style: PassphraseStyle) -> Protected<String> {
+	let mut rng = rand_chacha::ChaCha20Rng::from_entropy();
+
+	match style {
+		PassphraseStyle::Words(len) => {
+			let wordlist = include_str!("../assets/eff_large_wordlist.txt")
+				.lines()
+				.collect::<Vec<&str>>();
+
+			let words: Vec<&str> = wordlist
+				.choose_multiple(&mut rng, len.max(MINIMUM_PASSPHRASE_WORDS))
+				.copied()
+				.collect();
+
+			Protected::new(words.join("-"))
+		}
+		PassphraseStyle::Characters(len) => {
+			let passphrase = (0..len.max(MINIMUM_PASSPHRASE_CHARACTERS))
+				.map(|_| {
+					// the charset is never empty, so this is always `Some`
+					PASSPHRASE_CHARSET
+						.choose(&mut rng)
+						.map_or('-', |c| char::from(*c))
+				})
+				.collect();
+
+			Protected::new(passphrase)
+		}
+	}
+}
+
+/// This estimates the strength of a password, and returns a score from 0 to 4 (4 being the strongest).
+///
+/// It uses `zxcvbn`, so the score matches the password meter shown by the front end.
 #[must_use]
-pub fn generate_passphrase() -> Protected<String> {
-	let wordlist = include_str!("../assets/eff_large_wordlist.txt")
-		.lines()
-		.collect::<Vec<&str>>();
-
-	let words: Vec<String> = wordlist
-		.choose_multiple(
-			&mut rand_chacha::ChaCha20Rng::from_entropy(),
-			PASSPHRASE_LEN,
-		)
-		.map(ToString::to_string)
-		.collect();
-
-	let passphrase = words
-		.iter()
-		.enumerate()
-		.map(|(i, word)| {
-			if i < PASSPHRASE_LEN - 1 {
-				word.clone() + "-"
-			} else {
-				word.clone()
-			}
-		})
-		.into_iter()
-		.collect();
-
-	Protected::new(passphrase)
+pub fn password_strength(password: &Protected<String>) -> u8 {
+	zxcvbn::zxcvbn(password.expose(), &[]).map_or(0, |entropy| entropy.score())
 }
@@ -86,7 +86,7 @@ extension_category_enum! {
 
 // audio extensions
 extension_category_enum! {
-	AudioExtension _ALL_AUDIO_EXTENSIONS {
+	AudioExtension ALL_AUDIO_EXTENSIONS {
 		Mp3 = [0x49, 0x44, 0x33],
 		Mp2 = [0xFF, 0xFB] | [0xFF, 0xFD],
 		M4a = [0x66, 0x74, 0x79, 0x70, 0x4D, 0x34, 0x41, 0x20] + 4,
@@ -144,7 +144,7 @@ extension_category_enum! {
 
 // document extensions
 extension_category_enum! {
-	DocumentExtension _ALL_DOCUMENT_EXTENSIONS {
+	DocumentExtension ALL_DOCUMENT_EXTENSIONS {
 		Pdf = [0x25, 0x50, 0x44, 0x46, 0x2D],
 		Key = [0x50, 0x4B, 0x03, 0x04],
 		Pages = [0x50, 0x4B, 0x03, 0x04],
@@ -165,7 +165,7 @@ extension_category_enum! {
 
 // text file extensions
 extension_category_enum! {
-	TextExtension _ALL_TEXT_EXTENSIONS {
+	TextExtension ALL_TEXT_EXTENSIONS {
 		Txt,
 		Rtf,
 		Md,
@@ -3,57 +3,154 @@
 
 export type Procedures = {
     queries: 
+        { key: "albums.get", input: LibraryArgs<number>, result: Album } | 
+        { key: "albums.getExplorerData", input: LibraryArgs<number>, result: ExplorerData } | 
+        { key: "albums.list", input: LibraryArgs<null>, result: Array<Album> } | 
         { key: "buildInfo", input: never, result: BuildInfo } | 
-        { key: "files.get", input: LibraryArgs<GetArgs>, result: { id: number, cas_id: string, integrity_checksum: string | null, name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, date_modified: string, date_indexed: string, file_paths: Array<FilePath>, media_data: MediaData | null } | null } | 
+        { key: "files.get", input: LibraryArgs<GetArgs>, result: { id: number, cas_id: string, integrity_checksum: string | null, content_hash: string | null, content_hash_kind: number | null, name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, rating: number | null, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, encrypted_note: Array<number> | null, date_created: string, date_modified: string, date_indexed: string, file_paths: Array<FilePath>, media_data: MediaData | null } | null } | 
+        { key: "files.getAlbums", input: LibraryArgs<null>, result: Array<MusicAlbum> } | 
+        { key: "files.getByContentHash", input: LibraryArgs<GetByContentHashArgs>, result: Array<{ id: number, cas_id: string, integrity_checksum: string | null, content_hash: string | null, content_hash_kind: number | null, name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, rating: number | null, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, encrypted_note: Array<number> | null, date_created: string, date_modified: string, date_indexed: string, file_paths: Array<FilePath> }> } | 
+        { key: "files.getDuplicates", input: LibraryArgs<GetDuplicatesArgs>, result: DuplicatesReport } | 
+        { key: "files.getThumbnailCacheStats", input: LibraryArgs<null>, result: ThumbnailCacheStats } | 
+        { key: "files.listTrash", input: LibraryArgs<null>, result: Array<TrashedItem> } | 
+        { key: "files.listVersions", input: LibraryArgs<ListVersionsArgs>, result: Array<FileVersion> } | 
+        { key: "files.readVault", input: LibraryArgs<ReadVaultArgs>, result: VaultFile } | 
+        { key: "files.searchByAttribute", input: LibraryArgs<SearchByAttributeArgs>, result: Array<{ id: number, cas_id: string, integrity_checksum: string | null, content_hash: string | null, content_hash_kind: number | null, name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, rating: number | null, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, encrypted_note: Array<number> | null, date_created: string, date_modified: string, date_indexed: string, file_paths: Array<FilePath>, native_attributes: Array<NativeAttribute> }> } | 
+        { key: "files.searchMedia", input: LibraryArgs<SearchMediaArgs>, result: Array<{ id: number, cas_id: string, integrity_checksum: string | null, content_hash: string | null, content_hash_kind: number | null, name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, rating: number | null, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, encrypted_note: Array<number> | null, date_created: string, date_modified: string, date_indexed: string, file_paths: Array<FilePath>, media_data: MediaData | null }> } | 
+        { key: "jobs.getConcurrency", input: LibraryArgs<null>, result: JobConcurrency } | 
+        { key: "jobs.getDelegated", input: LibraryArgs<null>, result: Array<DelegatedJobReport> } | 
         { key: "jobs.getHistory", input: LibraryArgs<null>, result: Array<JobReport> } | 
+        { key: "jobs.getIoLimits", input: LibraryArgs<null>, result: IoLimits } | 
         { key: "jobs.getRunning", input: LibraryArgs<null>, result: Array<JobReport> } | 
+        { key: "jobs.getWaitingForKeys", input: LibraryArgs<null>, result: Array<WaitingJob> } | 
+        { key: "jobs.history", input: LibraryArgs<JobHistoryArgs>, result: JobHistory } | 
         { key: "jobs.isRunning", input: LibraryArgs<null>, result: boolean } | 
-        { key: "keys.getDefault", input: LibraryArgs<null>, result: string | null } | 
-        { key: "keys.getKey", input: LibraryArgs<string>, result: string } | 
+        { key: "jobs.logs", input: LibraryArgs<JobLogsArgs>, result: Array<JobLog> } | 
+        { key: "keys.auditLog", input: LibraryArgs<KeyAuditLogArgs>, result: KeyAuditLogPage } | 
+        { key: "keys.getAutoLock", input: LibraryArgs<null>, result: AutoLockConfig } | 
+        { key: "keys.getAutomountOrder", input: LibraryArgs<null>, result: Array<string> } | 
+        { key: "keys.getDefault", input: LibraryArgs<KeyDefaultContext | null>, result: string | null } | 
+        { key: "keys.getEncryptedFields", input: LibraryArgs<null>, result: Array<EncryptedField> } | 
+        { key: "keys.getKey", input: LibraryArgs<KeyGetArgs>, result: KeyValue } | 
+        { key: "keys.getPasswordHint", input: LibraryArgs<null>, result: string | null } | 
         { key: "keys.hasMasterPassword", input: LibraryArgs<null>, result: boolean } | 
+        { key: "keys.isOnboarded", input: LibraryArgs<null>, result: boolean } | 
         { key: "keys.list", input: LibraryArgs<null>, result: Array<StoredKey> } | 
-        { key: "keys.listMounted", input: LibraryArgs<null>, result: Array<string> } | 
+        { key: "keys.listApiTokens", input: never, result: Array<ApiTokenInfo> } | 
+        { key: "keys.listMounted", input: LibraryArgs<null>, result: Array<MountedKeyInfo> } | 
+        { key: "keys.listTotp", input: LibraryArgs<null>, result: Array<string> } | 
+        { key: "keys.stats", input: LibraryArgs<null>, result: KeystoreStats } | 
+        { key: "keys.validate", input: LibraryArgs<KeyValidateArgs>, result: boolean } | 
         { key: "library.getStatistics", input: LibraryArgs<null>, result: Statistics } | 
         { key: "library.list", input: never, result: Array<LibraryConfigWrapped> } | 
+        { key: "locations.encryption_rules.activity", input: LibraryArgs<EncryptionRuleActivityArgs>, result: Array<EncryptionRuleActivity> } | 
+        { key: "locations.encryption_rules.get", input: LibraryArgs<number>, result: EncryptionRule } | 
+        { key: "locations.encryption_rules.list", input: LibraryArgs<null>, result: Array<EncryptionRule> } | 
         { key: "locations.getById", input: LibraryArgs<number>, result: Location | null } | 
         { key: "locations.getExplorerData", input: LibraryArgs<LocationExplorerArgs>, result: ExplorerData } | 
+        { key: "locations.getGrowth", input: LibraryArgs<LocationGrowthArgs>, result: Array<LocationSnapshot> } | 
+        { key: "locations.getStatistics", input: LibraryArgs<LocationStatisticsArgs>, result: LocationStatistics } | 
         { key: "locations.indexer_rules.get", input: LibraryArgs<number>, result: IndexerRule } | 
         { key: "locations.indexer_rules.list", input: LibraryArgs<null>, result: Array<IndexerRule> } | 
-        { key: "locations.list", input: LibraryArgs<null>, result: Array<{ id: number, pub_id: Array<number>, node_id: number, name: string | null, local_path: string | null, total_capacity: number | null, available_capacity: number | null, filesystem: string | null, disk_type: number | null, is_removable: boolean | null, is_online: boolean, is_archived: boolean, date_created: string, node: Node }> } | 
+        { key: "locations.list", input: LibraryArgs<null>, result: Array<{ id: number, pub_id: Array<number>, node_id: number, name: string | null, local_path: string | null, total_capacity: number | null, available_capacity: number | null, filesystem: string | null, disk_type: number | null, is_removable: boolean | null, is_online: boolean, is_archived: boolean, date_created: string, vault_key_id: number | null, ignore_patterns: string | null, symlink_policy: number, versioning: boolean, max_versions: number | null, max_version_age: number | null, remote_config: string | null, remote_credentials: Array<number> | null, node: Node }> } | 
         { key: "nodeState", input: never, result: NodeState } | 
         { key: "normi.composite", input: never, result: NormalisedCompositeId } | 
         { key: "normi.org", input: never, result: NormalisedOrganisation } | 
         { key: "normi.user", input: never, result: NormalisedUser } | 
         { key: "normi.userSync", input: never, result: NormalisedUser } | 
         { key: "normi.version", input: never, result: string } | 
+        { key: "p2p.connectedPeers", input: never, result: Array<PeerInfo> } | 
+        { key: "p2p.discoveredPeers", input: never, result: Array<PeerInfo> } | 
+        { key: "savedSearches.get", input: LibraryArgs<number>, result: SavedSearch } | 
+        { key: "savedSearches.getExplorerData", input: LibraryArgs<SavedSearchExplorerArgs>, result: ExplorerData } | 
+        { key: "savedSearches.list", input: LibraryArgs<null>, result: Array<SavedSearch> } | 
+        { key: "schedules.list", input: LibraryArgs<null>, result: Array<Schedule> } | 
+        { key: "search.fullText", input: LibraryArgs<FullTextArgs>, result: Array<FullTextSearchResult> } | 
+        { key: "search.objects", input: LibraryArgs<ObjectSearch>, result: Array<{ id: number, cas_id: string, integrity_checksum: string | null, content_hash: string | null, content_hash_kind: number | null, name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, rating: number | null, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, encrypted_note: Array<number> | null, date_created: string, date_modified: string, date_indexed: string, file_paths: Array<FilePath> }> } | 
         { key: "tags.get", input: LibraryArgs<number>, result: Tag | null } | 
         { key: "tags.getExplorerData", input: LibraryArgs<number>, result: ExplorerData } | 
         { key: "tags.getForObject", input: LibraryArgs<number>, result: Array<Tag> } | 
         { key: "tags.list", input: LibraryArgs<null>, result: Array<Tag> } | 
+        { key: "tags.rules.get", input: LibraryArgs<number>, result: TagRule } | 
+        { key: "tags.rules.list", input: LibraryArgs<null>, result: Array<TagRule> } | 
         { key: "volumes.list", input: never, result: Array<Volume> },
     mutations: 
-        { key: "files.decryptFiles", input: LibraryArgs<FileDecryptorJobInit>, result: null } | 
+        { key: "albums.addObjects", input: LibraryArgs<AlbumObjectsArgs>, result: number } | 
+        { key: "albums.create", input: LibraryArgs<AlbumCreateArgs>, result: Album } | 
+        { key: "albums.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "albums.removeObjects", input: LibraryArgs<AlbumObjectsArgs>, result: bigint } | 
+        { key: "albums.reorder", input: LibraryArgs<AlbumReorderArgs>, result: null } | 
+        { key: "albums.setParent", input: LibraryArgs<AlbumSetParentArgs>, result: Album } | 
+        { key: "albums.update", input: LibraryArgs<AlbumUpdateArgs>, result: Album } | 
+        { key: "files.auditEncrypted", input: LibraryArgs<FileHeaderAuditorJobInit>, result: null } | 
+        { key: "files.closeStream", input: LibraryArgs<string>, result: null } | 
+        { key: "files.copyFiles", input: LibraryArgs<FileCopierJobInit>, result: null } | 
+        { key: "files.decrypt", input: LibraryArgs<FileDecryptorJobInit>, result: null } | 
         { key: "files.delete", input: LibraryArgs<number>, result: null } | 
-        { key: "files.encryptFiles", input: LibraryArgs<FileEncryptorJobInit>, result: null } | 
+        { key: "files.deleteFiles", input: LibraryArgs<FileDeleterJobInit>, result: null } | 
+        { key: "files.emptyTrash", input: LibraryArgs<EmptyTrashArgs>, result: null } | 
+        { key: "files.encrypt", input: LibraryArgs<FileEncryptorJobInit>, result: null } | 
+        { key: "files.encryptArchive", input: LibraryArgs<FileArchiverJobInit>, result: null } | 
+        { key: "files.moveFiles", input: LibraryArgs<FileMoverJobInit>, result: null } | 
+        { key: "files.openEncrypted", input: LibraryArgs<OpenEncryptedArgs>, result: TempFile } | 
+        { key: "files.renameFile", input: LibraryArgs<RenameFileArgs>, result: null } | 
+        { key: "files.resolveDuplicates", input: LibraryArgs<DuplicateResolverJobInit>, result: null } | 
+        { key: "files.restoreTrashed", input: LibraryArgs<number>, result: null } | 
+        { key: "files.restoreVersion", input: LibraryArgs<number>, result: null } | 
         { key: "files.setFavorite", input: LibraryArgs<SetFavoriteArgs>, result: null } | 
         { key: "files.setNote", input: LibraryArgs<SetNoteArgs>, result: null } | 
+        { key: "files.setRating", input: LibraryArgs<SetRatingArgs>, result: null } | 
+        { key: "files.setThumbnailCacheSize", input: LibraryArgs<bigint>, result: null } | 
+        { key: "files.shredDecrypted", input: LibraryArgs<string>, result: null } | 
+        { key: "files.streamEncrypted", input: LibraryArgs<StreamEncryptedArgs>, result: EncryptedStream } | 
+        { key: "files.verifyEncrypted", input: LibraryArgs<FileVerifierJobInit>, result: null } | 
+        { key: "jobs.cancel", input: LibraryArgs<string>, result: null } | 
         { key: "jobs.clearAll", input: LibraryArgs<null>, result: null } | 
         { key: "jobs.generateThumbsForLocation", input: LibraryArgs<GenerateThumbsForLocationArgs>, result: null } | 
         { key: "jobs.identifyUniqueFiles", input: LibraryArgs<IdentifyUniqueFilesArgs>, result: null } | 
         { key: "jobs.objectValidator", input: LibraryArgs<ObjectValidatorArgs>, result: null } | 
+        { key: "jobs.pause", input: LibraryArgs<string>, result: null } | 
+        { key: "jobs.resume", input: LibraryArgs<string>, result: null } | 
+        { key: "jobs.retryFailed", input: LibraryArgs<string>, result: null } | 
+        { key: "jobs.setConcurrency", input: LibraryArgs<JobConcurrency>, result: null } | 
+        { key: "jobs.setIoLimits", input: LibraryArgs<IoLimits>, result: null } | 
         { key: "keys.add", input: LibraryArgs<KeyAddArgs>, result: null } | 
         { key: "keys.backupKeystore", input: LibraryArgs<string>, result: null } | 
+        { key: "keys.backupKeystoreToBytes", input: LibraryArgs<null>, result: string } | 
+        { key: "keys.benchmarkHashing", input: LibraryArgs<null>, result: Array<HashingBenchmark> } | 
         { key: "keys.changeMasterPassword", input: LibraryArgs<MasterPasswordChangeArgs>, result: string } | 
+        { key: "keys.clearContextDefault", input: LibraryArgs<KeyDefaultContext>, result: null } | 
         { key: "keys.clearMasterPassword", input: LibraryArgs<null>, result: null } | 
+        { key: "keys.confirmTotp", input: LibraryArgs<TotpConfirmArgs>, result: null } | 
+        { key: "keys.copyToLibrary", input: LibraryArgs<KeyCopyArgs>, result: null } | 
+        { key: "keys.createApiToken", input: CreateApiTokenArgs, result: string } | 
         { key: "keys.deleteFromLibrary", input: LibraryArgs<string>, result: null } | 
-        { key: "keys.mount", input: LibraryArgs<string>, result: null } | 
+        { key: "keys.disableTotp", input: LibraryArgs<TotpArgs>, result: null } | 
+        { key: "keys.enrollTotp", input: LibraryArgs<string>, result: TotpEnrollmentInfo } | 
+        { key: "keys.export", input: LibraryArgs<KeyExportArgs>, result: string } | 
+        { key: "keys.exportAuditLog", input: LibraryArgs<KeyAuditLogExportArgs>, result: string } | 
+        { key: "keys.import", input: LibraryArgs<KeyImportArgs>, result: string } | 
+        { key: "keys.mount", input: LibraryArgs<KeyMountArgs>, result: string | null } | 
+        { key: "keys.mountMany", input: LibraryArgs<Array<string>>, result: Array<KeyBatchResult> } | 
         { key: "keys.onboarding", input: LibraryArgs<OnboardingArgs>, result: OnboardingKeys } | 
-        { key: "keys.restoreKeystore", input: LibraryArgs<RestoreBackupArgs>, result: number } | 
-        { key: "keys.setDefault", input: LibraryArgs<string>, result: null } | 
-        { key: "keys.setMasterPassword", input: LibraryArgs<SetMasterPasswordArgs>, result: null } | 
+        { key: "keys.regenerateRecoveryCodes", input: LibraryArgs<TotpArgs>, result: Array<string> } | 
+        { key: "keys.restoreKeystore", input: LibraryArgs<RestoreBackupArgs>, result: RestoreBackupResult } | 
+        { key: "keys.restoreKeystoreFromBytes", input: LibraryArgs<RestoreBackupBytesArgs>, result: RestoreBackupResult } | 
+        { key: "keys.restoreKeystoreFromRemote", input: LibraryArgs<RestoreBackupRemoteArgs>, result: RestoreBackupResult } | 
+        { key: "keys.revoke", input: LibraryArgs<KeyRevokeArgs>, result: string | null } | 
+        { key: "keys.revokeApiToken", input: string, result: null } | 
+        { key: "keys.rotate", input: LibraryArgs<KeyRotateArgs>, result: string | null } | 
+        { key: "keys.rotateSecretKey", input: LibraryArgs<SecretKeyRotationArgs>, result: string } | 
+        { key: "keys.setAutoLock", input: LibraryArgs<AutoLockConfig>, result: null } | 
+        { key: "keys.setAutomountOrder", input: LibraryArgs<Array<string>>, result: null } | 
+        { key: "keys.setDefault", input: LibraryArgs<KeySetDefaultArgs>, result: null } | 
+        { key: "keys.setEncryptedFields", input: LibraryArgs<Array<EncryptedField>>, result: null } | 
+        { key: "keys.setMasterPassword", input: LibraryArgs<SetMasterPasswordArgs>, result: AutomountResult } | 
         { key: "keys.syncKeyToLibrary", input: LibraryArgs<string>, result: null } | 
         { key: "keys.unmount", input: LibraryArgs<string>, result: null } | 
         { key: "keys.unmountAll", input: LibraryArgs<null>, result: null } | 
+        { key: "keys.unmountMany", input: LibraryArgs<Array<string>>, result: Array<KeyBatchResult> } | 
+        { key: "keys.unsyncFromLibrary", input: LibraryArgs<string>, result: null } | 
         { key: "keys.updateAutomountStatus", input: LibraryArgs<AutomountUpdateArgs>, result: null } | 
         { key: "keys.updateKeyName", input: LibraryArgs<KeyNameUpdateArgs>, result: null } | 
         { key: "library.create", input: string, result: LibraryConfigWrapped } | 
@@ -61,50 +158,168 @@ export type Procedures = {
         { key: "library.edit", input: EditLibraryArgs, result: null } | 
         { key: "locations.addLibrary", input: LibraryArgs<LocationCreateArgs>, result: null } | 
         { key: "locations.create", input: LibraryArgs<LocationCreateArgs>, result: null } | 
+        { key: "locations.createRemote", input: LibraryArgs<RemoteLocationCreateArgs>, result: null } | 
         { key: "locations.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "locations.encryption_rules.create", input: LibraryArgs<EncryptionRuleCreateArgs>, result: EncryptionRule } | 
+        { key: "locations.encryption_rules.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "locations.encryption_rules.update", input: LibraryArgs<EncryptionRuleUpdateArgs>, result: EncryptionRule } | 
         { key: "locations.fullRescan", input: LibraryArgs<number>, result: null } | 
         { key: "locations.indexer_rules.create", input: LibraryArgs<IndexerRuleCreateArgs>, result: IndexerRule } | 
         { key: "locations.indexer_rules.delete", input: LibraryArgs<number>, result: null } | 
-        { key: "locations.quickRescan", input: LibraryArgs<null>, result: null } | 
+        { key: "locations.quickRescan", input: LibraryArgs<number>, result: null } | 
         { key: "locations.relink", input: LibraryArgs<string>, result: null } | 
+        { key: "locations.transferRemote", input: LibraryArgs<RemoteTransferJobInit>, result: null } | 
         { key: "locations.update", input: LibraryArgs<LocationUpdateArgs>, result: null } | 
+        { key: "p2p.acceptPairingRequest", input: AcceptPairingRequestArgs, result: null } | 
+        { key: "p2p.pair", input: string, result: string } | 
+        { key: "savedSearches.create", input: LibraryArgs<SavedSearchCreateArgs>, result: SavedSearch } | 
+        { key: "savedSearches.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "savedSearches.update", input: LibraryArgs<SavedSearchUpdateArgs>, result: SavedSearch } | 
+        { key: "schedules.create", input: LibraryArgs<ScheduleCreateArgs>, result: Schedule } | 
+        { key: "schedules.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "schedules.runNow", input: LibraryArgs<number>, result: null } | 
+        { key: "schedules.update", input: LibraryArgs<ScheduleUpdateArgs>, result: Schedule } | 
         { key: "tags.assign", input: LibraryArgs<TagAssignArgs>, result: null } | 
+        { key: "tags.assignBulk", input: LibraryArgs<TagAssignBulkArgs>, result: string } | 
         { key: "tags.create", input: LibraryArgs<TagCreateArgs>, result: Tag } | 
         { key: "tags.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "tags.rules.apply", input: LibraryArgs<number | null>, result: null } | 
+        { key: "tags.rules.create", input: LibraryArgs<TagRuleCreateArgs>, result: TagRule } | 
+        { key: "tags.rules.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "tags.rules.update", input: LibraryArgs<TagRuleUpdateArgs>, result: TagRule } | 
+        { key: "tags.setParent", input: LibraryArgs<TagSetParentArgs>, result: Tag } | 
+        { key: "tags.undoBulk", input: LibraryArgs<string>, result: string } | 
         { key: "tags.update", input: LibraryArgs<TagUpdateArgs>, result: null },
     subscriptions: 
         { key: "invalidateQuery", input: never, result: InvalidateOperationEvent } | 
-        { key: "jobs.newThumbnail", input: LibraryArgs<null>, result: string }
+        { key: "jobs.events", input: JobEventFilter, result: JobReport } | 
+        { key: "jobs.newThumbnail", input: LibraryArgs<null>, result: string } | 
+        { key: "keys.changeMasterPasswordProgress", input: LibraryArgs<null>, result: [number, number] } | 
+        { key: "keys.lockState", input: LibraryArgs<null>, result: boolean } | 
+        { key: "keys.lockingSoon", input: LibraryArgs<null>, result: bigint } | 
+        { key: "p2p.pairingRequests", input: never, result: PairingRequest }
 };
 
+export interface AcceptPairingRequestArgs { peer_id: string, preshared_key: string }
+
+export interface Album { id: number, pub_id: Array<number>, name: string, is_hidden: boolean, date_created: string, date_modified: string, parent_id: number | null }
+
+export interface AlbumCreateArgs { name: string, parent_id: number | null }
+
+export interface AlbumObjectsArgs { album_id: number, object_ids: Array<number> }
+
+export interface AlbumReorderArgs { album_id: number, object_ids: Array<number> }
+
+export interface AlbumSetParentArgs { id: number, parent_id: number | null }
+
+export interface AlbumUpdateArgs { id: number, name: string | null, is_hidden: boolean | null }
+
 export type Algorithm = "XChaCha20Poly1305" | "Aes256Gcm"
 
+export interface ApiToken { id: string, name: string, capabilities: Array<KeyCapability>, hash: string }
+
+export interface ApiTokenInfo { id: string, name: string, capabilities: Array<KeyCapability> }
+
+export interface AutoLockConfig { minutes: number | null, warning_seconds: number, lock_on_sleep: boolean, lock_on_screensaver: boolean }
+
+export interface AutomountFailure { uuid: string, error: string }
+
+export interface AutomountResult { mounted: Array<string>, failed: Array<AutomountFailure>, jobs: Array<string> }
+
 export interface AutomountUpdateArgs { uuid: string, status: boolean }
 
 export interface BuildInfo { version: string, commit: string }
 
+export type BulkTagTargets = { Objects: Array<number> } | { Search: ObjectSearch }
+
 export interface ConfigMetadata { version: string | null }
 
+export type ConflictStrategy = "Skip" | "Overwrite" | "Rename" | "Error"
+
+export interface ContextDefaultKey { context: KeyDefaultContext, key_uuid: string }
+
+export interface CreateApiTokenArgs { name: string, capabilities: Array<KeyCapability> }
+
+export interface DateRange { from: string | null, to: string | null }
+
+export interface DelegatedJobReport { node_id: string, report: JobReport }
+
+export type DuplicateAction = "Delete" | "ReplaceWithLink" | { MoveToReview: string }
+
+export interface DuplicateFile { location_id: number, file_path_id: number, object_id: number, path: string }
+
+export interface DuplicateFileRef { location_id: number, file_path_id: number }
+
+export interface DuplicateGroup { content_hash: string, size_in_bytes: bigint, wasted_bytes: bigint, files: Array<DuplicateFile> }
+
+export interface DuplicateResolution { keep: DuplicateFileRef, duplicates: Array<DuplicateFileRef> }
+
+export interface DuplicateResolverJobInit { resolutions: Array<DuplicateResolution>, action: DuplicateAction }
+
+export interface DuplicatesReport { groups: Array<DuplicateGroup>, total_wasted_bytes: bigint }
+
 export interface EditLibraryArgs { id: string, name: string | null, description: string | null }
 
-export type ExplorerContext = { type: "Location" } & Location | { type: "Tag" } & Tag
+export interface EmptyTrashArgs { ids: Array<number> | null }
+
+export type EncryptedField = "Note" | "GpsLocation"
+
+export interface EncryptedStream { id: string, content_type: string }
+
+export interface EncryptionRule { id: number, name: string, glob: string, location_id: number | null, key_id: number, algorithm: Array<number>, erase_original: boolean, date_created: string, date_modified: string }
+
+export interface EncryptionRuleActivity { id: number, path: string, location_id: number, outcome: number, rule_id: number | null, error: string | null, date_created: string }
+
+export interface EncryptionRuleActivityArgs { rule_id: number | null, skip: bigint | null, take: bigint | null }
+
+export interface EncryptionRuleCreateArgs { name: string, glob: string, location_id: number | null, key_uuid: string, algorithm: Algorithm, erase_original: boolean }
+
+export interface EncryptionRuleUpdateArgs { id: number, name: string | null, glob: string | null, erase_original: boolean | null }
+
+export type ExplorerContext = { type: "Location" } & Location | { type: "Tag" } & Tag | { type: "SavedSearch" } & SavedSearch | { type: "Album" } & Album
 
 export interface ExplorerData { context: ExplorerContext, items: Array<ExplorerItem> }
 
-export type ExplorerItem = { type: "Path" } & { id: number, is_dir: boolean, location_id: number, materialized_path: string, name: string, extension: string | null, object_id: number | null, parent_id: number | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string, object: Object | null } | { type: "Object" } & { id: number, cas_id: string, integrity_checksum: string | null, name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, date_modified: string, date_indexed: string, file_paths: Array<FilePath> }
+export type ExplorerItem = { type: "Path" } & { id: number, is_dir: boolean, location_id: number, materialized_path: string, name: string, extension: string | null, object_id: number | null, parent_id: number | null, is_symlink: boolean, size_in_bytes: string | null, inode: string | null, fingerprint: string | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string, object: Object | null } | { type: "Object" } & { id: number, cas_id: string, integrity_checksum: string | null, content_hash: string | null, content_hash_kind: number | null, name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, rating: number | null, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, encrypted_note: Array<number> | null, date_created: string, date_modified: string, date_indexed: string, file_paths: Array<FilePath> }
+
+export interface FileArchiverJobInit { location_id: number, path_id: number, key_uuid: string, algorithm: Algorithm, output_directory: string | null, on_conflict: ConflictStrategy }
+
+export interface FileCopierJobInit { location_id: number, targets: Array<ObjectTarget>, target_location_id: number, target_directory_id: number | null, on_conflict: ConflictStrategy }
+
+export interface FileDecryptorJobInit { location_id: number, targets: Array<ObjectTarget>, output_directory: string | null, on_conflict: ConflictStrategy, password: string | null, save_to_library: boolean | null, only_file_paths: Array<number> | null }
+
+export interface FileDeleterJobInit { location_id: number, targets: Array<ObjectTarget>, permanent: boolean }
 
-export interface FileDecryptorJobInit { location_id: number, object_id: number, output_path: string | null, password: string | null, save_to_library: boolean | null }
+export interface FileEncryptorJobInit { location_id: number, targets: Array<ObjectTarget>, key_uuid: string, algorithm: Algorithm, metadata: boolean, preview_media: boolean, output_directory: string | null, on_conflict: ConflictStrategy, erase_original: boolean, encrypt_filename: boolean, random_filename: boolean, dry_run: boolean, only_file_paths: Array<number> | null, rule_id: number | null, upload: RemoteUpload | null }
 
-export interface FileEncryptorJobInit { location_id: number, object_id: number, key_uuid: string, algorithm: Algorithm, metadata: boolean, preview_media: boolean, output_path: string | null }
+export interface FileHeaderAuditorJobInit { location_ids: Array<number> }
 
-export interface FilePath { id: number, is_dir: boolean, location_id: number, materialized_path: string, name: string, extension: string | null, object_id: number | null, parent_id: number | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string }
+export interface FileMoverJobInit { location_id: number, targets: Array<ObjectTarget>, target_location_id: number, target_directory_id: number | null, new_name: string | null, on_conflict: ConflictStrategy }
+
+export interface FilePath { id: number, is_dir: boolean, location_id: number, materialized_path: string, name: string, extension: string | null, object_id: number | null, parent_id: number | null, is_symlink: boolean, size_in_bytes: string | null, inode: string | null, fingerprint: string | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string }
+
+export interface FileVerifierJobInit { location_ids: Array<number> }
+
+export interface FileVersion { id: number, materialized_path: string, location_id: number, content_hash: string, size_in_bytes: string, date_modified: string, date_created: string }
+
+export interface FullTextArgs { query: string, take: number | null, skip: number | null }
+
+export interface FullTextSearchResult { object: { id: number, cas_id: string, integrity_checksum: string | null, content_hash: string | null, content_hash_kind: number | null, name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, rating: number | null, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, encrypted_note: Array<number> | null, date_created: string, date_modified: string, date_indexed: string, file_paths: Array<FilePath> }, score: number, snippet: string }
 
 export interface GenerateThumbsForLocationArgs { id: number, path: string }
 
+export interface GeoBounds { min_longitude: number, max_longitude: number, min_latitude: number, max_latitude: number }
+
 export interface GetArgs { id: number }
 
+export interface GetByContentHashArgs { content_hashes: Array<string> }
+
+export interface GetDuplicatesArgs { location_ids: Array<number> | null }
+
 export type HashingAlgorithm = { Argon2id: Params }
 
+export interface HashingBenchmark { hashing_algorithm: HashingAlgorithm, memory_cost: number, milliseconds: bigint, recommended: boolean }
+
 export interface IdentifyUniqueFilesArgs { id: number, path: string }
 
 export interface IndexerRule { id: number, kind: number, name: string, parameters: Array<number>, date_created: string, date_modified: string }
@@ -113,37 +328,125 @@ export interface IndexerRuleCreateArgs { kind: RuleKind, name: string, parameter
 
 export interface InvalidateOperationEvent { key: string, arg: any }
 
-export interface JobReport { id: string, name: string, data: Array<number> | null, metadata: any | null, date_created: string, date_modified: string, status: JobStatus, task_count: number, completed_task_count: number, message: string, seconds_elapsed: number }
+export interface IoLimits { read: bigint | null, write: bigint | null, upload: bigint | null }
+
+export interface JobConcurrency { max_workers: number, limits: Record<string, number> }
+
+export interface JobEventFilter { library_ids: Array<string> | null, job_names: Array<string> | null, job_ids: Array<string> | null }
+
+export interface JobHistory { jobs: Array<JobReport>, total: JobStats, by_name: Record<string, JobStats> }
+
+export interface JobHistoryArgs { name: string | null, status: JobStatus | null, since: string | null, until: string | null, skip: bigint | null, take: bigint | null }
+
+export interface JobLog { id: number, level: JobLogLevel, message: string, path: string | null, date_created: string }
+
+export type JobLogLevel = "Info" | "Warning" | "Error"
+
+export interface JobLogsArgs { job_id: string, skip: bigint | null, take: bigint | null }
+
+export interface JobReport { id: string, name: string, data: Array<number> | null, checkpoint: Array<number> | null, next_jobs: Array<number> | null, metadata: any | null, date_created: string, date_modified: string, status: JobStatus, task_count: number, completed_task_count: number, message: string, seconds_elapsed: number, bytes_processed: bigint, transfer: TransferProgress | null }
+
+export interface JobStats { count: bigint, completed: bigint, canceled: bigint, failed: bigint, seconds_elapsed: bigint, tasks_completed: bigint, bytes_processed: bigint }
 
 export type JobStatus = "Queued" | "Running" | "Completed" | "Canceled" | "Failed" | "Paused"
 
-export interface KeyAddArgs { algorithm: Algorithm, hashing_algorithm: HashingAlgorithm, key: string, library_sync: boolean, automount: boolean }
+export interface KeyAddArgs { algorithm: Algorithm, hashing_algorithm: HashingAlgorithm, key: KeyInput, library_sync: boolean, automount: boolean }
+
+export type KeyAuditAction = "Unknown" | "Unlock" | "Lock" | "Mount" | "Unmount" | "Reveal" | "Add" | "Delete" | "Export" | "Backup" | "Restore" | "MasterPasswordChange" | "SecretKeyRotation" | "TotpEnable" | "TotpDisable" | "TotpRecovery"
+
+export interface KeyAuditLogArgs { filter: KeyAuditLogFilter, skip: bigint | null, take: bigint | null }
+
+export interface KeyAuditLogEntry { id: number, action: KeyAuditAction, key_uuid: string | null, node: LibraryNode, date_created: string }
+
+export interface KeyAuditLogExportArgs { filter: KeyAuditLogFilter, format: KeyAuditLogFormat }
+
+export interface KeyAuditLogFilter { from: string | null, to: string | null, key_uuid: string | null }
+
+export type KeyAuditLogFormat = "Json" | "Csv"
+
+export interface KeyAuditLogPage { entries: Array<KeyAuditLogEntry>, total: bigint }
+
+export interface KeyBatchResult { uuid: string, error: string | null }
+
+export type KeyCapability = "Read" | "Mount" | "Unlock" | "Reveal" | "Export" | "Manage" | "ChangeMasterPassword"
+
+export interface KeyCopyArgs { key_uuid: string, target_library: string }
+
+export type KeyDefaultContext = { Location: number } | { Tag: number }
+
+export interface KeyExportArgs { uuid: string, password: string }
+
+export interface KeyGetArgs { uuid: string, reveal: boolean, totp: TotpCode | null }
+
+export interface KeyImportArgs { key: string, password: string, library_sync: boolean, automount: boolean }
+
+export type KeyInput = { encoding: "Utf8", value: string } | { encoding: "Hex", value: string } | { encoding: "Base64", value: string } | { encoding: "Bytes", value: Array<number> } | { encoding: "KeyfilePath", value: string } | { encoding: "Keyfile", value: Array<number> }
+
+export interface KeyMountArgs { uuid: string, decrypt_headers: boolean, totp: TotpCode | null }
 
 export interface KeyNameUpdateArgs { uuid: string, name: string }
 
-export interface LibraryArgs<T> { library_id: string, arg: T }
+export interface KeyRevokeArgs { uuid: string, replacement: string }
+
+export interface KeyRotateArgs { uuid: string, algorithm: Algorithm, hashing_algorithm: HashingAlgorithm, password: string | null }
+
+export interface KeySetDefaultArgs { uuid: string, context: KeyDefaultContext | null }
+
+export type KeySource = "Typed" | "Keyfile"
 
-export interface LibraryConfig { version: string | null, name: string, description: string }
+export interface KeyValidateArgs { uuid: string, password: string }
+
+export interface KeyValue { value: string, masked: boolean }
+
+export interface KeystoreBackupDiff { new_keys: Array<string>, duplicates: Array<string>, conflicting: Array<string>, verification_key_changed: boolean }
+
+export interface KeystoreStats { total: number, mounted: number, memory_only: number, algorithms: Array<[Algorithm, number]>, hashing_algorithms: Array<[HashingAlgorithm, number]>, last_backup: string | null, unused_keys: Array<string> }
+
+export interface KindStatistics { kind: number, file_count: bigint, total_size: bigint }
+
+export interface LibraryArgs<T> { library_id: string, arg: T, token: string | null }
+
+export interface LibraryConfig { version: string | null, name: string, description: string, auto_lock: AutoLockConfig, last_keystore_backup: string | null, master_password_hint: string | null, context_default_keys: Array<ContextDefaultKey>, encrypted_fields: Array<EncryptedField> }
 
 export interface LibraryConfigWrapped { uuid: string, config: LibraryConfig }
 
-export interface Location { id: number, pub_id: Array<number>, node_id: number, name: string | null, local_path: string | null, total_capacity: number | null, available_capacity: number | null, filesystem: string | null, disk_type: number | null, is_removable: boolean | null, is_online: boolean, is_archived: boolean, date_created: string }
+export interface LibraryNode { uuid: string, name: string, platform: Platform, last_seen: string }
+
+export interface ListVersionsArgs { location_id: number, materialized_path: string }
+
+export interface Location { id: number, pub_id: Array<number>, node_id: number, name: string | null, local_path: string | null, total_capacity: number | null, available_capacity: number | null, filesystem: string | null, disk_type: number | null, is_removable: boolean | null, is_online: boolean, is_archived: boolean, date_created: string, vault_key_id: number | null, ignore_patterns: string | null, symlink_policy: number, versioning: boolean, max_versions: number | null, max_version_age: number | null, remote_config: string | null, remote_credentials: Array<number> | null }
 
-export interface LocationCreateArgs { path: string, indexer_rules_ids: Array<number> }
+export interface LocationCreateArgs { path: string, indexer_rules_ids: Array<number>, vault_key: string | null }
 
 export interface LocationExplorerArgs { location_id: number, path: string, limit: number, cursor: string | null }
 
-export interface LocationUpdateArgs { id: number, name: string | null, indexer_rules_ids: Array<number> }
+export interface LocationGrowthArgs { location_id: number, since: string | null }
+
+export interface LocationSnapshot { id: number, location_id: number, total_size: string, file_count: number, directory_count: number, date_created: string }
+
+export interface LocationStatistics { location_id: number, total_size: bigint, file_count: bigint, directory_count: bigint, by_kind: Array<KindStatistics>, largest_files: Array<{ id: number, is_dir: boolean, location_id: number, materialized_path: string, name: string, extension: string | null, object_id: number | null, parent_id: number | null, is_symlink: boolean, size_in_bytes: string | null, inode: string | null, fingerprint: string | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string, object: Object | null }>, recently_added: Array<{ id: number, is_dir: boolean, location_id: number, materialized_path: string, name: string, extension: string | null, object_id: number | null, parent_id: number | null, is_symlink: boolean, size_in_bytes: string | null, inode: string | null, fingerprint: string | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string, object: Object | null }> }
 
-export interface MasterPasswordChangeArgs { password: string, algorithm: Algorithm, hashing_algorithm: HashingAlgorithm }
+export interface LocationStatisticsArgs { location_id: number, limit: number | null }
 
-export interface MediaData { id: number, pixel_width: number | null, pixel_height: number | null, longitude: number | null, latitude: number | null, fps: number | null, capture_device_make: string | null, capture_device_model: string | null, capture_device_software: string | null, duration_seconds: number | null, codecs: string | null, streams: number | null }
+export interface LocationUpdateArgs { id: number, name: string | null, indexer_rules_ids: Array<number>, ignore_patterns: string | null, symlink_policy: SymlinkPolicy | null, versioning: boolean | null, version_retention: VersionRetention | null }
+
+export interface MasterPasswordChangeArgs { password: string, algorithm: Algorithm, hashing_algorithm: HashingAlgorithm, hint: string | null }
+
+export interface MediaData { id: number, pixel_width: number | null, pixel_height: number | null, longitude: number | null, latitude: number | null, encrypted_location: Array<number> | null, fps: number | null, capture_device_make: string | null, capture_device_model: string | null, capture_device_software: string | null, capture_date: string | null, orientation: number | null, duration_seconds: number | null, codecs: string | null, container: string | null, title: string | null, artist: string | null, album: string | null, album_artist: string | null, genre: string | null, track_number: number | null, year: number | null, streams: number | null }
+
+export type MountSource = "Manual" | "Automount" | "Job"
+
+export interface MountedKeyInfo { uuid: string, mounted_at: string, source: MountSource, idle_ttl: bigint | null }
+
+export interface MusicAlbum { name: string, artist: string | null, year: number | null, object_ids: Array<number> }
+
+export interface NativeAttribute { id: number, object_id: number, kind: number, name: string, value: string }
 
 export interface Node { id: number, pub_id: Array<number>, name: string, platform: number, version: string | null, last_seen: string, timezone: string | null, date_created: string }
 
-export interface NodeConfig { version: string | null, id: string, name: string, p2p_port: number | null }
+export interface NodeConfig { version: string | null, id: string, name: string, p2p_port: number | null, api_tokens: Array<ApiToken>, job_concurrency: JobConcurrency, io_limits: IoLimits, decrypted_file_ttl: bigint, decrypted_file_quota: bigint, thumbnail_cache_size: bigint }
 
-export interface NodeState { version: string | null, id: string, name: string, p2p_port: number | null, data_path: string }
+export interface NodeState { version: string | null, id: string, name: string, p2p_port: number | null, api_tokens: Array<ApiToken>, job_concurrency: JobConcurrency, io_limits: IoLimits, decrypted_file_ttl: bigint, decrypted_file_quota: bigint, thumbnail_cache_size: bigint, data_path: string }
 
 export interface NormalisedCompositeId { $type: string, $id: any, org_id: string, user_id: string }
 
@@ -153,36 +456,152 @@ export interface NormalisedUser { $type: string, $id: any, id: string, name: str
 
 export interface NormalizedVec<T> { $type: string, edges: Array<T> }
 
-export interface Object { id: number, cas_id: string, integrity_checksum: string | null, name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, date_modified: string, date_indexed: string }
+export interface Object { id: number, cas_id: string, integrity_checksum: string | null, content_hash: string | null, content_hash_kind: number | null, name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, rating: number | null, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, encrypted_note: Array<number> | null, date_created: string, date_modified: string, date_indexed: string }
+
+export interface ObjectSearch { filter: SearchFilter | null, order_by: SearchOrderBy, direction: SearchDirection, take: number | null, skip: number | null }
+
+export type ObjectTarget = { Object: number } | { FilePath: number }
 
 export interface ObjectValidatorArgs { id: number, path: string }
 
-export interface OnboardingArgs { algorithm: Algorithm, hashing_algorithm: HashingAlgorithm }
+export interface OnboardingArgs { algorithm: Algorithm, hashing_algorithm: HashingAlgorithm, password: string | null, passphrase_style: PassphraseStyle | null, hint: string | null }
 
 export interface OnboardingKeys { master_password: string, secret_key: string }
 
+export interface OpenEncryptedArgs { location_id: number, file_path_id: number, ttl: bigint | null }
+
+export interface PairingRequest { peer_id: string, name: string }
+
 export type Params = "Standard" | "Hardened" | "Paranoid"
 
-export interface RestoreBackupArgs { password: string, secret_key: string, path: string }
+export type PassphraseStyle = { Words: number } | { Characters: number }
+
+export interface PeerInfo { peer_id: string, name: string, paired: boolean, node_id: string | null }
+
+export type Platform = "Unknown" | "Windows" | "MacOS" | "Linux" | "IOS" | "Android"
+
+export interface RatingRange { min: number | null, max: number | null }
+
+export interface ReadVaultArgs { location_id: number, file_path_id: number }
+
+export type RemoteBackupSource = { type: "Location", location_id: number, path: string } | { type: "Remote", config: RemoteConfig, credentials: RemoteCredentials, path: string }
+
+export type RemoteConfig = { type: "S3" } & S3Config | { type: "Sftp" } & SftpConfig | { type: "WebDav" } & WebDavConfig
+
+export type RemoteCredentials = { type: "S3", access_key: string, secret_key: string } | { type: "Sftp", username: string, password: string | null, private_key: string | null, passphrase: string | null } | { type: "WebDav", username: string, password: string }
+
+export interface RemoteLocationCreateArgs { name: string, config: RemoteConfig, credentials: RemoteCredentials }
+
+export type RemoteTransfer = { type: "Upload", source: string, directory: string, remove_source: boolean } | { type: "Download", file_path_ids: Array<number>, output_directory: string }
+
+export interface RemoteTransferJobInit { location_id: number, transfer: RemoteTransfer }
+
+export interface RemoteUpload { location_id: number, directory: string }
+
+export interface RenameFileArgs { location_id: number, file_path_id: number, new_name: string, on_conflict: ConflictStrategy }
+
+export interface RestoreBackupArgs { password: string, secret_key: string, path: string, dry_run: boolean }
+
+export interface RestoreBackupBytesArgs { password: string, secret_key: string, backup: string, dry_run: boolean }
+
+export interface RestoreBackupRemoteArgs { password: string, secret_key: string, source: RemoteBackupSource, dry_run: boolean }
+
+export type RestoreBackupResult = { Restored: number } | { DryRun: KeystoreBackupDiff }
 
 export type RuleKind = "AcceptFilesByGlob" | "RejectFilesByGlob" | "AcceptIfChildrenDirectoriesArePresent" | "RejectIfChildrenDirectoriesArePresent"
 
+export interface S3Config { bucket: string, region: string, endpoint: string | null, path_style: boolean, prefix: string }
+
+export interface SavedSearch { id: number, name: string, search: ObjectSearch, date_created: string, date_modified: string }
+
+export interface SavedSearchCreateArgs { name: string, search: ObjectSearch }
+
+export interface SavedSearchExplorerArgs { id: number, take: number | null, skip: number | null }
+
+export interface SavedSearchUpdateArgs { id: number, name: string | null, search: ObjectSearch | null }
+
+export interface Schedule { id: number, name: string, task: ScheduledTask, timing: ScheduleTiming, enabled: boolean, last_run: string | null, last_error: string | null, next_run: string }
+
+export interface ScheduleCreateArgs { name: string, task: ScheduledTask, timing: ScheduleTiming }
+
+export type ScheduleTiming = { type: "Interval", seconds: number } | { type: "Cron", expression: string }
+
+export interface ScheduleUpdateArgs { id: number, name: string | null, task: ScheduledTask | null, timing: ScheduleTiming | null, enabled: boolean | null }
+
+export type ScheduledTask = { type: "KeystoreBackup", directory: string } | { type: "VerifyEncryptedFiles", location_ids: Array<number> } | { type: "IdentifyFiles", location_id: number }
+
+export interface SearchByAttributeArgs { query: string, name: string | null }
+
+export type SearchDirection = "Asc" | "Desc"
+
+export type SearchFilter = { And: Array<SearchFilter> } | { Or: Array<SearchFilter> } | { Name: string } | { Kind: Array<number> } | { Extension: Array<string> } | { Size: SizeRange } | { DateCreated: DateRange } | { DateModified: DateRange } | { DateIndexed: DateRange } | { Tags: { tag_ids: Array<number>, include_descendants: boolean } } | { Location: Array<number> } | { Encrypted: boolean } | { HasThumbnail: boolean } | { Favorite: boolean } | { Rating: RatingRange }
+
+export interface SearchMediaArgs { captured_after: string | null, captured_before: string | null, camera: string | null, bounds: GeoBounds | null, min_duration_seconds: number | null, max_duration_seconds: number | null, codec: string | null, container: string | null, min_height: number | null, artist: string | null, album: string | null, title: string | null, genre: string | null }
+
+export type SearchOrderBy = "Name" | "Size" | "Rating" | "DateCreated" | "DateModified" | "DateIndexed"
+
+export interface SecretKeyRotationArgs { password: string, secret_key: string }
+
 export interface SetFavoriteArgs { id: number, favorite: boolean }
 
-export interface SetMasterPasswordArgs { password: string, secret_key: string }
+export interface SetMasterPasswordArgs { password: string, secret_key: string, decrypt_headers: boolean }
 
 export interface SetNoteArgs { id: number, note: string | null }
 
+export interface SetRatingArgs { id: number, rating: number | null }
+
+export interface SftpConfig { host: string, port: number, root: string, host_key: string | null }
+
+export interface SizeRange { min: bigint | null, max: bigint | null }
+
 export interface Statistics { id: number, date_captured: string, total_object_count: number, library_db_size: string, total_bytes_used: string, total_bytes_capacity: string, total_unique_bytes: string, total_bytes_free: string, preview_media_bytes: string }
 
-export interface StoredKey { uuid: string, algorithm: Algorithm, hashing_algorithm: HashingAlgorithm, content_salt: Array<number>, master_key: Array<number>, master_key_nonce: Array<number>, key_nonce: Array<number>, key: Array<number>, salt: Array<number>, memory_only: boolean, automount: boolean }
+export interface StoredKey { uuid: string, algorithm: Algorithm, hashing_algorithm: HashingAlgorithm, content_salt: Array<number>, master_key: Array<number>, master_key_nonce: Array<number>, key_nonce: Array<number>, key: Array<number>, salt: Array<number>, memory_only: boolean, automount: boolean, name: string | null, source: KeySource }
+
+export interface StreamEncryptedArgs { location_id: number, file_path_id: number }
 
-export interface Tag { id: number, pub_id: Array<number>, name: string | null, color: string | null, total_objects: number | null, redundancy_goal: number | null, date_created: string, date_modified: string }
+export type SymlinkPolicy = "Skip" | "IndexAsLink" | "Follow"
+
+export interface Tag { id: number, pub_id: Array<number>, name: string | null, color: string | null, total_objects: number | null, redundancy_goal: number | null, date_created: string, date_modified: string, parent_id: number | null }
 
 export interface TagAssignArgs { object_id: number, tag_id: number, unassign: boolean }
 
-export interface TagCreateArgs { name: string, color: string }
+export interface TagAssignBulkArgs { tag_ids: Array<number>, targets: BulkTagTargets, unassign: boolean }
+
+export interface TagCreateArgs { name: string, color: string, parent_id: number | null }
+
+export interface TagRule { id: number, name: string, tag_id: number, enabled: boolean, location_id: number | null, extensions: string | null, glob: string | null, camera: string | null, min_size: bigint | null, max_size: bigint | null, date_created: string, date_modified: string }
+
+export interface TagRuleCreateArgs { name: string, tag_id: number, location_id: number | null, extensions: Array<string> | null, glob: string | null, camera: string | null, min_size: bigint | null, max_size: bigint | null }
+
+export interface TagRuleUpdateArgs { id: number, name: string | null, enabled: boolean | null, extensions: Array<string> | null, glob: string | null, camera: string | null, min_size: bigint | null, max_size: bigint | null }
+
+export interface TagSetParentArgs { id: number, parent_id: number | null }
 
 export interface TagUpdateArgs { id: number, name: string | null, color: string | null }
 
+export interface TempFile { id: string, path: string, ttl: bigint }
+
+export interface ThumbnailCacheStats { entries: number, size: bigint, budget: bigint }
+
+export interface TotpArgs { uuid: string, code: TotpCode }
+
+export type TotpCode = { Code: string } | { Recovery: string }
+
+export interface TotpConfirmArgs { uuid: string, code: string }
+
+export interface TotpEnrollmentInfo { uri: string, secret: string, recovery_codes: Array<string> }
+
+export interface TransferProgress { current_file: string | null, current_file_percentage: number, bytes_processed: bigint, total_bytes: bigint, bytes_per_second: bigint, eta_seconds: bigint | null }
+
+export interface TrashedItem { id: number, materialized_path: string, location_id: number, is_dir: boolean, size_in_bytes: string, object_id: number | null, date_trashed: string }
+
+export interface VaultFile { name: string, contents: string }
+
+export interface VersionRetention { max_versions: number | null, max_age_days: number | null }
+
 export interface Volume { name: string, mount_point: string, total_capacity: bigint, available_capacity: bigint, is_removable: boolean, disk_type: string | null, file_system: string | null, is_root_filesystem: boolean }
+
+export interface WaitingJob { report: JobReport, key_uuids: Array<string> }
+
+export interface WebDavConfig { url: string }
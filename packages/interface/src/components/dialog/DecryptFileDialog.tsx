@@ -19,7 +19,7 @@ interface DecryptDialogProps {
 export const DecryptFileDialog = (props: DecryptDialogProps) => {
 	const platform = usePlatform();
 	const { location_id, object_id } = props;
-	const decryptFile = useLibraryMutation('files.decryptFiles');
+	const decryptFile = useLibraryMutation('files.decrypt');
 	const [outputPath, setOutputpath] = useState('');
 	const [password, setPassword] = useState('');
 	const [saveToKeyManager, setSaveToKeyManager] = useState(true);
@@ -46,7 +46,7 @@ export const DecryptFileDialog = (props: DecryptDialogProps) => {
 				open={props.open}
 				setOpen={props.setOpen}
 				title="Decrypt a file"
-				description="Leave the output file blank for the default."
+				description="Leave the output directory blank to decrypt alongside the original."
 				loading={decryptFile.isLoading}
 				ctaLabel="Decrypt"
 				ctaAction={() => {
@@ -61,8 +61,9 @@ export const DecryptFileDialog = (props: DecryptDialogProps) => {
 						decryptFile.mutate(
 							{
 								location_id,
-								object_id,
-								output_path: output,
+								targets: [{ Object: object_id }],
+								output_directory: output,
+								overwrite: false,
 								password: pw,
 								save_to_library: save
 							},
@@ -155,7 +156,7 @@ export const DecryptFileDialog = (props: DecryptDialogProps) => {
 
 				<div className="grid w-full grid-cols-2 gap-4 mt-4 mb-3">
 					<div className="flex flex-col">
-						<span className="text-xs font-bold">Output file</span>
+						<span className="text-xs font-bold">Output directory</span>
 
 						<Button
 							size="sm"
@@ -163,8 +164,7 @@ export const DecryptFileDialog = (props: DecryptDialogProps) => {
 							className="h-[23px] text-xs leading-3 mt-2"
 							type="button"
 							onClick={() => {
-								// if we allow the user to encrypt multiple files simultaneously, this should become a directory instead
-								if (!platform.saveFilePickerDialog) {
+								if (!platform.openDirectoryPickerDialog) {
 									// TODO: Support opening locations on web
 									props.setAlertDialogData({
 										open: true,
@@ -175,7 +175,7 @@ export const DecryptFileDialog = (props: DecryptDialogProps) => {
 									});
 									return;
 								}
-								platform.saveFilePickerDialog().then((result) => {
+								platform.openDirectoryPickerDialog().then((result) => {
 									if (result) setOutputpath(result as string);
 								});
 							}}
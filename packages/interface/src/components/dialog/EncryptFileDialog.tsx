@@ -28,7 +28,7 @@ export const EncryptFileDialog = (props: EncryptDialogProps) => {
 			if (key === '' && data.length !== 0) {
 				// when this query updates and a key is officially mounted, update `key` (the user shouldn't be able to see this dialog before a key is mounted)
 				// only update if no key is currently set
-				UpdateKey(data[0]);
+				UpdateKey(data[0].uuid);
 			}
 		}
 	});
@@ -41,7 +41,7 @@ export const EncryptFileDialog = (props: EncryptDialogProps) => {
 		hashAlg && setHashingAlgo(getHashingAlgorithmString(hashAlg));
 	};
 
-	const encryptFile = useLibraryMutation('files.encryptFiles');
+	const encryptFile = useLibraryMutation('files.encrypt');
 
 	// the selected key will be random, we should prioritise the default
 	const [key, setKey] = useState('');
@@ -49,6 +49,7 @@ export const EncryptFileDialog = (props: EncryptDialogProps) => {
 	// decided against react-hook-form, as it doesn't allow us to work with select boxes and such
 	const [metadata, setMetadata] = useState(false);
 	const [previewMedia, setPreviewMedia] = useState(false);
+	const [eraseOriginal, setEraseOriginal] = useState(false);
 	const [encryptionAlgo, setEncryptionAlgo] = useState('XChaCha20Poly1305');
 	const [hashingAlgo, setHashingAlgo] = useState('');
 	const [outputPath, setOutputpath] = useState('');
@@ -59,7 +60,7 @@ export const EncryptFileDialog = (props: EncryptDialogProps) => {
 				open={props.open}
 				setOpen={props.setOpen}
 				title="Encrypt a file"
-				description="Configure your encryption settings. Leave the output file blank for the default."
+				description="Configure your encryption settings. Leave the output directory blank to encrypt alongside the original."
 				loading={encryptFile.isLoading}
 				ctaLabel="Encrypt"
 				ctaAction={() => {
@@ -74,10 +75,12 @@ export const EncryptFileDialog = (props: EncryptDialogProps) => {
 								algorithm,
 								key_uuid: key,
 								location_id,
-								object_id,
+								targets: [{ Object: object_id }],
 								metadata,
 								preview_media: previewMedia,
-								output_path: output
+								output_directory: output,
+								overwrite: false,
+								erase_original: eraseOriginal
 							},
 							{
 								onSuccess: () => {
@@ -113,11 +116,11 @@ export const EncryptFileDialog = (props: EncryptDialogProps) => {
 								UpdateKey(e);
 							}}
 						>
-							{mountedUuids.data && <SelectOptionKeyList keys={mountedUuids.data} />}
+							{mountedUuids.data && <SelectOptionKeyList keys={mountedUuids.data.map((m) => m.uuid)} />}
 						</Select>
 					</div>
 					<div className="flex flex-col">
-						<span className="text-xs font-bold">Output file</span>
+						<span className="text-xs font-bold">Output directory</span>
 
 						<Button
 							size="sm"
@@ -125,8 +128,7 @@ export const EncryptFileDialog = (props: EncryptDialogProps) => {
 							className="h-[23px] text-xs leading-3 mt-2"
 							type="button"
 							onClick={() => {
-								// if we allow the user to encrypt multiple files simultaneously, this should become a directory instead
-								if (!platform.saveFilePickerDialog) {
+								if (!platform.openDirectoryPickerDialog) {
 									// TODO: Support opening locations on web
 									props.setAlertDialogData({
 										open: true,
@@ -137,7 +139,7 @@ export const EncryptFileDialog = (props: EncryptDialogProps) => {
 									});
 									return;
 								}
-								platform.saveFilePickerDialog().then((result) => {
+								platform.openDirectoryPickerDialog().then((result) => {
 									if (result) setOutputpath(result as string);
 								});
 							}}
@@ -179,6 +181,10 @@ export const EncryptFileDialog = (props: EncryptDialogProps) => {
 						<span className="text-sm font-bold mr-3 ml-0.5 mt-0.5">Preview Media</span>
 						<Checkbox checked={previewMedia} onChange={(e) => setPreviewMedia(e.target.checked)} />
 					</div>
+					<div className="flex">
+						<span className="text-sm font-bold mr-3 ml-0.5 mt-0.5">Erase Original</span>
+						<Checkbox checked={eraseOriginal} onChange={(e) => setEraseOriginal(e.target.checked)} />
+					</div>
 				</div>
 			</Dialog>
 		</>
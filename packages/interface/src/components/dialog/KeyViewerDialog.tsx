@@ -19,9 +19,9 @@ export const KeyUpdater = (props: {
 	setHashingAlgo: (value: string) => void;
 	setContentSalt: (value: string) => void;
 }) => {
-	useLibraryQuery(['keys.getKey', props.uuid], {
+	useLibraryQuery(['keys.getKey', { uuid: props.uuid, reveal: true }], {
 		onSuccess: (data) => {
-			props.setKey(data);
+			props.setKey(data.value);
 		}
 	});
 
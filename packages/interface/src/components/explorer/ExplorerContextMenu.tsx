@@ -97,6 +97,7 @@ export function ExplorerContextMenu(props: PropsWithChildren) {
 
 	const generateThumbsForLocation = useLibraryMutation('jobs.generateThumbsForLocation');
 	const objectValidator = useLibraryMutation('jobs.objectValidator');
+	const verifyEncrypted = useLibraryMutation('files.verifyEncrypted');
 	const rescanLocation = useLibraryMutation('locations.fullRescan');
 
 	return (
@@ -144,6 +145,13 @@ export function ExplorerContextMenu(props: PropsWithChildren) {
 						label="Generate Checksums"
 						icon={ShieldCheck}
 					/>
+					<CM.Item
+						onClick={() =>
+							store.locationId && verifyEncrypted.mutate({ location_ids: [store.locationId] })
+						}
+						label="Verify Encrypted Files"
+						icon={ShieldCheck}
+					/>
 				</CM.SubMenu>
 
 				<CM.Separator />
@@ -172,10 +180,39 @@ export function FileItemContextMenu(props: FileItemContextMenuProps) {
 	const hasMountedKeys =
 		mountedUuids.data !== undefined && mountedUuids.data.length > 0 ? true : false;
 
+	const platform = usePlatform();
+	const openEncrypted = useLibraryMutation('files.openEncrypted');
+
+	// encrypted files are decrypted to a temporary copy, which is erased once it's no longer being used
+	const filePath = isObject(props.item) ? props.item.file_paths[0] : props.item;
+	const isEncrypted = filePath?.extension === 'sdenc';
+
 	return (
 		<div className="relative">
 			<CM.ContextMenu trigger={props.children}>
-				<CM.Item label="Open" keybind="⌘O" />
+				<CM.Item
+					label="Open"
+					keybind="⌘O"
+					onClick={() => {
+						if (!isEncrypted || !filePath || !platform.openPath) return;
+
+						if (!hasMountedKeys) {
+							props.setAlertDialogData({
+								open: true,
+								title: 'No mounted keys',
+								description: '',
+								value: 'No mounted keys were found. Please mount a key and try again.',
+								inputBox: false
+							});
+							return;
+						}
+
+						openEncrypted.mutate(
+							{ location_id: filePath.location_id, file_path_id: filePath.id, ttl: null },
+							{ onSuccess: (file) => platform.openPath!(file.path) }
+						);
+					}}
+				/>
 				<CM.Item label="Open with..." />
 
 				<CM.Separator />
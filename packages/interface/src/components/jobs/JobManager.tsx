@@ -14,6 +14,7 @@ import {
 	LockSimple,
 	LockSimpleOpen,
 	Pause,
+	Play,
 	Question,
 	Trash,
 	X
@@ -118,6 +119,9 @@ function Job({ job }: { job: JobReport }) {
 		icon: Question
 	};
 	const isRunning = job.status === 'Running';
+	const pauseJob = useLibraryMutation(['jobs.pause']);
+	const resumeJob = useLibraryMutation(['jobs.resume']);
+	const cancelJob = useLibraryMutation(['jobs.cancel']);
 	return (
 		<div className="flex items-center px-2 py-2 pl-4 border-b border-app-line/50 bg-opacity-60">
 			<Tooltip label={job.status}>
@@ -132,6 +136,13 @@ function Job({ job }: { job: JobReport }) {
 						<ProgressBar value={job.completed_task_count} total={job.task_count} />
 					</div>
 				)}
+				{isRunning && job.transfer?.current_file && (
+					<span className="text-xs truncate text-ink-faint">
+						{job.transfer.current_file} ({job.transfer.current_file_percentage}%)
+						<span className="mx-1 opacity-50">&#8226;</span>
+						{(job.transfer.bytes_per_second / 1024 / 1024).toFixed(1)} MB/s
+					</span>
+				)}
 				<div className="flex items-center truncate text-ink-faint">
 					<span className="text-xs">
 						{isRunning ? 'Elapsed' : job.status === 'Failed' ? 'Failed after' : 'Took'}{' '}
@@ -142,7 +153,11 @@ function Job({ job }: { job: JobReport }) {
 					<span className="mx-1 opacity-50">&#8226;</span>
 					{
 						<span className="text-xs">
-							{isRunning ? 'Unknown time remaining' : dayjs(job.date_created).toNow(true) + ' ago'}
+							{isRunning
+								? job.transfer?.eta_seconds != null
+									? dayjs.duration({ seconds: job.transfer.eta_seconds }).humanize() + ' remaining'
+									: 'Unknown time remaining'
+								: dayjs(job.date_created).toNow(true) + ' ago'}
 						</span>
 					}
 				</div>
@@ -151,18 +166,25 @@ function Job({ job }: { job: JobReport }) {
 			<div className="flex-grow" />
 			<div className="flex flex-row space-x-2 ml-7">
 				{job.status === 'Running' && (
-					<Button size="icon">
+					<Button size="icon" onClick={() => pauseJob.mutate(job.id)}>
 						<Pause className="w-4 h-4" />
 					</Button>
 				)}
+				{job.status === 'Paused' && (
+					<Button size="icon" onClick={() => resumeJob.mutate(job.id)}>
+						<Play className="w-4 h-4" />
+					</Button>
+				)}
 				{job.status === 'Failed' && (
 					<Button size="icon">
 						<ArrowsClockwise className="w-4" />
 					</Button>
 				)}
-				<Button size="icon">
-					<X className="w-4 h-4" />
-				</Button>
+				{(job.status === 'Running' || job.status === 'Queued' || job.status === 'Paused') && (
+					<Button size="icon" onClick={() => cancelJob.mutate(job.id)}>
+						<X className="w-4 h-4" />
+					</Button>
+				)}
 			</div>
 		</div>
 	);
@@ -174,7 +174,7 @@ export const Key: React.FC<{ data: Key; index: number }> = ({ data, index }) =>
 					/>
 					<KeyDropdownItem
 						onClick={() => {
-							mountKey.mutate(data.id);
+							mountKey.mutate({ uuid: data.id, decrypt_headers: false });
 						}}
 						hidden={data.mounted}
 						value="Mount"
@@ -187,7 +187,7 @@ export const Key: React.FC<{ data: Key; index: number }> = ({ data, index }) =>
 					/>
 					<KeyDropdownItem
 						onClick={() => {
-							setDefaultKey.mutate(data.id);
+							setDefaultKey.mutate({ uuid: data.id, context: null });
 						}}
 						hidden={data.default}
 						value="Set as Default"
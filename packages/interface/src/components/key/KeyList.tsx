@@ -22,12 +22,12 @@ export const SelectOptionKeyList = (props: { keys: string[] }) => {
 export const ListOfKeys = () => {
 	const keys = useLibraryQuery(['keys.list']);
 	const mountedUuids = useLibraryQuery(['keys.listMounted']);
-	const defaultKey = useLibraryQuery(['keys.getDefault']);
+	const defaultKey = useLibraryQuery(['keys.getDefault', null]);
 
 	const [mountedKeys, unmountedKeys] = useMemo(
 		() => [
-			keys.data?.filter((key) => mountedUuids.data?.includes(key.uuid)) ?? [],
-			keys.data?.filter((key) => !mountedUuids.data?.includes(key.uuid)) ?? []
+			keys.data?.filter((key) => mountedUuids.data?.some((m) => m.uuid === key.uuid)) ?? [],
+			keys.data?.filter((key) => !mountedUuids.data?.some((m) => m.uuid === key.uuid)) ?? []
 		],
 		[keys, mountedUuids]
 	);
@@ -3,6 +3,7 @@ import { Button, ButtonLink, Input, Tabs } from '@sd/ui';
 import { Eye, EyeSlash, Gear, Lock } from 'phosphor-react';
 import { useState } from 'react';
 
+import { getCryptoSettings } from '../../screens/settings/library/KeysSetting';
 import { DefaultProps } from '../primitive/types';
 import { KeyList } from './KeyList';
 import { KeyMounter } from './KeyMounter';
@@ -10,7 +11,9 @@ import { KeyMounter } from './KeyMounter';
 export type KeyManagerProps = DefaultProps;
 
 export function KeyManager(props: KeyManagerProps) {
+	const isOnboarded = useLibraryQuery(['keys.isOnboarded']);
 	const hasMasterPw = useLibraryQuery(['keys.hasMasterPassword']);
+	const onboarding = useLibraryMutation('keys.onboarding');
 	const setMasterPasswordMutation = useLibraryMutation('keys.setMasterPassword');
 	const unmountAll = useLibraryMutation('keys.unmountAll');
 	const clearMasterPassword = useLibraryMutation('keys.clearMasterPassword');
@@ -21,6 +24,60 @@ export function KeyManager(props: KeyManagerProps) {
 	const [masterPassword, setMasterPassword] = useState('');
 	const [secretKey, setSecretKey] = useState('');
 
+	// the generated details are only ever shown once, so they're kept until the user has written them down
+	if (isOnboarded.data === false || onboarding.data) {
+		return (
+			<div className="p-2">
+				{onboarding.data ? (
+					<>
+						<p className="mb-2 text-xs text-ink-dull">
+							Please write these down and store them securely, as they're needed to unlock your key
+							manager.
+						</p>
+						<Input
+							value={onboarding.data.master_password}
+							className="flex-grow mb-2 !py-0.5"
+							readOnly
+						/>
+						<Input value={onboarding.data.secret_key} className="flex-grow mb-2 !py-0.5" readOnly />
+						<Button className="w-full" variant="accent" onClick={() => onboarding.reset()}>
+							I've written them down
+						</Button>
+					</>
+				) : (
+					<Button
+						className="w-full"
+						variant="accent"
+						disabled={onboarding.isLoading}
+						onClick={() => {
+							const [algorithm, hashing_algorithm] = getCryptoSettings(
+								'XChaCha20Poly1305',
+								'Argon2id-s'
+							);
+
+							onboarding.mutate(
+								{
+									algorithm,
+									hashing_algorithm,
+									password: null,
+									passphrase_style: null,
+									hint: null
+								},
+								{
+									onError: () => {
+										alert('There was an error while setting up your key manager.');
+									}
+								}
+							);
+						}}
+					>
+						Set up key manager
+					</Button>
+				)}
+			</div>
+		);
+	}
+
 	if (!hasMasterPw?.data) {
 		const MPCurrentEyeIcon = showMasterPassword ? EyeSlash : Eye;
 		const SKCurrentEyeIcon = showSecretKey ? EyeSlash : Eye;
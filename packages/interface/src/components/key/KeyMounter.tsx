@@ -149,7 +149,7 @@ export function KeyMounter() {
 					createKey.mutate({
 						algorithm,
 						hashing_algorithm,
-						key,
+						key: { encoding: 'Utf8', value: key },
 						library_sync: librarySync,
 						automount: autoMount
 					});
@@ -2,7 +2,7 @@ import clsx from 'clsx';
 import { useState } from 'react';
 import { useNavigate } from 'react-router';
 
-import { Button, Input } from '../../../../ui/src';
+import { Button } from '../../../../ui/src';
 import { useOperatingSystem } from '../../hooks/useOperatingSystem';
 import CreateLibraryDialog from '../dialog/CreateLibraryDialog';
 
@@ -20,27 +20,9 @@ export default function OnboardingPage() {
 			)}
 		>
 			<h1 className="text-red-500">Welcome to Spacedrive</h1>
-			<div className="text-white mt-2 mb-4">
-				<p className="text-sm mb-1">
-					The default keymanager details are below. This is only for development, and will be
-					completely random once onboarding has completed. The secret key is just 16x zeroes encoded
-					in hex.
-				</p>
-				<div className="flex space-x-2">
-					<div className="relative flex">
-						<p className="mr-2 text-sm mt-2">Password:</p>
-						<Input value="password" className="flex-grow !py-0.5" disabled />
-					</div>
-					<div className="relative flex w-[375px]">
-						<p className="mr-2 text-sm mt-2">Secret Key:</p>
-						<Input
-							value="30303030-30303030-30303030-30303030"
-							className="flex-grow !py-0.5"
-							disabled
-						/>
-					</div>
-				</div>
-			</div>
+			<p className="text-white text-sm mt-2 mb-4">
+				Your key manager can be set up once your library has been created.
+			</p>
 
 			<CreateLibraryDialog open={open} setOpen={setOpen} onSubmit={() => navigate('/overview')}>
 				<Button variant="accent" size="sm">